@@ -0,0 +1,224 @@
+use crate::common::{lexer::Span, syntax_tree::Type};
+
+/// What is known about an inference variable that isn't bound to a type yet.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Any,
+    /// Holds an integer literal; `signed` once it has been negated.
+    Integer { signed: bool },
+    /// Holds a float literal.
+    Float
+}
+
+impl Kind {
+    /// The kind of a variable constrained to be both `self` and `other`.
+    fn meet(self, other: Kind) -> Option<Kind> {
+        match (self, other) {
+            (Kind::Any, kind) | (kind, Kind::Any) => Some(kind),
+            (Kind::Integer { signed: a }, Kind::Integer { signed: b }) => Some(Kind::Integer { signed: a || b }),
+            (Kind::Float, Kind::Float) => Some(Kind::Float),
+            _ => None
+        }
+    }
+
+    fn admits(self, ty: &Type<'_>) -> bool {
+        match self {
+            Kind::Any => !ty.is_void(),
+            Kind::Integer { signed } => ty.is_integer() && (!signed || ty.is_signed()),
+            Kind::Float => ty.is_float()
+        }
+    }
+}
+
+struct Var<'a> {
+    /// The variable whose type this is.
+    name: String,
+    kind: Kind,
+    /// The type this variable was unified with: another variable, or what it stands for.
+    ty: Option<Type<'a>>,
+    /// The last place that told something about the type, to point at when a later use
+    /// disagrees. Starts out as the declaration's initializer, or the declaration itself.
+    decided: Span,
+    /// Integer literals of this type, which must fit once the type is known.
+    literals: Vec<(i128, Span)>
+}
+
+/// The types of `let`s declared without one, worked out by unifying each with the types
+/// of the places it is used. A variable that only ever holds literals defaults to the
+/// first of `Int32`, `Int64` and `Uint64` that fits them all, or to `Float64`.
+#[derive(Default)]
+pub(crate) struct Inference<'a> {
+    vars: Vec<Var<'a>>
+}
+
+impl<'a> Inference<'a> {
+
+    /// A new variable for the type of `name`, whose value comes from `origin`.
+    pub fn fresh(&mut self, name: &str, origin: Span) -> Type<'a> {
+        self.vars.push(Var {
+            name: name.to_string(),
+            kind: Kind::Any,
+            ty: None,
+            decided: origin,
+            literals: Vec::new()
+        });
+        Type::Var(self.vars.len() as u32 - 1)
+    }
+
+    /// The variable `id` stands for the same type as.
+    fn root(&self, mut id: u32) -> u32 {
+        while let Some(Type::Var(next)) = &self.vars[id as usize].ty {
+            id = *next;
+        }
+        id
+    }
+
+    /// `ty` with every variable replaced by what it is known to be so far.
+    pub fn resolve(&self, ty: &Type<'a>) -> Type<'a> {
+        match ty {
+            Type::Var(id) => {
+                let root = self.root(*id);
+                match &self.vars[root as usize].ty {
+                    Some(ty) => self.resolve(ty),
+                    None => Type::Var(root)
+                }
+            },
+            Type::Applied(name, args) => Type::Applied(name.clone(), args.iter().map(|arg| self.resolve(arg)).collect()),
+            Type::UnsafePtr(inner) => Type::UnsafePtr(Box::new(self.resolve(inner))),
+            Type::SafePtr(inner) => Type::SafePtr(Box::new(self.resolve(inner))),
+            Type::Array(inner, len) => Type::Array(Box::new(self.resolve(inner)), *len),
+            Type::Slice(inner) => Type::Slice(Box::new(self.resolve(inner))),
+            _ => ty.clone()
+        }
+    }
+
+    /// The kind of `ty`, if it is a variable that isn't bound yet.
+    pub fn kind(&self, ty: &Type<'a>) -> Option<Kind> {
+        match self.resolve(ty) {
+            Type::Var(id) => Some(self.vars[id as usize].kind),
+            _ => None
+        }
+    }
+
+    /// Whether a variable that isn't bound yet occurs in `ty`.
+    pub fn is_open(&self, ty: &Type<'a>) -> bool {
+        match self.resolve(ty) {
+            Type::Var(_) => true,
+            Type::Applied(_, args) => args.iter().any(|arg| self.is_open(arg)),
+            Type::UnsafePtr(inner) | Type::SafePtr(inner) | Type::Array(inner, _) | Type::Slice(inner) => self.is_open(&inner),
+            _ => false
+        }
+    }
+
+    /// Narrows `ty`, if it is an unbound variable, to `kind`, because of what happens at
+    /// `at`. Fails for other types and for variables of another kind.
+    pub fn narrow(&mut self, ty: &Type<'a>, kind: Kind, at: Span) -> bool {
+        let Type::Var(id) = self.resolve(ty) else {
+            return false;
+        };
+        let var = &mut self.vars[id as usize];
+        match var.kind.meet(kind) {
+            Some(meet) => {
+                if meet != var.kind {
+                    var.kind = meet;
+                    var.decided = at;
+                }
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Records that the integer literal `value` at `span` has the type `ty`.
+    pub fn literal(&mut self, ty: &Type<'a>, value: i128, span: Span) {
+        if let Type::Var(id) = self.resolve(ty) {
+            self.vars[id as usize].literals.push((value, span));
+        }
+    }
+
+    /// Makes `a` and `b` the same type, because of what happens at `at`, binding the
+    /// variables in either. Fails when they can't be.
+    pub fn unify(&mut self, a: &Type<'a>, b: &Type<'a>, at: Span) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        if a == b {
+            return true;
+        }
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) => {
+                let Some(kind) = self.vars[*x as usize].kind.meet(self.vars[*y as usize].kind) else {
+                    return false;
+                };
+                let literals = std::mem::take(&mut self.vars[*x as usize].literals);
+                self.vars[*x as usize].ty = Some(b.clone());
+                let root = &mut self.vars[*y as usize];
+                root.kind = kind;
+                root.literals.extend(literals);
+                root.decided = at;
+                true
+            },
+            (Type::Var(id), ty) | (ty, Type::Var(id)) => {
+                let var = &mut self.vars[*id as usize];
+                if !var.kind.admits(ty) {
+                    return false;
+                }
+                var.ty = Some(ty.clone());
+                var.decided = at;
+                true
+            },
+            (Type::Applied(x, xs), Type::Applied(y, ys)) => x == y && xs.len() == ys.len()
+                && xs.iter().zip(ys).all(|(x, y)| self.unify(x, y, at)),
+            (Type::UnsafePtr(x), Type::UnsafePtr(y))
+            | (Type::SafePtr(x), Type::SafePtr(y))
+            | (Type::Slice(x), Type::Slice(y)) => self.unify(x, y, at),
+            (Type::Array(x, n), Type::Array(y, m)) => n == m && self.unify(x, y, at),
+            _ => false
+        }
+    }
+
+    /// The name of the variable whose type `id` is, and where its type was last decided.
+    pub fn origin(&self, id: u32) -> (&str, Span) {
+        (&self.vars[id as usize].name, self.vars[self.root(id) as usize].decided)
+    }
+
+    /// `ty` as diagnostics show it, with the kinds of the variables still unbound.
+    pub fn describe(&self, ty: &Type<'a>) -> String {
+        match (self.resolve(ty), self.kind(ty)) {
+            (_, Some(Kind::Integer { .. })) => "{integer}".to_string(),
+            (_, Some(Kind::Float)) => "{float}".to_string(),
+            (ty, _) => ty.to_string()
+        }
+    }
+
+    /// Binds every variable left unbound to its default type. Gives those that have none,
+    /// bound to `Type::Infer`.
+    pub fn settle(&mut self) -> Vec<u32> {
+        let mut unknown = Vec::new();
+        for (id, var) in self.vars.iter_mut().enumerate() {
+            if var.ty.is_some() {
+                continue;
+            }
+            var.ty = Some(match var.kind {
+                Kind::Integer { signed } => {
+                    let candidates = [Type::Int32, Type::Int64, Type::Uint64];
+                    let candidates = &candidates[..if signed { 2 } else { 3 }];
+                    let fits = |ty: &&Type<'a>| ty.int_range().is_some_and(|(min, max)| var.literals.iter().all(|(value, _)| (min..=max).contains(value)));
+                    candidates.iter().find(fits).unwrap_or(&Type::Int64).clone()
+                },
+                Kind::Float => Type::Float64,
+                Kind::Any => {
+                    unknown.push(id as u32);
+                    Type::Infer
+                }
+            });
+        }
+        unknown
+    }
+
+    /// The integer literals whose type was inferred, with that type and the variable it
+    /// is the type of.
+    pub fn literals(&self) -> Vec<(u32, Type<'a>, i128, Span)> {
+        self.vars.iter().enumerate()
+            .flat_map(|(id, var)| var.literals.iter().map(move |(value, span)| (id as u32, self.resolve(&Type::Var(id as u32)), *value, *span)))
+            .collect()
+    }
+}
@@ -0,0 +1,5 @@
+pub(crate) mod infer;
+pub(crate) mod resolve;
+pub(crate) mod symbols;
+pub(crate) mod traits;
+pub(crate) mod typeck;
@@ -0,0 +1,660 @@
+use std::collections::HashMap;
+
+use crate::{
+    analysis::{
+        symbols::{Implementation, ScopeId, ScopeKind, SymbolId, SymbolKind},
+        traits::TraitChecker
+    },
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        session::Session,
+        syntax_tree::{Bodies, DefunDescriptor, ExtendDescriptor, GenericParam, Node, Type, VarDeclaration},
+        yarn::Yarn
+    }
+};
+
+/// What each identifier and call target resolved to, keyed by the node's span.
+#[derive(Default)]
+pub(crate) struct Resolutions {
+    names: HashMap<Span, SymbolId>,
+    params: HashMap<Span, Vec<SymbolId>>
+}
+
+impl Resolutions {
+    pub fn get(&self, node: &Node<'_>) -> Option<SymbolId> {
+        self.names.get(&node.span()).copied()
+    }
+
+    /// The parameters of the `defun` whose body node spans `span`, in order.
+    pub fn params(&self, span: Span) -> &[SymbolId] {
+        self.params.get(&span).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Adds the resolutions of a later, separately resolved tree.
+    pub fn extend(&mut self, other: Resolutions) {
+        self.names.extend(other.names);
+        self.params.extend(other.params);
+    }
+
+    fn insert(&mut self, span: Span, symbol: SymbolId) {
+        self.names.insert(span, symbol);
+    }
+}
+
+/// Declares every item and local in the session's symbol table and binds each use to
+/// its declaration.
+pub(crate) struct Resolver<'s, 'a> {
+    session: &'s mut Session<'a>,
+    resolutions: Resolutions,
+    /// The labels of the loops around the code being resolved, innermost last.
+    loops: Vec<Option<String>>,
+    /// Whether the code being resolved is in a function, so `return` has one to leave.
+    in_function: bool
+}
+
+impl<'s, 'a> Resolver<'s, 'a> {
+
+    pub fn new(session: &'s mut Session<'a>) -> Self {
+        Self {
+            session,
+            resolutions: Resolutions::default(),
+            loops: Vec::new(),
+            in_function: false
+        }
+    }
+
+    pub fn resolve_program(mut self, tree: &Node<'a>) -> Resolutions {
+        let items = top_level(tree);
+        for item in items {
+            self.collect(item);
+        }
+        // An `extend` block may come before the `obj` or `trait` it names.
+        for item in items {
+            if let Node::Body { discriptor, body, span } = item {
+                if let Bodies::Extension(extension) = discriptor.as_ref() {
+                    self.collect_extension(extension, body, *span);
+                }
+            }
+        }
+        for item in items {
+            self.resolve_item(item);
+        }
+        TraitChecker::new(self.session).check_program(items);
+        self.resolutions
+    }
+
+    /// Resolves a lone expression against the current scope.
+    pub fn resolve_expression(mut self, expr: &Node<'a>) -> Resolutions {
+        self.resolve_expr(expr);
+        self.resolutions
+    }
+
+    fn error(&mut self, diag: Diagnostic) {
+        self.session.report(diag);
+    }
+
+    fn define(&mut self, name: &str, kind: SymbolKind<'a>, span: Span) -> Option<SymbolId> {
+        match self.session.symbols.define(name, kind, span) {
+            Ok(id) => Some(id),
+            Err(diag) => {
+                self.error(diag);
+                None
+            }
+        }
+    }
+
+    fn define_fields(&mut self, fields: &[VarDeclaration<'a>], span: Span) {
+        for field in fields {
+            if let Some(name) = field.name() {
+                self.define(name.as_slice(), SymbolKind::Field(field.ty()), span);
+            }
+        }
+    }
+
+    fn define_methods(&mut self, methods: &[Node<'a>]) {
+        for method in methods {
+            if let Node::Body { discriptor, span, .. } = method {
+                if let Bodies::Defun(defun) = discriptor.as_ref() {
+                    self.define(defun.name().as_slice(), SymbolKind::Function(defun.clone()), *span);
+                }
+            }
+        }
+    }
+
+    /// Declares top-level items up front so they can be used before their definition.
+    fn collect(&mut self, item: &Node<'a>) {
+        let Node::Body { discriptor, body, span } = item else {
+            return;
+        };
+
+        match discriptor.as_ref() {
+            Bodies::Defun(defun) => {
+                self.define(defun.name().as_slice(), SymbolKind::Function(defun.clone()), *span);
+            },
+            Bodies::Object(obj) => {
+                let Some(id) = self.define(obj.name().as_slice(), SymbolKind::Object(obj.clone()), *span) else {
+                    return;
+                };
+                let members = self.session.symbols.enter(ScopeKind::Object, Some(obj.name().as_slice()));
+                self.define_fields(obj.fields(), *span);
+                self.define_methods(body);
+                self.session.symbols.exit();
+                self.session.symbols.set_members(id, members);
+            },
+            Bodies::Composition(comp) => {
+                let Some(id) = self.define(comp.name().as_slice(), SymbolKind::Composition(comp.clone()), *span) else {
+                    return;
+                };
+                let members = self.session.symbols.enter(ScopeKind::Object, Some(comp.name().as_slice()));
+                self.define_fields(comp.fields(), *span);
+                self.session.symbols.exit();
+                self.session.symbols.set_members(id, members);
+            },
+            Bodies::Trait(tr) => {
+                let Some(id) = self.define(tr.name().as_slice(), SymbolKind::Trait(tr.clone()), *span) else {
+                    return;
+                };
+                let members = self.session.symbols.enter(ScopeKind::Object, Some(tr.name().as_slice()));
+                self.define_methods(body);
+                self.session.symbols.exit();
+                self.session.symbols.set_members(id, members);
+            },
+            Bodies::Extension(_) => {}
+        }
+    }
+
+    /// Declares the methods of an `extend` block in a scope of their own among the `obj`'s
+    /// members, so they are qualified as `Obj::Trait::method`.
+    fn collect_extension(&mut self, extension: &ExtendDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        let obj = self.expect_type(extension.obj().as_slice(), ("an", "obj"), span, |kind| matches!(kind, SymbolKind::Object(_)));
+        let with = self.expect_type(extension.with().as_slice(), ("a", "trait"), span, |kind| matches!(kind, SymbolKind::Trait(_)));
+        let (Some(Some(obj_members)), Some(_)) = (obj, with) else {
+            return;
+        };
+        let (obj, with) = (extension.obj().to_string(), extension.with().to_string());
+        let obj_arity = self.check_type_args(&obj, extension.generics().len(), span);
+        let trait_arity = self.check_type_args(&with, extension.trait_args().len(), span);
+        if !obj_arity || !trait_arity {
+            return;
+        }
+        if let Some(previous) = self.session.symbols.implementation(&obj, &with) {
+            let previous = previous.span;
+            self.error(Diagnostic::error(format!("conflicting implementations of `{}` for `{}`", with, obj))
+                .with_code(codes::CONFLICTING_IMPLEMENTATIONS)
+                .with_primary(span, "conflicting implementation")
+                .with_secondary(previous, "first implementation here")
+                .with_note("an `obj` can be extended with each trait only once"));
+            return;
+        }
+
+        self.session.symbols.enter_existing(obj_members);
+        let members = self.session.symbols.enter(ScopeKind::Object, Some(&with));
+        self.define_methods(body);
+        self.session.symbols.exit();
+        self.session.symbols.exit();
+
+        // The block's parameters keep the bounds the `obj` puts on its own.
+        let params: Vec<Type<'a>> = extension.generics().iter().map(GenericParam::as_type).collect();
+        let declared = self.session.symbols.generics_of(&obj).to_vec();
+        let generics = extension.generics().iter().zip(&declared)
+            .map(|(param, own)| {
+                let mut bounds = param.bounds().to_vec();
+                bounds.extend(own.bounds().iter().map(|bound| bound.instantiate(&declared, &params)));
+                GenericParam::new(param.name().clone(), bounds)
+            })
+            .collect();
+        let trait_args = extension.trait_args().to_vec();
+        self.session.symbols.implement(Implementation { obj, with, generics, trait_args, members, span });
+    }
+
+    /// Whether the generic item `name` is given as many type arguments as it declares
+    /// parameters, reporting it if not.
+    fn check_type_args(&mut self, name: &str, found: usize, span: Span) -> bool {
+        let generics = self.session.symbols.generics_of(name);
+        if generics.len() == found {
+            return true;
+        }
+        let params: Vec<&str> = generics.iter().map(|param| param.name().as_slice()).collect();
+        let plural = if params.len() == 1 { "" } else { "s" };
+        let were = if found == 1 { "was" } else { "were" };
+        let diag = Diagnostic::error(format!("`{}` takes {} type argument{} but {} {} supplied", name, params.len(), plural, found, were))
+            .with_code(codes::WRONG_TYPE_ARG_COUNT)
+            .with_primary(span, format!("expected {} type argument{}", params.len(), plural));
+        let diag = if params.is_empty() {
+            diag.with_note(format!("`{}` is not generic", name))
+        } else {
+            diag.with_help(format!("write it as `{}<{}>`", name, params.join(", ")))
+        };
+        self.error(diag);
+        false
+    }
+
+    /// Reports bounds that aren't traits, and unknown types among them.
+    fn resolve_generics(&mut self, generics: &[GenericParam<'a>], span: Span) {
+        for param in generics {
+            for bound in param.bounds() {
+                self.resolve_type(bound, span);
+                let Some(name) = bound.type_name() else {
+                    self.error(Diagnostic::error(format!("expected trait, found `{}`", bound))
+                        .with_code(codes::UNKNOWN_TYPE)
+                        .with_primary(span, format!("`{}` can't be a bound of `{}`", bound, param.name())));
+                    continue;
+                };
+                let found = self.session.symbols.lookup_qualified(name)
+                    .filter(|symbol| !matches!(symbol.kind, SymbolKind::Trait(_)))
+                    .map(|symbol| symbol.kind.describe());
+                if let Some(found) = found {
+                    self.error(Diagnostic::error(format!("expected trait, found {} `{}`", found, name))
+                        .with_code(codes::UNKNOWN_TYPE)
+                        .with_primary(span, format!("`{}` can't be a bound of `{}`", name, param.name()))
+                        .with_note("bounds name the traits a type argument must be extended with"));
+                }
+            }
+        }
+    }
+
+    /// Looks up the type `name` that an `extend` block names, reporting it unless `expected`
+    /// accepts its kind. The result is the type's member scope, if it has one.
+    fn expect_type(&mut self, name: &str, (article, what): (&str, &str), span: Span, expected: impl Fn(&SymbolKind<'a>) -> bool) -> Option<Option<ScopeId>> {
+        let found = self.session.symbols.lookup_qualified(name).map(|symbol| (expected(&symbol.kind), symbol.kind.describe(), symbol.members));
+        match found {
+            Some((true, _, members)) => return Some(members),
+            Some((false, found, _)) => {
+                self.error(Diagnostic::error(format!("expected {}, found {} `{}`", what, found, name))
+                    .with_code(codes::UNKNOWN_TYPE)
+                    .with_primary(span, format!("not {} {}", article, what)));
+            },
+            None => {
+                let candidates: Vec<String> = self.session.symbols.qualified_symbols()
+                    .filter(|symbol| expected(&symbol.kind))
+                    .map(|symbol| symbol.qualified.clone())
+                    .collect();
+                let diag = Diagnostic::error(format!("cannot find {} `{}`", what, name))
+                    .with_code(codes::UNKNOWN_TYPE)
+                    .with_primary(span, "not found in this scope");
+                let diag = with_suggestion(diag, &format!("{} {}", article, what), name, candidates.iter().map(String::as_str));
+                self.error(diag);
+            }
+        }
+        None
+    }
+
+    /// Reports any user-defined type inside `ty` that was never declared, or that is given
+    /// the wrong number of type arguments.
+    fn resolve_type(&mut self, ty: &Type<'a>, span: Span) {
+        match ty {
+            Type::UnsafePtr(inner) | Type::SafePtr(inner) | Type::Array(inner, _) | Type::Slice(inner) => self.resolve_type(inner, span),
+            Type::Named(name) | Type::Applied(name, _) => {
+                let name = name.as_slice();
+                if self.session.symbols.lookup_qualified(name).is_some_and(|symbol| symbol.kind.is_type()) {
+                    for arg in ty.type_args() {
+                        self.resolve_type(arg, span);
+                    }
+                    self.check_type_args(name, ty.type_args().len(), span);
+                    return;
+                }
+                let candidates = self.session.symbols.qualified_symbols()
+                    .filter(|symbol| symbol.kind.is_type())
+                    .map(|symbol| symbol.qualified.as_str())
+                    .chain(Type::PRIMITIVES);
+                let diag = Diagnostic::error(format!("cannot find type `{}`", name))
+                    .with_code(codes::UNKNOWN_TYPE)
+                    .with_primary(span, "not found in this scope");
+                let diag = with_suggestion(diag, "a type", name, candidates);
+                self.error(diag);
+            },
+            _ => {}
+        }
+    }
+
+    fn resolve_signature(&mut self, defun: &DefunDescriptor<'a>, span: Span) {
+        self.resolve_generics(defun.generics(), span);
+        for arg in defun.args() {
+            self.resolve_type(&arg.ty(), span);
+        }
+        self.resolve_type(defun.return_type(), span);
+    }
+
+    fn resolve_item(&mut self, item: &Node<'a>) {
+        let Node::Body { discriptor, body, span } = item else {
+            self.resolve_statement(item);
+            return;
+        };
+
+        match discriptor.as_ref() {
+            Bodies::Defun(defun) => self.resolve_defun(defun, body, *span),
+            Bodies::Object(obj) => {
+                self.resolve_generics(obj.generics(), *span);
+                for field in obj.fields() {
+                    self.resolve_type(&field.ty(), *span);
+                }
+                // A redefined `obj` never got a member scope of its own.
+                let members = self.session.symbols.lookup_qualified(obj.name().as_slice())
+                    .filter(|symbol| symbol.span == *span)
+                    .and_then(|symbol| symbol.members);
+                if let Some(members) = members {
+                    self.session.symbols.enter_existing(members);
+                    for method in body {
+                        self.resolve_item(method);
+                    }
+                    self.session.symbols.exit();
+                }
+            },
+            Bodies::Composition(comp) => {
+                self.resolve_generics(comp.generics(), *span);
+                for field in comp.fields() {
+                    self.resolve_type(&field.ty(), *span);
+                }
+            },
+            Bodies::Trait(tr) => {
+                self.resolve_generics(tr.generics(), *span);
+                let members = self.session.symbols.lookup_qualified(tr.name().as_slice())
+                    .filter(|symbol| symbol.span == *span)
+                    .and_then(|symbol| symbol.members);
+                let Some(members) = members else {
+                    return;
+                };
+                // Only default bodies have anything to resolve past their signature.
+                self.session.symbols.enter_existing(members);
+                for method in body {
+                    if let Node::Body { discriptor, span, .. } = method {
+                        if let Bodies::Defun(defun) = discriptor.as_ref() {
+                            if !tr.has_default(defun.name().as_slice()) {
+                                self.resolve_signature(defun, *span);
+                                continue;
+                            }
+                        }
+                    }
+                    self.resolve_item(method);
+                }
+                self.session.symbols.exit();
+            },
+            Bodies::Extension(extension) => {
+                self.resolve_generics(extension.generics(), *span);
+                for arg in extension.trait_args() {
+                    self.resolve_type(arg, *span);
+                }
+                let members = self.session.symbols.implementations().iter()
+                    .find(|implementation| implementation.span == *span)
+                    .map(|implementation| implementation.members);
+                let Some(members) = members else {
+                    return;
+                };
+                let Some(obj_members) = self.session.symbols.scope(members).parent() else {
+                    return;
+                };
+                self.session.symbols.enter_existing(obj_members);
+                self.session.symbols.enter_existing(members);
+                for method in body {
+                    self.resolve_item(method);
+                }
+                self.session.symbols.exit();
+                self.session.symbols.exit();
+            }
+        }
+    }
+
+    fn resolve_defun(&mut self, defun: &DefunDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        self.resolve_signature(defun, span);
+        self.session.symbols.enter(ScopeKind::Function, None);
+        let mut params = Vec::new();
+        for arg in defun.args() {
+            if let Some(name) = arg.name() {
+                params.extend(self.define(name.as_slice(), SymbolKind::Parameter(arg.ty()), span));
+            }
+        }
+        self.resolutions.params.insert(span, params);
+        // Loops around a nested function don't reach into its body.
+        let loops = std::mem::take(&mut self.loops);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        self.resolve_block(body);
+        self.loops = loops;
+        self.in_function = in_function;
+        self.session.symbols.exit();
+    }
+
+    fn resolve_loop(&mut self, label: &Option<Yarn<'a>>, body: &[Node<'a>]) {
+        self.loops.push(label.as_ref().map(Yarn::to_string));
+        self.resolve_block(body);
+        self.loops.pop();
+    }
+
+    /// Checks that a `break` or `continue` is in a loop, and that the loop it names exists.
+    fn resolve_jump(&mut self, keyword: &str, label: &Option<Yarn<'a>>, span: Span) {
+        if self.loops.is_empty() {
+            self.error(Diagnostic::error(format!("`{}` outside of a loop", keyword))
+                .with_code(codes::JUMP_OUTSIDE_LOOP)
+                .with_primary(span, format!("cannot `{}` outside of a loop", keyword)));
+            return;
+        }
+        let Some(label) = label else {
+            return;
+        };
+        if self.loops.iter().flatten().any(|name| name == label.as_slice()) {
+            return;
+        }
+        let diag = Diagnostic::error(format!("use of undeclared label `{}`", label))
+            .with_code(codes::UNDEFINED_LABEL)
+            .with_primary(span, "no enclosing loop has this label");
+        let diag = with_suggestion(diag, "a label", label.as_slice(), self.loops.iter().flatten().map(String::as_str));
+        self.error(diag);
+    }
+
+    fn resolve_block(&mut self, stmts: &[Node<'a>]) {
+        self.session.symbols.enter(ScopeKind::Block, None);
+        for stmt in stmts {
+            self.resolve_statement(stmt);
+        }
+        self.session.symbols.exit();
+    }
+
+    fn declare(&mut self, decl: &VarDeclaration<'a>, span: Span) {
+        let ty = decl.ty();
+        self.resolve_type(&ty, span);
+        if let Some(name) = decl.name() {
+            if let Some(id) = self.define(name.as_slice(), SymbolKind::Variable(ty), span) {
+                self.resolutions.insert(span, id);
+            }
+        }
+    }
+
+    fn resolve_statement(&mut self, stmt: &Node<'a>) {
+        match stmt {
+            Node::Value { ret, span } => self.declare(ret, *span),
+            // The initializer can't see the binding it initializes: `let x: Int32 = x;` uses an outer `x`.
+            Node::BinaryOp { lhs, rhs, .. } if matches!(lhs.as_ref(), Node::Value { .. }) => {
+                self.resolve_expr(rhs);
+                if let Node::Value { ret, span } = lhs.as_ref() {
+                    self.declare(ret, *span);
+                }
+            },
+            Node::Chain { chained } => self.resolve_block(chained),
+            Node::Body { .. } => self.resolve_item(stmt),
+            expr => self.resolve_expr(expr)
+        }
+    }
+
+    fn resolve_expr(&mut self, node: &Node<'a>) {
+        match node {
+            Node::Ident { name, span } => {
+                let name = name.as_slice();
+                let found = self.session.symbols.lookup(name).map(|symbol| (symbol.id, symbol.kind.value_type().is_some(), symbol.kind.describe()));
+                match found {
+                    Some((id, true, _)) => self.resolutions.insert(*span, id),
+                    Some((_, false, what)) => {
+                        self.error(Diagnostic::error(format!("expected value, found {} `{}`", what, name))
+                            .with_code(codes::UNDEFINED_VARIABLE)
+                            .with_primary(*span, "not a value"));
+                    },
+                    None => {
+                        let candidates: Vec<String> = self.session.symbols.visible()
+                            .filter(|symbol| symbol.kind.value_type().is_some())
+                            .map(|symbol| symbol.name.clone())
+                            .collect();
+                        let diag = Diagnostic::error(format!("cannot find value `{}` in this scope", name))
+                            .with_code(codes::UNDEFINED_VARIABLE)
+                            .with_primary(*span, "not found in this scope");
+                        let diag = with_suggestion(diag, "a local variable", name, candidates.iter().map(String::as_str));
+                        self.error(diag);
+                    }
+                }
+            },
+            Node::Call { func, args, span } => {
+                let func = func.as_slice();
+                let found = self.session.symbols.resolve_path(func).map(|symbol| (symbol.id, symbol.kind.clone()));
+                match found {
+                    Some((id, kind)) if kind.is_callable() => {
+                        self.resolutions.insert(*span, id);
+                    },
+                    Some((_, kind)) => {
+                        self.error(Diagnostic::error(format!("expected function, found {} `{}`", kind.describe(), func))
+                            .with_code(codes::UNDEFINED_FUNCTION)
+                            .with_primary(*span, "not a function"));
+                    },
+                    None => {
+                        let candidates: Vec<(String, &str)> = self.session.symbols.visible()
+                            .chain(self.session.symbols.qualified_symbols())
+                            .filter(|symbol| symbol.kind.is_callable())
+                            .map(|symbol| (if func.contains("::") { symbol.qualified.clone() } else { symbol.name.clone() }, symbol.kind.describe()))
+                            .collect();
+                        let mut diag = Diagnostic::error(format!("cannot find function `{}` in this scope", func))
+                            .with_code(codes::UNDEFINED_FUNCTION)
+                            .with_primary(*span, "not found in this scope");
+                        // Objs are callable too, so the help names what the candidate actually is.
+                        if let Some(found) = suggest(func, candidates.iter().map(|(name, _)| name.as_str())) {
+                            let what = candidates.iter().find(|(name, _)| name == found).map_or("function", |(_, what)| what);
+                            let article = if what.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+                            diag = diag.with_help(format!("{} {} with a similar name exists: `{}`", article, what, found));
+                        }
+                        self.error(diag);
+                    }
+                }
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            },
+            // The method itself depends on the receiver's type, so the type checker binds it.
+            Node::ObjCall { recv, args, .. } => {
+                self.resolve_expr(recv);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            },
+            Node::Field { recv, .. } => self.resolve_expr(recv),
+            Node::BinaryOp { lhs, rhs, .. } => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            },
+            Node::UnaryOp { lhs, .. } => self.resolve_expr(lhs),
+            Node::If { cond, then, els, .. } => {
+                self.resolve_expr(cond);
+                self.resolve_block(then);
+                if let Some(els) = els {
+                    self.resolve_block(els);
+                }
+            },
+            Node::While { label, cond, body, .. } => {
+                self.resolve_expr(cond);
+                self.resolve_loop(label, body);
+            },
+            // The counter is only in scope in the body, not in the bounds.
+            Node::For { label, var, start, end, body, .. } => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+                self.session.symbols.enter(ScopeKind::Block, None);
+                self.resolve_statement(var);
+                self.resolve_loop(label, body);
+                self.session.symbols.exit();
+            },
+            Node::Loop { label, body, .. } => self.resolve_loop(label, body),
+            Node::Break { label, span } => self.resolve_jump("break", label, *span),
+            Node::Continue { label, span } => self.resolve_jump("continue", label, *span),
+            Node::Return { value, span } => {
+                if !self.in_function {
+                    self.error(Diagnostic::error("`return` outside of a function")
+                        .with_code(codes::RETURN_OUTSIDE_FUNCTION)
+                        .with_primary(*span, "top-level statements have no function to return from"));
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            },
+            Node::Chain { chained } => self.resolve_block(chained),
+            Node::Value { .. } | Node::Body { .. } | Node::Head { .. } => self.resolve_statement(node),
+            Node::Literal { .. } | Node::Error { .. } => {}
+        }
+    }
+}
+
+/// Adds a "did you mean" help to `diag` when one of `candidates` is close to `name`.
+fn with_suggestion<'c>(diag: Diagnostic, what: &str, name: &str, candidates: impl Iterator<Item = &'c str>) -> Diagnostic {
+    match suggest(name, candidates) {
+        Some(found) => diag.with_help(format!("{} with a similar name exists: `{}`", what, found)),
+        None => diag
+    }
+}
+
+/// The closest candidate within a third of `name`'s length, preferring the
+/// alphabetically first on ties so suggestions are stable.
+pub(crate) fn suggest<'c>(name: &str, candidates: impl Iterator<Item = &'c str>) -> Option<&'c str> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| {
+            // A difference only in case is as good as a single edit.
+            let distance = if candidate.eq_ignore_ascii_case(name) { 1 } else { edit_distance(name, candidate) };
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance over chars, counting an adjacent transposition as a single edit.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The items of a `Head { next: Chain { .. } }` program.
+pub(crate) fn top_level<'n, 'a>(tree: &'n Node<'a>) -> &'n [Node<'a>] {
+    match tree {
+        Node::Head { next } => top_level(next),
+        Node::Chain { chained } => chained,
+        _ => &[]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::edit_distance;
+
+    #[test]
+    fn edit_distance_counts_single_edits() {
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("count", "coun"), 1);
+        assert_eq!(edit_distance("count", "conut"), 1);
+        assert_eq!(edit_distance("", "inc"), 3);
+        assert_eq!(edit_distance("größe", "grösse"), 2);
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::common::{
+    diagnostics::{codes, Diagnostic},
+    lexer::Span,
+    syntax_tree::{CompDescriptor, DefunDescriptor, GenericParam, ObjDescriptor, TraitDescriptor, Type}
+};
+
+pub(crate) type ScopeId = usize;
+pub(crate) type SymbolId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ScopeKind {
+    Module,
+    /// The members of an `obj`, `comp`, `trait` or `extend` block; only reachable through a
+    /// qualified path or a receiver.
+    Object,
+    Function,
+    Block
+}
+
+impl ScopeKind {
+    /// `let` may rebind a name already declared in the same function or block scope.
+    fn allows_shadowing(&self) -> bool {
+        matches!(self, Self::Function | Self::Block)
+    }
+}
+
+/// Functions provided by the runtime rather than declared in source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Builtin {
+    /// Writes its arguments separated by spaces.
+    Print,
+    /// `Print` followed by a newline.
+    Println
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 2] = [Self::Print, Self::Println];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Print => "print",
+            Self::Println => "println"
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum SymbolKind<'a> {
+    Variable(Type<'a>),
+    Parameter(Type<'a>),
+    Field(Type<'a>),
+    Function(DefunDescriptor<'a>),
+    Object(ObjDescriptor<'a>),
+    Composition(CompDescriptor<'a>),
+    Trait(TraitDescriptor<'a>),
+    Builtin(Builtin)
+}
+
+impl<'a> SymbolKind<'a> {
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Variable(_) => "variable",
+            Self::Parameter(_) => "parameter",
+            Self::Field(_) => "field",
+            Self::Function(_) => "function",
+            Self::Object(_) => "obj",
+            Self::Composition(_) => "comp",
+            Self::Trait(_) => "trait",
+            Self::Builtin(_) => "builtin function"
+        }
+    }
+
+    /// The type of a value symbol.
+    pub fn value_type(&self) -> Option<&Type<'a>> {
+        match self {
+            Self::Variable(ty) | Self::Parameter(ty) | Self::Field(ty) => Some(ty),
+            _ => None
+        }
+    }
+
+    pub fn is_callable(&self) -> bool {
+        matches!(self, Self::Function(_) | Self::Object(_) | Self::Composition(_) | Self::Builtin(_))
+    }
+
+    pub fn is_type(&self) -> bool {
+        matches!(self, Self::Object(_) | Self::Composition(_) | Self::Trait(_))
+    }
+
+    fn is_variable(&self) -> bool {
+        matches!(self, Self::Variable(_) | Self::Parameter(_))
+    }
+}
+
+pub(crate) struct Symbol<'a> {
+    pub id: SymbolId,
+    pub name: String,
+    pub qualified: String,
+    pub kind: SymbolKind<'a>,
+    pub span: Span,
+    /// The scope holding an `obj`/`comp`'s fields and methods, or a `trait`'s methods.
+    pub members: Option<ScopeId>
+}
+
+/// What looking up a method finds.
+pub(crate) enum MethodLookup<'t, 'a> {
+    Found(&'t Symbol<'a>),
+    /// The method is provided by several traits, named here, none of which comes first.
+    Ambiguous(Vec<String>),
+    Missing
+}
+
+impl<'t, 'a> MethodLookup<'t, 'a> {
+    pub fn found(self) -> Option<&'t Symbol<'a>> {
+        match self {
+            Self::Found(symbol) => Some(symbol),
+            Self::Ambiguous(_) | Self::Missing => None
+        }
+    }
+}
+
+/// An `extend Obj with Trait` block.
+pub(crate) struct Implementation<'a> {
+    pub obj: String,
+    pub with: String,
+    /// The block's parameters for a generic `obj`, bounded by the `obj`'s own bounds and
+    /// any the block adds; only arguments meeting them are extended with the trait.
+    pub generics: Vec<GenericParam<'a>>,
+    /// The arguments of a generic trait, in terms of `generics`.
+    pub trait_args: Vec<Type<'a>>,
+    /// The scope holding the block's methods, inside the `obj`'s members.
+    pub members: ScopeId,
+    pub span: Span
+}
+
+pub(crate) struct Scope {
+    kind: ScopeKind,
+    parent: Option<ScopeId>,
+    /// Prefix contributed to the qualified names of symbols defined inside.
+    name: Option<String>,
+    symbols: HashMap<String, SymbolId>
+}
+
+impl Scope {
+
+    pub fn parent(&self) -> Option<ScopeId> {
+        self.parent
+    }
+}
+
+/// Every name declared in a compilation, organised as a tree of lexical scopes. The root
+/// scope is a prelude holding the builtins, so user code may shadow them.
+///
+/// Scopes are never discarded: leaving one only moves the cursor back to its parent,
+/// so later passes can walk the same tree again through `enter_existing`.
+pub(crate) struct SymbolTable<'a> {
+    scopes: Vec<Scope>,
+    symbols: Vec<Symbol<'a>>,
+    qualified: HashMap<String, SymbolId>,
+    implementations: Vec<Implementation<'a>>,
+    current: ScopeId,
+    /// Whether `let` may rebind a variable in the module scope too, as the REPL allows.
+    rebind_globals: bool
+}
+
+impl Default for SymbolTable<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> SymbolTable<'a> {
+
+    pub fn new() -> Self {
+        let mut table = Self {
+            scopes: vec![Scope { kind: ScopeKind::Module, parent: None, name: None, symbols: HashMap::new() }],
+            symbols: Vec::new(),
+            qualified: HashMap::new(),
+            implementations: Vec::new(),
+            current: 0,
+            rebind_globals: false
+        };
+        for builtin in Builtin::ALL {
+            table.scopes[0].symbols.insert(builtin.name().to_string(), table.symbols.len());
+            table.symbols.push(Symbol {
+                id: table.symbols.len(),
+                name: builtin.name().to_string(),
+                qualified: builtin.name().to_string(),
+                kind: SymbolKind::Builtin(builtin),
+                span: Span::default(),
+                members: None
+            });
+        }
+        table.enter(ScopeKind::Module, None);
+        table
+    }
+
+    /// Lets a top-level `let` rebind an earlier top-level variable, so an interactive
+    /// session can enter the same declaration again.
+    pub fn allow_global_rebinding(&mut self) {
+        self.rebind_globals = true;
+    }
+
+    /// The module scope that top-level items are declared in.
+    pub fn root(&self) -> ScopeId {
+        1
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id]
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol<'a> {
+        &self.symbols[id]
+    }
+
+    /// Gives a variable declared without a type the one inferred for it.
+    pub fn infer(&mut self, id: SymbolId, ty: Type<'a>) {
+        if let SymbolKind::Variable(declared) = &mut self.symbols[id].kind {
+            *declared = ty;
+        }
+    }
+
+    /// Opens a new child of the current scope and makes it current.
+    pub fn enter(&mut self, kind: ScopeKind, name: Option<&str>) -> ScopeId {
+        let id = self.scopes.len();
+        self.scopes.push(Scope {
+            kind,
+            parent: Some(self.current),
+            name: name.map(str::to_string),
+            symbols: HashMap::new()
+        });
+        self.current = id;
+        id
+    }
+
+    /// Re-enters a scope created earlier, e.g. an `obj`'s members while checking its methods.
+    pub fn enter_existing(&mut self, id: ScopeId) {
+        debug_assert_eq!(self.scopes[id].parent, Some(self.current), "scope entered out of order");
+        self.current = id;
+    }
+
+    pub fn exit(&mut self) {
+        if let Some(parent) = self.scopes[self.current].parent {
+            self.current = parent;
+        }
+    }
+
+    fn qualify(&self, scope: ScopeId, name: &str) -> String {
+        let mut parts = vec![name];
+        let mut cursor = Some(scope);
+        while let Some(id) = cursor {
+            if let Some(prefix) = &self.scopes[id].name {
+                parts.push(prefix);
+            }
+            cursor = self.scopes[id].parent;
+        }
+        parts.reverse();
+        parts.join("::")
+    }
+
+    /// Declares `name` in the current scope.
+    ///
+    /// Variables may shadow anything from an enclosing scope, and `let` may rebind a variable
+    /// in the same function or block scope (or the module scope, once rebinding globals is
+    /// allowed); every other clash is a redefinition error.
+    pub fn define(&mut self, name: &str, kind: SymbolKind<'a>, span: Span) -> Result<SymbolId, Diagnostic> {
+        let scope = self.current;
+        if let Some(&previous) = self.scopes[scope].symbols.get(name) {
+            let prev = &self.symbols[previous];
+            let rebind = (self.scopes[scope].kind.allows_shadowing() || (self.rebind_globals && scope == self.root()))
+                && matches!(kind, SymbolKind::Variable(_))
+                && prev.kind.is_variable();
+            if !rebind {
+                let diag = Diagnostic::error(format!("the name `{}` is defined multiple times", name))
+                    .with_code(codes::REDEFINITION)
+                    .with_primary(span, format!("`{}` redefined here", name));
+                // Members and parameters share their item's span, so only point back when it helps.
+                return Err(if prev.span == span {
+                    diag.with_note(format!("`{}` is already a {} in this scope", name, prev.kind.describe()))
+                } else {
+                    diag.with_secondary(prev.span, format!("previous definition of the {} `{}` here", prev.kind.describe(), name))
+                });
+            }
+        }
+
+        let id = self.symbols.len();
+        let qualified = self.qualify(scope, name);
+        if !kind.is_variable() {
+            self.qualified.insert(qualified.clone(), id);
+        }
+        self.symbols.push(Symbol {
+            id,
+            name: name.to_string(),
+            qualified,
+            kind,
+            span,
+            members: None
+        });
+        self.scopes[scope].symbols.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    pub fn set_members(&mut self, symbol: SymbolId, members: ScopeId) {
+        self.symbols[symbol].members = Some(members);
+    }
+
+    /// Resolves `name` from the current scope outwards. Member scopes are skipped, so a
+    /// method body sees its fields only through `self`.
+    pub fn lookup(&self, name: &str) -> Option<&Symbol<'a>> {
+        let mut cursor = Some(self.current);
+        while let Some(id) = cursor {
+            let scope = &self.scopes[id];
+            if scope.kind != ScopeKind::Object {
+                if let Some(&symbol) = scope.symbols.get(name) {
+                    return Some(&self.symbols[symbol]);
+                }
+            }
+            cursor = scope.parent;
+        }
+        None
+    }
+
+    /// Looks `name` up in `scope` only.
+    pub fn lookup_in(&self, scope: ScopeId, name: &str) -> Option<&Symbol<'a>> {
+        self.scopes[scope].symbols.get(name).map(|&id| &self.symbols[id])
+    }
+
+    /// Looks up a function or type by its qualified name, such as `Point::len`.
+    pub fn lookup_qualified(&self, path: &str) -> Option<&Symbol<'a>> {
+        self.qualified.get(path).map(|&id| &self.symbols[id])
+    }
+
+    /// Resolves a path from a call: qualified paths go through the index, bare names
+    /// through the scope chain.
+    pub fn resolve_path(&self, path: &str) -> Option<&Symbol<'a>> {
+        if path.contains("::") {
+            self.lookup_qualified(path)
+        } else {
+            self.lookup(path)
+        }
+    }
+
+    pub fn implement(&mut self, implementation: Implementation<'a>) {
+        self.implementations.push(implementation);
+    }
+
+    /// The `extend` block implementing the trait `with` for `obj`.
+    pub fn implementation(&self, obj: &str, with: &str) -> Option<&Implementation<'a>> {
+        self.implementations.iter().find(|implementation| implementation.obj == obj && implementation.with == with)
+    }
+
+    /// Every `extend` block, in the order they were declared.
+    pub fn implementations(&self) -> &[Implementation<'a>] {
+        &self.implementations
+    }
+
+    /// The type parameters of the function or type `path`; none if it isn't generic.
+    pub fn generics_of(&self, path: &str) -> &[GenericParam<'a>] {
+        match self.lookup_qualified(path).map(|symbol| &symbol.kind) {
+            Some(SymbolKind::Function(defun)) => defun.generics(),
+            Some(SymbolKind::Object(obj)) => obj.generics(),
+            Some(SymbolKind::Composition(comp)) => comp.generics(),
+            Some(SymbolKind::Trait(tr)) => tr.generics(),
+            _ => &[]
+        }
+    }
+
+    /// Whether a value of type `ty` meets the trait bound `bound`, such as `Show` or
+    /// `Into<Int32>`. `in_scope` are the type parameters `ty` may refer to.
+    ///
+    /// An `obj` meets it when it is extended with the trait, for those type arguments of a
+    /// generic trait, and its own arguments meet the bounds of the `extend` block. A trait
+    /// object or a type parameter meets the traits its trait or bounds require.
+    pub fn satisfies(&self, ty: &Type<'a>, bound: &Type<'a>, in_scope: &[GenericParam<'a>]) -> bool {
+        let Some(with) = bound.type_name() else {
+            return false;
+        };
+        // A trait required through supertraits can't take type arguments.
+        let requires = |tr: &Type<'a>| tr.type_name().is_some_and(|name| {
+            if name == with { tr.type_args() == bound.type_args() } else { self.requires(name, with) }
+        });
+        match ty {
+            Type::Param(name) => in_scope.iter()
+                .filter(|param| param.name() == name)
+                .flat_map(|param| param.bounds())
+                .any(requires),
+            Type::SafePtr(inner) if self.dispatch_trait(ty).is_some() => requires(inner),
+            _ if self.dispatch_trait(ty).is_some() => requires(ty),
+            _ => {
+                let Some(implementation) = ty.type_name().and_then(|obj| self.implementation(obj, with)) else {
+                    return false;
+                };
+                let args = ty.type_args();
+                let trait_args: Vec<Type<'a>> = implementation.trait_args.iter()
+                    .map(|arg| arg.instantiate(&implementation.generics, args))
+                    .collect();
+                trait_args == bound.type_args()
+                    && implementation.generics.iter().zip(args).all(|(param, arg)| param.bounds().iter()
+                        .all(|bound| self.satisfies(arg, &bound.instantiate(&implementation.generics, args), in_scope)))
+            }
+        }
+    }
+
+    /// A function or method by its qualified name.
+    fn function(&self, path: &str) -> Option<&Symbol<'a>> {
+        self.lookup_qualified(path).filter(|symbol| matches!(symbol.kind, SymbolKind::Function(_)))
+    }
+
+    pub fn is_trait(&self, name: &str) -> bool {
+        self.lookup_qualified(name).is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Trait(_)))
+    }
+
+    /// The names of the traits `with` directly requires.
+    pub fn super_traits(&self, with: &str) -> Vec<String> {
+        match self.lookup_qualified(with).map(|symbol| &symbol.kind) {
+            Some(SymbolKind::Trait(tr)) => tr.super_traits().iter().map(|super_trait| super_trait.name().to_string()).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// `with` and every trait it requires, directly or not, nearest first; a trait
+    /// reachable along several paths comes once, at its shortest distance.
+    fn trait_levels(&self, with: &str) -> Vec<Vec<String>> {
+        let mut seen = HashSet::from([with.to_string()]);
+        let mut levels = vec![vec![with.to_string()]];
+        loop {
+            let next: Vec<String> = levels[levels.len() - 1].iter()
+                .flat_map(|tr| self.super_traits(tr))
+                .filter(|tr| seen.insert(tr.clone()))
+                .collect();
+            if next.is_empty() {
+                return levels;
+            }
+            levels.push(next);
+        }
+    }
+
+    /// Whether the trait `with` is `other` or requires it, directly or not.
+    pub fn requires(&self, with: &str, other: &str) -> bool {
+        self.trait_levels(with).iter().flatten().any(|tr| tr == other)
+    }
+
+    /// The method `name` of a value whose type is `owner`.
+    ///
+    /// On an `obj`, its own methods come first, then the traits it is extended with,
+    /// whose supertraits the `obj` must be extended with as well; the implementation in
+    /// the `extend` block is preferred over a default body. On a trait, the methods it
+    /// declares come first, then those of its supertraits, nearest first. Two traits
+    /// providing the method at the same point of that order make the call ambiguous.
+    pub fn method(&self, owner: &str, name: &str) -> MethodLookup<'_, 'a> {
+        if self.is_trait(owner) {
+            for level in self.trait_levels(owner) {
+                let found: Vec<(&String, &Symbol<'a>)> = level.iter()
+                    .filter_map(|tr| Some((tr, self.function(&format!("{}::{}", tr, name))?)))
+                    .collect();
+                match found.as_slice() {
+                    [] => continue,
+                    [(_, symbol)] => return MethodLookup::Found(symbol),
+                    _ => return MethodLookup::Ambiguous(found.iter().map(|(tr, _)| tr.to_string()).collect())
+                }
+            }
+            return MethodLookup::Missing;
+        }
+
+        if let Some(symbol) = self.function(&format!("{}::{}", owner, name)) {
+            return MethodLookup::Found(symbol);
+        }
+        let found: Vec<(&String, &Symbol<'a>)> = self.implementations.iter()
+            .filter(|implementation| implementation.obj == owner)
+            .filter_map(|implementation| {
+                let with = &implementation.with;
+                let symbol = self.function(&format!("{}::{}::{}", owner, with, name))
+                    .or_else(|| self.function(&format!("{}::{}", with, name)))?;
+                Some((with, symbol))
+            })
+            .collect();
+        match found.as_slice() {
+            [] => MethodLookup::Missing,
+            [(_, symbol)] => MethodLookup::Found(symbol),
+            _ => MethodLookup::Ambiguous(found.iter().map(|(with, _)| with.to_string()).collect())
+        }
+    }
+
+    /// The function an instance of `obj` runs for the trait method `declared`: the
+    /// implementation in its `extend` block, or else the trait's default body.
+    pub fn implementing(&self, obj: &str, declared: &Symbol<'a>) -> Option<&Symbol<'a>> {
+        let (with, name) = declared.qualified.rsplit_once("::")?;
+        if let Some(symbol) = self.function(&format!("{}::{}::{}", obj, with, name)) {
+            return Some(symbol);
+        }
+        let SymbolKind::Trait(tr) = &self.lookup_qualified(with)?.kind else {
+            return None;
+        };
+        tr.has_default(name).then(|| self.function(&declared.qualified))?
+    }
+
+    /// The methods of `with` and its supertraits, in lookup order, that a value of type
+    /// `with` can be called with; a name already seen in a nearer trait is skipped.
+    pub fn trait_methods(&self, with: &str) -> Vec<&Symbol<'a>> {
+        let mut names = HashSet::new();
+        let mut methods = Vec::new();
+        for tr in self.trait_levels(with).into_iter().flatten() {
+            let Some(SymbolKind::Trait(descriptor)) = self.lookup_qualified(&tr).map(|symbol| &symbol.kind) else {
+                continue;
+            };
+            for defun in descriptor.functions() {
+                let name = defun.name().as_slice();
+                if let Some(symbol) = self.function(&format!("{}::{}", tr, name)).filter(|_| names.insert(name.to_string())) {
+                    methods.push(symbol);
+                }
+            }
+        }
+        methods
+    }
+
+    /// The trait a method call on a value of type `ty` goes through: that of a trait
+    /// object, or the trait a default body belongs to for its `self`.
+    pub fn dispatch_trait<'t>(&self, ty: &'t Type<'_>) -> Option<&'t str> {
+        let name = match ty {
+            Type::SafePtr(inner) => inner.type_name()?,
+            _ => ty.type_name()?
+        };
+        self.is_trait(name).then_some(name)
+    }
+
+    /// The fields of the `obj` or `comp` named by `ty`, in declaration order, with the
+    /// type arguments of a generic one filled in.
+    pub fn fields_of(&self, ty: &Type<'a>) -> Option<Vec<(String, Type<'a>)>> {
+        let (generics, fields) = match &self.lookup_qualified(ty.type_name()?)?.kind {
+            SymbolKind::Object(obj) => (obj.generics(), obj.fields()),
+            SymbolKind::Composition(comp) => (comp.generics(), comp.fields()),
+            _ => return None
+        };
+        Some(fields.iter()
+            .filter_map(|field| Some((field.name()?.to_string(), field.ty().instantiate(generics, ty.type_args()))))
+            .collect())
+    }
+
+    /// Every symbol reachable by a bare name from the current scope, innermost first.
+    pub fn visible(&self) -> impl Iterator<Item = &Symbol<'a>> {
+        let mut cursor = Some(self.current);
+        std::iter::from_fn(move || {
+            let id = cursor?;
+            cursor = self.scopes[id].parent;
+            Some(id)
+        })
+        .filter(|&id| self.scopes[id].kind != ScopeKind::Object)
+        .flat_map(|id| self.scopes[id].symbols.values().map(|&symbol| &self.symbols[symbol]))
+    }
+
+    /// Every function, type and member, reachable by its qualified name.
+    pub fn qualified_symbols(&self) -> impl Iterator<Item = &Symbol<'a>> {
+        self.qualified.values().map(|&id| &self.symbols[id])
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+
+use crate::{
+    analysis::{resolve::suggest, symbols::SymbolKind},
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        session::Session,
+        syntax_tree::{Bodies, DefunDescriptor, ExtendDescriptor, GenericParam, Node, TraitDescriptor, Type}
+    }
+};
+
+/// Checks trait declarations and the `extend` blocks implementing them, once the resolver
+/// has declared every item: supertraits must be traits without cycles, and each block must
+/// implement exactly the methods of its trait, with their signatures, for an `obj` that is
+/// also extended with the trait's supertraits.
+pub(crate) struct TraitChecker<'s, 'a> {
+    session: &'s mut Session<'a>,
+    /// Traits already reported as part of a supertrait cycle.
+    cyclic: HashSet<String>
+}
+
+impl<'s, 'a> TraitChecker<'s, 'a> {
+
+    pub fn new(session: &'s mut Session<'a>) -> Self {
+        Self {
+            session,
+            cyclic: HashSet::new()
+        }
+    }
+
+    pub fn check_program(mut self, items: &[Node<'a>]) {
+        for item in items {
+            let Node::Body { discriptor, body, span } = item else {
+                continue;
+            };
+            match discriptor.as_ref() {
+                Bodies::Trait(tr) => self.check_trait(tr, *span),
+                Bodies::Extension(extension) => self.check_extension(extension, body, *span),
+                _ => {}
+            }
+        }
+    }
+
+    fn error(&mut self, diag: Diagnostic) {
+        self.session.report(diag);
+    }
+
+    /// The trait called `name` and where it is declared.
+    fn lookup_trait(&self, name: &str) -> Option<(TraitDescriptor<'a>, Span)> {
+        let symbol = self.session.symbols.lookup_qualified(name)?;
+        match &symbol.kind {
+            SymbolKind::Trait(tr) => Some((tr.clone(), symbol.span)),
+            _ => None
+        }
+    }
+
+    /// Where the method `name` of the trait `with` is declared.
+    fn declaration(&self, with: &str, name: &str) -> Option<Span> {
+        self.session.symbols.lookup_qualified(&format!("{}::{}", with, name)).map(|symbol| symbol.span)
+    }
+
+    fn check_trait(&mut self, tr: &TraitDescriptor<'a>, span: Span) {
+        let name = tr.name().as_slice();
+        for super_trait in tr.super_traits() {
+            let super_name = super_trait.name().as_slice();
+            let found = self.session.symbols.lookup_qualified(super_name)
+                .map(|symbol| (matches!(symbol.kind, SymbolKind::Trait(_)), symbol.kind.describe()));
+            match found {
+                Some((true, _)) if !self.session.symbols.generics_of(super_name).is_empty() => {
+                    self.error(Diagnostic::error(format!("the generic trait `{}` can't be a supertrait", super_name))
+                        .with_code(codes::WRONG_TYPE_ARG_COUNT)
+                        .with_primary(span, format!("`{}` takes type arguments", super_name))
+                        .with_note("supertraits are named without type arguments"));
+                },
+                Some((true, _)) => {},
+                Some((false, found)) => self.error(Diagnostic::error(format!("expected trait, found {} `{}`", found, super_name))
+                    .with_code(codes::UNKNOWN_TYPE)
+                    .with_primary(span, format!("`{}` can't be a supertrait", super_name))),
+                None => {
+                    let candidates: Vec<&str> = self.session.symbols.qualified_symbols()
+                        .filter(|symbol| matches!(symbol.kind, SymbolKind::Trait(_)))
+                        .map(|symbol| symbol.qualified.as_str())
+                        .collect();
+                    let diag = Diagnostic::error(format!("cannot find trait `{}`", super_name))
+                        .with_code(codes::UNKNOWN_TYPE)
+                        .with_primary(span, "not found in this scope");
+                    let diag = match suggest(super_name, candidates.into_iter()) {
+                        Some(found) => diag.with_help(format!("a trait with a similar name exists: `{}`", found)),
+                        None => diag
+                    };
+                    self.error(diag);
+                }
+            }
+        }
+
+        if self.cyclic.contains(name) {
+            return;
+        }
+        let cycle = tr.super_traits().iter()
+            .find_map(|super_trait| self.path(super_trait.name().as_slice(), name, &mut HashSet::new()));
+        if let Some(cycle) = cycle {
+            self.cyclic.extend(cycle.iter().cloned());
+            let chain: Vec<String> = std::iter::once(name).chain(cycle.iter().map(String::as_str))
+                .map(|name| format!("`{}`", name))
+                .collect();
+            self.error(Diagnostic::error(format!("the supertraits of `{}` depend on `{}` itself", name, name))
+                .with_code(codes::CYCLIC_SUPERTRAITS)
+                .with_primary(span, "cycle in the supertraits")
+                .with_note(format!("{} is a cycle", chain.join(" requires "))));
+        }
+    }
+
+    /// A chain of supertraits leading from `from` to `to`, both included.
+    fn path(&self, from: &str, to: &str, seen: &mut HashSet<String>) -> Option<Vec<String>> {
+        if from == to {
+            return Some(vec![to.to_string()]);
+        }
+        if !seen.insert(from.to_string()) {
+            return None;
+        }
+        let (tr, _) = self.lookup_trait(from)?;
+        tr.super_traits().iter().find_map(|super_trait| {
+            let mut path = self.path(super_trait.name().as_slice(), to, seen)?;
+            path.insert(0, from.to_string());
+            Some(path)
+        })
+    }
+
+    fn check_extension(&mut self, extension: &ExtendDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        let (obj, with) = (extension.obj().as_slice(), extension.with().as_slice());
+        // Blocks naming something that isn't an `obj` and a trait, or a trait the `obj` was
+        // already extended with, have been reported by the resolver.
+        if self.session.symbols.implementation(obj, with).is_none_or(|implementation| implementation.span != span) {
+            return;
+        }
+        let Some((tr, _)) = self.lookup_trait(with) else {
+            return;
+        };
+        let trait_args = extension.trait_args();
+
+        for method in body {
+            let Node::Body { discriptor, span: method_span, .. } = method else {
+                continue;
+            };
+            let Bodies::Defun(defun) = discriptor.as_ref() else {
+                continue;
+            };
+            let name = defun.name().as_slice();
+            match tr.functions().iter().find(|declared| declared.name().as_slice() == name) {
+                Some(declared) => self.check_signature(with, &tr, trait_args, declared, defun, *method_span),
+                None => {
+                    let diag = Diagnostic::error(format!("method `{}` is not a member of trait `{}`", name, with))
+                        .with_code(codes::NOT_A_TRAIT_METHOD)
+                        .with_primary(*method_span, format!("not a member of `{}`", with));
+                    let declared = tr.functions().iter().map(|declared| declared.name().as_slice());
+                    let diag = match suggest(name, declared) {
+                        Some(found) => diag.with_help(format!("a method with a similar name exists: `{}`", found)),
+                        None => diag.with_note(format!("methods of an `obj` that aren't part of `{}` go in the `obj` itself", with))
+                    };
+                    self.error(diag);
+                }
+            }
+        }
+
+        let implemented: Vec<&str> = extension.functions().iter().map(|defun| defun.name().as_slice()).collect();
+        let missing: Vec<&str> = tr.functions().iter()
+            .map(|declared| declared.name().as_slice())
+            .filter(|name| !tr.has_default(name) && !implemented.contains(name))
+            .collect();
+        if !missing.is_empty() {
+            let names: Vec<String> = missing.iter().map(|name| format!("`{}`", name)).collect();
+            let mut diag = Diagnostic::error(format!("not all methods of `{}` are implemented for `{}`", with, obj))
+                .with_code(codes::MISSING_TRAIT_METHOD)
+                .with_primary(span, format!("missing {}", names.join(", ")));
+            for name in &missing {
+                if let Some(declared) = self.declaration(with, name) {
+                    diag = diag.with_secondary(declared, format!("`{}` is declared here", name));
+                }
+            }
+            self.error(diag);
+        }
+
+        for super_trait in tr.super_traits() {
+            let super_name = super_trait.name().as_slice();
+            if self.lookup_trait(super_name).is_some() && self.session.symbols.implementation(obj, super_name).is_none() {
+                self.error(Diagnostic::error(format!("`{}` is extended with `{}` but not with its supertrait `{}`", obj, with, super_name))
+                    .with_code(codes::UNSATISFIED_SUPERTRAIT)
+                    .with_primary(span, format!("`{}` requires `{}`", with, super_name))
+                    .with_help(format!("add an `extend {} with {}` block", obj, super_name)));
+            }
+        }
+    }
+
+    /// Whether `implemented` takes `self` when `declared` does, and the same parameters
+    /// and return type otherwise, once a generic trait's arguments are filled in.
+    fn check_signature(
+        &mut self,
+        with: &str,
+        tr: &TraitDescriptor<'a>,
+        trait_args: &[Type<'a>],
+        declared: &DefunDescriptor<'a>,
+        implemented: &DefunDescriptor<'a>,
+        span: Span
+    ) {
+        // The methods' own parameters may be named differently.
+        let own: Vec<Type<'a>> = implemented.generics().iter().map(GenericParam::as_type).collect();
+        let instantiate = |ty: &Type<'a>| ty.instantiate(tr.generics(), trait_args).instantiate(declared.generics(), &own);
+        let declared_params: Vec<Type<'a>> = params(declared).iter().map(instantiate).collect();
+        if takes_self(declared) == takes_self(implemented)
+            && declared.generics().len() == implemented.generics().len()
+            && declared_params == params(implemented)
+            && instantiate(declared.return_type()) == *implemented.return_type() {
+            return;
+        }
+        let name = declared.name().as_slice();
+        let diag = Diagnostic::error(format!("method `{}` doesn't match its declaration in `{}`", name, with))
+            .with_code(codes::SIGNATURE_MISMATCH)
+            .with_primary(span, format!("found `{}`", signature(implemented)));
+        let diag = match self.declaration(with, name) {
+            Some(at) => diag.with_secondary(at, format!("declared as `{}`", signature(declared))),
+            None => diag
+        };
+        self.error(diag);
+    }
+}
+
+fn takes_self(defun: &DefunDescriptor<'_>) -> bool {
+    defun.args().first().and_then(|arg| arg.name()).is_some_and(|name| name.as_slice() == "self")
+}
+
+/// The types of the parameters other than `self`, whose type differs between a trait and
+/// the `obj` implementing it.
+fn params<'a>(defun: &DefunDescriptor<'a>) -> Vec<Type<'a>> {
+    defun.args().iter().skip(usize::from(takes_self(defun))).map(|arg| arg.ty()).collect()
+}
+
+fn signature(defun: &DefunDescriptor<'_>) -> String {
+    let args: Vec<String> = defun.args().iter().map(|arg| match arg.name() {
+        Some(name) if name.as_slice() == "self" => "self".to_string(),
+        Some(name) => format!("{}: {}", name, arg.ty()),
+        None => arg.ty().to_string()
+    }).collect();
+    let generics: Vec<&str> = defun.generics().iter().map(|param| param.name().as_slice()).collect();
+    let generics = if generics.is_empty() { String::new() } else { format!("<{}>", generics.join(", ")) };
+    format!("defun {}{}({}) => {}", defun.name(), generics, args.join(", "), defun.return_type())
+}
@@ -0,0 +1,1255 @@
+use std::collections::HashMap;
+
+use crate::{
+    analysis::{
+        infer::{Inference, Kind},
+        resolve::{top_level, Resolutions},
+        symbols::{MethodLookup, Symbol, SymbolId, SymbolKind}
+    },
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        session::Session,
+        syntax_tree::{BinOp, Bodies, DefunDescriptor, GenericParam, Literal, Node, Type, UniOp, VarDeclaration},
+        yarn::Yarn
+    }
+};
+
+/// The type assigned to each expression, keyed by the expression's span.
+#[derive(Default)]
+pub(crate) struct TypeTable<'a> {
+    types: HashMap<Span, Type<'a>>,
+    /// Expressions whose value is turned into a trait object, with the object's type.
+    coercions: HashMap<Span, Type<'a>>,
+    /// Calls of generic functions, methods and constructors, with the type arguments they
+    /// were inferred to take: the owner's first, for a method of a generic `obj` or trait.
+    instances: HashMap<Span, Vec<Type<'a>>>,
+    /// Method calls on a value of a type parameter, with the trait of the bound that
+    /// provides the method.
+    through: HashMap<Span, String>
+}
+
+impl<'a> TypeTable<'a> {
+    pub fn get(&self, node: &Node<'_>) -> Option<&Type<'a>> {
+        self.types.get(&node.span())
+    }
+
+    /// The trait object type `node`'s value is used as, if it isn't one already.
+    pub fn coercion(&self, node: &Node<'_>) -> Option<&Type<'a>> {
+        self.coercions.get(&node.span())
+    }
+
+    /// The type arguments a call of a generic item takes.
+    pub fn instance(&self, node: &Node<'_>) -> Option<&[Type<'a>]> {
+        self.instances.get(&node.span()).map(Vec::as_slice)
+    }
+
+    /// The type a `let` declares: for one written without a type, the type inferred.
+    pub fn declared(&self, value: &Node<'_>, ret: &VarDeclaration<'a>) -> Type<'a> {
+        match (ret, self.get(value)) {
+            (VarDeclaration::Inferred { .. }, Some(ty)) => ty.clone(),
+            _ => ret.ty()
+        }
+    }
+
+    /// The trait a method call on a value of a type parameter goes through.
+    pub fn through(&self, node: &Node<'_>) -> Option<&str> {
+        self.through.get(&node.span()).map(String::as_str)
+    }
+
+    pub fn extend(&mut self, other: TypeTable<'a>) {
+        self.types.extend(other.types);
+        self.coercions.extend(other.coercions);
+        self.instances.extend(other.instances);
+        self.through.extend(other.through);
+    }
+
+    fn insert(&mut self, span: Span, ty: Type<'a>) {
+        self.types.insert(span, ty);
+    }
+}
+
+/// What a call is checked against: the callee's parameter and return types, in terms of
+/// its type parameters. The arguments of the leading parameters may be known already,
+/// like those of the `obj` a method is called on; the others are inferred.
+struct Signature<'a> {
+    generics: Vec<GenericParam<'a>>,
+    known: Vec<Type<'a>>,
+    params: Vec<Type<'a>>,
+    ret: Type<'a>
+}
+
+impl<'a> Signature<'a> {
+    fn new(params: Vec<Type<'a>>, ret: Type<'a>) -> Self {
+        Self {
+            generics: Vec::new(),
+            known: Vec::new(),
+            params,
+            ret
+        }
+    }
+}
+
+/// Types a program whose names the resolver has already bound.
+///
+/// A generic item is checked once, with its type parameters standing for types that
+/// only have the methods of their bounds. Each call of one infers its type arguments
+/// from the arguments and, failing that, from the type the result is expected to have.
+///
+/// A `let` without a type gets an inference variable, which its initializer and later
+/// uses decide; once the whole program is checked, the variables still open take their
+/// default types and every type recorded is resolved.
+pub(crate) struct TypeChecker<'s, 'a> {
+    session: &'s mut Session<'a>,
+    resolutions: &'s Resolutions,
+    table: TypeTable<'a>,
+    /// The type parameters of the items being checked, innermost last.
+    generics: Vec<GenericParam<'a>>,
+    inference: Inference<'a>,
+    /// The variables declared without a type, with the inference variable of each.
+    inferred: Vec<(SymbolId, Type<'a>)>,
+    /// Uses of those variables, with their inference variables, to explain mismatches.
+    uses: HashMap<Span, u32>,
+    /// The return type of the function being checked, for its `return`s.
+    returns: Option<Type<'a>>
+}
+
+impl<'s, 'a> TypeChecker<'s, 'a> {
+
+    pub fn new(session: &'s mut Session<'a>, resolutions: &'s Resolutions) -> Self {
+        Self {
+            session,
+            resolutions,
+            table: TypeTable::default(),
+            generics: Vec::new(),
+            inference: Inference::default(),
+            inferred: Vec::new(),
+            uses: HashMap::new(),
+            returns: None
+        }
+    }
+
+    /// Checks a whole program. Every expression that could be typed gets an entry in the
+    /// returned table; everything else is explained by the session's diagnostics.
+    pub fn check_program(mut self, tree: &Node<'a>) -> TypeTable<'a> {
+        for item in top_level(tree) {
+            self.check_item(item);
+        }
+        self.finish_inference();
+        self.table
+    }
+
+    /// Defaults the inference variables still open, checks the literals they hold and
+    /// replaces every variable in the table, and in the symbols, by its type.
+    fn finish_inference(&mut self) {
+        for id in self.inference.settle() {
+            let (name, origin) = self.inference.origin(id);
+            let diag = Diagnostic::error(format!("cannot infer the type of `{}`", name))
+                .with_code(codes::CANNOT_INFER)
+                .with_primary(origin, "type annotations needed")
+                .with_help(format!("give `{}` a type: `let {}: Type`", name, name));
+            self.error(diag);
+        }
+        for (id, ty, value, span) in self.inference.literals() {
+            if let Some(diag) = out_of_range(&ty, value, span) {
+                let diag = self.explain(diag, id);
+                self.error(diag);
+            }
+        }
+
+        let inference = &self.inference;
+        for ty in self.table.types.values_mut().chain(self.table.coercions.values_mut()).chain(self.table.instances.values_mut().flatten()) {
+            *ty = inference.resolve(ty);
+        }
+        for (symbol, var) in std::mem::take(&mut self.inferred) {
+            let ty = self.inference.resolve(&var);
+            self.session.symbols.infer(symbol, ty);
+        }
+    }
+
+    fn error(&mut self, diag: Diagnostic) {
+        self.session.report(diag);
+    }
+
+    fn mismatch(&mut self, span: Span, expected: &Type<'a>, found: &Type<'a>) {
+        let diag = self.mismatched(span, expected, found);
+        let diag = match self.uses.get(&span) {
+            Some(&id) => self.explain(diag, id),
+            None => diag
+        };
+        self.error(diag);
+    }
+
+    /// Explains the types of the `operands` that are uses of variables declared without one.
+    fn explain_uses(&self, diag: Diagnostic, operands: &[&Node<'a>]) -> Diagnostic {
+        operands.iter()
+            .filter_map(|operand| self.uses.get(&operand.span()))
+            .fold(diag, |diag, &id| self.explain(diag, id))
+    }
+
+    fn mismatched(&self, span: Span, expected: &Type<'a>, found: &Type<'a>) -> Diagnostic {
+        let (expected, found) = (&self.inference.resolve(expected), &self.inference.resolve(found));
+        let diag = Diagnostic::error("mismatched types")
+            .with_code(codes::MISMATCHED_TYPES)
+            .with_primary(span, format!("expected `{}`, found `{}`", self.inference.describe(expected), self.inference.describe(found)));
+        let symbols = &self.session.symbols;
+        match (symbols.dispatch_trait(expected), found.type_name(), symbols.dispatch_trait(found)) {
+            (Some(with), Some(obj), _) if matches!(expected, Type::SafePtr(_)) && !symbols.is_trait(obj) =>
+                diag.with_note(format!("`{}` is not extended with `{}`", obj, with)),
+            (Some(with), _, Some(_)) if matches!((expected, found), (Type::SafePtr(_), Type::SafePtr(_))) =>
+                diag.with_note(format!("trait objects can't be converted between traits; convert the `obj` to `*{}` instead", with)),
+            _ => diag
+        }
+    }
+
+    /// Points out where the type of the variable whose inference variable is `id` was decided.
+    fn explain(&self, diag: Diagnostic, id: u32) -> Diagnostic {
+        let (name, decided) = self.inference.origin(id);
+        diag.with_secondary(decided, format!("`{}` was inferred to be `{}` here", name, self.inference.describe(&Type::Var(id))))
+    }
+
+    /// Whether `node`, of type `found`, can be used where a value of type `expected` is.
+    /// Besides the same type, a trait object takes an `obj` extended with its trait, and
+    /// the `self` of a default body whose trait is or requires it; lowering needs to know
+    /// about those, so they are recorded.
+    fn coerces(&mut self, node: &Node<'a>, expected: &Type<'a>, found: &Type<'a>) -> bool {
+        let (expected, found) = (&self.inference.resolve(expected), &self.inference.resolve(found));
+        if found == expected {
+            return true;
+        }
+        if (self.inference.is_open(expected) || self.inference.is_open(found)) && self.inference.unify(expected, found, node.span()) {
+            return true;
+        }
+        let symbols = &self.session.symbols;
+        let (Type::SafePtr(with), Some(_)) = (expected, symbols.dispatch_trait(expected)) else {
+            return false;
+        };
+        let valid = !matches!(found, Type::SafePtr(_)) && symbols.satisfies(found, with, &self.generics);
+        if valid {
+            self.table.coercions.insert(node.span(), expected.clone());
+        }
+        valid
+    }
+
+    /// Whether every user-defined type inside `ty` exists; the resolver reports those that don't.
+    fn is_known(&self, ty: &Type<'a>) -> bool {
+        match ty {
+            Type::UnsafePtr(inner) | Type::SafePtr(inner) | Type::Array(inner, _) | Type::Slice(inner) => self.is_known(inner),
+            Type::Named(name) => self.session.symbols.lookup_qualified(name.as_slice()).is_some_and(|symbol| symbol.kind.is_type()),
+            Type::Applied(name, args) => self.session.symbols.lookup_qualified(name.as_slice()).is_some_and(|symbol| symbol.kind.is_type())
+                && self.session.symbols.generics_of(name.as_slice()).len() == args.len()
+                && args.iter().all(|arg| self.is_known(arg)),
+            _ => true
+        }
+    }
+
+    /// Checks `check` with `generics` in scope.
+    fn with_generics<T>(&mut self, generics: &[GenericParam<'a>], check: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.generics.len();
+        self.generics.extend(generics.iter().cloned());
+        let result = check(self);
+        self.generics.truncate(outer);
+        result
+    }
+
+    fn check_item(&mut self, item: &Node<'a>) {
+        match item {
+            Node::Body { discriptor, body, span } => match discriptor.as_ref() {
+                Bodies::Defun(defun) => self.check_defun(defun, body, *span),
+                Bodies::Object(obj) => self.with_generics(obj.generics(), |checker| {
+                    for method in body {
+                        checker.check_item(method);
+                    }
+                }),
+                Bodies::Extension(extension) => {
+                    // The implementation knows the bounds the `obj` adds to the block's parameters.
+                    let generics = self.session.symbols.implementations().iter()
+                        .find(|implementation| implementation.span == *span)
+                        .map_or_else(|| extension.generics().to_vec(), |implementation| implementation.generics.clone());
+                    self.with_generics(&generics, |checker| {
+                        for method in body {
+                            checker.check_item(method);
+                        }
+                    });
+                },
+                Bodies::Trait(tr) => self.with_generics(tr.generics(), |checker| {
+                    for method in body {
+                        if let Node::Body { discriptor, body, span } = method {
+                            match discriptor.as_ref() {
+                                Bodies::Defun(defun) if tr.has_default(defun.name().as_slice()) => checker.check_defun(defun, body, *span),
+                                _ => {}
+                            }
+                        }
+                    }
+                }),
+                Bodies::Composition(_) => {}
+            },
+            other => {
+                self.check_statement(other);
+            }
+        }
+    }
+
+    fn check_defun(&mut self, defun: &DefunDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        self.with_generics(defun.generics(), |checker| checker.check_body(defun, body, span));
+    }
+
+    fn check_body(&mut self, defun: &DefunDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        let return_type = defun.return_type().clone();
+        let expected = (!return_type.is_void()).then_some(&return_type);
+        let outer = self.returns.replace(return_type.clone());
+        let found = self.check_block(body, expected);
+        self.returns = outer;
+
+        // A body that never reaches its end returns through its `return`s, checked already.
+        if return_type.is_void() || !self.is_known(&return_type) || !block_flow(body).completes {
+            return;
+        }
+
+        match found {
+            Some(Type::Void) => {
+                let at = body.last().map(|node| node.span()).unwrap_or(span);
+                let diag = Diagnostic::error(format!("function `{}` must return a value of type `{}`", defun.qualified(), return_type))
+                    .with_code(codes::MISSING_RETURN_VALUE)
+                    .with_primary(at, "the last statement does not produce a value")
+                    .with_help("end the body with an expression of the return type");
+                self.error(match body.last() {
+                    Some(Node::If { els: None, .. }) => diag.with_note("an `if` without an `else` has no value when its condition is false"),
+                    _ => diag
+                });
+            },
+            Some(found) => {
+                match body.last() {
+                    Some(tail) if self.coerces(tail, &return_type, &found) => {},
+                    Some(tail) => self.mismatch(tail.span(), &return_type, &found),
+                    None if found != return_type => self.mismatch(span, &return_type, &found),
+                    None => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// The block's type is that of its last statement. The first statement after one that
+    /// never completes is warned about, as none of those after it run either.
+    fn check_block(&mut self, stmts: &[Node<'a>], expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let mut last = Some(Type::Void);
+        let mut warned = false;
+        for (idx, stmt) in stmts.iter().enumerate() {
+            last = if idx + 1 == stmts.len() {
+                self.check_tail(stmt, expected)
+            } else {
+                self.check_statement(stmt)
+            };
+            match stmts.get(idx + 1) {
+                Some(next) if !warned && !matches!(next, Node::Body { .. }) && !flow(stmt).completes => {
+                    self.error(Diagnostic::warning("unreachable statement")
+                        .with_primary(next.span(), "unreachable statement")
+                        .with_secondary(stmt.span(), "any code following this is unreachable"));
+                    warned = true;
+                },
+                _ => {}
+            }
+        }
+        last
+    }
+
+    fn check_tail(&mut self, stmt: &Node<'a>, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        match stmt {
+            Node::Value { .. } | Node::Chain { .. } => self.check_statement(stmt),
+            Node::BinaryOp { op, .. } if op.is_assignment() => self.check_statement(stmt),
+            expr => self.check_expr(expr, expected)
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &Node<'a>) -> Option<Type<'a>> {
+        match stmt {
+            Node::Value { ret, span } => {
+                if matches!(ret, VarDeclaration::Inferred { .. }) {
+                    self.declare_inferred(stmt, ret, *span);
+                }
+                Some(Type::Void)
+            },
+            Node::BinaryOp { lhs, rhs, op: BinOp::Assign, .. } if matches!(lhs.as_ref(), Node::Value { .. }) => {
+                let Node::Value { ret, .. } = lhs.as_ref() else {
+                    return None;
+                };
+                let ty = match ret {
+                    VarDeclaration::Inferred { .. } => self.declare_inferred(lhs, ret, rhs.span()),
+                    _ => ret.ty()
+                };
+                if self.is_known(&ty) {
+                    if let Some(found) = self.check_expr(rhs, Some(&ty)) {
+                        if found.is_void() && matches!(ret, VarDeclaration::Inferred { .. }) {
+                            self.error(Diagnostic::error("variables cannot have type `Void`")
+                                .with_code(codes::VOID_DECLARATION)
+                                .with_primary(rhs.span(), "this expression has no value"));
+                            self.inference.unify(&ty, &Type::Infer, rhs.span());
+                        } else if !self.coerces(rhs, &ty, &found) {
+                            self.mismatch(rhs.span(), &ty, &found);
+                        }
+                    } else if matches!(ret, VarDeclaration::Inferred { .. }) {
+                        // The initializer's error has been reported; the variable's uses
+                        // shouldn't add that its type is unknown as well.
+                        self.inference.unify(&ty, &Type::Infer, rhs.span());
+                    }
+                } else {
+                    self.check_expr(rhs, None);
+                }
+                Some(Type::Void)
+            },
+            Node::Chain { chained } => {
+                self.check_block(chained, None);
+                Some(Type::Void)
+            },
+            Node::Body { .. } => {
+                self.check_item(stmt);
+                Some(Type::Void)
+            },
+            expr => self.check_expr(expr, None)
+        }
+    }
+
+    /// Gives the variable `value` declares without a type an inference variable, which
+    /// `origin` is the first to decide.
+    fn declare_inferred(&mut self, value: &Node<'a>, ret: &VarDeclaration<'a>, origin: Span) -> Type<'a> {
+        let name = ret.name().map(Yarn::as_slice).unwrap_or_default();
+        let var = self.inference.fresh(name, origin);
+        if let Some(symbol) = self.resolutions.get(value) {
+            self.session.symbols.infer(symbol, var.clone());
+            self.inferred.push((symbol, var.clone()));
+        }
+        self.table.insert(value.span(), var.clone());
+        var
+    }
+
+    /// Types `node`, using `expected` to give unannotated literals a type.
+    pub fn check_expr(&mut self, node: &Node<'a>, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let ty = self.infer_expr(node, expected)?;
+        let ty = self.inference.resolve(&ty);
+        // A variable whose type couldn't be inferred, which has been reported already.
+        if ty == Type::Infer {
+            return None;
+        }
+        self.table.insert(node.span(), ty.clone());
+        Some(ty)
+    }
+
+    fn infer_expr(&mut self, node: &Node<'a>, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        match node {
+            Node::Literal { value, span } => self.check_literal(value, *span, expected, false),
+            Node::Ident { span, .. } => {
+                let symbol = self.resolutions.get(node)?;
+                let ty = self.session.symbols.symbol(symbol).kind.value_type()?;
+                if let Type::Var(id) = ty {
+                    self.uses.insert(*span, *id);
+                }
+                Some(ty.clone())
+            },
+            Node::BinaryOp { lhs, rhs, op, span } => self.check_binary(lhs, rhs, *op, *span, expected),
+            Node::UnaryOp { lhs, op, span, .. } => self.check_unary(lhs, *op, *span, expected),
+            Node::Call { func, args, span } => self.check_call(node, func.as_slice(), args, *span, expected),
+            Node::ObjCall { recv, func, args, span } => self.check_method_call(recv, func.as_slice(), args, *span, expected),
+            Node::Field { recv, name, span } => {
+                let recv_ty = self.check_expr(recv, None)?;
+                let fields = self.session.symbols.fields_of(&recv_ty);
+                match fields.and_then(|fields| fields.into_iter().find(|(field, _)| field == name.as_slice())) {
+                    Some((_, ty)) => Some(ty),
+                    None => {
+                        self.error(Diagnostic::error(format!("no field `{}` on type `{}`", name, recv_ty))
+                            .with_code(codes::NO_SUCH_FIELD)
+                            .with_primary(*span, "unknown field"));
+                        None
+                    }
+                }
+            },
+            Node::Chain { chained } => self.check_block(chained, expected),
+            Node::If { cond, then, els, .. } => self.check_if(cond, then, els.as_deref(), expected),
+            Node::While { cond, body, .. } => {
+                self.check_condition(cond);
+                self.check_block(body, None);
+                Some(Type::Void)
+            },
+            Node::For { var, start, end, body, .. } => {
+                self.check_for(var, start, end);
+                self.check_block(body, None);
+                Some(Type::Void)
+            },
+            Node::Loop { body, .. } => {
+                self.check_block(body, None);
+                Some(Type::Void)
+            },
+            Node::Break { .. } | Node::Continue { .. } => Some(Type::Void),
+            Node::Return { value, span } => {
+                self.check_return(value.as_deref(), *span);
+                Some(Type::Void)
+            },
+            Node::Value { .. } | Node::Body { .. } | Node::Head { .. } => self.check_statement(node),
+            Node::Error { .. } => None
+        }
+    }
+
+    /// Checks that `cond` is a `Boolean`: numbers and pointers aren't tested for zero.
+    fn check_condition(&mut self, cond: &Node<'a>) {
+        let Some(found) = self.check_expr(cond, Some(&Type::Boolean)) else {
+            return;
+        };
+        if self.inference.unify(&found, &Type::Boolean, cond.span()) {
+            return;
+        }
+        let diag = Diagnostic::error(format!("expected a `Boolean` condition, found `{}`", self.inference.describe(&found)))
+            .with_code(codes::NON_BOOLEAN_CONDITION)
+            .with_primary(cond.span(), "expected `Boolean`");
+        let numeric = found.is_numeric() || self.inference.kind(&found).is_some_and(|kind| kind != Kind::Any);
+        let diag = if numeric { diag.with_help("compare it instead, as in `x != 0`") } else { diag };
+        let diag = self.explain_uses(diag, &[cond]);
+        self.error(diag);
+    }
+
+    /// An `if` with an `else` has the type of its branches, which must agree unless one
+    /// never completes or is `Void`; without one, it is `Void`.
+    fn check_if(&mut self, cond: &Node<'a>, then: &[Node<'a>], els: Option<&[Node<'a>]>, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        self.check_condition(cond);
+        let then_ty = self.check_block(then, expected);
+        let Some(els) = els else {
+            return Some(Type::Void);
+        };
+        let hint = expected.cloned().or_else(|| then_ty.clone().filter(|ty| !ty.is_void()));
+        let els_ty = self.check_block(els, hint.as_ref());
+
+        match (block_flow(then).completes, block_flow(els).completes) {
+            (false, _) => return els_ty,
+            (_, false) => return then_ty,
+            _ => {}
+        }
+        let (then_ty, els_ty) = (then_ty?, els_ty?);
+        if then_ty.is_void() || els_ty.is_void() {
+            return Some(Type::Void);
+        }
+        let (Some(then_tail), Some(els_tail)) = (then.last(), els.last()) else {
+            return Some(Type::Void);
+        };
+        if self.coerces(els_tail, &then_ty, &els_ty) {
+            return Some(then_ty);
+        }
+        let (then_ty, els_ty) = (self.inference.describe(&then_ty), self.inference.describe(&els_ty));
+        let diag = Diagnostic::error("`if` and `else` have incompatible types")
+            .with_code(codes::MISMATCHED_TYPES)
+            .with_primary(els_tail.span(), format!("expected `{}`, found `{}`", then_ty, els_ty))
+            .with_secondary(then_tail.span(), "expected because of this");
+        let diag = self.explain_uses(diag, &[then_tail, els_tail]);
+        self.error(diag);
+        None
+    }
+
+    /// Gives the counter of a `for` the type of its bounds, which must be an integer.
+    fn check_for(&mut self, var: &Node<'a>, start: &Node<'a>, end: &Node<'a>) {
+        let ret = match var.extract_value() {
+            Ok(ret) => ret,
+            Err(diag) => {
+                self.error(diag);
+                return;
+            }
+        };
+        let ty = self.declare_inferred(var, ret, start.span());
+        for bound in [start, end] {
+            if let Some(found) = self.check_expr(bound, Some(&ty)) {
+                if !self.coerces(bound, &ty, &found) {
+                    self.mismatch(bound.span(), &ty, &found);
+                }
+            }
+        }
+        let counter = self.inference.resolve(&ty);
+        if counter.is_integer() || self.inference.narrow(&counter, Kind::Integer { signed: false }, start.span()) {
+            return;
+        }
+        self.error(Diagnostic::error(format!("cannot count over a range of `{}`", self.inference.describe(&counter)))
+            .with_code(codes::MISMATCHED_TYPES)
+            .with_primary(start.span().join(end.span()), "expected a range of integers"));
+    }
+
+    /// Checks a `return` against the return type of the function it is in; the resolver
+    /// reports one outside of any.
+    fn check_return(&mut self, value: Option<&Node<'a>>, span: Span) {
+        let Some(returns) = self.returns.clone().filter(|ty| self.is_known(ty)) else {
+            if let Some(value) = value {
+                self.check_expr(value, None);
+            }
+            return;
+        };
+        match value {
+            Some(value) => {
+                let expected = (!returns.is_void()).then_some(&returns);
+                let Some(found) = self.check_expr(value, expected) else {
+                    return;
+                };
+                if returns.is_void() {
+                    if !found.is_void() {
+                        self.mismatch(value.span(), &returns, &found);
+                    }
+                } else if !self.coerces(value, &returns, &found) {
+                    self.mismatch(value.span(), &returns, &found);
+                }
+            },
+            None if !returns.is_void() => {
+                self.error(Diagnostic::error(format!("`return` without a value in a function returning `{}`", returns))
+                    .with_code(codes::MISSING_RETURN_VALUE)
+                    .with_primary(span, format!("expected a value of type `{}`", returns)));
+            },
+            None => {}
+        }
+    }
+
+    /// A literal expected to have the type of a variable still open narrows it; the
+    /// variable's final type is checked to fit the literal once it is known.
+    fn check_literal(&mut self, value: &Literal<'a>, span: Span, expected: Option<&Type<'a>>, negated: bool) -> Option<Type<'a>> {
+        let expected = expected.map(|ty| self.inference.resolve(ty));
+        match value {
+            Literal::Int(value) => {
+                let value = if negated { -(*value as i128) } else { *value as i128 };
+                let ty = match expected {
+                    Some(ty) if ty.is_integer() => ty,
+                    Some(ty) if self.inference.narrow(&ty, Kind::Integer { signed: value < 0 }, span) => {
+                        self.inference.literal(&ty, value, span);
+                        return Some(ty);
+                    },
+                    _ => Type::Int32
+                };
+                if let Some(diag) = out_of_range(&ty, value, span) {
+                    self.error(diag);
+                }
+                Some(ty)
+            },
+            Literal::Float(_) => match expected {
+                Some(ty) if ty.is_float() || self.inference.narrow(&ty, Kind::Float, span) => Some(ty),
+                _ => Some(Type::Float64)
+            },
+            Literal::Str(_) => Some(Type::Str),
+            Literal::Boolean(_) => Some(Type::Boolean)
+        }
+    }
+
+    /// Literals adapt to the other operand, so check the non-literal side first.
+    fn check_operands(&mut self, lhs: &Node<'a>, rhs: &Node<'a>, expected: Option<&Type<'a>>) -> (Option<Type<'a>>, Option<Type<'a>>) {
+        if is_literal(lhs) && !is_literal(rhs) {
+            let rhs_ty = self.check_expr(rhs, expected);
+            let lhs_ty = self.check_expr(lhs, rhs_ty.as_ref().or(expected));
+            return (lhs_ty, rhs_ty);
+        }
+
+        let lhs_ty = self.check_expr(lhs, expected);
+        let rhs_ty = self.check_expr(rhs, lhs_ty.as_ref().or(expected));
+        (lhs_ty, rhs_ty)
+    }
+
+    fn check_binary(&mut self, lhs: &Node<'a>, rhs: &Node<'a>, op: BinOp, span: Span, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        if op == BinOp::Assign {
+            let place = self.check_expr(lhs, None)?;
+            let value = self.check_expr(rhs, Some(&place))?;
+            if !self.coerces(rhs, &place, &value) {
+                let diag = self.mismatched(rhs.span(), &place, &value);
+                let diag = match self.uses.get(&lhs.span()).or(self.uses.get(&rhs.span())) {
+                    Some(&id) => self.explain(diag, id),
+                    None => diag
+                };
+                self.error(diag);
+            }
+            return Some(Type::Void);
+        }
+
+        let operand_hint = match op {
+            BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulus => expected,
+            _ => None
+        };
+        let (lhs_ty, rhs_ty) = self.check_operands(lhs, rhs, operand_hint);
+        let (mut lhs_ty, mut rhs_ty) = (lhs_ty?, rhs_ty?);
+        if lhs_ty != rhs_ty && self.inference.unify(&lhs_ty, &rhs_ty, span) {
+            lhs_ty = self.inference.resolve(&lhs_ty);
+            rhs_ty = lhs_ty.clone();
+        }
+
+        let base = op.compound_base().unwrap_or(op);
+        let valid = lhs_ty == rhs_ty && match base {
+            BinOp::Add => self.is_numeric(&lhs_ty) || lhs_ty == Type::Str,
+            BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulus => self.is_numeric(&lhs_ty),
+            BinOp::GreaterThan | BinOp::GreaterThanEq | BinOp::LessThan | BinOp::LessThanEq => self.is_numeric(&lhs_ty) || lhs_ty == Type::Str,
+            BinOp::Equals | BinOp::NotEquals => !lhs_ty.is_void(),
+            BinOp::LogAnd | BinOp::LogOr => self.inference.unify(&lhs_ty, &Type::Boolean, span),
+            _ => false
+        };
+
+        if !valid {
+            let (lhs_name, rhs_name) = (self.inference.describe(&lhs_ty), self.inference.describe(&rhs_ty));
+            let diag = Diagnostic::error(format!("cannot apply `{}` to `{}` and `{}`", op.as_str(), lhs_name, rhs_name))
+                .with_code(codes::INVALID_BINARY_OPERANDS)
+                .with_primary(span, "invalid operands")
+                .with_secondary(lhs.span(), format!("`{}`", lhs_name))
+                .with_secondary(rhs.span(), format!("`{}`", rhs_name));
+            let diag = match base {
+                BinOp::LogAnd | BinOp::LogOr => diag.with_note("logical operators take `Boolean` operands"),
+                _ if lhs_ty != rhs_ty && lhs_ty.is_numeric() && rhs_ty.is_numeric() => diag.with_note("numeric operands must have the same type"),
+                _ => diag
+            };
+            let diag = self.explain_uses(diag, &[lhs, rhs]);
+            self.error(diag);
+            return None;
+        }
+
+        if op.is_assignment() {
+            return Some(Type::Void);
+        }
+
+        match op {
+            BinOp::Equals | BinOp::NotEquals | BinOp::GreaterThan | BinOp::GreaterThanEq
+            | BinOp::LessThan | BinOp::LessThanEq | BinOp::LogAnd | BinOp::LogOr => Some(Type::Boolean),
+            _ => Some(lhs_ty)
+        }
+    }
+
+    /// Whether `ty` is numeric, or a variable holding numeric literals.
+    fn is_numeric(&self, ty: &Type<'a>) -> bool {
+        ty.is_numeric() || matches!(self.inference.kind(ty), Some(Kind::Integer { .. } | Kind::Float))
+    }
+
+    fn check_unary(&mut self, lhs: &Node<'a>, op: UniOp, span: Span, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let ty = match (op, lhs) {
+            (UniOp::Negative, Node::Literal { value: value @ Literal::Int(_), span: lit_span }) => {
+                let ty = self.check_literal(value, *lit_span, expected, true)?;
+                self.table.insert(*lit_span, ty.clone());
+                ty
+            },
+            _ => self.check_expr(lhs, expected)?
+        };
+
+        let valid = match op {
+            UniOp::Negative => ty.is_signed() || self.inference.narrow(&ty, Kind::Integer { signed: true }, span)
+                || self.inference.kind(&ty) == Some(Kind::Float),
+            UniOp::LogNot => self.inference.unify(&ty, &Type::Boolean, span),
+            UniOp::BitNot => ty.is_integer() || matches!(self.inference.kind(&ty), Some(Kind::Integer { .. })),
+            UniOp::Increment | UniOp::Decrement => self.is_numeric(&ty)
+        };
+
+        if !valid {
+            let diag = Diagnostic::error(format!("cannot apply unary `{}` to `{}`", op.as_str(), self.inference.describe(&ty)))
+                .with_code(codes::INVALID_UNARY_OPERAND)
+                .with_primary(span, "invalid operand");
+            let diag = match op {
+                UniOp::Negative if ty.is_integer() => diag.with_note("unsigned values cannot be negated"),
+                UniOp::LogNot if ty.is_integer() => diag.with_help("use `~` for bitwise negation"),
+                _ => diag
+            };
+            let diag = self.explain_uses(diag, &[lhs]);
+            self.error(diag);
+            return None;
+        }
+        Some(ty)
+    }
+
+    fn check_arity(&mut self, callee: &str, params: usize, args: usize, span: Span) {
+        if params != args {
+            let plural = if params == 1 { "" } else { "s" };
+            let were = if args == 1 { "was" } else { "were" };
+            self.error(Diagnostic::error(format!("`{}` takes {} argument{} but {} {} supplied", callee, params, plural, args, were))
+                .with_code(codes::WRONG_ARG_COUNT)
+                .with_primary(span, format!("expected {} argument{}", params, plural)));
+        }
+    }
+
+    fn check_args(&mut self, callee: &str, params: &[Type<'a>], args: &[Node<'a>], span: Span) {
+        self.check_arity(callee, params.len(), args.len(), span);
+
+        for (idx, arg) in args.iter().enumerate() {
+            match params.get(idx) {
+                Some(param) => {
+                    if let Some(found) = self.check_expr(arg, Some(param)) {
+                        if !self.coerces(arg, param, &found) {
+                            self.mismatch(arg.span(), param, &found);
+                        }
+                    }
+                },
+                None => {
+                    self.check_expr(arg, None);
+                }
+            }
+        }
+    }
+
+    fn check_call(&mut self, call: &Node<'a>, func: &str, args: &[Node<'a>], span: Span, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let callee = self.resolutions.get(call).map(|symbol| self.session.symbols.symbol(symbol).kind.clone());
+        match callee {
+            // Naming an `obj` or `comp` constructs it from its fields, in order.
+            Some(SymbolKind::Object(obj)) => self.check_constructor(obj.name(), obj.generics(), obj.fields(), args, span, expected),
+            Some(SymbolKind::Composition(comp)) => self.check_constructor(comp.name(), comp.generics(), comp.fields(), args, span, expected),
+            Some(SymbolKind::Function(defun)) => {
+                let params: Vec<Type<'a>> = defun.args().iter().map(|arg| arg.ty()).collect();
+                let signature = Signature {
+                    generics: defun.generics().to_vec(),
+                    ..Signature::new(params, defun.return_type().clone())
+                };
+                self.check_signature(func, signature, args, span, expected)
+            },
+            // Builtins format any value they are given.
+            Some(SymbolKind::Builtin(_)) => {
+                for arg in args {
+                    self.check_expr(arg, None);
+                }
+                Some(Type::Void)
+            },
+            // The resolver has already reported the callee.
+            _ => {
+                for arg in args {
+                    self.check_expr(arg, None);
+                }
+                None
+            }
+        }
+    }
+
+    fn check_constructor(
+        &mut self,
+        name: &Yarn<'a>,
+        generics: &[GenericParam<'a>],
+        fields: &[VarDeclaration<'a>],
+        args: &[Node<'a>],
+        span: Span,
+        expected: Option<&Type<'a>>
+    ) -> Option<Type<'a>> {
+        let params: Vec<Type<'a>> = fields.iter().map(|field| field.ty()).collect();
+        let ret = if generics.is_empty() {
+            Type::Named(name.clone())
+        } else {
+            Type::Applied(name.clone(), generics.iter().map(GenericParam::as_type).collect())
+        };
+        let signature = Signature {
+            generics: generics.to_vec(),
+            ..Signature::new(params, ret)
+        };
+        self.check_signature(name.as_slice(), signature, args, span, expected)
+    }
+
+    /// Checks a call against `signature`, inferring the type arguments it doesn't know
+    /// and checking them against their bounds, and gives the type of its result.
+    fn check_signature(&mut self, callee: &str, signature: Signature<'a>, args: &[Node<'a>], span: Span, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let Signature { generics, known, params, ret } = signature;
+        if generics.is_empty() {
+            self.check_args(callee, &params, args, span);
+            return Some(ret);
+        }
+
+        let mut bindings: Vec<Option<Type<'a>>> = (0..generics.len()).map(|idx| known.get(idx).cloned()).collect();
+        // The expected type only helps when it fits the result as a whole.
+        if let Some(expected) = expected {
+            let mut attempt = bindings.clone();
+            if unify(&generics, &ret, expected, &mut attempt) {
+                bindings = attempt;
+            }
+        }
+
+        self.check_arity(callee, params.len(), args.len(), span);
+        // Where each inferred argument came from, to point at when it breaks a bound.
+        let mut origins: Vec<Option<Span>> = vec![None; generics.len()];
+        let mut complete = true;
+        // Literals adapt to what the other arguments decide, so they come last.
+        let mut order: Vec<usize> = (0..args.len()).collect();
+        order.sort_by_key(|&idx| is_literal(&args[idx]));
+        for idx in order {
+            let arg = &args[idx];
+            let Some(param) = params.get(idx) else {
+                self.check_expr(arg, None);
+                continue;
+            };
+            let hint = resolved(&generics, param, &bindings);
+            let Some(found) = self.check_expr(arg, hint.as_ref()) else {
+                complete = false;
+                continue;
+            };
+            match hint {
+                Some(hint) => {
+                    if !self.coerces(arg, &hint, &found) {
+                        self.mismatch(arg.span(), &hint, &found);
+                    }
+                },
+                None => {
+                    let before: Vec<bool> = bindings.iter().map(Option::is_some).collect();
+                    if unify(&generics, param, &found, &mut bindings) {
+                        for (origin, (was, now)) in origins.iter_mut().zip(before.iter().zip(&bindings)) {
+                            if !was && now.is_some() {
+                                *origin = Some(arg.span());
+                            }
+                        }
+                    } else {
+                        let partial = param.substitute(&|name| lookup(&generics, &bindings, name));
+                        self.mismatch(arg.span(), &partial, &found);
+                        complete = false;
+                    }
+                }
+            }
+        }
+
+        let unknown: Vec<String> = generics.iter().zip(&bindings)
+            .filter(|(_, binding)| binding.is_none())
+            .map(|(param, _)| format!("`{}`", param.name()))
+            .collect();
+        if !unknown.is_empty() {
+            // An argument that failed to check may be all that was missing.
+            if complete {
+                let plural = if unknown.len() == 1 { "" } else { "s" };
+                self.error(Diagnostic::error(format!("cannot infer the type argument{} {} of `{}`", plural, unknown.join(", "), callee))
+                    .with_code(codes::CANNOT_INFER)
+                    .with_primary(span, "type annotations needed")
+                    .with_help("give the variable the result is stored in a type, which decides the rest"));
+            }
+            return None;
+        }
+
+        let types: Vec<Type<'a>> = bindings.into_iter().flatten().collect();
+        for (idx, param) in generics.iter().enumerate().skip(known.len()) {
+            for bound in param.bounds() {
+                let required = bound.instantiate(&generics, &types);
+                if !self.session.symbols.satisfies(&types[idx], &required, &self.generics) {
+                    self.unsatisfied(callee, param, bound, &types[idx], &required, origins[idx].unwrap_or(span));
+                }
+            }
+        }
+        let ret = ret.instantiate(&generics, &types);
+        self.table.instances.insert(span, types);
+        Some(ret)
+    }
+
+    /// Reports that `arg`, the argument of `param`, isn't extended with the trait `required`.
+    fn unsatisfied(&mut self, callee: &str, param: &GenericParam<'a>, bound: &Type<'a>, arg: &Type<'a>, required: &Type<'a>, at: Span) {
+        let shown = self.inference.describe(arg);
+        let diag = Diagnostic::error(format!("the trait bound `{}: {}` is not satisfied", shown, required))
+            .with_code(codes::UNSATISFIED_BOUND)
+            .with_primary(at, format!("`{}` is not extended with `{}`", shown, required))
+            .with_note(format!("required by the bound `{}: {}` of `{}`", param.name(), bound, callee));
+        let symbols = &self.session.symbols;
+        let (name, with) = (arg.type_name().unwrap_or_default(), required.type_name().unwrap_or_default());
+        let diag = match (arg, symbols.implementation(name, with)) {
+            (Type::Param(name), _) => diag.with_help(format!("add the bound to the type parameter: `{}: {}`", name, required)),
+            (_, Some(implementation)) => {
+                let generic = Type::Applied(Yarn::owned(name.into()), implementation.generics.iter().map(GenericParam::as_type).collect());
+                let bounds: Vec<String> = implementation.generics.iter()
+                    .flat_map(|param| param.bounds().iter().map(move |bound| format!("`{}: {}`", param.name(), bound)))
+                    .collect();
+                diag.with_note(format!("`{}` is only extended with `{}` where {}", generic, with, bounds.join(", ")))
+            },
+            _ if !name.is_empty() && !symbols.is_trait(name) && symbols.dispatch_trait(arg).is_none() =>
+                diag.with_help(format!("add an `extend {} with {}` block", name, required)),
+            _ => diag.with_note("only `obj`s and trait objects can meet trait bounds")
+        };
+        self.error(diag);
+    }
+
+    /// The type parameters of the item a method found on a value of type `recv_ty` belongs
+    /// to, with the arguments `recv_ty` gives them: those of the `obj` or of its `extend`
+    /// block, or those of the trait a default body or a trait object's method comes from.
+    fn owner_generics(&self, recv_ty: &Type<'a>, method: &Symbol<'a>) -> (Vec<GenericParam<'a>>, Vec<Type<'a>>) {
+        let symbols = &self.session.symbols;
+        let Some((owner, _)) = method.qualified.rsplit_once("::") else {
+            return (Vec::new(), Vec::new());
+        };
+        let recv_ty = match recv_ty {
+            Type::SafePtr(inner) if symbols.dispatch_trait(recv_ty).is_some() => inner.as_ref(),
+            _ => recv_ty
+        };
+        let (Some(recv), args) = (recv_ty.type_name(), recv_ty.type_args()) else {
+            return (Vec::new(), Vec::new());
+        };
+        if symbols.is_trait(recv) || owner == recv {
+            // Supertraits take no type arguments, so only the trait itself has any.
+            if owner != recv {
+                return (Vec::new(), Vec::new());
+            }
+            return (symbols.generics_of(owner).to_vec(), args.to_vec());
+        }
+        if let Some(implementation) = owner.strip_prefix(recv).and_then(|rest| rest.strip_prefix("::")).and_then(|with| symbols.implementation(recv, with)) {
+            return (implementation.generics.clone(), args.to_vec());
+        }
+        // A default body, instantiated for the `obj` with the trait arguments of its block.
+        match symbols.implementation(recv, owner) {
+            Some(implementation) => (
+                symbols.generics_of(owner).to_vec(),
+                implementation.trait_args.iter().map(|arg| arg.instantiate(&implementation.generics, args)).collect()
+            ),
+            None => (Vec::new(), Vec::new())
+        }
+    }
+
+    /// A method called on a value of the type parameter `param`, which only has the
+    /// methods of its bounds. Gives the bound providing it and the method.
+    fn bounded_method(&mut self, param: &str, func: &str, span: Span) -> Option<(Type<'a>, SymbolId)> {
+        let bounds: Vec<Type<'a>> = self.generics.iter().rev()
+            .find(|generic| generic.name().as_slice() == param)
+            .map(|generic| generic.bounds().to_vec())
+            .unwrap_or_default();
+        let symbols = &self.session.symbols;
+        let mut found: Vec<(Type<'a>, SymbolId)> = Vec::new();
+        let mut ambiguous = None;
+        for bound in &bounds {
+            match bound.type_name().map(|with| symbols.method(with, func)) {
+                Some(MethodLookup::Found(symbol)) if !found.iter().any(|(_, id)| *id == symbol.id) => {
+                    found.push((bound.clone(), symbol.id));
+                },
+                Some(MethodLookup::Ambiguous(traits)) => ambiguous = Some(traits),
+                _ => {}
+            }
+        }
+
+        if let Some(traits) = ambiguous {
+            self.ambiguous(param, func, &traits, span);
+            return None;
+        }
+        match found.as_slice() {
+            [(bound, id)] => Some((bound.clone(), *id)),
+            [] => {
+                let diag = Diagnostic::error(format!("no method `{}` on type `{}`", func, param))
+                    .with_code(codes::NO_SUCH_METHOD)
+                    .with_primary(span, "method not found");
+                let names: Vec<String> = bounds.iter().map(|bound| format!("`{}`", bound)).collect();
+                let diag = if names.is_empty() {
+                    diag.with_note(format!("`{}` has no bounds, so its values have no methods", param))
+                } else {
+                    diag.with_note(format!("`{}` only has the methods of {}", param, names.join(" and ")))
+                };
+                // A trait declaring the method is probably the bound that was meant.
+                let mut providers: Vec<&str> = symbols.qualified_symbols()
+                    .filter(|symbol| matches!(symbol.kind, SymbolKind::Function(_)) && symbol.name == func)
+                    .filter_map(|symbol| symbol.qualified.rsplit_once("::").map(|(owner, _)| owner))
+                    .filter(|owner| symbols.is_trait(owner))
+                    .collect();
+                providers.sort_unstable();
+                let diag = match providers.first() {
+                    Some(with) => diag.with_help(format!("add a bound: `{}: {}`", param, with)),
+                    None => diag
+                };
+                self.error(diag);
+                None
+            },
+            _ => {
+                let traits: Vec<String> = found.iter().filter_map(|(bound, _)| bound.type_name().map(str::to_string)).collect();
+                self.ambiguous(param, func, &traits, span);
+                None
+            }
+        }
+    }
+
+    fn check_method_call(&mut self, recv: &Node<'a>, func: &str, args: &[Node<'a>], span: Span, expected: Option<&Type<'a>>) -> Option<Type<'a>> {
+        let recv_ty = self.check_expr(recv, None)?;
+        if let Type::Param(param) = &recv_ty {
+            let Some((bound, id)) = self.bounded_method(param.as_slice(), func, span) else {
+                for arg in args {
+                    self.check_expr(arg, None);
+                }
+                return None;
+            };
+            let method = self.session.symbols.symbol(id);
+            let SymbolKind::Function(defun) = &method.kind else {
+                return None;
+            };
+            let defun = defun.clone();
+            let owner = self.owner_generics(&bound, method);
+            if let Some(with) = bound.type_name() {
+                self.table.through.insert(span, with.to_string());
+            }
+            return self.check_method_signature(func, &defun, owner, args, span, expected);
+        }
+
+        let symbols = &self.session.symbols;
+        let owner = symbols.dispatch_trait(&recv_ty).or(recv_ty.type_name());
+        let (method, ambiguous) = match owner.map(|owner| symbols.method(owner, func)) {
+            Some(MethodLookup::Found(symbol)) => match &symbol.kind {
+                SymbolKind::Function(defun) => (Some((defun.clone(), self.owner_generics(&recv_ty, symbol))), None),
+                _ => (None, None)
+            },
+            Some(MethodLookup::Ambiguous(traits)) => (None, owner.map(|owner| (owner.to_string(), traits))),
+            Some(MethodLookup::Missing) | None => (None, None)
+        };
+
+        if let Some((owner, traits)) = ambiguous {
+            self.ambiguous(&owner, func, &traits, span);
+            for arg in args {
+                self.check_expr(arg, None);
+            }
+            return None;
+        }
+        let Some((method, owner)) = method else {
+            self.error(Diagnostic::error(format!("no method `{}` on type `{}`", func, recv_ty))
+                .with_code(codes::NO_SUCH_METHOD)
+                .with_primary(span, "method not found"));
+            for arg in args {
+                self.check_expr(arg, None);
+            }
+            return None;
+        };
+        self.check_method_signature(func, &method, owner, args, span, expected)
+    }
+
+    /// Checks the arguments of a call of `method`, whose owner has the type parameters
+    /// and arguments `owner`.
+    fn check_method_signature(
+        &mut self,
+        func: &str,
+        method: &DefunDescriptor<'a>,
+        owner: (Vec<GenericParam<'a>>, Vec<Type<'a>>),
+        args: &[Node<'a>],
+        span: Span,
+        expected: Option<&Type<'a>>
+    ) -> Option<Type<'a>> {
+        let params: Vec<Type<'a>> = method.args().iter()
+            .skip_while(|arg| arg.name().is_some_and(|name| name.as_slice() == "self"))
+            .map(|arg| arg.ty())
+            .collect();
+        let (mut generics, known) = owner;
+        generics.extend(method.generics().iter().cloned());
+        let signature = Signature { generics, known, params, ret: method.return_type().clone() };
+        self.check_signature(func, signature, args, span, expected)
+    }
+
+    fn ambiguous(&mut self, owner: &str, func: &str, traits: &[String], span: Span) {
+        let names: Vec<String> = traits.iter().map(|with| format!("`{}`", with)).collect();
+        let (last, rest) = names.split_last().expect("an ambiguous method has several providers");
+        let diag = Diagnostic::error(format!("multiple traits of `{}` provide the method `{}`", owner, func))
+            .with_code(codes::AMBIGUOUS_METHOD)
+            .with_primary(span, "ambiguous method call")
+            .with_note(format!("`{}` is provided by {} and {}", func, rest.join(", "), last));
+        // Only a method the `extend` block implements itself can be named by its path.
+        let symbols = &self.session.symbols;
+        let path = traits.iter()
+            .map(|with| format!("{}::{}::{}", owner, with, func))
+            .find(|path| !symbols.is_trait(owner) && symbols.lookup_qualified(path).is_some());
+        let diag = match path {
+            Some(path) => diag.with_help(format!("call it through the trait you mean, as `{}(...)`", path)),
+            None => diag
+        };
+        self.error(diag);
+    }
+}
+
+/// Matches `pattern`, a type in terms of `generics`, against `found`, binding the
+/// parameters it meets. Fails when `found` doesn't fit or a parameter is already bound
+/// to another type.
+fn unify<'a>(generics: &[GenericParam<'a>], pattern: &Type<'a>, found: &Type<'a>, bindings: &mut [Option<Type<'a>>]) -> bool {
+    if let Type::Param(name) = pattern {
+        if let Some(idx) = generics.iter().position(|param| param.name() == name) {
+            return match &bindings[idx] {
+                Some(bound) => bound == found,
+                None => {
+                    bindings[idx] = Some(found.clone());
+                    true
+                }
+            };
+        }
+    }
+    match (pattern, found) {
+        (Type::Applied(a, xs), Type::Applied(b, ys)) => a == b && xs.len() == ys.len()
+            && xs.iter().zip(ys).all(|(x, y)| unify(generics, x, y, bindings)),
+        (Type::UnsafePtr(x), Type::UnsafePtr(y))
+        | (Type::SafePtr(x), Type::SafePtr(y))
+        | (Type::Slice(x), Type::Slice(y)) => unify(generics, x, y, bindings),
+        (Type::Array(x, n), Type::Array(y, m)) => n == m && unify(generics, x, y, bindings),
+        _ => pattern == found
+    }
+}
+
+/// What the parameter `name` of `generics` is bound to so far.
+fn lookup<'a>(generics: &[GenericParam<'a>], bindings: &[Option<Type<'a>>], name: &str) -> Option<Type<'a>> {
+    let idx = generics.iter().position(|param| param.name().as_slice() == name)?;
+    bindings[idx].clone()
+}
+
+/// `pattern` with its parameters filled in, once all of them are bound.
+fn resolved<'a>(generics: &[GenericParam<'a>], pattern: &Type<'a>, bindings: &[Option<Type<'a>>]) -> Option<Type<'a>> {
+    let unbound = generics.iter().zip(bindings)
+        .any(|(param, binding)| binding.is_none() && mentions(pattern, param.name().as_slice()));
+    (!unbound).then(|| pattern.substitute(&|name| lookup(generics, bindings, name)))
+}
+
+/// Whether the type parameter `name` occurs in `ty`.
+fn mentions(ty: &Type<'_>, name: &str) -> bool {
+    match ty {
+        Type::Param(param) => param.as_slice() == name,
+        Type::Applied(_, args) => args.iter().any(|arg| mentions(arg, name)),
+        Type::UnsafePtr(inner) | Type::SafePtr(inner) | Type::Array(inner, _) | Type::Slice(inner) => mentions(inner, name),
+        _ => false
+    }
+}
+
+/// The error for the integer literal `value` at `span`, if it doesn't fit in `ty`.
+fn out_of_range(ty: &Type<'_>, value: i128, span: Span) -> Option<Diagnostic> {
+    let (min, max) = ty.int_range()?;
+    (value < min || value > max).then(|| Diagnostic::error(format!("literal out of range for `{}`", ty))
+        .with_code(codes::LITERAL_OUT_OF_RANGE)
+        .with_primary(span, format!("`{}` does not fit", value))
+        .with_note(format!("the range of `{}` is `{}..={}`", ty, min, max)))
+}
+
+fn is_literal(node: &Node<'_>) -> bool {
+    match node {
+        Node::Literal { value: Literal::Int(_) | Literal::Float(_), .. } => true,
+        Node::UnaryOp { lhs, op: UniOp::Negative, .. } => is_literal(lhs),
+        _ => false
+    }
+}
+
+/// How control leaves a statement: whether it can go on to the one after it, and the
+/// labels of the loops around it that it `break`s out of, `None` for the innermost.
+struct Flow {
+    completes: bool,
+    breaks: Vec<Option<String>>
+}
+
+fn flow(node: &Node<'_>) -> Flow {
+    match node {
+        Node::Break { label, .. } => Flow {
+            completes: false,
+            breaks: vec![label.as_ref().map(Yarn::to_string)]
+        },
+        Node::Continue { .. } | Node::Return { .. } => Flow {
+            completes: false,
+            breaks: Vec::new()
+        },
+        Node::If { then, els, .. } => {
+            let then = block_flow(then);
+            let Some(els) = els else {
+                return Flow { completes: true, ..then };
+            };
+            let els = block_flow(els);
+            Flow {
+                completes: then.completes || els.completes,
+                breaks: then.breaks.into_iter().chain(els.breaks).collect()
+            }
+        },
+        // A loop with a condition may always end; one without only by a `break` of its own.
+        Node::While { label, body, .. } | Node::For { label, body, .. } | Node::Loop { label, body, .. } => {
+            let (own, breaks) = block_flow(body).breaks.into_iter()
+                .partition::<Vec<_>, _>(|target| target.is_none() || target.as_deref() == label.as_ref().map(Yarn::as_slice));
+            Flow {
+                completes: !matches!(node, Node::Loop { .. }) || !own.is_empty(),
+                breaks
+            }
+        },
+        Node::Chain { chained } => block_flow(chained),
+        _ => Flow {
+            completes: true,
+            breaks: Vec::new()
+        }
+    }
+}
+
+/// The flow of `stmts` run one after the other, up to the first that doesn't complete.
+fn block_flow(stmts: &[Node<'_>]) -> Flow {
+    let mut block = Flow {
+        completes: true,
+        breaks: Vec::new()
+    };
+    for stmt in stmts {
+        let next = flow(stmt);
+        block.breaks.extend(next.breaks);
+        if !next.completes {
+            block.completes = false;
+            break;
+        }
+    }
+    block
+}
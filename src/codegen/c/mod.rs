@@ -0,0 +1,685 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write
+};
+
+use crate::{
+    analysis::symbols::Builtin,
+    codegen::{check, operand_type, type_of},
+    common::diagnostics::Diagnostic,
+    ir::{display_name, ssa, BinaryOp, BlockId, Const, Function, Inst, Module, Operand, Reg, StructDef, Terminator, TraitDef, Ty, UnaryOp, VTable}
+};
+
+/// Headers and the routines every program needs, placed before the generated code.
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// IR names aren't all valid C identifiers and may be C keywords, so each kind of name
+/// gets a prefix.
+fn function_name(name: &str) -> String {
+    format!("fn_{}", name.replace("::", "__").replace('.', "_"))
+}
+
+fn global_name(name: &str) -> String {
+    format!("g_{}", name)
+}
+
+fn struct_name(name: &str) -> String {
+    format!("s_{}", name.replace('.', "_"))
+}
+
+fn field_name(name: &str) -> String {
+    format!("m_{}", name)
+}
+
+/// The heap cell a trait object of `with` points to: the instance and its vtable.
+fn dyn_name(with: &str) -> String {
+    format!("d_{}", with)
+}
+
+/// The struct of function pointers the vtables of `with` are.
+fn vtable_type(with: &str) -> String {
+    format!("v_{}", with)
+}
+
+/// The vtable of the struct `obj` for the trait `with`.
+fn vtable_name(obj: &str, with: &str) -> String {
+    format!("vt_{}_{}", obj.replace('.', "_"), with)
+}
+
+/// The vtable member of a trait method, named by the trait declaring it.
+fn slot_name(method: &str) -> String {
+    format!("m_{}", method.replace("::", "__"))
+}
+
+/// The routine that prints an instance of the struct `name`.
+fn show_name(name: &str) -> String {
+    format!("show_{}", name.replace('.', "_"))
+}
+
+fn reg_name(reg: Reg) -> String {
+    format!("r{}", reg.0)
+}
+
+fn block_label(block: BlockId) -> String {
+    block.to_string()
+}
+
+/// The C type a value of `ty` is held in; instances are shared, so structs are pointers.
+fn c_type(ty: &Ty) -> String {
+    match ty {
+        Ty::I8 => "int8_t".into(),
+        Ty::I16 => "int16_t".into(),
+        Ty::I32 => "int32_t".into(),
+        Ty::I64 => "int64_t".into(),
+        Ty::U8 => "uint8_t".into(),
+        Ty::U16 => "uint16_t".into(),
+        Ty::U32 => "uint32_t".into(),
+        Ty::U64 => "uint64_t".into(),
+        ty if ty.is_single() => "float".into(),
+        Ty::F64 => "double".into(),
+        Ty::Bool => "bool".into(),
+        Ty::Str => "const char *".into(),
+        Ty::Void => "void".into(),
+        Ty::Struct(name) => format!("struct {} *", struct_name(name)),
+        Ty::Dyn(with) => format!("struct {} *", dyn_name(with)),
+        _ => unreachable!("every float type is single or double precision")
+    }
+}
+
+/// `c_type` followed by a declarator, without a space after a `*`.
+fn declare(ty: &Ty, name: &str) -> String {
+    let ty = c_type(ty);
+    if ty.ends_with('*') { format!("{}{}", ty, name) } else { format!("{} {}", ty, name) }
+}
+
+/// The `<stdint.h>` limit macros of an integer type.
+fn limits(ty: &Ty) -> (String, String) {
+    let bits = ty.bits().unwrap_or(64);
+    if ty.is_signed() {
+        (format!("INT{}_MIN", bits), format!("INT{}_MAX", bits))
+    } else {
+        ("0".into(), format!("UINT{}_MAX", bits))
+    }
+}
+
+/// Escapes a string for a C literal. Anything but printable ASCII is written as three
+/// octal digits, so a following digit can't extend the escape, and `?` is escaped so
+/// no trigraph forms.
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'?' => out.push_str("\\?"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// A constant written as a C expression of type `ty`.
+fn constant(value: &Const, ty: &Ty) -> String {
+    match value {
+        Const::Int(value) if *value == i64::MIN as i128 => "INT64_MIN".into(),
+        Const::Int(value) if *value > i64::MAX as i128 => format!("UINT64_C({})", value),
+        Const::Int(value) if *value < 0 => format!("({})", value),
+        Const::Int(value) => value.to_string(),
+        Const::Float(value) => {
+            let text = if value.is_nan() {
+                "NAN".to_string()
+            } else if value.is_infinite() {
+                if *value > 0.0 { "INFINITY".into() } else { "(-INFINITY)".into() }
+            } else if *value < 0.0 {
+                format!("({:?})", value)
+            } else {
+                format!("{:?}", value)
+            };
+            if ty.is_single() { format!("(float){}", text) } else { text }
+        },
+        Const::Bool(value) => value.to_string(),
+        Const::Str(value) => format!("\"{}\"", escape(value))
+    }
+}
+
+/// Routines generated for what the program uses: checked arithmetic for each operation
+/// and integer type, and printers for the structs that get printed.
+#[derive(Default)]
+struct Helpers {
+    arith: BTreeMap<String, String>,
+    shows: BTreeSet<String>
+}
+
+impl Helpers {
+
+    /// The routine for `op` on the integer type `ty`, trapping like the interpreter does.
+    /// Every check happens before the operation, which would otherwise be undefined.
+    fn arith(&mut self, op: BinaryOp, ty: &Ty) -> String {
+        let name = format!("beta_{}_{}", op.as_str(), ty);
+        if self.arith.contains_key(&name) {
+            return name;
+        }
+        let (min, max) = limits(ty);
+        let overflow = format!("beta_trap(\"attempt to {} with overflow\");", op.verb());
+        let mut body = Vec::new();
+        match op {
+            BinaryOp::Add if ty.is_signed() => {
+                body.push(format!("if ((b > 0 && a > {max} - b) || (b < 0 && a < {min} - b)) {overflow}"));
+                body.push(format!("return ({})(a + b);", c_type(ty)));
+            },
+            BinaryOp::Add => {
+                body.push(format!("if (a > {max} - b) {overflow}"));
+                body.push(format!("return ({})(a + b);", c_type(ty)));
+            },
+            BinaryOp::Sub if ty.is_signed() => {
+                body.push(format!("if ((b < 0 && a > {max} + b) || (b > 0 && a < {min} + b)) {overflow}"));
+                body.push(format!("return ({})(a - b);", c_type(ty)));
+            },
+            BinaryOp::Sub => {
+                body.push(format!("if (b > a) {overflow}"));
+                body.push(format!("return ({})(a - b);", c_type(ty)));
+            },
+            BinaryOp::Mul if ty.is_signed() => {
+                body.push(format!(
+                    "if (a > 0 ? (b > 0 ? a > {max} / b : b < {min} / a) : (b > 0 ? a < {min} / b : a != 0 && b < {max} / a)) {overflow}"
+                ));
+                body.push(format!("return ({})(a * b);", c_type(ty)));
+            },
+            BinaryOp::Mul => {
+                body.push(format!("if (b != 0 && a > {max} / b) {overflow}"));
+                body.push(format!("return ({})(a * b);", c_type(ty)));
+            },
+            BinaryOp::Div | BinaryOp::Rem => {
+                let zero = if op == BinaryOp::Div {
+                    "attempt to divide by zero"
+                } else {
+                    "attempt to calculate the remainder with a divisor of zero"
+                };
+                body.push(format!("if (b == 0) beta_trap(\"{}\");", zero));
+                // The most negative value divided by -1 doesn't fit; its remainder is 0.
+                if ty.is_signed() && op == BinaryOp::Div {
+                    body.push(format!("if (a == {min} && b == -1) {overflow}"));
+                } else if ty.is_signed() {
+                    body.push("if (b == -1) return 0;".into());
+                }
+                let symbol = if op == BinaryOp::Div { "/" } else { "%" };
+                body.push(format!("return ({})(a {} b);", c_type(ty), symbol));
+            },
+            _ => unreachable!("comparisons don't trap")
+        }
+        let mut def = format!("static {}({} a, {} b) {{\n", declare(ty, &name), c_type(ty), c_type(ty));
+        for line in body {
+            let _ = writeln!(def, "    {}", line);
+        }
+        def.push_str("}\n");
+        self.arith.insert(name.clone(), def);
+        name
+    }
+
+    /// Negation, which only overflows for the most negative value, or anything but zero
+    /// when unsigned.
+    fn neg(&mut self, ty: &Ty) -> String {
+        let name = format!("beta_neg_{}", ty);
+        if !self.arith.contains_key(&name) {
+            let check = if ty.is_signed() { format!("a == {}", limits(ty).0) } else { "a != 0".to_string() };
+            let def = format!(
+                "static {}({} a) {{\n    if ({}) beta_trap(\"attempt to negate with overflow\");\n    return ({})-a;\n}}\n",
+                declare(ty, &name), c_type(ty), check, c_type(ty)
+            );
+            self.arith.insert(name.clone(), def);
+        }
+        name
+    }
+}
+
+/// The statement printing `value`, of type `ty`; shared with the instance printers.
+fn print_value(value: &str, ty: &Ty, helpers: &mut Helpers) -> String {
+    match ty {
+        Ty::Str => format!("beta_print_str({});", value),
+        Ty::Bool => format!("beta_print_bool({});", value),
+        Ty::Struct(name) => {
+            helpers.shows.insert(name.clone());
+            format!("{}({});", show_name(name), value)
+        },
+        Ty::Dyn(_) => format!("{}->vtable->show({}->obj);", value, value),
+        ty if ty.is_float() => format!("beta_print_float({}, {});", value, ty.is_single()),
+        ty if ty.is_signed() => format!("beta_print_int({});", value),
+        _ => format!("beta_print_uint({});", value)
+    }
+}
+
+/// Writes the routine printing an instance of `structure` the way the interpreter does,
+/// as `Point { x: 1, y: 2 }`.
+fn show(structure: &StructDef, helpers: &mut Helpers) -> String {
+    let mut out = format!("static void {}(struct {} *value) {{\n", show_name(&structure.name), struct_name(&structure.name));
+    if structure.fields.is_empty() {
+        out.push_str("    (void)value;\n");
+    }
+    let mut text = format!("{} {{ ", display_name(&structure.name));
+    for (idx, (name, ty)) in structure.fields.iter().enumerate() {
+        if idx > 0 {
+            text.push_str(", ");
+        }
+        text.push_str(&format!("{}: ", name));
+        let _ = writeln!(out, "    fputs(\"{}\", stdout);", escape(&text));
+        let field = format!("value->{}", field_name(name));
+        if *ty == Ty::Str {
+            let _ = writeln!(out, "    beta_print_str_debug({});", field);
+        } else {
+            let _ = writeln!(out, "    {}", print_value(&field, ty, helpers));
+        }
+        text.clear();
+    }
+    text.push_str(" }");
+    let _ = writeln!(out, "    fputs(\"{}\", stdout);", escape(&text));
+    out.push_str("}\n");
+    out
+}
+
+/// The struct definition; C has no empty structs, so one without fields gets a byte.
+fn definition(structure: &StructDef) -> String {
+    let mut out = format!("struct {} {{\n", struct_name(&structure.name));
+    for (name, ty) in &structure.fields {
+        let _ = writeln!(out, "    {};", declare(ty, &field_name(name)));
+    }
+    if structure.fields.is_empty() {
+        out.push_str("    char empty;\n");
+    }
+    out.push_str("};\n");
+    out
+}
+
+/// The vtable layout of a trait and its objects. Vtable functions take the instance as
+/// a `void *`, and the first one prints it.
+fn trait_definition(def: &TraitDef) -> String {
+    let mut out = format!("struct {} {{\n    void (*show)(void *);\n", vtable_type(&def.name));
+    for slot in &def.slots {
+        let params: Vec<String> = std::iter::once("void *".to_string()).chain(slot.params.iter().map(c_type)).collect();
+        let _ = writeln!(out, "    {};", declare(&slot.ret, &format!("(*{})({})", slot_name(&slot.method), params.join(", "))));
+    }
+    out.push_str("};\n");
+    let _ = writeln!(out, "struct {} {{\n    void *obj;\n    const struct {} *vtable;\n}};", dyn_name(&def.name), vtable_type(&def.name));
+    out
+}
+
+/// The vtable of `vtable.obj` for `def`, behind thunks that give the instance its type
+/// back, since calling a function through a pointer of another type is undefined.
+fn vtable_definition(def: &TraitDef, vtable: &VTable) -> String {
+    let name = vtable_name(&vtable.obj, &vtable.with);
+    let mut out = format!("static void {}_show(void *self) {{\n    {}(self);\n}}\n", name, show_name(&vtable.obj));
+    let mut members = vec![format!("{}_show", name)];
+    for (idx, (slot, function)) in def.slots.iter().zip(&vtable.functions).enumerate() {
+        let thunk = format!("{}_{}", name, idx);
+        let mut params = vec!["void *self".to_string()];
+        let mut args = vec!["self".to_string()];
+        for (arg, ty) in slot.params.iter().enumerate() {
+            params.push(declare(ty, &format!("a{}", arg)));
+            args.push(format!("a{}", arg));
+        }
+        let call = format!("{}({})", function_name(function), args.join(", "));
+        let body = if slot.ret == Ty::Void { format!("{};", call) } else { format!("return {};", call) };
+        let _ = writeln!(out, "static {}({}) {{\n    {}\n}}", declare(&slot.ret, &thunk), params.join(", "), body);
+        members.push(thunk);
+    }
+    let _ = writeln!(out, "static const struct {} {} = {{ {} }};", vtable_type(&def.name), name, members.join(", "));
+    out
+}
+
+fn prototype(function: &Function) -> String {
+    let params: Vec<String> = function.params.iter().map(|(reg, ty)| declare(ty, &reg_name(*reg))).collect();
+    let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+    format!("static {}({})", declare(&function.ret, &function_name(&function.name)), params)
+}
+
+/// Translation of one function. Blocks become labels and terminators `goto`s, and
+/// every register is declared up front, so the function reads like its IR.
+struct Translator<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    types: HashMap<Reg, Ty>,
+    helpers: &'m mut Helpers,
+    /// Blocks some `goto` jumps to.
+    gotos: BTreeSet<BlockId>,
+    out: String
+}
+
+impl<'m> Translator<'m> {
+
+    fn new(module: &'m Module, function: &'m Function, helpers: &'m mut Helpers) -> Self {
+        let mut types: HashMap<Reg, Ty> = function.params.iter().cloned().collect();
+        for (reg, ty) in function.blocks.iter().flat_map(|block| &block.insts).filter_map(Inst::def) {
+            types.insert(reg, ty.clone());
+        }
+        Self { module, function, types, helpers, gotos: BTreeSet::new(), out: String::new() }
+    }
+
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+
+    /// `operand` as a C expression, read as a `ty`.
+    fn operand(&self, operand: &Operand, ty: &Ty) -> String {
+        match operand {
+            Operand::Reg(reg) => reg_name(*reg),
+            Operand::Const(value) => constant(value, ty)
+        }
+    }
+
+    fn field_type(&self, obj: Reg, field: &str) -> Ty {
+        match self.types.get(&obj) {
+            Some(Ty::Struct(name)) => self.module.structure(name).and_then(|def| def.field(field)).cloned().unwrap_or(Ty::I64),
+            _ => Ty::I64
+        }
+    }
+
+    fn function(mut self) -> String {
+        self.out = format!("{} {{\n", prototype(self.function));
+
+        let params: Vec<Reg> = self.function.params.iter().map(|(reg, _)| *reg).collect();
+        let mut locals: Vec<(Reg, Ty)> = self.types.iter()
+            .filter(|(reg, _)| !params.contains(reg))
+            .map(|(reg, ty)| (*reg, ty.clone()))
+            .collect();
+        locals.sort_by_key(|(reg, _)| *reg);
+        for (reg, ty) in &locals {
+            self.emit(format!("{};", declare(ty, &reg_name(*reg))));
+        }
+
+        // Only blocks a `goto` goes to need a label; the rest are fallen into.
+        let mut body = Vec::new();
+        let head = std::mem::take(&mut self.out);
+        let blocks = &self.function.blocks;
+        for (idx, block) in blocks.iter().enumerate() {
+            for inst in &block.insts {
+                self.inst(inst);
+            }
+            let next = blocks.get(idx + 1).map(|block| block.id);
+            self.terminator(&block.term, next);
+            body.push((block.id, std::mem::take(&mut self.out)));
+        }
+        let mut out = head;
+        for (block, text) in body {
+            if self.gotos.contains(&block) {
+                let _ = writeln!(out, "{}:", block_label(block));
+            }
+            out.push_str(&text);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn terminator(&mut self, term: &Terminator, next: Option<BlockId>) {
+        match term {
+            Terminator::Ret(value) => {
+                match value {
+                    Some(value) => {
+                        let value = self.operand(value, &self.function.ret);
+                        self.emit(format!("return {};", value));
+                    },
+                    None => self.emit("return;")
+                }
+            },
+            Terminator::Jump(target) => self.jump(*target, next),
+            Terminator::Branch { cond: Operand::Const(Const::Bool(cond)), then, els } => {
+                self.jump(if *cond { *then } else { *els }, next);
+            },
+            Terminator::Branch { cond, then, els } => {
+                let cond = self.operand(cond, &Ty::Bool);
+                if next == Some(*then) {
+                    self.gotos.insert(*els);
+                    self.emit(format!("if (!{}) goto {};", cond, block_label(*els)));
+                } else {
+                    self.gotos.insert(*then);
+                    self.emit(format!("if ({}) goto {};", cond, block_label(*then)));
+                    self.jump(*els, next);
+                }
+            },
+            Terminator::Unreachable => self.emit("abort();")
+        }
+    }
+
+    fn jump(&mut self, target: BlockId, next: Option<BlockId>) {
+        if next != Some(target) {
+            self.gotos.insert(target);
+            self.emit(format!("goto {};", block_label(target)));
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dst, ty, src } => {
+                let src = self.operand(src, ty);
+                self.emit(format!("{} = {};", reg_name(*dst), src));
+            },
+            Inst::Binary { dst, ty, op, lhs, rhs } => {
+                let operand_ty = operand_type(&self.types, *op, ty, lhs, rhs);
+                let (a, b) = (self.operand(lhs, &operand_ty), self.operand(rhs, &operand_ty));
+                let value = if op.is_comparison() {
+                    let symbol = match op {
+                        BinaryOp::Eq => "==",
+                        BinaryOp::Ne => "!=",
+                        BinaryOp::Lt => "<",
+                        BinaryOp::Le => "<=",
+                        BinaryOp::Gt => ">",
+                        _ => ">="
+                    };
+                    if operand_ty == Ty::Str {
+                        format!("strcmp({}, {}) {} 0", a, b, symbol)
+                    } else {
+                        format!("{} {} {}", a, symbol, b)
+                    }
+                } else if operand_ty.is_integer() {
+                    format!("{}({}, {})", self.helpers.arith(*op, &operand_ty), a, b)
+                } else if operand_ty.is_float() {
+                    match op {
+                        BinaryOp::Add => format!("{} + {}", a, b),
+                        BinaryOp::Sub => format!("{} - {}", a, b),
+                        BinaryOp::Mul => format!("{} * {}", a, b),
+                        BinaryOp::Div => format!("{} / {}", a, b),
+                        _ => format!("{}({}, {})", if operand_ty.is_single() { "fmodf" } else { "fmod" }, a, b)
+                    }
+                } else {
+                    // Only strings are left, and they only add.
+                    format!("beta_concat({}, {})", a, b)
+                };
+                self.emit(format!("{} = {};", reg_name(*dst), value));
+            },
+            Inst::Unary { dst, ty, op, src } => {
+                let src = self.operand(src, ty);
+                let value = match op {
+                    UnaryOp::Neg if ty.is_float() => format!("-{}", src),
+                    UnaryOp::Neg => format!("{}({})", self.helpers.neg(ty), src),
+                    UnaryOp::Not => format!("!{}", src),
+                    UnaryOp::BitNot => format!("({})~{}", c_type(ty), src)
+                };
+                self.emit(format!("{} = {};", reg_name(*dst), value));
+            },
+            Inst::Call { dst, func, args } => self.call(dst.as_ref(), func, args),
+            Inst::Load { dst, global, .. } => self.emit(format!("{} = {};", reg_name(*dst), global_name(global))),
+            Inst::Store { global, src } => {
+                let ty = self.module.global(global).map_or(Ty::I64, |global| global.ty.clone());
+                let src = self.operand(src, &ty);
+                self.emit(format!("{} = {};", global_name(global), src));
+            },
+            Inst::New { dst, ty, args } => {
+                let fields = match ty {
+                    Ty::Struct(name) => self.module.structure(name).map(|def| def.fields.clone()).unwrap_or_default(),
+                    _ => Vec::new()
+                };
+                let dst = reg_name(*dst);
+                self.emit(format!("{} = beta_alloc(sizeof *{});", dst, dst));
+                for ((name, ty), arg) in fields.iter().zip(args) {
+                    let arg = self.operand(arg, ty);
+                    self.emit(format!("{}->{} = {};", dst, field_name(name), arg));
+                }
+            },
+            Inst::GetField { dst, obj, field, .. } => {
+                self.emit(format!("{} = {}->{};", reg_name(*dst), reg_name(*obj), field_name(field)));
+            },
+            Inst::SetField { obj, field, src } => {
+                let ty = self.field_type(*obj, field);
+                let src = self.operand(src, &ty);
+                self.emit(format!("{}->{} = {};", reg_name(*obj), field_name(field), src));
+            },
+            Inst::Dyn { dst, ty, concrete, src } => {
+                let Ty::Dyn(with) = ty else {
+                    unreachable!("the verifier only allows `dyn` to define trait objects");
+                };
+                let dst = reg_name(*dst);
+                self.emit(format!("{} = beta_alloc(sizeof *{});", dst, dst));
+                self.emit(format!("{}->obj = {};", dst, reg_name(*src)));
+                self.emit(format!("{}->vtable = &{};", dst, vtable_name(concrete, with)));
+            },
+            Inst::CallDyn { dst, recv, method, args } => {
+                let params = match self.types.get(recv) {
+                    Some(Ty::Dyn(with)) => self.module.trait_def(with).and_then(|def| def.slot(method)).map(|(_, slot)| slot.params.clone()),
+                    _ => None
+                }.unwrap_or_default();
+                let recv = reg_name(*recv);
+                let mut values = vec![format!("{}->obj", recv)];
+                values.extend(args.iter().zip(&params).map(|(arg, ty)| self.operand(arg, ty)));
+                let call = format!("{}->vtable->{}({})", recv, slot_name(method), values.join(", "));
+                match dst {
+                    Some((dst, _)) => self.emit(format!("{} = {};", reg_name(*dst), call)),
+                    None => self.emit(format!("{};", call))
+                }
+            },
+            Inst::Phi { .. } => unreachable!("phis are removed before translation")
+        }
+    }
+
+    fn call(&mut self, dst: Option<&(Reg, Ty)>, func: &str, args: &[Operand]) {
+        if let Some(builtin) = Builtin::ALL.into_iter().find(|builtin| builtin.name() == func) {
+            return self.print(builtin, args);
+        }
+        let Some(callee) = self.module.function(func) else {
+            unreachable!("calls to unknown functions are rejected before translation");
+        };
+        let args: Vec<String> = args.iter().zip(&callee.params).map(|(arg, (_, ty))| self.operand(arg, ty)).collect();
+        let call = format!("{}({})", function_name(func), args.join(", "));
+        match dst {
+            Some((dst, _)) => self.emit(format!("{} = {};", reg_name(*dst), call)),
+            None => self.emit(format!("{};", call))
+        }
+    }
+
+    /// `print` and `println` write their arguments separated by spaces, each according
+    /// to its type.
+    fn print(&mut self, builtin: Builtin, args: &[Operand]) {
+        for (idx, arg) in args.iter().enumerate() {
+            if idx > 0 {
+                self.emit("putchar(' ');");
+            }
+            let ty = type_of(&self.types, arg);
+            let value = self.operand(arg, &ty);
+            let line = print_value(&value, &ty, self.helpers);
+            self.emit(line);
+        }
+        if builtin == Builtin::Println {
+            self.emit("putchar('\\n');");
+        }
+    }
+}
+
+/// Generates a single C11 translation unit. Integer types map to `<stdint.h>` types,
+/// `f8` to `f32` to `float`, and instances of an `obj` or `comp` are pointers to a
+/// struct. A trait object points to the instance and a constant vtable of function
+/// pointers. `main` runs the module's `.init`, then the program's `main`, and returns 0;
+/// runtime errors exit with the status the interpreter uses. Programs taking the
+/// remainder of floats call `fmod`, so they link with `-lm`.
+pub(crate) fn generate(module: &Module) -> Result<String, Vec<Diagnostic>> {
+    check(module, "C")?;
+    let mut module = module.clone();
+    for function in &mut module.functions {
+        ssa::destruct(function);
+    }
+
+    let mut helpers = Helpers::default();
+    let functions: Vec<String> = module.functions.iter()
+        .map(|function| Translator::new(&module, function, &mut helpers).function())
+        .collect();
+    // Vtables can print their instances, and printing an instance prints its fields,
+    // which may be instances too.
+    helpers.shows.extend(module.vtables.iter().map(|vtable| vtable.obj.clone()));
+    let mut pending: Vec<String> = helpers.shows.iter().cloned().collect();
+    while let Some(name) = pending.pop() {
+        for (_, ty) in module.structure(&name).map_or(&[][..], |def| &def.fields) {
+            if let Ty::Struct(field) = ty {
+                if helpers.shows.insert(field.clone()) {
+                    pending.push(field.clone());
+                }
+            }
+        }
+    }
+    let shows: Vec<&StructDef> = module.structs.iter().filter(|def| helpers.shows.contains(&def.name)).collect();
+
+    let mut out = String::from(RUNTIME);
+    if !module.structs.is_empty() || !module.traits.is_empty() {
+        out.push('\n');
+        for structure in &module.structs {
+            let _ = writeln!(out, "struct {};", struct_name(&structure.name));
+        }
+        for def in &module.traits {
+            let _ = writeln!(out, "struct {};", dyn_name(&def.name));
+        }
+        for structure in &module.structs {
+            out.push('\n');
+            out.push_str(&definition(structure));
+        }
+        for def in &module.traits {
+            out.push('\n');
+            out.push_str(&trait_definition(def));
+        }
+    }
+    if !module.globals.is_empty() {
+        out.push('\n');
+        for global in &module.globals {
+            let name = global_name(&global.name);
+            let _ = match &global.init {
+                Some(init) => writeln!(out, "static {} = {};", declare(&global.ty, &name), constant(init, &global.ty)),
+                None => writeln!(out, "static {};", declare(&global.ty, &name))
+            };
+        }
+    }
+    for def in helpers.arith.values() {
+        out.push('\n');
+        out.push_str(def);
+    }
+    if !module.functions.is_empty() || !shows.is_empty() {
+        out.push('\n');
+    }
+    for structure in &shows {
+        let _ = writeln!(out, "static void {}(struct {} *value);", show_name(&structure.name), struct_name(&structure.name));
+    }
+    for function in &module.functions {
+        let _ = writeln!(out, "{};", prototype(function));
+    }
+    for vtable in &module.vtables {
+        if let Some(def) = module.trait_def(&vtable.with) {
+            out.push('\n');
+            out.push_str(&vtable_definition(def, vtable));
+        }
+    }
+    for structure in &shows {
+        out.push('\n');
+        out.push_str(&show(structure, &mut helpers));
+    }
+    for function in functions {
+        out.push('\n');
+        out.push_str(&function);
+    }
+
+    out.push_str("\nint main(void) {\n");
+    for entry in [Module::INIT, "main"] {
+        if module.function(entry).is_some() {
+            let _ = writeln!(out, "    {}();", function_name(entry));
+        }
+    }
+    out.push_str("    return 0;\n}\n");
+    Ok(out)
+}
@@ -0,0 +1,122 @@
+#include <inttypes.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Reports a runtime error and exits with the status the interpreter uses for one. */
+static _Noreturn void beta_trap(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(5);
+}
+
+/* Instances and concatenated strings are never freed. */
+static inline void *beta_alloc(size_t size) {
+    void *memory = malloc(size ? size : 1);
+    if (!memory) {
+        beta_trap("out of memory");
+    }
+    return memory;
+}
+
+static inline const char *beta_concat(const char *a, const char *b) {
+    size_t len_a = strlen(a), len_b = strlen(b);
+    char *out = beta_alloc(len_a + len_b + 1);
+    memcpy(out, a, len_a);
+    memcpy(out + len_a, b, len_b + 1);
+    return out;
+}
+
+static inline void beta_print_int(int64_t value) {
+    printf("%" PRId64, value);
+}
+
+static inline void beta_print_uint(uint64_t value) {
+    printf("%" PRIu64, value);
+}
+
+static inline void beta_print_bool(bool value) {
+    fputs(value ? "true" : "false", stdout);
+}
+
+static inline void beta_print_str(const char *value) {
+    fputs(value, stdout);
+}
+
+/* Strings inside an instance are quoted, with quotes, backslashes and line breaks escaped. */
+static inline void beta_print_str_debug(const char *value) {
+    putchar('"');
+    for (; *value; value++) {
+        switch (*value) {
+        case '"': fputs("\\\"", stdout); break;
+        case '\\': fputs("\\\\", stdout); break;
+        case '\n': fputs("\\n", stdout); break;
+        case '\t': fputs("\\t", stdout); break;
+        case '\r': fputs("\\r", stdout); break;
+        default: putchar(*value);
+        }
+    }
+    putchar('"');
+}
+
+/* Prints the fewest significant digits that read back as the same value, without an
+   exponent, like the interpreter. */
+static inline void beta_print_float(double value, bool single) {
+    char buffer[32], digits[32];
+    int precision, count = 0, exponent, idx;
+    const char *at;
+
+    if (isnan(value)) {
+        fputs("NaN", stdout);
+        return;
+    }
+    if (signbit(value)) {
+        putchar('-');
+        value = -value;
+    }
+    if (isinf(value)) {
+        fputs("inf", stdout);
+        return;
+    }
+    if (value == 0) {
+        putchar('0');
+        return;
+    }
+    for (precision = 1; ; precision++) {
+        snprintf(buffer, sizeof buffer, "%.*e", precision - 1, value);
+        if (precision == (single ? 9 : 17)) {
+            break;
+        }
+        if (single ? strtof(buffer, NULL) == (float)value : strtod(buffer, NULL) == value) {
+            break;
+        }
+    }
+
+    for (at = buffer; *at != 'e'; at++) {
+        if (*at != '.') {
+            digits[count++] = *at;
+        }
+    }
+    exponent = atoi(at + 1);
+    while (count > 1 && digits[count - 1] == '0') {
+        count--;
+    }
+    if (exponent < 0) {
+        fputs("0.", stdout);
+        for (idx = 0; idx < -exponent - 1; idx++) {
+            putchar('0');
+        }
+        fwrite(digits, 1, count, stdout);
+        return;
+    }
+    for (idx = 0; idx <= exponent; idx++) {
+        putchar(idx < count ? digits[idx] : '0');
+    }
+    if (count > exponent + 1) {
+        putchar('.');
+        fwrite(digits + exponent + 1, 1, count - exponent - 1, stdout);
+    }
+}
@@ -0,0 +1,67 @@
+pub(crate) mod c;
+pub(crate) mod wasm;
+pub(crate) mod x86_64;
+
+use std::collections::HashMap;
+
+use crate::{
+    analysis::symbols::Builtin,
+    common::{diagnostics::{codes, Diagnostic}, Targets},
+    ir::{BinaryOp, Const, Inst, Module, Operand, Reg, Ty}
+};
+
+/// Generates code for `target` from an optimized, verified module, as the contents of
+/// the file `build` writes.
+pub(crate) fn generate(target: Targets, module: &Module) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    match target {
+        Targets::X86_64Linux => x86_64::generate(module).map(String::into_bytes),
+        Targets::Wasm32 => {
+            let bytes = wasm::generate(module)?.encode();
+            // Modules are checked before they are written, so a module that doesn't
+            // validate is a compiler bug rather than a broken file.
+            wasm::validate(&bytes).map_err(|err| {
+                vec![Diagnostic::error(format!("generated an invalid WebAssembly module: {}", err)).with_code(codes::INVALID_WASM)]
+            })?;
+            Ok(bytes)
+        },
+        Targets::Bytecode => crate::vm::compile::compile(module).map(|program| crate::vm::format::encode(&program))
+    }
+}
+
+/// Calls can only go to the module's own functions and to the builtins every target's
+/// runtime implements; `target` names the target in the errors for any other call.
+pub(crate) fn check(module: &Module, target: &str) -> Result<(), Vec<Diagnostic>> {
+    let errors: Vec<Diagnostic> = module.functions.iter()
+        .flat_map(|function| function.blocks.iter().flat_map(|block| &block.insts))
+        .filter_map(|inst| match inst {
+            Inst::Call { func, .. } if module.function(func).is_none() && !Builtin::ALL.iter().any(|builtin| builtin.name() == func) => {
+                Some(Diagnostic::error(format!("`{}` is not available on {}", func, target)).with_code(codes::UNSUPPORTED_BY_TARGET))
+            },
+            _ => None
+        })
+        .collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// The type the operands of a `Binary` of type `ty` are read as. A comparison gives a
+/// `bool`, so its operands are typed by whichever side is a register; there is always
+/// one.
+pub(crate) fn operand_type(types: &HashMap<Reg, Ty>, op: BinaryOp, ty: &Ty, lhs: &Operand, rhs: &Operand) -> Ty {
+    if !op.is_comparison() {
+        return ty.clone();
+    }
+    if lhs.reg().is_some() { type_of(types, lhs) } else { type_of(types, rhs) }
+}
+
+/// The type an operand is read as; constants have no type of their own, so this is
+/// only used where any width of the right kind will do, as for the arguments of `print`.
+pub(crate) fn type_of(types: &HashMap<Reg, Ty>, operand: &Operand) -> Ty {
+    match operand {
+        Operand::Reg(reg) => types[reg].clone(),
+        Operand::Const(Const::Int(value)) if *value > i64::MAX as i128 => Ty::U64,
+        Operand::Const(Const::Int(_)) => Ty::I64,
+        Operand::Const(Const::Float(_)) => Ty::F64,
+        Operand::Const(Const::Bool(_)) => Ty::Bool,
+        Operand::Const(Const::Str(_)) => Ty::Str
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use super::{opcodes::{self, *}, FuncType, Instr, ValType, Wasm};
+
+/// Section ids.
+pub(super) mod id {
+    pub const CUSTOM: u8 = 0;
+    pub const TYPE: u8 = 1;
+    pub const IMPORT: u8 = 2;
+    pub const FUNCTION: u8 = 3;
+    pub const TABLE: u8 = 4;
+    pub const MEMORY: u8 = 5;
+    pub const GLOBAL: u8 = 6;
+    pub const EXPORT: u8 = 7;
+    pub const START: u8 = 8;
+    pub const ELEMENT: u8 = 9;
+    pub const CODE: u8 = 10;
+    pub const DATA: u8 = 11;
+    pub const DATA_COUNT: u8 = 12;
+}
+
+pub(super) const MAGIC: &[u8] = b"\0asm";
+pub(super) const VERSION: &[u8] = &[1, 0, 0, 0];
+
+/// The prefix of a function type in the type section.
+pub(super) const FUNC_TYPE: u8 = 0x60;
+
+/// The element type of a table of functions.
+pub(super) const FUNC_REF: u8 = 0x70;
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, value: &str) {
+    unsigned(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, count: usize, contents: Vec<u8>) {
+    if count == 0 {
+        return;
+    }
+    let mut body = Vec::new();
+    unsigned(&mut body, count as u64);
+    body.extend(contents);
+    out.push(id);
+    unsigned(out, body.len() as u64);
+    out.extend(body);
+}
+
+fn block_type(out: &mut Vec<u8>, ty: Option<ValType>) {
+    out.push(ty.map_or(EMPTY, ValType::byte));
+}
+
+/// `types` gives the index of every function type `call_indirect` can name.
+fn instr(out: &mut Vec<u8>, instr: &Instr, types: &HashMap<&FuncType, u32>) {
+    match instr {
+        Instr::Simple(opcode) => out.push(*opcode),
+        Instr::Block(ty) => {
+            out.push(BLOCK);
+            block_type(out, *ty);
+        },
+        Instr::Loop(ty) => {
+            out.push(LOOP);
+            block_type(out, *ty);
+        },
+        Instr::If(ty) => {
+            out.push(IF);
+            block_type(out, *ty);
+        },
+        Instr::Else => out.push(ELSE),
+        Instr::End => out.push(END),
+        Instr::Br(depth) => {
+            out.push(BR);
+            unsigned(out, *depth as u64);
+        },
+        Instr::BrIf(depth) => {
+            out.push(BR_IF);
+            unsigned(out, *depth as u64);
+        },
+        Instr::BrTable(targets, default) => {
+            out.push(BR_TABLE);
+            unsigned(out, targets.len() as u64);
+            for target in targets {
+                unsigned(out, *target as u64);
+            }
+            unsigned(out, *default as u64);
+        },
+        Instr::Return => out.push(RETURN),
+        Instr::Call(index) => {
+            out.push(CALL);
+            unsigned(out, *index as u64);
+        },
+        Instr::CallIndirect(ty) => {
+            out.push(CALL_INDIRECT);
+            unsigned(out, types[ty] as u64);
+            out.push(0x00);
+        },
+        Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => {
+            out.push(match instr {
+                Instr::LocalGet(_) => LOCAL_GET,
+                Instr::LocalSet(_) => LOCAL_SET,
+                _ => LOCAL_TEE
+            });
+            unsigned(out, *index as u64);
+        },
+        Instr::GlobalGet(index) => {
+            out.push(GLOBAL_GET);
+            unsigned(out, *index as u64);
+        },
+        Instr::GlobalSet(index) => {
+            out.push(GLOBAL_SET);
+            unsigned(out, *index as u64);
+        },
+        Instr::Memory { opcode, offset } => {
+            out.push(*opcode);
+            let align = opcodes::memory(*opcode).expect("memory instructions are known").align;
+            unsigned(out, align as u64);
+            unsigned(out, *offset as u64);
+        },
+        Instr::MemorySize => out.extend([MEMORY_SIZE, 0]),
+        Instr::MemoryGrow => out.extend([MEMORY_GROW, 0]),
+        Instr::I32Const(value) => {
+            out.push(I32_CONST);
+            signed(out, *value as i64);
+        },
+        Instr::I64Const(value) => {
+            out.push(I64_CONST);
+            signed(out, *value);
+        },
+        Instr::F32Const(value) => {
+            out.push(F32_CONST);
+            out.extend(value.to_le_bytes());
+        },
+        Instr::F64Const(value) => {
+            out.push(F64_CONST);
+            out.extend(value.to_le_bytes());
+        }
+    }
+}
+
+/// The binary format, with a `name` section so tools show function names.
+pub(super) fn encode(wasm: &Wasm) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(VERSION);
+
+    let mut types: Vec<&FuncType> = Vec::new();
+    let mut type_indices: HashMap<&FuncType, u32> = HashMap::new();
+    let indirect = wasm.funcs.iter().flat_map(|func| &func.body).filter_map(|instr| match instr {
+        Instr::CallIndirect(ty) => Some(ty),
+        _ => None
+    });
+    for ty in wasm.imports.iter().map(|import| &import.ty).chain(wasm.funcs.iter().map(|func| &func.ty)).chain(indirect) {
+        type_indices.entry(ty).or_insert_with(|| {
+            types.push(ty);
+            types.len() as u32 - 1
+        });
+    }
+
+    let mut contents = Vec::new();
+    for ty in &types {
+        contents.push(FUNC_TYPE);
+        unsigned(&mut contents, ty.params.len() as u64);
+        contents.extend(ty.params.iter().map(|ty| ty.byte()));
+        unsigned(&mut contents, ty.results.len() as u64);
+        contents.extend(ty.results.iter().map(|ty| ty.byte()));
+    }
+    section(&mut out, id::TYPE, types.len(), contents);
+
+    let mut contents = Vec::new();
+    for import in &wasm.imports {
+        name(&mut contents, &import.module);
+        name(&mut contents, &import.field);
+        contents.push(0x00);
+        unsigned(&mut contents, type_indices[&import.ty] as u64);
+    }
+    section(&mut out, id::IMPORT, wasm.imports.len(), contents);
+
+    let mut contents = Vec::new();
+    for func in &wasm.funcs {
+        unsigned(&mut contents, type_indices[&func.ty] as u64);
+    }
+    section(&mut out, id::FUNCTION, wasm.funcs.len(), contents);
+
+    // One table of function references, exactly as large as its entries.
+    let mut contents = vec![FUNC_REF, 0x00];
+    unsigned(&mut contents, wasm.table.len() as u64);
+    section(&mut out, id::TABLE, usize::from(!wasm.table.is_empty()), contents);
+
+    let mut contents = vec![0x00];
+    unsigned(&mut contents, wasm.pages as u64);
+    section(&mut out, id::MEMORY, 1, contents);
+
+    let mut contents = Vec::new();
+    for global in &wasm.globals {
+        contents.push(global.ty.byte());
+        contents.push(global.mutable as u8);
+        instr(&mut contents, &global.init, &type_indices);
+        contents.push(END);
+    }
+    section(&mut out, id::GLOBAL, wasm.globals.len(), contents);
+
+    let mut contents = Vec::new();
+    name(&mut contents, "memory");
+    contents.extend([0x02, 0x00]);
+    let mut exports = 1;
+    for (idx, func) in wasm.funcs.iter().enumerate() {
+        if let Some(export) = &func.export {
+            name(&mut contents, export);
+            contents.push(0x00);
+            unsigned(&mut contents, (wasm.imports.len() + idx) as u64);
+            exports += 1;
+        }
+    }
+    section(&mut out, id::EXPORT, exports, contents);
+
+    let mut contents = vec![0x00];
+    instr(&mut contents, &Instr::I32Const(0), &type_indices);
+    contents.push(END);
+    unsigned(&mut contents, wasm.table.len() as u64);
+    for index in &wasm.table {
+        unsigned(&mut contents, *index as u64);
+    }
+    section(&mut out, id::ELEMENT, usize::from(!wasm.table.is_empty()), contents);
+
+    let mut contents = Vec::new();
+    for func in &wasm.funcs {
+        // Locals are declared as runs of one type.
+        let mut runs: Vec<(u32, ValType)> = Vec::new();
+        for ty in &func.locals {
+            match runs.last_mut() {
+                Some((count, last)) if last == ty => *count += 1,
+                _ => runs.push((1, *ty))
+            }
+        }
+        let mut body = Vec::new();
+        unsigned(&mut body, runs.len() as u64);
+        for (count, ty) in runs {
+            unsigned(&mut body, count as u64);
+            body.push(ty.byte());
+        }
+        for item in &func.body {
+            instr(&mut body, item, &type_indices);
+        }
+        body.push(END);
+        unsigned(&mut contents, body.len() as u64);
+        contents.extend(body);
+    }
+    section(&mut out, id::CODE, wasm.funcs.len(), contents);
+
+    let mut contents = Vec::new();
+    for (address, bytes) in &wasm.data {
+        contents.push(0x00);
+        instr(&mut contents, &Instr::I32Const(*address as i32), &type_indices);
+        contents.push(END);
+        unsigned(&mut contents, bytes.len() as u64);
+        contents.extend_from_slice(bytes);
+    }
+    section(&mut out, id::DATA, wasm.data.len(), contents);
+
+    let mut names = Vec::new();
+    let count = wasm.imports.len() + wasm.funcs.len();
+    unsigned(&mut names, count as u64);
+    for index in 0..count {
+        unsigned(&mut names, index as u64);
+        name(&mut names, wasm.function_name(index as u32));
+    }
+    let mut contents = Vec::new();
+    name(&mut contents, "name");
+    contents.push(0x01);
+    unsigned(&mut contents, names.len() as u64);
+    contents.extend(names);
+    out.push(id::CUSTOM);
+    unsigned(&mut out, contents.len() as u64);
+    out.extend(contents);
+    out
+}
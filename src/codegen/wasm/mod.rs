@@ -0,0 +1,454 @@
+mod binary;
+mod opcodes;
+mod parse;
+mod select;
+mod text;
+mod validate;
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    codegen::check,
+    common::diagnostics::Diagnostic,
+    ir::{display_name, ssa, Const, Module, StructDef, Ty}
+};
+
+use self::select::{print_value, Selector};
+
+pub(crate) use self::validate::validate;
+
+/// Routines every program needs, in the flat text form `parse` reads.
+const RUNTIME: &str = include_str!("runtime.wat");
+
+/// Linear memory starts with scratch space the runtime uses for system calls and
+/// number formatting; string constants follow, then the heap.
+const DATA_START: u32 = 64;
+const PAGE_SIZE: u32 = 65536;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum ValType {
+    I32,
+    I64,
+    F32,
+    F64
+}
+
+impl ValType {
+    fn byte(self) -> u8 {
+        match self {
+            Self::I32 => 0x7f,
+            Self::I64 => 0x7e,
+            Self::F32 => 0x7d,
+            Self::F64 => 0x7c
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0x7f => Self::I32,
+            0x7e => Self::I64,
+            0x7d => Self::F32,
+            0x7c => Self::F64,
+            _ => return None
+        })
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "i32" => Self::I32,
+            "i64" => Self::I64,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            _ => return None
+        })
+    }
+
+    /// The wasm type a value of `ty` is held in. Integers up to 32 bits, booleans, and
+    /// addresses of strings and instances in linear memory are all `i32`.
+    fn from_ty(ty: &Ty) -> Option<Self> {
+        match ty {
+            Ty::Void => None,
+            Ty::I64 | Ty::U64 => Some(Self::I64),
+            Ty::F64 => Some(Self::F64),
+            ty if ty.is_float() => Some(Self::F32),
+            _ => Some(Self::I32)
+        }
+    }
+}
+
+impl Display for ValType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64"
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub(super) struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>
+}
+
+/// An instruction; branches count enclosing blocks outwards from 0, and calls and
+/// global accesses use indices into the module's index spaces.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Instr {
+    /// An instruction without immediates, by opcode.
+    Simple(u8),
+    Block(Option<ValType>),
+    Loop(Option<ValType>),
+    If(Option<ValType>),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    /// A call through the table, to a function of the given type.
+    CallIndirect(FuncType),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// A load or store at its natural alignment.
+    Memory { opcode: u8, offset: u32 },
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64)
+}
+
+impl Instr {
+    fn simple(name: &str) -> Self {
+        Self::Simple(opcodes::simple_named(name).expect("instruction names are known").opcode)
+    }
+
+    fn memory(name: &str, offset: u32) -> Self {
+        Self::Memory { opcode: opcodes::memory_named(name).expect("instruction names are known").opcode, offset }
+    }
+}
+
+/// A function the host provides.
+#[derive(Clone, Debug)]
+pub(super) struct Import {
+    pub module: String,
+    pub field: String,
+    pub name: String,
+    pub ty: FuncType
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Func {
+    pub name: String,
+    pub ty: FuncType,
+    /// Locals after the parameters.
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+    pub export: Option<String>
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Global {
+    pub name: String,
+    pub ty: ValType,
+    pub mutable: bool,
+    /// A constant instruction.
+    pub init: Instr
+}
+
+/// A WebAssembly module with one memory, exported as `memory`, and a table of vtable
+/// entries when trait objects are used. Imported functions come first in the function
+/// index space.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Wasm {
+    imports: Vec<Import>,
+    funcs: Vec<Func>,
+    globals: Vec<Global>,
+    /// The functions in the table `call_indirect` goes through, from slot 0.
+    table: Vec<u32>,
+    /// The memory's initial size, in 64 KiB pages.
+    pages: u32,
+    /// Active data segments, each placed at an address.
+    data: Vec<(u32, Vec<u8>)>
+}
+
+impl Wasm {
+
+    /// The binary format.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        binary::encode(self)
+    }
+
+    fn function_name(&self, index: u32) -> &str {
+        let index = index as usize;
+        match self.imports.get(index) {
+            Some(import) => &import.name,
+            None => &self.funcs[index - self.imports.len()].name
+        }
+    }
+}
+
+/// IR names like `Point::sum` and `.init` are valid wasm identifiers, but a function
+/// could share a name with a runtime routine, so each kind of name gets a prefix.
+fn function_symbol(name: &str) -> String {
+    format!("fn.{}", name)
+}
+
+fn global_symbol(name: &str) -> String {
+    format!("global.{}", name)
+}
+
+/// The routine that prints an instance of the struct `name`.
+fn show_symbol(name: &str) -> String {
+    format!("show.{}", name)
+}
+
+/// The routine that prints a trait object through its vtable.
+const SHOW_DYN: &str = "beta_show_dyn";
+
+/// The module under construction, with names for everything `parse` and the
+/// selector refer to.
+pub(super) struct Builder {
+    wasm: Wasm,
+    functions: HashMap<String, u32>,
+    globals: HashMap<String, u32>,
+    strings: HashMap<Vec<u8>, u32>,
+    data: Vec<u8>,
+    /// The first table slot of the vtable of each struct for each trait.
+    vtables: HashMap<(String, String), u32>
+}
+
+impl Builder {
+
+    fn new() -> Self {
+        Self {
+            wasm: Wasm::default(),
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            strings: HashMap::new(),
+            data: Vec::new(),
+            vtables: HashMap::new()
+        }
+    }
+
+    /// Imports have to be added before any function is.
+    fn import(&mut self, module: &str, field: &str, ty: FuncType) {
+        let index = self.wasm.imports.len() as u32;
+        self.wasm.imports.push(Import { module: module.into(), field: field.into(), name: field.into(), ty });
+        self.functions.insert(field.into(), index);
+    }
+
+    fn global(&mut self, name: String, ty: ValType, init: Instr) -> u32 {
+        let index = self.wasm.globals.len() as u32;
+        self.globals.insert(name.clone(), index);
+        self.wasm.globals.push(Global { name, ty, mutable: true, init });
+        index
+    }
+
+    /// Adds a function without a body yet, so calls to it can be made before it is
+    /// defined.
+    fn reserve(&mut self, name: String, ty: FuncType, export: Option<String>) -> u32 {
+        let index = (self.wasm.imports.len() + self.wasm.funcs.len()) as u32;
+        self.functions.insert(name.clone(), index);
+        self.wasm.funcs.push(Func { name, ty, locals: Vec::new(), body: Vec::new(), export });
+        index
+    }
+
+    fn define(&mut self, index: u32, locals: Vec<ValType>, body: Vec<Instr>) {
+        let func = &mut self.wasm.funcs[index as usize - self.wasm.imports.len()];
+        func.locals = locals;
+        func.body = body;
+    }
+
+    fn function(&self, name: &str) -> Option<u32> {
+        self.functions.get(name).copied()
+    }
+
+    fn function_type(&self, index: u32) -> &FuncType {
+        let index = index as usize;
+        match self.wasm.imports.get(index) {
+            Some(import) => &import.ty,
+            None => &self.wasm.funcs[index - self.wasm.imports.len()].ty
+        }
+    }
+
+    fn global_index(&self, name: &str) -> Option<u32> {
+        self.globals.get(name).copied()
+    }
+
+    /// The address of a NUL-terminated copy of `value`, placed in memory once.
+    fn string(&mut self, value: &[u8]) -> u32 {
+        if let Some(address) = self.strings.get(value) {
+            return *address;
+        }
+        let address = DATA_START + self.data.len() as u32;
+        self.data.extend_from_slice(value);
+        self.data.push(0);
+        self.strings.insert(value.to_vec(), address);
+        address
+    }
+
+    /// The table slot where the vtable of `obj` for `with` starts.
+    fn vtable_base(&self, obj: &str, with: &str) -> Option<u32> {
+        self.vtables.get(&(obj.to_string(), with.to_string())).copied()
+    }
+
+    /// A function defined by flat text, unless it exists already.
+    fn helper(&mut self, name: &str, text: impl FnOnce() -> String) -> u32 {
+        if let Some(index) = self.function(name) {
+            return index;
+        }
+        parse::define(&text(), self).expect("generated helpers are well-formed");
+        self.function(name).expect("the helper defines its name")
+    }
+
+    /// Places the strings and starts the heap after them.
+    fn finish(mut self) -> Wasm {
+        let heap = (DATA_START + self.data.len() as u32 + 15) & !15;
+        let index = self.global_index("heap").expect("the heap pointer is a runtime global");
+        self.wasm.globals[index as usize].init = Instr::I32Const(heap as i32);
+        self.wasm.pages = heap / PAGE_SIZE + 1;
+        if !self.data.is_empty() {
+            self.wasm.data.push((DATA_START, self.data));
+        }
+        self.wasm
+    }
+}
+
+/// The initial value of a global, which is its zero unless the IR gives a constant.
+fn global_init(init: Option<&Const>, ty: ValType, builder: &mut Builder) -> Instr {
+    match (init, ty) {
+        (Some(Const::Int(value)), ValType::I64) => Instr::I64Const(*value as i64),
+        (Some(Const::Int(value)), _) => Instr::I32Const(*value as i32),
+        (Some(Const::Float(value)), ValType::F32) => Instr::F32Const(*value as f32),
+        (Some(Const::Float(value)), _) => Instr::F64Const(*value),
+        (Some(Const::Bool(value)), _) => Instr::I32Const(*value as i32),
+        (Some(Const::Str(value)), _) => Instr::I32Const(builder.string(value.as_bytes()) as i32),
+        (None, ValType::I32) => Instr::I32Const(0),
+        (None, ValType::I64) => Instr::I64Const(0),
+        (None, ValType::F32) => Instr::F32Const(0.0),
+        (None, ValType::F64) => Instr::F64Const(0.0)
+    }
+}
+
+/// The body of the routine printing an instance of `structure` the way the interpreter
+/// does, as `Point { x: 1, y: 2 }`.
+fn show(structure: &StructDef, builder: &mut Builder) -> Vec<Instr> {
+    let print_str = builder.function("beta_print_str").expect("the runtime prints strings");
+    let print_str_debug = builder.function("beta_print_str_debug").expect("the runtime prints strings");
+    let mut body = Vec::new();
+    let mut text = format!("{} {{ ", display_name(&structure.name));
+    for (idx, (name, ty)) in structure.fields.iter().enumerate() {
+        if idx > 0 {
+            text.push_str(", ");
+        }
+        text.push_str(&format!("{}: ", name));
+        body.push(Instr::I32Const(builder.string(text.as_bytes()) as i32));
+        body.push(Instr::Call(print_str));
+        body.push(Instr::LocalGet(0));
+        let load = format!("{}.load", ValType::from_ty(ty).unwrap_or(ValType::I32));
+        body.push(Instr::memory(&load, 8 * idx as u32));
+        if *ty == Ty::Str {
+            body.push(Instr::Call(print_str_debug));
+        } else {
+            body.extend(print_value(ty, builder));
+        }
+        text.clear();
+    }
+    text.push_str(" }");
+    body.push(Instr::I32Const(builder.string(text.as_bytes()) as i32));
+    body.push(Instr::Call(print_str));
+    body
+}
+
+/// Generates a WASI command module. Strings and instances live in linear memory; each
+/// source function is exported under its own name, and `_start` runs the module's
+/// `.init`, then `main`. Trait objects call through the table, where each vtable
+/// is a printer followed by the functions for the trait's slots. Output goes through `fd_write`, and runtime errors exit
+/// through `proc_exit` with the status the interpreter uses.
+pub(crate) fn generate(module: &Module) -> Result<Wasm, Vec<Diagnostic>> {
+    use ValType::*;
+
+    check(module, "wasm32")?;
+    let mut module = module.clone();
+    for function in &mut module.functions {
+        ssa::destruct(function);
+    }
+
+    let mut builder = Builder::new();
+    builder.import("wasi_snapshot_preview1", "fd_write", FuncType { params: vec![I32; 4], results: vec![I32] });
+    builder.import("wasi_snapshot_preview1", "proc_exit", FuncType { params: vec![I32], results: Vec::new() });
+    builder.global("heap".into(), I32, Instr::I32Const(0));
+    for global in &module.globals {
+        let ty = ValType::from_ty(&global.ty).unwrap_or(I32);
+        let init = global_init(global.init.as_ref(), ty, &mut builder);
+        builder.global(global_symbol(&global.name), ty, init);
+    }
+    parse::define(RUNTIME, &mut builder).expect("the runtime is well-formed");
+    // Instances can hold each other, so every printer is reserved before any is defined.
+    let shows: Vec<u32> = module.structs.iter()
+        .map(|structure| builder.reserve(show_symbol(&structure.name), FuncType { params: vec![I32], results: Vec::new() }, None))
+        .collect();
+    // A trait object is the address of a cell holding its instance's address at offset 0
+    // and the table slot of its vtable at offset 8; the vtable's first entry prints it.
+    if !module.traits.is_empty() {
+        let index = builder.reserve(SHOW_DYN.into(), FuncType { params: vec![I32], results: Vec::new() }, None);
+        let body = vec![
+            Instr::LocalGet(0),
+            Instr::memory("i32.load", 0),
+            Instr::LocalGet(0),
+            Instr::memory("i32.load", 8),
+            Instr::CallIndirect(FuncType { params: vec![I32], results: Vec::new() })
+        ];
+        builder.define(index, Vec::new(), body);
+    }
+    for (structure, index) in module.structs.iter().zip(shows) {
+        let body = show(structure, &mut builder);
+        builder.define(index, Vec::new(), body);
+    }
+
+    let mut indices = Vec::new();
+    for function in &module.functions {
+        let ty = FuncType {
+            params: function.params.iter().filter_map(|(_, ty)| ValType::from_ty(ty)).collect(),
+            results: ValType::from_ty(&function.ret).into_iter().collect()
+        };
+        let export = (function.name != Module::INIT && function.name != "_start" && function.name != "memory")
+            .then(|| function.name.clone());
+        indices.push(builder.reserve(function_symbol(&function.name), ty, export));
+    }
+    for vtable in &module.vtables {
+        let base = builder.wasm.table.len() as u32;
+        builder.vtables.insert((vtable.obj.clone(), vtable.with.clone()), base);
+        let entries: Vec<u32> = std::iter::once(show_symbol(&vtable.obj))
+            .chain(vtable.functions.iter().map(|function| function_symbol(function)))
+            .map(|name| builder.function(&name).expect("vtables name defined functions"))
+            .collect();
+        builder.wasm.table.extend(entries);
+    }
+    for (function, index) in module.functions.iter().zip(indices) {
+        let (locals, body) = Selector::new(&module, function, &mut builder).function();
+        builder.define(index, locals, body);
+    }
+
+    let start = builder.reserve("_start".into(), FuncType::default(), Some("_start".into()));
+    let mut body = Vec::new();
+    for entry in [Module::INIT, "main"] {
+        if let Some(index) = builder.function(&function_symbol(entry)) {
+            body.push(Instr::Call(index));
+            if !builder.function_type(index).results.is_empty() {
+                body.push(Instr::simple("drop"));
+            }
+        }
+    }
+    builder.define(start, Vec::new(), body);
+    Ok(builder.finish())
+}
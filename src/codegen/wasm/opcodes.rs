@@ -0,0 +1,244 @@
+use super::ValType::{self, F32, F64, I32, I64};
+
+/// An instruction without immediates. `unreachable`, `drop` and `select` don't have a
+/// fixed signature, so the validator checks them by opcode and their lists stay empty.
+pub(super) struct Simple {
+    pub name: &'static str,
+    pub opcode: u8,
+    pub params: &'static [ValType],
+    pub results: &'static [ValType]
+}
+
+pub(super) const UNREACHABLE: u8 = 0x00;
+pub(super) const DROP: u8 = 0x1a;
+pub(super) const SELECT: u8 = 0x1b;
+
+const fn op(name: &'static str, opcode: u8, params: &'static [ValType], results: &'static [ValType]) -> Simple {
+    Simple { name, opcode, params, results }
+}
+
+pub(super) const SIMPLE: &[Simple] = &[
+    op("unreachable", UNREACHABLE, &[], &[]),
+    op("nop", 0x01, &[], &[]),
+    op("drop", DROP, &[], &[]),
+    op("select", SELECT, &[], &[]),
+
+    op("i32.eqz", 0x45, &[I32], &[I32]),
+    op("i32.eq", 0x46, &[I32, I32], &[I32]),
+    op("i32.ne", 0x47, &[I32, I32], &[I32]),
+    op("i32.lt_s", 0x48, &[I32, I32], &[I32]),
+    op("i32.lt_u", 0x49, &[I32, I32], &[I32]),
+    op("i32.gt_s", 0x4a, &[I32, I32], &[I32]),
+    op("i32.gt_u", 0x4b, &[I32, I32], &[I32]),
+    op("i32.le_s", 0x4c, &[I32, I32], &[I32]),
+    op("i32.le_u", 0x4d, &[I32, I32], &[I32]),
+    op("i32.ge_s", 0x4e, &[I32, I32], &[I32]),
+    op("i32.ge_u", 0x4f, &[I32, I32], &[I32]),
+
+    op("i64.eqz", 0x50, &[I64], &[I32]),
+    op("i64.eq", 0x51, &[I64, I64], &[I32]),
+    op("i64.ne", 0x52, &[I64, I64], &[I32]),
+    op("i64.lt_s", 0x53, &[I64, I64], &[I32]),
+    op("i64.lt_u", 0x54, &[I64, I64], &[I32]),
+    op("i64.gt_s", 0x55, &[I64, I64], &[I32]),
+    op("i64.gt_u", 0x56, &[I64, I64], &[I32]),
+    op("i64.le_s", 0x57, &[I64, I64], &[I32]),
+    op("i64.le_u", 0x58, &[I64, I64], &[I32]),
+    op("i64.ge_s", 0x59, &[I64, I64], &[I32]),
+    op("i64.ge_u", 0x5a, &[I64, I64], &[I32]),
+
+    op("f32.eq", 0x5b, &[F32, F32], &[I32]),
+    op("f32.ne", 0x5c, &[F32, F32], &[I32]),
+    op("f32.lt", 0x5d, &[F32, F32], &[I32]),
+    op("f32.gt", 0x5e, &[F32, F32], &[I32]),
+    op("f32.le", 0x5f, &[F32, F32], &[I32]),
+    op("f32.ge", 0x60, &[F32, F32], &[I32]),
+
+    op("f64.eq", 0x61, &[F64, F64], &[I32]),
+    op("f64.ne", 0x62, &[F64, F64], &[I32]),
+    op("f64.lt", 0x63, &[F64, F64], &[I32]),
+    op("f64.gt", 0x64, &[F64, F64], &[I32]),
+    op("f64.le", 0x65, &[F64, F64], &[I32]),
+    op("f64.ge", 0x66, &[F64, F64], &[I32]),
+
+    op("i32.clz", 0x67, &[I32], &[I32]),
+    op("i32.ctz", 0x68, &[I32], &[I32]),
+    op("i32.popcnt", 0x69, &[I32], &[I32]),
+    op("i32.add", 0x6a, &[I32, I32], &[I32]),
+    op("i32.sub", 0x6b, &[I32, I32], &[I32]),
+    op("i32.mul", 0x6c, &[I32, I32], &[I32]),
+    op("i32.div_s", 0x6d, &[I32, I32], &[I32]),
+    op("i32.div_u", 0x6e, &[I32, I32], &[I32]),
+    op("i32.rem_s", 0x6f, &[I32, I32], &[I32]),
+    op("i32.rem_u", 0x70, &[I32, I32], &[I32]),
+    op("i32.and", 0x71, &[I32, I32], &[I32]),
+    op("i32.or", 0x72, &[I32, I32], &[I32]),
+    op("i32.xor", 0x73, &[I32, I32], &[I32]),
+    op("i32.shl", 0x74, &[I32, I32], &[I32]),
+    op("i32.shr_s", 0x75, &[I32, I32], &[I32]),
+    op("i32.shr_u", 0x76, &[I32, I32], &[I32]),
+    op("i32.rotl", 0x77, &[I32, I32], &[I32]),
+    op("i32.rotr", 0x78, &[I32, I32], &[I32]),
+
+    op("i64.clz", 0x79, &[I64], &[I64]),
+    op("i64.ctz", 0x7a, &[I64], &[I64]),
+    op("i64.popcnt", 0x7b, &[I64], &[I64]),
+    op("i64.add", 0x7c, &[I64, I64], &[I64]),
+    op("i64.sub", 0x7d, &[I64, I64], &[I64]),
+    op("i64.mul", 0x7e, &[I64, I64], &[I64]),
+    op("i64.div_s", 0x7f, &[I64, I64], &[I64]),
+    op("i64.div_u", 0x80, &[I64, I64], &[I64]),
+    op("i64.rem_s", 0x81, &[I64, I64], &[I64]),
+    op("i64.rem_u", 0x82, &[I64, I64], &[I64]),
+    op("i64.and", 0x83, &[I64, I64], &[I64]),
+    op("i64.or", 0x84, &[I64, I64], &[I64]),
+    op("i64.xor", 0x85, &[I64, I64], &[I64]),
+    op("i64.shl", 0x86, &[I64, I64], &[I64]),
+    op("i64.shr_s", 0x87, &[I64, I64], &[I64]),
+    op("i64.shr_u", 0x88, &[I64, I64], &[I64]),
+    op("i64.rotl", 0x89, &[I64, I64], &[I64]),
+    op("i64.rotr", 0x8a, &[I64, I64], &[I64]),
+
+    op("f32.abs", 0x8b, &[F32], &[F32]),
+    op("f32.neg", 0x8c, &[F32], &[F32]),
+    op("f32.ceil", 0x8d, &[F32], &[F32]),
+    op("f32.floor", 0x8e, &[F32], &[F32]),
+    op("f32.trunc", 0x8f, &[F32], &[F32]),
+    op("f32.nearest", 0x90, &[F32], &[F32]),
+    op("f32.sqrt", 0x91, &[F32], &[F32]),
+    op("f32.add", 0x92, &[F32, F32], &[F32]),
+    op("f32.sub", 0x93, &[F32, F32], &[F32]),
+    op("f32.mul", 0x94, &[F32, F32], &[F32]),
+    op("f32.div", 0x95, &[F32, F32], &[F32]),
+    op("f32.min", 0x96, &[F32, F32], &[F32]),
+    op("f32.max", 0x97, &[F32, F32], &[F32]),
+    op("f32.copysign", 0x98, &[F32, F32], &[F32]),
+
+    op("f64.abs", 0x99, &[F64], &[F64]),
+    op("f64.neg", 0x9a, &[F64], &[F64]),
+    op("f64.ceil", 0x9b, &[F64], &[F64]),
+    op("f64.floor", 0x9c, &[F64], &[F64]),
+    op("f64.trunc", 0x9d, &[F64], &[F64]),
+    op("f64.nearest", 0x9e, &[F64], &[F64]),
+    op("f64.sqrt", 0x9f, &[F64], &[F64]),
+    op("f64.add", 0xa0, &[F64, F64], &[F64]),
+    op("f64.sub", 0xa1, &[F64, F64], &[F64]),
+    op("f64.mul", 0xa2, &[F64, F64], &[F64]),
+    op("f64.div", 0xa3, &[F64, F64], &[F64]),
+    op("f64.min", 0xa4, &[F64, F64], &[F64]),
+    op("f64.max", 0xa5, &[F64, F64], &[F64]),
+    op("f64.copysign", 0xa6, &[F64, F64], &[F64]),
+
+    op("i32.wrap_i64", 0xa7, &[I64], &[I32]),
+    op("i32.trunc_f32_s", 0xa8, &[F32], &[I32]),
+    op("i32.trunc_f32_u", 0xa9, &[F32], &[I32]),
+    op("i32.trunc_f64_s", 0xaa, &[F64], &[I32]),
+    op("i32.trunc_f64_u", 0xab, &[F64], &[I32]),
+    op("i64.extend_i32_s", 0xac, &[I32], &[I64]),
+    op("i64.extend_i32_u", 0xad, &[I32], &[I64]),
+    op("i64.trunc_f32_s", 0xae, &[F32], &[I64]),
+    op("i64.trunc_f32_u", 0xaf, &[F32], &[I64]),
+    op("i64.trunc_f64_s", 0xb0, &[F64], &[I64]),
+    op("i64.trunc_f64_u", 0xb1, &[F64], &[I64]),
+    op("f32.convert_i32_s", 0xb2, &[I32], &[F32]),
+    op("f32.convert_i32_u", 0xb3, &[I32], &[F32]),
+    op("f32.convert_i64_s", 0xb4, &[I64], &[F32]),
+    op("f32.convert_i64_u", 0xb5, &[I64], &[F32]),
+    op("f32.demote_f64", 0xb6, &[F64], &[F32]),
+    op("f64.convert_i32_s", 0xb7, &[I32], &[F64]),
+    op("f64.convert_i32_u", 0xb8, &[I32], &[F64]),
+    op("f64.convert_i64_s", 0xb9, &[I64], &[F64]),
+    op("f64.convert_i64_u", 0xba, &[I64], &[F64]),
+    op("f64.promote_f32", 0xbb, &[F32], &[F64]),
+    op("i32.reinterpret_f32", 0xbc, &[F32], &[I32]),
+    op("i64.reinterpret_f64", 0xbd, &[F64], &[I64]),
+    op("f32.reinterpret_i32", 0xbe, &[I32], &[F32]),
+    op("f64.reinterpret_i64", 0xbf, &[I64], &[F64]),
+    op("i32.extend8_s", 0xc0, &[I32], &[I32]),
+    op("i32.extend16_s", 0xc1, &[I32], &[I32]),
+    op("i64.extend8_s", 0xc2, &[I64], &[I64]),
+    op("i64.extend16_s", 0xc3, &[I64], &[I64]),
+    op("i64.extend32_s", 0xc4, &[I64], &[I64])
+];
+
+pub(super) fn simple(opcode: u8) -> Option<&'static Simple> {
+    SIMPLE.iter().find(|simple| simple.opcode == opcode)
+}
+
+pub(super) fn simple_named(name: &str) -> Option<&'static Simple> {
+    SIMPLE.iter().find(|simple| simple.name == name)
+}
+
+/// A load or store; `align` is the natural alignment as a power of two.
+pub(super) struct Memory {
+    pub name: &'static str,
+    pub opcode: u8,
+    pub align: u32,
+    pub ty: ValType,
+    pub store: bool
+}
+
+const fn mem(name: &'static str, opcode: u8, align: u32, ty: ValType, store: bool) -> Memory {
+    Memory { name, opcode, align, ty, store }
+}
+
+pub(super) const MEMORY: &[Memory] = &[
+    mem("i32.load", 0x28, 2, I32, false),
+    mem("i64.load", 0x29, 3, I64, false),
+    mem("f32.load", 0x2a, 2, F32, false),
+    mem("f64.load", 0x2b, 3, F64, false),
+    mem("i32.load8_s", 0x2c, 0, I32, false),
+    mem("i32.load8_u", 0x2d, 0, I32, false),
+    mem("i32.load16_s", 0x2e, 1, I32, false),
+    mem("i32.load16_u", 0x2f, 1, I32, false),
+    mem("i64.load8_s", 0x30, 0, I64, false),
+    mem("i64.load8_u", 0x31, 0, I64, false),
+    mem("i64.load16_s", 0x32, 1, I64, false),
+    mem("i64.load16_u", 0x33, 1, I64, false),
+    mem("i64.load32_s", 0x34, 2, I64, false),
+    mem("i64.load32_u", 0x35, 2, I64, false),
+    mem("i32.store", 0x36, 2, I32, true),
+    mem("i64.store", 0x37, 3, I64, true),
+    mem("f32.store", 0x38, 2, F32, true),
+    mem("f64.store", 0x39, 3, F64, true),
+    mem("i32.store8", 0x3a, 0, I32, true),
+    mem("i32.store16", 0x3b, 1, I32, true),
+    mem("i64.store8", 0x3c, 0, I64, true),
+    mem("i64.store16", 0x3d, 1, I64, true),
+    mem("i64.store32", 0x3e, 2, I64, true)
+];
+
+pub(super) fn memory(opcode: u8) -> Option<&'static Memory> {
+    MEMORY.iter().find(|memory| memory.opcode == opcode)
+}
+
+pub(super) fn memory_named(name: &str) -> Option<&'static Memory> {
+    MEMORY.iter().find(|memory| memory.name == name)
+}
+
+// Opcodes of the instructions with immediates.
+pub(super) const BLOCK: u8 = 0x02;
+pub(super) const LOOP: u8 = 0x03;
+pub(super) const IF: u8 = 0x04;
+pub(super) const ELSE: u8 = 0x05;
+pub(super) const END: u8 = 0x0b;
+pub(super) const BR: u8 = 0x0c;
+pub(super) const BR_IF: u8 = 0x0d;
+pub(super) const BR_TABLE: u8 = 0x0e;
+pub(super) const RETURN: u8 = 0x0f;
+pub(super) const CALL: u8 = 0x10;
+pub(super) const CALL_INDIRECT: u8 = 0x11;
+pub(super) const LOCAL_GET: u8 = 0x20;
+pub(super) const LOCAL_SET: u8 = 0x21;
+pub(super) const LOCAL_TEE: u8 = 0x22;
+pub(super) const GLOBAL_GET: u8 = 0x23;
+pub(super) const GLOBAL_SET: u8 = 0x24;
+pub(super) const MEMORY_SIZE: u8 = 0x3f;
+pub(super) const MEMORY_GROW: u8 = 0x40;
+pub(super) const I32_CONST: u8 = 0x41;
+pub(super) const I64_CONST: u8 = 0x42;
+pub(super) const F32_CONST: u8 = 0x43;
+pub(super) const F64_CONST: u8 = 0x44;
+
+/// The empty block type.
+pub(super) const EMPTY: u8 = 0x40;
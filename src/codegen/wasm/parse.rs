@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use super::{opcodes, Builder, FuncType, Instr, ValType};
+
+/// Reads functions written in flat WebAssembly text, as the runtime and the generated
+/// helpers are:
+///
+/// ```text
+/// (func $name (param $a i32) (result i32) (local $b i64)
+///   local.get $a
+///   ...
+/// )
+/// ```
+///
+/// Calls, globals, locals and branch labels may be named. As an extension,
+/// `i32.const "text"` stands for the address of the string in memory. A function
+/// reserved under the same name beforehand gets its body here.
+pub(super) fn define(src: &str, builder: &mut Builder) -> Result<(), String> {
+    let tokens = tokenize(src)?;
+    let mut headers = Vec::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        let header = Header::parse(&tokens, &mut pos)?;
+        let index = match builder.function(&header.name) {
+            Some(index) if *builder.function_type(index) == header.ty => index,
+            Some(_) => return Err(format!("`${}` is reserved with a different type", header.name)),
+            None => builder.reserve(header.name.clone(), header.ty.clone(), None)
+        };
+        // Skip the body for now, so functions can call ones defined after them.
+        let start = pos;
+        let mut depth = 0;
+        loop {
+            match tokens.get(pos) {
+                Some(Token::Open) => depth += 1,
+                Some(Token::Close) if depth == 0 => break,
+                Some(Token::Close) => depth -= 1,
+                Some(_) => {},
+                None => return Err(format!("`${}` is missing its closing `)`", header.name))
+            }
+            pos += 1;
+        }
+        headers.push((header, index, start, pos));
+        pos += 1;
+    }
+
+    for (header, index, start, end) in headers {
+        let body = Body::new(&header, &tokens[start..end], builder).parse()?;
+        builder.define(index, header.locals.iter().map(|(_, ty)| *ty).collect(), body);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Atom(String),
+    Str(Vec<u8>)
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'(' => tokens.push(Token::Open),
+            b')' => tokens.push(Token::Close),
+            b';' if bytes.get(pos + 1) == Some(&b';') => {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            },
+            byte if byte.is_ascii_whitespace() => {},
+            b'"' => {
+                let mut value = Vec::new();
+                pos += 1;
+                loop {
+                    match bytes.get(pos) {
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            let escape = bytes.get(pos + 1).ok_or("unterminated string")?;
+                            match escape {
+                                b'n' => value.push(b'\n'),
+                                b't' => value.push(b'\t'),
+                                b'"' | b'\\' | b'\'' => value.push(*escape),
+                                _ => {
+                                    let hex = src.get(pos + 1..pos + 3).ok_or("unterminated string")?;
+                                    value.push(u8::from_str_radix(hex, 16).map_err(|_| format!("bad escape `\\{}`", hex))?);
+                                    pos += 1;
+                                }
+                            }
+                            pos += 1;
+                        },
+                        Some(byte) => value.push(*byte),
+                        None => return Err("unterminated string".into())
+                    }
+                    pos += 1;
+                }
+                tokens.push(Token::Str(value));
+            },
+            _ => {
+                let start = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && !matches!(bytes[pos], b'(' | b')' | b'"' | b';') {
+                    pos += 1;
+                }
+                tokens.push(Token::Atom(src[start..pos].to_string()));
+                continue;
+            }
+        }
+        pos += 1;
+    }
+    Ok(tokens)
+}
+
+fn atom<'t>(tokens: &'t [Token], pos: &mut usize) -> Result<&'t str, String> {
+    match tokens.get(*pos) {
+        Some(Token::Atom(atom)) => {
+            *pos += 1;
+            Ok(atom)
+        },
+        other => Err(format!("expected a name or number, found {:?}", other))
+    }
+}
+
+fn expect(tokens: &[Token], pos: &mut usize, token: Token) -> Result<(), String> {
+    if tokens.get(*pos) == Some(&token) {
+        *pos += 1;
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, found {:?}", token, tokens.get(*pos)))
+    }
+}
+
+fn id(text: &str) -> Result<&str, String> {
+    text.strip_prefix('$').ok_or_else(|| format!("expected a `$name`, found `{}`", text))
+}
+
+fn val_type(text: &str) -> Result<ValType, String> {
+    ValType::from_name(text).ok_or_else(|| format!("unknown type `{}`", text))
+}
+
+struct Header {
+    name: String,
+    ty: FuncType,
+    params: Vec<String>,
+    locals: Vec<(String, ValType)>
+}
+
+impl Header {
+    fn parse(tokens: &[Token], pos: &mut usize) -> Result<Self, String> {
+        expect(tokens, pos, Token::Open)?;
+        if atom(tokens, pos)? != "func" {
+            return Err("expected `func`".into());
+        }
+        let name = id(atom(tokens, pos)?)?.to_string();
+        let mut header = Header { name, ty: FuncType::default(), params: Vec::new(), locals: Vec::new() };
+        while tokens.get(*pos) == Some(&Token::Open) {
+            let mut peek = *pos + 1;
+            let kind = atom(tokens, &mut peek)?;
+            if !matches!(kind, "param" | "result" | "local") {
+                break;
+            }
+            *pos = peek;
+            match kind {
+                "result" => header.ty.results.push(val_type(atom(tokens, pos)?)?),
+                _ => {
+                    let name = id(atom(tokens, pos)?)?.to_string();
+                    let ty = val_type(atom(tokens, pos)?)?;
+                    if kind == "param" {
+                        header.params.push(name);
+                        header.ty.params.push(ty);
+                    } else {
+                        header.locals.push((name, ty));
+                    }
+                }
+            }
+            expect(tokens, pos, Token::Close)?;
+        }
+        Ok(header)
+    }
+}
+
+struct Body<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    builder: &'a mut Builder,
+    locals: HashMap<String, u32>,
+    /// Labels of the enclosing blocks, innermost last.
+    labels: Vec<Option<String>>
+}
+
+impl<'a> Body<'a> {
+
+    fn new(header: &Header, tokens: &'a [Token], builder: &'a mut Builder) -> Self {
+        let locals = header.params.iter().chain(header.locals.iter().map(|(name, _)| name))
+            .enumerate()
+            .map(|(idx, name)| (name.clone(), idx as u32))
+            .collect();
+        Self { tokens, pos: 0, builder, locals, labels: Vec::new() }
+    }
+
+    fn atom(&mut self) -> Result<&'a str, String> {
+        atom(self.tokens, &mut self.pos)
+    }
+
+    fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+        text.replace('_', "").parse().map_err(|_| format!("bad number `{}`", text))
+    }
+
+    fn local(&mut self) -> Result<u32, String> {
+        let text = self.atom()?;
+        match text.strip_prefix('$') {
+            Some(name) => self.locals.get(name).copied().ok_or_else(|| format!("unknown local `{}`", text)),
+            None => Self::number(text)
+        }
+    }
+
+    fn label(&mut self) -> Result<u32, String> {
+        let text = self.atom()?;
+        match text.strip_prefix('$') {
+            Some(name) => self.labels.iter().rev()
+                .position(|label| label.as_deref() == Some(name))
+                .map(|depth| depth as u32)
+                .ok_or_else(|| format!("unknown label `{}`", text)),
+            None => Self::number(text)
+        }
+    }
+
+    /// An optional label and result type after `block`, `loop` or `if`.
+    fn block_type(&mut self) -> Result<Option<ValType>, String> {
+        let label = match self.tokens.get(self.pos) {
+            Some(Token::Atom(text)) if text.starts_with('$') => {
+                self.pos += 1;
+                Some(text[1..].to_string())
+            },
+            _ => None
+        };
+        self.labels.push(label);
+        if self.tokens.get(self.pos) != Some(&Token::Open) {
+            return Ok(None);
+        }
+        self.pos += 1;
+        if self.atom()? != "result" {
+            return Err("expected `result`".into());
+        }
+        let ty = val_type(self.atom()?)?;
+        expect(self.tokens, &mut self.pos, Token::Close)?;
+        Ok(Some(ty))
+    }
+
+    fn parse(mut self) -> Result<Vec<Instr>, String> {
+        let mut body = Vec::new();
+        while self.pos < self.tokens.len() {
+            let name = self.atom()?;
+            let instr = match name {
+                "block" => Instr::Block(self.block_type()?),
+                "loop" => Instr::Loop(self.block_type()?),
+                "if" => Instr::If(self.block_type()?),
+                "else" => Instr::Else,
+                "end" => {
+                    self.labels.pop().ok_or("`end` without a block")?;
+                    Instr::End
+                },
+                "br" => Instr::Br(self.label()?),
+                "br_if" => Instr::BrIf(self.label()?),
+                "return" => Instr::Return,
+                "call" => {
+                    let text = self.atom()?;
+                    Instr::Call(self.builder.function(id(text)?).ok_or_else(|| format!("unknown function `{}`", text))?)
+                },
+                "local.get" => Instr::LocalGet(self.local()?),
+                "local.set" => Instr::LocalSet(self.local()?),
+                "local.tee" => Instr::LocalTee(self.local()?),
+                "global.get" | "global.set" => {
+                    let text = self.atom()?;
+                    let index = self.builder.global_index(id(text)?).ok_or_else(|| format!("unknown global `{}`", text))?;
+                    if name == "global.get" { Instr::GlobalGet(index) } else { Instr::GlobalSet(index) }
+                },
+                "memory.size" => Instr::MemorySize,
+                "memory.grow" => Instr::MemoryGrow,
+                "i32.const" => match self.tokens.get(self.pos) {
+                    Some(Token::Str(value)) => {
+                        self.pos += 1;
+                        Instr::I32Const(self.builder.string(value) as i32)
+                    },
+                    _ => Instr::I32Const(Self::number::<i64>(self.atom()?)? as i32)
+                },
+                "i64.const" => {
+                    let text = self.atom()?;
+                    // The most negative value only parses as an unsigned magnitude.
+                    match text.strip_prefix('-') {
+                        Some(magnitude) => Instr::I64Const(Self::number::<u64>(magnitude)?.wrapping_neg() as i64),
+                        None => Instr::I64Const(Self::number::<u64>(text)? as i64)
+                    }
+                },
+                "f32.const" => Instr::F32Const(Self::number(self.atom()?)?),
+                "f64.const" => Instr::F64Const(Self::number(self.atom()?)?),
+                _ => {
+                    if let Some(simple) = opcodes::simple_named(name) {
+                        Instr::Simple(simple.opcode)
+                    } else if let Some(memory) = opcodes::memory_named(name) {
+                        let mut offset = 0;
+                        if let Some(Token::Atom(text)) = self.tokens.get(self.pos) {
+                            if let Some(value) = text.strip_prefix("offset=") {
+                                offset = Self::number(value)?;
+                                self.pos += 1;
+                            }
+                        }
+                        Instr::Memory { opcode: memory.opcode, offset }
+                    } else {
+                        return Err(format!("unknown instruction `{}`", name));
+                    }
+                }
+            };
+            body.push(instr);
+        }
+        if !self.labels.is_empty() {
+            return Err("a block is missing its `end`".into());
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,724 @@
+;; Runtime support for programs built by the wasm32 backend, in the flat text form the
+;; backend reads. Output goes through WASI's `fd_write`. The first 64 bytes of memory are
+;; scratch: an iovec at 16, the count written at 24, a character at 28 and the digits of
+;; a number at 32..64.
+
+;; beta_write(fd, ptr, len) writes `len` bytes at `ptr`.
+(func $beta_write (param $fd i32) (param $ptr i32) (param $len i32)
+  i32.const 16
+  local.get $ptr
+  i32.store
+  i32.const 20
+  local.get $len
+  i32.store
+  local.get $fd
+  i32.const 16
+  i32.const 1
+  i32.const 24
+  call $fd_write
+  drop
+)
+
+(func $beta_strlen (param $s i32) (result i32) (local $len i32)
+  block $done
+    loop $next
+      local.get $s
+      local.get $len
+      i32.add
+      i32.load8_u
+      i32.eqz
+      br_if $done
+      local.get $len
+      i32.const 1
+      i32.add
+      local.set $len
+      br $next
+    end
+  end
+  local.get $len
+)
+
+;; beta_write_str(fd, s) writes the whole NUL-terminated string.
+(func $beta_write_str (param $fd i32) (param $s i32)
+  local.get $fd
+  local.get $s
+  local.get $s
+  call $beta_strlen
+  call $beta_write
+)
+
+(func $beta_print_str (param $s i32)
+  i32.const 1
+  local.get $s
+  call $beta_write_str
+)
+
+(func $beta_print_char (param $c i32)
+  i32.const 28
+  local.get $c
+  i32.store8
+  i32.const 1
+  i32.const 28
+  i32.const 1
+  call $beta_write
+)
+
+(func $beta_print_uint (param $value i64) (local $at i32)
+  i32.const 64
+  local.set $at
+  loop $digit
+    local.get $at
+    i32.const 1
+    i32.sub
+    local.tee $at
+    local.get $value
+    i64.const 10
+    i64.rem_u
+    i32.wrap_i64
+    i32.const 48
+    i32.add
+    i32.store8
+    local.get $value
+    i64.const 10
+    i64.div_u
+    local.tee $value
+    i64.const 0
+    i64.ne
+    br_if $digit
+  end
+  i32.const 1
+  local.get $at
+  i32.const 64
+  local.get $at
+  i32.sub
+  call $beta_write
+)
+
+(func $beta_print_int (param $value i64)
+  local.get $value
+  i64.const 0
+  i64.lt_s
+  if
+    i32.const 45
+    call $beta_print_char
+    ;; The most negative value negates to itself, which is right when read unsigned.
+    i64.const 0
+    local.get $value
+    i64.sub
+    local.set $value
+  end
+  local.get $value
+  call $beta_print_uint
+)
+
+(func $beta_print_bool (param $value i32)
+  i32.const "true"
+  i32.const "false"
+  local.get $value
+  select
+  call $beta_print_str
+)
+
+(func $beta_print_zeros (param $count i64)
+  block $done
+    loop $next
+      local.get $count
+      i64.const 0
+      i64.le_s
+      br_if $done
+      i32.const 48
+      call $beta_print_char
+      local.get $count
+      i64.const 1
+      i64.sub
+      local.set $count
+      br $next
+    end
+  end
+)
+
+;; beta_print_str_debug(s) prints a string quoted, as it appears inside an instance.
+(func $beta_print_str_debug (param $s i32) (local $c i32) (local $escape i32)
+  i32.const 34
+  call $beta_print_char
+  block $done
+    loop $next
+      local.get $s
+      i32.load8_u
+      local.tee $c
+      i32.eqz
+      br_if $done
+      local.get $s
+      i32.const 1
+      i32.add
+      local.set $s
+      i32.const 0
+      local.set $escape
+      local.get $c
+      i32.const 34
+      i32.eq
+      local.get $c
+      i32.const 92
+      i32.eq
+      i32.or
+      if
+        local.get $c
+        local.set $escape
+      end
+      local.get $c
+      i32.const 10
+      i32.eq
+      if
+        i32.const 110
+        local.set $escape
+      end
+      local.get $c
+      i32.const 9
+      i32.eq
+      if
+        i32.const 116
+        local.set $escape
+      end
+      local.get $c
+      i32.const 13
+      i32.eq
+      if
+        i32.const 114
+        local.set $escape
+      end
+      local.get $escape
+      if
+        i32.const 92
+        call $beta_print_char
+        local.get $escape
+        local.set $c
+      end
+      local.get $c
+      call $beta_print_char
+      br $next
+    end
+  end
+  i32.const 34
+  call $beta_print_char
+)
+
+(func $beta_pow10 (param $n i64) (result f64) (local $p f64)
+  f64.const 1
+  local.set $p
+  block $done
+    loop $next
+      local.get $n
+      i64.const 0
+      i64.le_s
+      br_if $done
+      local.get $p
+      f64.const 10
+      f64.mul
+      local.set $p
+      local.get $n
+      i64.const 1
+      i64.sub
+      local.set $n
+      br $next
+    end
+  end
+  local.get $p
+)
+
+;; beta_print_float(value, single) prints the fewest significant digits that read back
+;; as the same value, without an exponent, like the interpreter. Reading back is checked
+;; with double arithmetic, which can round differently from an exact conversion, so now
+;; and then a digit more than necessary is printed. Values of 1e15 and up are scaled down
+;; first, and only their leading digits are exact.
+(func $beta_print_float (param $value f64) (param $single i32)
+  (local $try i64) (local $max i64) (local $scaled i64) (local $int i64) (local $frac f64)
+  (local $fd i64) (local $lz i64) (local $fi i64) (local $t i64) (local $back f64)
+  local.get $value
+  local.get $value
+  f64.ne
+  if
+    i32.const "NaN"
+    call $beta_print_str
+    return
+  end
+  local.get $value
+  i64.reinterpret_f64
+  i64.const 0
+  i64.lt_s
+  if
+    i32.const 45
+    call $beta_print_char
+    local.get $value
+    f64.neg
+    local.set $value
+  end
+  local.get $value
+  f64.const inf
+  f64.eq
+  if
+    i32.const "inf"
+    call $beta_print_str
+    return
+  end
+  ;; Scale values too large for an integer, counting the zeros that takes off.
+  block $small
+    loop $scale
+      local.get $value
+      f64.const 1e15
+      f64.lt
+      br_if $small
+      local.get $value
+      f64.const 10
+      f64.div
+      local.set $value
+      local.get $scaled
+      i64.const 1
+      i64.add
+      local.set $scaled
+      br $scale
+    end
+  end
+  i64.const 9
+  i64.const 17
+  local.get $single
+  select
+  local.set $max
+  i64.const 1
+  local.set $try
+  block $print
+    loop $attempt
+      local.get $value
+      i64.trunc_f64_u
+      local.set $int
+      local.get $value
+      local.get $int
+      f64.convert_i64_u
+      f64.sub
+      local.set $frac
+      ;; $fd: digits left for the fraction once the integer part has used its share.
+      local.get $try
+      local.set $fd
+      local.get $int
+      local.set $t
+      block $counted
+        loop $count
+          local.get $t
+          i64.eqz
+          br_if $counted
+          local.get $t
+          i64.const 10
+          i64.div_u
+          local.set $t
+          local.get $fd
+          i64.const 1
+          i64.sub
+          local.set $fd
+          br $count
+        end
+      end
+      ;; $lz: zeros after the point, which don't count as significant.
+      i64.const 0
+      local.set $lz
+      local.get $int
+      i64.eqz
+      local.get $frac
+      f64.const 0
+      f64.ne
+      i32.and
+      if
+        block $leading
+          loop $zero
+            local.get $frac
+            f64.const 0.1
+            f64.ge
+            br_if $leading
+            local.get $frac
+            f64.const 10
+            f64.mul
+            local.set $frac
+            local.get $lz
+            i64.const 1
+            i64.add
+            local.set $lz
+            br $zero
+          end
+        end
+      end
+      local.get $scaled
+      i64.eqz
+      local.get $fd
+      i64.const 0
+      i64.gt_s
+      i32.and
+      i32.eqz
+      if
+        i64.const 0
+        local.set $fd
+      end
+      ;; $fi: the fraction as an integer of $fd digits, rounded.
+      local.get $frac
+      local.get $fd
+      call $beta_pow10
+      f64.mul
+      f64.nearest
+      i64.trunc_f64_u
+      local.tee $fi
+      local.get $fd
+      call $beta_pow10
+      i64.trunc_f64_u
+      i64.ge_u
+      if
+        ;; Rounding carried out of the fraction.
+        local.get $lz
+        i64.eqz
+        if
+          local.get $int
+          i64.const 1
+          i64.add
+          local.set $int
+          i64.const 0
+          local.set $fi
+        else
+          local.get $lz
+          i64.const 1
+          i64.sub
+          local.set $lz
+          local.get $fi
+          i64.const 10
+          i64.div_u
+          local.set $fi
+        end
+      end
+      ;; Print these digits if they read back as the value, or if there is no more to try.
+      local.get $scaled
+      i64.const 0
+      i64.ne
+      br_if $print
+      local.get $try
+      local.get $max
+      i64.ge_u
+      br_if $print
+      local.get $fi
+      f64.convert_i64_u
+      local.get $fd
+      local.get $lz
+      i64.add
+      call $beta_pow10
+      f64.div
+      local.get $int
+      f64.convert_i64_u
+      f64.add
+      local.set $back
+      local.get $single
+      if (result i32)
+        local.get $back
+        f32.demote_f64
+        local.get $value
+        f32.demote_f64
+        f32.eq
+      else
+        local.get $back
+        local.get $value
+        f64.eq
+      end
+      br_if $print
+      local.get $try
+      i64.const 1
+      i64.add
+      local.set $try
+      br $attempt
+    end
+  end
+  local.get $int
+  call $beta_print_uint
+  local.get $scaled
+  call $beta_print_zeros
+  local.get $fi
+  i64.eqz
+  if
+    return
+  end
+  block $trimmed
+    loop $trim
+      local.get $fi
+      i64.const 10
+      i64.rem_u
+      i64.const 0
+      i64.ne
+      br_if $trimmed
+      local.get $fi
+      i64.const 10
+      i64.div_u
+      local.set $fi
+      local.get $fd
+      i64.const 1
+      i64.sub
+      local.set $fd
+      br $trim
+    end
+  end
+  i32.const 46
+  call $beta_print_char
+  local.get $lz
+  call $beta_print_zeros
+  ;; Pad the fraction to $fd digits.
+  local.get $fi
+  local.set $t
+  loop $pad
+    local.get $fd
+    i64.const 1
+    i64.sub
+    local.set $fd
+    local.get $t
+    i64.const 10
+    i64.div_u
+    local.tee $t
+    i64.const 0
+    i64.ne
+    br_if $pad
+  end
+  local.get $fd
+  call $beta_print_zeros
+  local.get $fi
+  call $beta_print_uint
+)
+
+;; beta_fmod(a, b), the remainder truncated toward zero like C's fmod. Taking away the
+;; divisor scaled by powers of two is exact, so the result is too.
+(func $beta_fmod (param $a f64) (param $b f64) (result f64) (local $x f64) (local $t f64)
+  local.get $a
+  f64.abs
+  local.set $x
+  local.get $b
+  f64.abs
+  local.set $b
+  ;; A NaN operand, an infinite dividend or a zero divisor gives NaN.
+  local.get $x
+  local.get $x
+  f64.ne
+  local.get $b
+  local.get $b
+  f64.ne
+  i32.or
+  local.get $x
+  f64.const inf
+  f64.eq
+  i32.or
+  local.get $b
+  f64.const 0
+  f64.eq
+  i32.or
+  if
+    f64.const nan
+    return
+  end
+  local.get $x
+  local.get $b
+  f64.lt
+  if
+    local.get $a
+    return
+  end
+  local.get $b
+  local.set $t
+  block $scaled
+    loop $double
+      local.get $t
+      f64.const 2
+      f64.mul
+      local.get $x
+      f64.gt
+      br_if $scaled
+      local.get $t
+      f64.const 2
+      f64.mul
+      local.set $t
+      br $double
+    end
+  end
+  block $done
+    loop $halve
+      local.get $t
+      local.get $b
+      f64.lt
+      br_if $done
+      local.get $x
+      local.get $t
+      f64.ge
+      if
+        local.get $x
+        local.get $t
+        f64.sub
+        local.set $x
+      end
+      local.get $t
+      f64.const 0.5
+      f64.mul
+      local.set $t
+      br $halve
+    end
+  end
+  local.get $x
+  local.get $a
+  f64.copysign
+)
+
+;; Single precision values convert exactly, and so does their remainder.
+(func $beta_fmodf (param $a f32) (param $b f32) (result f32)
+  local.get $a
+  f64.promote_f32
+  local.get $b
+  f64.promote_f32
+  call $beta_fmod
+  f32.demote_f64
+)
+
+;; beta_alloc(size) -> address. Memory grows as needed and is never freed.
+(func $beta_alloc (param $size i32) (result i32) (local $at i32) (local $end i32)
+  global.get $heap
+  local.tee $at
+  local.get $size
+  i32.const 15
+  i32.add
+  i32.const -16
+  i32.and
+  i32.add
+  local.tee $end
+  memory.size
+  i32.const 16
+  i32.shl
+  i32.gt_u
+  if
+    local.get $end
+    memory.size
+    i32.const 16
+    i32.shl
+    i32.sub
+    i32.const 65535
+    i32.add
+    i32.const 16
+    i32.shr_u
+    memory.grow
+    i32.const -1
+    i32.eq
+    if
+      i32.const "out of memory"
+      call $beta_trap
+    end
+  end
+  local.get $end
+  global.set $heap
+  local.get $at
+)
+
+(func $beta_copy (param $to i32) (param $from i32) (param $len i32)
+  block $done
+    loop $next
+      local.get $len
+      i32.eqz
+      br_if $done
+      local.get $to
+      local.get $from
+      i32.load8_u
+      i32.store8
+      local.get $to
+      i32.const 1
+      i32.add
+      local.set $to
+      local.get $from
+      i32.const 1
+      i32.add
+      local.set $from
+      local.get $len
+      i32.const 1
+      i32.sub
+      local.set $len
+      br $next
+    end
+  end
+)
+
+;; beta_str_concat(a, b) -> a new string
+(func $beta_str_concat (param $a i32) (param $b i32) (result i32)
+  (local $len_a i32) (local $len_b i32) (local $out i32)
+  local.get $a
+  call $beta_strlen
+  local.set $len_a
+  local.get $b
+  call $beta_strlen
+  local.set $len_b
+  local.get $len_a
+  local.get $len_b
+  i32.add
+  i32.const 1
+  i32.add
+  call $beta_alloc
+  local.set $out
+  local.get $out
+  local.get $a
+  local.get $len_a
+  call $beta_copy
+  local.get $out
+  local.get $len_a
+  i32.add
+  local.get $b
+  local.get $len_b
+  i32.const 1
+  i32.add
+  call $beta_copy
+  local.get $out
+)
+
+;; beta_str_cmp(a, b) -> -1, 0 or 1, comparing bytes
+(func $beta_str_cmp (param $a i32) (param $b i32) (result i32) (local $x i32) (local $y i32)
+  loop $next
+    local.get $a
+    i32.load8_u
+    local.set $x
+    local.get $b
+    i32.load8_u
+    local.set $y
+    local.get $x
+    local.get $y
+    i32.ne
+    if
+      i32.const -1
+      i32.const 1
+      local.get $x
+      local.get $y
+      i32.lt_u
+      select
+      return
+    end
+    local.get $x
+    if
+      local.get $a
+      i32.const 1
+      i32.add
+      local.set $a
+      local.get $b
+      i32.const 1
+      i32.add
+      local.set $b
+      br $next
+    end
+  end
+  i32.const 0
+)
+
+;; beta_trap(message) reports a runtime error and exits with the status the interpreter
+;; uses for one.
+(func $beta_trap (param $message i32)
+  i32.const 2
+  i32.const "error: "
+  call $beta_write_str
+  i32.const 2
+  local.get $message
+  call $beta_write_str
+  i32.const 2
+  i32.const "\n"
+  call $beta_write_str
+  i32.const 5
+  call $proc_exit
+  unreachable
+)
@@ -0,0 +1,547 @@
+use std::{collections::{hash_map::Entry, HashMap}, fmt::Write};
+
+use crate::{
+    analysis::symbols::Builtin,
+    codegen::{operand_type, type_of},
+    ir::{BinaryOp, BlockId, Const, Function, Inst, Module, Operand, Reg, Terminator, Ty, UnaryOp}
+};
+
+use super::{function_symbol, global_symbol, show_symbol, Builder, FuncType, Instr, ValType, SHOW_DYN};
+
+fn val_type(ty: &Ty) -> ValType {
+    ValType::from_ty(ty).unwrap_or(ValType::I32)
+}
+
+/// The runtime call for a value of type `ty` on the stack, which `print` makes; shared
+/// with the instance printers.
+pub(super) fn print_value(ty: &Ty, builder: &Builder) -> Vec<Instr> {
+    let call = |name: &str| Instr::Call(builder.function(name).expect("printers are defined first"));
+    match ty {
+        Ty::Str => vec![call("beta_print_str")],
+        Ty::Bool => vec![call("beta_print_bool")],
+        Ty::Struct(name) => vec![call(&show_symbol(name))],
+        Ty::Dyn(_) => vec![call(SHOW_DYN)],
+        ty if ty.is_single() => vec![Instr::simple("f64.promote_f32"), Instr::I32Const(1), call("beta_print_float")],
+        ty if ty.is_float() => vec![Instr::I32Const(0), call("beta_print_float")],
+        Ty::I64 => vec![call("beta_print_int")],
+        Ty::U64 => vec![call("beta_print_uint")],
+        ty if ty.is_signed() => vec![Instr::simple("i64.extend_i32_s"), call("beta_print_int")],
+        _ => vec![Instr::simple("i64.extend_i32_u"), call("beta_print_uint")]
+    }
+}
+
+/// Text for the sequence raising `message` as a runtime error when the value on the
+/// stack is true.
+fn trap_if(out: &mut String, message: &str) {
+    let _ = writeln!(out, "if\ni32.const \"{}\"\ncall $beta_trap\nend", message);
+}
+
+/// The helper doing `op` on two integers of type `ty`, trapping like the interpreter
+/// does. Integers held in an `i32` are worked on as `i64`, where nothing overflows, and
+/// range checked.
+fn arith_helper(op: BinaryOp, ty: &Ty) -> (String, String) {
+    let name = format!("beta_{}_{}", op.as_str(), ty);
+    let wasm = val_type(ty);
+    let signed = ty.is_signed();
+    let sign = if signed { "s" } else { "u" };
+    let mut body = String::new();
+
+    if matches!(op, BinaryOp::Div | BinaryOp::Rem) {
+        let _ = writeln!(body, "local.get $b\n{}.eqz", wasm);
+        if op == BinaryOp::Div {
+            trap_if(&mut body, "attempt to divide by zero");
+        } else {
+            trap_if(&mut body, "attempt to calculate the remainder with a divisor of zero");
+        }
+    }
+    let overflow = format!("attempt to {} with overflow", op.verb());
+
+    if wasm == ValType::I32 {
+        let (min, max) = ty.int_range().expect("integer types have a range");
+        let _ = writeln!(body, "local.get $a\ni64.extend_i32_{0}\nlocal.get $b\ni64.extend_i32_{0}", sign);
+        let _ = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => writeln!(body, "i64.{}", op.as_str()),
+            _ => writeln!(body, "i64.{}_{}", op.as_str(), sign)
+        };
+        // A remainder is never larger than its operands; neither is an unsigned quotient.
+        if op != BinaryOp::Rem && (op != BinaryOp::Div || signed) {
+            let _ = writeln!(body, "local.tee $r\ni64.const {}\ni64.lt_s\nlocal.get $r\ni64.const {}\ni64.gt_s\ni32.or", min, max);
+            trap_if(&mut body, &overflow);
+            body.push_str("local.get $r\n");
+        }
+        body.push_str("i32.wrap_i64\n");
+    } else {
+        // Each case leaves whether the operation overflows, then computes the result.
+        let (check, result) = match (op, signed) {
+            (BinaryOp::Add, true) => (
+                "local.get $a\nlocal.get $b\ni64.add\nlocal.tee $r\nlocal.get $a\ni64.xor\nlocal.get $r\nlocal.get $b\ni64.xor\ni64.and\ni64.const 0\ni64.lt_s".to_string(),
+                "local.get $r".to_string()
+            ),
+            (BinaryOp::Sub, true) => (
+                "local.get $a\nlocal.get $b\ni64.sub\nlocal.set $r\nlocal.get $a\nlocal.get $b\ni64.xor\nlocal.get $a\nlocal.get $r\ni64.xor\ni64.and\ni64.const 0\ni64.lt_s".to_string(),
+                "local.get $r".to_string()
+            ),
+            (BinaryOp::Add, false) => (
+                "local.get $a\nlocal.get $b\ni64.add\nlocal.tee $r\nlocal.get $a\ni64.lt_u".to_string(),
+                "local.get $r".to_string()
+            ),
+            (BinaryOp::Sub, false) => (
+                "local.get $a\nlocal.get $b\ni64.lt_u".to_string(),
+                "local.get $a\nlocal.get $b\ni64.sub".to_string()
+            ),
+            (BinaryOp::Mul, _) => {
+                if signed {
+                    // -1 times the most negative value is the one product division can't check.
+                    body.push_str("local.get $a\ni64.const -1\ni64.eq\nif\nlocal.get $b\ni64.const -9223372036854775808\ni64.eq\n");
+                    trap_if(&mut body, &overflow);
+                    body.push_str("i64.const 0\nlocal.get $b\ni64.sub\nreturn\nend\n");
+                }
+                (
+                    format!(
+                        "local.get $a\nlocal.get $b\ni64.mul\nlocal.set $r\nlocal.get $a\ni64.const 0\ni64.ne\n\
+                         if (result i32)\nlocal.get $r\nlocal.get $a\ni64.div_{}\nlocal.get $b\ni64.ne\nelse\ni32.const 0\nend",
+                        sign
+                    ),
+                    "local.get $r".to_string()
+                )
+            },
+            (BinaryOp::Div, true) => (
+                "local.get $a\ni64.const -9223372036854775808\ni64.eq\nlocal.get $b\ni64.const -1\ni64.eq\ni32.and".to_string(),
+                "local.get $a\nlocal.get $b\ni64.div_s".to_string()
+            ),
+            // Only a zero divisor goes wrong here, and that was checked above.
+            _ => (String::new(), format!("local.get $a\nlocal.get $b\ni64.{}_{}", op.as_str(), sign))
+        };
+        if !check.is_empty() {
+            let _ = writeln!(body, "{}", check);
+            trap_if(&mut body, &overflow);
+        }
+        let _ = writeln!(body, "{}", result);
+    }
+    let text = format!("(func ${} (param $a {1}) (param $b {1}) (result {1}) (local $r i64)\n{2})", name, wasm, body);
+    (name, text)
+}
+
+/// The helper negating an integer of type `ty`.
+fn neg_helper(ty: &Ty) -> (String, String) {
+    let name = format!("beta_neg_{}", ty);
+    let wasm = val_type(ty);
+    let mut body = String::new();
+    if !ty.is_signed() {
+        // Only zero negates to an unsigned value.
+        let _ = writeln!(body, "local.get $a\n{}.const 0\n{0}.ne", wasm);
+    } else if wasm == ValType::I64 {
+        body.push_str("local.get $a\ni64.const -9223372036854775808\ni64.eq\n");
+    } else {
+        let (min, max) = ty.int_range().expect("integer types have a range");
+        let _ = writeln!(body, "i64.const 0\nlocal.get $a\ni64.extend_i32_s\ni64.sub\nlocal.tee $r\ni64.const {}\ni64.lt_s\nlocal.get $r\ni64.const {}\ni64.gt_s\ni32.or", min, max);
+    }
+    trap_if(&mut body, "attempt to negate with overflow");
+    let _ = writeln!(body, "{0}.const 0\nlocal.get $a\n{0}.sub", wasm);
+    let text = format!("(func ${} (param $a {1}) (result {1}) (local $r i64)\n{2})", name, wasm, body);
+    (name, text)
+}
+
+/// Instruction selection for one function. Every IR register gets a wasm local, and
+/// values pass through the operand stack only within an instruction.
+///
+/// Blocks are laid out in order inside one `loop`, with a `block` ending before each so
+/// a `br_table` on the block number can enter any of them. A jump to the next block falls
+/// through; any other sets the block number and branches back to the `loop`.
+///
+/// Integers up to 32 bits are kept sign- or zero-extended in an `i32`, booleans are 0
+/// or 1, and strings and instances are addresses.
+pub(super) struct Selector<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    builder: &'m mut Builder,
+    types: HashMap<Reg, Ty>,
+    locals: HashMap<Reg, u32>,
+    /// Types of the locals after the parameters.
+    extra: Vec<ValType>,
+    /// The local holding the number of the block to enter.
+    pc: u32,
+    positions: HashMap<BlockId, usize>,
+    body: Vec<Instr>
+}
+
+impl<'m> Selector<'m> {
+
+    pub fn new(module: &'m Module, function: &'m Function, builder: &'m mut Builder) -> Self {
+        let mut types: HashMap<Reg, Ty> = function.params.iter().cloned().collect();
+        let mut locals = HashMap::new();
+        for (reg, ty) in &function.params {
+            if ValType::from_ty(ty).is_some() {
+                locals.insert(*reg, locals.len() as u32);
+            }
+        }
+        let params = locals.len() as u32;
+        let mut extra = Vec::new();
+        for (reg, ty) in function.blocks.iter().flat_map(|block| &block.insts).filter_map(Inst::def) {
+            types.insert(reg, ty.clone());
+            if let Entry::Vacant(entry) = locals.entry(reg) {
+                entry.insert(params + extra.len() as u32);
+                extra.push(val_type(ty));
+            }
+        }
+        let pc = params + extra.len() as u32;
+        extra.push(ValType::I32);
+        let positions = function.blocks.iter().enumerate().map(|(idx, block)| (block.id, idx)).collect();
+        Self { module, function, builder, types, locals, extra, pc, positions, body: Vec::new() }
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.body.push(instr);
+    }
+
+    fn simple(&mut self, name: &str) {
+        self.emit(Instr::simple(name));
+    }
+
+    fn call(&mut self, name: &str) {
+        let index = self.builder.function(name).expect("runtime routines are defined first");
+        self.emit(Instr::Call(index));
+    }
+
+    fn get(&mut self, reg: Reg) {
+        self.emit(Instr::LocalGet(self.locals[&reg]));
+    }
+
+    fn set(&mut self, reg: Reg) {
+        self.emit(Instr::LocalSet(self.locals[&reg]));
+    }
+
+    /// Pushes `operand`, read as a `ty`.
+    fn push(&mut self, operand: &Operand, ty: &Ty) {
+        let instr = match operand {
+            Operand::Reg(reg) => Instr::LocalGet(self.locals[reg]),
+            Operand::Const(Const::Int(value)) => match val_type(ty) {
+                ValType::I64 => Instr::I64Const(*value as i64),
+                ValType::F32 => Instr::F32Const(*value as f32),
+                ValType::F64 => Instr::F64Const(*value as f64),
+                ValType::I32 => Instr::I32Const(*value as i32)
+            },
+            Operand::Const(Const::Float(value)) if ty.is_single() => Instr::F32Const(*value as f32),
+            Operand::Const(Const::Float(value)) => Instr::F64Const(*value),
+            Operand::Const(Const::Bool(value)) => Instr::I32Const(*value as i32),
+            Operand::Const(Const::Str(value)) => Instr::I32Const(self.builder.string(value.as_bytes()) as i32)
+        };
+        self.emit(instr);
+    }
+
+    /// Selects the whole function, returning its locals after the parameters and its body.
+    pub fn function(mut self) -> (Vec<ValType>, Vec<Instr>) {
+        let function = self.function;
+        let blocks = &function.blocks;
+        let count = blocks.len();
+        let dispatch = count > 1 || blocks.iter().any(|block| !block.term.successors().is_empty());
+        if dispatch {
+            self.emit(Instr::Loop(None));
+            for _ in 0..count {
+                self.emit(Instr::Block(None));
+            }
+            self.emit(Instr::LocalGet(self.pc));
+            self.emit(Instr::BrTable((0..count as u32).collect(), 0));
+        }
+        for (idx, block) in blocks.iter().enumerate() {
+            if dispatch {
+                self.emit(Instr::End);
+            }
+            for inst in &block.insts {
+                self.inst(inst);
+            }
+            self.terminator(&block.term, idx);
+        }
+        if dispatch {
+            self.emit(Instr::End);
+            self.simple("unreachable");
+        }
+        (self.extra, self.body)
+    }
+
+    /// Continues at `target` from the block at `from`, inside `nested` further blocks.
+    fn jump(&mut self, target: BlockId, from: usize, nested: u32) {
+        let position = self.positions[&target];
+        if nested == 0 && position == from + 1 {
+            return;
+        }
+        self.emit(Instr::I32Const(position as i32));
+        self.emit(Instr::LocalSet(self.pc));
+        self.emit(Instr::Br((self.function.blocks.len() - 1 - from) as u32 + nested));
+    }
+
+    fn terminator(&mut self, term: &Terminator, from: usize) {
+        match term {
+            Terminator::Ret(value) => {
+                if let Some(value) = value {
+                    let ty = self.function.ret.clone();
+                    self.push(value, &ty);
+                }
+                self.emit(Instr::Return);
+            },
+            Terminator::Jump(target) => self.jump(*target, from, 0),
+            Terminator::Branch { cond: Operand::Const(Const::Bool(cond)), then, els } => {
+                self.jump(if *cond { *then } else { *els }, from, 0);
+            },
+            Terminator::Branch { then, els, .. } if then == els => self.jump(*then, from, 0),
+            Terminator::Branch { cond, then, els } => {
+                let next = self.positions[then] == from + 1;
+                if next || self.positions[els] == from + 1 {
+                    self.push(cond, &Ty::Bool);
+                    if next {
+                        self.simple("i32.eqz");
+                    }
+                    self.emit(Instr::If(None));
+                    self.jump(if next { *els } else { *then }, from, 1);
+                    self.emit(Instr::End);
+                } else {
+                    self.emit(Instr::I32Const(self.positions[then] as i32));
+                    self.emit(Instr::I32Const(self.positions[els] as i32));
+                    self.push(cond, &Ty::Bool);
+                    self.simple("select");
+                    self.emit(Instr::LocalSet(self.pc));
+                    self.emit(Instr::Br((self.function.blocks.len() - 1 - from) as u32));
+                }
+            },
+            Terminator::Unreachable => self.simple("unreachable")
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dst, ty, src } => {
+                self.push(src, ty);
+                self.set(*dst);
+            },
+            Inst::Binary { dst, ty, op, lhs, rhs } => {
+                let operand_ty = operand_type(&self.types, *op, ty, lhs, rhs);
+                self.push(lhs, &operand_ty);
+                self.push(rhs, &operand_ty);
+                if op.is_comparison() {
+                    self.compare(*op, &operand_ty);
+                } else if operand_ty.is_integer() {
+                    let (name, text) = arith_helper(*op, &operand_ty);
+                    let index = self.builder.helper(&name, || text);
+                    self.emit(Instr::Call(index));
+                } else if operand_ty.is_float() {
+                    self.float_arith(*op, operand_ty.is_single());
+                } else {
+                    // Only strings are left, and they only add.
+                    self.call("beta_str_concat");
+                }
+                self.set(*dst);
+            },
+            Inst::Unary { dst, ty, op, src } => {
+                self.push(src, ty);
+                self.unary(*op, ty);
+                self.set(*dst);
+            },
+            Inst::Call { dst, func, args } => self.call_function(dst.as_ref(), func, args),
+            Inst::Load { dst, global, .. } => {
+                let index = self.builder.global_index(&global_symbol(global)).expect("IR globals are declared first");
+                self.emit(Instr::GlobalGet(index));
+                self.set(*dst);
+            },
+            Inst::Store { global, src } => {
+                let ty = self.module.global(global).map_or(Ty::I64, |global| global.ty.clone());
+                self.push(src, &ty);
+                let index = self.builder.global_index(&global_symbol(global)).expect("IR globals are declared first");
+                self.emit(Instr::GlobalSet(index));
+            },
+            Inst::New { dst, ty, args } => {
+                let fields = match ty {
+                    Ty::Struct(name) => self.module.structure(name).map(|def| def.fields.clone()).unwrap_or_default(),
+                    _ => Vec::new()
+                };
+                self.emit(Instr::I32Const(8 * args.len().max(1) as i32));
+                self.call("beta_alloc");
+                self.set(*dst);
+                for (idx, arg) in args.iter().enumerate() {
+                    let ty = fields.get(idx).map_or(Ty::I64, |(_, ty)| ty.clone());
+                    self.get(*dst);
+                    self.push(arg, &ty);
+                    self.emit(Instr::memory(&format!("{}.store", val_type(&ty)), 8 * idx as u32));
+                }
+            },
+            Inst::Dyn { dst, ty, concrete, src } => {
+                let Ty::Dyn(with) = ty else {
+                    unreachable!("trait objects have a trait object type");
+                };
+                let base = self.builder.vtable_base(concrete, with).expect("every coercion has a vtable");
+                self.emit(Instr::I32Const(16));
+                self.call("beta_alloc");
+                self.set(*dst);
+                self.get(*dst);
+                self.get(*src);
+                self.emit(Instr::memory("i32.store", 0));
+                self.get(*dst);
+                self.emit(Instr::I32Const(base as i32));
+                self.emit(Instr::memory("i32.store", 8));
+            },
+            Inst::CallDyn { dst, recv, method, args } => self.call_dyn(dst.as_ref(), *recv, method, args),
+            Inst::GetField { dst, ty, obj, field } => {
+                let offset = self.field_offset(*obj, field);
+                self.get(*obj);
+                self.emit(Instr::memory(&format!("{}.load", val_type(ty)), offset));
+                self.set(*dst);
+            },
+            Inst::SetField { obj, field, src } => {
+                let offset = self.field_offset(*obj, field);
+                let ty = self.field_type(*obj, field);
+                self.get(*obj);
+                self.push(src, &ty);
+                self.emit(Instr::memory(&format!("{}.store", val_type(&ty)), offset));
+            },
+            Inst::Phi { .. } => unreachable!("phis are removed before instruction selection")
+        }
+    }
+
+    fn field_offset(&self, obj: Reg, field: &str) -> u32 {
+        let Some(Ty::Struct(name)) = self.types.get(&obj) else {
+            return 0;
+        };
+        self.module.structure(name)
+            .and_then(|def| def.fields.iter().position(|(name, _)| name == field))
+            .map_or(0, |idx| 8 * idx as u32)
+    }
+
+    fn field_type(&self, obj: Reg, field: &str) -> Ty {
+        match self.types.get(&obj) {
+            Some(Ty::Struct(name)) => self.module.structure(name).and_then(|def| def.field(field)).cloned().unwrap_or(Ty::I64),
+            _ => Ty::I64
+        }
+    }
+
+    fn float_arith(&mut self, op: BinaryOp, single: bool) {
+        let prefix = if single { "f32" } else { "f64" };
+        match op {
+            BinaryOp::Rem => self.call(if single { "beta_fmodf" } else { "beta_fmod" }),
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => self.simple(&format!("{}.{}", prefix, op.as_str())),
+            _ => unreachable!("comparisons are selected separately")
+        }
+    }
+
+    /// Compares the two values on the stack as `ty`, leaving 0 or 1.
+    fn compare(&mut self, op: BinaryOp, ty: &Ty) {
+        let name = match op {
+            BinaryOp::Eq => "eq",
+            BinaryOp::Ne => "ne",
+            BinaryOp::Lt => "lt",
+            BinaryOp::Le => "le",
+            BinaryOp::Gt => "gt",
+            _ => "ge"
+        };
+        let wasm = val_type(ty);
+        if ty.is_float() {
+            self.simple(&format!("{}.{}", wasm, name));
+            return;
+        }
+        let signed = if *ty == Ty::Str {
+            self.call("beta_str_cmp");
+            self.emit(Instr::I32Const(0));
+            true
+        } else {
+            ty.is_signed()
+        };
+        if matches!(op, BinaryOp::Eq | BinaryOp::Ne) {
+            self.simple(&format!("{}.{}", wasm, name));
+        } else {
+            self.simple(&format!("{}.{}_{}", wasm, name, if signed { "s" } else { "u" }));
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, ty: &Ty) {
+        match op {
+            UnaryOp::Neg if ty.is_float() => self.simple(if ty.is_single() { "f32.neg" } else { "f64.neg" }),
+            UnaryOp::Neg => {
+                let (name, text) = neg_helper(ty);
+                let index = self.builder.helper(&name, || text);
+                self.emit(Instr::Call(index));
+            },
+            UnaryOp::Not => self.simple("i32.eqz"),
+            UnaryOp::BitNot if val_type(ty) == ValType::I64 => {
+                self.emit(Instr::I64Const(-1));
+                self.simple("i64.xor");
+            },
+            UnaryOp::BitNot => {
+                // Flipping the bits of a sign-extended value leaves it sign-extended; an
+                // unsigned one has to stay within its width.
+                let mask = match ty.bits() {
+                    Some(bits) if bits < 32 && !ty.is_signed() => (1 << bits) - 1,
+                    _ => -1
+                };
+                self.emit(Instr::I32Const(mask));
+                self.simple("i32.xor");
+            }
+        }
+    }
+
+    fn call_function(&mut self, dst: Option<&(Reg, Ty)>, func: &str, args: &[Operand]) {
+        if let Some(builtin) = Builtin::ALL.into_iter().find(|builtin| builtin.name() == func) {
+            return self.print(builtin, args);
+        }
+        let Some(callee) = self.module.function(func) else {
+            unreachable!("calls to unknown functions are rejected before selection");
+        };
+        for (arg, (_, ty)) in args.iter().zip(&callee.params) {
+            self.push(arg, ty);
+        }
+        let index = self.builder.function(&function_symbol(func)).expect("IR functions are reserved first");
+        self.emit(Instr::Call(index));
+        match dst {
+            Some((dst, _)) => self.set(*dst),
+            None if ValType::from_ty(&callee.ret).is_some() => self.simple("drop"),
+            None => {}
+        }
+    }
+
+    /// Calls the function in the receiver's vtable for `method`, passing the instance as
+    /// `self`; slot 0 of a vtable is its printer.
+    fn call_dyn(&mut self, dst: Option<&(Reg, Ty)>, recv: Reg, method: &str, args: &[Operand]) {
+        let Some(Ty::Dyn(with)) = self.types.get(&recv).cloned() else {
+            unreachable!("dynamic calls go through trait objects");
+        };
+        let module = self.module;
+        let def = module.trait_def(&with).expect("trait object types are declared");
+        let (slot, signature) = def.slot(method).expect("dynamic calls name a slot");
+        self.get(recv);
+        self.emit(Instr::memory("i32.load", 0));
+        for (arg, ty) in args.iter().zip(&signature.params) {
+            self.push(arg, ty);
+        }
+        self.get(recv);
+        self.emit(Instr::memory("i32.load", 8));
+        self.emit(Instr::I32Const(slot as i32 + 1));
+        self.simple("i32.add");
+        let ty = FuncType {
+            params: std::iter::once(ValType::I32).chain(signature.params.iter().filter_map(ValType::from_ty)).collect(),
+            results: ValType::from_ty(&signature.ret).into_iter().collect()
+        };
+        let returns = !ty.results.is_empty();
+        self.emit(Instr::CallIndirect(ty));
+        match dst {
+            Some((dst, _)) => self.set(*dst),
+            None if returns => self.simple("drop"),
+            None => {}
+        }
+    }
+
+    /// `print` and `println` write their arguments separated by spaces, each according
+    /// to its type.
+    fn print(&mut self, builtin: Builtin, args: &[Operand]) {
+        for (idx, arg) in args.iter().enumerate() {
+            if idx > 0 {
+                self.emit(Instr::I32Const(32));
+                self.call("beta_print_char");
+            }
+            let ty = type_of(&self.types, arg);
+            self.push(arg, &ty);
+            let instrs = print_value(&ty, self.builder);
+            self.body.extend(instrs);
+        }
+        if builtin == Builtin::Println {
+            self.emit(Instr::I32Const(10));
+            self.call("beta_print_char");
+        }
+    }
+}
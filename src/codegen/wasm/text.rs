@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter, Result, Write};
+
+use super::{opcodes, Instr, Wasm};
+
+/// Writes a string literal, with anything but printable ASCII as hex escapes.
+fn string(f: &mut Formatter<'_>, value: &[u8]) -> Result {
+    f.write_char('"')?;
+    for byte in value {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+            0x20..=0x7e => f.write_char(*byte as char)?,
+            _ => write!(f, "\\{:02x}", byte)?
+        }
+    }
+    f.write_char('"')
+}
+
+/// `Debug` gives the shortest digits that read back exactly, in a form the text format
+/// accepts, except for NaN.
+fn float(value: impl std::fmt::Debug, nan: bool) -> String {
+    if nan { "nan".to_string() } else { format!("{:?}", value) }
+}
+
+fn types(f: &mut Formatter<'_>, kind: &str, types: &[super::ValType]) -> Result {
+    if types.is_empty() {
+        return Ok(());
+    }
+    write!(f, " ({}", kind)?;
+    for ty in types {
+        write!(f, " {}", ty)?;
+    }
+    f.write_char(')')
+}
+
+impl Wasm {
+    fn instr(&self, f: &mut Formatter<'_>, instr: &Instr) -> Result {
+        match instr {
+            Instr::Simple(opcode) => f.write_str(opcodes::simple(*opcode).expect("simple instructions are known").name),
+            Instr::Block(ty) | Instr::Loop(ty) | Instr::If(ty) => {
+                f.write_str(match instr {
+                    Instr::Block(_) => "block",
+                    Instr::Loop(_) => "loop",
+                    _ => "if"
+                })?;
+                match ty {
+                    Some(ty) => write!(f, " (result {})", ty),
+                    None => Ok(())
+                }
+            },
+            Instr::Else => f.write_str("else"),
+            Instr::End => f.write_str("end"),
+            Instr::Br(depth) => write!(f, "br {}", depth),
+            Instr::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instr::BrTable(targets, default) => {
+                f.write_str("br_table")?;
+                for target in targets {
+                    write!(f, " {}", target)?;
+                }
+                write!(f, " {}", default)
+            },
+            Instr::Return => f.write_str("return"),
+            Instr::Call(index) => write!(f, "call ${}", self.function_name(*index)),
+            Instr::CallIndirect(ty) => {
+                f.write_str("call_indirect")?;
+                types(f, "param", &ty.params)?;
+                types(f, "result", &ty.results)
+            },
+            Instr::LocalGet(index) => write!(f, "local.get {}", index),
+            Instr::LocalSet(index) => write!(f, "local.set {}", index),
+            Instr::LocalTee(index) => write!(f, "local.tee {}", index),
+            Instr::GlobalGet(index) => write!(f, "global.get ${}", self.globals[*index as usize].name),
+            Instr::GlobalSet(index) => write!(f, "global.set ${}", self.globals[*index as usize].name),
+            Instr::Memory { opcode, offset } => {
+                f.write_str(opcodes::memory(*opcode).expect("memory instructions are known").name)?;
+                if *offset > 0 {
+                    write!(f, " offset={}", offset)?;
+                }
+                Ok(())
+            },
+            Instr::MemorySize => f.write_str("memory.size"),
+            Instr::MemoryGrow => f.write_str("memory.grow"),
+            Instr::I32Const(value) => write!(f, "i32.const {}", value),
+            Instr::I64Const(value) => write!(f, "i64.const {}", value),
+            Instr::F32Const(value) => write!(f, "f32.const {}", float(value, value.is_nan())),
+            Instr::F64Const(value) => write!(f, "f64.const {}", float(value, value.is_nan()))
+        }
+    }
+}
+
+/// The text format, with functions and globals named and everything else by index.
+impl Display for Wasm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str("(module\n")?;
+        for import in &self.imports {
+            write!(f, "  (import ")?;
+            string(f, import.module.as_bytes())?;
+            f.write_char(' ')?;
+            string(f, import.field.as_bytes())?;
+            write!(f, " (func ${}", import.name)?;
+            types(f, "param", &import.ty.params)?;
+            types(f, "result", &import.ty.results)?;
+            f.write_str("))\n")?;
+        }
+        if !self.table.is_empty() {
+            writeln!(f, "  (table {} funcref)", self.table.len())?;
+        }
+        writeln!(f, "  (memory (export \"memory\") {})", self.pages)?;
+        for global in &self.globals {
+            write!(f, "  (global ${} ", global.name)?;
+            if global.mutable {
+                write!(f, "(mut {})", global.ty)?;
+            } else {
+                write!(f, "{}", global.ty)?;
+            }
+            f.write_str(" (")?;
+            self.instr(f, &global.init)?;
+            f.write_str("))\n")?;
+        }
+
+        if !self.table.is_empty() {
+            f.write_str("  (elem (i32.const 0)")?;
+            for index in &self.table {
+                write!(f, " ${}", self.function_name(*index))?;
+            }
+            f.write_str(")\n")?;
+        }
+
+        for func in &self.funcs {
+            write!(f, "  (func ${}", func.name)?;
+            if let Some(export) = &func.export {
+                f.write_str(" (export ")?;
+                string(f, export.as_bytes())?;
+                f.write_char(')')?;
+            }
+            types(f, "param", &func.ty.params)?;
+            types(f, "result", &func.ty.results)?;
+            types(f, "local", &func.locals)?;
+            f.write_char('\n')?;
+            let mut depth = 2;
+            for instr in &func.body {
+                if matches!(instr, Instr::Else | Instr::End) {
+                    depth -= 1;
+                }
+                write!(f, "{:width$}", "", width = 2 * depth)?;
+                self.instr(f, instr)?;
+                f.write_char('\n')?;
+                if matches!(instr, Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else) {
+                    depth += 1;
+                }
+            }
+            f.write_str("  )\n")?;
+        }
+
+        for (address, bytes) in &self.data {
+            write!(f, "  (data (i32.const {}) ", address)?;
+            string(f, bytes)?;
+            f.write_str(")\n")?;
+        }
+        f.write_str(")\n")
+    }
+}
@@ -0,0 +1,890 @@
+use std::{collections::HashSet, fmt::Display};
+
+use super::{
+    binary::{id, FUNC_REF, FUNC_TYPE, MAGIC, VERSION},
+    opcodes::{self, *},
+    FuncType, ValType
+};
+
+/// Memories are limited to 4 GiB, in 64 KiB pages.
+const MAX_PAGES: u32 = 65536;
+
+/// More locals than engines accept; it keeps a corrupt count from allocating without bound.
+const MAX_LOCALS: u64 = 50000;
+
+/// Why a module doesn't validate, and where in its bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ValidationError {
+    pub offset: usize,
+    pub message: String
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at offset {:#x}: {}", self.offset, self.message)
+    }
+}
+
+type Result<T> = std::result::Result<T, ValidationError>;
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+    /// Where the section or body being read ends.
+    end: usize
+}
+
+impl<'b> Reader<'b> {
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        Err(ValidationError { offset: self.pos, message: message.into() })
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.end
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        if self.at_end() {
+            return self.error("unexpected end");
+        }
+        self.pos += 1;
+        Ok(self.bytes[self.pos - 1])
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8]> {
+        if self.end - self.pos < len {
+            return self.error("unexpected end");
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    /// An unsigned LEB128 number of at most `bits` bits.
+    fn unsigned(&mut self, bits: u32) -> Result<u64> {
+        let mut value: u128 = 0;
+        for idx in 0..bits.div_ceil(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u128) << (7 * idx);
+            if byte & 0x80 == 0 {
+                if value >> bits != 0 {
+                    return self.error("integer too large");
+                }
+                return Ok(value as u64);
+            }
+        }
+        self.error("integer representation too long")
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.unsigned(32)? as u32)
+    }
+
+    /// A signed LEB128 number of at most `bits` bits.
+    fn signed(&mut self, bits: u32) -> Result<i64> {
+        let mut value: i128 = 0;
+        for idx in 0..bits.div_ceil(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as i128) << (7 * idx);
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    value -= 1 << (7 * (idx + 1));
+                }
+                let limit = 1i128 << (bits - 1);
+                if value < -limit || value >= limit {
+                    return self.error("integer too large");
+                }
+                return Ok(value as i64);
+            }
+        }
+        self.error("integer representation too long")
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let start = self.pos;
+        let bytes = self.take(len)?;
+        match std::str::from_utf8(bytes) {
+            Ok(name) => Ok(name.to_string()),
+            Err(_) => Err(ValidationError { offset: start, message: "malformed UTF-8 name".into() })
+        }
+    }
+
+    fn val_type(&mut self) -> Result<ValType> {
+        let byte = self.byte()?;
+        match ValType::from_byte(byte) {
+            Some(ty) => Ok(ty),
+            None => {
+                self.pos -= 1;
+                self.error(format!("unknown value type {:#04x}", byte))
+            }
+        }
+    }
+
+    fn val_types(&mut self) -> Result<Vec<ValType>> {
+        let count = self.u32()?;
+        (0..count).map(|_| self.val_type()).collect()
+    }
+
+    /// Memory limits, in pages.
+    fn limits(&mut self) -> Result<()> {
+        let min = match self.byte()? {
+            0x00 => self.u32()?,
+            0x01 => {
+                let min = self.u32()?;
+                let max = self.u32()?;
+                if max > MAX_PAGES {
+                    return self.error("memory size must be at most 65536 pages (4GiB)");
+                }
+                if min > max {
+                    return self.error("size minimum must not be greater than maximum");
+                }
+                min
+            },
+            flag => return self.error(format!("unknown limits flag {:#04x}", flag))
+        };
+        if min > MAX_PAGES {
+            return self.error("memory size must be at most 65536 pages (4GiB)");
+        }
+        Ok(())
+    }
+
+    /// A table's element type and limits, in entries.
+    fn table_type(&mut self) -> Result<()> {
+        if self.byte()? != FUNC_REF {
+            self.pos -= 1;
+            return self.error("only tables of functions are supported");
+        }
+        match self.byte()? {
+            0x00 => {
+                self.u32()?;
+            },
+            0x01 => {
+                let min = self.u32()?;
+                if min > self.u32()? {
+                    return self.error("size minimum must not be greater than maximum");
+                }
+            },
+            flag => return self.error(format!("unknown limits flag {:#04x}", flag))
+        }
+        Ok(())
+    }
+}
+
+/// What a module declares, as far as checking code needs.
+#[derive(Default)]
+struct Module {
+    types: Vec<FuncType>,
+    /// The type index of every function, imported ones first.
+    funcs: Vec<u32>,
+    imported_funcs: usize,
+    /// The type and mutability of every global, imported ones first.
+    globals: Vec<(ValType, bool)>,
+    imported_globals: usize,
+    tables: usize,
+    memories: usize,
+    bodies: usize,
+    data: usize,
+    data_count: Option<u32>
+}
+
+impl Module {
+    fn func_type(&self, reader: &Reader<'_>, index: u32) -> Result<FuncType> {
+        match self.funcs.get(index as usize) {
+            Some(ty) => Ok(self.types[*ty as usize].clone()),
+            None => reader.error(format!("unknown function {}", index))
+        }
+    }
+
+    fn type_index(&self, reader: &mut Reader<'_>) -> Result<u32> {
+        let index = reader.u32()?;
+        if index as usize >= self.types.len() {
+            return reader.error(format!("unknown type {}", index));
+        }
+        Ok(index)
+    }
+
+    fn needs_table(&self, reader: &Reader<'_>) -> Result<()> {
+        if self.tables == 0 {
+            return reader.error("unknown table 0");
+        }
+        Ok(())
+    }
+
+    fn needs_memory(&self, reader: &Reader<'_>) -> Result<()> {
+        if self.memories == 0 {
+            return reader.error("unknown memory 0");
+        }
+        Ok(())
+    }
+
+    /// A constant expression producing a `ty`, through its `end`.
+    fn const_expr(&self, reader: &mut Reader<'_>, ty: ValType) -> Result<()> {
+        let start = reader.pos;
+        let found = match reader.byte()? {
+            I32_CONST => {
+                reader.signed(32)?;
+                ValType::I32
+            },
+            I64_CONST => {
+                reader.signed(64)?;
+                ValType::I64
+            },
+            F32_CONST => {
+                reader.take(4)?;
+                ValType::F32
+            },
+            F64_CONST => {
+                reader.take(8)?;
+                ValType::F64
+            },
+            GLOBAL_GET => {
+                let index = reader.u32()? as usize;
+                match self.globals.get(index) {
+                    Some((ty, false)) if index < self.imported_globals => *ty,
+                    Some(_) => return reader.error("constant expressions may only read imported, immutable globals"),
+                    None => return reader.error(format!("unknown global {}", index))
+                }
+            },
+            _ => {
+                reader.pos = start;
+                return reader.error("constant expression required");
+            }
+        };
+        if found != ty {
+            reader.pos = start;
+            return reader.error(format!("type mismatch: expected {}, found {}", ty, found));
+        }
+        if reader.byte()? != END {
+            reader.pos -= 1;
+            return reader.error("constant expression required");
+        }
+        Ok(())
+    }
+
+    fn section(&mut self, kind: u8, reader: &mut Reader<'_>, exports: &mut HashSet<String>) -> Result<()> {
+        if kind == id::CUSTOM {
+            reader.name()?;
+            reader.pos = reader.end;
+            return Ok(());
+        }
+        if kind == id::START {
+            let index = reader.u32()?;
+            let ty = self.func_type(reader, index)?;
+            if !ty.params.is_empty() || !ty.results.is_empty() {
+                return reader.error("the start function must take and return nothing");
+            }
+            return Ok(());
+        }
+        if kind == id::DATA_COUNT {
+            self.data_count = Some(reader.u32()?);
+            return Ok(());
+        }
+
+        let count = reader.u32()?;
+        if kind == id::CODE && count as usize != self.funcs.len() - self.imported_funcs {
+            return reader.error("function and code section have inconsistent lengths");
+        }
+        for _ in 0..count {
+            match kind {
+                id::TYPE => {
+                    if reader.byte()? != FUNC_TYPE {
+                        reader.pos -= 1;
+                        return reader.error("malformed function type");
+                    }
+                    let params = reader.val_types()?;
+                    let results = reader.val_types()?;
+                    self.types.push(FuncType { params, results });
+                },
+                id::IMPORT => {
+                    reader.name()?;
+                    reader.name()?;
+                    match reader.byte()? {
+                        0x00 => {
+                            let ty = self.type_index(reader)?;
+                            self.funcs.push(ty);
+                            self.imported_funcs += 1;
+                        },
+                        0x02 => {
+                            reader.limits()?;
+                            self.memories += 1;
+                        },
+                        0x03 => {
+                            let ty = reader.val_type()?;
+                            let mutable = reader.byte()? == 0x01;
+                            self.globals.push((ty, mutable));
+                            self.imported_globals += 1;
+                        },
+                        0x01 => {
+                            reader.table_type()?;
+                            self.tables += 1;
+                        },
+                        kind => return reader.error(format!("unknown import kind {:#04x}", kind))
+                    }
+                },
+                id::FUNCTION => {
+                    let ty = self.type_index(reader)?;
+                    self.funcs.push(ty);
+                },
+                id::TABLE => {
+                    reader.table_type()?;
+                    self.tables += 1;
+                },
+                id::ELEMENT => {
+                    // Only active segments of function indices for table 0, as generated.
+                    match reader.u32()? {
+                        0x00 => {
+                            self.needs_table(reader)?;
+                            self.const_expr(reader, ValType::I32)?;
+                        },
+                        flag => return reader.error(format!("unsupported element segment flag {:#x}", flag))
+                    }
+                    for _ in 0..reader.u32()? {
+                        let index = reader.u32()?;
+                        self.func_type(reader, index)?;
+                    }
+                },
+                id::MEMORY => {
+                    reader.limits()?;
+                    self.memories += 1;
+                },
+                id::GLOBAL => {
+                    let ty = reader.val_type()?;
+                    let mutable = match reader.byte()? {
+                        0x00 => false,
+                        0x01 => true,
+                        _ => {
+                            reader.pos -= 1;
+                            return reader.error("malformed mutability");
+                        }
+                    };
+                    self.const_expr(reader, ty)?;
+                    self.globals.push((ty, mutable));
+                },
+                id::EXPORT => {
+                    let start = reader.pos;
+                    let name = reader.name()?;
+                    let kind = reader.byte()?;
+                    let index = reader.u32()? as usize;
+                    let known = match kind {
+                        0x00 => index < self.funcs.len(),
+                        0x02 => index < self.memories,
+                        0x03 => index < self.globals.len(),
+                        0x01 => index < self.tables,
+                        _ => return reader.error(format!("unknown export kind {:#04x}", kind))
+                    };
+                    if !known {
+                        return reader.error(format!("unknown export target {}", index));
+                    }
+                    if !exports.insert(name.clone()) {
+                        return Err(ValidationError { offset: start, message: format!("duplicate export name `{}`", name) });
+                    }
+                },
+                id::CODE => {
+                    let size = reader.u32()? as usize;
+                    if reader.end - reader.pos < size {
+                        return reader.error("unexpected end");
+                    }
+                    let end = reader.pos + size;
+                    let mut body = Reader { bytes: reader.bytes, pos: reader.pos, end };
+                    let index = (self.imported_funcs + self.bodies) as u32;
+                    let ty = self.func_type(&body, index)?;
+                    Code::new(self, &mut body, ty)?.check()?;
+                    reader.pos = end;
+                    self.bodies += 1;
+                },
+                id::DATA => {
+                    match reader.u32()? {
+                        0x00 => {
+                            self.needs_memory(reader)?;
+                            self.const_expr(reader, ValType::I32)?;
+                        },
+                        0x01 => {},
+                        0x02 => {
+                            if reader.u32()? != 0 {
+                                return reader.error("unknown memory");
+                            }
+                            self.needs_memory(reader)?;
+                            self.const_expr(reader, ValType::I32)?;
+                        },
+                        flag => return reader.error(format!("unknown data segment flag {:#x}", flag))
+                    }
+                    let len = reader.u32()? as usize;
+                    reader.take(len)?;
+                    self.data += 1;
+                },
+                _ => return reader.error(format!("unknown section {}", kind))
+            }
+        }
+        if self.memories > 1 {
+            return reader.error("multiple memories are not supported");
+        }
+        if self.tables > 1 {
+            return reader.error("multiple tables are not supported");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Block,
+    Loop,
+    If,
+    Else
+}
+
+struct Frame {
+    kind: Kind,
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+    /// The operand stack height when the frame was entered.
+    height: usize,
+    unreachable: bool
+}
+
+impl Frame {
+    /// What a branch to this frame carries.
+    fn label_types(&self) -> &[ValType] {
+        if self.kind == Kind::Loop { &self.params } else { &self.results }
+    }
+}
+
+/// The operand and control stacks of the validation algorithm in the specification's
+/// appendix. `None` stands for a value of unknown type, after code that can't be reached.
+struct Code<'m, 'r, 'b> {
+    module: &'m Module,
+    reader: &'r mut Reader<'b>,
+    locals: Vec<ValType>,
+    operands: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+    /// Where the instruction being checked starts.
+    start: usize
+}
+
+impl<'m, 'r, 'b> Code<'m, 'r, 'b> {
+
+    fn new(module: &'m Module, reader: &'r mut Reader<'b>, ty: FuncType) -> Result<Self> {
+        let mut locals = ty.params.clone();
+        let mut total = 0;
+        for _ in 0..reader.u32()? {
+            let count = reader.u32()?;
+            total += count as u64;
+            if total > MAX_LOCALS {
+                return reader.error("too many locals");
+            }
+            let ty = reader.val_type()?;
+            locals.extend(std::iter::repeat_n(ty, count as usize));
+        }
+        let frames = vec![Frame { kind: Kind::Block, params: Vec::new(), results: ty.results, height: 0, unreachable: false }];
+        Ok(Self { module, reader, locals, operands: Vec::new(), frames, start: 0 })
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T> {
+        Err(ValidationError { offset: self.start, message: message.into() })
+    }
+
+    fn push(&mut self, ty: ValType) {
+        self.operands.push(Some(ty));
+    }
+
+    fn pop(&mut self) -> Result<Option<ValType>> {
+        let frame = self.frames.last().expect("code has a frame until its end");
+        if self.operands.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return self.error("type mismatch: expected a value, but the stack is empty");
+        }
+        Ok(self.operands.pop().expect("the stack is above the frame"))
+    }
+
+    fn pop_expect(&mut self, expected: ValType) -> Result<()> {
+        self.pop_all(&[expected])
+    }
+
+    fn pop_all(&mut self, types: &[ValType]) -> Result<()> {
+        self.pop_values(types).map(|_| ())
+    }
+
+    /// Pops values of `types`, returning them in stack order.
+    fn pop_values(&mut self, types: &[ValType]) -> Result<Vec<Option<ValType>>> {
+        let mut values = Vec::new();
+        for ty in types.iter().rev() {
+            let value = self.pop()?;
+            if let Some(found) = value.filter(|found| found != ty) {
+                return self.error(format!("type mismatch: expected {}, found {}", ty, found));
+            }
+            values.push(value);
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    fn push_frame(&mut self, kind: Kind, params: Vec<ValType>, results: Vec<ValType>) {
+        let height = self.operands.len();
+        self.operands.extend(params.iter().copied().map(Some));
+        self.frames.push(Frame { kind, params, results, height, unreachable: false });
+    }
+
+    fn pop_frame(&mut self) -> Result<Frame> {
+        let results = self.frames.last().expect("code has a frame until its end").results.clone();
+        self.pop_all(&results)?;
+        let frame = self.frames.pop().expect("code has a frame until its end");
+        if self.operands.len() != frame.height {
+            return self.error("type mismatch: values remain on the stack at the end of a block");
+        }
+        Ok(frame)
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.frames.last_mut().expect("code has a frame until its end");
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label(&self, depth: u32) -> Result<Vec<ValType>> {
+        match self.frames.len().checked_sub(depth as usize + 1) {
+            Some(index) => Ok(self.frames[index].label_types().to_vec()),
+            None => self.error(format!("unknown label {}", depth))
+        }
+    }
+
+    fn block_type(&mut self) -> Result<FuncType> {
+        if let Some(&byte) = self.reader.bytes.get(self.reader.pos) {
+            if byte == EMPTY {
+                self.reader.pos += 1;
+                return Ok(FuncType::default());
+            }
+            if let Some(ty) = ValType::from_byte(byte) {
+                self.reader.pos += 1;
+                return Ok(FuncType { params: Vec::new(), results: vec![ty] });
+            }
+        }
+        let index = self.reader.signed(33)?;
+        match usize::try_from(index).ok().and_then(|index| self.module.types.get(index)) {
+            Some(ty) => Ok(ty.clone()),
+            None => self.error(format!("unknown type {}", index))
+        }
+    }
+
+    fn local(&mut self) -> Result<ValType> {
+        let index = self.reader.u32()?;
+        match self.locals.get(index as usize) {
+            Some(ty) => Ok(*ty),
+            None => self.error(format!("unknown local {}", index))
+        }
+    }
+
+    fn global(&mut self) -> Result<(ValType, bool)> {
+        let index = self.reader.u32()?;
+        match self.module.globals.get(index as usize) {
+            Some(global) => Ok(*global),
+            None => self.error(format!("unknown global {}", index))
+        }
+    }
+
+    fn check(mut self) -> Result<()> {
+        while !self.frames.is_empty() {
+            self.start = self.reader.pos;
+            let opcode = self.reader.byte()?;
+            self.instr(opcode)?;
+        }
+        if !self.reader.at_end() {
+            return self.reader.error("the function body continues after its end");
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, opcode: u8) -> Result<()> {
+        match opcode {
+            UNREACHABLE => self.set_unreachable(),
+            BLOCK | LOOP => {
+                let ty = self.block_type()?;
+                self.pop_all(&ty.params)?;
+                self.push_frame(if opcode == BLOCK { Kind::Block } else { Kind::Loop }, ty.params, ty.results);
+            },
+            IF => {
+                let ty = self.block_type()?;
+                self.pop_expect(ValType::I32)?;
+                self.pop_all(&ty.params)?;
+                self.push_frame(Kind::If, ty.params, ty.results);
+            },
+            ELSE => {
+                if self.frames.last().map(|frame| frame.kind) != Some(Kind::If) {
+                    return self.error("`else` outside of an `if`");
+                }
+                let frame = self.pop_frame()?;
+                self.push_frame(Kind::Else, frame.params, frame.results);
+            },
+            END => {
+                let frame = self.pop_frame()?;
+                if frame.kind == Kind::If && frame.params != frame.results {
+                    return self.error("type mismatch: an `if` without `else` must leave what it takes");
+                }
+                self.operands.extend(frame.results.into_iter().map(Some));
+            },
+            BR => {
+                let depth = self.reader.u32()?;
+                let types = self.label(depth)?;
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            },
+            BR_IF => {
+                let depth = self.reader.u32()?;
+                self.pop_expect(ValType::I32)?;
+                let types = self.label(depth)?;
+                self.pop_all(&types)?;
+                self.operands.extend(types.into_iter().map(Some));
+            },
+            BR_TABLE => {
+                let count = self.reader.u32()?;
+                let mut depths = Vec::new();
+                for _ in 0..count {
+                    depths.push(self.reader.u32()?);
+                }
+                let default = self.reader.u32()?;
+                self.pop_expect(ValType::I32)?;
+                let arity = self.label(default)?.len();
+                for depth in depths {
+                    let types = self.label(depth)?;
+                    if types.len() != arity {
+                        return self.error("type mismatch: `br_table` targets carry different numbers of values");
+                    }
+                    // Every target sees the same values, so they stay for the next one.
+                    let values = self.pop_values(&types)?;
+                    self.operands.extend(values);
+                }
+                let types = self.label(default)?;
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            },
+            RETURN => {
+                let types = self.frames[0].results.clone();
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            },
+            CALL => {
+                let index = self.reader.u32()?;
+                let ty = self.module.func_type(self.reader, index)?;
+                self.pop_all(&ty.params)?;
+                self.operands.extend(ty.results.into_iter().map(Some));
+            },
+            CALL_INDIRECT => {
+                let index = self.module.type_index(self.reader)?;
+                if self.reader.byte()? != 0x00 {
+                    return self.error("zero byte expected");
+                }
+                self.module.needs_table(self.reader)?;
+                let ty = self.module.types[index as usize].clone();
+                self.pop_expect(ValType::I32)?;
+                self.pop_all(&ty.params)?;
+                self.operands.extend(ty.results.into_iter().map(Some));
+            },
+            DROP => {
+                self.pop()?;
+            },
+            SELECT => {
+                self.pop_expect(ValType::I32)?;
+                let first = self.pop()?;
+                let second = self.pop()?;
+                match (first, second) {
+                    (Some(first), Some(second)) if first != second => {
+                        return self.error(format!("type mismatch: `select` of {} and {}", second, first));
+                    },
+                    (first, second) => self.operands.push(first.or(second))
+                }
+            },
+            LOCAL_GET => {
+                let ty = self.local()?;
+                self.push(ty);
+            },
+            LOCAL_SET => {
+                let ty = self.local()?;
+                self.pop_expect(ty)?;
+            },
+            LOCAL_TEE => {
+                let ty = self.local()?;
+                self.pop_expect(ty)?;
+                self.push(ty);
+            },
+            GLOBAL_GET => {
+                let (ty, _) = self.global()?;
+                self.push(ty);
+            },
+            GLOBAL_SET => {
+                let (ty, mutable) = self.global()?;
+                if !mutable {
+                    return self.error("global is immutable");
+                }
+                self.pop_expect(ty)?;
+            },
+            MEMORY_SIZE | MEMORY_GROW => {
+                if self.reader.byte()? != 0 {
+                    return self.error("zero byte expected");
+                }
+                self.module.needs_memory(self.reader)?;
+                if opcode == MEMORY_GROW {
+                    self.pop_expect(ValType::I32)?;
+                }
+                self.push(ValType::I32);
+            },
+            I32_CONST => {
+                self.reader.signed(32)?;
+                self.push(ValType::I32);
+            },
+            I64_CONST => {
+                self.reader.signed(64)?;
+                self.push(ValType::I64);
+            },
+            F32_CONST => {
+                self.reader.take(4)?;
+                self.push(ValType::F32);
+            },
+            F64_CONST => {
+                self.reader.take(8)?;
+                self.push(ValType::F64);
+            },
+            0xfc => return self.error("prefixed instructions are not supported"),
+            _ => {
+                if let Some(memory) = opcodes::memory(opcode) {
+                    let align = self.reader.u32()?;
+                    self.reader.u32()?;
+                    if align > memory.align {
+                        return self.error("alignment must not be larger than natural");
+                    }
+                    self.module.needs_memory(self.reader)?;
+                    if memory.store {
+                        self.pop_expect(memory.ty)?;
+                        self.pop_expect(ValType::I32)?;
+                    } else {
+                        self.pop_expect(ValType::I32)?;
+                        self.push(memory.ty);
+                    }
+                } else if let Some(simple) = opcodes::simple(opcode) {
+                    self.pop_all(simple.params)?;
+                    self.operands.extend(simple.results.iter().copied().map(Some));
+                } else {
+                    return self.error(format!("unknown opcode {:#04x}", opcode));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `bytes` are a well-formed, valid WebAssembly module that uses only what
+/// the backend generates: functions, globals, one table of functions with active
+/// element segments, one memory and data.
+pub(crate) fn validate(bytes: &[u8]) -> Result<()> {
+    let mut reader = Reader { bytes, pos: 0, end: bytes.len() };
+    if reader.take(4).ok() != Some(MAGIC) {
+        return Err(ValidationError { offset: 0, message: "magic header not detected".into() });
+    }
+    if reader.take(4).ok() != Some(VERSION) {
+        return Err(ValidationError { offset: 4, message: "unknown binary version".into() });
+    }
+
+    // Sections other than custom ones come in this order, each at most once.
+    const ORDER: [u8; 12] = [id::TYPE, id::IMPORT, id::FUNCTION, id::TABLE, id::MEMORY, id::GLOBAL, id::EXPORT, id::START, id::ELEMENT, id::DATA_COUNT, id::CODE, id::DATA];
+    let mut module = Module::default();
+    let mut exports = HashSet::new();
+    let mut last = None;
+    while !reader.at_end() {
+        let start = reader.pos;
+        let kind = reader.byte()?;
+        let size = reader.u32()? as usize;
+        if reader.end - reader.pos < size {
+            return reader.error("section size mismatch: the section runs past the end");
+        }
+        if kind != id::CUSTOM {
+            let Some(rank) = ORDER.iter().position(|ordered| *ordered == kind) else {
+                return Err(ValidationError { offset: start, message: format!("malformed section id {}", kind) });
+            };
+            if last.is_some_and(|last| rank <= last) {
+                return Err(ValidationError { offset: start, message: "unexpected section: out of order or duplicated".into() });
+            }
+            last = Some(rank);
+        }
+        let mut section = Reader { bytes, pos: reader.pos, end: reader.pos + size };
+        module.section(kind, &mut section, &mut exports)?;
+        if section.pos != section.end {
+            return section.error("section size mismatch");
+        }
+        reader.pos = section.end;
+    }
+
+    if module.bodies != module.funcs.len() - module.imported_funcs {
+        return reader.error("function and code section have inconsistent lengths");
+    }
+    if module.data_count.is_some_and(|count| count as usize != module.data) {
+        return reader.error("data count and data section have inconsistent lengths");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module of one function taking nothing and returning `results`, with `body` as
+    /// its code after the local declarations.
+    fn module(results: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = [MAGIC, VERSION].concat();
+        let mut types = vec![1, FUNC_TYPE, 0, results.len() as u8];
+        types.extend(results);
+        for (kind, contents) in [(id::TYPE, types), (id::FUNCTION, vec![1, 0])] {
+            bytes.extend([kind, contents.len() as u8]);
+            bytes.extend(contents);
+        }
+        let mut code = vec![1, body.len() as u8 + 1, 0];
+        code.extend(body);
+        bytes.extend([id::CODE, code.len() as u8]);
+        bytes.extend(code);
+        bytes
+    }
+
+    fn message(bytes: &[u8]) -> String {
+        validate(bytes).expect_err("an invalid module").message
+    }
+
+    #[test]
+    fn accepts_well_typed_functions() {
+        assert_eq!(validate(&[MAGIC, VERSION].concat()), Ok(()));
+        assert_eq!(validate(&module(&[0x7f], &[0x41, 7, 0x0b])), Ok(()));
+        assert_eq!(validate(&module(&[], &[0x02, 0x40, 0x0c, 0, 0x0b, 0x0b])), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_headers_and_sections() {
+        assert_eq!(message(b"\0wasm\x01\0\0\0"), "magic header not detected");
+        assert_eq!(message(b"\0asm\x02\0\0\0"), "unknown binary version");
+        let bytes = module(&[0x7f], &[0x41, 7, 0x0b]);
+        assert_eq!(message(&bytes[..bytes.len() - 1]), "section size mismatch: the section runs past the end");
+    }
+
+    #[test]
+    fn rejects_ill_typed_code() {
+        assert_eq!(message(&module(&[0x7f], &[0x42, 7, 0x0b])), "type mismatch: expected i32, found i64");
+        assert_eq!(message(&module(&[0x7f], &[0x0b])), "type mismatch: expected a value, but the stack is empty");
+        assert_eq!(message(&module(&[], &[0x41, 7, 0x0b])), "type mismatch: values remain on the stack at the end of a block");
+        assert_eq!(message(&module(&[], &[0x0c, 1, 0x0b])), "unknown label 1");
+        assert_eq!(message(&module(&[], &[0x20, 0, 0x1a, 0x0b])), "unknown local 0");
+    }
+
+    #[test]
+    fn accepts_generated_modules() {
+        let ir = crate::ir::parse::parse_module("
+extern @println(...) -> void
+
+fn @main() -> void {
+bb0:
+    %0: i32 = add 2, 3
+    call @println(%0)
+    ret
+}
+").unwrap();
+        let bytes = super::super::generate(&ir).unwrap().encode();
+        assert_eq!(validate(&bytes), Ok(()));
+    }
+}
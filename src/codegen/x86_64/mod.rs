@@ -0,0 +1,167 @@
+mod regalloc;
+mod select;
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    codegen::check,
+    common::diagnostics::Diagnostic,
+    ir::{display_name, ssa, Const, Global, Module, StructDef, Ty}
+};
+
+use self::select::{print_value, Selector};
+
+/// Routines every program needs, appended to the generated code so the result
+/// assembles and links on its own.
+const RUNTIME: &str = include_str!("runtime.s");
+
+/// IR names like `Point::sum` and `.init` aren't all valid symbols, and a global could
+/// be called `rax`, so each kind of name gets a prefix.
+fn function_symbol(name: &str) -> String {
+    format!("fn.{}", name.replace("::", "."))
+}
+
+fn global_symbol(name: &str) -> String {
+    format!("global.{}", name)
+}
+
+/// The routine that prints an instance of the struct `name`.
+fn show_symbol(name: &str) -> String {
+    format!("show.{}", name)
+}
+
+/// The vtable of the struct `obj` for the trait `with`.
+fn vtable_symbol(obj: &str, with: &str) -> String {
+    format!("vtable.{}.{}", obj, with)
+}
+
+/// String constants, each placed in `.rodata` once.
+#[derive(Default)]
+struct Strings {
+    labels: HashMap<String, String>,
+    data: String
+}
+
+impl Strings {
+    fn label(&mut self, value: &str) -> String {
+        if let Some(label) = self.labels.get(value) {
+            return label.clone();
+        }
+        let label = format!(".Lstr.{}", self.labels.len());
+        let _ = writeln!(self.data, "{}:\n    .asciz \"{}\"", label, escape(value));
+        self.labels.insert(value.to_string(), label.clone());
+        label
+    }
+}
+
+/// Escapes a string for `.asciz`, writing anything but printable ASCII as octal.
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// Writes the routine printing an instance of `structure` the way the interpreter does,
+/// as `Point { x: 1, y: 2 }`.
+fn show(structure: &StructDef, strings: &mut Strings) -> String {
+    let mut lines = vec![
+        "push rbx".to_string(),
+        "mov rbx, rdi".to_string()
+    ];
+    let mut text = format!("{} {{ ", display_name(&structure.name));
+    for (idx, (name, ty)) in structure.fields.iter().enumerate() {
+        if idx > 0 {
+            text.push_str(", ");
+        }
+        text.push_str(&format!("{}: ", name));
+        lines.push(format!("lea rdi, [rip + {}]", strings.label(&text)));
+        lines.push("call __beta_print_str".to_string());
+        lines.push(format!("mov rdi, qword ptr [rbx + {}]", 8 * idx));
+        if *ty == Ty::Str {
+            lines.push("call __beta_print_str_debug".to_string());
+        } else {
+            print_value(&mut |line| lines.push(line), ty);
+        }
+        text.clear();
+    }
+    text.push_str(" }");
+    lines.push(format!("lea rdi, [rip + {}]", strings.label(&text)));
+    lines.push("call __beta_print_str".to_string());
+    lines.push("pop rbx".to_string());
+    lines.push("ret".to_string());
+
+    let mut out = format!("\n{}:\n", show_symbol(&structure.name));
+    for line in lines {
+        let _ = writeln!(out, "    {}", line);
+    }
+    out
+}
+
+fn global(global: &Global, strings: &mut Strings) -> String {
+    let value = match &global.init {
+        Some(Const::Int(value)) => (*value as i64).to_string(),
+        Some(Const::Float(value)) if global.ty.is_single() => (*value as f32).to_bits().to_string(),
+        Some(Const::Float(value)) => value.to_bits().to_string(),
+        Some(Const::Bool(value)) => (*value as u8).to_string(),
+        Some(Const::Str(value)) => strings.label(value),
+        None => "0".to_string()
+    };
+    format!("{}:\n    .quad {}\n", global_symbol(&global.name), value)
+}
+
+/// Generates GNU assembler text for x86-64 Linux. `_start` runs the module's `.init`,
+/// then `main`, and exits with status 0; runtime errors exit with the status the
+/// interpreter uses. A trait object points at its instance and a read-only vtable that
+/// starts with the instance's printer.
+pub(crate) fn generate(module: &Module) -> Result<String, Vec<Diagnostic>> {
+    check(module, "x86-64")?;
+    let mut module = module.clone();
+    for function in &mut module.functions {
+        ssa::destruct(function);
+    }
+
+    let mut strings = Strings::default();
+    let mut out = String::from("    .intel_syntax noprefix\n    .text\n    .globl _start\n\n_start:\n");
+    for entry in [Module::INIT, "main"] {
+        if module.function(entry).is_some() {
+            let _ = writeln!(out, "    call {}", function_symbol(entry));
+        }
+    }
+    out.push_str("    xor edi, edi\n    jmp __beta_exit\n");
+
+    for (index, function) in module.functions.iter().enumerate() {
+        out.push_str(&Selector::new(&module, function, index, &mut strings).function());
+    }
+    for structure in &module.structs {
+        out.push_str(&show(structure, &mut strings));
+    }
+    out.push_str(RUNTIME);
+
+    let mut data = String::new();
+    for item in &module.globals {
+        data.push_str(&global(item, &mut strings));
+    }
+    out.push_str("\n    .section .rodata\n");
+    out.push_str(&strings.data);
+    if !module.vtables.is_empty() {
+        out.push_str("    .balign 8\n");
+    }
+    for vtable in &module.vtables {
+        let entries: Vec<String> = std::iter::once(show_symbol(&vtable.obj))
+            .chain(vtable.functions.iter().map(|function| function_symbol(function)))
+            .collect();
+        let _ = writeln!(out, "{}:\n    .quad {}", vtable_symbol(&vtable.obj, &vtable.with), entries.join(", "));
+    }
+    out.push_str("\n    .data\n    .balign 8\n");
+    out.push_str(&data);
+    Ok(out)
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{Function, Reg};
+
+/// Registers values live in. They are all callee-saved, so values survive calls without
+/// being saved around them, and the caller-saved registers stay free as scratch space
+/// for instruction selection.
+pub(super) const ALLOCATABLE: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+
+/// Where a value lives for its whole lifetime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Loc {
+    /// An index into `ALLOCATABLE`.
+    Reg(usize),
+    /// A stack slot, counted from the top of the frame.
+    Slot(usize)
+}
+
+pub(super) struct Allocation {
+    pub locs: HashMap<Reg, Loc>,
+    pub slots: usize,
+    /// The allocatable registers in use, which the prologue saves.
+    pub used: Vec<usize>
+}
+
+/// The positions a register is live over, in the function's block order. One range per
+/// register, without holes: a register live anywhere in a loop is live across all of it.
+struct Interval {
+    reg: Reg,
+    start: usize,
+    end: usize
+}
+
+fn intervals(function: &Function) -> Vec<Interval> {
+    let count = function.blocks.len();
+    let index: HashMap<_, _> = function.blocks.iter().enumerate().map(|(idx, block)| (block.id, idx)).collect();
+    let succs: Vec<Vec<usize>> = function.blocks.iter()
+        .map(|block| block.term.successors().iter().filter_map(|succ| index.get(succ).copied()).collect())
+        .collect();
+
+    let mut uses = vec![HashSet::new(); count];
+    let mut defs = vec![HashSet::new(); count];
+    for (idx, block) in function.blocks.iter().enumerate() {
+        for inst in &block.insts {
+            for reg in inst.uses() {
+                if !defs[idx].contains(&reg) {
+                    uses[idx].insert(reg);
+                }
+            }
+            if let Some((reg, _)) = inst.def() {
+                defs[idx].insert(reg);
+            }
+        }
+        if let Some(reg) = block.term.operand().and_then(|operand| operand.reg()) {
+            if !defs[idx].contains(&reg) {
+                uses[idx].insert(reg);
+            }
+        }
+    }
+
+    let mut live_in: Vec<HashSet<Reg>> = uses.clone();
+    let mut live_out: Vec<HashSet<Reg>> = vec![HashSet::new(); count];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..count).rev() {
+            let out: HashSet<Reg> = succs[idx].iter().flat_map(|&succ| live_in[succ].iter().copied()).collect();
+            let mut live: HashSet<Reg> = out.iter().copied().filter(|reg| !defs[idx].contains(reg)).collect();
+            live.extend(uses[idx].iter().copied());
+            if live != live_in[idx] || out != live_out[idx] {
+                live_in[idx] = live;
+                live_out[idx] = out;
+                changed = true;
+            }
+        }
+    }
+
+    // Parameters arrive at position 0, before the first instruction.
+    let mut ranges: HashMap<Reg, (usize, usize)> = HashMap::new();
+    let mut extend = |reg: Reg, pos: usize| {
+        let range = ranges.entry(reg).or_insert((pos, pos));
+        range.0 = range.0.min(pos);
+        range.1 = range.1.max(pos);
+    };
+    for (reg, _) in &function.params {
+        extend(*reg, 0);
+    }
+    let mut pos = 1;
+    for (idx, block) in function.blocks.iter().enumerate() {
+        let start = pos;
+        for reg in &live_in[idx] {
+            extend(*reg, start);
+        }
+        for inst in &block.insts {
+            for reg in inst.uses() {
+                extend(reg, pos);
+            }
+            if let Some((reg, _)) = inst.def() {
+                extend(reg, pos);
+            }
+            pos += 1;
+        }
+        if let Some(reg) = block.term.operand().and_then(|operand| operand.reg()) {
+            extend(reg, pos);
+        }
+        for reg in &live_out[idx] {
+            extend(*reg, pos);
+        }
+        pos += 1;
+    }
+
+    let mut intervals: Vec<Interval> = ranges.into_iter().map(|(reg, (start, end))| Interval { reg, start, end }).collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.reg));
+    intervals
+}
+
+/// Linear-scan register allocation (Poletto and Sarkar). When every register is taken,
+/// whichever of the live values ends last goes to the stack.
+pub(super) fn allocate(function: &Function) -> Allocation {
+    let mut locs = HashMap::new();
+    let mut slots = 0;
+    let mut used = Vec::new();
+    let mut free: Vec<usize> = (0..ALLOCATABLE.len()).rev().collect();
+    // (end, register, allocatable index) for values currently in registers.
+    let mut active: Vec<(usize, Reg, usize)> = Vec::new();
+
+    for interval in intervals(function) {
+        active.retain(|&(end, _, reg)| {
+            if end < interval.start {
+                free.push(reg);
+                false
+            } else {
+                true
+            }
+        });
+        free.sort_by(|a, b| b.cmp(a));
+
+        let loc = if let Some(reg) = free.pop() {
+            active.push((interval.end, interval.reg, reg));
+            Loc::Reg(reg)
+        } else {
+            let (position, &(end, spilled, reg)) = active.iter().enumerate()
+                .max_by_key(|(_, (end, ..))| *end)
+                .expect("no free register means every register is active");
+            if end > interval.end {
+                locs.insert(spilled, Loc::Slot(slots));
+                slots += 1;
+                active[position] = (interval.end, interval.reg, reg);
+                Loc::Reg(reg)
+            } else {
+                slots += 1;
+                Loc::Slot(slots - 1)
+            }
+        };
+        if let Loc::Reg(reg) = loc {
+            if !used.contains(&reg) {
+                used.push(reg);
+            }
+        }
+        locs.insert(interval.reg, loc);
+    }
+    used.sort();
+    Allocation { locs, slots, used }
+}
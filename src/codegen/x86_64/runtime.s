@@ -0,0 +1,463 @@
+
+# Runtime support for programs built by the x86-64 backend. It talks to Linux through
+# system calls only, so a program links with nothing but `ld`. Every routine follows the
+# System V calling convention, but may assume the stack is unaligned.
+
+    .text
+
+# __beta_exit(edi: status)
+__beta_exit:
+    mov eax, 231
+    syscall
+
+# __beta_strlen(rdi: str) -> rax
+__beta_strlen:
+    xor eax, eax
+1:  cmp byte ptr [rdi + rax], 0
+    je 2f
+    inc rax
+    jmp 1b
+2:  ret
+
+# __beta_write(edi: fd, rsi: str), writing the whole NUL-terminated string
+__beta_write:
+    push rdi
+    push rsi
+    mov rdi, rsi
+    call __beta_strlen
+    mov rdx, rax
+    pop rsi
+    pop rdi
+    mov eax, 1
+    syscall
+    ret
+
+# __beta_print_str(rdi: str)
+__beta_print_str:
+    mov rsi, rdi
+    mov edi, 1
+    jmp __beta_write
+
+# __beta_print_char(dil: byte)
+__beta_print_char:
+    push rdi
+    mov rsi, rsp
+    mov edx, 1
+    mov edi, 1
+    mov eax, 1
+    syscall
+    pop rdi
+    ret
+
+# __beta_print_uint(rdi: value)
+__beta_print_uint:
+    mov rax, rdi
+    sub rsp, 32
+    lea rsi, [rsp + 32]
+    mov ecx, 10
+1:  xor edx, edx
+    div rcx
+    add dl, 48
+    dec rsi
+    mov byte ptr [rsi], dl
+    test rax, rax
+    jnz 1b
+    lea rdx, [rsp + 32]
+    sub rdx, rsi
+    mov edi, 1
+    mov eax, 1
+    syscall
+    add rsp, 32
+    ret
+
+# __beta_print_int(rdi: value)
+__beta_print_int:
+    test rdi, rdi
+    jns __beta_print_uint
+    push rdi
+    mov edi, 45
+    call __beta_print_char
+    pop rdi
+    # The most negative value negates to itself, which is right when read unsigned.
+    neg rdi
+    jmp __beta_print_uint
+
+# __beta_print_bool(dil: value)
+__beta_print_bool:
+    test dil, dil
+    lea rdi, [rip + .Lfalse]
+    lea rax, [rip + .Ltrue]
+    cmovnz rdi, rax
+    jmp __beta_print_str
+
+# __beta_print_zeros(rdi: count)
+__beta_print_zeros:
+    push rbx
+    mov rbx, rdi
+1:  test rbx, rbx
+    jz 2f
+    mov edi, 48
+    call __beta_print_char
+    dec rbx
+    jmp 1b
+2:  pop rbx
+    ret
+
+# __beta_print_str_debug(rdi: str) prints a string quoted, as it appears inside an instance.
+__beta_print_str_debug:
+    push rbx
+    mov rbx, rdi
+    mov edi, 34
+    call __beta_print_char
+1:  movzx edi, byte ptr [rbx]
+    test edi, edi
+    jz 4f
+    inc rbx
+    cmp edi, 34
+    je 2f
+    cmp edi, 92
+    je 2f
+    mov esi, 110
+    cmp edi, 10
+    je 3f
+    mov esi, 116
+    cmp edi, 9
+    je 3f
+    mov esi, 114
+    cmp edi, 13
+    je 3f
+    call __beta_print_char
+    jmp 1b
+2:  mov esi, edi
+3:  push rsi
+    mov edi, 92
+    call __beta_print_char
+    pop rdi
+    call __beta_print_char
+    jmp 1b
+4:  mov edi, 34
+    pop rbx
+    jmp __beta_print_char
+
+# __beta_print_float(xmm0: value, edi: 1 for single precision)
+#
+# Prints the fewest significant digits that read back as the same value, without an
+# exponent, like the interpreter. Reading back is checked with double arithmetic, which
+# can round differently from an exact conversion, so now and then a digit more than
+# necessary is printed. Values of 1e15 and up are scaled down first, and only their
+# leading digits are exact.
+__beta_print_float:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    sub rsp, 32
+    # [rsp]: digits to try, [rsp + 8]: most digits, [rsp + 16]: single precision
+    mov qword ptr [rsp], 1
+    mov qword ptr [rsp + 8], 17
+    mov qword ptr [rsp + 16], rdi
+    test edi, edi
+    jz 1f
+    mov qword ptr [rsp + 8], 9
+1:  ucomisd xmm0, xmm0
+    jp .Lfloat_nan
+    movq r14, xmm0
+    btr r14, 63
+    jnc 2f
+    mov edi, 45
+    call __beta_print_char
+2:  movq xmm0, r14
+    mov rax, 0x7ff0000000000000
+    cmp r14, rax
+    je .Lfloat_inf
+    # Scale values too large for an integer register, counting the zeros that takes off.
+    xor r13d, r13d
+    mov rax, 0x430c6bf526340000
+    movq xmm2, rax
+    mov rax, 0x4024000000000000
+    movq xmm3, rax
+3:  ucomisd xmm0, xmm2
+    jb 4f
+    divsd xmm0, xmm3
+    inc r13
+    jmp 3b
+4:  movsd xmm5, xmm0
+.Lfloat_try:
+    movsd xmm0, xmm5
+    cvttsd2si rbx, xmm0
+    cvtsi2sd xmm1, rbx
+    subsd xmm0, xmm1
+    # r15: digits left for the fraction once the integer part has used its share.
+    mov r15, qword ptr [rsp]
+    mov rax, rbx
+    mov ecx, 10
+5:  test rax, rax
+    jz 6f
+    xor edx, edx
+    div rcx
+    dec r15
+    jmp 5b
+    # r14: zeros after the point, which don't count as significant.
+6:  xor r14d, r14d
+    test rbx, rbx
+    jnz 8f
+    xorpd xmm1, xmm1
+    ucomisd xmm0, xmm1
+    je 8f
+    mov rax, 0x3fb999999999999a
+    movq xmm1, rax
+7:  ucomisd xmm0, xmm1
+    jae 8f
+    mulsd xmm0, xmm3
+    inc r14
+    jmp 7b
+8:  test r13, r13
+    jnz 9f
+    test r15, r15
+    jg 10f
+9:  xor r15d, r15d
+    # r12: the fraction as an integer of r15 digits, rounded.
+10: mov rax, 0x3ff0000000000000
+    movq xmm4, rax
+    mov rcx, r15
+11: test rcx, rcx
+    jz 12f
+    mulsd xmm4, xmm3
+    dec rcx
+    jmp 11b
+12: mulsd xmm0, xmm4
+    cvtsd2si r12, xmm0
+    cvttsd2si rax, xmm4
+    cmp r12, rax
+    jb 14f
+    # Rounding carried out of the fraction.
+    test r14, r14
+    jz 13f
+    dec r14
+    xor edx, edx
+    mov ecx, 10
+    div rcx
+    mov r12, rax
+    jmp 14f
+13: inc rbx
+    xor r12d, r12d
+    # Print these digits if they read back as the value, or if there is no more to try.
+14: test r13, r13
+    jnz .Lfloat_print
+    mov rax, qword ptr [rsp]
+    cmp rax, qword ptr [rsp + 8]
+    jae .Lfloat_print
+    mov rcx, r15
+    add rcx, r14
+    mov rax, 0x3ff0000000000000
+    movq xmm1, rax
+15: test rcx, rcx
+    jz 16f
+    mulsd xmm1, xmm3
+    dec rcx
+    jmp 15b
+16: cvtsi2sd xmm0, r12
+    divsd xmm0, xmm1
+    cvtsi2sd xmm2, rbx
+    addsd xmm0, xmm2
+    cmp qword ptr [rsp + 16], 0
+    je 17f
+    cvtsd2ss xmm0, xmm0
+    cvtsd2ss xmm1, xmm5
+    ucomiss xmm0, xmm1
+    je .Lfloat_print
+    jmp 18f
+17: ucomisd xmm0, xmm5
+    je .Lfloat_print
+18: inc qword ptr [rsp]
+    jmp .Lfloat_try
+.Lfloat_print:
+    mov rdi, rbx
+    call __beta_print_uint
+    mov rdi, r13
+    call __beta_print_zeros
+    test r12, r12
+    jz .Lfloat_done
+    mov ecx, 10
+19: mov rax, r12
+    xor edx, edx
+    div rcx
+    test rdx, rdx
+    jnz 20f
+    mov r12, rax
+    dec r15
+    jmp 19b
+20: mov edi, 46
+    call __beta_print_char
+    mov rdi, r14
+    call __beta_print_zeros
+    # Pad the fraction to r15 digits.
+    mov rax, r12
+    mov ecx, 10
+21: xor edx, edx
+    div rcx
+    dec r15
+    test rax, rax
+    jnz 21b
+    mov rdi, r15
+    call __beta_print_zeros
+    mov rdi, r12
+    call __beta_print_uint
+    jmp .Lfloat_done
+.Lfloat_nan:
+    lea rdi, [rip + .Lnan]
+    call __beta_print_str
+    jmp .Lfloat_done
+.Lfloat_inf:
+    lea rdi, [rip + .Linf]
+    call __beta_print_str
+.Lfloat_done:
+    add rsp, 32
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+# __beta_fmod(xmm0: a, xmm1: b) -> xmm0, the remainder truncated toward zero like C's fmod.
+__beta_fmod:
+    sub rsp, 16
+    movsd qword ptr [rsp], xmm1
+    fld qword ptr [rsp]
+    movsd qword ptr [rsp], xmm0
+    fld qword ptr [rsp]
+1:  fprem
+    fnstsw ax
+    test ah, 4
+    jnz 1b
+    fstp qword ptr [rsp]
+    fstp st(0)
+    movsd xmm0, qword ptr [rsp]
+    add rsp, 16
+    ret
+
+# __beta_alloc(rdi: size) -> rax. Memory comes from anonymous mappings and is never freed.
+__beta_alloc:
+    add rdi, 15
+    and rdi, -16
+    mov rax, qword ptr [rip + .Lheap_next]
+    lea rdx, [rax + rdi]
+    cmp rdx, qword ptr [rip + .Lheap_end]
+    ja 1f
+    mov qword ptr [rip + .Lheap_next], rdx
+    ret
+1:  push rdi
+    mov rsi, 1048576
+    cmp rdi, rsi
+    cmova rsi, rdi
+    push rsi
+    xor edi, edi
+    mov edx, 3
+    mov r10d, 0x22
+    mov r8, -1
+    xor r9d, r9d
+    mov eax, 9
+    syscall
+    pop rsi
+    pop rdi
+    cmp rax, -4096
+    ja 2f
+    lea rdx, [rax + rdi]
+    mov qword ptr [rip + .Lheap_next], rdx
+    add rsi, rax
+    mov qword ptr [rip + .Lheap_end], rsi
+    ret
+2:  lea rdi, [rip + .Lout_of_memory]
+    jmp __beta_trap
+
+# __beta_str_concat(rdi: a, rsi: b) -> rax, a new string
+__beta_str_concat:
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov rbx, rdi
+    mov r12, rsi
+    call __beta_strlen
+    mov r13, rax
+    mov rdi, r12
+    call __beta_strlen
+    mov r14, rax
+    lea rdi, [r13 + r14 + 1]
+    call __beta_alloc
+    mov r15, rax
+    mov rdi, rax
+    mov rsi, rbx
+    mov rcx, r13
+    rep movsb
+    mov rsi, r12
+    mov rcx, r14
+    rep movsb
+    mov byte ptr [rdi], 0
+    mov rax, r15
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+# __beta_str_cmp(rdi: a, rsi: b) -> rax: -1, 0 or 1, comparing bytes
+__beta_str_cmp:
+1:  movzx eax, byte ptr [rdi]
+    movzx ecx, byte ptr [rsi]
+    cmp eax, ecx
+    jne 2f
+    test eax, eax
+    jz 3f
+    inc rdi
+    inc rsi
+    jmp 1b
+2:  mov eax, 1
+    mov rdx, -1
+    cmovb rax, rdx
+    ret
+3:  xor eax, eax
+    ret
+
+# __beta_trap(rdi: message) reports a runtime error and exits with the status the
+# interpreter uses for one.
+__beta_trap:
+    mov rbx, rdi
+    lea rsi, [rip + .Lerror]
+    mov edi, 2
+    call __beta_write
+    mov rsi, rbx
+    mov edi, 2
+    call __beta_write
+    lea rsi, [rip + .Lnewline]
+    mov edi, 2
+    call __beta_write
+    mov edi, 5
+    jmp __beta_exit
+
+    .section .rodata
+.Ltrue:
+    .asciz "true"
+.Lfalse:
+    .asciz "false"
+.Lnan:
+    .asciz "NaN"
+.Linf:
+    .asciz "inf"
+.Lerror:
+    .asciz "error: "
+.Lnewline:
+    .asciz "\n"
+.Lout_of_memory:
+    .asciz "out of memory"
+
+    .data
+    .balign 8
+.Lheap_next:
+    .quad 0
+.Lheap_end:
+    .quad 0
@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+
+use crate::{
+    analysis::symbols::Builtin,
+    codegen::{operand_type, type_of},
+    ir::{BinaryOp, BlockId, Const, Function, Inst, Module, Operand, Reg, Terminator, Ty, UnaryOp}
+};
+
+use super::{
+    function_symbol, global_symbol,
+    regalloc::{allocate, Allocation, Loc, ALLOCATABLE},
+    show_symbol, vtable_symbol, Strings
+};
+
+const INT_ARGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const FLOAT_ARGS: usize = 8;
+
+/// The low 32 bits of a 64-bit register.
+fn low32(reg: &str) -> String {
+    match reg {
+        "rax" | "rbx" | "rcx" | "rdx" | "rsi" | "rdi" => format!("e{}", &reg[1..]),
+        _ => format!("{}d", reg)
+    }
+}
+
+/// Instruction selection for one function, working straight from the IR: each
+/// instruction reads its operands into scratch registers, computes, and writes the result
+/// to wherever the register allocator put its destination.
+///
+/// Integers are kept sign- or zero-extended to 64 bits, so a result that doesn't
+/// survive being truncated to its width and extended again overflowed. Floats are kept
+/// in general-purpose registers as their bit pattern between operations.
+pub(super) struct Selector<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    alloc: Allocation,
+    types: HashMap<Reg, Ty>,
+    strings: &'m mut Strings,
+    /// Keeps the local labels of different functions apart.
+    index: usize,
+    /// Messages of the runtime errors the function can raise, by label number.
+    traps: Vec<String>,
+    labels: usize,
+    out: String
+}
+
+impl<'m> Selector<'m> {
+
+    pub fn new(module: &'m Module, function: &'m Function, index: usize, strings: &'m mut Strings) -> Self {
+        let mut types: HashMap<Reg, Ty> = function.params.iter().cloned().collect();
+        for (reg, ty) in function.blocks.iter().flat_map(|block| &block.insts).filter_map(Inst::def) {
+            types.insert(reg, ty.clone());
+        }
+        Self {
+            module,
+            function,
+            alloc: allocate(function),
+            types,
+            strings,
+            index,
+            traps: Vec::new(),
+            labels: 0,
+            out: String::new()
+        }
+    }
+
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&mut self, label: &str) {
+        self.out.push_str(label);
+        self.out.push_str(":\n");
+    }
+
+    fn block_label(&self, block: BlockId) -> String {
+        format!(".L{}.{}", self.index, block)
+    }
+
+    fn fresh_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}.{}", self.index, self.labels)
+    }
+
+    /// The label of a stub that raises `message` as a runtime error.
+    fn trap(&mut self, message: String) -> String {
+        let position = match self.traps.iter().position(|existing| *existing == message) {
+            Some(position) => position,
+            None => {
+                self.traps.push(message);
+                self.traps.len() - 1
+            }
+        };
+        format!(".L{}.trap{}", self.index, position)
+    }
+
+    fn overflow(&mut self, verb: &str) -> String {
+        self.trap(format!("attempt to {} with overflow", verb))
+    }
+
+    fn loc(&self, reg: Reg) -> String {
+        match self.alloc.locs.get(&reg) {
+            Some(Loc::Reg(idx)) => ALLOCATABLE[*idx].to_string(),
+            Some(Loc::Slot(slot)) => format!("qword ptr [rbp - {}]", 8 * (self.alloc.used.len() + 1 + slot)),
+            None => unreachable!("every register gets a location")
+        }
+    }
+
+    /// Moves `operand`, read as a `ty`, into the scratch register `target`.
+    fn load(&mut self, operand: &Operand, ty: &Ty, target: &str) {
+        match operand {
+            Operand::Reg(reg) => {
+                let loc = self.loc(*reg);
+                if loc != target {
+                    self.emit(format!("mov {}, {}", target, loc));
+                }
+            },
+            Operand::Const(Const::Int(value)) => self.emit(format!("mov {}, {}", target, *value as i64)),
+            Operand::Const(Const::Float(value)) if ty.is_single() => {
+                self.emit(format!("mov {}, {:#x}", low32(target), (*value as f32).to_bits()));
+            },
+            Operand::Const(Const::Float(value)) => self.emit(format!("mov {}, {:#x}", target, value.to_bits())),
+            Operand::Const(Const::Bool(value)) => self.emit(format!("mov {}, {}", low32(target), *value as u8)),
+            Operand::Const(Const::Str(value)) => {
+                let label = self.strings.label(value);
+                self.emit(format!("lea {}, [rip + {}]", target, label));
+            }
+        }
+    }
+
+    fn store(&mut self, dst: Reg, from: &str) {
+        let loc = self.loc(dst);
+        if loc != from {
+            self.emit(format!("mov {}, {}", loc, from));
+        }
+    }
+
+    fn move_to_xmm(&mut self, from: &str, xmm: &str, single: bool) {
+        if single {
+            self.emit(format!("movd {}, {}", xmm, low32(from)));
+        } else {
+            self.emit(format!("movq {}, {}", xmm, from));
+        }
+    }
+
+    fn move_from_xmm(&mut self, xmm: &str, to: &str, single: bool) {
+        if single {
+            self.emit(format!("movd {}, {}", low32(to), xmm));
+        } else {
+            self.emit(format!("movq {}, {}", to, xmm));
+        }
+    }
+
+    /// Traps unless `rax` holds a value of the narrow integer type `ty`.
+    fn check_range(&mut self, ty: &Ty, verb: &str) {
+        let extend = match (ty.bits(), ty.is_signed()) {
+            (Some(8), true) => "movsx rdx, al",
+            (Some(16), true) => "movsx rdx, ax",
+            (Some(32), true) => "movsxd rdx, eax",
+            (Some(8), false) => "movzx edx, al",
+            (Some(16), false) => "movzx edx, ax",
+            (Some(32), false) => "mov edx, eax",
+            _ => return
+        };
+        let trap = self.overflow(verb);
+        self.emit(extend);
+        self.emit("cmp rdx, rax");
+        self.emit(format!("jne {}", trap));
+    }
+
+    /// Emits the function: prologue, blocks, a shared epilogue, then the trap stubs.
+    pub fn function(mut self) -> String {
+        let symbol = function_symbol(&self.function.name);
+        self.out.push('\n');
+        self.label(&symbol);
+
+        self.emit("push rbp");
+        self.emit("mov rbp, rsp");
+        for &reg in &self.alloc.used.clone() {
+            self.emit(format!("push {}", ALLOCATABLE[reg]));
+        }
+        // The return address and `rbp` leave the stack aligned; keep it that way.
+        let mut frame = 8 * self.alloc.slots;
+        if (self.alloc.used.len() + self.alloc.slots) % 2 == 1 {
+            frame += 8;
+        }
+        if frame > 0 {
+            self.emit(format!("sub rsp, {}", frame));
+        }
+        let (mut ints, mut floats, mut stack) = (0, 0, 0);
+        for (reg, ty) in &self.function.params {
+            if ty.is_float() && floats < FLOAT_ARGS {
+                self.move_from_xmm(&format!("xmm{}", floats), "rax", ty.is_single());
+                self.store(*reg, "rax");
+                floats += 1;
+            } else if !ty.is_float() && ints < INT_ARGS.len() {
+                self.store(*reg, INT_ARGS[ints]);
+                ints += 1;
+            } else {
+                self.emit(format!("mov rax, qword ptr [rbp + {}]", 16 + 8 * stack));
+                self.store(*reg, "rax");
+                stack += 1;
+            }
+        }
+
+        let blocks = &self.function.blocks;
+        for (idx, block) in blocks.iter().enumerate() {
+            let label = self.block_label(block.id);
+            self.label(&label);
+            for inst in &block.insts {
+                self.inst(inst);
+            }
+            let next = blocks.get(idx + 1).map(|block| block.id);
+            self.terminator(&block.term, next);
+        }
+
+        let ret = format!(".L{}.ret", self.index);
+        self.label(&ret);
+        self.emit(format!("lea rsp, [rbp - {}]", 8 * self.alloc.used.len()));
+        for &reg in self.alloc.used.clone().iter().rev() {
+            self.emit(format!("pop {}", ALLOCATABLE[reg]));
+        }
+        self.emit("pop rbp");
+        self.emit("ret");
+
+        for (position, message) in std::mem::take(&mut self.traps).into_iter().enumerate() {
+            let label = self.strings.label(&message);
+            self.label(&format!(".L{}.trap{}", self.index, position));
+            self.emit(format!("lea rdi, [rip + {}]", label));
+            self.emit("call __beta_trap");
+        }
+        self.out
+    }
+
+    fn terminator(&mut self, term: &Terminator, next: Option<BlockId>) {
+        match term {
+            Terminator::Ret(value) => {
+                if let Some(value) = value {
+                    let ty = self.function.ret.clone();
+                    self.load(value, &ty, "rax");
+                    if ty.is_float() {
+                        self.move_to_xmm("rax", "xmm0", ty.is_single());
+                    }
+                }
+                if next.is_some() {
+                    self.emit(format!("jmp .L{}.ret", self.index));
+                }
+            },
+            Terminator::Jump(target) => self.jump(*target, next),
+            Terminator::Branch { cond: Operand::Const(Const::Bool(cond)), then, els } => {
+                self.jump(if *cond { *then } else { *els }, next);
+            },
+            Terminator::Branch { cond, then, els } => {
+                match cond {
+                    Operand::Reg(reg) => match self.alloc.locs.get(reg) {
+                        Some(Loc::Reg(_)) => {
+                            let loc = self.loc(*reg);
+                            self.emit(format!("test {}, {}", loc, loc));
+                        },
+                        _ => {
+                            let loc = self.loc(*reg);
+                            self.emit(format!("cmp {}, 0", loc));
+                        }
+                    },
+                    constant => {
+                        self.load(constant, &Ty::Bool, "rax");
+                        self.emit("test rax, rax");
+                    }
+                }
+                if next == Some(*then) {
+                    let els = self.block_label(*els);
+                    self.emit(format!("je {}", els));
+                } else {
+                    let then = self.block_label(*then);
+                    self.emit(format!("jne {}", then));
+                    self.jump(*els, next);
+                }
+            },
+            Terminator::Unreachable => self.emit("ud2")
+        }
+    }
+
+    fn jump(&mut self, target: BlockId, next: Option<BlockId>) {
+        if next != Some(target) {
+            let label = self.block_label(target);
+            self.emit(format!("jmp {}", label));
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dst, ty, src } => {
+                self.load(src, ty, "rax");
+                self.store(*dst, "rax");
+            },
+            Inst::Binary { dst, ty, op, lhs, rhs } => {
+                let operand_ty = operand_type(&self.types, *op, ty, lhs, rhs);
+                self.load(lhs, &operand_ty, "rax");
+                self.load(rhs, &operand_ty, "rcx");
+                if op.is_comparison() {
+                    self.compare(*op, &operand_ty);
+                } else if operand_ty.is_integer() {
+                    self.int_arith(*op, &operand_ty);
+                } else if operand_ty.is_float() {
+                    self.float_arith(*op, operand_ty.is_single());
+                } else {
+                    // Only strings are left, and they only add.
+                    self.emit("mov rdi, rax");
+                    self.emit("mov rsi, rcx");
+                    self.emit("call __beta_str_concat");
+                }
+                self.store(*dst, "rax");
+            },
+            Inst::Unary { dst, ty, op, src } => {
+                self.load(src, ty, "rax");
+                self.unary(*op, ty);
+                self.store(*dst, "rax");
+            },
+            Inst::Call { dst, func, args } => self.call(dst.as_ref(), func, args),
+            Inst::Load { dst, global, .. } => {
+                self.emit(format!("mov rax, qword ptr [rip + {}]", global_symbol(global)));
+                self.store(*dst, "rax");
+            },
+            Inst::Store { global, src } => {
+                let ty = self.module.global(global).map_or(Ty::I64, |global| global.ty.clone());
+                self.load(src, &ty, "rax");
+                self.emit(format!("mov qword ptr [rip + {}], rax", global_symbol(global)));
+            },
+            Inst::New { dst, ty, args } => {
+                let fields = match ty {
+                    Ty::Struct(name) => self.module.structure(name).map(|def| def.fields.clone()).unwrap_or_default(),
+                    _ => Vec::new()
+                };
+                self.emit(format!("mov edi, {}", 8 * args.len().max(1)));
+                self.emit("call __beta_alloc");
+                for (idx, arg) in args.iter().enumerate() {
+                    let ty = fields.get(idx).map_or(Ty::I64, |(_, ty)| ty.clone());
+                    self.load(arg, &ty, "rcx");
+                    self.emit(format!("mov qword ptr [rax + {}], rcx", 8 * idx));
+                }
+                self.store(*dst, "rax");
+            },
+            Inst::Dyn { dst, ty, concrete, src } => {
+                let Ty::Dyn(with) = ty else {
+                    unreachable!("trait objects have a trait object type");
+                };
+                self.emit("mov edi, 16");
+                self.emit("call __beta_alloc");
+                self.load(&Operand::Reg(*src), &Ty::I64, "rcx");
+                self.emit("mov qword ptr [rax], rcx");
+                self.emit(format!("lea rcx, [rip + {}]", vtable_symbol(concrete, with)));
+                self.emit("mov qword ptr [rax + 8], rcx");
+                self.store(*dst, "rax");
+            },
+            Inst::CallDyn { dst, recv, method, args } => self.call_dyn(dst.as_ref(), *recv, method, args),
+            Inst::GetField { dst, obj, field, .. } => {
+                let offset = self.field_offset(*obj, field);
+                self.load(&Operand::Reg(*obj), &Ty::I64, "rax");
+                self.emit(format!("mov rax, qword ptr [rax + {}]", offset));
+                self.store(*dst, "rax");
+            },
+            Inst::SetField { obj, field, src } => {
+                let offset = self.field_offset(*obj, field);
+                let ty = self.field_type(*obj, field);
+                self.load(&Operand::Reg(*obj), &Ty::I64, "rax");
+                self.load(src, &ty, "rcx");
+                self.emit(format!("mov qword ptr [rax + {}], rcx", offset));
+            },
+            Inst::Phi { .. } => unreachable!("phis are removed before instruction selection")
+        }
+    }
+
+    fn field_offset(&self, obj: Reg, field: &str) -> usize {
+        let Some(Ty::Struct(name)) = self.types.get(&obj) else {
+            return 0;
+        };
+        self.module.structure(name)
+            .and_then(|def| def.fields.iter().position(|(name, _)| name == field))
+            .map_or(0, |idx| 8 * idx)
+    }
+
+    fn field_type(&self, obj: Reg, field: &str) -> Ty {
+        match self.types.get(&obj) {
+            Some(Ty::Struct(name)) => self.module.structure(name).and_then(|def| def.field(field)).cloned().unwrap_or(Ty::I64),
+            _ => Ty::I64
+        }
+    }
+
+    /// `rax op rcx` into `rax` for integers, trapping like the interpreter does.
+    fn int_arith(&mut self, op: BinaryOp, ty: &Ty) {
+        let wide = ty.bits() == Some(64);
+        let signed = ty.is_signed();
+        match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                self.emit(if op == BinaryOp::Add { "add rax, rcx" } else { "sub rax, rcx" });
+                if wide {
+                    let trap = self.overflow(op.verb());
+                    self.emit(format!("{} {}", if signed { "jo" } else { "jc" }, trap));
+                } else {
+                    self.check_range(ty, op.verb());
+                }
+            },
+            BinaryOp::Mul if wide && !signed => {
+                let trap = self.overflow(op.verb());
+                self.emit("mul rcx");
+                self.emit(format!("jc {}", trap));
+            },
+            BinaryOp::Mul => {
+                self.emit("imul rax, rcx");
+                if wide {
+                    let trap = self.overflow(op.verb());
+                    self.emit(format!("jo {}", trap));
+                } else {
+                    self.check_range(ty, op.verb());
+                }
+            },
+            BinaryOp::Div | BinaryOp::Rem => {
+                let zero = if op == BinaryOp::Div {
+                    self.trap("attempt to divide by zero".to_string())
+                } else {
+                    self.trap("attempt to calculate the remainder with a divisor of zero".to_string())
+                };
+                self.emit("test rcx, rcx");
+                self.emit(format!("jz {}", zero));
+                if signed {
+                    if wide {
+                        // The most negative value divided by -1 doesn't fit, and `idiv` faults on it.
+                        let trap = self.overflow(op.verb());
+                        let ok = self.fresh_label();
+                        self.emit("cmp rcx, -1");
+                        self.emit(format!("jne {}", ok));
+                        self.emit("mov rdx, 0x8000000000000000");
+                        self.emit("cmp rax, rdx");
+                        self.emit(format!("je {}", trap));
+                        self.label(&ok);
+                    }
+                    self.emit("cqo");
+                    self.emit("idiv rcx");
+                } else {
+                    self.emit("xor edx, edx");
+                    self.emit("div rcx");
+                }
+                if op == BinaryOp::Rem {
+                    self.emit("mov rax, rdx");
+                } else if signed && !wide {
+                    self.check_range(ty, op.verb());
+                }
+            },
+            _ => unreachable!("comparisons are selected separately")
+        }
+    }
+
+    fn float_arith(&mut self, op: BinaryOp, single: bool) {
+        self.move_to_xmm("rax", "xmm0", single);
+        self.move_to_xmm("rcx", "xmm1", single);
+        let suffix = if single { "ss" } else { "sd" };
+        match op {
+            BinaryOp::Add => self.emit(format!("add{} xmm0, xmm1", suffix)),
+            BinaryOp::Sub => self.emit(format!("sub{} xmm0, xmm1", suffix)),
+            BinaryOp::Mul => self.emit(format!("mul{} xmm0, xmm1", suffix)),
+            BinaryOp::Div => self.emit(format!("div{} xmm0, xmm1", suffix)),
+            BinaryOp::Rem => {
+                if single {
+                    self.emit("cvtss2sd xmm0, xmm0");
+                    self.emit("cvtss2sd xmm1, xmm1");
+                }
+                self.emit("call __beta_fmod");
+                if single {
+                    self.emit("cvtsd2ss xmm0, xmm0");
+                }
+            },
+            _ => unreachable!("comparisons are selected separately")
+        }
+        self.move_from_xmm("xmm0", "rax", single);
+    }
+
+    /// Compares `rax` with `rcx` as `ty`, leaving 0 or 1 in `rax`.
+    fn compare(&mut self, op: BinaryOp, ty: &Ty) {
+        if ty.is_float() {
+            let single = ty.is_single();
+            self.move_to_xmm("rax", "xmm0", single);
+            self.move_to_xmm("rcx", "xmm1", single);
+            let ucomi = if single { "ucomiss" } else { "ucomisd" };
+            // An unordered comparison, with NaN, sets the parity flag.
+            match op {
+                BinaryOp::Eq => {
+                    self.emit(format!("{} xmm0, xmm1", ucomi));
+                    self.emit("sete al");
+                    self.emit("setnp cl");
+                    self.emit("and al, cl");
+                },
+                BinaryOp::Ne => {
+                    self.emit(format!("{} xmm0, xmm1", ucomi));
+                    self.emit("setne al");
+                    self.emit("setp cl");
+                    self.emit("or al, cl");
+                },
+                BinaryOp::Gt | BinaryOp::Ge => {
+                    self.emit(format!("{} xmm0, xmm1", ucomi));
+                    self.emit(if op == BinaryOp::Gt { "seta al" } else { "setae al" });
+                },
+                _ => {
+                    self.emit(format!("{} xmm1, xmm0", ucomi));
+                    self.emit(if op == BinaryOp::Lt { "seta al" } else { "setae al" });
+                }
+            }
+        } else {
+            let signed = if *ty == Ty::Str {
+                self.emit("mov rdi, rax");
+                self.emit("mov rsi, rcx");
+                self.emit("call __beta_str_cmp");
+                self.emit("cmp rax, 0");
+                true
+            } else {
+                self.emit("cmp rax, rcx");
+                ty.is_signed()
+            };
+            let set = match (op, signed) {
+                (BinaryOp::Eq, _) => "sete",
+                (BinaryOp::Ne, _) => "setne",
+                (BinaryOp::Lt, true) => "setl",
+                (BinaryOp::Le, true) => "setle",
+                (BinaryOp::Gt, true) => "setg",
+                (BinaryOp::Ge, true) => "setge",
+                (BinaryOp::Lt, false) => "setb",
+                (BinaryOp::Le, false) => "setbe",
+                (BinaryOp::Gt, false) => "seta",
+                (BinaryOp::Ge, false) => "setae",
+                _ => unreachable!("arithmetic is selected separately")
+            };
+            self.emit(format!("{} al", set));
+        }
+        self.emit("movzx eax, al");
+    }
+
+    fn unary(&mut self, op: UnaryOp, ty: &Ty) {
+        match op {
+            UnaryOp::Neg if ty.is_float() => {
+                self.emit(format!("btc rax, {}", if ty.is_single() { 31 } else { 63 }));
+            },
+            UnaryOp::Neg if ty.is_signed() => {
+                self.emit("neg rax");
+                if ty.bits() == Some(64) {
+                    let trap = self.overflow("negate");
+                    self.emit(format!("jo {}", trap));
+                } else {
+                    self.check_range(ty, "negate");
+                }
+            },
+            UnaryOp::Neg => {
+                // Only zero negates to an unsigned value.
+                let trap = self.overflow("negate");
+                self.emit("test rax, rax");
+                self.emit(format!("jnz {}", trap));
+            },
+            UnaryOp::Not => self.emit("xor eax, 1"),
+            UnaryOp::BitNot => {
+                self.emit("not rax");
+                if !ty.is_signed() {
+                    match ty.bits() {
+                        Some(8) => self.emit("movzx eax, al"),
+                        Some(16) => self.emit("movzx eax, ax"),
+                        Some(32) => self.emit("mov eax, eax"),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, dst: Option<&(Reg, Ty)>, func: &str, args: &[Operand]) {
+        if let Some(builtin) = Builtin::ALL.into_iter().find(|builtin| builtin.name() == func) {
+            return self.print(builtin, args);
+        }
+        let Some(callee) = self.module.function(func) else {
+            unreachable!("calls to unknown functions are rejected before selection");
+        };
+        let params: Vec<Ty> = callee.params.iter().map(|(_, ty)| ty.clone()).collect();
+        let pushed = self.pass(args, &params, 0);
+        self.emit(format!("call {}", function_symbol(func)));
+        self.returned(dst, pushed);
+    }
+
+    /// Calls the function in the receiver's vtable for `method`, with the instance in
+    /// `rdi` as `self`; a vtable starts with the instance's printer.
+    fn call_dyn(&mut self, dst: Option<&(Reg, Ty)>, recv: Reg, method: &str, args: &[Operand]) {
+        let Some(Ty::Dyn(with)) = self.types.get(&recv) else {
+            unreachable!("dynamic calls go through trait objects");
+        };
+        let module = self.module;
+        let def = module.trait_def(with).expect("trait object types are declared");
+        let (slot, signature) = def.slot(method).expect("dynamic calls name a slot");
+        let pushed = self.pass(args, &signature.params, 1);
+        // Trait objects live in callee-saved registers or stack slots, never in the
+        // argument registers just filled.
+        self.load(&Operand::Reg(recv), &Ty::I64, "rax");
+        self.emit("mov rdi, qword ptr [rax]");
+        self.emit("mov rax, qword ptr [rax + 8]");
+        self.emit(format!("call qword ptr [rax + {}]", 8 * (slot + 1)));
+        self.returned(dst, pushed);
+    }
+
+    /// Passes `args` as `params` the System V way: integers and pointers in six
+    /// registers, after the first `taken`, floats in eight, the rest on the stack in
+    /// order. Returns how many bytes were pushed.
+    fn pass(&mut self, args: &[Operand], params: &[Ty], taken: usize) -> usize {
+        let (mut ints, mut floats) = (Vec::new(), Vec::new());
+        let mut stack = Vec::new();
+        for (arg, ty) in args.iter().zip(params) {
+            if ty.is_float() && floats.len() < FLOAT_ARGS {
+                floats.push((arg, ty.clone()));
+            } else if !ty.is_float() && taken + ints.len() < INT_ARGS.len() {
+                ints.push((arg, ty.clone()));
+            } else {
+                stack.push((arg, ty.clone()));
+            }
+        }
+        let padding = if stack.len() % 2 == 1 { 8 } else { 0 };
+        if padding > 0 {
+            self.emit("sub rsp, 8");
+        }
+        for (arg, ty) in stack.iter().rev() {
+            self.load(arg, ty, "rax");
+            self.emit("push rax");
+        }
+        for (idx, (arg, ty)) in floats.iter().enumerate() {
+            self.load(arg, ty, "rax");
+            self.move_to_xmm("rax", &format!("xmm{}", idx), ty.is_single());
+        }
+        for ((arg, ty), reg) in ints.iter().zip(&INT_ARGS[taken..]) {
+            self.load(arg, ty, reg);
+        }
+        8 * stack.len() + padding
+    }
+
+    /// Pops what `pass` pushed and stores the result of the call.
+    fn returned(&mut self, dst: Option<&(Reg, Ty)>, pushed: usize) {
+        if pushed > 0 {
+            self.emit(format!("add rsp, {}", pushed));
+        }
+        if let Some((dst, ty)) = dst {
+            if ty.is_float() {
+                self.move_from_xmm("xmm0", "rax", ty.is_single());
+            }
+            self.store(*dst, "rax");
+        }
+    }
+
+    /// `print` and `println` write their arguments separated by spaces, each according
+    /// to its type.
+    fn print(&mut self, builtin: Builtin, args: &[Operand]) {
+        for (idx, arg) in args.iter().enumerate() {
+            if idx > 0 {
+                self.emit("mov edi, 32");
+                self.emit("call __beta_print_char");
+            }
+            let ty = type_of(&self.types, arg);
+            self.load(arg, &ty, "rdi");
+            self.print_value(&ty);
+        }
+        if builtin == Builtin::Println {
+            self.emit("mov edi, 10");
+            self.emit("call __beta_print_char");
+        }
+    }
+
+    /// Prints the value of type `ty` in `rdi`.
+    fn print_value(&mut self, ty: &Ty) {
+        print_value(&mut |line| self.emit(line), ty);
+    }
+}
+
+/// Prints the value of type `ty` in `rdi`; shared with the instance printers.
+pub(super) fn print_value(emit: &mut dyn FnMut(String), ty: &Ty) {
+    match ty {
+        Ty::Str => emit("call __beta_print_str".into()),
+        Ty::Bool => emit("call __beta_print_bool".into()),
+        Ty::Struct(name) => emit(format!("call {}", show_symbol(name))),
+        Ty::Dyn(_) => {
+            emit("mov rax, qword ptr [rdi + 8]".into());
+            emit("mov rdi, qword ptr [rdi]".into());
+            emit("call qword ptr [rax]".into());
+        },
+        ty if ty.is_float() => {
+            if ty.is_single() {
+                emit("movd xmm0, edi".into());
+                emit("cvtss2sd xmm0, xmm0".into());
+                emit("mov edi, 1".into());
+            } else {
+                emit("movq xmm0, rdi".into());
+                emit("xor edi, edi".into());
+            }
+            emit("call __beta_print_float".into());
+        },
+        ty if ty.is_signed() => emit("call __beta_print_int".into()),
+        _ => emit("call __beta_print_uint".into())
+    }
+}
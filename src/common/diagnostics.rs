@@ -0,0 +1,354 @@
+use std::{error::Error, fmt::{Display, Write}};
+
+use crate::preprocessor::LineMap;
+
+use super::lexer::Span;
+
+/// Stable error codes, grouped by the stage that reports them.
+pub(crate) mod codes {
+    // Lexing
+    pub const UNEXPECTED_CHAR: &str = "E0001";
+    pub const UNTERMINATED_STRING: &str = "E0002";
+    pub const UNTERMINATED_COMMENT: &str = "E0003";
+    pub const INVALID_ESCAPE: &str = "E0004";
+    pub const INVALID_NUMBER: &str = "E0005";
+    pub const INTEGER_OVERFLOW: &str = "E0006";
+
+    // Parsing
+    pub const EXPECTED_TOKEN: &str = "E0100";
+    pub const VOID_DECLARATION: &str = "E0101";
+    pub const INVALID_ASSIGN_TARGET: &str = "E0102";
+    pub const NOT_CONSTANT: &str = "E0103";
+    pub const UNKNOWN_OPERATOR: &str = "E0104";
+    pub const NOT_A_DECLARATION: &str = "E0105";
+
+    // Declarations
+    pub const LET_ABSENT: &str = "E0200";
+    pub const MISSING_NAME: &str = "E0201";
+    pub const MISSING_COLON: &str = "E0202";
+    pub const NO_SEMICOLON: &str = "E0203";
+    pub const NO_VALID_TYPE: &str = "E0204";
+
+    // Type checking
+    pub const MISMATCHED_TYPES: &str = "E0300";
+    pub const INVALID_BINARY_OPERANDS: &str = "E0301";
+    pub const INVALID_UNARY_OPERAND: &str = "E0302";
+    pub const WRONG_ARG_COUNT: &str = "E0303";
+    pub const UNDEFINED_VARIABLE: &str = "E0304";
+    pub const UNDEFINED_FUNCTION: &str = "E0305";
+    pub const UNKNOWN_TYPE: &str = "E0306";
+    pub const NO_SUCH_FIELD: &str = "E0307";
+    pub const NO_SUCH_METHOD: &str = "E0308";
+    pub const LITERAL_OUT_OF_RANGE: &str = "E0309";
+    pub const MISSING_RETURN_VALUE: &str = "E0310";
+    pub const NON_BOOLEAN_CONDITION: &str = "E0311";
+    pub const JUMP_OUTSIDE_LOOP: &str = "E0312";
+    pub const UNDEFINED_LABEL: &str = "E0313";
+    pub const RETURN_OUTSIDE_FUNCTION: &str = "E0314";
+
+    // Symbols
+    pub const REDEFINITION: &str = "E0320";
+
+    // Traits
+    pub const MISSING_TRAIT_METHOD: &str = "E0330";
+    pub const NOT_A_TRAIT_METHOD: &str = "E0331";
+    pub const SIGNATURE_MISMATCH: &str = "E0332";
+    pub const UNSATISFIED_SUPERTRAIT: &str = "E0333";
+    pub const CONFLICTING_IMPLEMENTATIONS: &str = "E0334";
+    pub const CYCLIC_SUPERTRAITS: &str = "E0335";
+    pub const AMBIGUOUS_METHOD: &str = "E0336";
+
+    // Generics
+    pub const UNSATISFIED_BOUND: &str = "E0340";
+    pub const CANNOT_INFER: &str = "E0341";
+    pub const WRONG_TYPE_ARG_COUNT: &str = "E0342";
+
+    // Runtime
+    pub const ARITHMETIC_OVERFLOW: &str = "E0400";
+    pub const DIVIDE_BY_ZERO: &str = "E0401";
+    pub const UNINITIALIZED: &str = "E0402";
+    pub const STACK_OVERFLOW: &str = "E0403";
+    pub const INVALID_OPERATION: &str = "E0404";
+
+    // Intermediate representation
+    pub const UNSUPPORTED_IN_IR: &str = "E0500";
+    pub const MALFORMED_IR: &str = "E0501";
+
+    // Code generation
+    pub const UNSUPPORTED_BY_TARGET: &str = "E0600";
+    pub const INVALID_WASM: &str = "E0601";
+    pub const INVALID_BYTECODE: &str = "E0602";
+
+    // Preprocessing
+    pub const UNKNOWN_DIRECTIVE: &str = "E0700";
+    pub const MALFORMED_DIRECTIVE: &str = "E0701";
+    pub const INCLUDE_NOT_FOUND: &str = "E0702";
+    pub const RECURSIVE_INCLUDE: &str = "E0703";
+    pub const UNBALANCED_CONDITIONAL: &str = "E0704";
+    pub const ERROR_DIRECTIVE: &str = "E0705";
+    pub const WRONG_MACRO_ARG_COUNT: &str = "E0706";
+    pub const INVALID_PASTE: &str = "E0707";
+    pub const UNTERMINATED_MACRO_CALL: &str = "E0708";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    Note,
+    Warning,
+    Error
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error"
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Label {
+    pub span: Span,
+    pub message: Option<String>,
+    pub primary: bool
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>
+}
+
+impl Diagnostic {
+
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new()
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: Some(message.into()).filter(|m| !m.is_empty()), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: Some(message.into()).filter(|m| !m.is_empty()), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+
+    /// Renders the diagnostic rustc-style, with caret-underlined snippets from `file`.
+    pub fn render(&self, file: &SourceFile<'_>) -> String {
+        let mut out = String::new();
+        let _ = write!(out, "{}", self);
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.line, !label.primary, label.span.col));
+
+        // Lines are numbered as they were written, which differs from the parsed source
+        // once it has been preprocessed.
+        let gutter = labels.iter()
+            .map(|label| file.locate(label.span.line).1.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut current = file.name();
+        if let Some(span) = self.primary_span().or(labels.first().map(|label| label.span)) {
+            let (name, line) = file.locate(span.line);
+            let _ = write!(out, "\n{}--> {}:{}:{}", pad, name, line, file.columns(span).0);
+            current = name;
+        }
+
+        let mut last_line = None;
+        for label in &labels {
+            let line = label.span.line;
+            let text = file.line(line).unwrap_or("");
+            if last_line != Some(line) {
+                let (name, written) = file.locate(line);
+                if name != current {
+                    let _ = write!(out, "\n{}::: {}:{}:{}", pad, name, written, file.columns(label.span).0);
+                    current = name;
+                } else if last_line.is_some_and(|prev| line > prev + 1) {
+                    let _ = write!(out, "\n{}...", pad);
+                } else {
+                    let _ = write!(out, "\n{} |", pad);
+                }
+                let _ = write!(out, "\n{:>width$} | {}", written, text.trim_end(), width = gutter);
+                last_line = Some(line);
+            }
+
+            // Spans that run past the end of their first line are underlined up to the line end.
+            let (start_col, span_chars) = file.columns(label.span);
+            let line_chars = text.trim_end().chars().count();
+            let width = span_chars.min(line_chars.saturating_sub(start_col - 1)).max(1);
+            let marker = if label.primary { "^" } else { "-" };
+
+            let _ = write!(out, "\n{} | {}{}", pad, " ".repeat(start_col - 1), marker.repeat(width));
+            if let Some(message) = &label.message {
+                let _ = write!(out, " {}", message);
+            }
+        }
+
+        if !labels.is_empty() && (!self.notes.is_empty() || !self.help.is_empty()) {
+            let _ = write!(out, "\n{} |", pad);
+        }
+        for note in &self.notes {
+            let _ = write!(out, "\n{} = note: {}", pad, note);
+        }
+        for help in &self.help {
+            let _ = write!(out, "\n{} = help: {}", pad, help);
+        }
+
+        out.push('\n');
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity.as_str(), code, self.message),
+            None => write!(f, "{}: {}", self.severity.as_str(), self.message)
+        }
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// A user source file, indexed by line so diagnostics can quote it. A preprocessed file
+/// carries the map back to the files its lines were written in.
+pub(crate) struct SourceFile<'a> {
+    name: String,
+    src: &'a str,
+    line_starts: Vec<usize>,
+    map: Option<&'a LineMap>
+}
+
+impl<'a> SourceFile<'a> {
+
+    pub fn new(name: impl Into<String>, src: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+
+        Self {
+            name: name.into(),
+            src,
+            line_starts,
+            map: None
+        }
+    }
+
+    pub fn with_line_map(mut self, map: &'a LineMap) -> Self {
+        self.map = Some(map);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The file the 1-based `line` was written in, and its line there.
+    pub fn locate(&self, line: usize) -> (&str, usize) {
+        self.map.and_then(|map| map.origin(line)).unwrap_or((&self.name, line))
+    }
+
+    /// The 1-based column `span` starts at and how many characters it covers on its first
+    /// line, as written. Anything that came out of a macro covers the whole invocation.
+    pub fn columns(&self, span: Span) -> (usize, usize) {
+        let width = self.src.get(span.start..span.end)
+            .map(|s| s.split('\n').next().unwrap_or("").chars().count())
+            .unwrap_or(span.len());
+        let start = span.col.max(1) - 1;
+        let cols = match self.map {
+            Some(map) => map.columns(span.line, start..start + width),
+            None => start..start + width
+        };
+        (cols.start + 1, cols.end - cols.start)
+    }
+
+    /// Notes on the macro expansions that `span` came out of, innermost first.
+    pub fn backtrace(&self, span: Span) -> Vec<String> {
+        self.map.map_or_else(Vec::new, |map| map.backtrace(span.line, span.col.max(1) - 1))
+    }
+
+    /// The text of the 1-based `line`, without its newline, as it was written.
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        if let Some(map) = self.map {
+            return map.text(line);
+        }
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self.line_starts.get(line).map(|next| next - 1).unwrap_or(self.src.len());
+        self.src.get(start..end)
+    }
+}
+
+/// Collects diagnostics from every stage of a compilation.
+#[derive(Default)]
+pub(crate) struct Diagnostics {
+    list: Vec<Diagnostic>
+}
+
+impl Diagnostics {
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.list.push(diagnostic);
+    }
+
+    /// Removes and returns everything collected so far.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.list)
+    }
+
+    /// Orders diagnostics by where they point, so output from several passes reads top to bottom.
+    pub fn sort_by_position(&mut self) {
+        self.list.sort_by_key(|diag| diag.primary_span().map(|span| span.start));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.list.iter()
+    }
+}
@@ -0,0 +1,776 @@
+use std::{error::Error, fmt::Display};
+
+use super::{diagnostics::{codes, Diagnostic}, syntax_tree::{BinOp, UniOp}, yarn::Yarn};
+
+/// A region of the source text, as byte offsets plus the 1-based line/column of `start`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self { start, end, line, col }
+    }
+
+    /// Smallest span covering both `self` and `other`, positioned at whichever starts first.
+    pub fn join(self, other: Span) -> Span {
+        let (first, _) = if self.start <= other.start { (self, other) } else { (other, self) };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            col: first.col
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Keyword {
+    Let,
+    Obj,
+    Comp,
+    Trait,
+    Extend,
+    With,
+    Defun,
+    True,
+    False,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    Return
+}
+
+impl Keyword {
+    pub fn from_ident(ident: &str) -> Option<Self> {
+        match ident {
+            "let" => Some(Self::Let),
+            "obj" => Some(Self::Obj),
+            "comp" => Some(Self::Comp),
+            "trait" => Some(Self::Trait),
+            "extend" => Some(Self::Extend),
+            "with" => Some(Self::With),
+            "defun" => Some(Self::Defun),
+            "true" => Some(Self::True),
+            "false" => Some(Self::False),
+            "if" => Some(Self::If),
+            "else" => Some(Self::Else),
+            "while" => Some(Self::While),
+            "for" => Some(Self::For),
+            "in" => Some(Self::In),
+            "loop" => Some(Self::Loop),
+            "break" => Some(Self::Break),
+            "continue" => Some(Self::Continue),
+            "return" => Some(Self::Return),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Let => "let",
+            Self::Obj => "obj",
+            Self::Comp => "comp",
+            Self::Trait => "trait",
+            Self::Extend => "extend",
+            Self::With => "with",
+            Self::Defun => "defun",
+            Self::True => "true",
+            Self::False => "false",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::While => "while",
+            Self::For => "for",
+            Self::In => "in",
+            Self::Loop => "loop",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::Return => "return"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Punct {
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Semicolon,
+    Colon,
+    PathSep,
+    Dot,
+    DotDot,
+    Arrow
+}
+
+impl Punct {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::Comma => ",",
+            Self::Semicolon => ";",
+            Self::Colon => ":",
+            Self::PathSep => "::",
+            Self::Dot => ".",
+            Self::DotDot => "..",
+            Self::Arrow => "=>"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TokenKind<'a> {
+    Keyword(Keyword),
+    Ident(Yarn<'a>),
+    Int(u64),
+    Float(f64),
+    Str(Yarn<'a>),
+    BinOp(BinOp),
+    UniOp(UniOp),
+    Punct(Punct),
+    Eof
+}
+
+impl Display for TokenKind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keyword(kw) => write!(f, "`{}`", kw.as_str()),
+            Self::Ident(name) => write!(f, "identifier `{}`", name),
+            Self::Int(value) => write!(f, "integer `{}`", value),
+            Self::Float(value) => write!(f, "float `{}`", value),
+            Self::Str(_) => f.write_str("string literal"),
+            Self::BinOp(op) => write!(f, "`{}`", op.as_str()),
+            Self::UniOp(op) => write!(f, "`{}`", op.as_str()),
+            Self::Punct(p) => write!(f, "`{}`", p.as_str()),
+            Self::Eof => f.write_str("end of file")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub span: Span
+}
+
+impl<'a> Token<'a> {
+    pub fn is_keyword(&self, kw: Keyword) -> bool {
+        self.kind == TokenKind::Keyword(kw)
+    }
+
+    pub fn is_punct(&self, p: Punct) -> bool {
+        self.kind == TokenKind::Punct(p)
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.kind == TokenKind::Eof
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LexErrorKind {
+    UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidEscape(char),
+    InvalidNumber,
+    IntegerOverflow
+}
+
+impl Display for LexErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedChar(ch) => write!(f, "UnexpectedChar({:?})", ch),
+            Self::UnterminatedString => f.write_str("UnterminatedString"),
+            Self::UnterminatedComment => f.write_str("UnterminatedComment"),
+            Self::InvalidEscape(ch) => write!(f, "InvalidEscape({:?})", ch),
+            Self::InvalidNumber => f.write_str("InvalidNumber"),
+            Self::IntegerOverflow => f.write_str("IntegerOverflow")
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("LexError: Type: {}, Line: {}, Column: {}", self.kind, self.span.line, self.span.col))
+    }
+}
+
+impl Error for LexError {}
+
+impl From<LexError> for Diagnostic {
+    fn from(err: LexError) -> Self {
+        match err.kind {
+            LexErrorKind::UnexpectedChar(ch) => Diagnostic::error(format!("unexpected character `{}`", ch.escape_debug()))
+                .with_code(codes::UNEXPECTED_CHAR)
+                .with_primary(err.span, "not valid in source text"),
+            LexErrorKind::UnterminatedString => Diagnostic::error("unterminated string literal")
+                .with_code(codes::UNTERMINATED_STRING)
+                .with_primary(err.span, "string starts here")
+                .with_help("add a closing `\"`"),
+            LexErrorKind::UnterminatedComment => Diagnostic::error("unterminated block comment")
+                .with_code(codes::UNTERMINATED_COMMENT)
+                .with_primary(err.span, "comment starts here")
+                .with_note("block comments nest, so every `/*` needs its own `*/`"),
+            LexErrorKind::InvalidEscape(ch) => Diagnostic::error(format!("unknown escape sequence `\\{}`", ch.escape_debug()))
+                .with_code(codes::INVALID_ESCAPE)
+                .with_primary(err.span, "")
+                .with_help("valid escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\\` and `\\\"`"),
+            LexErrorKind::InvalidNumber => Diagnostic::error("invalid numeric literal")
+                .with_code(codes::INVALID_NUMBER)
+                .with_primary(err.span, ""),
+            LexErrorKind::IntegerOverflow => Diagnostic::error("integer literal is too large")
+                .with_code(codes::INTEGER_OVERFLOW)
+                .with_primary(err.span, "")
+                .with_note("integer literals must fit in 64 bits")
+        }
+    }
+}
+
+/// Operators and punctuation, longest spelling first so matching is maximal-munch.
+const SYMBOLS: &[(&str, SymbolKind)] = &[
+    ("+=", SymbolKind::Bin),
+    ("-=", SymbolKind::Bin),
+    ("*=", SymbolKind::Bin),
+    ("/=", SymbolKind::Bin),
+    ("%=", SymbolKind::Bin),
+    ("==", SymbolKind::Bin),
+    ("!=", SymbolKind::Bin),
+    (">=", SymbolKind::Bin),
+    ("<=", SymbolKind::Bin),
+    ("||", SymbolKind::Bin),
+    ("&&", SymbolKind::Bin),
+    ("++", SymbolKind::Uni(UniOp::Increment)),
+    ("--", SymbolKind::Uni(UniOp::Decrement)),
+    ("=>", SymbolKind::Punct(Punct::Arrow)),
+    ("::", SymbolKind::Punct(Punct::PathSep)),
+    ("..", SymbolKind::Punct(Punct::DotDot)),
+    ("+", SymbolKind::Bin),
+    ("-", SymbolKind::Bin),
+    ("*", SymbolKind::Bin),
+    ("/", SymbolKind::Bin),
+    ("%", SymbolKind::Bin),
+    (">", SymbolKind::Bin),
+    ("<", SymbolKind::Bin),
+    ("!", SymbolKind::Uni(UniOp::LogNot)),
+    ("~", SymbolKind::Uni(UniOp::BitNot)),
+    ("=", SymbolKind::Bin),
+    ("(", SymbolKind::Punct(Punct::LParen)),
+    (")", SymbolKind::Punct(Punct::RParen)),
+    ("{", SymbolKind::Punct(Punct::LBrace)),
+    ("}", SymbolKind::Punct(Punct::RBrace)),
+    ("[", SymbolKind::Punct(Punct::LBracket)),
+    ("]", SymbolKind::Punct(Punct::RBracket)),
+    (",", SymbolKind::Punct(Punct::Comma)),
+    (";", SymbolKind::Punct(Punct::Semicolon)),
+    (":", SymbolKind::Punct(Punct::Colon)),
+    (".", SymbolKind::Punct(Punct::Dot))
+];
+
+#[derive(Clone, Copy)]
+enum SymbolKind {
+    /// Spelled as `BinOp::parse_op` reads it.
+    Bin,
+    Uni(UniOp),
+    Punct(Punct)
+}
+
+pub(crate) struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Added to every span, for text that continues an earlier source.
+    origin: usize,
+    line: usize,
+    col: usize,
+    done: bool,
+    /// Errors found inside an otherwise complete token, reported after it.
+    pending: Vec<LexError>
+}
+
+impl<'a> Lexer<'a> {
+
+    pub fn new(src: &'a Yarn<'a>) -> Self {
+        Self::from_str(src.as_slice())
+    }
+
+    pub fn from_str(src: &'a str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            origin: 0,
+            line: 1,
+            col: 1,
+            done: false,
+            pending: Vec::new()
+        }
+    }
+
+    /// Positions spans as if `src` started at byte `offset` on `line` of a larger text.
+    pub fn with_origin(mut self, offset: usize, line: usize) -> Self {
+        self.origin = offset;
+        self.line = line;
+        self
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(ch)
+    }
+
+    fn span_from(&self, start: usize, line: usize, col: usize) -> Span {
+        Span::new(start + self.origin, self.pos + self.origin, line, col)
+    }
+
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            match (self.peek(), self.peek_nth(1)) {
+                (Some(ch), _) if ch.is_whitespace() => {
+                    self.bump();
+                },
+                (Some('/'), Some('/')) => {
+                    while let Some(ch) = self.peek() {
+                        if ch == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                },
+                (Some('/'), Some('*')) => {
+                    let (start, line, col) = (self.pos, self.line, self.col);
+                    self.bump();
+                    self.bump();
+                    let mut depth = 1usize;
+                    while depth > 0 {
+                        match (self.bump(), self.peek()) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                depth -= 1;
+                            },
+                            (Some('/'), Some('*')) => {
+                                self.bump();
+                                depth += 1;
+                            },
+                            (Some(_), _) => {},
+                            (None, _) => return Err(LexError {
+                                kind: LexErrorKind::UnterminatedComment,
+                                span: self.span_from(start, line, col)
+                            })
+                        }
+                    }
+                },
+                _ => return Ok(())
+            }
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Token<'a>, LexError> {
+        self.skip_trivia()?;
+
+        let (start, line, col) = (self.pos, self.line, self.col);
+        let ch = match self.peek() {
+            Some(ch) => ch,
+            None => return Ok(Token {
+                kind: TokenKind::Eof,
+                span: self.span_from(start, line, col)
+            })
+        };
+
+        let kind = if ch.is_alphabetic() || ch == '_' {
+            self.lex_word()
+        } else if ch.is_ascii_digit() {
+            self.lex_number(start, line, col)?
+        } else if ch == '"' {
+            self.lex_string(start, line, col)?
+        } else {
+            self.lex_symbol(start, line, col)?
+        };
+
+        Ok(Token {
+            kind,
+            span: self.span_from(start, line, col)
+        })
+    }
+
+    fn lex_word(&mut self) -> TokenKind<'a> {
+        let start = self.pos;
+        while let Some(ch) = self.peek() {
+            if !(ch.is_alphanumeric() || ch == '_') {
+                break;
+            }
+            self.bump();
+        }
+
+        let word = &self.src[start..self.pos];
+        match Keyword::from_ident(word) {
+            Some(kw) => TokenKind::Keyword(kw),
+            None => TokenKind::Ident(Yarn::borrowed(word))
+        }
+    }
+
+    fn lex_number(&mut self, start: usize, line: usize, col: usize) -> Result<TokenKind<'a>, LexError> {
+        let radix = match (self.peek(), self.peek_nth(1)) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10
+        };
+
+        if radix != 10 {
+            self.bump();
+            self.bump();
+            let digits_start = self.pos;
+            while let Some(ch) = self.peek() {
+                if !(ch.is_digit(radix) || ch == '_') {
+                    break;
+                }
+                self.bump();
+            }
+            let digits: String = self.src[digits_start..self.pos].chars().filter(|ch| *ch != '_').collect();
+            self.reject_suffix(start, line, col)?;
+            return self.finish_int(&digits, radix, start, line, col);
+        }
+
+        let mut is_float = false;
+        self.eat_digits();
+
+        // Only treat `.` as a decimal point when a digit follows, so `1.foo()` still lexes as a call.
+        if self.peek() == Some('.') && self.peek_nth(1).is_some_and(|ch| ch.is_ascii_digit()) {
+            is_float = true;
+            self.bump();
+            self.eat_digits();
+        }
+
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let signed = matches!(self.peek_nth(1), Some('+' | '-'));
+            let digit_at = if signed { 2 } else { 1 };
+            if self.peek_nth(digit_at).is_some_and(|ch| ch.is_ascii_digit()) {
+                is_float = true;
+                self.bump();
+                if signed {
+                    self.bump();
+                }
+                self.eat_digits();
+            }
+        }
+
+        self.reject_suffix(start, line, col)?;
+
+        let text: String = self.src[start..self.pos].chars().filter(|ch| *ch != '_').collect();
+        if is_float {
+            return text.parse::<f64>().map(TokenKind::Float).map_err(|_| LexError {
+                kind: LexErrorKind::InvalidNumber,
+                span: self.span_from(start, line, col)
+            });
+        }
+
+        self.finish_int(&text, 10, start, line, col)
+    }
+
+    /// A number runs into a following word or digit it can't hold, as in `1st` or `0b102`;
+    /// the whole run is one invalid literal.
+    fn reject_suffix(&mut self, start: usize, line: usize, col: usize) -> Result<(), LexError> {
+        if !self.peek().is_some_and(|ch| ch.is_alphanumeric() || ch == '_') {
+            return Ok(());
+        }
+        self.lex_word();
+        Err(LexError {
+            kind: LexErrorKind::InvalidNumber,
+            span: self.span_from(start, line, col)
+        })
+    }
+
+    fn eat_digits(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !(ch.is_ascii_digit() || ch == '_') {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn finish_int(&self, digits: &str, radix: u32, start: usize, line: usize, col: usize) -> Result<TokenKind<'a>, LexError> {
+        if digits.is_empty() {
+            return Err(LexError {
+                kind: LexErrorKind::InvalidNumber,
+                span: self.span_from(start, line, col)
+            });
+        }
+
+        u64::from_str_radix(digits, radix).map(TokenKind::Int).map_err(|_| LexError {
+            kind: LexErrorKind::IntegerOverflow,
+            span: self.span_from(start, line, col)
+        })
+    }
+
+    fn lex_string(&mut self, start: usize, line: usize, col: usize) -> Result<TokenKind<'a>, LexError> {
+        self.bump();
+        let mut value = String::new();
+
+        loop {
+            let (esc_start, esc_line, esc_col) = (self.pos, self.line, self.col);
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some(other) => {
+                            // Keep scanning so the rest of the string is not lexed as code.
+                            self.pending.push(LexError {
+                                kind: LexErrorKind::InvalidEscape(other),
+                                span: self.span_from(esc_start, esc_line, esc_col)
+                            });
+                            other
+                        },
+                        None => return Err(LexError {
+                            kind: LexErrorKind::UnterminatedString,
+                            span: self.span_from(start, line, col)
+                        })
+                    };
+                    value.push(escaped);
+                },
+                Some(ch) => value.push(ch),
+                None => return Err(LexError {
+                    kind: LexErrorKind::UnterminatedString,
+                    span: self.span_from(start, line, col)
+                })
+            }
+        }
+
+        Ok(TokenKind::Str(Yarn::owned(value.into_boxed_str())))
+    }
+
+    fn lex_symbol(&mut self, start: usize, line: usize, col: usize) -> Result<TokenKind<'a>, LexError> {
+        let rest = self.rest();
+        for (spelling, kind) in SYMBOLS {
+            if rest.starts_with(spelling) {
+                for _ in 0..spelling.len() {
+                    self.bump();
+                }
+                return Ok(match *kind {
+                    SymbolKind::Bin => match BinOp::parse_op(&Yarn::borrowed(spelling)) {
+                        Ok(op) => TokenKind::BinOp(op),
+                        Err(_) => unreachable!("`{}` is not an operator", spelling)
+                    },
+                    SymbolKind::Uni(op) => TokenKind::UniOp(op),
+                    SymbolKind::Punct(p) => TokenKind::Punct(p)
+                });
+            }
+        }
+
+        let ch = self.bump().unwrap_or_default();
+        Err(LexError {
+            kind: LexErrorKind::UnexpectedChar(ch),
+            span: self.span_from(start, line, col)
+        })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    /// Yields every token up to and including `Eof`, each followed by the errors found inside
+    /// it; lexing goes on past malformed tokens as `tokenize_all` does.
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.pending.is_empty() {
+            return Some(Err(self.pending.remove(0)));
+        }
+
+        if self.done {
+            return None;
+        }
+
+        let token = self.next_token();
+        if matches!(token, Ok(Token { kind: TokenKind::Eof, .. })) {
+            self.done = true;
+        }
+        Some(token)
+    }
+}
+
+impl<'a> Lexer<'a> {
+    /// Lexes to the end of input, skipping over malformed tokens instead of stopping at the
+    /// first one. The token stream always ends with a `TokenKind::Eof` token.
+    pub fn tokenize_all(mut self) -> (Vec<Token<'a>>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+
+        loop {
+            let token = self.next_token();
+            errors.append(&mut self.pending);
+            match token {
+                Ok(token) if token.is_eof() => {
+                    tokens.push(token);
+                    return (tokens, errors);
+                },
+                Ok(token) => tokens.push(token),
+                Err(err) => errors.push(err)
+            }
+        }
+    }
+}
+
+/// Lexes the whole of `src`. The returned stream always ends with a `TokenKind::Eof` token.
+pub(crate) fn tokenize<'a>(src: &'a Yarn<'a>) -> Result<Vec<Token<'a>>, LexError> {
+    Lexer::new(src).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `src` before `Eof`, and the errors found on the way.
+    fn lex(src: &str) -> (Vec<TokenKind<'_>>, Vec<LexErrorKind>) {
+        let (tokens, errors) = Lexer::from_str(src).tokenize_all();
+        assert!(tokens.last().is_some_and(Token::is_eof));
+        let kinds = tokens.into_iter().map(|token| token.kind).filter(|kind| *kind != TokenKind::Eof).collect();
+        (kinds, errors.into_iter().map(|err| err.kind).collect())
+    }
+
+    #[test]
+    fn spans_count_lines_and_columns() {
+        let src = "let\tx:\n  Int8 ;\r\n\"é\" y";
+        let (tokens, errors) = Lexer::from_str(src).tokenize_all();
+        assert!(errors.is_empty());
+        let at: Vec<(usize, usize, usize, usize)> = tokens.iter().map(|token| (token.span.start, token.span.end, token.span.line, token.span.col)).collect();
+        // A tab is one column and columns count characters, while offsets count bytes.
+        assert_eq!(at, [(0, 3, 1, 1), (4, 5, 1, 5), (5, 6, 1, 6), (9, 13, 2, 3), (14, 15, 2, 8), (17, 21, 3, 1), (22, 23, 3, 5), (23, 23, 3, 6)]);
+
+        let (tokens, _) = Lexer::from_str("a\nb").with_origin(100, 7).tokenize_all();
+        assert_eq!(tokens[1].span, Span::new(102, 103, 8, 1));
+    }
+
+    #[test]
+    fn longest_symbol_wins() {
+        let (kinds, _) = lex(">= => .. >== ... +++ a-=1");
+        assert_eq!(kinds, [
+            TokenKind::BinOp(BinOp::GreaterThanEq),
+            TokenKind::Punct(Punct::Arrow),
+            TokenKind::Punct(Punct::DotDot),
+            TokenKind::BinOp(BinOp::GreaterThanEq),
+            TokenKind::BinOp(BinOp::Assign),
+            TokenKind::Punct(Punct::DotDot),
+            TokenKind::Punct(Punct::Dot),
+            TokenKind::UniOp(UniOp::Increment),
+            TokenKind::BinOp(BinOp::Add),
+            TokenKind::Ident(Yarn::borrowed("a")),
+            TokenKind::BinOp(BinOp::SubAssign),
+            TokenKind::Int(1)
+        ]);
+        assert_eq!(lex("1..10").0, [TokenKind::Int(1), TokenKind::Punct(Punct::DotDot), TokenKind::Int(10)]);
+    }
+
+    #[test]
+    fn comments_nest() {
+        assert_eq!(lex("a /* b /* c */ d */ e // f */\ng").0, [
+            TokenKind::Ident(Yarn::borrowed("a")),
+            TokenKind::Ident(Yarn::borrowed("e")),
+            TokenKind::Ident(Yarn::borrowed("g"))
+        ]);
+        assert_eq!(lex("a /* b /* c */ d").1, [LexErrorKind::UnterminatedComment]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(lex(r#""a\n\t\r\0\\\"b""#).0, [TokenKind::Str(Yarn::borrowed("a\n\t\r\0\\\"b"))]);
+
+        // A bad escape is reported after its string, which still ends at its own quote.
+        let (kinds, errors) = lex(r#""a\qb" c"#);
+        assert_eq!(kinds, [TokenKind::Str(Yarn::borrowed("aqb")), TokenKind::Ident(Yarn::borrowed("c"))]);
+        assert_eq!(errors, [LexErrorKind::InvalidEscape('q')]);
+        assert_eq!(lex("\"abc").1, [LexErrorKind::UnterminatedString]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(lex("0x1F 0o17 0b1010 1_000 2.5 1e3 1.foo").0, [
+            TokenKind::Int(31),
+            TokenKind::Int(15),
+            TokenKind::Int(10),
+            TokenKind::Int(1000),
+            TokenKind::Float(2.5),
+            TokenKind::Float(1000.0),
+            TokenKind::Int(1),
+            TokenKind::Punct(Punct::Dot),
+            TokenKind::Ident(Yarn::borrowed("foo"))
+        ]);
+        assert_eq!(lex("18446744073709551615").0, [TokenKind::Int(u64::MAX)]);
+        assert_eq!(lex("18446744073709551616 0x1_0000_0000_0000_0000").1, [LexErrorKind::IntegerOverflow, LexErrorKind::IntegerOverflow]);
+    }
+
+    #[test]
+    fn numbers_cannot_run_into_words() {
+        for src in ["0b102", "0xFFz", "0o8", "0x", "12ab", "1_x"] {
+            let (kinds, errors) = lex(src);
+            assert!(kinds.is_empty(), "{}", src);
+            assert_eq!(errors, [LexErrorKind::InvalidNumber], "{}", src);
+        }
+    }
+
+    #[test]
+    fn iterating_goes_past_errors() {
+        let yarn = Yarn::borrowed("\"\\q\" $ a");
+        let items: Vec<Result<TokenKind<'_>, LexErrorKind>> = Lexer::new(&yarn)
+            .map(|item| item.map(|token| token.kind).map_err(|err| err.kind))
+            .collect();
+        assert_eq!(items, [
+            Ok(TokenKind::Str(Yarn::borrowed("q"))),
+            Err(LexErrorKind::InvalidEscape('q')),
+            Err(LexErrorKind::UnexpectedChar('$')),
+            Ok(TokenKind::Ident(Yarn::borrowed("a"))),
+            Ok(TokenKind::Eof)
+        ]);
+        assert!(tokenize(&yarn).is_err());
+    }
+}
@@ -1,41 +1,136 @@
 pub(crate) mod yarn;
+pub(crate) mod lexer;
+pub(crate) mod diagnostics;
 pub(crate) mod syntax_tree;
 pub(crate) mod parser;
+pub(crate) mod session;
+// Channel scaffolding the compiler doesn't use yet.
+#[allow(dead_code, unused_imports)]
 pub(crate) mod threads;
 
 
-use std::{collections::HashMap, mem, sync::{Mutex, OnceLock}};
+/// What `build` can generate code for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Targets {
+    /// x86-64 Linux with the System V calling convention, as GNU assembler text.
+    X86_64Linux,
+    /// A WebAssembly module for WASI hosts, in the binary format.
+    Wasm32,
+    /// A `.bbc` file for the bytecode VM, which `run` executes.
+    Bytecode
+}
 
-use self::{syntax_tree::ObjDescriptor, yarn::Yarn};
+impl Targets {
+    pub const ALL: [Targets; 3] = [Self::X86_64Linux, Self::Wasm32, Self::Bytecode];
 
-pub(crate) static mut OBJECT_INDEX: OnceLock<Mutex<Vec<Box<ObjDescriptor<'static>>>>> = OnceLock::new();
-pub(crate) static mut DEFINED: OnceLock<Mutex<Box<HashMap<yarn::Yarn<'static>, usize>>>> = OnceLock::new();
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::X86_64Linux => "x86_64-linux",
+            Self::Wasm32 => "wasm32-wasi",
+            Self::Bytecode => "bytecode"
+        }
+    }
 
-pub(crate) unsafe fn transmute<'a, T>(src: &'a T) -> &'static T {
-    mem::transmute::<&'a T, &'static T>(&src)
-}
+    /// The extension of the file `build` writes.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::X86_64Linux => "s",
+            Self::Wasm32 => "wasm",
+            Self::Bytecode => "bbc"
+        }
+    }
 
+    /// Looks up a `--target` name; `native` is the machine the compiler runs on.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Self::native(),
+            "x86_64-linux" | "x86_64-unknown-linux-gnu" => Some(Self::X86_64Linux),
+            "wasm32" | "wasm32-wasi" | "wasm32-wasip1" => Some(Self::Wasm32),
+            "bytecode" | "bbc" => Some(Self::Bytecode),
+            _ => None
+        }
+    }
 
-pub(crate) fn register_object<'a>(obj: ObjDescriptor<'a>) -> Option<()> {
-    unsafe {
-        OBJECT_INDEX.get_mut()?.lock().unwrap().push(Box::new(obj.immortalize()));
-    };
-    Some(())
+    pub fn native() -> Option<Self> {
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            Some(Self::X86_64Linux)
+        } else {
+            None
+        }
+    }
 }
 
-pub(crate) fn register_defined<'a>(def: (Yarn<'a>, usize)) -> Option<()> {
-    unsafe {
-        DEFINED.get_mut()?.lock().unwrap().insert(def.0.immortalize(), def.1);
-    };
-    Some(())
+/// The names the preprocessor defines for `#if` conditions to test the target with.
+/// `__TARGET_OS__` is one of the `__OS_*__` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Constants {
+    TargetOs,
+    PointerWidth,
+    LittleEndian,
+    BigEndian,
+    OsNone,
+    OsLinux,
+    OsWasi
 }
 
-pub(crate) enum Targets {}
+impl Constants {
+    pub const ALL: [Constants; 7] = [
+        Self::TargetOs, Self::PointerWidth, Self::LittleEndian, Self::BigEndian,
+        Self::OsNone, Self::OsLinux, Self::OsWasi
+    ];
 
-pub(crate) struct Constants(usize);
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TargetOs => "__TARGET_OS__",
+            Self::PointerWidth => "__POINTER_WIDTH__",
+            Self::LittleEndian => "__LITTLE_ENDIAN__",
+            Self::BigEndian => "__BIG_ENDIAN__",
+            Self::OsNone => "__OS_NONE__",
+            Self::OsLinux => "__OS_LINUX__",
+            Self::OsWasi => "__OS_WASI__"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|constant| constant.name() == name)
+    }
+}
 
+/// What a program is compiled for, as the preprocessor's constants describe it.
+#[derive(Clone, Copy, Debug)]
 pub(crate) struct Globals {
-    target: Targets,
-    start: yarn::Yarn<'static>,
-    os: Constants
+    /// One of the `Os*` constants.
+    os: Constants,
+    pointer_width: u32,
+    little_endian: bool
+}
+
+impl Globals {
+
+    /// The globals for `target`, or for the machine the compiler runs on when the
+    /// program runs in the interpreter. The bytecode VM has no OS of its own.
+    pub fn new(target: Option<Targets>) -> Self {
+        match target {
+            Some(Targets::X86_64Linux) => Self { os: Constants::OsLinux, pointer_width: 64, little_endian: true },
+            Some(Targets::Wasm32) => Self { os: Constants::OsWasi, pointer_width: 32, little_endian: true },
+            Some(Targets::Bytecode) => Self { os: Constants::OsNone, pointer_width: 64, little_endian: true },
+            None => Self {
+                os: if cfg!(target_os = "linux") { Constants::OsLinux } else { Constants::OsNone },
+                pointer_width: usize::BITS,
+                little_endian: cfg!(target_endian = "little")
+            }
+        }
+    }
+
+    pub fn value(&self, constant: Constants) -> i64 {
+        match constant {
+            Constants::TargetOs => self.value(self.os),
+            Constants::PointerWidth => self.pointer_width as i64,
+            Constants::LittleEndian => self.little_endian as i64,
+            Constants::BigEndian => !self.little_endian as i64,
+            Constants::OsNone => 0,
+            Constants::OsLinux => 1,
+            Constants::OsWasi => 2
+        }
+    }
 }
\ No newline at end of file
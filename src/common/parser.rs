@@ -1,7 +1,20 @@
-use super::yarn::Yarn;
+use std::{error::Error, fmt::Display};
 
+use super::{
+    diagnostics::{codes, Diagnostic},
+    diagnostics::Severity,
+    lexer::{Keyword, LexError, LexErrorKind, Lexer, Punct, Span, Token, TokenKind},
+    syntax_tree::{
+        BinOp, Bodies, CompDescriptor, DefunDescriptor, ExtendDescriptor, GenericParam, Literal,
+        Node, ObjDescriptor, ToNodes, TraitDescriptor, Type, UniOp, VarDeclaration
+    },
+    yarn::Yarn
+};
 
 
+/// A run of source lines whose first and last lines delimit it. The parser reads whole
+/// token streams, so nothing splits source into chunks yet.
+#[allow(dead_code)]
 pub(crate) struct Chunk<'a> {
     start: Yarn<'a>,
     contents: Vec<Yarn<'a>>, // Lines within chunk,
@@ -9,12 +22,1207 @@ pub(crate) struct Chunk<'a> {
     id: usize
 }
 
+#[allow(dead_code)]
 impl<'a> Chunk<'a> {
 
-    pub(crate) fn from_raw(raw: Yarn<'a>, id: usize) -> Self {}
+    /// Splits `raw` into lines; the first and last lines delimit the chunk.
+    pub(crate) fn from_raw(raw: Yarn<'a>, id: usize) -> Self {
+        let mut lines: Vec<Yarn<'a>> = raw.as_slice()
+            .lines()
+            .map(|line| Yarn::owned(line.into()))
+            .collect();
+
+        let start = if lines.is_empty() { Yarn::from_static("") } else { lines.remove(0) };
+        let end = lines.pop().unwrap_or_else(|| Yarn::from_static(""));
+
+        Self {
+            start,
+            contents: lines,
+            end,
+            id
+        }
+    }
 
     pub(crate) fn from_parts(start: Yarn<'a>, contents: Yarn<'a>, end: Yarn<'a>, id: usize) -> Self {
+        Self {
+            start,
+            contents: contents.as_slice()
+                .lines()
+                .map(|line| Yarn::owned(line.into()))
+                .collect(),
+            end,
+            id
+        }
+    }
+
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn lines(&self) -> impl Iterator<Item = &Yarn<'a>> {
+        std::iter::once(&self.start)
+            .chain(self.contents.iter())
+            .chain(std::iter::once(&self.end))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ParseErrorKind {
+    Lex(LexErrorKind),
+    Expected {
+        expected: String,
+        found: String
+    },
+    VoidDeclaration,
+    InvalidAssignTarget,
+    NotConstant
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lex(kind) => write!(f, "Lex({})", kind),
+            Self::Expected { expected, found } => write!(f, "Expected {}, found {}", expected, found),
+            Self::VoidDeclaration => f.write_str("VoidDeclaration"),
+            Self::InvalidAssignTarget => f.write_str("InvalidAssignTarget"),
+            Self::NotConstant => f.write_str("NotConstant")
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span
+}
+
+impl ParseError {
+    fn expected(expected: impl Into<String>, found: &Token<'_>) -> Self {
+        Self {
+            kind: ParseErrorKind::Expected {
+                expected: expected.into(),
+                found: found.kind.to_string()
+            },
+            span: found.span
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("ParseError: Type: {}, Line: {}, Column: {}", self.kind, self.span.line, self.span.col))
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        match err.kind {
+            ParseErrorKind::Lex(kind) => LexError { kind, span: err.span }.into(),
+            ParseErrorKind::Expected { expected, found } => Diagnostic::error(format!("expected {}, found {}", expected, found))
+                .with_code(codes::EXPECTED_TOKEN)
+                .with_primary(err.span, format!("expected {}", expected)),
+            ParseErrorKind::VoidDeclaration => Diagnostic::error("variables cannot have type `Void`")
+                .with_code(codes::VOID_DECLARATION)
+                .with_primary(err.span, ""),
+            ParseErrorKind::InvalidAssignTarget => Diagnostic::error("invalid assignment target")
+                .with_code(codes::INVALID_ASSIGN_TARGET)
+                .with_primary(err.span, "cannot be assigned to")
+                .with_help("only variables and their fields can be assigned"),
+            ParseErrorKind::NotConstant => Diagnostic::error("expression is not constant")
+                .with_code(codes::NOT_CONSTANT)
+                .with_primary(err.span, "not allowed in a constant expression")
+        }
+    }
+}
 
-    }    
+type ParseResult<T> = Result<T, ParseError>;
+
+/// How many errors a single parse reports before giving up on the rest of the file.
+pub(crate) const DEFAULT_ERROR_LIMIT: usize = 50;
+
+/// Recursive-descent parser from a token stream to a `Node::Head`-rooted tree.
+pub(crate) struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    /// Names of the enclosing `obj`/`trait`/`extend` bodies, used to build qualified function names.
+    qualifier: Vec<Yarn<'a>>,
+    /// The type of a bare `self` parameter in the enclosing body.
+    receiver: Option<Type<'a>>,
+    /// Type parameters of the enclosing items, which `parse_type` makes `Type::Param`s.
+    generics: Vec<Yarn<'a>>,
+    errors: Vec<Diagnostic>,
+    error_limit: usize
 }
 
+impl<'a> Parser<'a> {
+
+    /// Lexes `src`; lexical errors are recorded and the malformed tokens skipped.
+    pub fn new(src: &'a Yarn<'a>) -> Self {
+        Self::from_lexer(Lexer::new(src))
+    }
+
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        let (tokens, lex_errors) = lexer.tokenize_all();
+        let mut parser = Self::from_tokens(tokens);
+        for err in lex_errors {
+            parser.report(ParseError {
+                kind: ParseErrorKind::Lex(err.kind),
+                span: err.span
+            });
+        }
+        parser
+    }
+
+    pub fn with_error_limit(mut self, limit: usize) -> Self {
+        self.error_limit = limit.max(1);
+        if self.errors.len() >= self.error_limit {
+            // Only lexical errors can exist yet, so everything here is an error.
+            self.errors.truncate(self.error_limit);
+            self.errors.push(self.limit_note());
+        }
+        self
+    }
+
+    fn limit_note(&self) -> Diagnostic {
+        Diagnostic::new(Severity::Note, format!("stopped parsing after {} errors", self.error_limit))
+    }
+
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    pub fn take_errors(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn error_count(&self) -> usize {
+        self.errors.iter().filter(|diag| diag.is_error()).count()
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.error_count() >= self.error_limit
+    }
+
+    fn report(&mut self, err: ParseError) {
+        if self.limit_reached() {
+            return;
+        }
+
+        self.errors.push(err.into());
+        if self.limit_reached() {
+            self.errors.push(self.limit_note());
+        }
+    }
+
+    /// Skips to the next statement boundary: just past a `;`, or before a `}` or a token
+    /// that starts a new declaration, ignoring any that sit inside nested braces.
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            match &self.peek().kind {
+                TokenKind::Eof => return,
+                TokenKind::Punct(Punct::LBrace) => depth += 1,
+                TokenKind::Punct(Punct::RBrace) => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                },
+                TokenKind::Punct(Punct::Semicolon) if depth == 0 => {
+                    self.bump();
+                    return;
+                },
+                TokenKind::Keyword(Keyword::Let | Keyword::Defun | Keyword::Obj | Keyword::Comp | Keyword::Trait | Keyword::Extend) if depth == 0 => return,
+                TokenKind::Keyword(
+                    Keyword::If | Keyword::While | Keyword::For | Keyword::Loop | Keyword::Break | Keyword::Continue | Keyword::Return
+                ) if depth == 0 => return,
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    /// Records `err`, resynchronizes and returns a placeholder for the broken construct.
+    /// Always consumes at least one token so callers' loops make progress.
+    fn recover(&mut self, err: ParseError, start: usize) -> Node<'a> {
+        let span = err.span;
+        self.report(err);
+        self.synchronize();
+        if self.pos == start {
+            self.bump();
+        }
+        Node::Error { span }
+    }
+
+    /// A missing `;` is reported as if it had been inserted, without skipping anything.
+    fn expect_semicolon(&mut self) {
+        if !self.eat_punct(Punct::Semicolon) {
+            let mut err = ParseError::expected("`;`", self.peek());
+            err.span = self.after_prev();
+            self.report(err);
+        }
+    }
+
+    pub fn from_tokens(mut tokens: Vec<Token<'a>>) -> Self {
+        if !tokens.last().is_some_and(Token::is_eof) {
+            let end = tokens.last().map(|tok| Span::new(tok.span.end, tok.span.end, tok.span.line, tok.span.col)).unwrap_or_default();
+            tokens.push(Token { kind: TokenKind::Eof, span: end });
+        }
+
+        Self {
+            tokens,
+            pos: 0,
+            qualifier: Vec::new(),
+            receiver: None,
+            generics: Vec::new(),
+            errors: Vec::new(),
+            error_limit: DEFAULT_ERROR_LIMIT
+        }
+    }
+
+    fn peek(&self) -> &Token<'a> {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token<'a> {
+        &self.tokens[(self.pos + n).min(self.tokens.len() - 1)]
+    }
+
+    fn bump(&mut self) -> Token<'a> {
+        let token = self.peek().clone();
+        if !token.is_eof() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn prev_span(&self) -> Span {
+        self.tokens[self.pos.saturating_sub(1)].span
+    }
+
+    fn at_punct(&self, p: Punct) -> bool {
+        self.peek().is_punct(p)
+    }
+
+    fn eat_punct(&mut self, p: Punct) -> bool {
+        if self.at_punct(p) {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    fn expect_punct(&mut self, p: Punct) -> ParseResult<Token<'a>> {
+        if self.at_punct(p) {
+            return Ok(self.bump());
+        }
+
+        let mut err = ParseError::expected(format!("`{}`", p.as_str()), self.peek());
+        if p == Punct::Semicolon {
+            err.span = self.after_prev();
+        }
+        Err(err)
+    }
+
+    /// An empty span just past the previous token, where a missing terminator belongs.
+    fn after_prev(&self) -> Span {
+        if self.pos == 0 {
+            return self.peek().span;
+        }
+        let prev = self.prev_span();
+        Span::new(prev.end, prev.end, prev.line, prev.col + prev.len())
+    }
+
+    fn at_binop(&self, op: BinOp) -> bool {
+        matches!(self.peek().kind, TokenKind::BinOp(found) if found == op)
+    }
+
+    fn expect_keyword(&mut self, kw: Keyword) -> ParseResult<Token<'a>> {
+        if self.peek().is_keyword(kw) {
+            return Ok(self.bump());
+        }
+        Err(ParseError::expected(format!("`{}`", kw.as_str()), self.peek()))
+    }
+
+    fn expect_ident(&mut self) -> ParseResult<(Yarn<'a>, Span)> {
+        match self.peek().kind.clone() {
+            TokenKind::Ident(name) => {
+                let span = self.bump().span;
+                Ok((name, span))
+            },
+            _ => Err(ParseError::expected("identifier", self.peek()))
+        }
+    }
+
+    fn qualify(&self, name: &Yarn<'a>) -> Yarn<'a> {
+        if self.qualifier.is_empty() {
+            return name.clone();
+        }
+
+        let mut path: Vec<&str> = self.qualifier.iter().map(Yarn::as_slice).collect();
+        path.push(name.as_slice());
+        Yarn::owned(path.join("::").into_boxed_str())
+    }
+
+    pub fn expect_end(&self) -> ParseResult<()> {
+        if self.peek().is_eof() {
+            return Ok(());
+        }
+        Err(ParseError::expected("end of input", self.peek()))
+    }
+
+    /// Parses a whole source file into `Head { next: Chain { .. } }`. Broken items become
+    /// `Node::Error` placeholders; the errors are available from `errors()`.
+    pub fn parse_program(&mut self) -> Node<'a> {
+        let mut items = Vec::new();
+        while !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            let item = self.parse_item().unwrap_or_else(|err| self.recover(err, start));
+            items.push(item);
+        }
+
+        // Lexical errors were collected up front; interleave them by position.
+        self.errors.sort_by_key(|diag| diag.primary_span().map_or(usize::MAX, |span| span.start));
+
+        Node::Head {
+            next: Box::new(Node::Chain { chained: items })
+        }
+    }
+
+    fn parse_item(&mut self) -> ParseResult<Node<'a>> {
+        match &self.peek().kind {
+            TokenKind::Keyword(Keyword::Obj) => self.scoped(Self::parse_obj),
+            TokenKind::Keyword(Keyword::Comp) => self.scoped(Self::parse_comp),
+            TokenKind::Keyword(Keyword::Trait) => self.scoped(Self::parse_trait),
+            TokenKind::Keyword(Keyword::Extend) => self.scoped(Self::parse_extend),
+            TokenKind::Keyword(Keyword::Defun) => self.scoped(Self::parse_defun),
+            _ => self.parse_statement()
+        }
+    }
+
+    /// Runs `parse` on an item whose type parameters go out of scope with it, even when
+    /// it fails to parse.
+    fn scoped<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        let outer = self.generics.len();
+        let result = parse(self);
+        self.generics.truncate(outer);
+        result
+    }
+
+    /// `<T, U: Bound + Other>` after an item's name, or nothing. The parameters are in
+    /// scope from here to the end of the item.
+    fn parse_generics(&mut self) -> ParseResult<Vec<GenericParam<'a>>> {
+        let mut generics = Vec::new();
+        if !self.at_binop(BinOp::LessThan) {
+            return Ok(generics);
+        }
+        self.bump();
+        loop {
+            let (name, _) = self.expect_ident()?;
+            let mut bounds = Vec::new();
+            if self.eat_punct(Punct::Colon) {
+                loop {
+                    bounds.push(self.parse_type()?);
+                    if !self.at_binop(BinOp::Add) {
+                        break;
+                    }
+                    self.bump();
+                }
+            }
+            self.generics.push(name.clone());
+            generics.push(GenericParam::new(name, bounds));
+            if !self.eat_punct(Punct::Comma) {
+                break;
+            }
+        }
+        self.expect_close_angle()?;
+        Ok(generics)
+    }
+
+    /// `<Type, Type>` after the name of a generic type.
+    fn parse_type_args(&mut self) -> ParseResult<Vec<Type<'a>>> {
+        self.bump();
+        let mut args = Vec::new();
+        loop {
+            args.push(self.parse_type()?);
+            if !self.eat_punct(Punct::Comma) {
+                break;
+            }
+        }
+        self.expect_close_angle()?;
+        Ok(args)
+    }
+
+    fn expect_close_angle(&mut self) -> ParseResult<()> {
+        if self.at_binop(BinOp::GreaterThan) {
+            self.bump();
+            return Ok(());
+        }
+        Err(ParseError::expected("`>`", self.peek()))
+    }
+
+    /// The type of `self` in the body of the item `name` declaring `generics`.
+    fn receiver_type(name: &Yarn<'a>, generics: &[GenericParam<'a>]) -> Type<'a> {
+        if generics.is_empty() {
+            return Type::Named(name.clone());
+        }
+        Type::Applied(name.clone(), generics.iter().map(GenericParam::as_type).collect())
+    }
+
+    fn parse_obj(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Obj)?.span;
+        let (name, _) = self.expect_ident()?;
+        let generics = self.parse_generics()?;
+        self.expect_punct(Punct::LBrace)?;
+
+        self.qualifier.push(name.clone());
+        let receiver = self.receiver.replace(Self::receiver_type(&name, &generics));
+        let mut fields = Vec::new();
+        let mut functions = Vec::new();
+        let mut body = Vec::new();
+
+        while !self.at_punct(Punct::RBrace) && !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            if self.peek().is_keyword(Keyword::Defun) {
+                let method = self.scoped(Self::parse_defun).unwrap_or_else(|err| self.recover(err, start));
+                if let Node::Body { discriptor, .. } = &method {
+                    if let Bodies::Defun(defun) = discriptor.as_ref() {
+                        functions.push(defun.clone());
+                    }
+                }
+                body.push(method);
+                continue;
+            }
+
+            match self.parse_field() {
+                Ok(field) => fields.push(field),
+                Err(err) => {
+                    self.recover(err, start);
+                }
+            }
+        }
+        self.receiver = receiver;
+        self.qualifier.pop();
+
+        let end = self.expect_punct(Punct::RBrace)?.span;
+        Ok(Node::Body {
+            discriptor: Box::new(Bodies::Object(ObjDescriptor::new(name, fields, functions).with_generics(generics))),
+            body,
+            span: start.join(end)
+        })
+    }
+
+    fn parse_comp(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Comp)?.span;
+        let (name, _) = self.expect_ident()?;
+        let generics = self.parse_generics()?;
+        self.expect_punct(Punct::LBrace)?;
+
+        let mut fields = Vec::new();
+        while !self.at_punct(Punct::RBrace) && !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            match self.parse_field() {
+                Ok(field) => fields.push(field),
+                Err(err) => {
+                    self.recover(err, start);
+                }
+            }
+        }
+
+        let end = self.expect_punct(Punct::RBrace)?.span;
+        Ok(Node::Body {
+            discriptor: Box::new(Bodies::Composition(CompDescriptor::new(name, fields).with_generics(generics))),
+            body: Vec::new(),
+            span: start.join(end)
+        })
+    }
+
+    /// `name: Type` followed by `,` or `;` (optional before the closing brace).
+    fn parse_field(&mut self) -> ParseResult<VarDeclaration<'a>> {
+        let decl = self.parse_binding()?;
+        if !self.eat_punct(Punct::Comma) && !self.eat_punct(Punct::Semicolon) && !self.at_punct(Punct::RBrace) {
+            return Err(ParseError::expected("`,` or `;`", self.peek()));
+        }
+        Ok(decl)
+    }
+
+    fn parse_trait(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Trait)?.span;
+        let (name, _) = self.expect_ident()?;
+        let generics = self.parse_generics()?;
+
+        let mut super_traits = Vec::new();
+        if self.eat_punct(Punct::Colon) {
+            loop {
+                let (super_name, _) = self.parse_path()?;
+                super_traits.push(TraitDescriptor::reference(super_name));
+                if !matches!(self.peek().kind, TokenKind::BinOp(BinOp::Add)) {
+                    break;
+                }
+                self.bump();
+            }
+        }
+
+        self.expect_punct(Punct::LBrace)?;
+        self.qualifier.push(name.clone());
+        let receiver = self.receiver.replace(Self::receiver_type(&name, &generics));
+        let mut functions = Vec::new();
+        let mut defaults = Vec::new();
+        let mut body = Vec::new();
+        while !self.at_punct(Punct::RBrace) && !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            match self.scoped(Self::parse_trait_method) {
+                Ok((signature, method, has_default)) => {
+                    if has_default {
+                        defaults.push(signature.name().clone());
+                    }
+                    functions.push(signature);
+                    body.push(method);
+                },
+                Err(err) => {
+                    self.recover(err, start);
+                }
+            }
+        }
+        self.receiver = receiver;
+        self.qualifier.pop();
+
+        let end = self.expect_punct(Punct::RBrace)?.span;
+        Ok(Node::Body {
+            discriptor: Box::new(Bodies::Trait(TraitDescriptor::new(name, functions, defaults, super_traits).with_generics(generics))),
+            body,
+            span: start.join(end)
+        })
+    }
+
+    /// A method signature ending in `;`, or one with a default body. Either way the method
+    /// becomes a `defun` node of the trait; only the flag tells them apart.
+    fn parse_trait_method(&mut self) -> ParseResult<(DefunDescriptor<'a>, Node<'a>, bool)> {
+        let (signature, span) = self.parse_signature()?;
+        let (body, span, has_default) = if self.at_punct(Punct::LBrace) {
+            let (body, end) = self.parse_block()?;
+            (body, span.join(end), true)
+        } else {
+            self.expect_semicolon();
+            (Vec::new(), span, false)
+        };
+        let method = Node::Body {
+            discriptor: Box::new(Bodies::Defun(signature.clone())),
+            body,
+            span
+        };
+        Ok((signature, method, has_default))
+    }
+
+    /// `extend Obj with Trait { defun ... }`, implementing the trait's methods for the `obj`;
+    /// `extend Box<T> with Into<T>` for a generic `obj` or trait.
+    fn parse_extend(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Extend)?.span;
+        let (obj, _) = self.parse_path()?;
+        let generics = self.parse_generics()?;
+        self.expect_keyword(Keyword::With)?;
+        let (with, _) = self.parse_path()?;
+        let trait_args = if self.at_binop(BinOp::LessThan) { self.parse_type_args()? } else { Vec::new() };
+        self.expect_punct(Punct::LBrace)?;
+
+        self.qualifier.push(obj.clone());
+        self.qualifier.push(with.clone());
+        let receiver = self.receiver.replace(Self::receiver_type(&obj, &generics));
+        let mut functions = Vec::new();
+        let mut body = Vec::new();
+        while !self.at_punct(Punct::RBrace) && !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            let method = self.scoped(Self::parse_defun).unwrap_or_else(|err| self.recover(err, start));
+            if let Node::Body { discriptor, .. } = &method {
+                if let Bodies::Defun(defun) = discriptor.as_ref() {
+                    functions.push(defun.clone());
+                }
+            }
+            body.push(method);
+        }
+        self.receiver = receiver;
+        self.qualifier.truncate(self.qualifier.len() - 2);
+
+        let end = self.expect_punct(Punct::RBrace)?.span;
+        Ok(Node::Body {
+            discriptor: Box::new(Bodies::Extension(ExtendDescriptor::new(obj, with, functions).with_generics(generics, trait_args))),
+            body,
+            span: start.join(end)
+        })
+    }
+
+    /// `defun name<T>(args) => Type`, where the type parameters are optional and the
+    /// return type defaults to `Void`.
+    fn parse_signature(&mut self) -> ParseResult<(DefunDescriptor<'a>, Span)> {
+        let start = self.expect_keyword(Keyword::Defun)?.span;
+        let (name, _) = self.expect_ident()?;
+        let generics = self.parse_generics()?;
+        self.expect_punct(Punct::LParen)?;
+
+        let mut args = Vec::new();
+        while !self.at_punct(Punct::RParen) {
+            args.push(self.parse_param()?);
+            if !self.eat_punct(Punct::Comma) {
+                break;
+            }
+        }
+        self.expect_punct(Punct::RParen)?;
+
+        let return_type = if self.eat_punct(Punct::Arrow) {
+            self.parse_type()?
+        } else {
+            Type::Void
+        };
+
+        let qualified = self.qualify(&name);
+        Ok((DefunDescriptor::new(name, qualified, args, return_type).with_generics(generics), start.join(self.prev_span())))
+    }
+
+    /// A parameter is `name: Type`, or a bare `self` inside an `obj`/`trait`/`extend` body.
+    fn parse_param(&mut self) -> ParseResult<VarDeclaration<'a>> {
+        let is_self = matches!(&self.peek().kind, TokenKind::Ident(name) if name.as_slice() == "self");
+        if is_self && !self.peek_nth(1).is_punct(Punct::Colon) {
+            if let Some(owner) = self.receiver.clone() {
+                let (name, span) = self.expect_ident()?;
+                return VarDeclaration::new(owner, Some(name))
+                    .ok_or(ParseError { kind: ParseErrorKind::VoidDeclaration, span });
+            }
+        }
+
+        self.parse_binding()
+    }
+
+    fn parse_binding(&mut self) -> ParseResult<VarDeclaration<'a>> {
+        let (name, span) = self.expect_ident()?;
+        self.expect_punct(Punct::Colon)?;
+        let ty = self.parse_type()?;
+        VarDeclaration::new(ty, Some(name)).ok_or(ParseError { kind: ParseErrorKind::VoidDeclaration, span })
+    }
+
+    fn parse_defun(&mut self) -> ParseResult<Node<'a>> {
+        let (signature, start) = self.parse_signature()?;
+        let (body, end) = self.parse_block()?;
+
+        Ok(Node::Body {
+            discriptor: Box::new(Bodies::Defun(signature)),
+            body,
+            span: start.join(end)
+        })
+    }
+
+    /// `{ statement* }`; the final expression statement may omit its `;`.
+    fn parse_block(&mut self) -> ParseResult<(Vec<Node<'a>>, Span)> {
+        let start = self.expect_punct(Punct::LBrace)?.span;
+        let mut statements = Vec::new();
+
+        while !self.at_punct(Punct::RBrace) && !self.peek().is_eof() && !self.limit_reached() {
+            let start = self.pos;
+            let statement = self.parse_statement().unwrap_or_else(|err| self.recover(err, start));
+            statements.push(statement);
+        }
+
+        let end = self.expect_punct(Punct::RBrace)?.span;
+        Ok((statements, start.join(end)))
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<Node<'a>> {
+        match &self.peek().kind {
+            TokenKind::Keyword(Keyword::Let) => return self.parse_let(),
+            // Statements ending in a block need no `;`.
+            TokenKind::Keyword(Keyword::If) => return self.parse_if(),
+            TokenKind::Keyword(Keyword::While | Keyword::For | Keyword::Loop) => return self.parse_loop(None, self.peek().span),
+            TokenKind::Keyword(Keyword::Break | Keyword::Continue) => return self.parse_jump(),
+            TokenKind::Keyword(Keyword::Return) => return self.parse_return(),
+            TokenKind::Ident(_) if self.peek_nth(1).is_punct(Punct::Colon) => {
+                let (label, start) = self.expect_ident()?;
+                self.bump();
+                return self.parse_loop(Some(label), start);
+            },
+            _ => {}
+        }
+
+        if self.at_punct(Punct::LBrace) {
+            let (chained, _) = self.parse_block()?;
+            return Ok(Node::Chain { chained });
+        }
+
+        let expr = self.parse_assignment()?;
+        if !self.at_punct(Punct::RBrace) {
+            self.expect_semicolon();
+        }
+        Ok(expr)
+    }
+
+    /// `if cond { .. }`, optionally followed by `else { .. }` or `else if ..`.
+    fn parse_if(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::If)?.span;
+        let cond = self.parse_expr()?;
+        let (then, mut end) = self.parse_block()?;
+
+        let els = if self.peek().is_keyword(Keyword::Else) {
+            self.bump();
+            if self.peek().is_keyword(Keyword::If) {
+                let nested = self.parse_if()?;
+                end = nested.span();
+                Some(vec![nested])
+            } else {
+                let (els, span) = self.parse_block()?;
+                end = span;
+                Some(els)
+            }
+        } else {
+            None
+        };
+
+        Ok(Node::If {
+            cond: Box::new(cond),
+            then,
+            els,
+            span: start.join(end)
+        })
+    }
+
+    /// `while cond { .. }`, `for var in start..end { .. }` or `loop { .. }`, after the
+    /// loop's label if it has one; `start` is where the label or the loop begins.
+    fn parse_loop(&mut self, label: Option<Yarn<'a>>, start: Span) -> ParseResult<Node<'a>> {
+        let token = self.peek().clone();
+        match &token.kind {
+            TokenKind::Keyword(Keyword::While) => {
+                self.bump();
+                let cond = self.parse_expr()?;
+                let (body, end) = self.parse_block()?;
+                Ok(Node::While { label, cond: Box::new(cond), body, span: start.join(end) })
+            },
+            TokenKind::Keyword(Keyword::For) => {
+                self.bump();
+                let (name, span) = self.expect_ident()?;
+                let var = Node::Value { ret: VarDeclaration::Inferred { name: Some(name) }, span };
+                self.expect_keyword(Keyword::In)?;
+                let from = self.parse_expr()?;
+                self.expect_punct(Punct::DotDot)?;
+                let to = self.parse_expr()?;
+                let (body, end) = self.parse_block()?;
+                Ok(Node::For {
+                    label,
+                    var: Box::new(var),
+                    start: Box::new(from),
+                    end: Box::new(to),
+                    body,
+                    span: start.join(end)
+                })
+            },
+            TokenKind::Keyword(Keyword::Loop) => {
+                self.bump();
+                let (body, end) = self.parse_block()?;
+                Ok(Node::Loop { label, body, span: start.join(end) })
+            },
+            _ => Err(ParseError::expected("`while`, `for` or `loop` after a label", &token))
+        }
+    }
+
+    /// `break;` or `continue;`, either naming the loop it applies to.
+    fn parse_jump(&mut self) -> ParseResult<Node<'a>> {
+        let keyword = self.bump();
+        let label = match &self.peek().kind {
+            TokenKind::Ident(_) => Some(self.expect_ident()?.0),
+            _ => None
+        };
+        let span = keyword.span.join(self.prev_span());
+        if !self.at_punct(Punct::RBrace) {
+            self.expect_semicolon();
+        }
+
+        Ok(if keyword.is_keyword(Keyword::Break) {
+            Node::Break { label, span }
+        } else {
+            Node::Continue { label, span }
+        })
+    }
+
+    /// `return;` or `return value;`
+    fn parse_return(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Return)?.span;
+        let value = if self.at_punct(Punct::Semicolon) || self.at_punct(Punct::RBrace) {
+            None
+        } else {
+            Some(Box::new(self.parse_expr()?))
+        };
+        let span = start.join(self.prev_span());
+        if !self.at_punct(Punct::RBrace) {
+            self.expect_semicolon();
+        }
+        Ok(Node::Return { value, span })
+    }
+
+    /// `let name: Type;` or `let name: Type = expr;`
+    fn parse_let(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.expect_keyword(Keyword::Let)?.span;
+        let decl = if self.peek_nth(1).is_punct(Punct::Colon) {
+            self.parse_binding()?
+        } else {
+            let (name, _) = self.expect_ident()?;
+            VarDeclaration::Inferred { name: Some(name) }
+        };
+        let decl_span = start.join(self.prev_span());
+        let value = Node::Value { ret: decl, span: decl_span };
+
+        let node = if matches!(self.peek().kind, TokenKind::BinOp(BinOp::Assign)) {
+            self.bump();
+            let init = self.parse_expr()?;
+            let span = decl_span.join(init.span());
+            Node::BinaryOp {
+                lhs: Box::new(value),
+                rhs: Box::new(init),
+                op: BinOp::Assign,
+                span
+            }
+        } else {
+            value
+        };
+
+        self.expect_semicolon();
+        Ok(node)
+    }
+
+    pub fn parse_type(&mut self) -> ParseResult<Type<'a>> {
+        let token = self.peek().clone();
+        match &token.kind {
+            TokenKind::BinOp(BinOp::Multiply) => {
+                self.bump();
+                let is_unsafe = matches!(&self.peek().kind, TokenKind::Ident(name) if name.as_slice() == "unsafe");
+                if is_unsafe {
+                    self.bump();
+                    return Ok(Type::UnsafePtr(Box::new(self.parse_type()?)));
+                }
+                Ok(Type::SafePtr(Box::new(self.parse_type()?)))
+            },
+            TokenKind::Punct(Punct::LBracket) => {
+                self.bump();
+                let inner = self.parse_type()?;
+                if self.eat_punct(Punct::Semicolon) {
+                    let len = match self.bump() {
+                        Token { kind: TokenKind::Int(len), .. } => len as usize,
+                        other => return Err(ParseError::expected("array length", &other))
+                    };
+                    self.expect_punct(Punct::RBracket)?;
+                    return Ok(Type::Array(Box::new(inner), len));
+                }
+                self.expect_punct(Punct::RBracket)?;
+                Ok(Type::Slice(Box::new(inner)))
+            },
+            TokenKind::Ident(_) => {
+                let (name, _) = self.parse_path()?;
+                if self.generics.iter().any(|param| param.as_slice() == name.as_slice()) {
+                    return Ok(Type::Param(name));
+                }
+                if self.at_binop(BinOp::LessThan) {
+                    let args = self.parse_type_args()?;
+                    return Ok(Type::Applied(name, args));
+                }
+                Ok(Type::from_name(name.as_slice()).unwrap_or(Type::Named(name)))
+            },
+            _ => Err(ParseError::expected("type", &token))
+        }
+    }
+
+    /// `a` or `a::b::c`, joined into a single qualified name.
+    fn parse_path(&mut self) -> ParseResult<(Yarn<'a>, Span)> {
+        let (first, start) = self.expect_ident()?;
+        if !self.at_punct(Punct::PathSep) {
+            return Ok((first, start));
+        }
+
+        let mut path = String::from(first.as_slice());
+        let mut span = start;
+        while self.eat_punct(Punct::PathSep) {
+            let (segment, seg_span) = self.expect_ident()?;
+            path.push_str("::");
+            path.push_str(segment.as_slice());
+            span = span.join(seg_span);
+        }
+        Ok((Yarn::owned(path.into_boxed_str()), span))
+    }
+
+    /// An expression statement: `place = value` and the compound forms are right-associative
+    /// and only allowed here, never nested inside another expression.
+    pub fn parse_assignment(&mut self) -> ParseResult<Node<'a>> {
+        let lhs = self.parse_expr()?;
+
+        let op = match self.peek().kind {
+            TokenKind::BinOp(op) if op.is_assignment() => op,
+            _ => return Ok(lhs)
+        };
+
+        if !lhs.is_place() {
+            return Err(ParseError {
+                kind: ParseErrorKind::InvalidAssignTarget,
+                span: lhs.span()
+            });
+        }
+
+        self.bump();
+        let rhs = self.parse_assignment()?;
+        let span = lhs.span().join(rhs.span());
+        Ok(Node::BinaryOp {
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            op,
+            span
+        })
+    }
+
+    /// Any expression that is not an assignment.
+    pub fn parse_expr(&mut self) -> ParseResult<Node<'a>> {
+        self.parse_binary(BinOp::Assign.precedence() + 1)
+    }
+
+    /// Precedence climbing: folds every operator binding at least as tightly as `min_prec`.
+    fn parse_binary(&mut self, min_prec: u8) -> ParseResult<Node<'a>> {
+        let mut lhs = self.parse_unary()?;
+
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::BinOp(op) if !op.is_assignment() && op.precedence() >= min_prec => op,
+                _ => break
+            };
+            self.bump();
+
+            let next_min = if op.is_right_assoc() { op.precedence() } else { op.precedence() + 1 };
+            let rhs = self.parse_binary(next_min)?;
+            let span = lhs.span().join(rhs.span());
+            lhs = Node::BinaryOp {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                op,
+                span
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Node<'a>> {
+        let start = self.peek().span;
+        let op = match self.peek().kind {
+            TokenKind::UniOp(op) => Some(op),
+            TokenKind::BinOp(BinOp::Subtract) => Some(UniOp::Negative),
+            _ => None
+        };
+
+        if let Some(op) = op {
+            self.bump();
+            let operand = self.parse_unary()?;
+            if matches!(op, UniOp::Increment | UniOp::Decrement) && !operand.is_place() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::InvalidAssignTarget,
+                    span: operand.span()
+                });
+            }
+
+            let span = start.join(operand.span());
+            return Ok(Node::UnaryOp {
+                lhs: Box::new(operand),
+                op,
+                postfix: false,
+                span
+            });
+        }
+
+        self.parse_postfix()
+    }
+
+    fn parse_postfix(&mut self) -> ParseResult<Node<'a>> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            if self.eat_punct(Punct::Dot) {
+                let (name, name_span) = self.expect_ident()?;
+                if self.at_punct(Punct::LParen) {
+                    let args = self.parse_args()?;
+                    let span = expr.span().join(self.prev_span());
+                    expr = Node::ObjCall {
+                        recv: Box::new(expr),
+                        func: name,
+                        args,
+                        span
+                    };
+                } else {
+                    let span = expr.span().join(name_span);
+                    expr = Node::Field {
+                        recv: Box::new(expr),
+                        name,
+                        span
+                    };
+                }
+                continue;
+            }
+
+            if let TokenKind::UniOp(op @ (UniOp::Increment | UniOp::Decrement)) = self.peek().kind {
+                if !expr.is_place() {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidAssignTarget,
+                        span: expr.span()
+                    });
+                }
+
+                let span = expr.span().join(self.bump().span);
+                expr = Node::UnaryOp {
+                    lhs: Box::new(expr),
+                    op,
+                    postfix: true,
+                    span
+                };
+                continue;
+            }
+
+            return Ok(expr);
+        }
+    }
+
+    fn parse_args(&mut self) -> ParseResult<Vec<Node<'a>>> {
+        self.expect_punct(Punct::LParen)?;
+        let mut args = Vec::new();
+        while !self.at_punct(Punct::RParen) {
+            args.push(self.parse_expr()?);
+            if !self.eat_punct(Punct::Comma) {
+                break;
+            }
+        }
+        self.expect_punct(Punct::RParen)?;
+        Ok(args)
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Node<'a>> {
+        let token = self.peek().clone();
+        let literal = match &token.kind {
+            TokenKind::Int(value) => Some(Literal::Int(*value)),
+            TokenKind::Float(value) => Some(Literal::Float(*value)),
+            TokenKind::Str(value) => Some(Literal::Str(value.clone())),
+            TokenKind::Keyword(Keyword::True) => Some(Literal::Boolean(true)),
+            TokenKind::Keyword(Keyword::False) => Some(Literal::Boolean(false)),
+            _ => None
+        };
+
+        if let Some(value) = literal {
+            self.bump();
+            return Ok(Node::Literal { value, span: token.span });
+        }
+
+        match &token.kind {
+            TokenKind::Ident(_) => {
+                let (name, span) = self.parse_path()?;
+                if self.at_punct(Punct::LParen) {
+                    let args = self.parse_args()?;
+                    return Ok(Node::Call {
+                        func: name,
+                        args,
+                        span: span.join(self.prev_span())
+                    });
+                }
+                Ok(Node::Ident { name, span })
+            },
+            TokenKind::Punct(Punct::LParen) => {
+                self.bump();
+                let inner = self.parse_expr()?;
+                self.expect_punct(Punct::RParen)?;
+                Ok(inner)
+            },
+            TokenKind::Keyword(Keyword::If) => self.parse_if(),
+            _ => Err(ParseError::expected("expression", &token))
+        }
+    }
+}
+
+/// Lexes and parses a whole source file, reporting every error up to the default limit.
+pub(crate) fn parse<'a>(src: &'a Yarn<'a>) -> Result<Node<'a>, Vec<Diagnostic>> {
+    let mut parser = Parser::new(src);
+    let tree = parser.parse_program();
+    if parser.errors().iter().any(Diagnostic::is_error) {
+        return Err(parser.take_errors());
+    }
+    Ok(tree)
+}
+
+impl ToNodes for Yarn<'_> {
+    fn eval(&self) -> Result<Node<'_>, Diagnostic> {
+        parse(self).map_err(|mut errors| errors.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `node` with its grouping made explicit, as `(op lhs rhs)`.
+    fn grouped(node: &Node<'_>) -> String {
+        match node {
+            Node::BinaryOp { lhs, rhs, op, .. } => format!("({} {} {})", op.as_str(), grouped(lhs), grouped(rhs)),
+            Node::UnaryOp { lhs, op, .. } => format!("({} {})", op.as_str(), grouped(lhs)),
+            Node::Ident { name, .. } => name.to_string(),
+            Node::Literal { value: Literal::Int(value), .. } => value.to_string(),
+            Node::Literal { value: Literal::Boolean(value), .. } => value.to_string(),
+            _ => "?".to_string()
+        }
+    }
+
+    fn parse_expr(src: &str) -> String {
+        let yarn = Yarn::borrowed(src);
+        let mut parser = Parser::new(&yarn);
+        let expr = parser.parse_expr().expect("a valid expression");
+        assert!(parser.errors().is_empty(), "{}", src);
+        grouped(&expr)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse_expr("a + b * -c >= d && !e"), "(&& (>= (+ a (* b (- c))) d) (! e))");
+        assert_eq!(parse_expr("a || b && c == d"), "(|| a (&& b (== c d)))");
+        assert_eq!(parse_expr("a - b - c"), "(- (- a b) c)");
+        assert_eq!(parse_expr("(a + b) * c"), "(* (+ a b) c)");
+        assert_eq!(parse_expr("-a * b % c"), "(% (* (- a) b) c)");
+    }
+
+    /// Where the errors parsing `src` with `limit` point, the notes after them, and how
+    /// many items the tree still has.
+    fn errors(src: &str, limit: usize) -> (Vec<(usize, usize)>, Vec<String>, usize) {
+        let yarn = Yarn::borrowed(src);
+        let mut parser = Parser::new(&yarn).with_error_limit(limit);
+        let tree = parser.parse_program();
+        let items = crate::analysis::resolve::top_level(&tree).len();
+        let (errors, notes): (Vec<&Diagnostic>, Vec<&Diagnostic>) = parser.errors().iter().partition(|diag| diag.is_error());
+        let at = errors.iter().map(|diag| diag.primary_span().map(|span| (span.line, span.col)).unwrap()).collect();
+        (at, notes.iter().map(|diag| diag.message.clone()).collect(), items)
+    }
+
+    const BROKEN: &str = "\
+defun a() { let = 1; }
+defun b() => Int32 { 1 + ; }
+defun c() { d(; }
+obj P { x Int32; }
+defun e() { }
+";
+
+    #[test]
+    fn recovers_at_the_next_statement_or_item() {
+        let (at, notes, items) = errors(BROKEN, DEFAULT_ERROR_LIMIT);
+        assert_eq!(at, [(1, 17), (2, 26), (3, 15), (4, 11)]);
+        assert!(notes.is_empty());
+        assert_eq!(items, 5);
+    }
+
+    #[test]
+    fn stops_at_the_error_limit() {
+        let (at, notes, _) = errors(BROKEN, 2);
+        assert_eq!(at, [(1, 17), (2, 26)]);
+        assert_eq!(notes, ["stopped parsing after 2 errors"]);
+    }
+
+    #[test]
+    fn lexical_errors_count_toward_the_limit() {
+        let (at, notes, _) = errors("let a = $;\nlet b = #;\nlet c = 1 +;\n", 1);
+        assert_eq!(at, [(1, 9)]);
+        assert_eq!(notes, ["stopped parsing after 1 errors"]);
+    }
+}
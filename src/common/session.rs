@@ -0,0 +1,22 @@
+use crate::analysis::symbols::SymbolTable;
+
+use super::diagnostics::{Diagnostic, Diagnostics};
+
+/// State owned by a single compilation. Nothing here is global, so several
+/// compilations can run side by side in one process.
+#[derive(Default)]
+pub(crate) struct Session<'a> {
+    pub symbols: SymbolTable<'a>,
+    pub diagnostics: Diagnostics
+}
+
+impl<'a> Session<'a> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
@@ -1,7 +1,8 @@
-use std::{error::Error, fmt::Display};
+use std::fmt::Display;
 
-use super::yarn::{self, Yarn};
+use super::{diagnostics::{codes, Diagnostic}, lexer::{tokenize, Keyword, Punct, Span, Token, TokenKind}, yarn::{self, Yarn}};
 
+#[derive(Clone)]
 pub(crate) struct Attribute<'a> {
     name: yarn::Yarn<'a>,
     value: yarn::Yarn<'a>,
@@ -19,23 +20,107 @@ impl Attribute<'_> {
     }
 }
 
+/// A type parameter of a generic item, with the traits its arguments must be extended with.
+#[derive(Clone)]
+pub(crate) struct GenericParam<'a> {
+    name: Yarn<'a>,
+    bounds: Vec<Type<'a>>
+}
+
+impl<'a> GenericParam<'a> {
+
+    pub fn new(name: Yarn<'a>, bounds: Vec<Type<'a>>) -> Self {
+        Self {
+            name,
+            bounds
+        }
+    }
+
+    pub fn name(&self) -> &Yarn<'a> {
+        &self.name
+    }
+
+    /// The traits named after `:`, as `Named` or `Applied` types.
+    pub fn bounds(&self) -> &[Type<'a>] {
+        &self.bounds
+    }
+
+    /// The parameter as a type, for use inside the item declaring it.
+    pub fn as_type(&self) -> Type<'a> {
+        Type::Param(self.name.clone())
+    }
+
+    pub fn immortalize(self) -> GenericParam<'static> {
+        GenericParam::<'static> {
+            name: self.name.immortalize(),
+            bounds: self.bounds.into_iter().map(Type::immortalize).collect()
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct DefunDescriptor<'a> {
     name: yarn::Yarn<'a>,
     qualified: yarn::Yarn<'a>,
     attrs: Vec<Attribute<'a>>,
-    args: Vec<Box<VarDeclaration<'a>>>,
+    generics: Vec<GenericParam<'a>>,
+    args: Vec<VarDeclaration<'a>>,
     return_type: Box<Type<'a>>,
     in_scope: bool
 }
 
-impl DefunDescriptor<'_> {
+impl<'a> DefunDescriptor<'a> {
+
+    pub fn new(
+        name: Yarn<'a>,
+        qualified: Yarn<'a>,
+        args: Vec<VarDeclaration<'a>>,
+        return_type: Type<'a>
+    ) -> Self {
+        Self {
+            name,
+            qualified,
+            attrs: Vec::new(),
+            generics: Vec::new(),
+            args,
+            return_type: Box::new(return_type),
+            in_scope: true
+        }
+    }
+
+    pub fn with_generics(mut self, generics: Vec<GenericParam<'a>>) -> Self {
+        self.generics = generics;
+        self
+    }
+
+    pub fn name(&self) -> &Yarn<'a> {
+        &self.name
+    }
+
+    pub fn qualified(&self) -> &Yarn<'a> {
+        &self.qualified
+    }
+
+    /// The function's own type parameters, not those of the item it is a method of.
+    pub fn generics(&self) -> &[GenericParam<'a>] {
+        &self.generics
+    }
+
+    pub fn args(&self) -> &[VarDeclaration<'a>] {
+        &self.args
+    }
+
+    pub fn return_type(&self) -> &Type<'a> {
+        &self.return_type
+    }
 
     pub fn immortalize(self) -> DefunDescriptor<'static> {
         DefunDescriptor::<'static> {
             name: self.name.immortalize(),
             qualified: self.qualified.immortalize(),
             attrs: self.attrs.into_iter().map(|at| at.immortalize()).collect(),
-            args: self.args.into_iter().map(|ar| Box::new(ar.immortalize())).collect(),
+            generics: self.generics.into_iter().map(GenericParam::immortalize).collect(),
+            args: self.args.into_iter().map(|ar| ar.immortalize()).collect(),
             return_type: Box::new(self.return_type.immortalize()),
             in_scope: self.in_scope
         }
@@ -43,66 +128,249 @@ impl DefunDescriptor<'_> {
 
 }
 
+#[derive(Clone)]
 pub(crate) struct TraitDescriptor<'a> {
-    functions: DefunDescriptor<'a>,
-    asociated_aliases: Vec<Box<VarDeclaration<'a>>>,
+    name: yarn::Yarn<'a>,
+    generics: Vec<GenericParam<'a>>,
+    functions: Vec<DefunDescriptor<'a>>,
+    /// The methods of `functions` that have a default body.
+    defaults: Vec<Yarn<'a>>,
+    asociated_aliases: Vec<VarDeclaration<'a>>,
     in_scope: bool,
-    super_traits: Vec<Box<TraitDescriptor<'a>>>
+    super_traits: Vec<TraitDescriptor<'a>>
 }
 
-impl TraitDescriptor<'_> {
+impl<'a> TraitDescriptor<'a> {
+
+    pub fn new(
+        name: Yarn<'a>,
+        functions: Vec<DefunDescriptor<'a>>,
+        defaults: Vec<Yarn<'a>>,
+        super_traits: Vec<TraitDescriptor<'a>>
+    ) -> Self {
+        Self {
+            name,
+            generics: Vec::new(),
+            functions,
+            defaults,
+            asociated_aliases: Vec::new(),
+            in_scope: true,
+            super_traits
+        }
+    }
+
+    /// A by-name reference to a trait declared elsewhere, e.g. a supertrait bound.
+    pub fn reference(name: Yarn<'a>) -> Self {
+        Self {
+            name,
+            generics: Vec::new(),
+            functions: Vec::new(),
+            defaults: Vec::new(),
+            asociated_aliases: Vec::new(),
+            in_scope: false,
+            super_traits: Vec::new()
+        }
+    }
+
+    pub fn name(&self) -> &Yarn<'a> {
+        &self.name
+    }
+
+    pub fn with_generics(mut self, generics: Vec<GenericParam<'a>>) -> Self {
+        self.generics = generics;
+        self
+    }
+
+    pub fn generics(&self) -> &[GenericParam<'a>] {
+        &self.generics
+    }
+
+    pub fn functions(&self) -> &[DefunDescriptor<'a>] {
+        &self.functions
+    }
+
+    pub fn super_traits(&self) -> &[TraitDescriptor<'a>] {
+        &self.super_traits
+    }
+
+    /// Whether the method `name` has a body that implementations may leave out.
+    pub fn has_default(&self, name: &str) -> bool {
+        self.defaults.iter().any(|default| default.as_slice() == name)
+    }
 
     pub fn immortalize(self) -> TraitDescriptor<'static> {
         TraitDescriptor::<'static> {
-            functions: self.functions.immortalize(),
-            asociated_aliases: self.asociated_aliases.into_iter().map(|aa| Box::new(aa.immortalize())).collect(),
+            name: self.name.immortalize(),
+            generics: self.generics.into_iter().map(GenericParam::immortalize).collect(),
+            functions: self.functions.into_iter().map(|f| f.immortalize()).collect(),
+            defaults: self.defaults.into_iter().map(Yarn::immortalize).collect(),
+            asociated_aliases: self.asociated_aliases.into_iter().map(|aa| aa.immortalize()).collect(),
             in_scope: self.in_scope,
-            super_traits: self.super_traits.into_iter().map(|st| Box::new(st.immortalize())).collect()
+            super_traits: self.super_traits.into_iter().map(|st| st.immortalize()).collect()
+        }
+    }
+}
+
+/// An `extend Obj with Trait` block, implementing the methods of `Trait` for `Obj`. A
+/// generic `obj` is written `extend Box<T> with Trait`, declaring a parameter for each of
+/// its own; a generic trait takes its arguments as in `with Into<Int32>`.
+#[derive(Clone)]
+pub(crate) struct ExtendDescriptor<'a> {
+    obj: yarn::Yarn<'a>,
+    generics: Vec<GenericParam<'a>>,
+    with: yarn::Yarn<'a>,
+    trait_args: Vec<Type<'a>>,
+    functions: Vec<DefunDescriptor<'a>>
+}
+
+impl<'a> ExtendDescriptor<'a> {
+
+    pub fn new(obj: Yarn<'a>, with: Yarn<'a>, functions: Vec<DefunDescriptor<'a>>) -> Self {
+        Self {
+            obj,
+            generics: Vec::new(),
+            with,
+            trait_args: Vec::new(),
+            functions
         }
     }
+
+    pub fn with_generics(mut self, generics: Vec<GenericParam<'a>>, trait_args: Vec<Type<'a>>) -> Self {
+        self.generics = generics;
+        self.trait_args = trait_args;
+        self
+    }
+
+    pub fn obj(&self) -> &Yarn<'a> {
+        &self.obj
+    }
+
+    /// The parameters standing for the `obj`'s type arguments, in order.
+    pub fn generics(&self) -> &[GenericParam<'a>] {
+        &self.generics
+    }
+
+    /// The trait being implemented.
+    pub fn with(&self) -> &Yarn<'a> {
+        &self.with
+    }
+
+    pub fn trait_args(&self) -> &[Type<'a>] {
+        &self.trait_args
+    }
+
+    pub fn functions(&self) -> &[DefunDescriptor<'a>] {
+        &self.functions
+    }
 }
 
-type Traits<'a> = Vec<Box<TraitDescriptor<'a>>>;
+type Traits<'a> = Vec<TraitDescriptor<'a>>;
 
+/// An `obj` as written. The traits it is extended with come from `extend` blocks
+/// elsewhere, so they aren't recorded here: the `SymbolTable` collects them as
+/// `Implementation`s, and method lookup walks those.
+#[derive(Clone)]
 pub(crate) struct ObjDescriptor<'a> {
     name: yarn::Yarn<'a>,
-    fields: Vec<Box<VarDeclaration<'a>>>,
+    generics: Vec<GenericParam<'a>>,
+    fields: Vec<VarDeclaration<'a>>,
     attrs: Vec<Attribute<'a>>,
     in_scope: bool,
-    traits: Vec<Box<TraitDescriptor<'a>>>,
-    functions: Vec<Box<DefunDescriptor<'a>>>
+    functions: Vec<DefunDescriptor<'a>>
 }
 
-impl ObjDescriptor<'_> {
+impl<'a> ObjDescriptor<'a> {
+
+    pub fn new(
+        name: Yarn<'a>,
+        fields: Vec<VarDeclaration<'a>>,
+        functions: Vec<DefunDescriptor<'a>>
+    ) -> Self {
+        Self {
+            name,
+            generics: Vec::new(),
+            fields,
+            attrs: Vec::new(),
+            in_scope: true,
+            functions
+        }
+    }
+
+    pub fn name(&self) -> &Yarn<'a> {
+        &self.name
+    }
+
+    pub fn with_generics(mut self, generics: Vec<GenericParam<'a>>) -> Self {
+        self.generics = generics;
+        self
+    }
+
+    pub fn generics(&self) -> &[GenericParam<'a>] {
+        &self.generics
+    }
+
+    pub fn fields(&self) -> &[VarDeclaration<'a>] {
+        &self.fields
+    }
 
     pub fn immortalize(self) -> ObjDescriptor<'static> {
 
         ObjDescriptor::<'static> {
             name: self.name.immortalize(),
-            fields: self.fields.into_iter().map(|f| Box::new(f.immortalize())).collect(),
+            generics: self.generics.into_iter().map(GenericParam::immortalize).collect(),
+            fields: self.fields.into_iter().map(|f| f.immortalize()).collect(),
             attrs: self.attrs.into_iter().map(|at| at.immortalize()).collect(),
             in_scope: self.in_scope,
-            traits: self.traits.into_iter().map(|t| Box::new(t.immortalize())).collect(),
-            functions: self.functions.into_iter().map(|f| Box::new(f.immortalize())).collect(),
+            functions: self.functions.into_iter().map(|f| f.immortalize()).collect(),
         }
 
     }
 
 }
 
+#[derive(Clone)]
 pub(crate) struct CompDescriptor<'a> {
     name: yarn::Yarn<'a>,
-    fields: Vec<Box<VarDeclaration<'a>>>,
+    generics: Vec<GenericParam<'a>>,
+    fields: Vec<VarDeclaration<'a>>,
     attrs: Vec<Attribute<'a>>,
     in_scope: bool
 }
 
-impl CompDescriptor<'_> {
+impl<'a> CompDescriptor<'a> {
+
+    pub fn new(name: Yarn<'a>, fields: Vec<VarDeclaration<'a>>) -> Self {
+        Self {
+            name,
+            generics: Vec::new(),
+            fields,
+            attrs: Vec::new(),
+            in_scope: true
+        }
+    }
+
+    pub fn with_generics(mut self, generics: Vec<GenericParam<'a>>) -> Self {
+        self.generics = generics;
+        self
+    }
+
+    pub fn name(&self) -> &Yarn<'a> {
+        &self.name
+    }
+
+    pub fn generics(&self) -> &[GenericParam<'a>] {
+        &self.generics
+    }
+
+    pub fn fields(&self) -> &[VarDeclaration<'a>] {
+        &self.fields
+    }
 
     pub fn immortalize(self) -> CompDescriptor<'static> {
         CompDescriptor::<'static> {
             name: self.name.immortalize(),
-            fields: self.fields.into_iter().map(|f| Box::new(f.immortalize())).collect(),
+            generics: self.generics.into_iter().map(GenericParam::immortalize).collect(),
+            fields: self.fields.into_iter().map(|f| f.immortalize()).collect(),
             attrs: self.attrs.into_iter().map(|a| a.immortalize()).collect(),
             in_scope: self.in_scope
         }
@@ -110,51 +378,61 @@ impl CompDescriptor<'_> {
 
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub(crate) enum DeclError {
     LetAbsent,
+    MissingName,
     MissingColon,
     NoSemicolon,
     NoValidType
 }
 
-impl Display for DeclError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl DeclError {
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::LetAbsent => f.write_str("LetAbsent"),
-            Self::MissingColon => f.write_str("MissingColon"),
-            Self::NoSemicolon => f.write_str("NoSemicolon"),
-            Self::NoValidType => f.write_str("NoValidType")
+            Self::LetAbsent => codes::LET_ABSENT,
+            Self::MissingName => codes::MISSING_NAME,
+            Self::MissingColon => codes::MISSING_COLON,
+            Self::NoSemicolon => codes::NO_SEMICOLON,
+            Self::NoValidType => codes::NO_VALID_TYPE
         }
     }
-}
 
-#[derive(Debug)]
-pub(crate) struct VariableError {
-    decl: DeclError,
-    line: usize
-}
+    /// Reports this error at the offending token.
+    pub fn at(self, found: &Token<'_>) -> Diagnostic {
+        let (message, label) = match self {
+            Self::LetAbsent => ("declaration must start with `let`", "expected `let`"),
+            Self::MissingName => ("declaration is missing a name", "expected a variable name"),
+            Self::MissingColon => ("declaration is missing `:` before its type", "expected `:` or `;`"),
+            Self::NoSemicolon => ("declaration is not terminated", "expected `;`"),
+            Self::NoValidType => ("declaration does not name a valid type", "expected a type")
+        };
 
-impl VariableError {
-    pub fn new(
-        tpe: DeclError,
-        line: usize
-    ) -> Self {
-        Self {
-            decl: tpe,
-            line
+        let diag = Diagnostic::error(message)
+            .with_code(self.code())
+            .with_primary(found.span, format!("{}, found {}", label, found.kind));
+
+        match self {
+            Self::MissingColon => diag.with_help("declarations are written `let name: Type;`, or `let name;` to infer the type"),
+            Self::NoValidType => diag.with_note("builtin types are `Int8`..`Int64`, `Uint8`..`Uint64`, `Float8`..`Float64`, `Boolean` and `Str`"),
+            _ => diag
         }
     }
 }
 
-impl Display for VariableError {
+impl Display for DeclError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("VariableError: Type: {}, Line: {}", self.decl, self.line))
+        match self {
+            Self::LetAbsent => f.write_str("LetAbsent"),
+            Self::MissingName => f.write_str("MissingName"),
+            Self::MissingColon => f.write_str("MissingColon"),
+            Self::NoSemicolon => f.write_str("NoSemicolon"),
+            Self::NoValidType => f.write_str("NoValidType")
+        }
     }
 }
 
-impl Error for VariableError {}
-
+#[derive(Clone)]
 pub(crate) enum Type<'a> {
     Int8,
     Int16,
@@ -172,32 +450,236 @@ pub(crate) enum Type<'a> {
     Str,
     UnsafePtr(Box<Type<'a>>),
     SafePtr(Box<Type<'a>>),
-    Array(Box<Type<'a>>),
+    Array(Box<Type<'a>>, usize),
     Slice(Box<Type<'a>>),
     Object(Box<ObjDescriptor<'a>>),
     Composition(Box<CompDescriptor<'a>>),
-    Trait(Box<TraitDescriptor<'a>>)
+    Trait(Box<TraitDescriptor<'a>>),
+    /// A user-defined type referred to by name, before it is looked up.
+    Named(Yarn<'a>),
+    /// A generic user-defined type with its type arguments, as in `Box<Int32>`.
+    Applied(Yarn<'a>, Vec<Type<'a>>),
+    /// A type parameter of an enclosing generic item.
+    Param(Yarn<'a>),
+    /// The type of a `let` written without an annotation, left for the checker to infer.
+    Infer,
+    /// An inference variable of the type checker, standing for a type it has yet to decide.
+    Var(u32),
+    Void
+}
+
+impl<'a> Type<'a> {
+
+    /// The spellings `from_name` accepts.
+    pub const PRIMITIVES: [&'static str; 14] = [
+        "Int8", "Int16", "Int32", "Int64", "Uint8", "Uint16", "Uint32", "Uint64",
+        "Float8", "Float16", "Float32", "Float64", "Boolean", "Str"
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Int8" => Self::Int8,
+            "Int16" => Self::Int16,
+            "Int32" => Self::Int32,
+            "Int64" => Self::Int64,
+            "Uint8" => Self::Uint8,
+            "Uint16" => Self::Uint16,
+            "Uint32" => Self::Uint32,
+            "Uint64" => Self::Uint64,
+            "Float8" => Self::Float8,
+            "Float16" => Self::Float16,
+            "Float32" => Self::Float32,
+            "Float64" => Self::Float64,
+            "Boolean" => Self::Boolean,
+            "Str" => Self::Str,
+            _ => return None
+        })
+    }
+
+    /// Copies everything `self` borrows from its source, so the type can outlive it.
+    /// Nothing needs that yet: the REPL, which keeps types across entries, leaks each
+    /// entry's text instead.
+    #[allow(dead_code)]
+    pub fn immortalize(self) -> Type<'static> {
+        match self {
+            Type::Int8 => Type::Int8,
+            Type::Int16 => Type::Int16,
+            Type::Int32 => Type::Int32,
+            Type::Int64 => Type::Int64,
+            Type::Uint8 => Type::Uint8,
+            Type::Uint16 => Type::Uint16,
+            Type::Uint32 => Type::Uint32,
+            Type::Uint64 => Type::Uint64,
+            Type::Float8 => Type::Float8,
+            Type::Float16 => Type::Float16,
+            Type::Float32 => Type::Float32,
+            Type::Float64 => Type::Float64,
+            Type::Boolean => Type::Boolean,
+            Type::Str => Type::Str,
+            Type::UnsafePtr(ty) => Type::UnsafePtr(Box::new(ty.immortalize())),
+            Type::SafePtr(ty) => Type::SafePtr(Box::new(ty.immortalize())),
+            Type::Array(ty, len) => Type::Array(Box::new(ty.immortalize()), len),
+            Type::Slice(ty) => Type::Slice(Box::new(ty.immortalize())),
+            Type::Object(ty) => Type::Object(Box::new(ty.immortalize())),
+            Type::Composition(ty) => Type::Composition(Box::new(ty.immortalize())),
+            Type::Trait(ty) => Type::Trait(Box::new(ty.immortalize())),
+            Type::Named(name) => Type::Named(name.immortalize()),
+            Type::Applied(name, args) => Type::Applied(name.immortalize(), args.into_iter().map(Type::immortalize).collect()),
+            Type::Param(name) => Type::Param(name.immortalize()),
+            Type::Infer => Type::Infer,
+            Type::Var(id) => Type::Var(id),
+            Type::Void => Type::Void
+        }
+    }
+
+    /// `self` with every type parameter `lookup` knows replaced by its argument.
+    pub fn substitute(&self, lookup: &impl Fn(&str) -> Option<Type<'a>>) -> Type<'a> {
+        match self {
+            Self::Param(name) => lookup(name.as_slice()).unwrap_or_else(|| self.clone()),
+            Self::Applied(name, args) => Self::Applied(name.clone(), args.iter().map(|arg| arg.substitute(lookup)).collect()),
+            Self::UnsafePtr(inner) => Self::UnsafePtr(Box::new(inner.substitute(lookup))),
+            Self::SafePtr(inner) => Self::SafePtr(Box::new(inner.substitute(lookup))),
+            Self::Array(inner, len) => Self::Array(Box::new(inner.substitute(lookup)), *len),
+            Self::Slice(inner) => Self::Slice(Box::new(inner.substitute(lookup))),
+            _ => self.clone()
+        }
+    }
+
+    /// `self` with the parameters `generics` replaced by `args`, position for position.
+    pub fn instantiate(&self, generics: &[GenericParam<'a>], args: &[Type<'a>]) -> Type<'a> {
+        if generics.is_empty() {
+            return self.clone();
+        }
+        self.substitute(&|name| generics.iter()
+            .position(|param| param.name.as_slice() == name)
+            .and_then(|idx| args.get(idx).cloned()))
+    }
+
+    /// The type arguments of an `Applied` type; other types have none.
+    pub fn type_args(&self) -> &[Type<'a>] {
+        match self {
+            Self::Applied(_, args) => args,
+            _ => &[]
+        }
+    }
 }
 
 impl Type<'_> {
 
-    pub fn immortalize(mut self) -> Type<'static> {
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Self::Int8 | Self::Int16 | Self::Int32 | Self::Int64 | Self::Uint8 | Self::Uint16 | Self::Uint32 | Self::Uint64
+        )
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Self::Int8 | Self::Int16 | Self::Int32 | Self::Int64) || self.is_float()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Float8 | Self::Float16 | Self::Float32 | Self::Float64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self, Self::Void)
+    }
+
+    /// Width in bits of a numeric type.
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Self::Int8 | Self::Uint8 | Self::Float8 => Some(8),
+            Self::Int16 | Self::Uint16 | Self::Float16 => Some(16),
+            Self::Int32 | Self::Uint32 | Self::Float32 => Some(32),
+            Self::Int64 | Self::Uint64 | Self::Float64 => Some(64),
+            _ => None
+        }
+    }
+
+    /// Inclusive value range of an integer type.
+    pub fn int_range(&self) -> Option<(i128, i128)> {
+        let bits = self.bits()?;
+        match self {
+            Self::Int8 | Self::Int16 | Self::Int32 | Self::Int64 => Some((-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)),
+            Self::Uint8 | Self::Uint16 | Self::Uint32 | Self::Uint64 => Some((0, (1i128 << bits) - 1)),
+            _ => None
+        }
+    }
+
+    /// The declared name of a user-defined type.
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            Self::Object(obj) => Some(obj.name.as_slice()),
+            Self::Composition(comp) => Some(comp.name.as_slice()),
+            Self::Trait(tr) => Some(tr.name.as_slice()),
+            Self::Named(name) | Self::Applied(name, _) => Some(name.as_slice()),
+            _ => None
+        }
+    }
+}
+
+/// Builtin types compare structurally; user-defined types compare by name, so a
+/// `Named` reference equals the descriptor it names, and applied types by their type
+/// arguments as well.
+impl PartialEq for Type<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Applied(a, x), Self::Applied(b, y)) => a == b && x == y,
+            (Self::Param(a), Self::Param(b)) => a == b,
+            (Self::Var(a), Self::Var(b)) => a == b,
+            (Self::UnsafePtr(a), Self::UnsafePtr(b))
+            | (Self::SafePtr(a), Self::SafePtr(b))
+            | (Self::Slice(a), Self::Slice(b)) => a == b,
+            (Self::Array(a, n), Self::Array(b, m)) => n == m && a == b,
+            _ => match (self.type_name(), other.type_name()) {
+                (Some(a), Some(b)) => a == b,
+                (None, None) => std::mem::discriminant(self) == std::mem::discriminant(other),
+                _ => false
+            }
+        }
+    }
+}
+
+impl Display for Type<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Type::UnsafePtr(ty) => Type::<'static>::UnsafePtr(Box::new(ty.immortalize())),
-            Type::SafePtr(ty) => Type::<'static>::UnsafePtr(Box::new(ty.immortalize())),
-            Type::Array(ty) => Type::<'static>::Array(Box::new(ty.immortalize())),
-            Type::Slice(ty) => Type::<'static>::Array(Box::new(ty.immortalize())),
-            Type::Object(ty) => Type::<'static>::Object(Box::new(ty.immortalize())),
-            Type::Composition(ty) => Type::<'static>::Composition(Box::new(ty.immortalize())),
-            Type::Trait(ty) => Type::<'static>::Trait(Box::new(ty.immortalize())),
-            Type::Int8 => Type::<'static>::Int8,
-            Type::Int16 => Type::<'static>::Int16,
-            Type::Int32 => Type::<'static>::Int32,
-            _ => todo!()
+            Self::Int8 => f.write_str("Int8"),
+            Self::Int16 => f.write_str("Int16"),
+            Self::Int32 => f.write_str("Int32"),
+            Self::Int64 => f.write_str("Int64"),
+            Self::Uint8 => f.write_str("Uint8"),
+            Self::Uint16 => f.write_str("Uint16"),
+            Self::Uint32 => f.write_str("Uint32"),
+            Self::Uint64 => f.write_str("Uint64"),
+            Self::Float8 => f.write_str("Float8"),
+            Self::Float16 => f.write_str("Float16"),
+            Self::Float32 => f.write_str("Float32"),
+            Self::Float64 => f.write_str("Float64"),
+            Self::Boolean => f.write_str("Boolean"),
+            Self::Str => f.write_str("Str"),
+            Self::UnsafePtr(ty) => write!(f, "*unsafe {}", ty),
+            Self::SafePtr(ty) => write!(f, "*{}", ty),
+            Self::Array(ty, len) => write!(f, "[{}; {}]", ty, len),
+            Self::Slice(ty) => write!(f, "[{}]", ty),
+            Self::Object(obj) => write!(f, "{}", obj.name),
+            Self::Composition(comp) => write!(f, "{}", comp.name),
+            Self::Trait(tr) => write!(f, "{}", tr.name),
+            Self::Named(name) | Self::Param(name) => write!(f, "{}", name),
+            Self::Applied(name, args) => {
+                let args: Vec<String> = args.iter().map(Type::to_string).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            },
+            Self::Infer | Self::Var(_) => f.write_str("_"),
+            Self::Void => f.write_str("Void")
         }
     }
 }
 
+#[derive(Clone)]
 pub(crate) enum VarDeclaration<'a> {
     Int8 {
         active_traits: Traits<'a>,
@@ -264,7 +746,7 @@ pub(crate) enum VarDeclaration<'a> {
         active_traits: Traits<'a>,
         name: Option<Yarn<'a>>,
         ptr_type: Box<Type<'a>>,
-        ptr_delagate: Box<ObjDescriptor<'a>>
+        ptr_delagate: Option<Box<ObjDescriptor<'a>>>
     },
     Array {
         active_traits: Traits<'a>,
@@ -290,156 +772,237 @@ pub(crate) enum VarDeclaration<'a> {
         name: Option<Yarn<'a>>,
         inner: Box<TraitDescriptor<'a>>
     },
+    Named {
+        active_traits: Traits<'a>,
+        name: Option<Yarn<'a>>,
+        type_name: Yarn<'a>
+    },
+    Applied {
+        active_traits: Traits<'a>,
+        name: Option<Yarn<'a>>,
+        type_name: Yarn<'a>,
+        args: Vec<Type<'a>>
+    },
+    Param {
+        name: Option<Yarn<'a>>,
+        param: Yarn<'a>
+    },
+    /// `let name = value;`, whose type the checker infers from its uses.
+    Inferred {
+        name: Option<Yarn<'a>>
+    },
 }
 
 impl<'a> VarDeclaration<'a> {
     
-    // obj Foo {}
-    
-    // TODO: Better Error Handling
-    pub fn from_yarn(string: &'a Yarn<'a>) -> Result<Self, VariableError> {
-        let parsed = string.spilt(' ');
-        let mut last = parsed[1].clone();
-
-        for i in 2..parsed.len() {
-            match parsed[i].as_slice() {
-                "Int8" => {
-                    if !last.as_slice().ends_with(':') {
-                        return Err(VariableError::new(
-                            DeclError::MissingColon,
-                            line!() as usize
-                        ));
-                    }
-
-                    if parsed[i - 2].clone().as_slice() != "let" {
-                        return Err(VariableError::new(
-                            DeclError::LetAbsent,
-                            line!() as usize
-                        ));
-                    }
-
-                    let name: Box<str> = last.as_slice()[..last.len() - 1].into();
-
-                    return Ok(Self::Int8 {
-                        active_traits: Vec::new(),
-                        name: Some(Yarn::owned(name))
-                    });
-                },
-                "Int16" => {
-                    if !last.as_slice().ends_with(':') {
-                        return Err(VariableError::new(
-                            DeclError::MissingColon,
-                            line!() as usize
-                        ));
-                    }
-
-                    if parsed[i - 2].clone().as_slice() != "let" {
-                        return Err(VariableError::new(
-                            DeclError::LetAbsent,
-                            line!() as usize
-                        ));
-                    }
-
-                    let name: Box<str> = last.as_slice()[..last.len() - 1].into();
-
-                    return Ok(Self::Int16 {
-                        active_traits: Vec::new(),
-                        name: Some(Yarn::owned(name))
-                    });
-                },
-                "Int32" => {
-                    if !last.as_slice().ends_with(':') {
-                        return Err(VariableError::new(
-                            DeclError::MissingColon,
-                            line!() as usize
-                        ));
-                    }
-
-                    if parsed[i - 2].clone().as_slice() != "let" {
-                        return Err(VariableError::new(
-                            DeclError::MissingColon,
-                            line!() as usize
-                        ));
-                    }
-
-                    let name: Box<str> = last.as_slice()[..last.len() - 1].into();
-
-                    return Ok(Self::Int32{
-                        active_traits: Vec::new(),
-                        name: Some(Yarn::owned(name))
-                    });
-                },
-                "Int64" => {
-
-                },
-                "Uint8" => {
-
-                },
-                "Uint16" => {
-
-                },
-                "Uint32" => {
-
-                },
-                "Uint64" => {
-
-                },
-                "Float8" => {
-
-                },
-                "Float16" => {
-
-                },
-                "Float32" => {
-
-                },
-                "Float64" => {
-
-                },
-                "Boolean" => {
-
-                },
-                "Str" => {
-
-                },
-                "*unsafe" => {
-                    
-                },
-                "obj" => {},
-                "comp" => {},
-                "trait" => {},
-                "extend" => {},
-                _ => {
-                    //TODO: Add Support for Arrays, Slices, and Safe pointers in here
-
-                    if i == parsed.len() {
-                        return Err(VariableError::new(
-                            DeclError::NoValidType,
-                            line!() as usize
-                        ));
-                    }
-                    continue;
-                }
-            }
-            last = parsed[i].clone();
+    /// Parses a single `let name: Type;` or `let name;` declaration. The compiler itself
+    /// only parses whole files, so nothing in it calls this yet.
+    #[allow(dead_code)]
+    pub fn from_yarn(string: &'a Yarn<'a>) -> Result<Self, Diagnostic> {
+        let tokens = tokenize(string)?;
+        let mut tokens = tokens.into_iter();
+        let mut next = || tokens.next().unwrap_or_else(|| Token {
+            kind: TokenKind::Eof,
+            span: Span::default()
+        });
+
+        let head = next();
+        if !head.is_keyword(Keyword::Let) {
+            return Err(DeclError::LetAbsent.at(&head));
+        }
+
+        let name = match next() {
+            Token { kind: TokenKind::Ident(name), .. } => name,
+            other => return Err(DeclError::MissingName.at(&other))
+        };
+
+        let colon = next();
+        if colon.is_punct(Punct::Semicolon) {
+            return Ok(Self::Inferred { name: Some(name) });
         }
+        if !colon.is_punct(Punct::Colon) {
+            return Err(DeclError::MissingColon.at(&colon));
+        }
+
+        let ty = next();
+        let decl = match &ty.kind {
+            TokenKind::Ident(ty_name) => Self::primitive(ty_name.as_slice(), name),
+            _ => None
+        };
+        let decl = decl.ok_or_else(|| DeclError::NoValidType.at(&ty))?;
+
+        let semi = next();
+        if !semi.is_punct(Punct::Semicolon) {
+            return Err(DeclError::NoSemicolon.at(&semi));
+        }
+
+        Ok(decl)
+    }
+
+    /// Builds the declaration for a builtin scalar type name such as `Int8` or `Str`.
+    pub fn primitive(ty_name: &str, name: Yarn<'a>) -> Option<Self> {
+        Self::new(Type::from_name(ty_name)?, Some(name))
+    }
 
-        todo!()
+    /// Builds a declaration of type `ty`. `Type::Void` has no values, so it yields `None`.
+    pub fn new(ty: Type<'a>, name: Option<Yarn<'a>>) -> Option<Self> {
+        let active_traits = Vec::new();
+
+        Some(match ty {
+            Type::Int8 => Self::Int8 { active_traits, name },
+            Type::Int16 => Self::Int16 { active_traits, name },
+            Type::Int32 => Self::Int32 { active_traits, name },
+            Type::Int64 => Self::Int64 { active_traits, name },
+            Type::Uint8 => Self::Uint8 { active_traits, name },
+            Type::Uint16 => Self::Uint16 { active_traits, name },
+            Type::Uint32 => Self::Uint32 { active_traits, name },
+            Type::Uint64 => Self::Uint64 { active_traits, name },
+            Type::Float8 => Self::Float8 { active_traits, name },
+            Type::Float16 => Self::Float16 { active_traits, name },
+            Type::Float32 => Self::Float32 { active_traits, name },
+            Type::Float64 => Self::Float64 { active_traits, name },
+            Type::Boolean => Self::Boolean { active_traits, name },
+            Type::Str => Self::Str { active_traits, name },
+            Type::UnsafePtr(ptr_type) => Self::UnsafePtr { active_traits, name, ptr_type },
+            Type::SafePtr(ptr_type) => Self::SafePtr { active_traits, name, ptr_type, ptr_delagate: None },
+            Type::Array(arr_type, number) => Self::Array { active_traits, name, arr_type, number },
+            Type::Slice(slice_type) => Self::Slice { active_traits, name, slice_type, len: 0 },
+            Type::Object(inner) => Self::Object { name, inner },
+            Type::Composition(inner) => Self::Composition { name, inner },
+            Type::Trait(inner) => Self::Trait { name, inner },
+            Type::Named(type_name) => Self::Named { active_traits, name, type_name },
+            Type::Applied(type_name, args) => Self::Applied { active_traits, name, type_name, args },
+            Type::Param(param) => Self::Param { name, param },
+            Type::Infer | Type::Var(_) => Self::Inferred { name },
+            Type::Void => return None
+        })
     }
 
-    pub fn immortalize(mut self) -> VarDeclaration<'static> {
+    pub fn name(&self) -> Option<&Yarn<'a>> {
         match self {
-            Self::Int8 { active_traits, name } => {
-                todo!()
+            Self::Int8 { name, .. }
+            | Self::Int16 { name, .. }
+            | Self::Int32 { name, .. }
+            | Self::Int64 { name, .. }
+            | Self::Uint8 { name, .. }
+            | Self::Uint16 { name, .. }
+            | Self::Uint32 { name, .. }
+            | Self::Uint64 { name, .. }
+            | Self::Float8 { name, .. }
+            | Self::Float16 { name, .. }
+            | Self::Float32 { name, .. }
+            | Self::Float64 { name, .. }
+            | Self::Boolean { name, .. }
+            | Self::Str { name, .. }
+            | Self::UnsafePtr { name, .. }
+            | Self::SafePtr { name, .. }
+            | Self::Array { name, .. }
+            | Self::Slice { name, .. }
+            | Self::Object { name, .. }
+            | Self::Composition { name, .. }
+            | Self::Trait { name, .. }
+            | Self::Named { name, .. }
+            | Self::Applied { name, .. }
+            | Self::Param { name, .. }
+            | Self::Inferred { name } => name.as_ref()
+        }
+    }
+
+    pub fn ty(&self) -> Type<'a> {
+        match self {
+            Self::Int8 { .. } => Type::Int8,
+            Self::Int16 { .. } => Type::Int16,
+            Self::Int32 { .. } => Type::Int32,
+            Self::Int64 { .. } => Type::Int64,
+            Self::Uint8 { .. } => Type::Uint8,
+            Self::Uint16 { .. } => Type::Uint16,
+            Self::Uint32 { .. } => Type::Uint32,
+            Self::Uint64 { .. } => Type::Uint64,
+            Self::Float8 { .. } => Type::Float8,
+            Self::Float16 { .. } => Type::Float16,
+            Self::Float32 { .. } => Type::Float32,
+            Self::Float64 { .. } => Type::Float64,
+            Self::Boolean { .. } => Type::Boolean,
+            Self::Str { .. } => Type::Str,
+            Self::UnsafePtr { ptr_type, .. } => Type::UnsafePtr(ptr_type.clone()),
+            Self::SafePtr { ptr_type, .. } => Type::SafePtr(ptr_type.clone()),
+            Self::Array { arr_type, number, .. } => Type::Array(arr_type.clone(), *number),
+            Self::Slice { slice_type, .. } => Type::Slice(slice_type.clone()),
+            Self::Object { inner, .. } => Type::Object(inner.clone()),
+            Self::Composition { inner, .. } => Type::Composition(inner.clone()),
+            Self::Trait { inner, .. } => Type::Trait(inner.clone()),
+            Self::Named { type_name, .. } => Type::Named(type_name.clone()),
+            Self::Applied { type_name, args, .. } => Type::Applied(type_name.clone(), args.clone()),
+            Self::Param { param, .. } => Type::Param(param.clone()),
+            Self::Inferred { .. } => Type::Infer
+        }
+    }
+
+    pub fn immortalize(self) -> VarDeclaration<'static> {
+        let traits = |traits: Traits<'a>| traits.into_iter().map(TraitDescriptor::immortalize).collect();
+        let named = |name: Option<Yarn<'a>>| name.map(Yarn::immortalize);
+        match self {
+            Self::Int8 { active_traits, name } => VarDeclaration::Int8 { active_traits: traits(active_traits), name: named(name) },
+            Self::Int16 { active_traits, name } => VarDeclaration::Int16 { active_traits: traits(active_traits), name: named(name) },
+            Self::Int32 { active_traits, name } => VarDeclaration::Int32 { active_traits: traits(active_traits), name: named(name) },
+            Self::Int64 { active_traits, name } => VarDeclaration::Int64 { active_traits: traits(active_traits), name: named(name) },
+            Self::Uint8 { active_traits, name } => VarDeclaration::Uint8 { active_traits: traits(active_traits), name: named(name) },
+            Self::Uint16 { active_traits, name } => VarDeclaration::Uint16 { active_traits: traits(active_traits), name: named(name) },
+            Self::Uint32 { active_traits, name } => VarDeclaration::Uint32 { active_traits: traits(active_traits), name: named(name) },
+            Self::Uint64 { active_traits, name } => VarDeclaration::Uint64 { active_traits: traits(active_traits), name: named(name) },
+            Self::Float8 { active_traits, name } => VarDeclaration::Float8 { active_traits: traits(active_traits), name: named(name) },
+            Self::Float16 { active_traits, name } => VarDeclaration::Float16 { active_traits: traits(active_traits), name: named(name) },
+            Self::Float32 { active_traits, name } => VarDeclaration::Float32 { active_traits: traits(active_traits), name: named(name) },
+            Self::Float64 { active_traits, name } => VarDeclaration::Float64 { active_traits: traits(active_traits), name: named(name) },
+            Self::Boolean { active_traits, name } => VarDeclaration::Boolean { active_traits: traits(active_traits), name: named(name) },
+            Self::Str { active_traits, name } => VarDeclaration::Str { active_traits: traits(active_traits), name: named(name) },
+            Self::UnsafePtr { active_traits, name, ptr_type } => VarDeclaration::UnsafePtr {
+                active_traits: traits(active_traits),
+                name: named(name),
+                ptr_type: Box::new(ptr_type.immortalize())
+            },
+            Self::SafePtr { active_traits, name, ptr_type, ptr_delagate } => VarDeclaration::SafePtr {
+                active_traits: traits(active_traits),
+                name: named(name),
+                ptr_type: Box::new(ptr_type.immortalize()),
+                ptr_delagate: ptr_delagate.map(|obj| Box::new(obj.immortalize()))
             },
-            _ =>  todo!()
+            Self::Array { active_traits, name, arr_type, number } => VarDeclaration::Array {
+                active_traits: traits(active_traits),
+                name: named(name),
+                arr_type: Box::new(arr_type.immortalize()),
+                number
+            },
+            Self::Slice { active_traits, name, slice_type, len } => VarDeclaration::Slice {
+                active_traits: traits(active_traits),
+                name: named(name),
+                slice_type: Box::new(slice_type.immortalize()),
+                len
+            },
+            Self::Object { name, inner } => VarDeclaration::Object { name: named(name), inner: Box::new(inner.immortalize()) },
+            Self::Composition { name, inner } => VarDeclaration::Composition { name: named(name), inner: Box::new(inner.immortalize()) },
+            Self::Trait { name, inner } => VarDeclaration::Trait { name: named(name), inner: Box::new(inner.immortalize()) },
+            Self::Named { active_traits, name, type_name } => VarDeclaration::Named {
+                active_traits: traits(active_traits),
+                name: named(name),
+                type_name: type_name.immortalize()
+            },
+            Self::Applied { active_traits, name, type_name, args } => VarDeclaration::Applied {
+                active_traits: traits(active_traits),
+                name: named(name),
+                type_name: type_name.immortalize(),
+                args: args.into_iter().map(Type::immortalize).collect()
+            },
+            Self::Param { name, param } => VarDeclaration::Param { name: named(name), param: param.immortalize() },
+            Self::Inferred { name } => VarDeclaration::Inferred { name: named(name) }
         }
     }
 
 } 
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum BinOp {
+    Assign,
     Add,
     AddAssign,
     Subtract,
@@ -461,8 +1024,10 @@ pub(crate) enum BinOp {
 }
 
 impl BinOp {
-    pub fn parse_op<'a>(yarn: &Yarn<'a>) -> Result<Self, ()> {
+    /// Callers attach the operator's span to the returned diagnostic.
+    pub fn parse_op<'a>(yarn: &Yarn<'a>) -> Result<Self, Diagnostic> {
         match yarn.as_slice() {
+            "=" => Ok(Self::Assign),
             "+" => Ok(Self::Add),
             "+=" => Ok(Self::AddAssign),
             "-" => Ok(Self::Subtract),
@@ -481,11 +1046,88 @@ impl BinOp {
             "<=" => Ok(Self::LessThanEq),
             "||" => Ok(Self::LogOr),
             "&&" => Ok(Self::LogAnd),
-            _ => Err(())
+            other => Err(Diagnostic::error(format!("unknown operator `{}`", other))
+                .with_code(codes::UNKNOWN_OPERATOR))
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Assign => "=",
+            Self::Add => "+",
+            Self::AddAssign => "+=",
+            Self::Subtract => "-",
+            Self::SubAssign => "-=",
+            Self::Divide => "/",
+            Self::DivAssign => "/=",
+            Self::Multiply => "*",
+            Self::MulAssign => "*=",
+            Self::Modulus => "%",
+            Self::ModAssign => "%=",
+            Self::Equals => "==",
+            Self::NotEquals => "!=",
+            Self::GreaterThan => ">",
+            Self::GreaterThanEq => ">=",
+            Self::LessThan => "<",
+            Self::LessThanEq => "<=",
+            Self::LogOr => "||",
+            Self::LogAnd => "&&"
+        }
+    }
+
+    /// Binding power used by the expression parser; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::Assign
+            | Self::AddAssign
+            | Self::SubAssign
+            | Self::DivAssign
+            | Self::MulAssign
+            | Self::ModAssign => 1,
+            Self::LogOr => 2,
+            Self::LogAnd => 3,
+            Self::Equals | Self::NotEquals => 4,
+            Self::GreaterThan | Self::GreaterThanEq | Self::LessThan | Self::LessThanEq => 5,
+            Self::Add | Self::Subtract => 6,
+            Self::Multiply | Self::Divide | Self::Modulus => 7
+        }
+    }
+
+    pub fn is_assignment(&self) -> bool {
+        self.precedence() == 1
+    }
+
+    pub fn is_right_assoc(&self) -> bool {
+        self.is_assignment()
+    }
+
+    /// The arithmetic operator a compound assignment applies, e.g. `Add` for `+=`.
+    pub fn compound_base(&self) -> Option<BinOp> {
+        match self {
+            Self::AddAssign => Some(Self::Add),
+            Self::SubAssign => Some(Self::Subtract),
+            Self::DivAssign => Some(Self::Divide),
+            Self::MulAssign => Some(Self::Multiply),
+            Self::ModAssign => Some(Self::Modulus),
+            _ => None
+        }
+    }
+
+    /// What an operator was trying to do, for "attempt to ... with overflow" and the
+    /// like.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Subtract => "subtract",
+            Self::Multiply => "multiply",
+            Self::Divide => "divide",
+            Self::Modulus => "calculate the remainder",
+            _ => "compare"
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum UniOp {
     Increment,
     Decrement,
@@ -494,13 +1136,34 @@ pub(crate) enum UniOp {
     BitNot
 }
 
+impl UniOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Increment => "++",
+            Self::Decrement => "--",
+            Self::Negative => "-",
+            Self::LogNot => "!",
+            Self::BitNot => "~"
+        }
+    }
+}
+
 pub(crate) enum Bodies<'a> {
     Object(ObjDescriptor<'a>),
     Composition(CompDescriptor<'a>),
     Trait(TraitDescriptor<'a>),
+    Extension(ExtendDescriptor<'a>),
     Defun(DefunDescriptor<'a>),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Literal<'a> {
+    Int(u64),
+    Float(f64),
+    Str(Yarn<'a>),
+    Boolean(bool)
+}
+
 pub enum Node<'a> {
     Head {
         next: Box<Node<'a>>
@@ -508,55 +1171,214 @@ pub enum Node<'a> {
     BinaryOp {
         lhs: Box<Node<'a>>,
         rhs: Box<Node<'a>>,  
-        op: BinOp
+        op: BinOp,
+        span: Span
     },
     UnaryOp {
         lhs: Box<Node<'a>>,
-        op: UniOp
+        op: UniOp,
+        /// Only `Increment`/`Decrement` may be postfix (`x++`).
+        postfix: bool,
+        span: Span
     },
     Body {
         discriptor: Box<Bodies<'a>>,
-        body: Vec<Box<Node<'a>>>
+        body: Vec<Node<'a>>,
+        span: Span
     },
+    /// A `let` declaration; initialized declarations are the `lhs` of a `BinOp::Assign`.
     Value {
-       ret: VarDeclaration<'a> 
+       ret: VarDeclaration<'a>,
+       span: Span
+    },
+    Literal {
+        value: Literal<'a>,
+        span: Span
+    },
+    Ident {
+        name: Yarn<'a>,
+        span: Span
     },
     Call {
-        func: Box<DefunDescriptor<'a>>,
+        func: Yarn<'a>,
+        args: Vec<Node<'a>>,
+        span: Span
     },
     Chain {
-        chained: Vec<Box<Node<'a>>>
+        chained: Vec<Node<'a>>
     },
     ObjCall {
-        obj: Box<ObjDescriptor<'a>>,
-        func: Box<DefunDescriptor<'a>>
+        recv: Box<Node<'a>>,
+        func: Yarn<'a>,
+        args: Vec<Node<'a>>,
+        span: Span
+    },
+    Field {
+        recv: Box<Node<'a>>,
+        name: Yarn<'a>,
+        span: Span
+    },
+    /// `if cond { then } else { els }`, which has the value of the branch taken when both
+    /// have one. An `else if` is an `If` alone in `els`.
+    If {
+        cond: Box<Node<'a>>,
+        then: Vec<Node<'a>>,
+        els: Option<Vec<Node<'a>>>,
+        span: Span
+    },
+    /// `label: while cond { body }`; loops take an optional label for `break` and `continue`.
+    While {
+        label: Option<Yarn<'a>>,
+        cond: Box<Node<'a>>,
+        body: Vec<Node<'a>>,
+        span: Span
+    },
+    /// `label: for var in start..end { body }`, running `body` with `var` counting up from
+    /// `start` to just before `end`. `var` is the `Value` declaring the counter, whose
+    /// type is inferred from the bounds.
+    For {
+        label: Option<Yarn<'a>>,
+        var: Box<Node<'a>>,
+        start: Box<Node<'a>>,
+        end: Box<Node<'a>>,
+        body: Vec<Node<'a>>,
+        span: Span
+    },
+    /// `label: loop { body }`, which only ends by a `break` or `return`.
+    Loop {
+        label: Option<Yarn<'a>>,
+        body: Vec<Node<'a>>,
+        span: Span
+    },
+    /// `break label;`, leaving the innermost loop without a label.
+    Break {
+        label: Option<Yarn<'a>>,
+        span: Span
+    },
+    /// `continue label;`, going on with the next iteration.
+    Continue {
+        label: Option<Yarn<'a>>,
+        span: Span
+    },
+    /// `return value;`, or `return;` from a function returning `Void`.
+    Return {
+        value: Option<Box<Node<'a>>>,
+        span: Span
+    },
+    /// Stands in for a construct that failed to parse, so later stages can keep going.
+    Error {
+        span: Span
     }
 }
 
 impl<'a> Node<'a> {
 
-    pub fn is_head(&self) -> bool {
+    pub fn extract_value(&self) -> Result<&VarDeclaration<'a>, Diagnostic> {
         match self {
-            Self::Head { .. } => true,
-            _ => false
+            Self::Value { ret, .. } => Ok(ret),
+            other => Err(Diagnostic::error("expected a declaration")
+                .with_code(codes::NOT_A_DECLARATION)
+                .with_primary(other.span(), "this is not a `let` declaration"))
         }
     }
 
-    pub fn is_tail(&self) -> bool {
+    /// Whether the node names a storage location that can be assigned to.
+    pub fn is_place(&self) -> bool {
         match self {
-            Self::Value { .. } => true,
+            Self::Ident { .. } => true,
+            Self::Field { recv, .. } => recv.is_place(),
             _ => false
         }
     }
 
-    pub fn extract_value(&self) -> Result<&VarDeclaration<'a>, ()> {
+    pub fn span(&self) -> Span {
         match self {
-            Self::Value { ret } => Ok(ret),
-            _ => Err(())
+            Self::Head { next } => next.span(),
+            Self::Chain { chained } => chained.iter()
+                .map(|node| node.span())
+                .reduce(Span::join)
+                .unwrap_or_default(),
+            Self::BinaryOp { span, .. }
+            | Self::UnaryOp { span, .. }
+            | Self::Body { span, .. }
+            | Self::Value { span, .. }
+            | Self::Literal { span, .. }
+            | Self::Ident { span, .. }
+            | Self::Call { span, .. }
+            | Self::ObjCall { span, .. }
+            | Self::Field { span, .. }
+            | Self::If { span, .. }
+            | Self::While { span, .. }
+            | Self::For { span, .. }
+            | Self::Loop { span, .. }
+            | Self::Break { span, .. }
+            | Self::Continue { span, .. }
+            | Self::Return { span, .. }
+            | Self::Error { span } => *span
         }
     }
 }
 
+/// Parses a whole file, stopping at its first error. The driver uses `Parser` directly
+/// instead, to report every error up to its limit.
+#[allow(dead_code)]
 pub(crate) trait ToNodes {
-    fn eval(&self) -> Result<Node<'_>, ()>;
+    fn eval(&self) -> Result<Node<'_>, Diagnostic>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The code `from_yarn` reports for `src`, or `None` when it parses.
+    fn decl_error(src: &str) -> Option<&'static str> {
+        let yarn = Yarn::borrowed(src);
+        VarDeclaration::from_yarn(&yarn).err().map(|diag| diag.code.unwrap())
+    }
+
+    #[test]
+    fn declarations_report_through_diagnostics() {
+        assert_eq!(decl_error("let x:Int8;"), None);
+        assert_eq!(decl_error("let\tx :\n Str ;"), None);
+        assert_eq!(decl_error("let x;"), None);
+        assert_eq!(decl_error("x: Int8;"), Some(codes::LET_ABSENT));
+        assert_eq!(decl_error("let 1: Int8;"), Some(codes::MISSING_NAME));
+        assert_eq!(decl_error("let x Int8;"), Some(codes::MISSING_COLON));
+        assert_eq!(decl_error("let x: Int8"), Some(codes::NO_SEMICOLON));
+        assert_eq!(decl_error("let x: Point;"), Some(codes::NO_VALID_TYPE));
+        assert_eq!(decl_error("let \"x: Int8;"), Some(codes::UNTERMINATED_STRING));
+    }
+
+    #[test]
+    fn unknown_operators_and_non_declarations() {
+        assert_eq!(BinOp::parse_op(&Yarn::borrowed(">=")).ok(), Some(BinOp::GreaterThanEq));
+        assert_eq!(BinOp::parse_op(&Yarn::borrowed("<>")).unwrap_err().code, Some(codes::UNKNOWN_OPERATOR));
+
+        let yarn = Yarn::borrowed("defun f() { for i in 0..3 { } }");
+        assert!(yarn.eval().is_ok());
+        let ident = Node::Ident { name: Yarn::borrowed("i"), span: Span::default() };
+        assert_eq!(ident.extract_value().err().and_then(|diag| diag.code), Some(codes::NOT_A_DECLARATION));
+    }
+
+    #[test]
+    fn eval_stops_at_the_first_error() {
+        let yarn = Yarn::borrowed("let = 1;\nlet y = ;\n");
+        let diag = yarn.eval().err().unwrap();
+        assert_eq!(diag.primary_span().map(|span| span.line), Some(1));
+    }
+
+    #[test]
+    fn immortalized_types_outlive_their_source() {
+        let (ty, shown) = {
+            let src = String::from("Pair Element");
+            let (pair, element) = src.split_once(' ').unwrap();
+            let ty = Type::Applied(Yarn::borrowed(pair), vec![
+                Type::SafePtr(Box::new(Type::Param(Yarn::borrowed(element)))),
+                Type::Array(Box::new(Type::Named(Yarn::borrowed(element))), 3)
+            ]);
+            let shown = ty.to_string();
+            (ty.immortalize(), shown)
+        };
+        assert_eq!(ty.to_string(), shown);
+    }
 }
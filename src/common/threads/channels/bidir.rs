@@ -4,5 +4,3 @@ struct RawShared<T, U> {
     queue_one: Arc<RwLock<VecDeque<T>>>,
     queue_two: Arc<RwLock<VecDeque<U>>>
 }
-
-enum 
\ No newline at end of file
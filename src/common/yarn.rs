@@ -1,8 +1,6 @@
-use std::{hash::Hash, marker::PhantomData, mem::{self, MaybeUninit}, num::NonZeroUsize, path::Path, ptr, str::{self, pattern::{Pattern, ReverseSearcher, Searcher}, FromStr}};
+use std::{fmt::{Debug, Display}, hash::Hash, marker::PhantomData, mem::{self, MaybeUninit}, num::NonZeroUsize, path::Path, ptr, str};
 use core::slice;
 
-use regex::Regex;
-
 const BORROWED: u8 = 0;
 const HEAP: u8 = 1;
 const SMALL: u8 = 2;
@@ -24,7 +22,7 @@ impl RawYarn {
 
         RawYarn {
             ptr: MaybeUninit::new(ptr),
-            len: NonZeroUsize::new_unchecked((kind as usize & 0b11) << usize::BITS - 2 | len)
+            len: NonZeroUsize::new_unchecked((kind as usize & 0b11) << (usize::BITS - 2) | len)
         }
     }
 
@@ -32,7 +30,7 @@ impl RawYarn {
         assert!(len <= SSO_LEN, "Not valid");
         let mut yarn = Self {
             ptr: MaybeUninit::uninit(),
-            len: NonZeroUsize::new_unchecked(((SMALL as usize) << 6 | len) << usize::BITS - 8)
+            len: NonZeroUsize::new_unchecked(((SMALL as usize) << 6 | len) << (usize::BITS - 8))
         };
 
         ptr::copy_nonoverlapping(
@@ -45,11 +43,11 @@ impl RawYarn {
     }
 
     fn kind(&self) -> u8 {
-        (self.len.clone().get() >> (usize::BITS - 2)) as u8
+        (self.len.get() >> (usize::BITS - 2)) as u8
     }
 
     fn len(&self) -> usize {
-        (self.len.clone().get() << 2) >> (2 + self.adjust())
+        (self.len.get() << 2) >> (2 + self.adjust())
     }
 
     unsafe fn as_ptr(&self) -> *const u8 {
@@ -89,19 +87,9 @@ impl PartialEq for RawYarn {
             self.as_slice() == other.as_slice()
         }
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        unsafe {
-            self.as_slice() != other.as_slice()
-        }
-    }
 }
 
-impl Eq for RawYarn {
-    fn assert_receiver_is_total_eq(&self) {
-        
-    }
-}
+impl Eq for RawYarn {}
 
 impl Hash for RawYarn {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -121,16 +109,6 @@ pub struct Yarn<'a> {
 
 impl<'a> Yarn<'a> {
 
-    pub fn from_char(data: char) -> Self {
-        let mut buf = [0u8; 4];
-        let data = data.encode_utf8(&mut buf);
-
-        Self {
-            raw: unsafe { RawYarn::from_small(buf.as_ptr(), 4) },
-            _ph: PhantomData
-        }
-    }
-
     pub fn from_static(data: &'static str) -> Self {
         let len = data.len();
         let ptr = data.as_ptr().cast_mut();
@@ -189,53 +167,6 @@ impl<'a> Yarn<'a> {
             str::from_utf8_unchecked(self.raw.as_slice())
         }
     }
-
-    fn as_ptr(&self) -> *const u8 {
-        unsafe{ self.raw.as_ptr() }
-    }
-
-    fn as_mut_ptr(&mut self) -> *mut u8 {
-        unsafe { self.raw.as_mut_ptr() }
-    }
-
-    pub fn len(&self) -> usize {
-        self.raw.len()
-    }
-
-    pub fn spilt<P>(&'a self, pattern: P) -> Vec<Yarn<'_>>
-    where P: Pattern<'a> {
-        self.as_slice().split(pattern).map(|ch|{
-            Self::borrowed(ch)
-        }).collect()
-    }
-
-    pub fn ends_with<P, S>(&'a self, pattern: P) -> bool 
-    where P: Pattern<'a, Searcher = S>,
-          S: ReverseSearcher<'a> {
-        self.as_slice().ends_with(pattern)
-    }
-
-    pub fn last_char(&self) -> Option<char> {
-        self.as_slice().chars().last()
-    }
-
-    pub fn starts_with<P, S>(&'a self, pattern: P) -> bool 
-    where P: Pattern<'a, Searcher = S>,
-          S: Searcher<'a> {
-        self.as_slice().starts_with(pattern)
-    }
-
-    pub fn regex_starts_with(&'a self, regex: Regex) -> bool {
-        regex.is_match_at(self.as_slice(), 0)
-    }
-
-    pub fn regex_ends_with(&'a self, regex: Regex) -> bool {
-        regex.is_match_at(self.as_slice(), self.len() - 1)
-    }
-
-    pub fn parse<T: FromStr>(&self) -> Result<T, <T as FromStr>::Err> {
-        self.as_slice().parse::<T>()
-    }
 }
 
 impl Yarn<'_> {
@@ -257,30 +188,48 @@ impl Yarn<'_> {
 
 }
 
-impl<'a> Into<Yarn<'a>> for &'a str {
-    fn into(self) -> Yarn<'a> {
-        Yarn::borrowed(self)
+impl<'a> From<&'a str> for Yarn<'a> {
+    fn from(src: &'a str) -> Self {
+        Yarn::borrowed(src)
     }
 }
 
+/// Only a `HEAP` yarn owns its bytes; the others borrow them or carry them inline.
 impl Drop for Yarn<'_> {
     fn drop(&mut self) {
-        let dropped = unsafe {
-            Box::from_raw(self.raw.as_mut_slice())
-        };
+        if self.raw.kind() == HEAP {
+            drop(unsafe {
+                Box::from_raw(self.raw.as_mut_slice() as *mut [u8])
+            });
+        }
     }
 }
 
+/// A `HEAP` yarn is copied to a heap yarn of its own. The others are copied bit for
+/// bit: an inline yarn carries its bytes along, and a borrowed or static one keeps
+/// pointing at what outlives it.
 impl Clone for Yarn<'_> {
     fn clone(&self) -> Self {
-        let ptr = self.as_ptr().cast_mut();
-        let len = self.len();
+        if self.raw.kind() == HEAP {
+            return Yarn::owned(self.as_slice().into());
+        }
 
         Self {
-            raw: unsafe{ RawYarn::from_raw_parts(ptr, len, BORROWED) },
+            raw: self.raw,
             _ph: PhantomData
         }
+    }
+}
 
+impl Display for Yarn<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_slice())
+    }
+}
+
+impl Debug for Yarn<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_slice(), f)
     }
 }
 
@@ -292,26 +241,17 @@ impl AsRef<Path> for Yarn<'_> {
 
 impl From<String> for Yarn<'_> {
     fn from(value: String) -> Self {
-        let ptr = value.as_ptr().cast_mut();
-        let len = value.len();
-
-        Self {
-            raw: unsafe{ RawYarn::from_raw_parts(ptr, len, BORROWED) },
-            _ph: PhantomData
-        }
+        Yarn::owned(value.into_boxed_str())
     }
 }
 
+/// Bytes that aren't UTF-8 are replaced, as `String::from_utf8_lossy` does.
 impl From<Vec<u8>> for Yarn<'_> {
 
     fn from(value: Vec<u8>) -> Self {
-        let ptr = value.as_ptr().cast_mut();
-        let len = value.len();
-
-        Self {
-            raw: unsafe { RawYarn::from_raw_parts(ptr, len, HEAP) },
-            _ph: PhantomData
-        }
+        let value = String::from_utf8(value)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+        value.into()
     }
 
 }
@@ -320,3 +260,59 @@ impl From<Vec<u8>> for Yarn<'_> {
 unsafe impl Send for Yarn<'_> {}
 unsafe impl Sync for Yarn<'_> {}
 
+
+#[cfg(test)]
+mod tests {
+    use super::Yarn;
+
+    #[test]
+    fn clone_of_inline_yarn_outlives_the_original() {
+        let clone = {
+            let short = Yarn::owned("Shape::describe".into());
+            let moved = [short];
+            moved[0].clone()
+        };
+        assert_eq!(clone.as_slice(), "Shape::describe");
+    }
+
+    #[test]
+    fn clone_of_heap_yarn_owns_its_bytes() {
+        let long = Yarn::owned("a name longer than the inline limit".into());
+        let clone = long.clone();
+        drop(long);
+        assert_eq!(clone.as_slice(), "a name longer than the inline limit");
+    }
+
+    #[test]
+    fn from_string_takes_ownership() {
+        let yarn: Yarn<'static> = format!("{}::{}", "Doggy", "Animal::legs").into();
+        assert_eq!(yarn.as_slice(), "Doggy::Animal::legs");
+        let short: Yarn<'static> = String::from("inc").into();
+        assert_eq!(short.as_slice(), "inc");
+    }
+
+    #[test]
+    fn borrowed_and_static_yarns_are_not_freed() {
+        let source = String::from("a borrowed slice of source text");
+        let borrowed = Yarn::borrowed(&source);
+        let copy = borrowed.clone();
+        drop(borrowed);
+        assert_eq!(copy.as_slice(), source);
+        drop(Yarn::from_static("a static string that isn't inline"));
+    }
+
+    #[test]
+    fn immortalized_yarns_own_their_text() {
+        let yarn = {
+            let source = String::from("borrowed text long enough for the heap");
+            Yarn::borrowed(&source).immortalize()
+        };
+        assert_eq!(yarn.as_slice(), "borrowed text long enough for the heap");
+    }
+
+    #[test]
+    fn from_bytes_copies_them() {
+        let yarn: Yarn<'static> = b"bytes from a file, long enough for the heap".to_vec().into();
+        assert_eq!(yarn.as_slice(), "bytes from a file, long enough for the heap");
+    }
+}
@@ -0,0 +1,361 @@
+use std::{error::Error, fmt::Display, path::PathBuf};
+
+use crate::{common::parser::DEFAULT_ERROR_LIMIT, ir::opt::Pass};
+
+pub(crate) const USAGE: &str = "\
+Usage: rust_comp <command> [options] <file>
+       rust_comp repl [options] [file]
+
+Commands:
+    build       Compile <file> for the selected target, written next to <file>
+                (`.s` for assembly, `.wasm` for WebAssembly, `.bbc` for bytecode)
+                unless -o says otherwise
+    check       Report errors in <file> without producing output; a `.wasm`
+                <file> is validated against the WebAssembly binary format and a
+                `.bbc` <file> is verified the way the VM loads it
+    parse       Parse <file> and print the result (see --emit); only ast, tokens
+                and preprocessed can be emitted, as nothing is checked
+    run         Check <file> and run it with the interpreter, or with the bytecode VM
+                given --vm; a `.bbc` <file> (as built for the bytecode target) runs
+                on the VM directly
+    repl        Evaluate code interactively, after loading [file] if given
+
+A <file> ending in `.ir` holds IR text (as written by --emit=ir); it is read back
+and verified instead of being compiled.
+
+Options:
+    -o <out>                    Write output to <out>; for build, the built file,
+                                so it can't be combined with --emit
+    --target <target>           Target to generate code for: x86_64-linux,
+                                wasm32-wasi, bytecode, or native (the default) for
+                                this machine
+    -I <dir>                    Add <dir> to the search path for #include, after
+                                the directory of the including file
+    --emit=<kind>[,<kind>..]    Output to produce: preprocessed for the source after
+                                #include, #define and #if, ast, tokens, ir, c for
+                                a C11 translation unit to build with the system
+                                `cc`, wat for the WebAssembly text format, or
+                                bytecode for a listing of the VM's bytecode
+    -O<level>                   Optimization level, 0 (default) to 3; -O is -O2
+    --passes=[+|-]<pass>[,..]   Turn single passes on (+) or off (-) on top of the
+                                level: inline, fold, copy-prop, cse, dce
+    --vm                        Run on the bytecode VM instead of the interpreter
+    --error-format=<format>     human (default) or json
+    --error-limit=<n>           Stop parsing after <n> errors (default 50)
+    -v, --verbose               Print progress information (repeat for more)
+    -q, --quiet                 Only print diagnostics
+    -h, --help                  Print this message
+    -V, --version               Print the compiler version
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Command {
+    Build,
+    Check,
+    Parse,
+    Run,
+    Repl
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Emit {
+    Ast,
+    Tokens,
+    Ir,
+    C,
+    Wat,
+    Bytecode,
+    Preprocessed
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ast" => Some(Self::Ast),
+            "tokens" => Some(Self::Tokens),
+            "ir" => Some(Self::Ir),
+            "c" => Some(Self::C),
+            "wat" => Some(Self::Wat),
+            "bytecode" => Some(Self::Bytecode),
+            "preprocessed" => Some(Self::Preprocessed),
+            _ => None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ast => "ast",
+            Self::Tokens => "tokens",
+            Self::Ir => "ir",
+            Self::C => "c",
+            Self::Wat => "wat",
+            Self::Bytecode => "bytecode",
+            Self::Preprocessed => "preprocessed"
+        }
+    }
+
+    /// Whether producing this needs a type-checked program, which `parse` never has.
+    fn needs_checking(self) -> bool {
+        matches!(self, Self::Ir | Self::C | Self::Wat | Self::Bytecode)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorFormat {
+    Human,
+    Json
+}
+
+#[derive(Debug)]
+pub(crate) struct Options {
+    pub command: Command,
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub target: Option<String>,
+    pub include_dirs: Vec<PathBuf>,
+    pub emit: Vec<Emit>,
+    pub error_format: ErrorFormat,
+    /// How many errors parsing reports before it gives up on the rest of the file.
+    pub error_limit: usize,
+    pub opt_level: u8,
+    /// Passes switched on or off with `--passes`, in order, on top of `opt_level`.
+    pub passes: Vec<(Pass, bool)>,
+    /// `run` compiles to bytecode and runs that instead of walking the tree.
+    pub vm: bool,
+    /// 0 is `--quiet`, 1 the default, 2 and up `--verbose`.
+    pub verbosity: u8
+}
+
+/// What the command line asked for, when it is not a compilation.
+#[derive(Debug)]
+pub(crate) enum Invocation {
+    Compile(Options),
+    Help,
+    Version
+}
+
+#[derive(Debug)]
+pub(crate) enum ArgError {
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(&'static str),
+    InvalidValue {
+        option: &'static str,
+        value: String
+    },
+    MissingInput,
+    ExtraInput(String),
+    /// An `--emit` kind that `parse` stops too early to produce.
+    UncheckedEmit(&'static str),
+    /// `-o` with `--emit` under `build`, where `-o` names the built file.
+    OutputWithEmit
+}
+
+impl Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingCommand => f.write_str("no command given"),
+            Self::UnknownCommand(cmd) => write!(f, "unknown command `{}`", cmd),
+            Self::UnknownOption(opt) => write!(f, "unknown option `{}`", opt),
+            Self::MissingValue(opt) => write!(f, "option `{}` requires a value", opt),
+            Self::InvalidValue { option, value } => write!(f, "invalid value `{}` for `{}`", value, option),
+            Self::MissingInput => f.write_str("no input file given"),
+            Self::ExtraInput(path) => write!(f, "unexpected extra input `{}`", path),
+            Self::UncheckedEmit(kind) => write!(f, "`parse` can't emit `{}`; use `check` or `build` instead", kind),
+            Self::OutputWithEmit => f.write_str("`-o` names the file `build` writes, so it can't be combined with `--emit`; use `check --emit` to write emitted code to a file")
+        }
+    }
+}
+
+impl Error for ArgError {}
+
+/// Parses the arguments after the program name.
+pub(crate) fn parse_args(args: &[String]) -> Result<Invocation, ArgError> {
+    let mut args = args.iter();
+
+    let command = match args.next().map(String::as_str) {
+        Some("build") => Command::Build,
+        Some("check") => Command::Check,
+        Some("parse") => Command::Parse,
+        Some("run") => Command::Run,
+        Some("repl") => Command::Repl,
+        Some("-h" | "--help" | "help") => return Ok(Invocation::Help),
+        Some("-V" | "--version") => return Ok(Invocation::Version),
+        Some(other) => return Err(ArgError::UnknownCommand(other.into())),
+        None => return Err(ArgError::MissingCommand)
+    };
+
+    let mut input = None;
+    let mut output = None;
+    let mut target = None;
+    let mut include_dirs = Vec::new();
+    let mut emit = Vec::new();
+    let mut error_format = ErrorFormat::Human;
+    let mut error_limit = DEFAULT_ERROR_LIMIT;
+    let mut opt_level = 0u8;
+    let mut passes = Vec::new();
+    let mut vm = false;
+    let mut verbosity = 1u8;
+
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None)
+        };
+        let mut value = |name: &'static str| -> Result<String, ArgError> {
+            inline.clone()
+                .or_else(|| args.next().cloned())
+                .ok_or(ArgError::MissingValue(name))
+        };
+
+        match flag {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "-o" | "--output" => output = Some(PathBuf::from(value("-o")?)),
+            "--target" => target = Some(value("--target")?),
+            "-I" | "--include" => include_dirs.push(PathBuf::from(value("-I")?)),
+            "--emit" => {
+                for kind in value("--emit")?.split(',') {
+                    emit.push(Emit::from_name(kind).ok_or_else(|| ArgError::InvalidValue {
+                        option: "--emit",
+                        value: kind.into()
+                    })?);
+                }
+            },
+            "--error-format" => {
+                error_format = match value("--error-format")?.as_str() {
+                    "human" => ErrorFormat::Human,
+                    "json" => ErrorFormat::Json,
+                    other => return Err(ArgError::InvalidValue {
+                        option: "--error-format",
+                        value: other.into()
+                    })
+                };
+            },
+            "--error-limit" => {
+                let limit = value("--error-limit")?;
+                error_limit = match limit.parse() {
+                    Ok(limit) if limit > 0 => limit,
+                    _ => return Err(ArgError::InvalidValue {
+                        option: "--error-limit",
+                        value: limit
+                    })
+                };
+            },
+            "-O" => opt_level = 2,
+            "--passes" => {
+                for name in value("--passes")?.split(',') {
+                    let (enabled, pass) = match name.strip_prefix('-') {
+                        Some(pass) => (false, pass),
+                        None => (true, name.strip_prefix('+').unwrap_or(name))
+                    };
+                    passes.push((Pass::from_name(pass).ok_or_else(|| ArgError::InvalidValue {
+                        option: "--passes",
+                        value: name.into()
+                    })?, enabled));
+                }
+            },
+            "--vm" => vm = true,
+            "-v" | "--verbose" => verbosity = verbosity.saturating_add(1),
+            "-q" | "--quiet" => verbosity = 0,
+            _ if flag.starts_with("-O") => {
+                opt_level = match &flag[2..] {
+                    level @ ("0" | "1" | "2" | "3") => level.parse().unwrap_or_default(),
+                    other => return Err(ArgError::InvalidValue {
+                        option: "-O",
+                        value: other.into()
+                    })
+                };
+            },
+            _ if flag.starts_with("-I") && flag.len() > 2 => include_dirs.push(PathBuf::from(&flag[2..])),
+            _ if flag.starts_with('-') && flag != "-" => return Err(ArgError::UnknownOption(arg.clone())),
+            _ => {
+                if input.is_some() {
+                    return Err(ArgError::ExtraInput(arg.clone()));
+                }
+                input = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    if command == Command::Parse {
+        if let Some(kind) = emit.iter().find(|kind| kind.needs_checking()) {
+            return Err(ArgError::UncheckedEmit(kind.name()));
+        }
+        if emit.is_empty() {
+            emit.push(Emit::Ast);
+        }
+    }
+    if command == Command::Build && output.is_some() && !emit.is_empty() {
+        return Err(ArgError::OutputWithEmit);
+    }
+    // The REPL is the one command that can start without a file.
+    let input = match (input, command) {
+        (Some(input), _) => input,
+        (None, Command::Repl) => PathBuf::new(),
+        (None, _) => return Err(ArgError::MissingInput)
+    };
+
+    Ok(Invocation::Compile(Options {
+        command,
+        input,
+        output,
+        target,
+        include_dirs,
+        emit,
+        error_format,
+        error_limit,
+        opt_level,
+        passes,
+        vm,
+        verbosity
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, ArgError> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        match parse_args(&args)? {
+            Invocation::Compile(opts) => Ok(opts),
+            other => panic!("expected a compilation, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parse_emits_the_tree_by_default() {
+        assert_eq!(parse("parse f.beta").unwrap().emit, [Emit::Ast]);
+        assert_eq!(parse("parse --emit=tokens,preprocessed f.beta").unwrap().emit, [Emit::Tokens, Emit::Preprocessed]);
+    }
+
+    #[test]
+    fn parse_rejects_what_needs_checking() {
+        for kind in ["ir", "c", "wat", "bytecode"] {
+            let err = parse(&format!("parse --emit=ast,{} f.beta", kind)).unwrap_err();
+            assert!(matches!(err, ArgError::UncheckedEmit(name) if name == kind), "{}", kind);
+        }
+        assert_eq!(parse("check --emit=ir,c f.beta").unwrap().emit, [Emit::Ir, Emit::C]);
+    }
+
+    #[test]
+    fn build_output_is_not_for_emitted_code() {
+        assert!(matches!(parse("build --emit=c f.beta -o f.c").unwrap_err(), ArgError::OutputWithEmit));
+        assert_eq!(parse("check --emit=c f.beta -o f.c").unwrap().output, Some(PathBuf::from("f.c")));
+        assert_eq!(parse("build --emit=c f.beta").unwrap().emit, [Emit::C]);
+    }
+
+    #[test]
+    fn error_limit() {
+        assert_eq!(parse("check f.beta").unwrap().error_limit, DEFAULT_ERROR_LIMIT);
+        assert_eq!(parse("check --error-limit=3 f.beta").unwrap().error_limit, 3);
+        assert_eq!(parse("check --error-limit 7 f.beta").unwrap().error_limit, 7);
+        for limit in ["0", "-1", "many"] {
+            let err = parse(&format!("check --error-limit={} f.beta", limit)).unwrap_err();
+            assert!(matches!(err, ArgError::InvalidValue { option: "--error-limit", .. }), "{}", limit);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+use crate::common::{
+    diagnostics::{Diagnostic, SourceFile},
+    lexer::Token,
+    syntax_tree::{Bodies, DefunDescriptor, Literal, Node, VarDeclaration},
+    yarn::Yarn
+};
+
+/// Renders `node` as an indented outline, one node per line.
+pub(crate) fn ast_to_string(node: &Node<'_>) -> String {
+    let mut out = String::new();
+    write_node(&mut out, node, 0);
+    out
+}
+
+fn write_decl(decl: &VarDeclaration<'_>) -> String {
+    match decl.name() {
+        Some(name) => format!("{}: {}", name, decl.ty()),
+        None => decl.ty().to_string()
+    }
+}
+
+fn write_signature(defun: &DefunDescriptor<'_>) -> String {
+    let args: Vec<String> = defun.args().iter().map(|arg| write_decl(arg)).collect();
+    format!("{}({}) => {}", defun.qualified(), args.join(", "), defun.return_type())
+}
+
+fn write_label(label: &Option<Yarn<'_>>) -> String {
+    label.as_ref().map(|label| format!(" {}", label)).unwrap_or_default()
+}
+
+/// Writes the statements of a block under a heading naming its role.
+fn write_block(out: &mut String, heading: &str, stmts: &[Node<'_>], depth: usize) {
+    let _ = writeln!(out, "{}{}", "  ".repeat(depth), heading);
+    for stmt in stmts {
+        write_node(out, stmt, depth + 1);
+    }
+}
+
+fn write_node(out: &mut String, node: &Node<'_>, depth: usize) {
+    let pad = "  ".repeat(depth);
+    let _ = write!(out, "{}", pad);
+
+    match node {
+        Node::Head { next } => {
+            out.push_str("Head\n");
+            write_node(out, next, depth + 1);
+        },
+        Node::Chain { chained } => {
+            out.push_str("Chain\n");
+            for child in chained {
+                write_node(out, child, depth + 1);
+            }
+        },
+        Node::BinaryOp { lhs, rhs, op, .. } => {
+            let _ = writeln!(out, "BinaryOp {}", op.as_str());
+            write_node(out, lhs, depth + 1);
+            write_node(out, rhs, depth + 1);
+        },
+        Node::UnaryOp { lhs, op, postfix, .. } => {
+            let _ = writeln!(out, "UnaryOp {}{}", op.as_str(), if *postfix { " (postfix)" } else { "" });
+            write_node(out, lhs, depth + 1);
+        },
+        Node::Body { discriptor, body, .. } => {
+            match discriptor.as_ref() {
+                Bodies::Object(obj) => {
+                    let _ = writeln!(out, "Obj {}", obj.name());
+                    for field in obj.fields() {
+                        let _ = writeln!(out, "{}  field {}", pad, write_decl(field));
+                    }
+                },
+                Bodies::Composition(comp) => {
+                    let _ = writeln!(out, "Comp {}", comp.name());
+                    for field in comp.fields() {
+                        let _ = writeln!(out, "{}  field {}", pad, write_decl(field));
+                    }
+                },
+                Bodies::Trait(tr) => {
+                    let supers: Vec<String> = tr.super_traits().iter().map(|st| st.name().to_string()).collect();
+                    if supers.is_empty() {
+                        let _ = writeln!(out, "Trait {}", tr.name());
+                    } else {
+                        let _ = writeln!(out, "Trait {}: {}", tr.name(), supers.join(" + "));
+                    }
+                    // Methods with a default body are written out in full below.
+                    for function in tr.functions().iter().filter(|function| !tr.has_default(function.name().as_slice())) {
+                        let _ = writeln!(out, "{}  defun {}", pad, write_signature(function));
+                    }
+                    for method in body {
+                        if let Node::Body { discriptor, .. } = method {
+                            if matches!(discriptor.as_ref(), Bodies::Defun(defun) if tr.has_default(defun.name().as_slice())) {
+                                write_node(out, method, depth + 1);
+                            }
+                        }
+                    }
+                    return;
+                },
+                Bodies::Extension(extension) => {
+                    let _ = writeln!(out, "Extend {} with {}", extension.obj(), extension.with());
+                },
+                Bodies::Defun(defun) => {
+                    let _ = writeln!(out, "Defun {}", write_signature(defun));
+                }
+            }
+            for child in body {
+                write_node(out, child, depth + 1);
+            }
+        },
+        Node::Value { ret, .. } => {
+            let _ = writeln!(out, "Let {}", write_decl(ret));
+        },
+        Node::Literal { value, .. } => {
+            let _ = match value {
+                Literal::Int(value) => writeln!(out, "Int {}", value),
+                Literal::Float(value) => writeln!(out, "Float {}", value),
+                Literal::Str(value) => writeln!(out, "Str {:?}", value),
+                Literal::Boolean(value) => writeln!(out, "Boolean {}", value)
+            };
+        },
+        Node::Ident { name, .. } => {
+            let _ = writeln!(out, "Ident {}", name);
+        },
+        Node::Call { func, args, .. } => {
+            let _ = writeln!(out, "Call {}", func);
+            for arg in args {
+                write_node(out, arg, depth + 1);
+            }
+        },
+        Node::ObjCall { recv, func, args, .. } => {
+            let _ = writeln!(out, "ObjCall .{}", func);
+            write_node(out, recv, depth + 1);
+            for arg in args {
+                write_node(out, arg, depth + 1);
+            }
+        },
+        Node::Field { recv, name, .. } => {
+            let _ = writeln!(out, "Field .{}", name);
+            write_node(out, recv, depth + 1);
+        },
+        Node::If { cond, then, els, .. } => {
+            out.push_str("If\n");
+            write_node(out, cond, depth + 1);
+            write_block(out, "Then", then, depth + 1);
+            if let Some(els) = els {
+                write_block(out, "Else", els, depth + 1);
+            }
+        },
+        Node::While { label, cond, body, .. } => {
+            let _ = writeln!(out, "While{}", write_label(label));
+            write_node(out, cond, depth + 1);
+            write_block(out, "Do", body, depth + 1);
+        },
+        Node::For { label, var, start, end, body, .. } => {
+            let _ = writeln!(out, "For{}", write_label(label));
+            write_node(out, var, depth + 1);
+            write_node(out, start, depth + 1);
+            write_node(out, end, depth + 1);
+            write_block(out, "Do", body, depth + 1);
+        },
+        Node::Loop { label, body, .. } => {
+            let _ = writeln!(out, "Loop{}", write_label(label));
+            for stmt in body {
+                write_node(out, stmt, depth + 1);
+            }
+        },
+        Node::Break { label, .. } => {
+            let _ = writeln!(out, "Break{}", write_label(label));
+        },
+        Node::Continue { label, .. } => {
+            let _ = writeln!(out, "Continue{}", write_label(label));
+        },
+        Node::Return { value, .. } => {
+            out.push_str("Return\n");
+            if let Some(value) = value {
+                write_node(out, value, depth + 1);
+            }
+        },
+        Node::Error { .. } => {
+            out.push_str("<error>\n");
+        }
+    }
+}
+
+pub(crate) fn tokens_to_string(tokens: &[Token<'_>]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        let _ = writeln!(out, "{}:{}\t{}", token.span.line, token.span.col, token.kind);
+    }
+    out
+}
+
+fn json_str(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            },
+            ch => out.push(ch)
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// One JSON object per diagnostic, for `--error-format=json`.
+pub(crate) fn diagnostic_to_json(diag: &Diagnostic, file: &SourceFile<'_>) -> String {
+    let labels: Vec<String> = diag.labels.iter().map(|label| format!(
+        "{{\"file\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"primary\":{},\"message\":{}}}",
+        json_str(file.locate(label.span.line).0),
+        label.span.start,
+        label.span.end,
+        file.locate(label.span.line).1,
+        file.columns(label.span).0,
+        label.primary,
+        label.message.as_deref().map_or("null".into(), json_str)
+    )).collect();
+    let notes: Vec<String> = diag.notes.iter().map(|note| json_str(note)).collect();
+    let help: Vec<String> = diag.help.iter().map(|help| json_str(help)).collect();
+
+    format!(
+        "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"labels\":[{}],\"notes\":[{}],\"help\":[{}],\"rendered\":{}}}",
+        json_str(diag.severity.as_str()),
+        diag.code.map_or("null".into(), json_str),
+        json_str(&diag.message),
+        json_str(diag.primary_span().map_or(file.name(), |span| file.locate(span.line).0)),
+        labels.join(","),
+        notes.join(","),
+        help.join(","),
+        json_str(&diag.render(file))
+    )
+}
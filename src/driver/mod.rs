@@ -0,0 +1,527 @@
+pub(crate) mod args;
+pub(crate) mod emit;
+pub(crate) mod repl;
+#[cfg(test)]
+mod tests;
+
+use std::{fs, io::{self, Write}, process::ExitCode};
+
+use crate::{
+    codegen,
+    analysis::{resolve::{Resolutions, Resolver}, typeck::{TypeChecker, TypeTable}},
+    interpreter::{Interpreter, Runtime},
+    preprocessor::Preprocessor,
+    ir::{lower::Lowerer, opt::Pipeline, parse::parse_module, verify::{verify, VerifyError}, Module},
+    vm::{bytecode::Program, compile::compile as compile_bytecode, format::decode, Vm},
+    common::{
+        diagnostics::{codes, Diagnostic, Severity, SourceFile},
+        lexer::Lexer,
+        parser::Parser,
+        session::Session,
+        syntax_tree::Node,
+        yarn::Yarn,
+        Globals,
+        Targets
+    }
+};
+
+use self::args::{parse_args, Command, Emit, ErrorFormat, Invocation, Options, USAGE};
+
+/// Process exit statuses, so build scripts can tell failures apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Status {
+    Success = 0,
+    CompileError = 1,
+    Usage = 2,
+    Io = 3,
+    Unsupported = 4,
+    RuntimeError = 5
+}
+
+impl From<Status> for ExitCode {
+    fn from(status: Status) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+/// Prints diagnostics in the format selected on the command line.
+pub(crate) struct Reporter<'f> {
+    format: ErrorFormat,
+    file: &'f SourceFile<'f>,
+    errors: usize,
+    warnings: usize
+}
+
+impl<'f> Reporter<'f> {
+
+    pub fn new(format: ErrorFormat, file: &'f SourceFile<'f>) -> Self {
+        Self {
+            format,
+            file,
+            errors: 0,
+            warnings: 0
+        }
+    }
+
+    pub fn report(&mut self, diag: &Diagnostic) {
+        // Whatever went wrong inside a macro also says which macros it came out of.
+        let backtrace = diag.primary_span().map(|span| self.file.backtrace(span)).unwrap_or_default();
+        let diag = &backtrace.into_iter().fold(diag.clone(), Diagnostic::with_note);
+
+        match diag.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            _ => {}
+        }
+
+        match self.format {
+            ErrorFormat::Human => eprintln!("{}", diag.render(self.file)),
+            ErrorFormat::Json => eprintln!("{}", emit::diagnostic_to_json(diag, self.file))
+        }
+    }
+
+    pub fn report_all<'d>(&mut self, diags: impl IntoIterator<Item = &'d Diagnostic>) {
+        for diag in diags {
+            self.report(diag);
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    /// The closing "aborting" line; JSON output stays machine-readable and skips it.
+    pub fn finish(&self) {
+        if self.format != ErrorFormat::Human {
+            return;
+        }
+        if self.errors > 0 {
+            let plural = if self.errors == 1 { "" } else { "s" };
+            eprintln!("error: aborting due to {} previous error{}", self.errors, plural);
+        } else if self.warnings > 0 {
+            let plural = if self.warnings == 1 { "" } else { "s" };
+            eprintln!("warning: {} warning{} emitted", self.warnings, plural);
+        }
+    }
+}
+
+fn info(opts: &Options, message: impl AsRef<str>) {
+    if opts.verbosity >= 2 {
+        eprintln!("info: {}", message.as_ref());
+    }
+}
+
+/// Whether the arguments ask for the tree-walking interpreter, which needs a thread with
+/// `interpreter::STACK_SIZE` of stack to run.
+pub(crate) fn interprets(args: &[String]) -> bool {
+    match parse_args(args) {
+        Ok(Invocation::Compile(opts)) => match opts.command {
+            Command::Repl => true,
+            Command::Run => !opts.vm && !matches!(opts.input.extension().and_then(|ext| ext.to_str()), Some("bbc" | "wasm" | "ir")),
+            _ => false
+        },
+        _ => false
+    }
+}
+
+/// Runs the compiler for the given arguments (without the program name).
+pub(crate) fn run(args: &[String]) -> Status {
+    let opts = match parse_args(args) {
+        Ok(Invocation::Compile(opts)) => opts,
+        Ok(Invocation::Help) => {
+            print!("{}", USAGE);
+            return Status::Success;
+        },
+        Ok(Invocation::Version) => {
+            println!("rust_comp {}", env!("CARGO_PKG_VERSION"));
+            return Status::Success;
+        },
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return Status::Usage;
+        }
+    };
+
+    if opts.command == Command::Repl {
+        return repl::run(&opts);
+    }
+    if opts.input.extension().is_some_and(|ext| ext == "wasm") {
+        return check_wasm(&opts);
+    }
+    if opts.input.extension().is_some_and(|ext| ext == "bbc") {
+        return run_bytecode(&opts);
+    }
+
+    let src = match fs::read_to_string(&opts.input) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("error: couldn't read `{}`: {}", opts.input.display(), err);
+            return Status::Io;
+        }
+    };
+
+    if opts.input.extension().is_some_and(|ext| ext == "ir") {
+        return compile_ir(&opts, &src);
+    }
+    compile(&opts, &src)
+}
+
+/// Lowering should always produce well-formed IR, so a verifier error is a compiler bug.
+fn verify_error(err: &VerifyError) -> Diagnostic {
+    Diagnostic::error(format!("malformed IR {}", err)).with_code(codes::MALFORMED_IR)
+}
+
+/// Reads IR text back, verifies it and, for `parse` or `--emit=ir`, prints it again.
+fn compile_ir(opts: &Options, src: &str) -> Status {
+    let file = SourceFile::new(opts.input.display().to_string(), src);
+    let mut reporter = Reporter::new(opts.error_format, &file);
+
+    info(opts, format!("reading IR from {}", file.name()));
+    let mut module = match parse_module(src) {
+        Ok(module) => module,
+        Err(diag) => {
+            reporter.report(&diag);
+            if opts.verbosity > 0 {
+                reporter.finish();
+            }
+            return Status::CompileError;
+        }
+    };
+    if let Err(errors) = verify(&module) {
+        for err in &errors {
+            reporter.report(&Diagnostic::error(err.to_string()).with_code(codes::MALFORMED_IR));
+        }
+        if opts.verbosity > 0 {
+            reporter.finish();
+        }
+        return Status::CompileError;
+    }
+    if let Err(diags) = optimize(opts, &mut module) {
+        reporter.report_all(&diags);
+        if opts.verbosity > 0 {
+            reporter.finish();
+        }
+        return Status::CompileError;
+    }
+
+    let status = match opts.command {
+        Command::Parse | Command::Check => Status::Success,
+        Command::Build | Command::Run | Command::Repl => {
+            eprintln!("error: IR input can only be parsed or checked");
+            Status::Unsupported
+        }
+    };
+    let mut out = String::new();
+    if opts.command == Command::Parse || opts.emit.contains(&Emit::Ir) {
+        out.push_str(&module.to_string());
+    }
+    if let Err(diags) = emit_code(opts, &module, &mut out) {
+        reporter.report_all(&diags);
+        return Status::CompileError;
+    }
+    if !out.is_empty() {
+        if let Err(status) = write_output(opts, &out) {
+            return status;
+        }
+    }
+    status
+}
+
+fn compile(opts: &Options, src: &str) -> Status {
+    let name = opts.input.display().to_string();
+    info(opts, format!("preprocessing {}", name));
+    let globals = Globals::new(preprocessor_target(opts));
+    let preprocessed = Preprocessor::new(&opts.include_dirs, globals).run(&opts.input, name.clone(), src);
+    let src = preprocessed.text.as_str();
+    let file = SourceFile::new(name, src).with_line_map(&preprocessed.map);
+    let yarn = Yarn::borrowed(src);
+    let mut reporter = Reporter::new(opts.error_format, &file);
+    let mut out = String::new();
+
+    // Parsing what's left after a failed `#include` or an `#error` would only add noise.
+    reporter.report_all(&preprocessed.diagnostics);
+    if reporter.has_errors() {
+        if opts.verbosity > 0 {
+            reporter.finish();
+        }
+        return Status::CompileError;
+    }
+
+    if opts.emit.contains(&Emit::Preprocessed) {
+        for line in src.lines() {
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+
+    if opts.emit.contains(&Emit::Tokens) {
+        let (tokens, _) = Lexer::new(&yarn).tokenize_all();
+        out.push_str(&emit::tokens_to_string(&tokens));
+    }
+
+    info(opts, format!("parsing {}", file.name()));
+    let mut parser = Parser::new(&yarn).with_error_limit(opts.error_limit);
+    let tree = parser.parse_program();
+    reporter.report_all(parser.errors());
+
+    if opts.emit.contains(&Emit::Ast) {
+        out.push_str(&emit::ast_to_string(&tree));
+    }
+
+    let mut status = None;
+    // Type errors in a tree with parse errors are mostly noise, so only check clean trees.
+    if opts.command != Command::Parse && !reporter.has_errors() {
+        let mut session = Session::new();
+        info(opts, "resolving names");
+        let resolutions = Resolver::new(&mut session).resolve_program(&tree);
+        info(opts, "type checking");
+        let types = TypeChecker::new(&mut session, &resolutions).check_program(&tree);
+        session.diagnostics.sort_by_position();
+        reporter.report_all(session.diagnostics.iter());
+
+        let wants_ir = opts.command == Command::Build
+            || (opts.command == Command::Run && opts.vm)
+            || opts.emit.iter().any(|emit| matches!(emit, Emit::Ir | Emit::C | Emit::Wat | Emit::Bytecode));
+        let mut lowered_module = None;
+        if wants_ir && !reporter.has_errors() {
+            info(opts, "lowering to IR");
+            let lowered = lower(&session, &resolutions, &types, &tree)
+                .and_then(|mut module| optimize(opts, &mut module).map(|_| module));
+            match lowered {
+                Ok(module) => {
+                    if opts.emit.contains(&Emit::Ir) {
+                        out.push_str(&module.to_string());
+                    }
+                    if let Err(diags) = emit_code(opts, &module, &mut out) {
+                        reporter.report_all(&diags);
+                    }
+                    if opts.command == Command::Build {
+                        status = Some(build(opts, &module, &mut reporter));
+                    }
+                    lowered_module = Some(module);
+                },
+                Err(diags) => reporter.report_all(&diags)
+            }
+        }
+
+        if let (Command::Run, true, Some(module)) = (opts.command, opts.vm, &lowered_module) {
+            if !reporter.has_errors() {
+                status = Some(match compile_bytecode(module) {
+                    Ok(program) => execute(opts, &program, &mut reporter),
+                    Err(diags) => {
+                        reporter.report_all(&diags);
+                        Status::CompileError
+                    }
+                });
+            }
+        } else if opts.command == Command::Run && !opts.vm && !reporter.has_errors() {
+            info(opts, "running");
+            let mut stdout = io::stdout().lock();
+            let mut runtime = Runtime::default();
+            let result = Interpreter::new(&session.symbols, &resolutions, &types, &mut runtime, &mut stdout).run_program(&tree);
+            let _ = stdout.flush();
+            status = Some(match result {
+                Ok(_) => Status::Success,
+                Err(diag) => {
+                    reporter.report(&diag);
+                    Status::RuntimeError
+                }
+            });
+        }
+    }
+
+    let status = match status {
+        Some(status) => status,
+        None if reporter.has_errors() => Status::CompileError,
+        None => Status::Success
+    };
+    if opts.verbosity > 0 {
+        reporter.finish();
+    }
+
+    // `build` writes its own output file, so anything else asked for goes to stdout.
+    if !out.is_empty() && opts.command == Command::Build {
+        print!("{}", out);
+    } else if !out.is_empty() {
+        if let Err(status) = write_output(opts, &out) {
+            return status;
+        }
+    }
+    status
+}
+
+/// The target `#if` conditions test: the one `build` generates code for, the VM for
+/// `run --vm`, and otherwise the machine the compiler runs on, where the interpreter does.
+fn preprocessor_target(opts: &Options) -> Option<Targets> {
+    match opts.command {
+        Command::Run if opts.vm => Some(Targets::Bytecode),
+        Command::Run | Command::Repl => None,
+        Command::Build => Targets::from_name(opts.target.as_deref().unwrap_or("native")),
+        Command::Check | Command::Parse => opts.target.as_deref().and_then(Targets::from_name)
+    }
+}
+
+/// Appends the C and WebAssembly text `--emit` asks for.
+fn emit_code(opts: &Options, module: &Module, out: &mut String) -> Result<(), Vec<Diagnostic>> {
+    if opts.emit.contains(&Emit::C) {
+        info(opts, "generating C");
+        out.push_str(&codegen::c::generate(module)?);
+    }
+    if opts.emit.contains(&Emit::Wat) {
+        info(opts, "generating WebAssembly text");
+        out.push_str(&codegen::wasm::generate(module)?.to_string());
+    }
+    if opts.emit.contains(&Emit::Bytecode) {
+        info(opts, "compiling to bytecode");
+        out.push_str(&compile_bytecode(module)?.to_string());
+    }
+    Ok(())
+}
+
+/// Runs a program on the VM, reporting a runtime error the way the interpreter does.
+fn execute(opts: &Options, program: &Program, reporter: &mut Reporter<'_>) -> Status {
+    info(opts, "running on the VM");
+    let mut stdout = io::stdout().lock();
+    let result = Vm::new(program, &mut stdout).run();
+    let _ = stdout.flush();
+    match result {
+        Ok(()) => Status::Success,
+        Err(diag) => {
+            reporter.report(&diag);
+            Status::RuntimeError
+        }
+    }
+}
+
+/// Loads a `.bbc` file, such as one `build --target=bytecode` wrote, and runs it, or
+/// for `check` only verifies it.
+fn run_bytecode(opts: &Options) -> Status {
+    if !matches!(opts.command, Command::Run | Command::Check) {
+        eprintln!("error: bytecode input can only be run or checked");
+        return Status::Unsupported;
+    }
+    let bytes = match fs::read(&opts.input) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("error: couldn't read `{}`: {}", opts.input.display(), err);
+            return Status::Io;
+        }
+    };
+    info(opts, format!("loading {}", opts.input.display()));
+    let file = SourceFile::new(opts.input.display().to_string(), "");
+    let mut reporter = Reporter::new(opts.error_format, &file);
+    let status = match decode(&bytes) {
+        Ok(program) if opts.command == Command::Run => execute(opts, &program, &mut reporter),
+        Ok(_) => Status::Success,
+        Err(err) => {
+            reporter.report(&Diagnostic::error(format!("invalid bytecode file {}", err)).with_code(codes::INVALID_BYTECODE));
+            Status::CompileError
+        }
+    };
+    if opts.verbosity > 0 {
+        reporter.finish();
+    }
+    status
+}
+
+/// Validates a WebAssembly module, such as one `build --target=wasm32-wasi` wrote.
+fn check_wasm(opts: &Options) -> Status {
+    if opts.command != Command::Check {
+        eprintln!("error: WebAssembly input can only be checked");
+        return Status::Unsupported;
+    }
+    let bytes = match fs::read(&opts.input) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("error: couldn't read `{}`: {}", opts.input.display(), err);
+            return Status::Io;
+        }
+    };
+    info(opts, format!("validating {}", opts.input.display()));
+    let file = SourceFile::new(opts.input.display().to_string(), "");
+    let mut reporter = Reporter::new(opts.error_format, &file);
+    let status = match codegen::wasm::validate(&bytes) {
+        Ok(()) => Status::Success,
+        Err(err) => {
+            reporter.report(&Diagnostic::error(format!("invalid WebAssembly module {}", err)).with_code(codes::INVALID_WASM));
+            Status::CompileError
+        }
+    };
+    if opts.verbosity > 0 {
+        reporter.finish();
+    }
+    status
+}
+
+/// Generates code for the selected target, written to `-o` or next to the input with
+/// the target's extension.
+fn build(opts: &Options, module: &Module, reporter: &mut Reporter<'_>) -> Status {
+    let name = opts.target.as_deref().unwrap_or("native");
+    let Some(target) = Targets::from_name(name) else {
+        if name == "native" {
+            eprintln!("error: no code generation backend is available for this machine; pick one with --target");
+            return Status::Unsupported;
+        }
+        let known: Vec<&str> = Targets::ALL.iter().map(Targets::name).collect();
+        eprintln!("error: unknown target `{}` (known targets: {})", name, known.join(", "));
+        return Status::Usage;
+    };
+
+    info(opts, format!("generating code for {}", target.name()));
+    let code = match codegen::generate(target, module) {
+        Ok(code) => code,
+        Err(diags) => {
+            reporter.report_all(&diags);
+            return Status::CompileError;
+        }
+    };
+    let path = opts.output.clone().unwrap_or_else(|| opts.input.with_extension(target.extension()));
+    match fs::write(&path, code) {
+        Ok(()) => {
+            info(opts, format!("wrote {}", path.display()));
+            Status::Success
+        },
+        Err(err) => {
+            eprintln!("error: couldn't write `{}`: {}", path.display(), err);
+            Status::Io
+        }
+    }
+}
+
+/// Lowers a checked program and verifies the result.
+fn lower<'a>(session: &Session<'a>, resolutions: &Resolutions, types: &TypeTable<'a>, tree: &Node<'a>) -> Result<Module, Vec<Diagnostic>> {
+    let module = Lowerer::new(&session.symbols, resolutions, types).lower_program(tree)?;
+    verify(&module).map_err(|errors| errors.iter().map(verify_error).collect::<Vec<_>>())?;
+    Ok(module)
+}
+
+/// Runs the passes `-O` and `--passes` select. Like lowering, a pass that leaves the
+/// module malformed is a compiler bug, reported against the pass.
+fn optimize(opts: &Options, module: &mut Module) -> Result<(), Vec<Diagnostic>> {
+    let mut pipeline = Pipeline::for_level(opts.opt_level);
+    for (pass, enabled) in &opts.passes {
+        pipeline.set(*pass, *enabled);
+    }
+    if pipeline.is_empty() {
+        return Ok(());
+    }
+    info(opts, format!("optimizing at -O{}", opts.opt_level));
+    pipeline.run(module, |message| info(opts, message)).map_err(|(pass, errors)| {
+        errors.iter()
+            .map(|err| Diagnostic::error(format!("malformed IR after `{}` {}", pass, err)).with_code(codes::MALFORMED_IR))
+            .collect()
+    })
+}
+
+fn write_output(opts: &Options, out: &str) -> Result<(), Status> {
+    match &opts.output {
+        Some(path) => fs::write(path, out).map_err(|err| {
+            eprintln!("error: couldn't write `{}`: {}", path.display(), err);
+            Status::Io
+        }),
+        None => {
+            print!("{}", out);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write}
+};
+
+use crate::{
+    analysis::{
+        resolve::{Resolutions, Resolver},
+        typeck::{TypeChecker, TypeTable}
+    },
+    common::{
+        diagnostics::{Diagnostic, SourceFile},
+        lexer::{Lexer, LexErrorKind, Punct, TokenKind},
+        parser::Parser,
+        session::Session,
+        syntax_tree::Node,
+        yarn::Yarn
+    },
+    interpreter::{value::Value, Interpreter, Runtime}
+};
+
+use super::{args::{ErrorFormat, Options}, emit, Reporter, Status};
+
+const PROMPT: &str = ">> ";
+const CONTINUE_PROMPT: &str = ".. ";
+
+const HELP: &str = "\
+Enter declarations, statements or expressions; a missing final `;` is added.
+Input continues over several lines until its braces are balanced.
+
+Commands:
+    :type <expr>    Print the type of <expr>
+    :ast <expr>     Print the syntax tree of <expr>
+    :load <file>    Evaluate the contents of <file>
+    :help           Print this message
+    :quit           Leave the REPL (or press Ctrl-D)
+";
+
+/// An interactive session. Every entry is resolved into the same symbol table and run
+/// against the same globals, so later entries see earlier declarations.
+///
+/// Trees borrow their source text and the runtime keeps borrowing function bodies, so
+/// each entry's text is leaked to live as long as the process. Names the symbol table
+/// keeps are yarns borrowing that text too, never the entry's parser or tokens.
+pub(crate) struct Repl {
+    session: Session<'static>,
+    resolutions: Resolutions,
+    types: TypeTable<'static>,
+    runtime: Runtime<'static, 'static>,
+    /// All text entered so far; spans index into it, so diagnostics can quote any entry.
+    text: String,
+    lines: usize,
+    error_format: ErrorFormat
+}
+
+impl Repl {
+
+    pub fn new(error_format: ErrorFormat) -> Self {
+        let mut session = Session::new();
+        session.symbols.allow_global_rebinding();
+        Self {
+            session,
+            resolutions: Resolutions::default(),
+            types: TypeTable::default(),
+            runtime: Runtime::default(),
+            text: String::new(),
+            lines: 0,
+            error_format
+        }
+    }
+
+    /// Records `typed` as the next entry and returns the yarn to parse, `src`, with the offset
+    /// and line it starts at. `src` is `typed` with at most a `;` added in place of trailing
+    /// whitespace, so its spans fall on the same text and diagnostics quote what was typed.
+    fn append(&mut self, typed: &str, src: &str) -> (&'static Yarn<'static>, usize, usize) {
+        let (offset, line) = (self.text.len(), self.lines + 1);
+        self.text.push_str(typed);
+        if !typed.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.lines = self.text.matches('\n').count();
+
+        let src: &'static str = Box::leak(src.to_owned().into_boxed_str());
+        (Box::leak(Box::new(Yarn::borrowed(src))), offset, line)
+    }
+
+    /// Prints `diags`, returning whether any of them is an error.
+    fn report(&self, diags: &[Diagnostic]) -> bool {
+        let file = SourceFile::new("<repl>", &self.text);
+        let mut reporter = Reporter::new(self.error_format, &file);
+        reporter.report_all(diags);
+        reporter.has_errors()
+    }
+
+    /// Runs one entry, printing the value of its last statement unless that is `Void`.
+    pub fn eval(&mut self, typed: &str, out: &mut dyn Write) -> Result<(), ()> {
+        let trimmed = typed.trim_end();
+        let src = if trimmed.ends_with(';') || trimmed.ends_with('}') || trimmed.is_empty() {
+            typed.to_string()
+        } else {
+            format!("{};", trimmed)
+        };
+
+        let (yarn, offset, line) = self.append(typed, &src);
+        let mut parser = Parser::from_lexer(Lexer::new(yarn).with_origin(offset, line));
+        let tree: &'static Node<'static> = Box::leak(Box::new(parser.parse_program()));
+        if self.report(parser.errors()) {
+            return Err(());
+        }
+
+        let resolutions = Resolver::new(&mut self.session).resolve_program(tree);
+        self.resolutions.extend(resolutions);
+        let types = TypeChecker::new(&mut self.session, &self.resolutions).check_program(tree);
+        self.types.extend(types);
+
+        let mut diags = self.session.diagnostics.take();
+        diags.sort_by_key(|diag| diag.primary_span().map(|span| span.start));
+        if self.report(&diags) {
+            return Err(());
+        }
+
+        let result = Interpreter::new(&self.session.symbols, &self.resolutions, &self.types, &mut self.runtime, out).run_entry(tree);
+        match result {
+            Ok(Value::Void) => Ok(()),
+            Ok(value) => {
+                let _ = writeln!(out, "{}", value);
+                Ok(())
+            },
+            Err(diag) => {
+                self.report(&[diag]);
+                Err(())
+            }
+        }
+    }
+
+    fn parse_expr(&mut self, src: &str) -> Option<&'static Node<'static>> {
+        let (yarn, offset, line) = self.append(src, src);
+        let mut parser = Parser::from_lexer(Lexer::new(yarn).with_origin(offset, line));
+        let expr = parser.parse_expr().and_then(|expr| parser.expect_end().map(|_| expr));
+        let mut diags = parser.take_errors();
+        match expr {
+            Ok(expr) if diags.is_empty() => Some(Box::leak(Box::new(expr))),
+            Ok(_) => {
+                self.report(&diags);
+                None
+            },
+            Err(err) => {
+                diags.push(err.into());
+                self.report(&diags);
+                None
+            }
+        }
+    }
+
+    /// `:type <expr>` checks without running anything.
+    fn type_of(&mut self, src: &str, out: &mut dyn Write) {
+        let Some(expr) = self.parse_expr(src) else {
+            return;
+        };
+        let resolutions = Resolver::new(&mut self.session).resolve_expression(expr);
+        self.resolutions.extend(resolutions);
+        let ty = TypeChecker::new(&mut self.session, &self.resolutions).check_expr(expr, None);
+
+        let diags = self.session.diagnostics.take();
+        if !self.report(&diags) {
+            if let Some(ty) = ty {
+                let _ = writeln!(out, "{}", ty);
+            }
+        }
+    }
+
+    fn ast_of(&mut self, src: &str, out: &mut dyn Write) {
+        if let Some(expr) = self.parse_expr(src) {
+            let _ = write!(out, "{}", emit::ast_to_string(expr));
+        }
+    }
+
+    fn load(&mut self, path: &str, out: &mut dyn Write) {
+        match fs::read_to_string(path) {
+            Ok(src) => {
+                let _ = self.eval(&src, out);
+            },
+            Err(err) => eprintln!("error: couldn't read `{}`: {}", path, err)
+        }
+    }
+
+    /// Handles a `:command`; returns `false` when the REPL should exit.
+    fn command(&mut self, line: &str, out: &mut dyn Write) -> bool {
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match name {
+            ":quit" | ":q" => return false,
+            ":help" | ":h" => {
+                let _ = write!(out, "{}", HELP);
+            },
+            ":type" | ":t" if !rest.is_empty() => self.type_of(rest, out),
+            ":ast" if !rest.is_empty() => self.ast_of(rest, out),
+            ":load" | ":l" if !rest.is_empty() => self.load(rest, out),
+            ":type" | ":t" | ":ast" | ":load" | ":l" => eprintln!("error: `{}` needs an argument", name),
+            _ => eprintln!("error: unknown command `{}`; try `:help`", name)
+        }
+        true
+    }
+}
+
+/// Whether `src` stops inside braces, parentheses, brackets, a string or a comment.
+pub(crate) fn is_incomplete(src: &str) -> bool {
+    let (tokens, errors) = Lexer::from_str(src).tokenize_all();
+    if errors.iter().any(|err| matches!(err.kind, LexErrorKind::UnterminatedString | LexErrorKind::UnterminatedComment)) {
+        return true;
+    }
+
+    let depth = tokens.iter().fold(0i64, |depth, token| match token.kind {
+        TokenKind::Punct(Punct::LBrace | Punct::LParen | Punct::LBracket) => depth + 1,
+        TokenKind::Punct(Punct::RBrace | Punct::RParen | Punct::RBracket) => depth - 1,
+        _ => depth
+    });
+    depth > 0
+}
+
+/// Runs the REPL on stdin until `:quit` or end of input.
+pub(crate) fn run(opts: &Options) -> Status {
+    let mut repl = Repl::new(opts.error_format);
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    if !opts.input.as_os_str().is_empty() {
+        repl.load(&opts.input.to_string_lossy(), &mut stdout);
+    }
+
+    let mut buffer = String::new();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let _ = write!(stdout, "{}", if buffer.is_empty() { PROMPT } else { CONTINUE_PROMPT });
+        let _ = stdout.flush();
+
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            if !repl.command(line.trim(), &mut stdout) {
+                break;
+            }
+            continue;
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if is_incomplete(&buffer) {
+            continue;
+        }
+
+        if !buffer.trim().is_empty() {
+            let _ = repl.eval(&buffer, &mut stdout);
+        }
+        buffer.clear();
+    }
+
+    let _ = writeln!(stdout);
+    Status::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the REPL prints for `entries`, each evaluated as one entry.
+    fn session(entries: &[&str]) -> String {
+        let mut repl = Repl::new(ErrorFormat::Human);
+        let mut out = Vec::new();
+        for entry in entries {
+            if entry.starts_with(':') {
+                assert!(repl.command(entry, &mut out));
+            } else {
+                let _ = repl.eval(entry, &mut out);
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn later_entries_call_earlier_functions() {
+        let out = session(&["defun inc(n: Int32) => Int32 { n + 1 }", "let x = 4;", "inc(x)", "inc(inc(x))"]);
+        assert_eq!(out, "5\n6\n");
+    }
+
+    #[test]
+    fn type_of_a_call_to_an_earlier_function() {
+        let out = session(&["defun inc(n: Int32) => Int32 { n + 1 }", "let x = 4;", ":type inc(x) * 2"]);
+        assert_eq!(out, "Int32\n");
+    }
+
+    #[test]
+    fn entering_a_let_again_rebinds_it() {
+        let out = session(&["let x = 1;", "let x = 1.5;", "x", "let x = 1.5;", "x * 2.0"]);
+        assert_eq!(out, "1.5\n3\n");
+
+        // Only variables rebind; a function keeps its name.
+        let mut repl = Repl::new(ErrorFormat::Human);
+        assert!(repl.eval("defun f() {}", &mut Vec::new()).is_ok());
+        assert!(repl.eval("let f = 1;", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn diagnostics_quote_the_entry_as_typed() {
+        let mut repl = Repl::new(ErrorFormat::Human);
+        assert!(repl.eval("undefined_y", &mut Vec::new()).is_err());
+        assert!(repl.eval("let z = 2;  ", &mut Vec::new()).is_ok());
+        assert_eq!(repl.text, "undefined_y\nlet z = 2;  \n");
+    }
+
+    #[test]
+    fn incomplete_input_waits_for_its_braces() {
+        assert!(is_incomplete("defun f() {\n"));
+        assert!(is_incomplete("let s = \"abc"));
+        assert!(!is_incomplete("defun f() { 1 }\n"));
+    }
+}
@@ -0,0 +1,363 @@
+//! Whole programs taken through the stages `compile` runs, and then through the
+//! interpreter, the VM and each code generator.
+
+use std::collections::BTreeMap;
+
+use super::*;
+
+/// Parses and checks `src`, handing what checking produced to `then`. The first error
+/// of any stage is returned instead.
+fn front<T>(src: &str, then: impl for<'a> FnOnce(&Session<'a>, &Resolutions, &TypeTable<'a>, &Node<'a>) -> Result<T, String>) -> Result<T, String> {
+    let yarn = Yarn::borrowed(src);
+    let mut parser = Parser::new(&yarn);
+    let tree = parser.parse_program();
+    first_error(parser.errors())?;
+    let mut session = Session::new();
+    let resolutions = Resolver::new(&mut session).resolve_program(&tree);
+    let types = TypeChecker::new(&mut session, &resolutions).check_program(&tree);
+    first_error(session.diagnostics.iter())?;
+    then(&session, &resolutions, &types, &tree)
+}
+
+fn first_error<'d>(diags: impl IntoIterator<Item = &'d Diagnostic>) -> Result<(), String> {
+    match diags.into_iter().find(|diag| diag.is_error()) {
+        Some(diag) => Err(diag.message.clone()),
+        None => Ok(())
+    }
+}
+
+/// The codes of the errors checking `src` reports, in order.
+fn error_codes(src: &str) -> Vec<&'static str> {
+    let yarn = Yarn::borrowed(src);
+    let tree = Parser::new(&yarn).parse_program();
+    let mut session = Session::new();
+    let resolutions = Resolver::new(&mut session).resolve_program(&tree);
+    TypeChecker::new(&mut session, &resolutions).check_program(&tree);
+    session.diagnostics.iter().filter(|diag| diag.is_error()).filter_map(|diag| diag.code).collect()
+}
+
+/// What the interpreter prints running `src`, or the error it stopped with.
+fn interpret(src: &str) -> Result<String, String> {
+    front(src, |session, resolutions, types, tree| {
+        let (mut out, mut runtime) = (Vec::new(), Runtime::default());
+        Interpreter::new(&session.symbols, resolutions, types, &mut runtime, &mut out)
+            .run_program(tree)
+            .map_err(|diag| diag.message)?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    })
+}
+
+/// The module `src` lowers to, verified.
+fn lowered(src: &str) -> Result<Module, String> {
+    front(src, |session, resolutions, types, tree| {
+        lower(session, resolutions, types, tree).map_err(|diags| diags[0].message.clone())
+    })
+}
+
+/// What the VM prints running `src` lowered and optimized at `level`.
+fn run_vm(src: &str, level: u8) -> Result<String, String> {
+    let mut module = lowered(src)?;
+    Pipeline::for_level(level).run(&mut module, |_| {}).map_err(|(pass, _)| format!("`{}` broke the module", pass))?;
+    let program = compile_bytecode(&module).map_err(|diags| diags[0].message.clone())?;
+    let mut out = Vec::new();
+    Vm::new(&program, &mut out).run().map_err(|diag| diag.message)?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// The output of every code generator for `src`, by target name.
+fn generated(src: &str) -> Result<BTreeMap<&'static str, String>, String> {
+    let module = lowered(src)?;
+    let first = |diags: Vec<Diagnostic>| diags[0].message.clone();
+    Ok(BTreeMap::from([
+        ("c", codegen::c::generate(&module).map_err(first)?),
+        ("wasm", codegen::wasm::generate(&module).map_err(first)?.to_string()),
+        ("x86_64", codegen::x86_64::generate(&module).map_err(first)?)
+    ]))
+}
+
+/// Checks that the interpreter and the VM, at every optimization level, print `expected`.
+fn assert_runs(src: &str, expected: &str) {
+    assert_eq!(interpret(src).as_deref(), Ok(expected), "interpreter");
+    for level in 0..=3 {
+        assert_eq!(run_vm(src, level).as_deref(), Ok(expected), "VM at -O{}", level);
+    }
+    generated(src).expect("code generation");
+}
+
+const TRAIT_OBJECT: &str = "
+trait AnimalBehaviour {
+    defun speak(self) => Int32;
+}
+obj Doggy { bark: Int32; }
+extend Doggy with AnimalBehaviour {
+    defun speak(self) => Int32 { self.bark }
+}
+defun total(a: *AnimalBehaviour) => Int32 { return a.speak(); }
+defun main() {
+    let d = Doggy(101);
+    println(total(d));
+}
+";
+
+#[test]
+fn trait_object_call() {
+    assert_runs(TRAIT_OBJECT, "101\n");
+}
+
+#[test]
+fn default_method_call() {
+    let src = "
+trait Shape {
+    defun area(self) => Int32;
+    defun describe(self) => Int32 { self.area() * 10 }
+}
+obj Square { side: Int32; }
+extend Square with Shape {
+    defun area(self) => Int32 { self.side * self.side }
+}
+defun main() {
+    let s = Square(3);
+    println(s.describe());
+}
+";
+    assert_runs(src, "90\n");
+    let module = lowered(src).unwrap();
+    assert!(module.functions.iter().any(|function| function.name == "Square::Shape::describe"));
+}
+
+#[test]
+fn default_method_through_trait_object() {
+    let src = "
+trait Animal {
+    defun name(self) => Int32;
+    defun legs(self) => Int32 { 4 }
+    defun describe(self) => Int32 { self.legs() * 100 + self.name() }
+}
+obj Doggy { id: Int32; }
+obj Bird { id: Int32; }
+extend Doggy with Animal {
+    defun name(self) => Int32 { self.id }
+}
+extend Bird with Animal {
+    defun name(self) => Int32 { self.id }
+    defun legs(self) => Int32 { 2 }
+}
+defun count(a: *Animal) => Int32 { a.describe() }
+defun main() {
+    println(count(Doggy(10)), count(Bird(7)));
+    let a: *Animal = Doggy(3);
+    println(a.legs());
+}
+";
+    assert_runs(src, "410 207\n4\n");
+}
+
+#[test]
+fn recursion_reaches_the_call_depth_limit() {
+    let src = |depth: usize| format!("
+defun down(n: Int32) => Int32 {{
+    if n == 0 {{ return 0; }}
+    return down(n - 1) + 1;
+}}
+defun main() {{ println(down({})); }}
+", depth);
+    // `main` itself takes one frame; the driver runs on a thread this size as well.
+    let deepest = src(crate::interpreter::MAX_CALL_DEPTH - 2);
+    let too_deep = src(crate::interpreter::MAX_CALL_DEPTH);
+    let (deepest, too_deep) = std::thread::Builder::new()
+        .stack_size(crate::interpreter::STACK_SIZE)
+        .spawn(move || (interpret(&deepest), interpret(&too_deep)))
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(deepest, Ok(format!("{}\n", crate::interpreter::MAX_CALL_DEPTH - 2)));
+    assert!(too_deep.is_err());
+}
+
+#[test]
+fn vm_recursion_reaches_its_own_limit() {
+    let src = |depth: usize| format!("
+defun down(n: Int32) => Int32 {{
+    if n == 0 {{ return 0; }}
+    return down(n - 1) + 1;
+}}
+defun main() {{ println(down({})); }}
+", depth);
+    let deepest = crate::vm::MAX_CALL_DEPTH - 2;
+    assert_eq!(run_vm(&src(deepest), 0), Ok(format!("{}\n", deepest)));
+    assert!(run_vm(&src(crate::vm::MAX_CALL_DEPTH), 0).is_err());
+}
+
+#[test]
+fn failed_initializer_leaves_the_type_unknown_silently() {
+    let src = "
+defun main() {
+    let y = true + 1;
+    let z = y * 2;
+    println(z);
+}
+";
+    assert_eq!(error_codes(src), [codes::INVALID_BINARY_OPERANDS]);
+    assert_eq!(error_codes("defun main() { let y; }"), [codes::CANNOT_INFER]);
+}
+
+#[test]
+fn suggestions_name_the_kind_of_the_candidate() {
+    let help = |src: &str| {
+        let yarn = Yarn::borrowed(src);
+        let tree = Parser::new(&yarn).parse_program();
+        let mut session = Session::new();
+        Resolver::new(&mut session).resolve_program(&tree);
+        let help = session.diagnostics.iter().find(|diag| diag.is_error()).map(|diag| diag.help.clone());
+        help.unwrap_or_default()
+    };
+    assert_eq!(help("obj Point { x: Int32; y: Int32; }\ndefun main() { let p = Piont(1, 2); }"),
+               ["an obj with a similar name exists: `Point`"]);
+    assert_eq!(help("defun point() {}\ndefun main() { piont(); }"),
+               ["a function with a similar name exists: `point`"]);
+}
+
+const SHAPES: &str = "
+trait Named {
+    defun name(self) => Int32;
+}
+trait Shape: Named {
+    defun area(self) => Int32;
+    defun scaled(self, by: Int32) => Int32;
+}
+obj Square { side: Int32; }
+extend Square with Named {
+    defun name(self) => Int32 { 4 }
+}
+";
+
+#[test]
+fn complete_implementations_check_cleanly() {
+    let src = format!("{}
+extend Square with Shape {{
+    defun area(self) => Int32 {{ self.side * self.side }}
+    defun scaled(self, by: Int32) => Int32 {{ self.area() * by }}
+}}
+", SHAPES);
+    assert_eq!(error_codes(&src), Vec::<&str>::new());
+}
+
+#[test]
+fn missing_trait_method() {
+    let src = format!("{}
+extend Square with Shape {{
+    defun area(self) => Int32 {{ self.side * self.side }}
+}}
+", SHAPES);
+    assert_eq!(error_codes(&src), [codes::MISSING_TRAIT_METHOD]);
+}
+
+#[test]
+fn method_not_in_the_trait() {
+    let src = format!("{}
+extend Square with Shape {{
+    defun area(self) => Int32 {{ self.side * self.side }}
+    defun scaled(self, by: Int32) => Int32 {{ by }}
+    defun perimeter(self) => Int32 {{ self.side * 4 }}
+}}
+", SHAPES);
+    assert_eq!(error_codes(&src), [codes::NOT_A_TRAIT_METHOD]);
+}
+
+#[test]
+fn signature_mismatch() {
+    let src = format!("{}
+extend Square with Shape {{
+    defun area(self) => Int64 {{ 1 }}
+    defun scaled(self, by: Boolean) => Int32 {{ 1 }}
+}}
+", SHAPES);
+    assert_eq!(error_codes(&src), [codes::SIGNATURE_MISMATCH, codes::SIGNATURE_MISMATCH]);
+}
+
+#[test]
+fn missing_supertrait() {
+    let src = "
+trait Named {
+    defun name(self) => Int32;
+}
+trait Shape: Named {
+    defun area(self) => Int32;
+}
+obj Circle { r: Int32; }
+extend Circle with Shape {
+    defun area(self) => Int32 { self.r * self.r * 3 }
+}
+";
+    assert_eq!(error_codes(src), [codes::UNSATISFIED_SUPERTRAIT]);
+}
+
+#[test]
+fn conflicting_implementations() {
+    let src = format!("{}
+extend Square with Named {{
+    defun name(self) => Int32 {{ 5 }}
+}}
+", SHAPES);
+    assert_eq!(error_codes(&src), [codes::CONFLICTING_IMPLEMENTATIONS]);
+}
+
+const GENERICS: &str = "
+trait Show {
+    defun show(self) => Int32;
+}
+obj Box<T> { value: T; }
+obj Plain { v: Int32; }
+extend Plain with Show {
+    defun show(self) => Int32 { self.v }
+}
+defun id<T>(x: T) => T { x }
+defun shown<T: Show>(x: T) => Int32 { x.show() }
+defun make<T>() => Int32 { 1 }
+";
+
+#[test]
+fn unsatisfied_bound() {
+    let src = format!("{}
+obj Bare {{ v: Int32; }}
+defun main() {{ println(shown(Bare(1))); }}
+", GENERICS);
+    assert_eq!(error_codes(&src), [codes::UNSATISFIED_BOUND]);
+}
+
+#[test]
+fn type_arguments_are_inferred_at_call_sites() {
+    let src = format!("{}
+defun main() {{
+    let a = id(3);
+    let b: Int64 = id(4000000000);
+    let c = Box(true);
+    println(a, b, c.value, id(c).value, shown(Plain(7)));
+}}
+", GENERICS);
+    assert_eq!(error_codes(&src), Vec::<&str>::new());
+    assert_runs(&src, "3 4000000000 true true 7\n");
+
+    let module = lowered(&src).unwrap();
+    for name in ["id.i32", "id.i64", "id.Box.bool", "shown.Plain"] {
+        assert!(module.function(name).is_some(), "no `@{}`", name);
+    }
+    assert!(module.structure("Box.bool").is_some());
+
+    let uninferable = format!("{}\ndefun main() {{ println(make()); }}\n", GENERICS);
+    assert_eq!(error_codes(&uninferable), [codes::CANNOT_INFER]);
+}
+
+#[test]
+fn monomorphized_names_reach_every_backend() {
+    let src = format!("{}
+defun main() {{
+    let b = Box(false);
+    println(id(2), b.value);
+}}
+", GENERICS);
+    let generated = generated(&src).unwrap();
+    assert!(generated["c"].contains("fn_id_i32(") && generated["c"].contains("struct s_Box_bool"), "{}", generated["c"]);
+    assert!(generated["x86_64"].contains("fn.id.i32:") && generated["x86_64"].contains("show.Box.bool:"), "{}", generated["x86_64"]);
+    assert!(generated["wasm"].contains("$fn.id.i32") && generated["wasm"].contains("$show.Box.bool"), "{}", generated["wasm"]);
+}
@@ -0,0 +1,566 @@
+pub(crate) mod value;
+
+use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+
+use crate::{
+    analysis::{
+        resolve::{top_level, Resolutions},
+        symbols::{Builtin, SymbolId, SymbolKind, SymbolTable},
+        typeck::TypeTable
+    },
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        syntax_tree::{BinOp, Bodies, DefunDescriptor, Literal, Node, Type, UniOp, VarDeclaration},
+        yarn::Yarn
+    }
+};
+
+use self::value::{ArithError, Instance, IntTy, Value};
+
+/// Deep recursion in the program is deep recursion here too, so it is cut off well
+/// before the host stack runs out.
+pub(crate) const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The stack the interpreter needs to reach `MAX_CALL_DEPTH`. An unoptimized build
+/// spends about 12 KiB of it on each call of a small function (an optimized one about
+/// 2 KiB), so this leaves room for calls whose bodies nest deeper. Only the pages a
+/// program actually reaches are ever touched.
+pub(crate) const STACK_SIZE: usize = 256 << 20;
+
+struct Function<'n, 'a> {
+    descriptor: &'n DefunDescriptor<'a>,
+    body: &'n [Node<'a>],
+    span: Span
+}
+
+/// Why evaluation left a node before its end: an error, or a jump that the statements
+/// around it pass on until it reaches the loop or call it is for.
+enum Exit {
+    /// Boxed so that the `Result`s every evaluation step passes back stay small.
+    Error(Box<Diagnostic>),
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Value)
+}
+
+impl From<Diagnostic> for Exit {
+    fn from(diag: Diagnostic) -> Self {
+        Exit::Error(Box::new(diag))
+    }
+}
+
+impl Exit {
+    /// The error that stopped the program; the resolver keeps jumps inside their loops
+    /// and functions, so no other exit reaches the top level.
+    fn into_error(self) -> Diagnostic {
+        match self {
+            Exit::Error(diag) => *diag,
+            _ => Diagnostic::error("`break`, `continue` or `return` outside of what it leaves").with_code(codes::INVALID_OPERATION)
+        }
+    }
+}
+
+/// The functions and globals a program has defined so far. It outlives a single
+/// `Interpreter`, so the REPL can keep them between entries.
+#[derive(Default)]
+pub(crate) struct Runtime<'n, 'a> {
+    functions: HashMap<String, Function<'n, 'a>>,
+    globals: HashMap<SymbolId, Value>
+}
+
+/// Evaluates a resolved and type-checked `Node` tree directly.
+pub(crate) struct Interpreter<'c, 'n, 'a> {
+    symbols: &'c SymbolTable<'a>,
+    resolutions: &'c Resolutions,
+    types: &'c TypeTable<'a>,
+    runtime: &'c mut Runtime<'n, 'a>,
+    frames: Vec<HashMap<SymbolId, Value>>,
+    out: &'c mut dyn Write
+}
+
+impl<'c, 'n, 'a> Interpreter<'c, 'n, 'a> {
+
+    pub fn new(
+        symbols: &'c SymbolTable<'a>,
+        resolutions: &'c Resolutions,
+        types: &'c TypeTable<'a>,
+        runtime: &'c mut Runtime<'n, 'a>,
+        out: &'c mut dyn Write
+    ) -> Self {
+        Self {
+            symbols,
+            resolutions,
+            types,
+            runtime,
+            frames: Vec::new(),
+            out
+        }
+    }
+
+    /// Runs the top-level statements in order, then `main` if the program defines one.
+    /// The result is `main`'s value, or `Void` without a `main`.
+    pub fn run_program(&mut self, tree: &'n Node<'a>) -> Result<Value, Diagnostic> {
+        self.run_entry(tree)?;
+
+        let main = self.symbols.lookup_in(self.symbols.root(), "main")
+            .and_then(|symbol| match &symbol.kind {
+                SymbolKind::Function(defun) => Some((defun.qualified().to_string(), symbol.span)),
+                _ => None
+            });
+        match main {
+            Some((main, span)) => self.call(&main, Vec::new(), span).map_err(Exit::into_error),
+            None => Ok(Value::Void)
+        }
+    }
+
+    /// Registers the program's functions and runs its top-level statements, giving the
+    /// value of the last one.
+    pub fn run_entry(&mut self, tree: &'n Node<'a>) -> Result<Value, Diagnostic> {
+        let items = top_level(tree);
+        for item in items {
+            self.register(item);
+        }
+        let mut last = Value::Void;
+        for item in items {
+            last = self.exec_statement(item).map_err(Exit::into_error)?;
+        }
+        Ok(last)
+    }
+
+    /// Makes the functions an item defines callable.
+    fn register(&mut self, item: &'n Node<'a>) {
+        let Node::Body { discriptor, body, span } = item else {
+            return;
+        };
+        match discriptor.as_ref() {
+            Bodies::Defun(descriptor) => {
+                self.runtime.functions.insert(descriptor.qualified().to_string(), Function { descriptor, body, span: *span });
+            },
+            Bodies::Object(_) | Bodies::Extension(_) => {
+                for method in body {
+                    self.register(method);
+                }
+            },
+            Bodies::Trait(tr) => {
+                for method in body {
+                    if let Node::Body { discriptor, .. } = method {
+                        if matches!(discriptor.as_ref(), Bodies::Defun(defun) if tr.has_default(defun.name().as_slice())) {
+                            self.register(method);
+                        }
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn error(&self, span: Span, code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(message)
+            .with_code(code)
+            .with_primary(span, "")
+    }
+
+    fn arith_error(&self, err: ArithError, verb: &str, span: Span) -> Diagnostic {
+        arith_error(err, verb).with_primary(span, "")
+    }
+
+    fn bind(&mut self, id: SymbolId, value: Value) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.insert(id, value),
+            None => self.runtime.globals.insert(id, value)
+        };
+    }
+
+    fn read(&self, id: SymbolId, span: Span) -> Result<Value, Exit> {
+        self.frames.last()
+            .and_then(|frame| frame.get(&id))
+            .or_else(|| self.runtime.globals.get(&id))
+            .cloned()
+            .ok_or_else(|| {
+                let name = &self.symbols.symbol(id).name;
+                self.error(span, codes::UNINITIALIZED, format!("`{}` was used before it was initialized", name)).into()
+            })
+    }
+
+    fn call(&mut self, qualified: &str, args: Vec<Value>, span: Span) -> Result<Value, Exit> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(self.too_deep(qualified, span));
+        }
+        let Some(function) = self.runtime.functions.get(qualified) else {
+            return Err(self.error(span, codes::INVALID_OPERATION, format!("`{}` has no body", qualified)).into());
+        };
+        let (descriptor, body) = (function.descriptor, function.body);
+
+        let frame = self.resolutions.params(function.span).iter().copied().zip(args).collect();
+        self.frames.push(frame);
+        let result = match self.exec_block(body) {
+            Err(Exit::Return(value)) => Ok(value),
+            result => result
+        };
+        self.frames.pop();
+
+        if descriptor.return_type().is_void() {
+            result.map(|_| Value::Void)
+        } else {
+            result
+        }
+    }
+
+    #[cold]
+    fn too_deep(&self, qualified: &str, span: Span) -> Exit {
+        self.error(span, codes::STACK_OVERFLOW, format!("call to `{}` exceeds the maximum call depth", qualified))
+            .with_note(format!("calls may nest at most {} deep", MAX_CALL_DEPTH)).into()
+    }
+
+    fn exec_block(&mut self, stmts: &'n [Node<'a>]) -> Result<Value, Exit> {
+        let mut last = Value::Void;
+        for stmt in stmts {
+            last = self.exec_statement(stmt)?;
+        }
+        Ok(last)
+    }
+
+    fn exec_statement(&mut self, stmt: &'n Node<'a>) -> Result<Value, Exit> {
+        match stmt {
+            Node::Value { ret, .. } => {
+                if let Some(id) = self.resolutions.get(stmt) {
+                    self.bind(id, default_value(&self.types.declared(stmt, ret)));
+                }
+                Ok(Value::Void)
+            },
+            Node::BinaryOp { lhs, rhs, op: BinOp::Assign, .. } if matches!(lhs.as_ref(), Node::Value { .. }) => {
+                let value = self.eval(rhs)?;
+                if let Some(id) = self.resolutions.get(lhs) {
+                    self.bind(id, value);
+                }
+                Ok(Value::Void)
+            },
+            Node::Chain { chained } => self.exec_block(chained),
+            Node::Body { .. } => Ok(Value::Void),
+            expr => self.eval(expr)
+        }
+    }
+
+    /// Stores into an identifier or a field.
+    fn assign(&mut self, place: &'n Node<'a>, value: Value) -> Result<(), Exit> {
+        match place {
+            Node::Ident { .. } => {
+                let Some(id) = self.resolutions.get(place) else {
+                    return Err(self.error(place.span(), codes::INVALID_OPERATION, "assignment to an unresolved name").into());
+                };
+                match self.frames.last_mut() {
+                    Some(frame) if frame.contains_key(&id) => frame.insert(id, value),
+                    _ => self.runtime.globals.insert(id, value)
+                };
+                Ok(())
+            },
+            Node::Field { recv, name, span } => {
+                let instance = self.instance(recv)?;
+                let mut instance = instance.borrow_mut();
+                match instance.field_mut(name.as_slice()) {
+                    Some(field) => {
+                        *field = value;
+                        Ok(())
+                    },
+                    None => Err(self.error(*span, codes::INVALID_OPERATION, format!("no field `{}`", name)).into())
+                }
+            },
+            other => Err(self.error(other.span(), codes::INVALID_OPERATION, "cannot assign to this expression").into())
+        }
+    }
+
+    fn instance(&mut self, recv: &'n Node<'a>) -> Result<Rc<RefCell<Instance>>, Exit> {
+        match self.eval(recv)? {
+            Value::Instance(instance) => Ok(instance),
+            other => Err(self.error(recv.span(), codes::INVALID_OPERATION, format!("`{}` is not an instance", other)).into())
+        }
+    }
+
+    fn eval(&mut self, node: &'n Node<'a>) -> Result<Value, Exit> {
+        match node {
+            Node::Literal { value, .. } => Ok(self.literal(node, value, false)),
+            Node::Ident { span, .. } => self.eval_ident(node, *span),
+            Node::BinaryOp { lhs, rhs, op, span } => self.eval_binary(lhs, rhs, *op, *span),
+            Node::UnaryOp { lhs, op, postfix, span } => self.eval_unary(lhs, *op, *postfix, *span),
+            Node::Call { args, span, .. } => self.eval_call(node, args, *span),
+            Node::ObjCall { recv, func, args, span } => self.eval_method(node, recv, func, args, *span),
+            Node::Field { recv, name, span } => self.eval_field(recv, name, *span),
+            Node::Chain { chained } => self.exec_block(chained),
+            Node::If { cond, then, els, .. } => self.eval_if(node, cond, then, els.as_deref()),
+            Node::While { label, cond, body, .. } => self.eval_while(label, cond, body),
+            Node::For { label, var, start, end, body, span } => self.eval_for(label, var, start, end, body, *span),
+            Node::Loop { label, body, .. } => {
+                while !self.iterate(label, body)? {}
+                Ok(Value::Void)
+            },
+            Node::Break { label, .. } => Err(Exit::Break(label.as_ref().map(Yarn::to_string))),
+            Node::Continue { label, .. } => Err(Exit::Continue(label.as_ref().map(Yarn::to_string))),
+            Node::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Void
+                };
+                Err(Exit::Return(value))
+            },
+            Node::Value { .. } | Node::Body { .. } => self.exec_statement(node),
+            Node::Head { next } => self.eval(next),
+            Node::Error { span } => Err(self.error(*span, codes::INVALID_OPERATION, "cannot run code that failed to parse").into())
+        }
+    }
+
+    // `eval` recurses through these for every call the program makes, so they stay out
+    // of its frame rather than growing it with locals most nodes never need.
+
+    #[inline(never)]
+    fn eval_call(&mut self, node: &'n Node<'a>, args: &'n [Node<'a>], span: Span) -> Result<Value, Exit> {
+        let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>, _>>()?;
+        let symbols = self.symbols;
+        let callee = self.resolutions.get(node).map(|id| &symbols.symbol(id).kind);
+        match callee {
+            Some(SymbolKind::Function(defun)) => self.call(defun.qualified().as_slice(), args, span),
+            Some(SymbolKind::Object(obj)) => Ok(construct(obj.name().as_slice(), obj.fields(), args)),
+            Some(SymbolKind::Composition(comp)) => Ok(construct(comp.name().as_slice(), comp.fields(), args)),
+            Some(SymbolKind::Builtin(builtin)) => self.builtin(*builtin, &args, span),
+            _ => Err(self.error(span, codes::INVALID_OPERATION, "call to something that is not a function").into())
+        }
+    }
+
+    #[inline(never)]
+    fn eval_method(&mut self, node: &'n Node<'a>, recv: &'n Node<'a>, func: &Yarn<'a>, args: &'n [Node<'a>], span: Span) -> Result<Value, Exit> {
+        let instance = self.instance(recv)?;
+        // Through a trait object, a type parameter's bound or the `self` of a default
+        // body, the checker found the trait's declaration; the instance decides what
+        // runs for it.
+        let symbols = self.symbols;
+        let ty = instance.borrow().ty.clone();
+        let with = self.types.through(node)
+            .or_else(|| self.types.get(recv).and_then(|static_ty| symbols.dispatch_trait(static_ty)));
+        let method = match with {
+            Some(with) => symbols.method(with, func.as_slice()).found().and_then(|declared| symbols.implementing(&ty, declared)),
+            None => symbols.method(&ty, func.as_slice()).found()
+        };
+        let method = method.and_then(|symbol| match &symbol.kind {
+            SymbolKind::Function(defun) => Some(defun),
+            _ => None
+        });
+        let Some(method) = method else {
+            return Err(self.error(span, codes::INVALID_OPERATION, format!("no method `{}` on `{}`", func, instance.borrow().ty)).into());
+        };
+        let qualified = method.qualified().as_slice();
+        let takes_self = method.args().first()
+            .and_then(|arg| arg.name())
+            .is_some_and(|name| name.as_slice() == "self");
+
+        let mut values = Vec::with_capacity(args.len() + 1);
+        if takes_self {
+            values.push(Value::Instance(instance));
+        }
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+        self.call(qualified, values, span)
+    }
+
+    #[inline(never)]
+    fn eval_ident(&self, node: &'n Node<'a>, span: Span) -> Result<Value, Exit> {
+        match self.resolutions.get(node) {
+            Some(id) => self.read(id, span),
+            None => Err(self.error(span, codes::INVALID_OPERATION, "unresolved name").into())
+        }
+    }
+
+    #[inline(never)]
+    fn eval_field(&mut self, recv: &'n Node<'a>, name: &Yarn<'a>, span: Span) -> Result<Value, Exit> {
+        let instance = self.instance(recv)?;
+        let field = instance.borrow().field(name.as_slice()).cloned();
+        field.ok_or_else(|| self.error(span, codes::INVALID_OPERATION, format!("no field `{}`", name)).into())
+    }
+
+    #[inline(never)]
+    fn eval_if(&mut self, node: &'n Node<'a>, cond: &'n Node<'a>, then: &'n [Node<'a>], els: Option<&'n [Node<'a>]>) -> Result<Value, Exit> {
+        let value = if self.test(cond)? {
+            self.exec_block(then)?
+        } else if let Some(els) = els {
+            self.exec_block(els)?
+        } else {
+            Value::Void
+        };
+        // A branch's value is dropped when the other has none.
+        let typed = self.types.get(node).is_some_and(|ty| !ty.is_void());
+        Ok(if typed { value } else { Value::Void })
+    }
+
+    #[inline(never)]
+    fn eval_while(&mut self, label: &Option<Yarn<'a>>, cond: &'n Node<'a>, body: &'n [Node<'a>]) -> Result<Value, Exit> {
+        while self.test(cond)? {
+            if self.iterate(label, body)? {
+                break;
+            }
+        }
+        Ok(Value::Void)
+    }
+
+    #[inline(never)]
+    fn eval_for(
+        &mut self,
+        label: &Option<Yarn<'a>>,
+        var: &'n Node<'a>,
+        start: &'n Node<'a>,
+        end: &'n Node<'a>,
+        body: &'n [Node<'a>],
+        span: Span
+    ) -> Result<Value, Exit> {
+        let mut counter = self.eval(start)?;
+        let end = self.eval(end)?;
+        let Some(id) = self.resolutions.get(var) else {
+            return Ok(Value::Void);
+        };
+        loop {
+            let below = counter.binary(BinOp::LessThan, &end).map_err(|err| self.arith_error(err, "compare", span))?;
+            if below.truthy() != Some(true) {
+                break;
+            }
+            self.bind(id, counter);
+            if self.iterate(label, body)? {
+                break;
+            }
+            // The body may have assigned to the counter.
+            counter = self.read(id, span)?.step(1).map_err(|err| self.arith_error(err, "increment", span))?;
+        }
+        Ok(Value::Void)
+    }
+
+    fn test(&mut self, cond: &'n Node<'a>) -> Result<bool, Exit> {
+        let value = self.eval(cond)?;
+        value.truthy().ok_or_else(|| self.arith_error(ArithError::Mismatch, "test", cond.span()).into())
+    }
+
+    /// Runs the body of the loop labelled `label` once, giving whether a `break` ended the
+    /// loop. Jumps for a loop further out are passed on.
+    fn iterate(&mut self, label: &Option<Yarn<'a>>, body: &'n [Node<'a>]) -> Result<bool, Exit> {
+        let ours = |target: &Option<String>| target.is_none() || target.as_deref() == label.as_ref().map(Yarn::as_slice);
+        match self.exec_block(body) {
+            Ok(_) => Ok(false),
+            Err(Exit::Break(target)) if ours(&target) => Ok(true),
+            Err(Exit::Continue(target)) if ours(&target) => Ok(false),
+            Err(exit) => Err(exit)
+        }
+    }
+
+    fn literal(&self, node: &Node<'a>, value: &Literal<'a>, negated: bool) -> Value {
+        let ty = self.types.get(node);
+        match value {
+            Literal::Int(value) => {
+                let ty = ty.and_then(IntTy::from_type).unwrap_or(IntTy::INT32);
+                let value = if negated { -(*value as i128) } else { *value as i128 };
+                Value::Int(value, ty)
+            },
+            Literal::Float(value) => {
+                let bits = ty.and_then(Type::bits).unwrap_or(64);
+                Value::float(if negated { -value } else { *value }, bits)
+            },
+            Literal::Str(value) => Value::Str(value.as_slice().into()),
+            Literal::Boolean(value) => Value::Bool(*value)
+        }
+    }
+
+    fn eval_binary(&mut self, lhs: &'n Node<'a>, rhs: &'n Node<'a>, op: BinOp, span: Span) -> Result<Value, Exit> {
+        match op {
+            BinOp::Assign => {
+                let value = self.eval(rhs)?;
+                self.assign(lhs, value)?;
+                return Ok(Value::Void);
+            },
+            BinOp::LogAnd | BinOp::LogOr => {
+                let short = op == BinOp::LogOr;
+                if self.test(lhs)? == short {
+                    return Ok(Value::Bool(short));
+                }
+                return self.test(rhs).map(Value::Bool);
+            },
+            _ => {}
+        }
+
+        let left = self.eval(lhs)?;
+        let right = self.eval(rhs)?;
+        self.apply(lhs, left, op, right, span)
+    }
+
+    /// Combines the operands of `op`, storing the result back into `lhs` for a compound
+    /// assignment.
+    #[inline(never)]
+    fn apply(&mut self, lhs: &'n Node<'a>, left: Value, op: BinOp, right: Value, span: Span) -> Result<Value, Exit> {
+        let base = op.compound_base().unwrap_or(op);
+        let result = left.binary(base, &right).map_err(|err| self.arith_error(err, base.verb(), span))?;
+
+        if op.is_assignment() {
+            self.assign(lhs, result)?;
+            return Ok(Value::Void);
+        }
+        Ok(result)
+    }
+
+    fn eval_unary(&mut self, lhs: &'n Node<'a>, op: UniOp, postfix: bool, span: Span) -> Result<Value, Exit> {
+        // `-128` is a literal of its own, not the negation of an out-of-range `128`.
+        if let (UniOp::Negative, Node::Literal { value: value @ (Literal::Int(_) | Literal::Float(_)), .. }) = (op, lhs) {
+            return Ok(self.literal(lhs, value, true));
+        }
+
+        let value = self.eval(lhs)?;
+        match op {
+            UniOp::Negative => value.negate().map_err(|err| self.arith_error(err, "negate", span).into()),
+            UniOp::LogNot => value.not().map_err(|err| self.arith_error(err, "negate", span).into()),
+            UniOp::BitNot => value.bit_not().map_err(|err| self.arith_error(err, "complement", span).into()),
+            UniOp::Increment | UniOp::Decrement => {
+                let (by, verb) = if op == UniOp::Increment { (1, "increment") } else { (-1, "decrement") };
+                let stepped = value.step(by).map_err(|err| self.arith_error(err, verb, span))?;
+                self.assign(lhs, stepped.clone())?;
+                Ok(if postfix { value } else { stepped })
+            }
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Value], span: Span) -> Result<Value, Exit> {
+        let text = args.iter().map(Value::to_string).collect::<Vec<_>>().join(" ");
+        let result = match builtin {
+            Builtin::Print => write!(self.out, "{}", text),
+            Builtin::Println => writeln!(self.out, "{}", text)
+        };
+        result.map(|_| Value::Void)
+            .map_err(|err| self.error(span, codes::INVALID_OPERATION, format!("couldn't write output: {}", err)).into())
+    }
+}
+
+/// Builds an instance from constructor arguments given in field order.
+fn construct(ty: &str, fields: &[VarDeclaration<'_>], args: Vec<Value>) -> Value {
+    let fields = fields.iter()
+        .filter_map(|field| field.name().map(|name| name.to_string()))
+        .zip(args)
+        .collect();
+    Value::Instance(Rc::new(RefCell::new(Instance { ty: ty.to_string(), fields })))
+}
+
+/// The value of a `let` without an initializer.
+fn default_value(ty: &Type<'_>) -> Value {
+    match ty {
+        Type::Boolean => Value::Bool(false),
+        Type::Str => Value::Str("".into()),
+        ty if ty.is_float() => Value::float(0.0, ty.bits().unwrap_or(64)),
+        ty => match IntTy::from_type(ty) {
+            Some(int) => Value::Int(0, int),
+            None => Value::Void
+        }
+    }
+}
+
+/// The runtime error for an operation that failed with `err`; `verb` says what the
+/// operation was doing, as in "attempt to add with overflow".
+pub(crate) fn arith_error(err: ArithError, verb: &str) -> Diagnostic {
+    match err {
+        ArithError::Overflow => Diagnostic::error(format!("attempt to {} with overflow", verb)).with_code(codes::ARITHMETIC_OVERFLOW),
+        ArithError::DivideByZero if verb == "divide" => Diagnostic::error("attempt to divide by zero").with_code(codes::DIVIDE_BY_ZERO),
+        ArithError::DivideByZero => Diagnostic::error(format!("attempt to {} with a divisor of zero", verb)).with_code(codes::DIVIDE_BY_ZERO),
+        ArithError::Mismatch => Diagnostic::error(format!("cannot {} these values", verb)).with_code(codes::INVALID_OPERATION)
+    }
+}
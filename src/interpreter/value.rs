@@ -0,0 +1,235 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::{
+    common::syntax_tree::{BinOp, Type},
+    ir::display_name
+};
+
+/// The width and signedness of an integer value, mirroring `Int8`..`Uint64`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct IntTy {
+    pub bits: u32,
+    pub signed: bool
+}
+
+impl IntTy {
+    pub const INT32: IntTy = IntTy { bits: 32, signed: true };
+
+    pub fn from_type(ty: &Type<'_>) -> Option<Self> {
+        ty.is_integer().then(|| Self {
+            bits: ty.bits().unwrap_or(32),
+            signed: ty.is_signed()
+        })
+    }
+
+    pub fn min(&self) -> i128 {
+        if self.signed { -(1i128 << (self.bits - 1)) } else { 0 }
+    }
+
+    pub fn max(&self) -> i128 {
+        if self.signed { (1i128 << (self.bits - 1)) - 1 } else { (1i128 << self.bits) - 1 }
+    }
+
+    pub fn contains(&self, value: i128) -> bool {
+        (self.min()..=self.max()).contains(&value)
+    }
+}
+
+/// An instance of an `obj` or `comp`. Instances are shared by reference.
+#[derive(Debug)]
+pub(crate) struct Instance {
+    pub ty: String,
+    pub fields: Vec<(String, Value)>
+}
+
+impl Instance {
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.fields.iter_mut().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Value {
+    Void,
+    Bool(bool),
+    /// Always within the range of its `IntTy`.
+    Int(i128, IntTy),
+    /// `Float32` and narrower are rounded through `f32` after every operation.
+    Float(f64, u32),
+    Str(Rc<str>),
+    Instance(Rc<RefCell<Instance>>)
+}
+
+/// Why an operation couldn't produce a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ArithError {
+    Overflow,
+    DivideByZero,
+    /// Operands the type checker should have rejected.
+    Mismatch
+}
+
+impl Value {
+
+    pub fn float(value: f64, bits: u32) -> Self {
+        if bits <= 32 {
+            Self::Float(value as f32 as f64, bits)
+        } else {
+            Self::Float(value, bits)
+        }
+    }
+
+    pub fn truthy(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    /// Applies a non-logical binary operator; compound assignments use their base operator.
+    pub fn binary(&self, op: BinOp, rhs: &Value) -> Result<Value, ArithError> {
+        let op = op.compound_base().unwrap_or(op);
+        match (self, rhs) {
+            (Self::Int(a, ty), Self::Int(b, rty)) if ty == rty => int_binary(op, *a, *b, *ty),
+            (Self::Float(a, bits), Self::Float(b, _)) => float_binary(op, *a, *b, *bits),
+            (Self::Str(a), Self::Str(b)) => match op {
+                BinOp::Add => Ok(Self::Str(format!("{}{}", a, b).into())),
+                _ => compare(op, a.cmp(b)).ok_or(ArithError::Mismatch)
+            },
+            (Self::Bool(a), Self::Bool(b)) => match op {
+                BinOp::Equals => Ok(Self::Bool(a == b)),
+                BinOp::NotEquals => Ok(Self::Bool(a != b)),
+                _ => Err(ArithError::Mismatch)
+            },
+            (Self::Instance(a), Self::Instance(b)) => match op {
+                BinOp::Equals => Ok(Self::Bool(Rc::ptr_eq(a, b))),
+                BinOp::NotEquals => Ok(Self::Bool(!Rc::ptr_eq(a, b))),
+                _ => Err(ArithError::Mismatch)
+            },
+            _ => Err(ArithError::Mismatch)
+        }
+    }
+
+    pub fn negate(&self) -> Result<Value, ArithError> {
+        match self {
+            Self::Int(value, ty) => checked(-value, *ty),
+            Self::Float(value, bits) => Ok(Self::float(-value, *bits)),
+            _ => Err(ArithError::Mismatch)
+        }
+    }
+
+    pub fn not(&self) -> Result<Value, ArithError> {
+        match self {
+            Self::Bool(value) => Ok(Self::Bool(!value)),
+            _ => Err(ArithError::Mismatch)
+        }
+    }
+
+    /// Bitwise complement within the value's width.
+    pub fn bit_not(&self) -> Result<Value, ArithError> {
+        match self {
+            Self::Int(value, ty) if ty.signed => Ok(Self::Int(!value, *ty)),
+            Self::Int(value, ty) => Ok(Self::Int(ty.max() - value, *ty)),
+            _ => Err(ArithError::Mismatch)
+        }
+    }
+
+    /// `++`/`--` step by one in the value's own type.
+    pub fn step(&self, by: i8) -> Result<Value, ArithError> {
+        match self {
+            Self::Int(value, ty) => checked(value + by as i128, *ty),
+            Self::Float(value, bits) => Ok(Self::float(value + by as f64, *bits)),
+            _ => Err(ArithError::Mismatch)
+        }
+    }
+}
+
+fn checked(value: i128, ty: IntTy) -> Result<Value, ArithError> {
+    if ty.contains(value) {
+        Ok(Value::Int(value, ty))
+    } else {
+        Err(ArithError::Overflow)
+    }
+}
+
+fn compare(op: BinOp, ord: std::cmp::Ordering) -> Option<Value> {
+    use std::cmp::Ordering::*;
+    let result = match op {
+        BinOp::Equals => ord == Equal,
+        BinOp::NotEquals => ord != Equal,
+        BinOp::LessThan => ord == Less,
+        BinOp::LessThanEq => ord != Greater,
+        BinOp::GreaterThan => ord == Greater,
+        BinOp::GreaterThanEq => ord != Less,
+        _ => return None
+    };
+    Some(Value::Bool(result))
+}
+
+/// Integer arithmetic traps instead of wrapping: a result outside the operands' type is an overflow.
+fn int_binary(op: BinOp, a: i128, b: i128, ty: IntTy) -> Result<Value, ArithError> {
+    let result = match op {
+        BinOp::Add => a.checked_add(b),
+        BinOp::Subtract => a.checked_sub(b),
+        BinOp::Multiply => a.checked_mul(b),
+        BinOp::Divide | BinOp::Modulus if b == 0 => return Err(ArithError::DivideByZero),
+        BinOp::Divide => a.checked_div(b),
+        BinOp::Modulus => a.checked_rem(b),
+        _ => return compare(op, a.cmp(&b)).ok_or(ArithError::Mismatch)
+    };
+    checked(result.ok_or(ArithError::Overflow)?, ty)
+}
+
+/// Floats follow IEEE 754, so dividing by zero gives an infinity or NaN.
+fn float_binary(op: BinOp, a: f64, b: f64, bits: u32) -> Result<Value, ArithError> {
+    let result = match op {
+        BinOp::Add => a + b,
+        BinOp::Subtract => a - b,
+        BinOp::Multiply => a * b,
+        BinOp::Divide => a / b,
+        BinOp::Modulus => a % b,
+        _ => {
+            return a.partial_cmp(&b)
+                .and_then(|ord| compare(op, ord))
+                .or(match op {
+                    // Every comparison with NaN is false, except `!=`.
+                    BinOp::NotEquals => Some(Value::Bool(true)),
+                    BinOp::Equals | BinOp::LessThan | BinOp::LessThanEq | BinOp::GreaterThan | BinOp::GreaterThanEq => Some(Value::Bool(false)),
+                    _ => None
+                })
+                .ok_or(ArithError::Mismatch);
+        }
+    };
+    Ok(Value::float(result, bits))
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Void => f.write_str("()"),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Int(value, _) => write!(f, "{}", value),
+            Self::Float(value, bits) if *bits <= 32 => write!(f, "{}", *value as f32),
+            Self::Float(value, _) => write!(f, "{}", value),
+            Self::Str(value) => f.write_str(value),
+            Self::Instance(instance) => {
+                let instance = instance.borrow();
+                write!(f, "{} {{ ", display_name(&instance.ty))?;
+                for (idx, (name, value)) in instance.fields.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match value {
+                        Self::Str(value) => write!(f, "{}: {:?}", name, value)?,
+                        value => write!(f, "{}: {}", name, value)?
+                    }
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
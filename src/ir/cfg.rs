@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{BlockId, Function, Inst};
+
+/// The control-flow graph of a function, by block index, with its dominator tree.
+/// Branches to labels that don't exist are ignored; the verifier reports them.
+pub(crate) struct Cfg {
+    pub index: HashMap<BlockId, usize>,
+    pub preds: Vec<Vec<usize>>,
+    pub succs: Vec<Vec<usize>>,
+    /// Blocks reachable from the entry, in reverse postorder.
+    pub rpo: Vec<usize>,
+    /// Immediate dominators; the entry and unreachable blocks have none.
+    pub idom: Vec<Option<usize>>
+}
+
+impl Cfg {
+
+    pub fn new(function: &Function) -> Self {
+        let count = function.blocks.len();
+        let index: HashMap<BlockId, usize> = function.blocks.iter().enumerate().map(|(idx, block)| (block.id, idx)).collect();
+        let mut preds = vec![Vec::new(); count];
+        let mut succs = vec![Vec::new(); count];
+        for (idx, block) in function.blocks.iter().enumerate() {
+            for target in block.term.successors() {
+                if let Some(&target) = index.get(&target) {
+                    // `br c, bb1, bb1` is still a single edge.
+                    if !succs[idx].contains(&target) {
+                        succs[idx].push(target);
+                        preds[target].push(idx);
+                    }
+                }
+            }
+        }
+
+        let rpo = reverse_postorder(&succs, count);
+        let idom = dominators(&preds, &rpo, count);
+        Self { index, preds, succs, rpo, idom }
+    }
+
+    pub fn is_reachable(&self, block: usize) -> bool {
+        block == 0 || self.idom[block].is_some()
+    }
+
+    /// Children of each block in the dominator tree.
+    pub fn dom_children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.idom.len()];
+        for &block in &self.rpo {
+            if let Some(parent) = self.idom[block] {
+                children[parent].push(block);
+            }
+        }
+        children
+    }
+
+    /// Dominance frontiers, following Cooper, Harvey and Kennedy.
+    pub fn frontiers(&self) -> Vec<HashSet<usize>> {
+        let mut frontiers = vec![HashSet::new(); self.idom.len()];
+        for &block in &self.rpo {
+            let preds: Vec<usize> = self.preds[block].iter().copied().filter(|&pred| self.is_reachable(pred)).collect();
+            if preds.len() < 2 {
+                continue;
+            }
+            for pred in preds {
+                let mut runner = Some(pred);
+                while let Some(current) = runner {
+                    if Some(current) == self.idom[block] {
+                        break;
+                    }
+                    frontiers[current].insert(block);
+                    runner = self.idom[current];
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+fn reverse_postorder(succs: &[Vec<usize>], count: usize) -> Vec<usize> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let mut visited = vec![false; count];
+    let mut order = Vec::with_capacity(count);
+    // An explicit stack of (block, next successor to visit), so deep graphs can't overflow.
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        if let Some(&succ) = succs[block].get(next) {
+            stack.push((block, next + 1));
+            if !visited[succ] {
+                visited[succ] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            order.push(block);
+        }
+    }
+    order.reverse();
+    order
+}
+
+fn dominators(preds: &[Vec<usize>], rpo: &[usize], count: usize) -> Vec<Option<usize>> {
+    let mut position = vec![usize::MAX; count];
+    for (pos, &block) in rpo.iter().enumerate() {
+        position[block] = pos;
+    }
+
+    let mut idom: Vec<Option<usize>> = vec![None; count];
+    if count == 0 {
+        return idom;
+    }
+    idom[0] = Some(0);
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while position[a] > position[b] {
+                a = idom[a].unwrap_or(0);
+            }
+            while position[b] > position[a] {
+                b = idom[b].unwrap_or(0);
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in rpo.iter().skip(1) {
+            let mut new_idom = None;
+            for &pred in &preds[block] {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    Some(current) => intersect(&idom, pred, current),
+                    None => pred
+                });
+            }
+            if new_idom.is_some() && idom[block] != new_idom {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    idom[0] = None;
+    idom
+}
+
+/// Drops blocks the entry can't reach, along with the phi inputs they fed.
+/// Returns whether anything was removed.
+pub(crate) fn remove_unreachable(function: &mut Function) -> bool {
+    let cfg = Cfg::new(function);
+    let dead: HashSet<BlockId> = function.blocks.iter().enumerate()
+        .filter(|(idx, _)| !cfg.is_reachable(*idx))
+        .map(|(_, block)| block.id)
+        .collect();
+    if dead.is_empty() {
+        return false;
+    }
+
+    function.blocks.retain(|block| !dead.contains(&block.id));
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            if let Inst::Phi { incoming, .. } = inst {
+                incoming.retain(|(from, _)| !dead.contains(from));
+            }
+        }
+    }
+    true
+}
+
+/// Removes the inputs `from` gives to the phis of `block`, when that edge goes away.
+pub(crate) fn drop_edge(function: &mut Function, from: BlockId, block: BlockId) {
+    if let Some(block) = function.blocks.iter_mut().find(|candidate| candidate.id == block) {
+        for inst in &mut block.insts {
+            if let Inst::Phi { incoming, .. } = inst {
+                incoming.retain(|(pred, _)| *pred != from);
+            }
+        }
+    }
+}
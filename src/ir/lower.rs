@@ -0,0 +1,1208 @@
+use std::collections::HashMap;
+
+use crate::{
+    analysis::{
+        resolve::{top_level, Resolutions},
+        symbols::{Builtin, Symbol, SymbolId, SymbolKind, SymbolTable},
+        typeck::TypeTable
+    },
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        syntax_tree::{BinOp, Bodies, DefunDescriptor, ExtendDescriptor, Literal, Node, Type, UniOp},
+        yarn::Yarn
+    }
+};
+
+use super::{
+    BinaryOp, Block, BlockId, Const, Extern, Function, Global, Inst, Module, Operand, Reg,
+    Slot, StructDef, Terminator, TraitDef, Ty, UnaryOp, VTable
+};
+
+/// Somewhere a value can be stored.
+enum Place {
+    Local(Reg, Ty),
+    Global(String, Ty),
+    Field(Reg, String, Ty)
+}
+
+/// The function being built: its blocks so far and where locals live.
+struct Builder {
+    name: String,
+    params: Vec<(Reg, Ty)>,
+    ret: Ty,
+    blocks: Vec<(BlockId, Vec<Inst>, Option<Terminator>)>,
+    current: usize,
+    next_reg: u32,
+    locals: HashMap<SymbolId, (Reg, Ty)>,
+    /// The loops around the code being lowered, innermost last: each one's label, the
+    /// block a `continue` goes to and the block a `break` does.
+    loops: Vec<(Option<String>, BlockId, BlockId)>
+}
+
+impl Builder {
+
+    fn new(name: &str, ret: Ty) -> Self {
+        Self {
+            name: name.to_string(),
+            params: Vec::new(),
+            ret,
+            blocks: vec![(BlockId(0), Vec::new(), None)],
+            current: 0,
+            next_reg: 0,
+            locals: HashMap::new(),
+            loops: Vec::new()
+        }
+    }
+
+    fn reg(&mut self) -> Reg {
+        self.next_reg += 1;
+        Reg(self.next_reg - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        let id = BlockId(self.blocks.len() as u32);
+        self.blocks.push((id, Vec::new(), None));
+        id
+    }
+
+    fn switch_to(&mut self, id: BlockId) {
+        self.current = id.0 as usize;
+    }
+
+    /// Appends to the current block; code after a terminator goes into a fresh,
+    /// unreachable block so every block keeps a single exit.
+    fn push(&mut self, inst: Inst) {
+        if self.blocks[self.current].2.is_some() {
+            let block = self.new_block();
+            self.switch_to(block);
+        }
+        self.blocks[self.current].1.push(inst);
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        let block = &mut self.blocks[self.current];
+        if block.2.is_none() {
+            block.2 = Some(term);
+        }
+    }
+
+    /// The loop a `break` or `continue` with `label` is for; without one, the innermost.
+    fn target(&self, label: Option<&str>) -> Option<(BlockId, BlockId)> {
+        self.loops.iter().rev()
+            .find(|(name, _, _)| label.is_none() || name.as_deref() == label)
+            .map(|(_, next, exit)| (*next, *exit))
+    }
+
+    fn finish(self) -> Function {
+        Function {
+            name: self.name,
+            params: self.params,
+            ret: self.ret,
+            blocks: self.blocks.into_iter()
+                .map(|(id, insts, term)| Block { id, insts, term: term.unwrap_or(Terminator::Unreachable) })
+                .collect()
+        }
+    }
+}
+
+/// A method declared in a block: its signature, body and span.
+type Method<'n, 'a> = (&'n DefunDescriptor<'a>, &'n [Node<'a>], Span);
+
+/// How far generic functions may instantiate one another, and generic types nest,
+/// before lowering gives up on a program that would instantiate forever.
+const INSTANTIATION_LIMIT: usize = 64;
+
+/// A generic function or method to instantiate: the function it comes from, by its
+/// qualified name, the name of the instance and what the type parameters stand for.
+struct Request<'a> {
+    template: String,
+    name: String,
+    env: HashMap<String, Type<'a>>,
+    instance: Option<(String, Type<'a>)>,
+    depth: usize
+}
+
+/// Lowers a resolved and type-checked program into a `Module`.
+///
+/// Top-level `let`s become globals. The other top-level statements run, in order, in
+/// the module's `.init` function, which is only emitted when there is something to run.
+///
+/// Default bodies of trait methods are instantiated for every `obj` that is extended
+/// with the trait and doesn't implement the method itself, as `Obj::Trait::method`, so
+/// calls on a known `obj` go straight to a function. Calls on trait objects go through
+/// the vtables of the traits the program uses as trait objects.
+///
+/// Generic functions, the methods of generic `obj`s and the default bodies reached
+/// through them are instantiated for each list of type arguments the program uses, as
+/// are generic `obj`s and `comp`s. An instance is named after its arguments, like
+/// `max.i32`, `Box.i32` and `Box.i32::get`.
+pub(crate) struct Lowerer<'c, 'a> {
+    symbols: &'c SymbolTable<'a>,
+    resolutions: &'c Resolutions,
+    types: &'c TypeTable<'a>,
+    module: Module,
+    globals: HashMap<SymbolId, (String, Ty)>,
+    builder: Builder,
+    /// The trait and `obj` of the default body being instantiated; its `self` is the `obj`.
+    instance: Option<(String, Type<'a>)>,
+    /// What the type parameters of the function being lowered stand for.
+    env: HashMap<String, Type<'a>>,
+    /// Instances of generic functions, in the order they were first called; those past
+    /// the first `lowered` still need their bodies.
+    pending: Vec<Request<'a>>,
+    lowered: usize,
+    /// How many instantiations led to the function being lowered.
+    depth: usize,
+    /// Instances of generic `obj`s and `comp`s, by their names.
+    struct_instances: Vec<(String, Type<'a>)>,
+    /// Traits whose objects the program creates or takes, in order of first use.
+    dyn_traits: Vec<String>,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl<'c, 'a> Lowerer<'c, 'a> {
+
+    pub fn new(symbols: &'c SymbolTable<'a>, resolutions: &'c Resolutions, types: &'c TypeTable<'a>) -> Self {
+        Self {
+            symbols,
+            resolutions,
+            types,
+            module: Module::default(),
+            globals: HashMap::new(),
+            builder: Builder::new(Module::INIT, Ty::Void),
+            instance: None,
+            env: HashMap::new(),
+            pending: Vec::new(),
+            lowered: 0,
+            depth: 0,
+            struct_instances: Vec::new(),
+            dyn_traits: Vec::new(),
+            diagnostics: Vec::new()
+        }
+    }
+
+    pub fn lower_program(mut self, tree: &Node<'a>) -> Result<Module, Vec<Diagnostic>> {
+        let items = top_level(tree);
+        let mut defaults: HashMap<&str, Vec<Method<'_, 'a>>> = HashMap::new();
+        // Functions only lowered once they are instantiated, by their qualified names.
+        let mut templates: HashMap<String, Method<'_, 'a>> = HashMap::new();
+        for item in items {
+            self.declare(item);
+            if let Node::Body { discriptor, body, span } = item {
+                match discriptor.as_ref() {
+                    Bodies::Trait(tr) => {
+                        let bodies: Vec<Method<'_, 'a>> = methods(body).filter(|(defun, ..)| tr.has_default(defun.name().as_slice())).collect();
+                        for &(defun, body, span) in &bodies {
+                            templates.insert(defun.qualified().to_string(), (defun, body, span));
+                        }
+                        defaults.insert(tr.name().as_slice(), bodies);
+                    },
+                    Bodies::Defun(defun) if !defun.generics().is_empty() => {
+                        templates.insert(defun.qualified().to_string(), (defun, body.as_slice(), *span));
+                    },
+                    Bodies::Object(obj) => {
+                        let generic = !obj.generics().is_empty();
+                        for (defun, body, span) in methods(body).filter(|(defun, ..)| generic || !defun.generics().is_empty()) {
+                            templates.insert(defun.qualified().to_string(), (defun, body, span));
+                        }
+                    },
+                    Bodies::Extension(extension) => {
+                        let generic = !extension.generics().is_empty();
+                        for (defun, body, span) in methods(body).filter(|(defun, ..)| generic || !defun.generics().is_empty()) {
+                            templates.insert(defun.qualified().to_string(), (defun, body, span));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        for item in items {
+            match item {
+                Node::Body { discriptor, body, span } => match discriptor.as_ref() {
+                    Bodies::Defun(defun) if defun.generics().is_empty() => self.lower_function(defun.qualified().as_slice(), defun, body, *span),
+                    Bodies::Object(obj) if obj.generics().is_empty() => {
+                        for (defun, body, span) in methods(body).filter(|(defun, ..)| defun.generics().is_empty()) {
+                            self.lower_function(defun.qualified().as_slice(), defun, body, span);
+                        }
+                    },
+                    // The default bodies a generic `obj` needs are instantiated with it.
+                    Bodies::Extension(extension) if extension.generics().is_empty() => {
+                        for (defun, body, span) in methods(body).filter(|(defun, ..)| defun.generics().is_empty()) {
+                            self.lower_function(defun.qualified().as_slice(), defun, body, span);
+                        }
+                        let defaults = defaults.get(extension.with().as_slice()).map(Vec::as_slice).unwrap_or_default();
+                        self.instantiate_defaults(extension, defaults);
+                    },
+                    // Default bodies of traits are only reached through an `obj`, which
+                    // has them instantiated for it.
+                    _ => {}
+                },
+                // Constant initializers were folded into the global itself.
+                Node::Value { .. } => {},
+                Node::BinaryOp { lhs, rhs, op: BinOp::Assign, .. } if matches!(lhs.as_ref(), Node::Value { .. }) && constant(rhs).is_some() => {},
+                stmt => {
+                    self.lower_statement(stmt);
+                }
+            }
+        }
+
+        // Instances lead to further instances, and new `obj`s to further vtables.
+        loop {
+            while let Some(request) = self.pending.get(self.lowered) {
+                self.lowered += 1;
+                let Some(&(defun, body, span)) = templates.get(&request.template) else {
+                    continue;
+                };
+                let name = request.name.clone();
+                let env = std::mem::replace(&mut self.env, request.env.clone());
+                let instance = std::mem::replace(&mut self.instance, request.instance.clone());
+                let depth = std::mem::replace(&mut self.depth, request.depth);
+                self.lower_function(&name, defun, body, span);
+                self.env = env;
+                self.instance = instance;
+                self.depth = depth;
+            }
+            self.lower_vtables();
+            if self.lowered == self.pending.len() {
+                break;
+            }
+        }
+
+        let init = std::mem::replace(&mut self.builder, Builder::new("", Ty::Void));
+        if init.blocks.iter().any(|(_, insts, _)| !insts.is_empty()) {
+            let mut init = init;
+            init.terminate(Terminator::Ret(None));
+            self.module.functions.insert(0, init.finish());
+        }
+
+        if self.diagnostics.is_empty() {
+            Ok(self.module)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn unsupported(&mut self, span: Span, what: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::error(format!("{} can't be lowered to IR yet", what.into()))
+            .with_code(codes::UNSUPPORTED_IN_IR)
+            .with_primary(span, ""));
+    }
+
+    /// `ty` with the type parameters in scope replaced by what they stand for.
+    fn concrete(&self, ty: &Type<'a>) -> Type<'a> {
+        if self.env.is_empty() {
+            return ty.clone();
+        }
+        ty.substitute(&|name| self.env.get(name).cloned())
+    }
+
+    fn ty(&mut self, ty: &Type<'a>, span: Span) -> Ty {
+        let ty = &self.concrete(ty);
+        if let Some(with) = self.symbols.dispatch_trait(ty) {
+            if matches!(ty, Type::SafePtr(_)) {
+                if !ty.type_args().is_empty() || matches!(ty, Type::SafePtr(inner) if !inner.type_args().is_empty()) {
+                    self.unsupported(span, format!("a generic trait object `{}`", ty));
+                    return Ty::Void;
+                }
+                if !self.dyn_traits.iter().any(|used| used == with) {
+                    self.dyn_traits.push(with.to_string());
+                }
+                return Ty::Dyn(with.to_string());
+            }
+            match self.instance.clone() {
+                Some((instance, obj)) if instance == with => return self.ty(&obj, span),
+                _ => {
+                    self.unsupported(span, format!("a value of trait type `{}`", ty));
+                    return Ty::Void;
+                }
+            }
+        }
+        if let Type::Applied(name, args) = ty {
+            return Ty::Struct(self.instantiate_struct(name.as_slice(), args, span));
+        }
+        Ty::from_type(ty).unwrap_or_else(|| {
+            self.unsupported(span, format!("a value of type `{}`", ty));
+            Ty::Void
+        })
+    }
+
+    /// Lays out the instance of the generic `obj` or `comp` `name` for `args`, the first
+    /// time it is used, and gives its name.
+    fn instantiate_struct(&mut self, name: &str, args: &[Type<'a>], span: Span) -> String {
+        let tys: Vec<Ty> = args.iter().map(|arg| self.ty(arg, span)).collect();
+        let mangled = mangle(name, &tys);
+        if self.module.structure(&mangled).is_some() {
+            return mangled;
+        }
+        let symbols = self.symbols;
+        let (generics, fields) = match symbols.lookup_qualified(name).map(|symbol| &symbol.kind) {
+            Some(SymbolKind::Object(obj)) => (obj.generics(), obj.fields()),
+            Some(SymbolKind::Composition(comp)) => (comp.generics(), comp.fields()),
+            _ => {
+                self.unsupported(span, format!("a value of type `{}`", mangled));
+                return mangled;
+            }
+        };
+
+        // Fields may hold the instance itself, so it is declared before they are lowered.
+        let idx = self.module.structs.len();
+        self.module.structs.push(StructDef { name: mangled.clone(), fields: Vec::new() });
+        self.struct_instances.push((mangled.clone(), Type::Applied(Yarn::owned(name.into()), args.to_vec())));
+        if args.iter().any(|arg| nesting(arg) > INSTANTIATION_LIMIT) {
+            self.unsupported(span, format!("instantiating `{}` with arguments nested this deeply", name));
+            return mangled;
+        }
+        let fields = fields.iter()
+            .filter_map(|field| Some((field.name()?.to_string(), field.ty().instantiate(generics, args))))
+            .map(|(name, ty)| (name, self.ty(&ty, span)))
+            .collect();
+        self.module.structs[idx].fields = fields;
+        mangled
+    }
+
+    /// Asks for the instance `name` of the generic function `template`, lowered once
+    /// the functions lowered so far are done.
+    fn request(&mut self, request: Request<'a>, span: Span) {
+        if self.pending.iter().any(|pending| pending.name == request.name) {
+            return;
+        }
+        if request.depth > INSTANTIATION_LIMIT {
+            self.unsupported(span, format!("instantiating `{}` this deeply", request.template));
+            return;
+        }
+        self.pending.push(request);
+    }
+
+    /// The function a call of `method` on a value of type `obj` runs, instantiated for
+    /// `obj`'s type arguments and `own`, those of the method itself. When there is none
+    /// that can be lowered, says why.
+    fn method_target(&mut self, obj: &Type<'a>, method: &Symbol<'a>, own: &[Type<'a>], span: Span) -> Option<String> {
+        let SymbolKind::Function(defun) = &method.kind else {
+            self.unsupported(span, format!("a call of `{}`, which is not a function", method.name));
+            return None;
+        };
+        let symbols = self.symbols;
+        let qualified = defun.qualified().as_slice();
+        let (Some(obj_name), Ty::Struct(mangled)) = (obj.type_name(), self.ty(obj, span)) else {
+            self.unsupported(span, format!("a call of `{}` on a value of type `{}`", qualified, obj));
+            return None;
+        };
+        let obj_args = obj.type_args();
+        let Some((owner, _)) = qualified.rsplit_once("::") else {
+            self.unsupported(span, format!("a call of `{}`, which is not a method", qualified));
+            return None;
+        };
+        let mut env: HashMap<String, Type<'a>> = defun.generics().iter()
+            .map(|param| param.name().to_string())
+            .zip(own.iter().cloned())
+            .collect();
+        let (name, instance) = if self.is_default(defun) {
+            // A default body sees the trait's parameters as the `extend` block gives them.
+            let Some(implementation) = symbols.implementation(obj_name, owner) else {
+                self.unsupported(span, format!("a call of `{}` on `{}`, which isn't extended with `{}`", qualified, obj, owner));
+                return None;
+            };
+            for (param, arg) in symbols.generics_of(owner).iter().zip(&implementation.trait_args) {
+                env.insert(param.name().to_string(), arg.instantiate(&implementation.generics, obj_args));
+            }
+            (format!("{}::{}", mangled, qualified), Some((owner.to_string(), obj.clone())))
+        } else {
+            let generics = match owner.split_once("::") {
+                Some((_, with)) => symbols.implementation(obj_name, with).map(|implementation| implementation.generics.as_slice()).unwrap_or_default(),
+                None => symbols.generics_of(obj_name)
+            };
+            for (param, arg) in generics.iter().zip(obj_args) {
+                env.insert(param.name().to_string(), arg.clone());
+            }
+            let Some(method) = qualified.strip_prefix(obj_name) else {
+                self.unsupported(span, format!("a call of `{}` on a value of type `{}`", qualified, obj));
+                return None;
+            };
+            (format!("{}{}", mangled, method), None)
+        };
+        // A default body is always requested, so whatever refers to it, a vtable
+        // included, can count on it being lowered.
+        if obj_args.is_empty() && own.is_empty() && instance.is_none() {
+            return Some(name);
+        }
+
+        let own: Vec<Ty> = own.iter().map(|arg| self.ty(arg, span)).collect();
+        let name = mangle(&name, &own);
+        let request = Request { template: qualified.to_string(), name: name.clone(), env, instance, depth: self.depth + 1 };
+        self.request(request, span);
+        Some(name)
+    }
+
+    /// The type the checker gave `node`.
+    fn type_of(&mut self, node: &Node<'a>) -> Ty {
+        match self.types.get(node) {
+            Some(ty) => self.ty(&ty.clone(), node.span()),
+            None => Ty::Void
+        }
+    }
+
+    /// Records struct layouts and globals, so functions can refer to them in any order.
+    fn declare(&mut self, item: &Node<'a>) {
+        match item {
+            Node::Body { discriptor, span, .. } => {
+                // Generic ones are laid out for each of their instances.
+                let (name, fields) = match discriptor.as_ref() {
+                    Bodies::Object(obj) if obj.generics().is_empty() => (obj.name(), obj.fields()),
+                    Bodies::Composition(comp) if comp.generics().is_empty() => (comp.name(), comp.fields()),
+                    _ => return
+                };
+                let fields = fields.iter()
+                    .filter_map(|field| Some((field.name()?.to_string(), field.ty())))
+                    .map(|(name, ty)| (name, self.ty(&ty, *span)))
+                    .collect();
+                self.module.structs.push(StructDef { name: name.to_string(), fields });
+            },
+            Node::Value { ret, span } => {
+                let ty = self.types.declared(item, ret);
+                let ty = self.ty(&ty, *span);
+                self.declare_global(item, ty.clone(), Const::zero(&ty));
+            },
+            Node::BinaryOp { lhs, rhs, op: BinOp::Assign, .. } => {
+                if let Node::Value { ret, span } = lhs.as_ref() {
+                    let ty = self.types.declared(lhs, ret);
+                    let ty = self.ty(&ty, *span);
+                    self.declare_global(lhs, ty, constant(rhs));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Whether `defun` is the default body of a trait method.
+    fn is_default(&self, defun: &DefunDescriptor<'a>) -> bool {
+        defun.qualified().as_slice().rsplit_once("::")
+            .and_then(|(owner, _)| self.symbols.lookup_qualified(owner))
+            .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Trait(_)))
+    }
+
+    fn declare_global(&mut self, value: &Node<'a>, ty: Ty, init: Option<Const>) {
+        let Some(id) = self.resolutions.get(value) else {
+            return;
+        };
+        let name = self.symbols.symbol(id).name.clone();
+        self.globals.insert(id, (name.clone(), ty.clone()));
+        self.module.globals.push(Global { name, ty, init });
+    }
+
+    /// Requests the default bodies of the extended trait that the `extend` block leaves
+    /// out, for the `obj` it extends. Generic ones are instantiated when they are called.
+    fn instantiate_defaults(&mut self, extension: &ExtendDescriptor<'a>, defaults: &[Method<'_, 'a>]) {
+        let (obj, with) = (extension.obj().as_slice(), extension.with().as_slice());
+        if self.symbols.implementation(obj, with).is_none() {
+            return;
+        }
+        let env: HashMap<String, Type<'a>> = self.symbols.generics_of(with).iter()
+            .map(|param| param.name().to_string())
+            .zip(extension.trait_args().iter().cloned())
+            .collect();
+        for &(defun, _, span) in defaults {
+            let name = defun.name().as_slice();
+            if !defun.generics().is_empty() || extension.functions().iter().any(|implemented| implemented.name().as_slice() == name) {
+                continue;
+            }
+            let request = Request {
+                template: defun.qualified().to_string(),
+                name: format!("{}::{}", obj, defun.qualified()),
+                env: env.clone(),
+                instance: Some((with.to_string(), Type::Named(extension.obj().clone()))),
+                depth: self.depth + 1
+            };
+            self.request(request, span);
+        }
+    }
+
+    /// Lays out the vtable of each trait used as a trait object, with one for every
+    /// `obj` extended with it, and every instance of a generic `obj` whose arguments
+    /// meet the bounds of its `extend` block. Slots hold the methods taking `self`, as
+    /// `trait_methods` orders them, supertraits' included.
+    ///
+    /// Runs until no instances are left to lower, so it only adds what is missing.
+    fn lower_vtables(&mut self) {
+        let symbols = self.symbols;
+        // Slot types may name further traits, which get their vtables in turn.
+        let mut idx = 0;
+        while let Some(with) = self.dyn_traits.get(idx).cloned() {
+            idx += 1;
+            let declared: Vec<&Symbol<'a>> = symbols.trait_methods(&with).into_iter()
+                .filter(|symbol| matches!(&symbol.kind, SymbolKind::Function(defun) if takes_self(defun)))
+                .collect();
+            if self.module.traits.iter().all(|tr| tr.name != with) {
+                let mut slots = Vec::new();
+                for symbol in &declared {
+                    let SymbolKind::Function(defun) = &symbol.kind else {
+                        continue;
+                    };
+                    if !defun.generics().is_empty() {
+                        self.unsupported(symbol.span, format!("the generic method `{}` of a trait object", symbol.qualified));
+                        continue;
+                    }
+                    let params = defun.args().iter().skip(1).map(|arg| self.ty(&arg.ty(), symbol.span)).collect();
+                    let ret = self.ty(defun.return_type(), symbol.span);
+                    slots.push(Slot { method: symbol.qualified.clone(), params, ret });
+                }
+                self.module.traits.push(TraitDef { name: with.clone(), slots });
+            }
+
+            let bound = Type::Named(Yarn::owned(with.as_str().into()));
+            let mut objs = Vec::new();
+            for implementation in symbols.implementations().iter().filter(|implementation| implementation.with == with) {
+                if implementation.generics.is_empty() {
+                    objs.push((Type::Named(Yarn::owned(implementation.obj.as_str().into())), implementation.span));
+                    continue;
+                }
+                objs.extend(self.struct_instances.iter()
+                    .filter(|(_, ty)| ty.type_name() == Some(implementation.obj.as_str()) && symbols.satisfies(ty, &bound, &[]))
+                    .map(|(_, ty)| (ty.clone(), implementation.span)));
+            }
+            for (obj, span) in objs {
+                let Ty::Struct(name) = self.ty(&obj, span) else {
+                    continue;
+                };
+                if self.module.vtables.iter().any(|vtable| vtable.obj == name && vtable.with == with) {
+                    continue;
+                }
+                let obj_name = obj.type_name().unwrap_or_default();
+                let functions = declared.iter()
+                    .filter(|declared| matches!(&declared.kind, SymbolKind::Function(defun) if defun.generics().is_empty()))
+                    .map(|declared| {
+                        let symbol = symbols.implementing(obj_name, declared).unwrap_or(declared);
+                        self.method_target(&obj, symbol, &[], span).unwrap_or_default()
+                    })
+                    .collect();
+                self.module.vtables.push(VTable { obj: name, with: with.clone(), functions });
+            }
+        }
+    }
+
+    fn lower_function(&mut self, name: &str, defun: &DefunDescriptor<'a>, body: &[Node<'a>], span: Span) {
+        let ret = self.ty(defun.return_type(), span);
+        let outer = std::mem::replace(&mut self.builder, Builder::new(name, ret.clone()));
+
+        for &param in self.resolutions.params(span) {
+            let symbol = self.symbols.symbol(param);
+            let ty = symbol.kind.value_type().cloned().unwrap_or(Type::Void);
+            let ty = self.ty(&ty, span);
+            let reg = self.builder.reg();
+            self.builder.params.push((reg, ty.clone()));
+            self.builder.locals.insert(param, (reg, ty));
+        }
+
+        let value = self.lower_block(body);
+        let value = match (value, body.last()) {
+            (Some(value), Some(tail)) => Some(self.coerce(tail, value)),
+            (value, _) => value
+        };
+        let term = match (ret, value) {
+            (Ty::Void, _) => Terminator::Ret(None),
+            (_, Some(value)) => Terminator::Ret(Some(value)),
+            // The checker reports a missing return value; otherwise every path returns first.
+            (_, None) => Terminator::Unreachable
+        };
+        self.builder.terminate(term);
+
+        let function = std::mem::replace(&mut self.builder, outer).finish();
+        self.module.functions.push(function);
+    }
+
+    /// Lowers statements in order, giving the value of the last one.
+    fn lower_block(&mut self, stmts: &[Node<'a>]) -> Option<Operand> {
+        let mut last = None;
+        for stmt in stmts {
+            last = self.lower_statement(stmt);
+        }
+        last
+    }
+
+    fn lower_statement(&mut self, stmt: &Node<'a>) -> Option<Operand> {
+        match stmt {
+            Node::Value { ret, span } => {
+                let ty = self.types.declared(stmt, ret);
+                let ty = self.ty(&ty, *span);
+                let value = Const::zero(&ty).map(Operand::Const);
+                self.bind(stmt, ty, value);
+                None
+            },
+            Node::BinaryOp { lhs, rhs, op: BinOp::Assign, .. } if matches!(lhs.as_ref(), Node::Value { .. }) => {
+                let Node::Value { ret, span } = lhs.as_ref() else {
+                    return None;
+                };
+                let ty = self.types.declared(lhs, ret);
+                let ty = self.ty(&ty, *span);
+                let value = self.lower_expr(rhs).map(|value| self.coerce(rhs, value));
+                self.bind(lhs, ty, value);
+                None
+            },
+            Node::Chain { chained } => self.lower_block(chained),
+            Node::Body { .. } => None,
+            expr => self.lower_expr(expr)
+        }
+    }
+
+    /// Gives the variable a `let` declares its own register (or, at the top level, its
+    /// global) and stores `value` there.
+    fn bind(&mut self, value_node: &Node<'a>, ty: Ty, value: Option<Operand>) {
+        let Some(id) = self.resolutions.get(value_node) else {
+            return;
+        };
+        if let Some((global, _)) = self.globals.get(&id) {
+            let global = global.clone();
+            if let Some(src) = value {
+                self.builder.push(Inst::Store { global, src });
+            }
+            return;
+        }
+
+        let reg = self.builder.reg();
+        self.builder.locals.insert(id, (reg, ty.clone()));
+        if let Some(src) = value {
+            self.builder.push(Inst::Copy { dst: reg, ty, src });
+        }
+    }
+
+    /// Defines a fresh register as `inst` with the register filled in.
+    fn define(&mut self, ty: Ty, inst: impl FnOnce(Reg, Ty) -> Inst) -> Operand {
+        let reg = self.builder.reg();
+        self.builder.push(inst(reg, ty));
+        Operand::Reg(reg)
+    }
+
+    /// Copies a register operand into a fresh one, so a later step of the same
+    /// expression can't change the value it stands for.
+    fn pin(&mut self, operand: Operand, ty: Ty) -> Operand {
+        match operand {
+            Operand::Reg(_) => self.define(ty, |dst, ty| Inst::Copy { dst, ty, src: operand }),
+            constant => constant
+        }
+    }
+
+    fn place(&mut self, node: &Node<'a>) -> Option<Place> {
+        match node {
+            Node::Ident { .. } => {
+                let id = self.resolutions.get(node)?;
+                if let Some((reg, ty)) = self.builder.locals.get(&id) {
+                    return Some(Place::Local(*reg, ty.clone()));
+                }
+                if let Some((name, ty)) = self.globals.get(&id) {
+                    return Some(Place::Global(name.clone(), ty.clone()));
+                }
+                self.unsupported(node.span(), format!("`{}`", self.symbols.symbol(id).name));
+                None
+            },
+            Node::Field { recv, name, .. } => {
+                let obj = self.lower_object(recv)?;
+                let ty = self.type_of(node);
+                Some(Place::Field(obj, name.to_string(), ty))
+            },
+            other => {
+                self.unsupported(other.span(), "this assignment target");
+                None
+            }
+        }
+    }
+
+    fn read(&mut self, place: &Place) -> Operand {
+        match place {
+            Place::Local(reg, _) => Operand::Reg(*reg),
+            Place::Global(global, ty) => {
+                let global = global.clone();
+                self.define(ty.clone(), |dst, ty| Inst::Load { dst, ty, global })
+            },
+            Place::Field(obj, field, ty) => {
+                let (obj, field) = (*obj, field.clone());
+                self.define(ty.clone(), |dst, ty| Inst::GetField { dst, ty, obj, field })
+            }
+        }
+    }
+
+    fn write(&mut self, place: &Place, src: Operand) {
+        let inst = match place {
+            Place::Local(dst, ty) => Inst::Copy { dst: *dst, ty: ty.clone(), src },
+            Place::Global(global, _) => Inst::Store { global: global.clone(), src },
+            Place::Field(obj, field, _) => Inst::SetField { obj: *obj, field: field.clone(), src }
+        };
+        self.builder.push(inst);
+    }
+
+    /// Turns `value`, the value of `node`, into the trait object the checker has it used
+    /// as, if any.
+    fn coerce(&mut self, node: &Node<'a>, value: Operand) -> Operand {
+        let types = self.types;
+        let Some(object) = types.coercion(node) else {
+            return value;
+        };
+        let ty = self.ty(object, node.span());
+        match (&ty, &value, self.type_of(node)) {
+            (Ty::Dyn(_), Operand::Reg(src), Ty::Struct(concrete)) => {
+                let src = *src;
+                self.define(ty, |dst, ty| Inst::Dyn { dst, ty, concrete, src })
+            },
+            // A type argument that is itself a trait object.
+            (Ty::Dyn(to), _, Ty::Dyn(from)) if *to != from => {
+                self.unsupported(node.span(), format!("turning a `dyn {}` into a `{}`", from, ty));
+                value
+            },
+            _ => value
+        }
+    }
+
+    /// Lowers an expression whose value is an instance, returning the register holding it.
+    fn lower_object(&mut self, node: &Node<'a>) -> Option<Reg> {
+        let value = self.lower_expr(node)?;
+        match value {
+            Operand::Reg(reg) => Some(reg),
+            Operand::Const(_) => {
+                self.unsupported(node.span(), "a constant receiver");
+                None
+            }
+        }
+    }
+
+    /// Lowers `node`, giving the operand that holds its value, or `None` for `Void`.
+    fn lower_expr(&mut self, node: &Node<'a>) -> Option<Operand> {
+        match node {
+            Node::Literal { value, .. } => Some(Operand::Const(literal(value, false))),
+            Node::Ident { .. } => {
+                let place = self.place(node)?;
+                Some(self.read(&place))
+            },
+            Node::BinaryOp { lhs, rhs, op, span } => self.lower_binary(node, lhs, rhs, *op, *span),
+            Node::UnaryOp { lhs, op, postfix, .. } => self.lower_unary(node, lhs, *op, *postfix),
+            Node::Call { args, span, .. } => {
+                let symbols = self.symbols;
+                let Some(callee) = self.resolutions.get(node).map(|id| &symbols.symbol(id).kind) else {
+                    self.unsupported(*span, "a call to an unresolved function");
+                    return None;
+                };
+                let args = self.lower_args(args)?;
+                let ty = self.type_of(node);
+                match callee {
+                    SymbolKind::Function(defun) if !defun.generics().is_empty() => {
+                        let types = self.types;
+                        let instance: Vec<Type<'a>> = types.instance(node).unwrap_or_default().iter().map(|arg| self.concrete(arg)).collect();
+                        let tys: Vec<Ty> = instance.iter().map(|arg| self.ty(arg, *span)).collect();
+                        let qualified = defun.qualified().to_string();
+                        let name = mangle(&qualified, &tys);
+                        let env = defun.generics().iter().map(|param| param.name().to_string()).zip(instance).collect();
+                        self.request(Request { template: qualified, name: name.clone(), env, instance: None, depth: self.depth + 1 }, *span);
+                        self.call(name, args, ty)
+                    },
+                    SymbolKind::Function(defun) => self.call(defun.qualified().to_string(), args, ty),
+                    SymbolKind::Object(_) | SymbolKind::Composition(_) => {
+                        Some(self.define(ty, |dst, ty| Inst::New { dst, ty, args }))
+                    },
+                    SymbolKind::Builtin(builtin) => {
+                        self.declare_builtin(*builtin);
+                        self.call(builtin.name().to_string(), args, Ty::Void)
+                    },
+                    other => {
+                        self.unsupported(*span, format!("a call to a {}", other.describe()));
+                        None
+                    }
+                }
+            },
+            Node::ObjCall { recv, func, args, span } => {
+                let obj = self.lower_object(recv)?;
+                let (symbols, types) = (self.symbols, self.types);
+                let static_ty = types.get(recv);
+                let recv_ty = static_ty.map(|ty| self.concrete(ty)).unwrap_or(Type::Void);
+                // A trait object, possibly the argument of a type parameter.
+                if let (Some(with), Type::SafePtr(_)) = (symbols.dispatch_trait(&recv_ty), &recv_ty) {
+                    return self.lower_dyn_call(node, obj, with, func.as_slice(), args, *span);
+                }
+                let (owner, method) = match (types.through(node), static_ty.and_then(|ty| symbols.dispatch_trait(ty)), &self.instance) {
+                    // A type parameter's bound names the method; its argument implements it.
+                    (Some(with), ..) => {
+                        let obj_name = recv_ty.type_name().unwrap_or_default();
+                        let method = symbols.method(with, func.as_slice()).found().and_then(|declared| symbols.implementing(obj_name, declared));
+                        (recv_ty.clone(), method)
+                    },
+                    // In an instantiated default body, `self` is the `obj` it was made for.
+                    (None, Some(with), Some((_, instance))) => {
+                        let obj_name = instance.type_name().unwrap_or_default();
+                        let method = symbols.method(with, func.as_slice()).found().and_then(|declared| symbols.implementing(obj_name, declared));
+                        (instance.clone(), method)
+                    },
+                    _ => {
+                        let method = symbols.method(recv_ty.type_name().unwrap_or_default(), func.as_slice()).found();
+                        (recv_ty.clone(), method)
+                    }
+                };
+                let (Some(method), Some(SymbolKind::Function(defun))) = (method, method.map(|symbol| &symbol.kind)) else {
+                    self.unsupported(*span, format!("a call to `{}::{}`", owner.type_name().unwrap_or_default(), func));
+                    return None;
+                };
+                // The method's own type arguments follow those of its owner.
+                let own = types.instance(node).unwrap_or_default();
+                let own: Vec<Type<'a>> = own[own.len().saturating_sub(defun.generics().len())..].iter().map(|arg| self.concrete(arg)).collect();
+                let qualified = self.method_target(&owner, method, &own, *span)?;
+
+                let mut values = Vec::with_capacity(args.len() + 1);
+                if takes_self(defun) {
+                    values.push(Operand::Reg(obj));
+                }
+                values.extend(self.lower_args(args)?);
+                let ty = self.type_of(node);
+                self.call(qualified, values, ty)
+            },
+            Node::Field { .. } => {
+                let place = self.place(node)?;
+                Some(self.read(&place))
+            },
+            Node::Chain { chained } => self.lower_block(chained),
+            Node::If { cond, then, els, .. } => self.lower_if(node, cond, then, els.as_deref()),
+            Node::While { label, cond, body, .. } => {
+                self.lower_while(label.as_ref().map(Yarn::as_slice), cond, body);
+                None
+            },
+            Node::For { label, var, start, end, body, .. } => {
+                self.lower_for(label.as_ref().map(Yarn::as_slice), var, start, end, body);
+                None
+            },
+            Node::Loop { label, body, .. } => {
+                let (head, exit) = (self.builder.new_block(), self.builder.new_block());
+                self.builder.terminate(Terminator::Jump(head));
+                self.builder.switch_to(head);
+                self.lower_loop_body(label.as_ref().map(Yarn::as_slice), head, exit, body);
+                self.builder.terminate(Terminator::Jump(head));
+                self.builder.switch_to(exit);
+                None
+            },
+            Node::Break { label, .. } | Node::Continue { label, .. } => {
+                // The resolver reports a jump without a loop to go to.
+                if let Some((next, exit)) = self.builder.target(label.as_ref().map(Yarn::as_slice)) {
+                    let to = if matches!(node, Node::Break { .. }) { exit } else { next };
+                    self.builder.terminate(Terminator::Jump(to));
+                }
+                None
+            },
+            Node::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => {
+                        let result = self.lower_expr(value);
+                        result.map(|result| self.coerce(value, result))
+                    },
+                    None => None
+                };
+                // `return f();` in a function returning `Void` still calls `f`.
+                let value = value.filter(|_| !matches!(self.builder.ret, Ty::Void));
+                self.builder.terminate(Terminator::Ret(value));
+                None
+            },
+            Node::Value { .. } | Node::Body { .. } => self.lower_statement(node),
+            Node::Head { next } => self.lower_expr(next),
+            Node::Error { span } => {
+                self.unsupported(*span, "code that failed to parse");
+                None
+            }
+        }
+    }
+
+    /// Branches to `then` or `els`, each copying its value, if the `if` has one, into a
+    /// register both write, and joins after them.
+    fn lower_if(&mut self, node: &Node<'a>, cond: &Node<'a>, then: &[Node<'a>], els: Option<&[Node<'a>]>) -> Option<Operand> {
+        let cond = self.lower_expr(cond)?;
+        let ty = self.type_of(node);
+        let result = (!matches!(ty, Ty::Void)).then(|| self.builder.reg());
+
+        let (then_block, join) = (self.builder.new_block(), self.builder.new_block());
+        let els_block = if els.is_some() { self.builder.new_block() } else { join };
+        self.builder.terminate(Terminator::Branch { cond, then: then_block, els: els_block });
+
+        let branches = [(then_block, Some(then)), (els_block, els)];
+        for (block, stmts) in branches.into_iter().filter_map(|(block, stmts)| Some((block, stmts?))) {
+            self.builder.switch_to(block);
+            let value = self.lower_block(stmts);
+            // A branch that never completes has no value to copy.
+            if let (Some(dst), Some(value), Some(tail)) = (result, value, stmts.last()) {
+                let src = self.coerce(tail, value);
+                self.builder.push(Inst::Copy { dst, ty: ty.clone(), src });
+            }
+            self.builder.terminate(Terminator::Jump(join));
+        }
+
+        self.builder.switch_to(join);
+        result.map(Operand::Reg)
+    }
+
+    /// Tests `cond` in a block of its own, which the end of the body and `continue` go back to.
+    fn lower_while(&mut self, label: Option<&str>, cond: &Node<'a>, body: &[Node<'a>]) {
+        let (head, body_block, exit) = (self.builder.new_block(), self.builder.new_block(), self.builder.new_block());
+        self.builder.terminate(Terminator::Jump(head));
+        self.builder.switch_to(head);
+        let Some(cond) = self.lower_expr(cond) else {
+            return;
+        };
+        self.builder.terminate(Terminator::Branch { cond, then: body_block, els: exit });
+
+        self.builder.switch_to(body_block);
+        self.lower_loop_body(label, head, exit, body);
+        self.builder.terminate(Terminator::Jump(head));
+        self.builder.switch_to(exit);
+    }
+
+    /// Evaluates both bounds once, then runs the body while the counter is below the end,
+    /// stepping it in a block of its own that `continue` goes to.
+    fn lower_for(&mut self, label: Option<&str>, var: &Node<'a>, start: &Node<'a>, end: &Node<'a>, body: &[Node<'a>]) {
+        let Node::Value { ret, span } = var else {
+            return;
+        };
+        let ty = self.types.declared(var, ret);
+        let ty = self.ty(&ty, *span);
+        let Some(first) = self.lower_expr(start) else {
+            return;
+        };
+        let first = if mutates(end) { self.pin(first, ty.clone()) } else { first };
+        let Some(last) = self.lower_expr(end) else {
+            return;
+        };
+        let last = self.pin(last, ty.clone());
+        self.bind(var, ty.clone(), Some(first));
+        let Some((counter, _)) = self.resolutions.get(var).and_then(|id| self.builder.locals.get(&id)).cloned() else {
+            return;
+        };
+
+        let (head, body_block, step, exit) = (self.builder.new_block(), self.builder.new_block(), self.builder.new_block(), self.builder.new_block());
+        self.builder.terminate(Terminator::Jump(head));
+        self.builder.switch_to(head);
+        let cond = self.define(Ty::Bool, |dst, ty| Inst::Binary { dst, ty, op: BinaryOp::Lt, lhs: Operand::Reg(counter), rhs: last });
+        self.builder.terminate(Terminator::Branch { cond, then: body_block, els: exit });
+
+        self.builder.switch_to(body_block);
+        self.lower_loop_body(label, step, exit, body);
+        self.builder.terminate(Terminator::Jump(step));
+
+        self.builder.switch_to(step);
+        let stepped = self.define(ty.clone(), |dst, ty| Inst::Binary { dst, ty, op: BinaryOp::Add, lhs: Operand::Reg(counter), rhs: Operand::Const(Const::Int(1)) });
+        self.builder.push(Inst::Copy { dst: counter, ty, src: stepped });
+        self.builder.terminate(Terminator::Jump(head));
+        self.builder.switch_to(exit);
+    }
+
+    /// Lowers the body of a loop whose `continue`s go to `next` and `break`s to `exit`.
+    fn lower_loop_body(&mut self, label: Option<&str>, next: BlockId, exit: BlockId, body: &[Node<'a>]) {
+        self.builder.loops.push((label.map(str::to_string), next, exit));
+        self.lower_block(body);
+        self.builder.loops.pop();
+    }
+
+    /// Lowers arguments left to right. An argument read from a local is pinned when a
+    /// later argument could change that local, e.g. `f(x, x++)`.
+    fn lower_args(&mut self, args: &[Node<'a>]) -> Option<Vec<Operand>> {
+        let mut values = Vec::with_capacity(args.len());
+        for (idx, arg) in args.iter().enumerate() {
+            let mut value = self.lower_expr(arg)?;
+            if args[idx + 1..].iter().any(|later| mutates(later)) {
+                let ty = self.type_of(arg);
+                value = self.pin(value, ty);
+            }
+            values.push(self.coerce(arg, value));
+        }
+        Some(values)
+    }
+
+    /// Calls the method `func` of a trait object through its vtable.
+    fn lower_dyn_call(&mut self, node: &Node<'a>, recv: Reg, with: &str, func: &str, args: &[Node<'a>], span: Span) -> Option<Operand> {
+        let method = self.symbols.method(with, func).found()
+            .filter(|symbol| matches!(&symbol.kind, SymbolKind::Function(defun) if takes_self(defun)));
+        let Some(method) = method else {
+            self.unsupported(span, format!("a call to `{}::{}` through a trait object", with, func));
+            return None;
+        };
+        let method = method.qualified.clone();
+        let args = self.lower_args(args)?;
+        let ty = self.type_of(node);
+        if ty == Ty::Void {
+            self.builder.push(Inst::CallDyn { dst: None, recv, method, args });
+            return None;
+        }
+        Some(self.define(ty, |dst, ty| Inst::CallDyn { dst: Some((dst, ty)), recv, method, args }))
+    }
+
+    fn call(&mut self, func: String, args: Vec<Operand>, ret: Ty) -> Option<Operand> {
+        if ret == Ty::Void {
+            self.builder.push(Inst::Call { dst: None, func, args });
+            return None;
+        }
+        Some(self.define(ret, |dst, ty| Inst::Call { dst: Some((dst, ty)), func, args }))
+    }
+
+    fn declare_builtin(&mut self, builtin: Builtin) {
+        if self.module.external(builtin.name()).is_none() {
+            self.module.externs.push(Extern {
+                name: builtin.name().to_string(),
+                params: Vec::new(),
+                variadic: true,
+                ret: Ty::Void
+            });
+        }
+    }
+
+    fn lower_binary(&mut self, node: &Node<'a>, lhs: &Node<'a>, rhs: &Node<'a>, op: BinOp, span: Span) -> Option<Operand> {
+        match op {
+            BinOp::Assign => {
+                let place = self.place(lhs)?;
+                let value = self.lower_expr(rhs)?;
+                let value = self.coerce(rhs, value);
+                self.write(&place, value);
+                return None;
+            },
+            BinOp::LogAnd | BinOp::LogOr => return self.lower_logical(lhs, rhs, op == BinOp::LogOr),
+            _ => {}
+        }
+
+        let Some(ir_op) = BinaryOp::from_bin_op(op) else {
+            self.unsupported(span, format!("the `{}` operator", op.as_str()));
+            return None;
+        };
+        let operand_ty = self.type_of(lhs);
+
+        if op.is_assignment() {
+            let place = self.place(lhs)?;
+            let current = self.read(&place);
+            let current = if mutates(rhs) { self.pin(current, operand_ty.clone()) } else { current };
+            let value = self.lower_expr(rhs)?;
+            let result = self.define(operand_ty, |dst, ty| Inst::Binary { dst, ty, op: ir_op, lhs: current, rhs: value });
+            self.write(&place, result);
+            return None;
+        }
+
+        let mut left = self.lower_expr(lhs)?;
+        if mutates(rhs) {
+            left = self.pin(left, operand_ty.clone());
+        }
+        let right = self.lower_expr(rhs)?;
+        // A comparison takes its operand type from a register, so two constants need one.
+        if ir_op.is_comparison() && left.reg().is_none() && right.reg().is_none() {
+            left = self.define(operand_ty, |dst, ty| Inst::Copy { dst, ty, src: left });
+        }
+        let ty = self.type_of(node);
+        Some(self.define(ty, |dst, ty| Inst::Binary { dst, ty, op: ir_op, lhs: left, rhs: right }))
+    }
+
+    /// `a && b` and `a || b` only evaluate `b` when `a` doesn't decide the result.
+    fn lower_logical(&mut self, lhs: &Node<'a>, rhs: &Node<'a>, is_or: bool) -> Option<Operand> {
+        let left = self.lower_expr(lhs)?;
+        let result = self.builder.reg();
+        self.builder.push(Inst::Copy { dst: result, ty: Ty::Bool, src: left.clone() });
+
+        let (eval_rhs, join) = (self.builder.new_block(), self.builder.new_block());
+        let (then, els) = if is_or { (join, eval_rhs) } else { (eval_rhs, join) };
+        self.builder.terminate(Terminator::Branch { cond: left, then, els });
+
+        self.builder.switch_to(eval_rhs);
+        let right = self.lower_expr(rhs)?;
+        self.builder.push(Inst::Copy { dst: result, ty: Ty::Bool, src: right });
+        self.builder.terminate(Terminator::Jump(join));
+
+        self.builder.switch_to(join);
+        Some(Operand::Reg(result))
+    }
+
+    fn lower_unary(&mut self, node: &Node<'a>, lhs: &Node<'a>, op: UniOp, postfix: bool) -> Option<Operand> {
+        // `-128` is a literal of its own, not the negation of an out-of-range `128`.
+        if let (UniOp::Negative, Node::Literal { value: value @ (Literal::Int(_) | Literal::Float(_)), .. }) = (op, lhs) {
+            return Some(Operand::Const(literal(value, true)));
+        }
+
+        let ty = self.type_of(node);
+        if let Some(ir_op) = UnaryOp::from_uni_op(op) {
+            let src = self.lower_expr(lhs)?;
+            return Some(self.define(ty, |dst, ty| Inst::Unary { dst, ty, op: ir_op, src }));
+        }
+
+        let place = self.place(lhs)?;
+        let current = self.read(&place);
+        let old = if postfix { self.pin(current.clone(), ty.clone()) } else { current.clone() };
+        let one = if ty.is_float() { Const::Float(1.0) } else { Const::Int(1) };
+        let ir_op = if op == UniOp::Increment { BinaryOp::Add } else { BinaryOp::Sub };
+        let stepped = self.define(ty, |dst, ty| Inst::Binary { dst, ty, op: ir_op, lhs: current, rhs: Operand::Const(one) });
+        self.write(&place, stepped.clone());
+        Some(if postfix { old } else { stepped })
+    }
+}
+
+fn literal(value: &Literal<'_>, negated: bool) -> Const {
+    match value {
+        Literal::Int(value) => Const::Int(if negated { -(*value as i128) } else { *value as i128 }),
+        Literal::Float(value) => Const::Float(if negated { -value } else { *value }),
+        Literal::Str(value) => Const::Str(value.to_string()),
+        Literal::Boolean(value) => Const::Bool(*value)
+    }
+}
+
+/// The methods defined in the body of an `obj`, `trait` or `extend` block.
+fn methods<'n, 'a>(body: &'n [Node<'a>]) -> impl Iterator<Item = Method<'n, 'a>> {
+    body.iter().filter_map(|method| match method {
+        Node::Body { discriptor, body, span } => match discriptor.as_ref() {
+            Bodies::Defun(defun) => Some((defun, body.as_slice(), *span)),
+            _ => None
+        },
+        _ => None
+    })
+}
+
+/// The name of the instance of `base` for the type arguments `args`.
+fn mangle(base: &str, args: &[Ty]) -> String {
+    let mut name = base.to_string();
+    for arg in args {
+        match arg {
+            Ty::Dyn(with) => name.push_str(&format!(".dyn_{}", with)),
+            arg => name.push_str(&format!(".{}", arg))
+        }
+    }
+    name
+}
+
+/// How deeply type arguments nest in `ty`.
+fn nesting(ty: &Type<'_>) -> usize {
+    match ty {
+        Type::Applied(_, args) => 1 + args.iter().map(nesting).max().unwrap_or_default(),
+        Type::UnsafePtr(inner) | Type::SafePtr(inner) | Type::Array(inner, _) | Type::Slice(inner) => nesting(inner),
+        _ => 0
+    }
+}
+
+fn takes_self(defun: &DefunDescriptor<'_>) -> bool {
+    defun.args().first().and_then(|arg| arg.name()).is_some_and(|name| name.as_slice() == "self")
+}
+
+/// The value of an initializer that needs no code: a literal, possibly negated.
+fn constant(node: &Node<'_>) -> Option<Const> {
+    match node {
+        Node::Literal { value, .. } => Some(literal(value, false)),
+        Node::UnaryOp { lhs, op: UniOp::Negative, .. } => match lhs.as_ref() {
+            Node::Literal { value: value @ (Literal::Int(_) | Literal::Float(_)), .. } => Some(literal(value, true)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Whether evaluating `node` can change a local variable.
+fn mutates(node: &Node<'_>) -> bool {
+    match node {
+        Node::UnaryOp { op: UniOp::Increment | UniOp::Decrement, .. } => true,
+        Node::BinaryOp { op, .. } if op.is_assignment() => true,
+        Node::BinaryOp { lhs, rhs, .. } => mutates(lhs) || mutates(rhs),
+        Node::UnaryOp { lhs, .. } => mutates(lhs),
+        Node::Call { args, .. } => args.iter().any(|arg| mutates(arg)),
+        Node::ObjCall { recv, args, .. } => mutates(recv) || args.iter().any(|arg| mutates(arg)),
+        Node::Field { recv, .. } => mutates(recv),
+        Node::Head { next } => mutates(next),
+        Node::Chain { chained } => chained.iter().any(|node| mutates(node)),
+        Node::If { cond, then, els, .. } => mutates(cond) || then.iter().chain(els.iter().flatten()).any(|node| mutates(node)),
+        Node::While { cond, body, .. } => mutates(cond) || body.iter().any(|node| mutates(node)),
+        // A `for` steps its counter.
+        Node::For { .. } => true,
+        Node::Loop { body, .. } => body.iter().any(|node| mutates(node)),
+        Node::Return { value, .. } => value.as_deref().is_some_and(mutates),
+        _ => false
+    }
+}
@@ -0,0 +1,801 @@
+pub(crate) mod cfg;
+pub(crate) mod lower;
+pub(crate) mod opt;
+pub(crate) mod parse;
+pub(crate) mod ssa;
+pub(crate) mod verify;
+
+use std::fmt::Display;
+
+use crate::common::syntax_tree::{BinOp, Type, UniOp};
+
+/// The value types IR registers, globals and fields carry. Objects are handled by
+/// reference, so a `Struct` value is a pointer to a heap instance of the named layout,
+/// and a `Dyn` value refers to an instance together with its vtable for the trait.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Ty {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F8,
+    F16,
+    F32,
+    F64,
+    Bool,
+    Str,
+    Void,
+    Struct(String),
+    Dyn(String)
+}
+
+impl Ty {
+
+    /// Maps a source type; pointers, arrays, slices and traits have no IR form yet.
+    /// Lowering maps pointers to traits, which need the symbol table to tell apart, to
+    /// trait objects, and instantiates generic types, which need the arguments in scope.
+    pub fn from_type(ty: &Type<'_>) -> Option<Self> {
+        Some(match ty {
+            Type::Int8 => Self::I8,
+            Type::Int16 => Self::I16,
+            Type::Int32 => Self::I32,
+            Type::Int64 => Self::I64,
+            Type::Uint8 => Self::U8,
+            Type::Uint16 => Self::U16,
+            Type::Uint32 => Self::U32,
+            Type::Uint64 => Self::U64,
+            Type::Float8 => Self::F8,
+            Type::Float16 => Self::F16,
+            Type::Float32 => Self::F32,
+            Type::Float64 => Self::F64,
+            Type::Boolean => Self::Bool,
+            Type::Str => Self::Str,
+            Type::Void => Self::Void,
+            Type::Object(_) | Type::Composition(_) | Type::Named(_) => Self::Struct(ty.type_name()?.to_string()),
+            Type::UnsafePtr(_) | Type::SafePtr(_) | Type::Array(..) | Type::Slice(_) | Type::Trait(_)
+            | Type::Applied(..) | Type::Param(_) | Type::Infer | Type::Var(_) => return None
+        })
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" => Self::I64,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "f8" => Self::F8,
+            "f16" => Self::F16,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            "bool" => Self::Bool,
+            "str" => Self::Str,
+            "void" => Self::Void,
+            _ => return None
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64 | Self::U8 | Self::U16 | Self::U32 | Self::U64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::F8 | Self::F16 | Self::F32 | Self::F64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Floats narrower than 64 bits are single precision, as in the interpreter.
+    pub fn is_single(&self) -> bool {
+        self.is_float() && self.bits().is_some_and(|bits| bits <= 32)
+    }
+
+    /// Width in bits of a numeric type.
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Self::I8 | Self::U8 | Self::F8 => Some(8),
+            Self::I16 | Self::U16 | Self::F16 => Some(16),
+            Self::I32 | Self::U32 | Self::F32 => Some(32),
+            Self::I64 | Self::U64 | Self::F64 => Some(64),
+            _ => None
+        }
+    }
+
+    /// Inclusive value range of an integer type.
+    pub fn int_range(&self) -> Option<(i128, i128)> {
+        let bits = self.bits()? as i128;
+        match self {
+            _ if self.is_signed() => Some((-(1 << (bits - 1)), (1 << (bits - 1)) - 1)),
+            _ if self.is_integer() => Some((0, (1 << bits) - 1)),
+            _ => None
+        }
+    }
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F8 => "f8",
+            Self::F16 => "f16",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Bool => "bool",
+            Self::Str => "str",
+            Self::Void => "void",
+            Self::Struct(name) => name,
+            Self::Dyn(with) => return write!(f, "dyn {}", with)
+        };
+        f.write_str(name)
+    }
+}
+
+/// A virtual register. Registers are local to a function and may be assigned more than
+/// once; each definition states the register's type, and all of them must agree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Reg(pub u32);
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct BlockId(pub u32);
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// An immediate operand. Constants are untyped; they take the type of the register,
+/// parameter or global they meet.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Const {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Str(String)
+}
+
+impl Const {
+
+    /// Whether the constant can stand for a value of type `ty`.
+    pub fn fits(&self, ty: &Ty) -> bool {
+        match self {
+            Self::Int(value) => ty.int_range().is_some_and(|(min, max)| (min..=max).contains(value)),
+            Self::Float(_) => ty.is_float(),
+            Self::Bool(_) => *ty == Ty::Bool,
+            Self::Str(_) => *ty == Ty::Str
+        }
+    }
+
+    /// The zero value of `ty`, which `let` without an initializer starts from.
+    pub fn zero(ty: &Ty) -> Option<Self> {
+        Some(match ty {
+            Ty::Bool => Self::Bool(false),
+            Ty::Str => Self::Str(String::new()),
+            ty if ty.is_float() => Self::Float(0.0),
+            ty if ty.is_integer() => Self::Int(0),
+            _ => return None
+        })
+    }
+}
+
+impl Display for Const {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{}", value),
+            // `{:?}` keeps the `.0`, so a float never reads back as an integer.
+            Self::Float(value) if value.is_finite() => write!(f, "{:?}", value),
+            Self::Float(value) if value.is_nan() => f.write_str("nan"),
+            Self::Float(value) => f.write_str(if *value > 0.0 { "inf" } else { "-inf" }),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Str(value) => write!(f, "{:?}", value)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Operand {
+    Reg(Reg),
+    Const(Const)
+}
+
+impl Operand {
+    pub fn reg(&self) -> Option<Reg> {
+        match self {
+            Self::Reg(reg) => Some(*reg),
+            Self::Const(_) => None
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reg(reg) => write!(f, "{}", reg),
+            Self::Const(value) => write!(f, "{}", value)
+        }
+    }
+}
+
+/// Integer arithmetic traps on overflow and on a zero divisor, as in the interpreter;
+/// float arithmetic follows IEEE 754. `add` also concatenates strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge
+}
+
+impl BinaryOp {
+    pub const ALL: [BinaryOp; 11] = [
+        Self::Add, Self::Sub, Self::Mul, Self::Div, Self::Rem,
+        Self::Eq, Self::Ne, Self::Lt, Self::Le, Self::Gt, Self::Ge
+    ];
+
+    /// The IR operator for a source operator; compound assignments map to their base.
+    /// `&&`, `||` and `=` are control flow and stores, not instructions.
+    pub fn from_bin_op(op: BinOp) -> Option<Self> {
+        Some(match op.compound_base().unwrap_or(op) {
+            BinOp::Add => Self::Add,
+            BinOp::Subtract => Self::Sub,
+            BinOp::Multiply => Self::Mul,
+            BinOp::Divide => Self::Div,
+            BinOp::Modulus => Self::Rem,
+            BinOp::Equals => Self::Eq,
+            BinOp::NotEquals => Self::Ne,
+            BinOp::LessThan => Self::Lt,
+            BinOp::LessThanEq => Self::Le,
+            BinOp::GreaterThan => Self::Gt,
+            BinOp::GreaterThanEq => Self::Ge,
+            _ => return None
+        })
+    }
+
+    /// The source operator this one computes, which is how the interpreter's `Value`
+    /// implements it.
+    pub fn bin_op(&self) -> BinOp {
+        match self {
+            Self::Add => BinOp::Add,
+            Self::Sub => BinOp::Subtract,
+            Self::Mul => BinOp::Multiply,
+            Self::Div => BinOp::Divide,
+            Self::Rem => BinOp::Modulus,
+            Self::Eq => BinOp::Equals,
+            Self::Ne => BinOp::NotEquals,
+            Self::Lt => BinOp::LessThan,
+            Self::Le => BinOp::LessThanEq,
+            Self::Gt => BinOp::GreaterThan,
+            Self::Ge => BinOp::GreaterThanEq
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        !matches!(self, Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Rem)
+    }
+
+    /// As `BinOp::verb`, for the runtime errors generated code reports.
+    pub fn verb(&self) -> &'static str {
+        self.bin_op().verb()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Le => "le",
+            Self::Gt => "gt",
+            Self::Ge => "ge"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum UnaryOp {
+    Neg,
+    Not,
+    BitNot
+}
+
+impl UnaryOp {
+    pub const ALL: [UnaryOp; 3] = [Self::Neg, Self::Not, Self::BitNot];
+
+    /// `++`/`--` lower to an `add`/`sub` and a store, so they have no unary form.
+    pub fn from_uni_op(op: UniOp) -> Option<Self> {
+        match op {
+            UniOp::Negative => Some(Self::Neg),
+            UniOp::LogNot => Some(Self::Not),
+            UniOp::BitNot => Some(Self::BitNot),
+            UniOp::Increment | UniOp::Decrement => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Neg => "neg",
+            Self::Not => "not",
+            Self::BitNot => "bnot"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Inst {
+    /// `%d: ty = copy a`
+    Copy { dst: Reg, ty: Ty, src: Operand },
+    /// `%d: ty = add a, b`; comparisons define a `bool`.
+    Binary { dst: Reg, ty: Ty, op: BinaryOp, lhs: Operand, rhs: Operand },
+    /// `%d: ty = neg a`
+    Unary { dst: Reg, ty: Ty, op: UnaryOp, src: Operand },
+    /// `%d: ty = call @f(a, b)`, or `call @f(a, b)` for a `void` function.
+    Call { dst: Option<(Reg, Ty)>, func: String, args: Vec<Operand> },
+    /// `%d: ty = load @g`
+    Load { dst: Reg, ty: Ty, global: String },
+    /// `store @g, a`
+    Store { global: String, src: Operand },
+    /// `%d: Point = new Point(a, b)` allocates an instance, taking fields in order.
+    New { dst: Reg, ty: Ty, args: Vec<Operand> },
+    /// `%d: ty = getfield %p, x`
+    GetField { dst: Reg, ty: Ty, obj: Reg, field: String },
+    /// `setfield %p, x, a`
+    SetField { obj: Reg, field: String, src: Operand },
+    /// `%d: dyn Show = dyn Point %p` pairs an instance with the vtable of `Point` for `Show`.
+    Dyn { dst: Reg, ty: Ty, concrete: String, src: Reg },
+    /// `%d: ty = calldyn %t, @Show::show(a, b)` calls the function the vtable of the trait
+    /// object `%t` has for the trait method, passing the instance before the arguments.
+    CallDyn { dst: Option<(Reg, Ty)>, recv: Reg, method: String, args: Vec<Operand> },
+    /// `%d: ty = phi [bb0: a], [bb1: b]` picks the input of the predecessor control came
+    /// from. Phis only appear at the start of a block, once the function is in SSA form.
+    Phi { dst: Reg, ty: Ty, incoming: Vec<(BlockId, Operand)> }
+}
+
+impl Inst {
+
+    /// The register this instruction defines, with its type.
+    pub fn def(&self) -> Option<(Reg, &Ty)> {
+        match self {
+            Self::Copy { dst, ty, .. }
+            | Self::Binary { dst, ty, .. }
+            | Self::Unary { dst, ty, .. }
+            | Self::Load { dst, ty, .. }
+            | Self::New { dst, ty, .. }
+            | Self::GetField { dst, ty, .. }
+            | Self::Dyn { dst, ty, .. }
+            | Self::Phi { dst, ty, .. } => Some((*dst, ty)),
+            Self::Call { dst, .. } | Self::CallDyn { dst, .. } => dst.as_ref().map(|(reg, ty)| (*reg, ty)),
+            Self::Store { .. } | Self::SetField { .. } => None
+        }
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Reg> {
+        match self {
+            Self::Copy { dst, .. }
+            | Self::Binary { dst, .. }
+            | Self::Unary { dst, .. }
+            | Self::Load { dst, .. }
+            | Self::New { dst, .. }
+            | Self::GetField { dst, .. }
+            | Self::Dyn { dst, .. }
+            | Self::Phi { dst, .. } => Some(dst),
+            Self::Call { dst, .. } | Self::CallDyn { dst, .. } => dst.as_mut().map(|(reg, _)| reg),
+            Self::Store { .. } | Self::SetField { .. } => None
+        }
+    }
+
+    /// Every operand the instruction reads, in order.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Self::Copy { src, .. } | Self::Unary { src, .. } | Self::Store { src, .. } => vec![src],
+            Self::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Self::Call { args, .. } | Self::CallDyn { args, .. } | Self::New { args, .. } => args.iter().collect(),
+            Self::SetField { src, .. } => vec![src],
+            Self::Phi { incoming, .. } => incoming.iter().map(|(_, value)| value).collect(),
+            Self::Load { .. } | Self::GetField { .. } | Self::Dyn { .. } => Vec::new()
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Copy { src, .. } | Self::Unary { src, .. } | Self::Store { src, .. } => vec![src],
+            Self::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Self::Call { args, .. } | Self::CallDyn { args, .. } | Self::New { args, .. } => args.iter_mut().collect(),
+            Self::SetField { src, .. } => vec![src],
+            Self::Phi { incoming, .. } => incoming.iter_mut().map(|(_, value)| value).collect(),
+            Self::Load { .. } | Self::GetField { .. } | Self::Dyn { .. } => Vec::new()
+        }
+    }
+
+    /// The instance a field access goes through, the instance a trait object is made
+    /// from, or the trait object a call dispatches on; unlike operands, it is always a
+    /// register.
+    pub fn obj_mut(&mut self) -> Option<&mut Reg> {
+        match self {
+            Self::GetField { obj, .. } | Self::SetField { obj, .. } | Self::Dyn { src: obj, .. } | Self::CallDyn { recv: obj, .. } => Some(obj),
+            _ => None
+        }
+    }
+
+    pub fn is_phi(&self) -> bool {
+        matches!(self, Self::Phi { .. })
+    }
+
+    /// Every register the instruction reads, including object operands.
+    pub fn uses(&self) -> Vec<Reg> {
+        let mut regs: Vec<Reg> = self.operands().into_iter().filter_map(Operand::reg).collect();
+        if let Self::GetField { obj, .. } | Self::SetField { obj, .. } | Self::Dyn { src: obj, .. } | Self::CallDyn { recv: obj, .. } = self {
+            regs.insert(0, *obj);
+        }
+        regs
+    }
+}
+
+fn write_args(f: &mut std::fmt::Formatter<'_>, args: &[Operand]) -> std::fmt::Result {
+    for (idx, arg) in args.iter().enumerate() {
+        if idx > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", arg)?;
+    }
+    Ok(())
+}
+
+impl Display for Inst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((dst, ty)) = self.def() {
+            write!(f, "{}: {} = ", dst, ty)?;
+        }
+        match self {
+            Self::Copy { src, .. } => write!(f, "copy {}", src),
+            Self::Binary { op, lhs, rhs, .. } => write!(f, "{} {}, {}", op.as_str(), lhs, rhs),
+            Self::Unary { op, src, .. } => write!(f, "{} {}", op.as_str(), src),
+            Self::Call { func, args, .. } => {
+                write!(f, "call @{}(", func)?;
+                write_args(f, args)?;
+                f.write_str(")")
+            },
+            Self::Load { global, .. } => write!(f, "load @{}", global),
+            Self::Store { global, src } => write!(f, "store @{}, {}", global, src),
+            Self::New { ty, args, .. } => {
+                write!(f, "new {}(", ty)?;
+                write_args(f, args)?;
+                f.write_str(")")
+            },
+            Self::GetField { obj, field, .. } => write!(f, "getfield {}, {}", obj, field),
+            Self::SetField { obj, field, src } => write!(f, "setfield {}, {}, {}", obj, field, src),
+            Self::Dyn { concrete, src, .. } => write!(f, "dyn {} {}", concrete, src),
+            Self::CallDyn { recv, method, args, .. } => {
+                write!(f, "calldyn {}, @{}(", recv, method)?;
+                write_args(f, args)?;
+                f.write_str(")")
+            },
+            Self::Phi { incoming, .. } => {
+                f.write_str("phi ")?;
+                for (idx, (block, value)) in incoming.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "[{}: {}]", block, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Terminator {
+    /// `ret` or `ret a`
+    Ret(Option<Operand>),
+    /// `jmp bb1`
+    Jump(BlockId),
+    /// `br c, bb1, bb2`
+    Branch { cond: Operand, then: BlockId, els: BlockId },
+    /// Control never reaches the end of the block.
+    Unreachable
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Jump(target) => vec![*target],
+            Self::Branch { then, els, .. } => vec![*then, *els],
+            Self::Ret(_) | Self::Unreachable => Vec::new()
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Self::Jump(target) => vec![target],
+            Self::Branch { then, els, .. } => vec![then, els],
+            Self::Ret(_) | Self::Unreachable => Vec::new()
+        }
+    }
+
+    /// The value a `ret` returns or a `br` tests.
+    pub fn operand(&self) -> Option<&Operand> {
+        match self {
+            Self::Ret(Some(value)) | Self::Branch { cond: value, .. } => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Self::Ret(Some(value)) | Self::Branch { cond: value, .. } => Some(value),
+            _ => None
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ret(None) => f.write_str("ret"),
+            Self::Ret(Some(value)) => write!(f, "ret {}", value),
+            Self::Jump(target) => write!(f, "jmp {}", target),
+            Self::Branch { cond, then, els } => write!(f, "br {}, {}, {}", cond, then, els),
+            Self::Unreachable => f.write_str("unreachable")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Block {
+    pub id: BlockId,
+    pub insts: Vec<Inst>,
+    pub term: Terminator
+}
+
+/// A function body; the first block is the entry.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Function {
+    pub name: String,
+    pub params: Vec<(Reg, Ty)>,
+    pub ret: Ty,
+    pub blocks: Vec<Block>
+}
+
+impl Function {
+
+    /// A register number no parameter or instruction uses yet.
+    pub fn next_reg(&self) -> u32 {
+        let params = self.params.iter().map(|(reg, _)| reg.0);
+        let insts = self.blocks.iter().flat_map(|block| &block.insts).flat_map(|inst| {
+            inst.def().map(|(reg, _)| reg).into_iter().chain(inst.uses())
+        });
+        params.chain(insts.map(|reg| reg.0)).max().map_or(0, |max| max + 1)
+    }
+
+    /// A block label not in use yet.
+    pub fn next_block(&self) -> u32 {
+        self.blocks.iter().map(|block| block.id.0 + 1).max().unwrap_or(0)
+    }
+
+    pub fn inst_count(&self) -> usize {
+        self.blocks.iter().map(|block| block.insts.len() + 1).sum()
+    }
+}
+
+/// A function the runtime provides, such as `print`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Extern {
+    pub name: String,
+    pub params: Vec<Ty>,
+    /// Accepts any number of further arguments of any type.
+    pub variadic: bool,
+    pub ret: Ty
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Global {
+    pub name: String,
+    pub ty: Ty,
+    /// Globals without a constant initializer are set by the module's `.init` function.
+    pub init: Option<Const>
+}
+
+/// The field layout of an `obj` or `comp`, or of an instance of a generic one, which is
+/// named after its type arguments, like `Box.i32`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StructDef {
+    pub name: String,
+    pub fields: Vec<(String, Ty)>
+}
+
+/// The name of the `obj` or `comp` the struct `name` lays out, which is what printing
+/// an instance shows: `Box` for `Box.i32`.
+pub(crate) fn display_name(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+impl StructDef {
+    pub fn field(&self, name: &str) -> Option<&Ty> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, ty)| ty)
+    }
+}
+
+/// A method trait objects can be called through, named by the trait that declares it;
+/// the parameters leave out the instance, which always comes first.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Slot {
+    pub method: String,
+    pub params: Vec<Ty>,
+    pub ret: Ty
+}
+
+/// The vtable layout of a trait's objects: a slot for every method taking `self` that
+/// the trait or one of its supertraits declares.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TraitDef {
+    pub name: String,
+    pub slots: Vec<Slot>
+}
+
+impl TraitDef {
+    pub fn slot(&self, method: &str) -> Option<(usize, &Slot)> {
+        self.slots.iter().enumerate().find(|(_, slot)| slot.method == method)
+    }
+}
+
+/// The functions an instance of the struct `obj` runs for the slots of `with`, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct VTable {
+    pub obj: String,
+    pub with: String,
+    pub functions: Vec<String>
+}
+
+/// A whole program in three-address form.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Module {
+    pub structs: Vec<StructDef>,
+    pub traits: Vec<TraitDef>,
+    pub vtables: Vec<VTable>,
+    pub globals: Vec<Global>,
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>
+}
+
+impl Module {
+    /// Runs the top-level statements; names can't contain `.`, so it never clashes.
+    pub const INIT: &'static str = ".init";
+
+    pub fn structure(&self, name: &str) -> Option<&StructDef> {
+        self.structs.iter().find(|def| def.name == name)
+    }
+
+    pub fn trait_def(&self, name: &str) -> Option<&TraitDef> {
+        self.traits.iter().find(|def| def.name == name)
+    }
+
+    pub fn vtable(&self, obj: &str, with: &str) -> Option<&VTable> {
+        self.vtables.iter().find(|vtable| vtable.obj == obj && vtable.with == with)
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|global| global.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    pub fn external(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|external| external.name == name)
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fn @{}(", self.name)?;
+        for (idx, (reg, ty)) in self.params.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", reg, ty)?;
+        }
+        writeln!(f, ") -> {} {{", self.ret)?;
+        for block in &self.blocks {
+            writeln!(f, "{}:", block.id)?;
+            for inst in &block.insts {
+                writeln!(f, "    {}", inst)?;
+            }
+            writeln!(f, "    {}", block.term)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The textual form `parse::parse_module` reads back.
+impl Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sections = 0;
+        let mut separate = |f: &mut std::fmt::Formatter<'_>, items: usize| {
+            let gap = sections > 0 && items > 0;
+            sections += (items > 0) as usize;
+            if gap { writeln!(f) } else { Ok(()) }
+        };
+
+        separate(f, self.structs.len())?;
+        for def in &self.structs {
+            let fields: Vec<String> = def.fields.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+            writeln!(f, "struct {} {{ {} }}", def.name, fields.join(", "))?;
+        }
+
+        separate(f, self.traits.len())?;
+        for def in &self.traits {
+            let slots: Vec<String> = def.slots.iter().map(|slot| {
+                let params: Vec<String> = slot.params.iter().map(Ty::to_string).collect();
+                format!("@{}({}) -> {}", slot.method, params.join(", "), slot.ret)
+            }).collect();
+            writeln!(f, "trait {} {{ {} }}", def.name, slots.join(", "))?;
+        }
+
+        separate(f, self.vtables.len())?;
+        for vtable in &self.vtables {
+            let functions: Vec<String> = vtable.functions.iter().map(|function| format!("@{}", function)).collect();
+            writeln!(f, "vtable {} as {} {{ {} }}", vtable.obj, vtable.with, functions.join(", "))?;
+        }
+
+        separate(f, self.externs.len())?;
+        for external in &self.externs {
+            let mut params: Vec<String> = external.params.iter().map(Ty::to_string).collect();
+            if external.variadic {
+                params.push("...".into());
+            }
+            writeln!(f, "extern @{}({}) -> {}", external.name, params.join(", "), external.ret)?;
+        }
+
+        separate(f, self.globals.len())?;
+        for global in &self.globals {
+            match &global.init {
+                Some(init) => writeln!(f, "global @{}: {} = {}", global.name, global.ty, init)?,
+                None => writeln!(f, "global @{}: {}", global.name, global.ty)?
+            }
+        }
+
+        for function in &self.functions {
+            separate(f, 1)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
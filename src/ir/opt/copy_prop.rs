@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use crate::ir::{Const, Function, Inst, Operand, Reg, Ty};
+
+/// What a register defined once by a copy, or by a phi whose inputs all agree, stands for.
+fn forwarded(function: &Function) -> HashMap<Reg, Operand> {
+    let mut defs: HashMap<Reg, usize> = function.params.iter().map(|(reg, _)| (*reg, 1)).collect();
+    for (reg, _) in function.blocks.iter().flat_map(|block| &block.insts).filter_map(Inst::def) {
+        *defs.entry(reg).or_default() += 1;
+    }
+
+    let mut forwards = HashMap::new();
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        let (dst, value) = match inst {
+            Inst::Copy { dst, src, .. } => (*dst, src.clone()),
+            Inst::Phi { dst, incoming, .. } => {
+                // A phi that feeds itself around a loop adds no value of its own.
+                let mut inputs = incoming.iter().map(|(_, value)| value).filter(|value| value.reg() != Some(*dst));
+                let Some(first) = inputs.next() else {
+                    continue;
+                };
+                if !inputs.all(|value| value == first) {
+                    continue;
+                }
+                (*dst, first.clone())
+            },
+            _ => continue
+        };
+        if defs.get(&dst) == Some(&1) && value.reg() != Some(dst) {
+            forwards.insert(dst, value);
+        }
+    }
+    forwards
+}
+
+/// Follows a chain of forwards to its end; in SSA form the end dominates every use.
+fn resolve(forwards: &HashMap<Reg, Operand>, operand: &Operand) -> Operand {
+    let mut current = operand.clone();
+    for _ in 0..=forwards.len() {
+        match current.reg().and_then(|reg| forwards.get(&reg)) {
+            Some(next) => current = next.clone(),
+            None => break
+        }
+    }
+    current
+}
+
+/// Replaces reads of forwarded registers with what they forward. The copies and phis
+/// themselves are left for dead-code elimination.
+pub(super) fn run(function: &mut Function) -> bool {
+    let forwards = forwarded(function);
+    if forwards.is_empty() {
+        return false;
+    }
+
+    let types: HashMap<Reg, Ty> = function.blocks.iter()
+        .flat_map(|block| &block.insts)
+        .filter_map(|inst| inst.def().map(|(reg, ty)| (reg, ty.clone())))
+        .collect();
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            match &mut *inst {
+                // A comparison takes its operand type from a register, so keep one.
+                Inst::Binary { op, lhs, rhs, .. } if op.is_comparison() => {
+                    let (new_lhs, new_rhs) = (resolve(&forwards, lhs), resolve(&forwards, rhs));
+                    if new_lhs.reg().is_some() || new_rhs.reg().is_some() {
+                        changed |= replace(lhs, new_lhs);
+                        changed |= replace(rhs, new_rhs);
+                    } else if lhs.reg().is_some() && rhs.reg().is_some() {
+                        changed |= replace(lhs, new_lhs);
+                    }
+                },
+                // Variadic builtins take the type of an argument from the argument, and a
+                // float constant reads as an `f64`, so narrower floats stay in registers.
+                Inst::Call { args, .. } => {
+                    for arg in args {
+                        let resolved = resolve(&forwards, arg);
+                        let narrow = arg.reg().and_then(|reg| types.get(&reg)).is_some_and(|ty| *ty != Ty::F64);
+                        if !(narrow && matches!(resolved, Operand::Const(Const::Float(_)))) {
+                            changed |= replace(arg, resolved);
+                        }
+                    }
+                },
+                _ => {
+                    for operand in inst.operands_mut() {
+                        let resolved = resolve(&forwards, operand);
+                        changed |= replace(operand, resolved);
+                    }
+                }
+            }
+            if let Some(obj) = inst.obj_mut() {
+                if let Operand::Reg(reg) = resolve(&forwards, &Operand::Reg(*obj)) {
+                    changed |= reg != *obj;
+                    *obj = reg;
+                }
+            }
+        }
+        if let Some(operand) = block.term.operand_mut() {
+            let resolved = resolve(&forwards, operand);
+            changed |= replace(operand, resolved);
+        }
+    }
+    changed
+}
+
+fn replace(operand: &mut Operand, resolved: Operand) -> bool {
+    if resolved == *operand {
+        return false;
+    }
+    *operand = resolved;
+    true
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::ir::{cfg::Cfg, BinaryOp, Function, Inst, Operand, Reg};
+
+/// A key identifying what an instruction computes, or `None` if it can't be shared.
+/// Only pure arithmetic qualifies; loads and field reads may see different values.
+fn key(inst: &Inst) -> Option<String> {
+    match inst {
+        Inst::Binary { ty, op, lhs, rhs, .. } => {
+            // Operand order doesn't matter for these, so `a + b` and `b + a` match.
+            let commutative = matches!(op, BinaryOp::Eq | BinaryOp::Ne)
+                || (matches!(op, BinaryOp::Add | BinaryOp::Mul) && ty.is_numeric());
+            let (mut lhs, mut rhs) = (lhs.to_string(), rhs.to_string());
+            if commutative && lhs > rhs {
+                std::mem::swap(&mut lhs, &mut rhs);
+            }
+            Some(format!("{} {} {}, {}", ty, op.as_str(), lhs, rhs))
+        },
+        Inst::Unary { ty, op, src, .. } => Some(format!("{} {} {}", ty, op.as_str(), src)),
+        _ => None
+    }
+}
+
+enum Visit {
+    Enter(usize),
+    /// Leaving a block forgets the computations it made available.
+    Leave(Vec<String>)
+}
+
+/// Replaces a computation with a copy of an identical one that dominates it. Trapping
+/// operations qualify too: the earlier one would already have trapped.
+pub(super) fn run(function: &mut Function) -> bool {
+    if function.blocks.is_empty() {
+        return false;
+    }
+    let cfg = Cfg::new(function);
+    let children = cfg.dom_children();
+
+    let mut available: HashMap<String, Reg> = HashMap::new();
+    let mut changed = false;
+    let mut work = vec![Visit::Enter(0)];
+    while let Some(visit) = work.pop() {
+        let idx = match visit {
+            Visit::Enter(idx) => idx,
+            Visit::Leave(keys) => {
+                for key in keys {
+                    available.remove(&key);
+                }
+                continue;
+            }
+        };
+
+        let mut added = Vec::new();
+        for inst in &mut function.blocks[idx].insts {
+            let (Some(key), Some((dst, ty))) = (key(inst), inst.def()) else {
+                continue;
+            };
+            match available.get(&key) {
+                Some(&prev) => {
+                    *inst = Inst::Copy { dst, ty: ty.clone(), src: Operand::Reg(prev) };
+                    changed = true;
+                },
+                None => {
+                    available.insert(key.clone(), dst);
+                    added.push(key);
+                }
+            }
+        }
+
+        work.push(Visit::Leave(added));
+        for &child in children[idx].iter().rev() {
+            work.push(Visit::Enter(child));
+        }
+    }
+    changed
+}
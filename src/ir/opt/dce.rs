@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::ir::{
+    cfg::{self, Cfg},
+    Function, Inst, Reg, Terminator, UnaryOp
+};
+
+/// Whether dropping `inst` when its result is unused changes nothing observable.
+/// Integer arithmetic can trap on overflow or division by zero, so it stays.
+fn removable(inst: &Inst) -> bool {
+    match inst {
+        Inst::Copy { .. } | Inst::Phi { .. } | Inst::Load { .. } | Inst::GetField { .. } | Inst::New { .. } | Inst::Dyn { .. } => true,
+        Inst::Binary { ty, op, .. } => op.is_comparison() || !ty.is_integer(),
+        Inst::Unary { ty, op, .. } => !(ty.is_integer() && *op == UnaryOp::Neg),
+        Inst::Call { .. } | Inst::CallDyn { .. } | Inst::Store { .. } | Inst::SetField { .. } => false
+    }
+}
+
+/// Removes unused instructions until none are left.
+fn remove_dead_insts(function: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let mut uses: HashMap<Reg, usize> = HashMap::new();
+        for block in &function.blocks {
+            for reg in block.insts.iter().flat_map(Inst::uses) {
+                *uses.entry(reg).or_default() += 1;
+            }
+            if let Some(reg) = block.term.operand().and_then(|operand| operand.reg()) {
+                *uses.entry(reg).or_default() += 1;
+            }
+        }
+
+        let mut removed = false;
+        for block in &mut function.blocks {
+            block.insts.retain(|inst| {
+                let dead = removable(inst) && inst.def().is_some_and(|(reg, _)| {
+                    // A phi that only feeds itself is as dead as one nothing reads.
+                    let own = inst.uses().iter().filter(|used| **used == reg).count();
+                    uses.get(&reg).copied().unwrap_or(0) == own
+                });
+                removed |= dead;
+                !dead
+            });
+        }
+        if !removed {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+/// Merges a block into the one before it when that is its only way in, so straight-line
+/// code left behind by folding and inlining ends up in one block.
+fn merge_blocks(function: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let cfg = Cfg::new(function);
+        let merge = function.blocks.iter().enumerate().find_map(|(idx, block)| {
+            let Terminator::Jump(target) = block.term else {
+                return None;
+            };
+            let target = *cfg.index.get(&target)?;
+            let single = cfg.preds[target].len() == 1 && target != 0 && target != idx;
+            single.then_some((idx, target))
+        });
+        let Some((idx, target)) = merge else {
+            return changed;
+        };
+
+        let mut absorbed = function.blocks.remove(target);
+        // With one predecessor, each phi has one input and is just a copy of it.
+        for inst in &mut absorbed.insts {
+            if let Inst::Phi { dst, ty, incoming } = inst {
+                if let Some((_, src)) = incoming.pop() {
+                    *inst = Inst::Copy { dst: *dst, ty: ty.clone(), src };
+                }
+            }
+        }
+        let idx = if target < idx { idx - 1 } else { idx };
+        let id = function.blocks[idx].id;
+        for succ in absorbed.term.successors() {
+            if let Some(block) = function.blocks.iter_mut().find(|block| block.id == succ) {
+                for inst in &mut block.insts {
+                    if let Inst::Phi { incoming, .. } = inst {
+                        for (pred, _) in incoming.iter_mut().filter(|(pred, _)| *pred == absorbed.id) {
+                            *pred = id;
+                        }
+                    }
+                }
+            }
+        }
+        let block = &mut function.blocks[idx];
+        block.insts.extend(absorbed.insts);
+        block.term = absorbed.term;
+        changed = true;
+    }
+}
+
+/// Removes unreachable blocks, unused side-effect-free instructions and jumps to a block
+/// with no other predecessor.
+pub(super) fn run(function: &mut Function) -> bool {
+    let unreachable = cfg::remove_unreachable(function);
+    let insts = remove_dead_insts(function);
+    let merged = merge_blocks(function);
+    unreachable | insts | merged
+}
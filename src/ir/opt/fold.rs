@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::{
+    common::syntax_tree::BinOp,
+    interpreter::value::{IntTy, Value},
+    ir::{cfg, BinaryOp, Const, Function, Inst, Operand, Reg, Terminator, Ty, UnaryOp}
+};
+
+/// The source operator with the same meaning, so folding shares the interpreter's
+/// arithmetic: wrapping is never silent, and an operation that would trap is left to
+/// trap at run time instead of being folded.
+fn source_op(op: BinaryOp) -> BinOp {
+    match op {
+        BinaryOp::Add => BinOp::Add,
+        BinaryOp::Sub => BinOp::Subtract,
+        BinaryOp::Mul => BinOp::Multiply,
+        BinaryOp::Div => BinOp::Divide,
+        BinaryOp::Rem => BinOp::Modulus,
+        BinaryOp::Eq => BinOp::Equals,
+        BinaryOp::Ne => BinOp::NotEquals,
+        BinaryOp::Lt => BinOp::LessThan,
+        BinaryOp::Le => BinOp::LessThanEq,
+        BinaryOp::Gt => BinOp::GreaterThan,
+        BinaryOp::Ge => BinOp::GreaterThanEq
+    }
+}
+
+fn value(constant: &Const, ty: &Ty) -> Option<Value> {
+    Some(match constant {
+        Const::Int(value) => Value::Int(*value, IntTy { bits: ty.bits()?, signed: ty.is_signed() }),
+        Const::Float(value) => Value::float(*value, ty.bits()?),
+        Const::Bool(value) => Value::Bool(*value),
+        Const::Str(value) => Value::Str(value.as_str().into())
+    })
+}
+
+fn constant(value: Value) -> Option<Const> {
+    Some(match value {
+        Value::Int(value, _) => Const::Int(value),
+        Value::Float(value, _) => Const::Float(value),
+        Value::Bool(value) => Const::Bool(value),
+        Value::Str(value) => Const::Str(value.to_string()),
+        Value::Void | Value::Instance(_) => return None
+    })
+}
+
+/// The type two constant operands of a comparison are read as. Comparisons don't
+/// check ranges, so any width of the right kind gives the same answer.
+fn comparison_type(lhs: &Const) -> Ty {
+    match lhs {
+        Const::Int(_) => Ty::I64,
+        Const::Float(_) => Ty::F64,
+        Const::Bool(_) => Ty::Bool,
+        Const::Str(_) => Ty::Str
+    }
+}
+
+fn fold_binary(ty: &Ty, op: BinaryOp, lhs: &Const, rhs: &Const) -> Option<Const> {
+    let operand_ty = if op.is_comparison() { comparison_type(lhs) } else { ty.clone() };
+    let (lhs, rhs) = (value(lhs, &operand_ty)?, value(rhs, &operand_ty)?);
+    constant(lhs.binary(source_op(op), &rhs).ok()?)
+}
+
+fn fold_unary(ty: &Ty, op: UnaryOp, src: &Const) -> Option<Const> {
+    let src = value(src, ty)?;
+    let result = match op {
+        UnaryOp::Neg => src.negate(),
+        UnaryOp::Not => src.not(),
+        UnaryOp::BitNot => src.bit_not()
+    };
+    constant(result.ok()?)
+}
+
+/// Integer identities that can't trap: `x + 0`, `x - 0`, `x * 1`, `x / 1` and `x * 0`.
+/// Float identities are left alone, since `-0.0 + 0.0` is `0.0`.
+fn simplify(ty: &Ty, op: BinaryOp, lhs: &Operand, rhs: &Operand) -> Option<Operand> {
+    if !ty.is_integer() {
+        return None;
+    }
+    let int = |operand: &Operand| match operand {
+        Operand::Const(Const::Int(value)) => Some(*value),
+        _ => None
+    };
+    match (op, int(lhs), int(rhs)) {
+        (BinaryOp::Add | BinaryOp::Sub, _, Some(0)) | (BinaryOp::Mul | BinaryOp::Div, _, Some(1)) => Some(lhs.clone()),
+        (BinaryOp::Add, Some(0), _) | (BinaryOp::Mul, Some(1), _) => Some(rhs.clone()),
+        (BinaryOp::Mul, Some(0), _) | (BinaryOp::Mul, _, Some(0)) => Some(Operand::Const(Const::Int(0))),
+        _ => None
+    }
+}
+
+/// Registers defined as a copy of a constant. In SSA form that is their only value.
+fn constants(function: &Function) -> HashMap<Reg, Const> {
+    function.blocks.iter()
+        .flat_map(|block| &block.insts)
+        .filter_map(|inst| match inst {
+            Inst::Copy { dst, src: Operand::Const(value), .. } => Some((*dst, value.clone())),
+            _ => None
+        })
+        .collect()
+}
+
+/// Folds constant operations into copies and constant branches into jumps, reading
+/// registers known to hold a constant as that constant.
+pub(super) fn run(function: &mut Function) -> bool {
+    let mut known = constants(function);
+    let lookup = |known: &HashMap<Reg, Const>, operand: &Operand| match operand {
+        Operand::Const(value) => Some(value.clone()),
+        Operand::Reg(reg) => known.get(reg).cloned()
+    };
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            let folded = match inst {
+                Inst::Binary { ty, op, lhs, rhs, .. } => match (lookup(&known, lhs), lookup(&known, rhs)) {
+                    (Some(lhs), Some(rhs)) => fold_binary(ty, *op, &lhs, &rhs).map(Operand::Const),
+                    _ => simplify(ty, *op, lhs, rhs)
+                },
+                Inst::Unary { ty, op, src, .. } => lookup(&known, src).and_then(|src| fold_unary(ty, *op, &src)).map(Operand::Const),
+                _ => None
+            };
+            if let (Some(src), Some((dst, ty))) = (folded, inst.def()) {
+                if let Operand::Const(value) = &src {
+                    known.insert(dst, value.clone());
+                }
+                *inst = Inst::Copy { dst, ty: ty.clone(), src };
+                changed = true;
+            }
+        }
+    }
+
+    let mut dropped = Vec::new();
+    for block in &mut function.blocks {
+        let Terminator::Branch { cond, then, els } = &block.term else {
+            continue;
+        };
+        if let Some(Const::Bool(cond)) = lookup(&known, cond) {
+            let (taken, skipped) = if cond { (*then, *els) } else { (*els, *then) };
+            block.term = Terminator::Jump(taken);
+            if taken != skipped {
+                dropped.push((block.id, skipped));
+            }
+            changed = true;
+        }
+    }
+    for (from, block) in dropped {
+        cfg::drop_edge(function, from, block);
+    }
+    changed
+}
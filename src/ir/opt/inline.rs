@@ -0,0 +1,157 @@
+use crate::ir::{cfg, Block, BlockId, Function, Inst, Module, Operand, Reg, Terminator};
+
+/// Inlining stops growing a function past this many instructions, which also bounds
+/// mutually recursive functions inlining into each other.
+const MAX_FUNCTION_SIZE: usize = 1000;
+
+fn calls_itself(function: &Function) -> bool {
+    function.blocks.iter()
+        .flat_map(|block| &block.insts)
+        .any(|inst| matches!(inst, Inst::Call { func, .. } if *func == function.name))
+}
+
+/// Whether `callee` is worth copying into `caller`. A callee whose entry block can be
+/// jumped back to would need phis for the new way in, so it is left alone.
+fn inlinable(caller: &Function, callee: &Function, limit: usize) -> bool {
+    callee.name != caller.name
+        && !callee.blocks.is_empty()
+        && callee.inst_count() <= limit
+        && caller.inst_count() + callee.inst_count() <= MAX_FUNCTION_SIZE
+        && !calls_itself(callee)
+        && !callee.blocks.iter().any(|block| block.term.successors().contains(&callee.blocks[0].id))
+}
+
+/// The position of the first call in `caller` to a function that can be inlined.
+fn find_call(module: &Module, caller: &Function, limit: usize) -> Option<(usize, usize, Function)> {
+    caller.blocks.iter().enumerate().find_map(|(idx, block)| {
+        block.insts.iter().enumerate().find_map(|(position, inst)| {
+            let Inst::Call { func, .. } = inst else {
+                return None;
+            };
+            let callee = module.function(func)?;
+            inlinable(caller, callee, limit).then(|| (idx, position, callee.clone()))
+        })
+    })
+}
+
+/// Gives the callee's registers and blocks numbers past the caller's own.
+struct Renumber {
+    regs: u32,
+    blocks: u32
+}
+
+impl Renumber {
+    fn reg(&self, reg: Reg) -> Reg {
+        Reg(reg.0 + self.regs)
+    }
+
+    fn block(&self, block: BlockId) -> BlockId {
+        BlockId(block.0 + self.blocks)
+    }
+
+    fn operand(&self, operand: &mut Operand) {
+        if let Operand::Reg(reg) = operand {
+            *reg = self.reg(*reg);
+        }
+    }
+}
+
+/// Replaces the call at `position` in block `idx` of `caller` with the body of `callee`.
+/// The rest of the block moves to a continuation block that each `ret` jumps to, and the
+/// call's result is a copy of the returned value, or a phi when there are several.
+fn inline_call(caller: &mut Function, idx: usize, position: usize, callee: Function) {
+    let renumber = Renumber { regs: caller.next_reg(), blocks: caller.next_block() };
+    let continuation = BlockId(renumber.blocks + callee.next_block());
+
+    let block = &mut caller.blocks[idx];
+    let tail = block.insts.split_off(position + 1);
+    let Some(Inst::Call { dst, args, .. }) = block.insts.pop() else {
+        unreachable!("`find_call` points at a call");
+    };
+    for ((param, ty), arg) in callee.params.iter().zip(args) {
+        block.insts.push(Inst::Copy { dst: renumber.reg(*param), ty: ty.clone(), src: arg });
+    }
+    let entry = renumber.block(callee.blocks[0].id);
+    let term = std::mem::replace(&mut block.term, Terminator::Jump(entry));
+    let id = block.id;
+
+    // The successors of the call's block are now reached from the continuation.
+    for succ in term.successors() {
+        for succ in caller.blocks.iter_mut().filter(|block| block.id == succ) {
+            for inst in &mut succ.insts {
+                if let Inst::Phi { incoming, .. } = inst {
+                    for (pred, _) in incoming.iter_mut().filter(|(pred, _)| *pred == id) {
+                        *pred = continuation;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut returns = Vec::new();
+    let mut body = callee.blocks;
+    for block in &mut body {
+        block.id = renumber.block(block.id);
+        for inst in &mut block.insts {
+            if let Some(dst) = inst.def_mut() {
+                *dst = renumber.reg(*dst);
+            }
+            for operand in inst.operands_mut() {
+                renumber.operand(operand);
+            }
+            if let Some(obj) = inst.obj_mut() {
+                *obj = renumber.reg(*obj);
+            }
+            if let Inst::Phi { incoming, .. } = inst {
+                for (pred, _) in incoming {
+                    *pred = renumber.block(*pred);
+                }
+            }
+        }
+        if let Terminator::Ret(value) = &mut block.term {
+            if let Some(value) = value {
+                renumber.operand(value);
+            }
+            returns.push((block.id, value.take()));
+            block.term = Terminator::Jump(continuation);
+        } else {
+            for target in block.term.successors_mut() {
+                *target = renumber.block(*target);
+            }
+            if let Some(operand) = block.term.operand_mut() {
+                renumber.operand(operand);
+            }
+        }
+    }
+
+    let mut insts = Vec::new();
+    if let Some((dst, ty)) = dst {
+        let mut incoming: Vec<(BlockId, Operand)> = returns.into_iter()
+            .filter_map(|(block, value)| Some((block, value?)))
+            .collect();
+        match incoming.len() {
+            0 => {},
+            1 => insts.push(Inst::Copy { dst, ty, src: incoming.remove(0).1 }),
+            _ => insts.push(Inst::Phi { dst, ty, incoming })
+        }
+    }
+    insts.extend(tail);
+
+    let at = idx + 1;
+    caller.blocks.splice(at..at, body.into_iter().chain([Block { id: continuation, insts, term }]));
+    // A callee that never returns leaves the continuation unreachable.
+    cfg::remove_unreachable(caller);
+}
+
+/// Inlines calls to functions of at most `limit` instructions, including calls that
+/// inlining itself brings in.
+pub(super) fn run(module: &mut Module, limit: usize) -> bool {
+    let mut changed = false;
+    for idx in 0..module.functions.len() {
+        while let Some((block, position, callee)) = find_call(module, &module.functions[idx], limit) {
+            inline_call(&mut module.functions[idx], block, position, callee);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,262 @@
+mod copy_prop;
+mod cse;
+mod dce;
+mod fold;
+mod inline;
+
+use super::{
+    ssa,
+    verify::{verify, VerifyError},
+    Module
+};
+
+/// An optimization that can be switched on or off on its own with `--passes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pass {
+    /// Replaces calls to small functions with their bodies.
+    Inline,
+    /// Evaluates operations on constants and branches on constant conditions.
+    Fold,
+    /// Reads through copies and phis that only forward one value.
+    CopyProp,
+    /// Reuses an earlier identical computation that dominates a later one.
+    Cse,
+    /// Removes unused side-effect-free instructions and unreachable blocks.
+    Dce
+}
+
+impl Pass {
+    /// The order passes run in within a round.
+    pub const ALL: [Pass; 5] = [Self::Inline, Self::Fold, Self::CopyProp, Self::Cse, Self::Dce];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Inline => "inline",
+            Self::Fold => "fold",
+            Self::CopyProp => "copy-prop",
+            Self::Cse => "cse",
+            Self::Dce => "dce"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+}
+
+/// Which passes run, and how hard they try, for an `-O` level.
+#[derive(Clone, Debug)]
+pub(crate) struct Pipeline {
+    passes: Vec<Pass>,
+    /// Callees with at most this many instructions are inlined.
+    inline_limit: usize,
+    /// Rounds of the whole pipeline; stops early once a round changes nothing.
+    rounds: usize
+}
+
+impl Pipeline {
+
+    pub fn for_level(level: u8) -> Self {
+        let (passes, inline_limit, rounds) = match level {
+            0 => (Vec::new(), 16, 1),
+            1 => (vec![Pass::Fold, Pass::CopyProp, Pass::Dce], 16, 1),
+            2 => (Pass::ALL.to_vec(), 16, 4),
+            _ => (Pass::ALL.to_vec(), 48, 8)
+        };
+        Self { passes, inline_limit, rounds }
+    }
+
+    pub fn set(&mut self, pass: Pass, enabled: bool) {
+        self.passes.retain(|existing| *existing != pass);
+        if enabled {
+            self.passes.push(pass);
+            self.passes.sort_by_key(|pass| Pass::ALL.iter().position(|other| other == pass));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Converts `module` to SSA form and runs the passes over it, verifying after each
+    /// one so a broken pass is named rather than surfacing later. `trace` hears which
+    /// passes changed something.
+    pub fn run(&self, module: &mut Module, mut trace: impl FnMut(&str)) -> Result<(), (&'static str, Vec<VerifyError>)> {
+        if self.passes.is_empty() {
+            return Ok(());
+        }
+        for function in &mut module.functions {
+            ssa::construct(function);
+        }
+        verify(module).map_err(|errors| ("ssa", errors))?;
+
+        for round in 1..=self.rounds {
+            let mut changed = false;
+            for pass in &self.passes {
+                let pass_changed = match pass {
+                    Pass::Inline => inline::run(module, self.inline_limit),
+                    Pass::Fold => module.functions.iter_mut().fold(false, |changed, function| fold::run(function) | changed),
+                    Pass::CopyProp => module.functions.iter_mut().fold(false, |changed, function| copy_prop::run(function) | changed),
+                    Pass::Cse => module.functions.iter_mut().fold(false, |changed, function| cse::run(function) | changed),
+                    Pass::Dce => module.functions.iter_mut().fold(false, |changed, function| dce::run(function) | changed)
+                };
+                if pass_changed {
+                    trace(&format!("round {}: `{}` changed the module", round, pass.name()));
+                    verify(module).map_err(|errors| (pass.name(), errors))?;
+                }
+                changed |= pass_changed;
+            }
+            if !changed {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parse::parse_module;
+
+    /// The function `@f` of `src` after `pipeline` ran over it.
+    fn optimized(src: &str, pipeline: &Pipeline) -> String {
+        let mut module = parse_module(src).expect("valid IR");
+        pipeline.run(&mut module, |_| {}).unwrap_or_else(|(pass, _)| panic!("`{}` broke the module", pass));
+        module.function("f").expect("`@f` is kept").to_string()
+    }
+
+    /// The function `@f` of `src` after a single round of `pass` alone.
+    fn after(src: &str, pass: Pass) -> String {
+        let mut pipeline = Pipeline::for_level(0);
+        pipeline.set(pass, true);
+        optimized(src, &pipeline)
+    }
+
+    const REDUNDANT: &str = "
+fn @f(%0: i32) -> i32 {
+bb0:
+    %1: i32 = mul 2, 3
+    %2: i32 = copy %1
+    %3: i32 = add %0, %2
+    %4: i32 = add %0, %2
+    %5: i32 = mul %0, 1
+    br true, bb1, bb2
+bb1:
+    %6: i32 = add %3, %4
+    ret %6
+bb2:
+    ret %5
+}
+";
+
+    #[test]
+    fn fold_evaluates_constants_and_identities() {
+        assert_eq!(after(REDUNDANT, Pass::Fold), "\
+fn @f(%0: i32) -> i32 {
+bb0:
+    %1: i32 = copy 6
+    %2: i32 = copy %1
+    %3: i32 = add %0, %2
+    %4: i32 = add %0, %2
+    %5: i32 = copy %0
+    jmp bb1
+bb1:
+    %6: i32 = add %3, %4
+    ret %6
+bb2:
+    ret %5
+}
+");
+    }
+
+    #[test]
+    fn fold_leaves_operations_that_trap() {
+        let src = "
+fn @f() -> i32 {
+bb0:
+    %0: i32 = add 2147483647, 1
+    %1: i32 = div 1, 0
+    %2: i32 = add %0, %1
+    ret %2
+}
+";
+        assert_eq!(after(src, Pass::Fold), parse_module(src).unwrap().function("f").unwrap().to_string());
+    }
+
+    #[test]
+    fn copy_prop_reads_through_copies() {
+        let optimized = after(REDUNDANT, Pass::CopyProp);
+        assert!(optimized.contains("%3: i32 = add %0, %1\n    %4: i32 = add %0, %1\n"), "{}", optimized);
+    }
+
+    #[test]
+    fn cse_reuses_the_dominating_computation() {
+        let optimized = after(REDUNDANT, Pass::Cse);
+        assert!(optimized.contains("%3: i32 = add %0, %2\n    %4: i32 = copy %3\n"), "{}", optimized);
+    }
+
+    #[test]
+    fn dce_removes_unused_and_unreachable_code() {
+        let src = "
+fn @f(%0: i32) -> i32 {
+bb0:
+    %1: i32 = copy %0
+    %2: i32 = add %0, 1
+    ret %0
+bb1:
+    ret 1
+}
+";
+        // The unused `add` stays, since it can overflow.
+        assert_eq!(after(src, Pass::Dce), "\
+fn @f(%0: i32) -> i32 {
+bb0:
+    %2: i32 = add %0, 1
+    ret %0
+}
+");
+    }
+
+    #[test]
+    fn inline_replaces_calls_to_small_functions() {
+        let src = "
+fn @double(%0: i32) -> i32 {
+bb0:
+    %1: i32 = add %0, %0
+    ret %1
+}
+
+fn @f(%0: i32) -> i32 {
+bb0:
+    %1: i32 = call @double(%0)
+    ret %1
+}
+";
+        assert_eq!(after(src, Pass::Inline), "\
+fn @f(%0: i32) -> i32 {
+bb0:
+    %2: i32 = copy %0
+    jmp bb1
+bb1:
+    %3: i32 = add %2, %2
+    jmp bb2
+bb2:
+    %1: i32 = copy %3
+    ret %1
+}
+");
+    }
+
+    #[test]
+    fn passes_together_reach_a_fixpoint() {
+        assert_eq!(optimized(REDUNDANT, &Pipeline::for_level(2)), "\
+fn @f(%0: i32) -> i32 {
+bb0:
+    %3: i32 = add %0, 6
+    %6: i32 = add %3, %3
+    ret %6
+}
+");
+    }
+}
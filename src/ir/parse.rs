@@ -0,0 +1,672 @@
+use crate::common::{
+    diagnostics::{codes, Diagnostic},
+    lexer::Span
+};
+
+use super::{
+    BinaryOp, Block, BlockId, Const, Extern, Function, Global, Inst, Module, Operand, Reg,
+    Slot, StructDef, Terminator, TraitDef, Ty, UnaryOp, VTable
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    /// `@name`; names may contain `::` and `.`.
+    Global(String),
+    /// `%3`
+    Reg(u32),
+    Word(String),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+    End
+}
+
+impl Tok {
+    fn describe(&self) -> String {
+        match self {
+            Self::Global(name) => format!("`@{}`", name),
+            Self::Reg(reg) => format!("`%{}`", reg),
+            Self::Word(word) => format!("`{}`", word),
+            Self::Int(value) => format!("`{}`", value),
+            Self::Float(value) => format!("`{:?}`", value),
+            Self::Str(value) => format!("{:?}", value),
+            Self::Punct(punct) => format!("`{}`", punct),
+            Self::End => "end of input".into()
+        }
+    }
+}
+
+const PUNCTS: [&str; 12] = ["...", "->", "(", ")", "{", "}", "[", "]", ",", ":", "=", "-"];
+
+fn error(span: Span, message: impl Into<String>) -> Diagnostic {
+    Diagnostic::error(message)
+        .with_code(codes::MALFORMED_IR)
+        .with_primary(span, "")
+}
+
+/// Splits IR text into tokens; `;` starts a comment that runs to the end of the line.
+fn tokenize(src: &str) -> Result<Vec<(Tok, Span)>, Diagnostic> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let (mut pos, mut line, mut line_start) = (0, 1, 0);
+
+    let is_name = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'.';
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let start = pos;
+        let span = |end: usize| Span::new(start, end, line, start - line_start + 1);
+
+        if c == b'\n' {
+            pos += 1;
+            line += 1;
+            line_start = pos;
+            continue;
+        }
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if c == b';' {
+            while pos < bytes.len() && bytes[pos] != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+
+        let tok = match c {
+            b'@' => {
+                pos += 1;
+                while pos < bytes.len() && (is_name(bytes[pos]) || bytes[pos..].starts_with(b"::")) {
+                    pos += if bytes[pos] == b':' { 2 } else { 1 };
+                }
+                if pos == start + 1 {
+                    return Err(error(span(pos), "expected a name after `@`"));
+                }
+                Tok::Global(src[start + 1..pos].to_string())
+            },
+            b'%' => {
+                pos += 1;
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+                let number = src[start + 1..pos].parse().map_err(|_| error(span(pos), "expected a register number after `%`"))?;
+                Tok::Reg(number)
+            },
+            b'"' => {
+                pos += 1;
+                let mut value = String::new();
+                loop {
+                    let Some(ch) = src[pos..].chars().next() else {
+                        return Err(error(span(pos), "unterminated string"));
+                    };
+                    pos += ch.len_utf8();
+                    match ch {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = src[pos..].chars().next().ok_or_else(|| error(span(pos), "unterminated string"))?;
+                            pos += escaped.len_utf8();
+                            value.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                'r' => '\r',
+                                '0' => '\0',
+                                '\\' | '"' | '\'' => escaped,
+                                'u' => {
+                                    let close = src[pos..].find('}').ok_or_else(|| error(span(pos), "unterminated escape"))?;
+                                    let code = u32::from_str_radix(&src[pos + 1..pos + close], 16).ok().and_then(char::from_u32);
+                                    pos += close + 1;
+                                    code.ok_or_else(|| error(span(pos), "invalid unicode escape"))?
+                                },
+                                other => return Err(error(span(pos), format!("unknown escape `\\{}`", other)))
+                            });
+                        },
+                        ch => value.push(ch)
+                    }
+                }
+                Tok::Str(value)
+            },
+            b'0'..=b'9' | b'-' if c != b'-' || bytes.get(pos + 1).is_some_and(u8::is_ascii_digit) => {
+                pos += 1;
+                let mut float = false;
+                while pos < bytes.len() {
+                    match bytes[pos] {
+                        b'0'..=b'9' => {},
+                        b'.' | b'e' | b'E' => float = true,
+                        b'+' | b'-' if matches!(bytes[pos - 1], b'e' | b'E') => {},
+                        _ => break
+                    }
+                    pos += 1;
+                }
+                let text = &src[start..pos];
+                if float {
+                    Tok::Float(text.parse().map_err(|_| error(span(pos), format!("invalid number `{}`", text)))?)
+                } else {
+                    Tok::Int(text.parse().map_err(|_| error(span(pos), format!("invalid number `{}`", text)))?)
+                }
+            },
+            // Instances of generic items are named after their type arguments, like `Box.i32`.
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_' || bytes[pos] == b'.') {
+                    pos += 1;
+                }
+                Tok::Word(src[start..pos].to_string())
+            },
+            _ => match PUNCTS.iter().find(|punct| src[pos..].starts_with(**punct)) {
+                Some(punct) => {
+                    pos += punct.len();
+                    Tok::Punct(punct)
+                },
+                None => {
+                    let ch = src[pos..].chars().next().unwrap_or_default();
+                    return Err(error(span(pos + ch.len_utf8()), format!("unexpected character `{}`", ch)));
+                }
+            }
+        };
+        tokens.push((tok, span(pos)));
+    }
+
+    tokens.push((Tok::End, Span::new(pos, pos, line, pos - line_start + 1)));
+    Ok(tokens)
+}
+
+/// Reads the textual form that `Module`'s `Display` writes.
+struct IrParser {
+    tokens: Vec<(Tok, Span)>,
+    pos: usize
+}
+
+impl IrParser {
+
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> Tok {
+        let tok = self.tokens[self.pos].0.clone();
+        if tok != Tok::End {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        error(self.span(), format!("expected {}, found {}", expected, self.peek().describe()))
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Tok::Punct(found) if *found == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), Diagnostic> {
+        if self.eat(punct) { Ok(()) } else { Err(self.unexpected(&format!("`{}`", punct))) }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Tok::Word(found) if found == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, Diagnostic> {
+        match self.peek() {
+            Tok::Word(word) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            },
+            _ => Err(self.unexpected(what))
+        }
+    }
+
+    fn global(&mut self) -> Result<String, Diagnostic> {
+        match self.peek() {
+            Tok::Global(name) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            },
+            _ => Err(self.unexpected("an `@` name"))
+        }
+    }
+
+    fn reg(&mut self) -> Result<Reg, Diagnostic> {
+        match self.peek() {
+            Tok::Reg(reg) => {
+                let reg = Reg(*reg);
+                self.pos += 1;
+                Ok(reg)
+            },
+            _ => Err(self.unexpected("a register"))
+        }
+    }
+
+    fn ty(&mut self) -> Result<Ty, Diagnostic> {
+        if self.eat_word("dyn") {
+            return Ok(Ty::Dyn(self.word("a trait name")?));
+        }
+        let name = self.word("a type")?;
+        Ok(Ty::from_name(&name).unwrap_or(Ty::Struct(name)))
+    }
+
+    fn label(&mut self) -> Result<BlockId, Diagnostic> {
+        let span = self.span();
+        let word = self.word("a block label")?;
+        word.strip_prefix("bb")
+            .and_then(|number| number.parse().ok())
+            .map(BlockId)
+            .ok_or_else(|| error(span, format!("`{}` is not a block label", word)))
+    }
+
+    fn at_operand(&self) -> bool {
+        match self.peek() {
+            Tok::Reg(_) | Tok::Int(_) | Tok::Float(_) | Tok::Str(_) => true,
+            Tok::Word(word) => matches!(word.as_str(), "true" | "false" | "nan" | "inf"),
+            Tok::Punct("-") => true,
+            _ => false
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, Diagnostic> {
+        if !self.at_operand() {
+            return Err(self.unexpected("an operand"));
+        }
+        Ok(match self.next() {
+            Tok::Reg(reg) => Operand::Reg(Reg(reg)),
+            Tok::Int(value) => Operand::Const(Const::Int(value)),
+            Tok::Float(value) => Operand::Const(Const::Float(value)),
+            Tok::Str(value) => Operand::Const(Const::Str(value)),
+            Tok::Word(word) => Operand::Const(match word.as_str() {
+                "true" => Const::Bool(true),
+                "false" => Const::Bool(false),
+                "nan" => Const::Float(f64::NAN),
+                _ => Const::Float(f64::INFINITY)
+            }),
+            _ => {
+                // Only `-inf` starts with a bare `-`.
+                if !self.eat_word("inf") {
+                    return Err(self.unexpected("`inf`"));
+                }
+                Operand::Const(Const::Float(f64::NEG_INFINITY))
+            }
+        })
+    }
+
+    fn constant(&mut self) -> Result<Const, Diagnostic> {
+        let span = self.span();
+        match self.operand()? {
+            Operand::Const(value) => Ok(value),
+            Operand::Reg(_) => Err(error(span, "expected a constant"))
+        }
+    }
+
+    /// A parenthesized, comma-separated list.
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, Diagnostic>) -> Result<Vec<T>, Diagnostic> {
+        self.expect("(")?;
+        let mut items = Vec::new();
+        if !self.eat(")") {
+            loop {
+                items.push(item(self)?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        Ok(items)
+    }
+
+    fn module(&mut self) -> Result<Module, Diagnostic> {
+        let mut module = Module::default();
+        loop {
+            match self.peek() {
+                Tok::End => return Ok(module),
+                Tok::Word(word) if word == "struct" => {
+                    self.pos += 1;
+                    module.structs.push(self.structure()?);
+                },
+                Tok::Word(word) if word == "trait" => {
+                    self.pos += 1;
+                    module.traits.push(self.trait_def()?);
+                },
+                Tok::Word(word) if word == "vtable" => {
+                    self.pos += 1;
+                    module.vtables.push(self.vtable()?);
+                },
+                Tok::Word(word) if word == "extern" => {
+                    self.pos += 1;
+                    module.externs.push(self.external()?);
+                },
+                Tok::Word(word) if word == "global" => {
+                    self.pos += 1;
+                    let name = self.global()?;
+                    self.expect(":")?;
+                    let ty = self.ty()?;
+                    let init = if self.eat("=") { Some(self.constant()?) } else { None };
+                    module.globals.push(Global { name, ty, init });
+                },
+                Tok::Word(word) if word == "fn" => {
+                    self.pos += 1;
+                    module.functions.push(self.function()?);
+                },
+                _ => return Err(self.unexpected("`struct`, `trait`, `vtable`, `extern`, `global` or `fn`"))
+            }
+        }
+    }
+
+    fn structure(&mut self) -> Result<StructDef, Diagnostic> {
+        let name = self.word("a struct name")?;
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        while !self.eat("}") {
+            if !fields.is_empty() {
+                self.expect(",")?;
+            }
+            let field = self.word("a field name")?;
+            self.expect(":")?;
+            fields.push((field, self.ty()?));
+        }
+        Ok(StructDef { name, fields })
+    }
+
+    fn trait_def(&mut self) -> Result<TraitDef, Diagnostic> {
+        let name = self.word("a trait name")?;
+        self.expect("{")?;
+        let mut slots = Vec::new();
+        while !self.eat("}") {
+            if !slots.is_empty() {
+                self.expect(",")?;
+            }
+            let method = self.global()?;
+            let params = self.list(Self::ty)?;
+            self.expect("->")?;
+            slots.push(Slot { method, params, ret: self.ty()? });
+        }
+        Ok(TraitDef { name, slots })
+    }
+
+    fn vtable(&mut self) -> Result<VTable, Diagnostic> {
+        let obj = self.word("a struct name")?;
+        if !self.eat_word("as") {
+            return Err(self.unexpected("`as`"));
+        }
+        let with = self.word("a trait name")?;
+        self.expect("{")?;
+        let mut functions = Vec::new();
+        while !self.eat("}") {
+            if !functions.is_empty() {
+                self.expect(",")?;
+            }
+            functions.push(self.global()?);
+        }
+        Ok(VTable { obj, with, functions })
+    }
+
+    fn external(&mut self) -> Result<Extern, Diagnostic> {
+        let name = self.global()?;
+        let mut variadic = false;
+        let params = self.list(|parser| {
+            if variadic {
+                return Err(parser.unexpected("`)` after `...`"));
+            }
+            if parser.eat("...") {
+                variadic = true;
+                return Ok(None);
+            }
+            parser.ty().map(Some)
+        })?;
+        self.expect("->")?;
+        let ret = self.ty()?;
+        Ok(Extern { name, params: params.into_iter().flatten().collect(), variadic, ret })
+    }
+
+    fn function(&mut self) -> Result<Function, Diagnostic> {
+        let name = self.global()?;
+        let params = self.list(|parser| {
+            let reg = parser.reg()?;
+            parser.expect(":")?;
+            Ok((reg, parser.ty()?))
+        })?;
+        self.expect("->")?;
+        let ret = self.ty()?;
+        self.expect("{")?;
+
+        let mut blocks = Vec::new();
+        while !self.eat("}") {
+            let id = self.label()?;
+            self.expect(":")?;
+            let mut insts = Vec::new();
+            let term = loop {
+                if let Some(term) = self.terminator()? {
+                    break term;
+                }
+                insts.push(self.inst()?);
+            };
+            blocks.push(Block { id, insts, term });
+        }
+        Ok(Function { name, params, ret, blocks })
+    }
+
+    fn terminator(&mut self) -> Result<Option<Terminator>, Diagnostic> {
+        let Tok::Word(word) = self.peek() else {
+            return Ok(None);
+        };
+        Ok(Some(match word.as_str() {
+            "ret" => {
+                self.pos += 1;
+                Terminator::Ret(if self.at_operand() { Some(self.operand()?) } else { None })
+            },
+            "jmp" => {
+                self.pos += 1;
+                Terminator::Jump(self.label()?)
+            },
+            "br" => {
+                self.pos += 1;
+                let cond = self.operand()?;
+                self.expect(",")?;
+                let then = self.label()?;
+                self.expect(",")?;
+                let els = self.label()?;
+                Terminator::Branch { cond, then, els }
+            },
+            "unreachable" => {
+                self.pos += 1;
+                Terminator::Unreachable
+            },
+            _ => return Ok(None)
+        }))
+    }
+
+    fn inst(&mut self) -> Result<Inst, Diagnostic> {
+        match self.peek().clone() {
+            Tok::Reg(reg) => {
+                self.pos += 1;
+                self.expect(":")?;
+                let ty = self.ty()?;
+                self.expect("=")?;
+                self.definition(Reg(reg), ty)
+            },
+            Tok::Word(word) if word == "call" => {
+                self.pos += 1;
+                let func = self.global()?;
+                let args = self.list(Self::operand)?;
+                Ok(Inst::Call { dst: None, func, args })
+            },
+            Tok::Word(word) if word == "calldyn" => {
+                self.pos += 1;
+                let (recv, method, args) = self.call_dyn()?;
+                Ok(Inst::CallDyn { dst: None, recv, method, args })
+            },
+            Tok::Word(word) if word == "store" => {
+                self.pos += 1;
+                let global = self.global()?;
+                self.expect(",")?;
+                Ok(Inst::Store { global, src: self.operand()? })
+            },
+            Tok::Word(word) if word == "setfield" => {
+                self.pos += 1;
+                let obj = self.reg()?;
+                self.expect(",")?;
+                let field = self.word("a field name")?;
+                self.expect(",")?;
+                Ok(Inst::SetField { obj, field, src: self.operand()? })
+            },
+            _ => Err(self.unexpected("an instruction or terminator"))
+        }
+    }
+
+    /// The right-hand side of `%d: ty = ...`.
+    fn definition(&mut self, dst: Reg, ty: Ty) -> Result<Inst, Diagnostic> {
+        let span = self.span();
+        let opcode = self.word("an opcode")?;
+        if let Some(op) = BinaryOp::ALL.into_iter().find(|op| op.as_str() == opcode) {
+            let lhs = self.operand()?;
+            self.expect(",")?;
+            return Ok(Inst::Binary { dst, ty, op, lhs, rhs: self.operand()? });
+        }
+        if let Some(op) = UnaryOp::ALL.into_iter().find(|op| op.as_str() == opcode) {
+            return Ok(Inst::Unary { dst, ty, op, src: self.operand()? });
+        }
+        Ok(match opcode.as_str() {
+            "copy" => Inst::Copy { dst, ty, src: self.operand()? },
+            "call" => {
+                let func = self.global()?;
+                let args = self.list(Self::operand)?;
+                Inst::Call { dst: Some((dst, ty)), func, args }
+            },
+            "calldyn" => {
+                let (recv, method, args) = self.call_dyn()?;
+                Inst::CallDyn { dst: Some((dst, ty)), recv, method, args }
+            },
+            "dyn" => {
+                let concrete = self.word("a struct name")?;
+                Inst::Dyn { dst, ty, concrete, src: self.reg()? }
+            },
+            "load" => Inst::Load { dst, ty, global: self.global()? },
+            "new" => {
+                let name = self.ty()?;
+                if name != ty {
+                    return Err(error(span, format!("`new {}` defines a register of type `{}`", name, ty)));
+                }
+                Inst::New { dst, ty, args: self.list(Self::operand)? }
+            },
+            "phi" => {
+                let mut incoming = Vec::new();
+                loop {
+                    self.expect("[")?;
+                    let block = self.label()?;
+                    self.expect(":")?;
+                    incoming.push((block, self.operand()?));
+                    self.expect("]")?;
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                Inst::Phi { dst, ty, incoming }
+            },
+            "getfield" => {
+                let obj = self.reg()?;
+                self.expect(",")?;
+                Inst::GetField { dst, ty, obj, field: self.word("a field name")? }
+            },
+            other => return Err(error(span, format!("unknown opcode `{}`", other)))
+        })
+    }
+
+    /// The operands of `calldyn %t, @Trait::method(args)`.
+    fn call_dyn(&mut self) -> Result<(Reg, String, Vec<Operand>), Diagnostic> {
+        let recv = self.reg()?;
+        self.expect(",")?;
+        let method = self.global()?;
+        Ok((recv, method, self.list(Self::operand)?))
+    }
+}
+
+/// Parses a module from its textual form. The result still needs `verify` before use.
+pub(crate) fn parse_module(src: &str) -> Result<Module, Diagnostic> {
+    let mut parser = IrParser { tokens: tokenize(src)?, pos: 0 };
+    parser.module()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Structs, traits, vtables, globals, phis and dynamic calls, as the printer writes them.
+    const MODULE: &str = "\
+struct Sq { s: i32 }
+
+trait Shape { @Shape::area() -> i32 }
+
+vtable Sq as Shape { @Sq::Shape::area }
+
+extern @println(...) -> void
+
+global @total: i32 = 5
+
+fn @Sq::Shape::area(%0: Sq) -> i32 {
+bb0:
+    %1: i32 = getfield %0, s
+    %2: i32 = mul %1, %1
+    ret %2
+}
+
+fn @pick(%0: bool) -> i32 {
+bb0:
+    br %0, bb1, bb3
+bb1:
+    jmp bb2
+bb2:
+    %5: i32 = phi [bb3: 2], [bb1: 1]
+    ret %5
+bb3:
+    jmp bb2
+}
+
+fn @main() -> void {
+bb0:
+    %0: Sq = new Sq(4)
+    %1: dyn Shape = dyn Sq %0
+    %2: i32 = calldyn %1, @Shape::area()
+    %3: i32 = load @total
+    %4: i32 = add %2, %3
+    store @total, %4
+    %5: f64 = neg 1.5
+    call @println(%4, %5)
+    ret
+}
+";
+
+    #[test]
+    fn printing_and_parsing_round_trip() {
+        let printed = parse_module(MODULE).expect("valid IR").to_string();
+        assert_eq!(printed, MODULE);
+        assert_eq!(parse_module(&printed).unwrap().to_string(), printed);
+    }
+
+    #[test]
+    fn phis_keep_their_inputs() {
+        let module = parse_module(MODULE).unwrap();
+        let pick = module.function("pick").unwrap();
+        assert!(matches!(&pick.blocks[2].insts[0], Inst::Phi { incoming, .. } if incoming.len() == 2));
+    }
+
+    #[test]
+    fn malformed_text_is_reported() {
+        let message = |src: &str| parse_module(src).unwrap_err().message;
+        assert_eq!(message("fn @f() -> void {\nbb0:\n    %0: i32 = frob 1, 2\n    ret\n}\n"), "unknown opcode `frob`");
+        assert!(parse_module("fn @f() -> void {\nbb0:\n    ret\n").is_err());
+    }
+}
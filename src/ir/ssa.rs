@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    cfg::{self, Cfg},
+    Block, BlockId, Function, Inst, Operand, Reg, Terminator, Ty
+};
+
+/// Rewrites `function` so every register has exactly one definition, with phis where
+/// different definitions of a register meet (Cytron et al.).
+///
+/// Phis are only placed where the register is live, so with every read preceded by a
+/// write on all paths, which the verifier guarantees, each phi has a value for every
+/// predecessor. Unreachable blocks are dropped first, since they have no dominators.
+pub(crate) fn construct(function: &mut Function) {
+    cfg::remove_unreachable(function);
+    let cfg = Cfg::new(function);
+    let count = function.blocks.len();
+
+    let mut def_blocks: HashMap<Reg, Vec<usize>> = HashMap::new();
+    let mut def_count: HashMap<Reg, usize> = HashMap::new();
+    let mut types: HashMap<Reg, Ty> = HashMap::new();
+    for (reg, ty) in &function.params {
+        def_blocks.entry(*reg).or_default().push(0);
+        *def_count.entry(*reg).or_default() += 1;
+        types.insert(*reg, ty.clone());
+    }
+    for (idx, block) in function.blocks.iter().enumerate() {
+        for (reg, ty) in block.insts.iter().filter_map(Inst::def) {
+            def_blocks.entry(reg).or_default().push(idx);
+            *def_count.entry(reg).or_default() += 1;
+            types.insert(reg, ty.clone());
+        }
+    }
+    let vars: HashSet<Reg> = def_count.iter().filter(|(_, count)| **count > 1).map(|(reg, _)| *reg).collect();
+    if vars.is_empty() {
+        return;
+    }
+
+    let live_in = liveness(function, &cfg, &vars);
+    let frontiers = cfg.frontiers();
+
+    // Phi placement: the iterated dominance frontier of each variable's definitions.
+    let mut phis: Vec<Vec<Reg>> = vec![Vec::new(); count];
+    let mut ordered: Vec<&Reg> = vars.iter().collect();
+    ordered.sort();
+    for &var in ordered {
+        let mut work: Vec<usize> = def_blocks[&var].clone();
+        let mut queued: HashSet<usize> = work.iter().copied().collect();
+        let mut placed = HashSet::new();
+        while let Some(block) = work.pop() {
+            for &frontier in &frontiers[block] {
+                if !live_in[frontier].contains(&var) || !placed.insert(frontier) {
+                    continue;
+                }
+                phis[frontier].push(var);
+                if queued.insert(frontier) {
+                    work.push(frontier);
+                }
+            }
+        }
+    }
+    for (block, vars) in function.blocks.iter_mut().zip(&phis) {
+        let new_phis = vars.iter().map(|var| Inst::Phi { dst: *var, ty: types[var].clone(), incoming: Vec::new() });
+        block.insts.splice(0..0, new_phis);
+    }
+
+    rename(function, &cfg, &vars, &phis);
+}
+
+/// Which of `vars` are live on entry to each block.
+fn liveness(function: &Function, cfg: &Cfg, vars: &HashSet<Reg>) -> Vec<HashSet<Reg>> {
+    let count = function.blocks.len();
+    let mut uses = vec![HashSet::new(); count];
+    let mut defs = vec![HashSet::new(); count];
+    for (idx, block) in function.blocks.iter().enumerate() {
+        let term = block.term.operand().and_then(Operand::reg);
+        for inst in &block.insts {
+            for reg in inst.uses() {
+                if vars.contains(&reg) && !defs[idx].contains(&reg) {
+                    uses[idx].insert(reg);
+                }
+            }
+            if let Some((reg, _)) = inst.def() {
+                if vars.contains(&reg) {
+                    defs[idx].insert(reg);
+                }
+            }
+        }
+        if let Some(reg) = term {
+            if vars.contains(&reg) && !defs[idx].contains(&reg) {
+                uses[idx].insert(reg);
+            }
+        }
+    }
+
+    let mut live_in = uses.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &idx in cfg.rpo.iter().rev() {
+            let mut live: HashSet<Reg> = cfg.succs[idx].iter().flat_map(|&succ| live_in[succ].iter().copied()).collect();
+            live.retain(|reg| !defs[idx].contains(reg));
+            live.extend(uses[idx].iter().copied());
+            if live != live_in[idx] {
+                live_in[idx] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+enum Visit {
+    Enter(usize),
+    /// Leaving a block pops the names it pushed.
+    Leave(Vec<Reg>)
+}
+
+/// Gives every definition of a variable a fresh register, walking the dominator tree so
+/// each read sees the nearest dominating definition.
+fn rename(function: &mut Function, cfg: &Cfg, vars: &HashSet<Reg>, phis: &[Vec<Reg>]) {
+    let mut next = function.next_reg();
+    let mut stacks: HashMap<Reg, Vec<Reg>> = HashMap::new();
+    // Parameters keep their registers; they are the first definition.
+    for (reg, _) in &function.params {
+        if vars.contains(reg) {
+            stacks.entry(*reg).or_default().push(*reg);
+        }
+    }
+
+    let children = cfg.dom_children();
+    let mut work = vec![Visit::Enter(0)];
+    while let Some(visit) = work.pop() {
+        let idx = match visit {
+            Visit::Enter(idx) => idx,
+            Visit::Leave(pushed) => {
+                for var in pushed {
+                    stacks.get_mut(&var).and_then(Vec::pop);
+                }
+                continue;
+            }
+        };
+
+        let mut pushed = Vec::new();
+        let block = &mut function.blocks[idx];
+        for (position, inst) in block.insts.iter_mut().enumerate() {
+            if position >= phis[idx].len() {
+                for operand in inst.operands_mut() {
+                    if let Operand::Reg(reg) = operand {
+                        *reg = current(&stacks, *reg);
+                    }
+                }
+                if let Some(obj) = inst.obj_mut() {
+                    *obj = current(&stacks, *obj);
+                }
+            }
+            if let Some(dst) = inst.def_mut() {
+                if vars.contains(dst) {
+                    let var = *dst;
+                    *dst = Reg(next);
+                    next += 1;
+                    stacks.entry(var).or_default().push(*dst);
+                    pushed.push(var);
+                }
+            }
+        }
+        if let Some(Operand::Reg(reg)) = block.term.operand_mut() {
+            *reg = current(&stacks, *reg);
+        }
+
+        let id = block.id;
+        for &succ in &cfg.succs[idx] {
+            for (position, var) in phis[succ].iter().enumerate() {
+                if let Some(&value) = stacks.get(var).and_then(|stack| stack.last()) {
+                    if let Inst::Phi { incoming, .. } = &mut function.blocks[succ].insts[position] {
+                        incoming.push((id, Operand::Reg(value)));
+                    }
+                }
+            }
+        }
+
+        work.push(Visit::Leave(pushed));
+        for &child in children[idx].iter().rev() {
+            work.push(Visit::Enter(child));
+        }
+    }
+}
+
+/// The name `reg` currently has, if it is one of the variables being renamed.
+fn current(stacks: &HashMap<Reg, Vec<Reg>>, reg: Reg) -> Reg {
+    stacks.get(&reg).and_then(|stack| stack.last()).copied().unwrap_or(reg)
+}
+
+/// Replaces phis with copies at the end of each predecessor, for backends that have no
+/// phis of their own. Registers may be defined more than once afterwards.
+///
+/// An edge from a block with several successors into a block with phis gets a block of
+/// its own first, so the copies only run when that edge is taken. The copies on an edge
+/// happen at once, so they go through fresh registers when there is more than one.
+pub(crate) fn destruct(function: &mut Function) {
+    let cfg = Cfg::new(function);
+    let mut next_block = function.next_block();
+    let mut split = Vec::new();
+    for (idx, block) in function.blocks.iter().enumerate() {
+        if !block.insts.iter().any(Inst::is_phi) {
+            continue;
+        }
+        for &pred in &cfg.preds[idx] {
+            if cfg.succs[pred].len() > 1 {
+                split.push((pred, block.id, BlockId(next_block)));
+                next_block += 1;
+            }
+        }
+    }
+    for (pred, target, edge) in split {
+        for succ in function.blocks[pred].term.successors_mut() {
+            if *succ == target {
+                *succ = edge;
+            }
+        }
+        let from = function.blocks[pred].id;
+        if let Some(block) = function.blocks.iter_mut().find(|block| block.id == target) {
+            for inst in &mut block.insts {
+                if let Inst::Phi { incoming, .. } = inst {
+                    for (pred, _) in incoming.iter_mut().filter(|(pred, _)| *pred == from) {
+                        *pred = edge;
+                    }
+                }
+            }
+        }
+        function.blocks.push(Block { id: edge, insts: Vec::new(), term: Terminator::Jump(target) });
+    }
+
+    let mut next_reg = function.next_reg();
+    let mut copies: HashMap<BlockId, Vec<(Reg, Ty, Operand)>> = HashMap::new();
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| {
+            let Inst::Phi { dst, ty, incoming } = inst else {
+                return true;
+            };
+            for (pred, value) in incoming {
+                copies.entry(*pred).or_default().push((*dst, ty.clone(), value.clone()));
+            }
+            false
+        });
+    }
+    for block in &mut function.blocks {
+        let Some(copies) = copies.remove(&block.id) else {
+            continue;
+        };
+        if let [(dst, ty, src)] = copies.as_slice() {
+            block.insts.push(Inst::Copy { dst: *dst, ty: ty.clone(), src: src.clone() });
+            continue;
+        }
+        let temps: Vec<Reg> = copies.iter().map(|_| {
+            next_reg += 1;
+            Reg(next_reg - 1)
+        }).collect();
+        for ((_, ty, src), temp) in copies.iter().zip(&temps) {
+            block.insts.push(Inst::Copy { dst: *temp, ty: ty.clone(), src: src.clone() });
+        }
+        for ((dst, ty, _), temp) in copies.into_iter().zip(temps) {
+            block.insts.push(Inst::Copy { dst, ty, src: Operand::Reg(temp) });
+        }
+    }
+}
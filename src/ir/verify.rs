@@ -0,0 +1,610 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display
+};
+
+use super::{cfg::Cfg, BinaryOp, BlockId, Function, Inst, Module, Operand, Reg, Terminator, Ty, UnaryOp, VTable};
+
+/// A well-formedness violation, located by function and block where it has one.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct VerifyError {
+    pub function: Option<String>,
+    pub block: Option<BlockId>,
+    pub message: String
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.function, self.block) {
+            (Some(function), Some(block)) => write!(f, "in `@{}`, {}: {}", function, block, self.message),
+            (Some(function), None) => write!(f, "in `@{}`: {}", function, self.message),
+            _ => f.write_str(&self.message)
+        }
+    }
+}
+
+struct Verifier<'m> {
+    module: &'m Module,
+    errors: Vec<VerifyError>,
+    function: Option<&'m str>,
+    block: Option<BlockId>
+}
+
+impl<'m> Verifier<'m> {
+
+    fn error(&mut self, message: impl Into<String>) {
+        self.errors.push(VerifyError {
+            function: self.function.map(str::to_string),
+            block: self.block,
+            message: message.into()
+        });
+    }
+
+    /// A type values can have: not `void`, and naming a declared struct or trait.
+    fn value_type(&mut self, ty: &Ty, what: &str) {
+        match ty {
+            Ty::Void => self.error(format!("{} can't have type `void`", what)),
+            Ty::Struct(name) if self.module.structure(name).is_none() => self.error(format!("{} has unknown type `{}`", what, name)),
+            Ty::Dyn(name) if self.module.trait_def(name).is_none() => self.error(format!("{} has unknown trait `{}`", what, name)),
+            _ => {}
+        }
+    }
+
+    fn module(&mut self) {
+        let module = self.module;
+        let mut names = HashSet::new();
+        for def in &module.structs {
+            if !names.insert(("struct", def.name.as_str())) {
+                self.error(format!("struct `{}` is defined more than once", def.name));
+            }
+            let mut fields = HashSet::new();
+            for (field, ty) in &def.fields {
+                if !fields.insert(field) {
+                    self.error(format!("struct `{}` has field `{}` more than once", def.name, field));
+                }
+                self.value_type(ty, &format!("field `{}::{}`", def.name, field));
+            }
+        }
+        for def in &module.traits {
+            if !names.insert(("trait", def.name.as_str())) {
+                self.error(format!("trait `{}` is defined more than once", def.name));
+            }
+            let mut methods = HashSet::new();
+            for slot in &def.slots {
+                if !methods.insert(&slot.method) {
+                    self.error(format!("trait `{}` has method `@{}` more than once", def.name, slot.method));
+                }
+                for ty in &slot.params {
+                    self.value_type(ty, &format!("a parameter of `@{}`", slot.method));
+                }
+            }
+        }
+        let mut vtables = HashSet::new();
+        for vtable in &module.vtables {
+            if !vtables.insert((vtable.obj.as_str(), vtable.with.as_str())) {
+                self.error(format!("the vtable of `{}` as `{}` is defined more than once", vtable.obj, vtable.with));
+            }
+            self.vtable(vtable);
+        }
+        for global in &module.globals {
+            if !names.insert(("global", global.name.as_str())) {
+                self.error(format!("global `@{}` is defined more than once", global.name));
+            }
+            self.value_type(&global.ty, &format!("global `@{}`", global.name));
+            if let Some(init) = &global.init {
+                if !init.fits(&global.ty) {
+                    self.error(format!("global `@{}` of type `{}` can't start as `{}`", global.name, global.ty, init));
+                }
+            }
+        }
+        // Externs and functions share the namespace calls look names up in.
+        for external in &module.externs {
+            if !names.insert(("fn", external.name.as_str())) {
+                self.error(format!("function `@{}` is defined more than once", external.name));
+            }
+            for ty in &external.params {
+                self.value_type(ty, &format!("a parameter of `@{}`", external.name));
+            }
+        }
+        for function in &module.functions {
+            if !names.insert(("fn", function.name.as_str())) {
+                self.error(format!("function `@{}` is defined more than once", function.name));
+            }
+            self.function(function);
+        }
+    }
+
+    /// A vtable lists, for a declared struct and trait, one function per slot that takes
+    /// the instance and then the slot's parameters.
+    fn vtable(&mut self, vtable: &VTable) {
+        let module = self.module;
+        let what = format!("vtable of `{}` as `{}`", vtable.obj, vtable.with);
+        if module.structure(&vtable.obj).is_none() {
+            self.error(format!("the {} is for unknown struct `{}`", what, vtable.obj));
+        }
+        let Some(def) = module.trait_def(&vtable.with) else {
+            self.error(format!("the {} is for unknown trait `{}`", what, vtable.with));
+            return;
+        };
+        if vtable.functions.len() != def.slots.len() {
+            self.error(format!("the {} has {} function(s) but `{}` has {} method(s)", what, vtable.functions.len(), def.name, def.slots.len()));
+        }
+        let instance = Ty::Struct(vtable.obj.clone());
+        for (name, slot) in vtable.functions.iter().zip(&def.slots) {
+            let Some(function) = module.function(name) else {
+                self.error(format!("the {} refers to undefined function `@{}`", what, name));
+                continue;
+            };
+            let params: Vec<&Ty> = function.params.iter().map(|(_, ty)| ty).collect();
+            let expected: Vec<&Ty> = std::iter::once(&instance).chain(&slot.params).collect();
+            if params != expected || function.ret != slot.ret {
+                self.error(format!("`@{}` in the {} doesn't have the signature of `@{}`", name, what, slot.method));
+            }
+        }
+    }
+
+    fn function(&mut self, function: &'m Function) {
+        self.function = Some(&function.name);
+        self.block = None;
+
+        let mut regs: HashMap<Reg, &Ty> = HashMap::new();
+        for (reg, ty) in &function.params {
+            self.value_type(ty, &format!("parameter `{}`", reg));
+            if regs.insert(*reg, ty).is_some() {
+                self.error(format!("parameter `{}` is declared more than once", reg));
+            }
+        }
+
+        let mut labels = HashSet::new();
+        for block in &function.blocks {
+            if !labels.insert(block.id) {
+                self.error(format!("block `{}` is defined more than once", block.id));
+            }
+            self.block = Some(block.id);
+            for (reg, ty) in block.insts.iter().filter_map(Inst::def) {
+                match regs.get(&reg) {
+                    Some(previous) if *previous != ty => self.error(format!("`{}` is defined as both `{}` and `{}`", reg, previous, ty)),
+                    Some(_) => {},
+                    None => {
+                        self.value_type(ty, &format!("register `{}`", reg));
+                        regs.insert(reg, ty);
+                    }
+                }
+            }
+        }
+        self.block = None;
+        if function.blocks.is_empty() {
+            self.error("function has no blocks");
+            return;
+        }
+
+        for block in &function.blocks {
+            self.block = Some(block.id);
+            for inst in &block.insts {
+                self.inst(inst, &regs);
+            }
+            self.terminator(&block.term, function, &regs, &labels);
+        }
+        self.block = None;
+        let cfg = Cfg::new(function);
+        self.phis(function, &cfg);
+        self.definite_assignment(function, &cfg);
+    }
+
+    /// Checks `operand` can be read as a `ty`.
+    fn operand(&mut self, operand: &Operand, ty: &Ty, regs: &HashMap<Reg, &Ty>) {
+        match operand {
+            Operand::Reg(reg) => match regs.get(reg) {
+                Some(found) if *found == ty => {},
+                Some(found) => self.error(format!("expected `{}` to be `{}`, but it is `{}`", reg, ty, found)),
+                None => self.error(format!("`{}` is never defined", reg))
+            },
+            Operand::Const(value) if !value.fits(ty) => self.error(format!("constant `{}` is not a valid `{}`", value, ty)),
+            Operand::Const(_) => {}
+        }
+    }
+
+    fn struct_field(&mut self, obj: Reg, field: &str, regs: &HashMap<Reg, &Ty>) -> Option<&'m Ty> {
+        let module = self.module;
+        match regs.get(&obj) {
+            Some(Ty::Struct(name)) => match module.structure(name).and_then(|def| def.field(field)) {
+                Some(ty) => Some(ty),
+                None => {
+                    self.error(format!("`{}` has no field `{}`", name, field));
+                    None
+                }
+            },
+            Some(ty) => {
+                self.error(format!("`{}` has type `{}`, which has no fields", obj, ty));
+                None
+            },
+            None => {
+                self.error(format!("`{}` is never defined", obj));
+                None
+            }
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst, regs: &HashMap<Reg, &Ty>) {
+        let module = self.module;
+        match inst {
+            Inst::Copy { ty, src, .. } => self.operand(src, ty, regs),
+            Inst::Binary { ty, op, lhs, rhs, .. } if op.is_comparison() => {
+                if *ty != Ty::Bool {
+                    self.error(format!("`{}` defines a `bool`, not a `{}`", op.as_str(), ty));
+                }
+                // Constants are untyped, so a register has to fix what is being compared.
+                let operand_ty = [lhs, rhs].into_iter()
+                    .find_map(|operand| operand.reg().and_then(|reg| regs.get(&reg)));
+                let Some(&operand_ty) = operand_ty else {
+                    if lhs.reg().is_none() && rhs.reg().is_none() {
+                        self.error(format!("`{}` needs at least one register operand", op.as_str()));
+                    }
+                    self.operand(lhs, &Ty::Bool, regs);
+                    self.operand(rhs, &Ty::Bool, regs);
+                    return;
+                };
+                let ordered = matches!(op, BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge);
+                if ordered && !(operand_ty.is_numeric() || *operand_ty == Ty::Str) {
+                    self.error(format!("`{}` can't order values of type `{}`", op.as_str(), operand_ty));
+                }
+                self.operand(lhs, operand_ty, regs);
+                self.operand(rhs, operand_ty, regs);
+            },
+            Inst::Binary { ty, op, lhs, rhs, .. } => {
+                if !(ty.is_numeric() || (*op == BinaryOp::Add && *ty == Ty::Str)) {
+                    self.error(format!("`{}` can't produce a `{}`", op.as_str(), ty));
+                }
+                self.operand(lhs, ty, regs);
+                self.operand(rhs, ty, regs);
+            },
+            Inst::Unary { ty, op, src, .. } => {
+                let valid = match op {
+                    UnaryOp::Neg => ty.is_numeric(),
+                    UnaryOp::Not => *ty == Ty::Bool,
+                    UnaryOp::BitNot => ty.is_integer()
+                };
+                if !valid {
+                    self.error(format!("`{}` can't produce a `{}`", op.as_str(), ty));
+                }
+                self.operand(src, ty, regs);
+            },
+            Inst::Call { dst, func, args } => {
+                let (params, variadic, ret) = if let Some(function) = module.function(func) {
+                    (function.params.iter().map(|(_, ty)| ty.clone()).collect(), false, &function.ret)
+                } else if let Some(external) = module.external(func) {
+                    (external.params.clone(), external.variadic, &external.ret)
+                } else {
+                    self.error(format!("call to undefined function `@{}`", func));
+                    return;
+                };
+
+                let count_ok = if variadic { args.len() >= params.len() } else { args.len() == params.len() };
+                if !count_ok {
+                    self.error(format!("`@{}` takes {} argument(s) but {} were given", func, params.len(), args.len()));
+                }
+                for (arg, ty) in args.iter().zip(&params) {
+                    self.operand(arg, ty, regs);
+                }
+                for arg in args.iter().skip(params.len()) {
+                    if let Operand::Reg(reg) = arg {
+                        if !regs.contains_key(reg) {
+                            self.error(format!("`{}` is never defined", reg));
+                        }
+                    }
+                }
+                match dst {
+                    Some((reg, _)) if *ret == Ty::Void => self.error(format!("`@{}` returns nothing to store in `{}`", func, reg)),
+                    Some((reg, ty)) if ty != ret => self.error(format!("`@{}` returns `{}`, but `{}` is `{}`", func, ret, reg, ty)),
+                    _ => {}
+                }
+            },
+            Inst::Load { ty, global, .. } => match module.global(global) {
+                Some(found) if found.ty == *ty => {},
+                Some(found) => self.error(format!("`@{}` is `{}`, not `{}`", global, found.ty, ty)),
+                None => self.error(format!("load from undefined global `@{}`", global))
+            },
+            Inst::Store { global, src } => match module.global(global) {
+                Some(found) => self.operand(src, &found.ty, regs),
+                None => self.error(format!("store to undefined global `@{}`", global))
+            },
+            Inst::New { ty, args, .. } => {
+                let def = match ty {
+                    Ty::Struct(name) => module.structure(name),
+                    _ => None
+                };
+                let Some(def) = def else {
+                    self.error(format!("`new` needs a struct type, not `{}`", ty));
+                    return;
+                };
+                if args.len() != def.fields.len() {
+                    self.error(format!("`{}` has {} field(s) but {} were given", def.name, def.fields.len(), args.len()));
+                }
+                for (arg, (_, ty)) in args.iter().zip(&def.fields) {
+                    self.operand(arg, ty, regs);
+                }
+            },
+            Inst::GetField { ty, obj, field, .. } => {
+                if let Some(found) = self.struct_field(*obj, field, regs) {
+                    if found != ty {
+                        self.error(format!("field `{}` is `{}`, not `{}`", field, found, ty));
+                    }
+                }
+            },
+            Inst::SetField { obj, field, src } => {
+                if let Some(found) = self.struct_field(*obj, field, regs) {
+                    self.operand(src, found, regs);
+                }
+            },
+            Inst::Dyn { ty, concrete, src, .. } => {
+                let Ty::Dyn(with) = ty else {
+                    self.error(format!("`dyn` defines a trait object, not a `{}`", ty));
+                    return;
+                };
+                self.operand(&Operand::Reg(*src), &Ty::Struct(concrete.clone()), regs);
+                if module.vtable(concrete, with).is_none() {
+                    self.error(format!("`{}` has no vtable for `{}`", concrete, with));
+                }
+            },
+            Inst::CallDyn { dst, recv, method, args } => {
+                let def = match regs.get(recv) {
+                    Some(Ty::Dyn(with)) => module.trait_def(with),
+                    Some(ty) => {
+                        self.error(format!("`{}` has type `{}`, which isn't a trait object", recv, ty));
+                        return;
+                    },
+                    None => {
+                        self.error(format!("`{}` is never defined", recv));
+                        return;
+                    }
+                };
+                let Some((_, slot)) = def.and_then(|def| def.slot(method)) else {
+                    self.error(format!("`{}` has no method `@{}`", recv, method));
+                    return;
+                };
+                if args.len() != slot.params.len() {
+                    self.error(format!("`@{}` takes {} argument(s) but {} were given", method, slot.params.len(), args.len()));
+                }
+                for (arg, ty) in args.iter().zip(&slot.params) {
+                    self.operand(arg, ty, regs);
+                }
+                match dst {
+                    Some((reg, _)) if slot.ret == Ty::Void => self.error(format!("`@{}` returns nothing to store in `{}`", method, reg)),
+                    Some((reg, ty)) if *ty != slot.ret => self.error(format!("`@{}` returns `{}`, but `{}` is `{}`", method, slot.ret, reg, ty)),
+                    _ => {}
+                }
+            },
+            Inst::Phi { ty, incoming, .. } => {
+                for (_, value) in incoming {
+                    self.operand(value, ty, regs);
+                }
+            }
+        }
+    }
+
+    fn terminator(&mut self, term: &Terminator, function: &Function, regs: &HashMap<Reg, &Ty>, labels: &HashSet<BlockId>) {
+        for target in term.successors() {
+            if !labels.contains(&target) {
+                self.error(format!("branch to undefined block `{}`", target));
+            }
+        }
+        match term {
+            Terminator::Ret(None) if function.ret != Ty::Void => self.error(format!("`ret` without a value in a function returning `{}`", function.ret)),
+            Terminator::Ret(Some(_)) if function.ret == Ty::Void => self.error("`ret` with a value in a function returning `void`"),
+            Terminator::Ret(Some(value)) => self.operand(value, &function.ret, regs),
+            Terminator::Branch { cond, .. } => self.operand(cond, &Ty::Bool, regs),
+            Terminator::Ret(None) | Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    /// Phis come first in their block and take exactly one input per predecessor.
+    fn phis(&mut self, function: &Function, cfg: &Cfg) {
+        for (idx, block) in function.blocks.iter().enumerate() {
+            self.block = Some(block.id);
+            let leading = block.insts.iter().take_while(|inst| inst.is_phi()).count();
+            if block.insts[leading..].iter().any(Inst::is_phi) {
+                self.error("phis must come before every other instruction in a block");
+            }
+
+            let preds: HashSet<BlockId> = cfg.preds[idx].iter().map(|&pred| function.blocks[pred].id).collect();
+            for inst in &block.insts[..leading] {
+                let Inst::Phi { dst, incoming, .. } = inst else {
+                    continue;
+                };
+                let mut seen = HashSet::new();
+                for (from, _) in incoming {
+                    if !seen.insert(*from) {
+                        self.error(format!("phi `{}` has more than one input from `{}`", dst, from));
+                    } else if !preds.contains(from) {
+                        self.error(format!("phi `{}` has an input from `{}`, which doesn't branch here", dst, from));
+                    }
+                }
+                for pred in preds.difference(&seen) {
+                    self.error(format!("phi `{}` has no input from predecessor `{}`", dst, pred));
+                }
+            }
+        }
+        self.block = None;
+    }
+
+    /// Every register must be assigned on all paths from the entry before it is read.
+    /// A phi reads each input at the end of the predecessor it comes from.
+    fn definite_assignment(&mut self, function: &Function, cfg: &Cfg) {
+        let entry: HashSet<Reg> = function.params.iter().map(|(reg, _)| *reg).collect();
+        let mut known = entry.clone();
+        known.extend(function.blocks.iter().flat_map(|block| &block.insts).filter_map(|inst| inst.def().map(|(reg, _)| reg)));
+
+        // `None` stands for "not reached yet", the top of the lattice.
+        let mut outs: Vec<Option<HashSet<Reg>>> = vec![None; function.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &idx in &cfg.rpo {
+                let Some(mut defined) = block_entry(idx, &entry, &cfg.preds, &outs) else {
+                    continue;
+                };
+                defined.extend(function.blocks[idx].insts.iter().filter_map(|inst| inst.def().map(|(reg, _)| reg)));
+                if outs[idx].as_ref() != Some(&defined) {
+                    outs[idx] = Some(defined);
+                    changed = true;
+                }
+            }
+        }
+
+        for &idx in &cfg.rpo {
+            let block = &function.blocks[idx];
+            let Some(mut defined) = block_entry(idx, &entry, &cfg.preds, &outs) else {
+                continue;
+            };
+            self.block = Some(block.id);
+            let check = |verifier: &mut Self, reg: Reg, defined: &HashSet<Reg>| {
+                // Registers with no definition at all were already reported.
+                if known.contains(&reg) && !defined.contains(&reg) {
+                    verifier.error(format!("`{}` may be used before it is defined", reg));
+                }
+            };
+
+            for inst in &block.insts {
+                if let Inst::Phi { incoming, .. } = inst {
+                    for (from, value) in incoming {
+                        let out = cfg.index.get(from).and_then(|&pred| outs[pred].as_ref());
+                        if let (Some(out), Some(reg)) = (out, value.reg()) {
+                            check(self, reg, out);
+                        }
+                    }
+                } else {
+                    for reg in inst.uses() {
+                        check(self, reg, &defined);
+                    }
+                }
+                defined.extend(inst.def().map(|(reg, _)| reg));
+            }
+            if let Some(reg) = block.term.operand().and_then(Operand::reg) {
+                check(self, reg, &defined);
+            }
+        }
+        self.block = None;
+    }
+}
+
+/// What is defined on entry to block `idx`: the parameters for the entry block, else
+/// what every reached predecessor defines.
+fn block_entry(idx: usize, entry: &HashSet<Reg>, preds: &[Vec<usize>], outs: &[Option<HashSet<Reg>>]) -> Option<HashSet<Reg>> {
+    if idx == 0 {
+        return Some(entry.clone());
+    }
+    preds[idx].iter()
+        .filter_map(|&pred| outs[pred].as_ref())
+        .fold(None, |acc: Option<HashSet<Reg>>, out| Some(match acc {
+            Some(acc) => acc.intersection(out).copied().collect(),
+            None => out.clone()
+        }))
+}
+
+/// Checks that `module` is well formed: names are unique, every operand has the type
+/// its instruction expects, branches target existing blocks and registers are defined
+/// on every path before they are read.
+pub(crate) fn verify(module: &Module) -> Result<(), Vec<VerifyError>> {
+    let mut verifier = Verifier { module, errors: Vec::new(), function: None, block: None };
+    verifier.module();
+    if verifier.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(verifier.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parse::parse_module;
+
+    /// The messages of the errors verifying `src` gives.
+    fn errors(src: &str) -> Vec<String> {
+        let module = parse_module(src).expect("IR that parses");
+        verify(&module).err().unwrap_or_default().into_iter().map(|err| err.message).collect()
+    }
+
+    #[test]
+    fn well_formed_functions_pass() {
+        assert_eq!(errors("
+fn @f(%0: bool) -> i32 {
+bb0:
+    br %0, bb1, bb2
+bb1:
+    jmp bb2
+bb2:
+    %1: i32 = phi [bb0: 1], [bb1: 2]
+    ret %1
+}
+"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn undefined_registers() {
+        assert_eq!(errors("
+fn @f() -> i32 {
+bb0:
+    %0: i32 = add %7, 1
+    ret %0
+}
+"), ["`%7` is never defined"]);
+        assert_eq!(errors("
+fn @f(%0: bool) -> i32 {
+bb0:
+    br %0, bb1, bb2
+bb1:
+    %1: i32 = copy 1
+    jmp bb2
+bb2:
+    ret %1
+}
+"), ["`%1` may be used before it is defined"]);
+    }
+
+    #[test]
+    fn type_mismatches() {
+        assert_eq!(errors("
+fn @f(%0: i64) -> i32 {
+bb0:
+    %1: i32 = add %0, 1
+    ret %1
+}
+"), ["expected `%0` to be `i32`, but it is `i64`"]);
+        assert_eq!(errors("
+fn @f(%0: i32) -> bool {
+bb0:
+    %1: bool = copy 300
+    %2: i32 = lt %0, 1
+    ret %1
+}
+"), ["constant `300` is not a valid `bool`", "`lt` defines a `bool`, not a `i32`"]);
+    }
+
+    #[test]
+    fn unknown_blocks() {
+        assert_eq!(errors("
+fn @f() -> void {
+bb0:
+    jmp bb4
+}
+"), ["branch to undefined block `bb4`"]);
+        let errors = errors("
+fn @f(%0: bool) -> i32 {
+bb0:
+    br %0, bb1, bb1
+bb1:
+    %1: i32 = phi [bb0: 1], [bb2: 2]
+    ret %1
+}
+");
+        assert!(errors.iter().any(|message| message.contains("`bb2`, which doesn't branch here")), "{:?}", errors);
+    }
+
+    #[test]
+    fn errors_name_their_function_and_block() {
+        let module = parse_module("fn @g() -> void {\nbb0:\n    jmp bb9\n}\n").unwrap();
+        let errors = verify(&module).unwrap_err();
+        assert_eq!(errors[0].to_string(), "in `@g`, bb0: branch to undefined block `bb9`");
+    }
+}
@@ -1,8 +1,24 @@
-#![feature(pattern)]
 
 mod preprocessor;
 mod common;
+mod driver;
+mod analysis;
+mod codegen;
+mod interpreter;
+mod ir;
+mod vm;
 
-fn main() {
-    println!("Hello, world!");
+use std::{panic, process::ExitCode, thread};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !driver::interprets(&args) {
+        return driver::run(&args).into();
+    }
+    // The main thread's stack is too small for the interpreter's deepest recursion.
+    let driver = thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(move || driver::run(&args))
+        .expect("failed to start the driver thread");
+    driver.join().unwrap_or_else(|payload| panic::resume_unwind(payload)).into()
 }
@@ -0,0 +1,568 @@
+use std::{collections::{HashMap, HashSet}, ops::Range};
+
+use crate::common::{
+    diagnostics::{codes, Diagnostic},
+    lexer::Span,
+    Constants,
+    Globals
+};
+
+use super::{node::{Token, Type}, word_end, Expansion};
+
+/// A `#define`d name. An object-like macro is replaced by its body wherever the name
+/// appears; a function-like one only where the name is followed by an argument list.
+pub(super) struct Macro {
+    pub name: String,
+    /// The parameters of a function-like macro, without the `...` of a variadic one.
+    pub params: Option<Vec<String>>,
+    /// Whether the macro takes any number of arguments after its parameters, which its
+    /// body reads as `__VA_ARGS__`.
+    pub variadic: bool,
+    pub value: String,
+    pub body: Vec<Token>,
+    /// Where the name and the body are on the `#define` line.
+    pub span: Span,
+    pub value_span: Span
+}
+
+impl Macro {
+
+    /// The argument a token of the body stands for: the index of a parameter, or one past
+    /// the last for `__VA_ARGS__`.
+    fn param(&self, token: &Token) -> Option<usize> {
+        let params = self.params.as_ref()?;
+        if token.ty != Type::Ident {
+            return None;
+        }
+        match params.iter().position(|param| *param == token.text) {
+            Some(index) => Some(index),
+            None => (self.variadic && token.text == "__VA_ARGS__").then_some(params.len())
+        }
+    }
+
+    /// The body with the names it declares with `let` renamed for expansion `id`, so they
+    /// can neither capture nor be captured by the names at the invocation. A local `tmp`
+    /// becomes `tmp__<id>`, or `tmp__<id>_<n>` for the first `n` that isn't `written` in
+    /// the source; the number after the last `__` tells apart the names of different
+    /// expansions. Fields keep their names.
+    fn hygienic_body(&self, id: usize, written: &HashSet<String>) -> Vec<Token> {
+        let mut locals: HashMap<&str, String> = HashMap::new();
+        let mut after_let = false;
+        for token in self.body.iter().filter(|token| !token.is_blank()) {
+            if after_let && token.ty == Type::Ident && self.param(token).is_none() {
+                locals.entry(&token.text).or_insert_with(|| {
+                    let base = format!("{}__{}", token.text, id);
+                    std::iter::once(base.clone())
+                        .chain((1..).map(|n| format!("{}_{}", base, n)))
+                        .find(|name| !written.contains(name))
+                        .expect("the names run out before the numbers do")
+                });
+            }
+            after_let = token.ty == Type::Ident && token.text == "let";
+        }
+
+        let mut body = self.body.clone();
+        let mut field = false;
+        for token in &mut body {
+            if token.ty == Type::Ident && !field {
+                if let Some(renamed) = locals.get(token.text.as_str()) {
+                    token.text = renamed.clone();
+                }
+            }
+            if !token.is_blank() {
+                field = token.is(".");
+            }
+        }
+        body
+    }
+}
+
+/// Reads the parameter list of a function-like macro from `text`, which starts just
+/// after its `(`. Gives the parameters, whether the macro is variadic, and how much of
+/// `text` the list takes with its `)`.
+pub(super) fn parameters(text: &str) -> Result<(Vec<String>, bool, usize), String> {
+    let close = text.find(')').ok_or("expected `)` after the parameters")?;
+    let list = text[..close].trim();
+    let mut params: Vec<String> = Vec::new();
+    let mut variadic = false;
+    for param in list.split(',').map(str::trim).filter(|_| !list.is_empty()) {
+        if variadic {
+            return Err("`...` must be the last parameter".to_string());
+        }
+        if param == "..." {
+            variadic = true;
+        } else if param.is_empty() || word_end(param, 0) != param.len() || param.starts_with(|ch: char| ch.is_ascii_digit()) {
+            return Err(format!("expected a parameter name, found `{}`", param));
+        } else if params.iter().any(|other| other == param) {
+            return Err(format!("parameter `{}` is listed twice", param));
+        } else {
+            params.push(param.to_string());
+        }
+    }
+    Ok((params, variadic, close + 1))
+}
+
+/// Checks that each `#` in the body of a function-like macro names a parameter, and
+/// that `##` has something on both sides.
+pub(super) fn check_body(definition: &Macro) -> Result<(), String> {
+    let tokens: Vec<&Token> = definition.body.iter().filter(|token| !token.is_blank()).collect();
+    if tokens.first().is_some_and(|token| token.is("##")) || tokens.last().is_some_and(|token| token.is("##")) {
+        return Err("`##` can't be at either end of a macro".to_string());
+    }
+    if definition.params.is_some() {
+        for (index, token) in tokens.iter().enumerate() {
+            if token.is("#") && tokens.get(index + 1).and_then(|next| definition.param(next)).is_none() {
+                return Err("`#` must be followed by a parameter".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The string literal `#` makes of an argument: its text with the whitespace between
+/// tokens collapsed, quoted and escaped.
+fn stringify(tokens: &[Token]) -> String {
+    let mut out = String::from("\"");
+    let mut space = false;
+    for token in tokens {
+        if token.is_blank() {
+            space = true;
+            continue;
+        }
+        if space && out.len() > 1 {
+            out.push(' ');
+        }
+        space = false;
+        for ch in token.text.chars() {
+            if matches!(ch, '"' | '\\') {
+                out.push('\\');
+            }
+            out.push(ch);
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn trim(tokens: &[Token]) -> &[Token] {
+    let start = tokens.iter().position(|token| !token.is_blank()).unwrap_or(tokens.len());
+    let end = tokens.iter().rposition(|token| !token.is_blank()).map_or(start, |end| end + 1);
+    &tokens[start..end]
+}
+
+/// Whether two tokens next to each other would be read as one.
+fn glues(lhs: &Token, rhs: &Token) -> bool {
+    let (Some(last), Some(first)) = (lhs.text.chars().last(), rhs.text.chars().next()) else {
+        return false;
+    };
+    let word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let symbol = |ch: char| !word(ch) && !ch.is_whitespace() && !"()[]{},;\"".contains(ch);
+    (word(last) && word(first)) || (symbol(last) && symbol(first))
+}
+
+/// Where an expansion written in the line ended up in the output.
+struct Top {
+    id: usize,
+    tokens: Range<usize>,
+    call: Range<usize>
+}
+
+/// Expands the macros in one line, recording each expansion so diagnostics can find
+/// where their spans came from.
+pub(super) struct Expander<'p> {
+    macros: &'p [Macro],
+    names: &'p HashMap<String, usize>,
+    written: &'p HashSet<String>,
+    globals: &'p Globals,
+    /// Expansions are numbered after the `base` already in the line map.
+    base: usize,
+    line: usize,
+    /// Only expand function-like macros, as in a condition, where the others are
+    /// evaluated by name.
+    functions_only: bool,
+    pub expansions: Vec<Expansion>,
+    /// The columns of the invocation being expanded, as written; errors inside its
+    /// expansion point there.
+    call: Range<usize>,
+    /// Errors, with the columns of the invocation they arose in.
+    errors: Vec<(Diagnostic, Range<usize>)>
+}
+
+impl<'p> Expander<'p> {
+
+    pub fn new(
+        macros: &'p [Macro],
+        names: &'p HashMap<String, usize>,
+        written: &'p HashSet<String>,
+        globals: &'p Globals,
+        base: usize,
+        line: usize,
+        functions_only: bool
+    ) -> Self {
+        Self {
+            macros,
+            names,
+            written,
+            globals,
+            base,
+            line,
+            functions_only,
+            expansions: Vec::new(),
+            call: 0..0,
+            errors: Vec::new()
+        }
+    }
+
+    fn expansion(&mut self, name: &str, definition: Option<Span>, parent: Option<usize>) -> usize {
+        self.expansions.push(Expansion { name: name.to_string(), definition, parent, line: self.line, flat: 0..0, call: None });
+        self.base + self.expansions.len() - 1
+    }
+
+    /// Expands `tokens`, read from a line, into the text of the output line. Errors come
+    /// with the columns they point at there.
+    pub fn line(&mut self, tokens: Vec<Token>) -> (String, Vec<(Diagnostic, Range<usize>)>) {
+        let mut tops = Vec::new();
+        let out = self.expand(tokens, None, &mut Vec::new(), Some(&mut tops));
+
+        // Tokens from different places that would run together are kept apart, and the
+        // space counts as part of the expansion.
+        let mut text = String::new();
+        let mut cols: Vec<Range<usize>> = Vec::with_capacity(out.len());
+        let mut col = 0;
+        for (index, token) in out.iter().enumerate() {
+            let mut start = col;
+            if index > 0 && out[index - 1].expansion != token.expansion && glues(&out[index - 1], token) {
+                text.push(' ');
+                col += 1;
+                if token.expansion.is_none() {
+                    cols[index - 1].end += 1;
+                    start = col;
+                }
+            }
+            text.push_str(&token.text);
+            col += token.text.chars().count();
+            cols.push(start..col);
+        }
+
+        // An expansion covers the tokens that came out of it and the expansions within.
+        let mut ranges: Vec<Option<Range<usize>>> = vec![None; self.expansions.len()];
+        for (token, cols) in out.iter().zip(&cols) {
+            let mut expansion = token.expansion;
+            while let Some(id) = expansion.filter(|id| *id >= self.base) {
+                let range = &mut ranges[id - self.base];
+                *range = Some(range.as_ref().map_or(cols.clone(), |range| range.start.min(cols.start)..range.end.max(cols.end)));
+                expansion = self.expansions[id - self.base].parent;
+            }
+        }
+        for top in &tops {
+            let at = cols.get(top.tokens.start).map_or(col, |cols| cols.start);
+            ranges[top.id - self.base] = Some(ranges[top.id - self.base].clone().unwrap_or(at..at));
+            self.expansions[top.id - self.base].call = Some(top.call.clone());
+        }
+        for (expansion, range) in self.expansions.iter_mut().zip(ranges) {
+            expansion.flat = range.unwrap_or(0..0);
+        }
+
+        // Errors point at the invocation they arose in, wherever it ended up.
+        let errors = std::mem::take(&mut self.errors);
+        let to_flat = |col: usize, end: bool| {
+            let mut shift = 0isize;
+            for top in &tops {
+                let flat = &self.expansions[top.id - self.base].flat;
+                if col < top.call.start || (end && col == top.call.start) {
+                    break;
+                }
+                if col < top.call.end || (end && col == top.call.end) {
+                    return if end { flat.end } else { flat.start };
+                }
+                shift = flat.end as isize - top.call.end as isize;
+            }
+            (col as isize + shift) as usize
+        };
+        let errors = errors.into_iter()
+            .map(|(diag, call)| (diag, to_flat(call.start, false)..to_flat(call.end, true)))
+            .collect();
+        (text, errors)
+    }
+
+    /// Expands the macros in `tokens`, which are part of expansion `parent` if any. The
+    /// macros in `hidden` are being expanded, and aren't again. `tops` is given for the
+    /// tokens of a line, and collects the expansions written in it.
+    fn expand(&mut self, tokens: Vec<Token>, parent: Option<usize>, hidden: &mut Vec<usize>, mut tops: Option<&mut Vec<Top>>) -> Vec<Token> {
+        let macros = self.macros;
+        let mut out = Vec::with_capacity(tokens.len());
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            let end_col = |token: &Token| token.col + token.text.chars().count();
+            let found = if token.ty == Type::Ident { self.names.get(&token.text).copied() } else { None };
+
+            let Some(found) = found.filter(|found| !hidden.contains(found)) else {
+                let constant = Constants::from_name(&token.text).filter(|_| token.ty == Type::Ident && found.is_none() && !self.functions_only);
+                match constant {
+                    Some(constant) => {
+                        let id = self.expansion(&token.text, None, token.expansion.or(parent));
+                        let start = out.len();
+                        out.push(Token { ty: Type::Number, text: self.globals.value(constant).to_string(), col: token.col, expansion: Some(id) });
+                        if let Some(tops) = tops.as_deref_mut() {
+                            tops.push(Top { id, tokens: start..out.len(), call: token.col..end_col(token) });
+                        }
+                    },
+                    None => out.push(token.clone())
+                }
+                index += 1;
+                continue;
+            };
+
+            let definition = &macros[found];
+            let (args, next) = match &definition.params {
+                None if self.functions_only => {
+                    out.push(token.clone());
+                    index += 1;
+                    continue;
+                },
+                None => (None, index + 1),
+                Some(_) => match arguments(&tokens, index + 1) {
+                    // Without an argument list the name is only a name.
+                    Arguments::Absent => {
+                        out.push(token.clone());
+                        index += 1;
+                        continue;
+                    },
+                    Arguments::Found(args, next) => (Some(args), next),
+                    Arguments::Unterminated => {
+                        let call = token.col..tokens.last().map_or(token.col, end_col);
+                        if tops.is_some() {
+                            self.call = call;
+                        }
+                        self.errors.push((Diagnostic::error(format!("unterminated invocation of `{}`", definition.name))
+                            .with_code(codes::UNTERMINATED_MACRO_CALL)
+                            .with_note("the arguments of a macro have to be on the line it is invoked on"), self.call.clone()));
+                        out.extend(tokens[index..].iter().cloned());
+                        break;
+                    }
+                }
+            };
+
+            if tops.is_some() {
+                self.call = token.col..end_col(&tokens[next - 1]);
+            }
+            let id = self.expansion(&definition.name, Some(definition.span), token.expansion.or(parent));
+            match self.substitute(definition, args, id, hidden) {
+                Ok(body) => {
+                    hidden.push(found);
+                    let expanded = self.expand(body, Some(id), hidden, None);
+                    hidden.pop();
+                    let start = out.len();
+                    out.extend(expanded);
+                    if let Some(tops) = tops.as_deref_mut() {
+                        tops.push(Top { id, tokens: start..out.len(), call: self.call.clone() });
+                    }
+                },
+                Err(diag) => {
+                    self.errors.push((diag, self.call.clone()));
+                    out.extend(tokens[index..next].iter().cloned());
+                }
+            }
+            index = next;
+        }
+        out
+    }
+
+    /// The body of `definition` for expansion `id`, with its parameters replaced by
+    /// `args`, `#` and `##` applied, and its local names renamed.
+    fn substitute(&mut self, definition: &Macro, args: Option<Vec<Vec<Token>>>, id: usize, hidden: &mut Vec<usize>) -> Result<Vec<Token>, Diagnostic> {
+        let mut args = args.unwrap_or_default();
+        let fixed = definition.params.as_ref().map_or(0, Vec::len);
+        if fixed == 0 && args.len() == 1 && trim(&args[0]).is_empty() {
+            args.clear();
+        }
+        if definition.params.is_some() && (args.len() < fixed || (!definition.variadic && args.len() > fixed)) {
+            let expected = match (fixed, definition.variadic) {
+                (1, false) => "1 argument".to_string(),
+                (count, false) => format!("{} arguments", count),
+                (count, true) => format!("at least {} argument{}", count, if count == 1 { "" } else { "s" })
+            };
+            return Err(Diagnostic::error(format!("`{}` takes {} but {} {} given", definition.name, expected, args.len(), if args.len() == 1 { "was" } else { "were" }))
+                .with_code(codes::WRONG_MACRO_ARG_COUNT));
+        }
+
+        // The variadic arguments are one more, their commas included.
+        let mut raw: Vec<Vec<Token>> = args.iter().take(fixed).map(|arg| trim(arg).to_vec()).collect();
+        if definition.variadic {
+            let mut rest = Vec::new();
+            for (index, arg) in args.iter().skip(fixed).enumerate() {
+                if index > 0 {
+                    rest.push(Token::new(Type::Punct, ","));
+                    rest.push(Token::new(Type::Space, " "));
+                }
+                rest.extend(trim(arg).iter().cloned());
+            }
+            raw.push(rest);
+        }
+        let expanded: Vec<Vec<Token>> = raw.iter().map(|arg| self.expand(arg.clone(), Some(id), hidden, None)).collect();
+
+        let body = definition.hygienic_body(id, self.written);
+        let next_solid = |from: usize| (from..body.len()).find(|&index| !body[index].is_blank());
+        let mut out: Vec<Token> = Vec::new();
+        let mut index = 0;
+        while index < body.len() {
+            let token = &body[index];
+            if definition.params.is_some() && token.is("#") {
+                let param = next_solid(index + 1).expect("checked when the macro was defined");
+                let arg = definition.param(&body[param]).expect("checked when the macro was defined");
+                out.push(Token::new(Type::Str, stringify(&raw[arg])));
+                index = param + 1;
+            } else if token.is("##") {
+                while out.last().is_some_and(Token::is_blank) {
+                    out.pop();
+                }
+                let operand = next_solid(index + 1).expect("checked when the macro was defined");
+                let rhs = match definition.param(&body[operand]) {
+                    Some(arg) => raw[arg].clone(),
+                    None => vec![body[operand].clone()]
+                };
+                match (out.pop(), rhs.split_first()) {
+                    (Some(lhs), Some((first, rest))) => {
+                        let text = format!("{}{}", lhs.text, first.text);
+                        let mut pasted = Token::tokenize(&text, &mut 0);
+                        if pasted.len() != 1 {
+                            return Err(Diagnostic::error(format!("pasting `{}` and `{}` doesn't give a valid token", lhs.text, first.text))
+                                .with_code(codes::INVALID_PASTE));
+                        }
+                        out.push(pasted.remove(0));
+                        out.extend(rest.iter().cloned());
+                    },
+                    (lhs, _) => out.extend(lhs.into_iter().chain(rhs))
+                }
+                index = operand + 1;
+            } else if let Some(arg) = definition.param(token) {
+                // An operand of `##` is pasted as written rather than expanded.
+                let pasted = next_solid(index + 1).is_some_and(|next| body[next].is("##"));
+                out.extend(if pasted { raw[arg].iter() } else { expanded[arg].iter() }.cloned());
+                index += 1;
+            } else {
+                out.push(token.clone());
+                index += 1;
+            }
+        }
+
+        for token in &mut out {
+            token.expansion.get_or_insert(id);
+        }
+        Ok(out)
+    }
+}
+
+/// What follows the name of a function-like macro.
+enum Arguments {
+    /// No `(`, so the name isn't an invocation.
+    Absent,
+    /// The line ends before the `)`.
+    Unterminated,
+    /// The arguments, and the index after the `)`.
+    Found(Vec<Vec<Token>>, usize)
+}
+
+/// The arguments of a function-like macro whose name is just before `from`.
+fn arguments(tokens: &[Token], from: usize) -> Arguments {
+    let Some(open) = (from..tokens.len()).find(|&index| !tokens[index].is_blank()) else {
+        return Arguments::Absent;
+    };
+    if !tokens[open].is("(") {
+        return Arguments::Absent;
+    }
+    let mut args = vec![Vec::new()];
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open + 1) {
+        if token.is(")") && depth == 0 {
+            return Arguments::Found(args, index + 1);
+        }
+        if token.is(",") && depth == 0 {
+            args.push(Vec::new());
+            continue;
+        }
+        if token.is("(") {
+            depth += 1;
+        } else if token.is(")") {
+            depth -= 1;
+        }
+        args.last_mut().unwrap().push(token.clone());
+    }
+    Arguments::Unterminated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::preprocessor::Preprocessor;
+
+    use super::*;
+
+    /// The lines `src` preprocesses to, without those left blank, and the messages of
+    /// the errors it reports.
+    fn expand(src: &str) -> (Vec<String>, Vec<String>) {
+        let preprocessed = Preprocessor::new(&[], Globals::new(None)).run(Path::new("test.beta"), "test.beta".into(), src);
+        let lines = preprocessed.text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect();
+        let errors = preprocessed.diagnostics.iter().filter(|diag| diag.is_error()).map(|diag| diag.message.clone()).collect();
+        (lines, errors)
+    }
+
+    #[test]
+    fn object_and_function_like_macros() {
+        let (lines, errors) = expand("\
+#define N 3
+#define M N + 1
+#define SQ(x) ((x) * (x))
+#define PAIR(a, b) a + b
+let q = SQ(1 + 2);
+let r = PAIR(f(1, 2), M);
+let t = SQ;
+");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(lines, ["let q = ((1 + 2) * (1 + 2));", "let r = f(1, 2) + 3 + 1;", "let t = SQ;"]);
+    }
+
+    #[test]
+    fn variadics_stringizing_and_pasting() {
+        let (lines, errors) = expand("\
+#define LOG(fmt, ...) println(fmt, __VA_ARGS__)
+#define STR(x) #x
+#define CAT(a, b) a ## b
+LOG(\"x\", 1, 2);
+let s = STR(a + b);
+let CAT(va, lue) = 1;
+");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(lines, ["println(\"x\", 1, 2);", "let s = \"a + b\";", "let value = 1;"]);
+    }
+
+    #[test]
+    fn recursive_macros_stop_at_themselves() {
+        let (lines, _) = expand("#define A B\n#define B A\nlet u = A;\n");
+        assert_eq!(lines, ["let u = A;"]);
+    }
+
+    #[test]
+    fn bad_invocations() {
+        let (_, errors) = expand("#define SQ(x) ((x) * (x))\nlet a = SQ(1, 2);\nlet b = SQ(1;\n");
+        assert_eq!(errors, ["`SQ` takes 1 argument but 2 were given", "unterminated invocation of `SQ`"]);
+    }
+
+    #[test]
+    fn renamed_locals_avoid_written_names() {
+        let (lines, errors) = expand("\
+#define TMP(v) let tmp = v; println(tmp, tmp__0)
+let tmp__0 = 7;
+TMP(1);
+TMP(2);
+");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(lines, [
+            "let tmp__0 = 7;",
+            "let tmp__0_1 = 1; println(tmp__0_1, tmp__0);",
+            "let tmp__1 = 2; println(tmp__1, tmp__0);"
+        ]);
+    }
+}
@@ -0,0 +1,717 @@
+pub(crate) mod node;
+mod macros;
+
+use std::{collections::{HashMap, HashSet}, fs, ops::Range, path::{Path, PathBuf}};
+
+use crate::common::{
+    diagnostics::{codes, Diagnostic},
+    lexer::Span,
+    yarn::Yarn,
+    Constants,
+    Globals
+};
+
+use self::{
+    macros::{check_body, parameters, Expander, Macro},
+    node::{OpNode, Token}
+};
+
+/// Where a line of the flattened source came from, as an index into the map's files
+/// and a 1-based line in that file.
+#[derive(Clone, Copy, Debug)]
+struct Origin {
+    file: usize,
+    line: usize
+}
+
+/// Maps each line of preprocessed source back to the file and line it came from, so
+/// diagnostics can point at what was written rather than what was parsed.
+#[derive(Default)]
+pub(crate) struct LineMap {
+    /// Each file's name and lines, in the order they were first read.
+    files: Vec<(String, Vec<String>)>,
+    lines: Vec<Origin>,
+    /// Every macro expansion, by line.
+    expansions: Vec<Expansion>
+}
+
+/// A macro expanded in the flattened source.
+struct Expansion {
+    name: String,
+    /// Where the macro was defined, or `None` for a predefined constant.
+    definition: Option<Span>,
+    /// The expansion this one happened inside of.
+    parent: Option<usize>,
+    /// The line of the flattened source it is on, and the 0-based columns, in characters,
+    /// it covers there.
+    line: usize,
+    flat: Range<usize>,
+    /// The columns of the invocation as written, for an expansion written in the line.
+    call: Option<Range<usize>>
+}
+
+impl LineMap {
+
+    fn add_file(&mut self, name: String, src: &str) -> usize {
+        self.files.push((name, src.lines().map(str::to_string).collect()));
+        self.files.len() - 1
+    }
+
+    /// The file and line that the 1-based `line` of the flattened source came from.
+    /// Lines past the end, such as where the lexer reports the end of input, continue
+    /// from the last one.
+    pub fn origin(&self, line: usize) -> Option<(&str, usize)> {
+        let last = self.lines.len();
+        let (origin, past) = match self.lines.get(line.checked_sub(1)?) {
+            Some(origin) => (*origin, 0),
+            None => (*self.lines.last()?, line - last)
+        };
+        Some((&self.files[origin.file].0, origin.line + past))
+    }
+
+    /// The text of the 1-based `line` as it was written, before any macro expansion.
+    pub fn text(&self, line: usize) -> Option<&str> {
+        let origin = self.lines.get(line.checked_sub(1)?)?;
+        self.files[origin.file].1.get(origin.line - 1).map(String::as_str)
+    }
+
+    /// The expansions on `line`, and the number of the first.
+    fn expansions(&self, line: usize) -> (usize, &[Expansion]) {
+        let start = self.expansions.partition_point(|expansion| expansion.line < line);
+        let end = self.expansions.partition_point(|expansion| expansion.line <= line);
+        (start, &self.expansions[start..end])
+    }
+
+    /// The columns that `cols` of `line` were written at, all 0-based and in characters.
+    /// Whatever came out of an expansion was written as its invocation.
+    pub fn columns(&self, line: usize, cols: Range<usize>) -> Range<usize> {
+        let (_, expansions) = self.expansions(line);
+        let written = |col: usize, end: bool| {
+            let mut shift = 0isize;
+            for expansion in expansions {
+                let (Some(call), flat) = (&expansion.call, &expansion.flat) else {
+                    continue;
+                };
+                if col < flat.start || (end && col == flat.start) {
+                    break;
+                }
+                if col < flat.end || (end && col == flat.end) {
+                    return if end { call.end } else { call.start };
+                }
+                shift = call.end as isize - flat.end as isize;
+            }
+            (col as isize + shift) as usize
+        };
+        let start = written(cols.start, false);
+        start..written(cols.end, true).max(start)
+    }
+
+    /// Notes naming the macros that column `col` of `line` came out of, innermost first.
+    pub fn backtrace(&self, line: usize, col: usize) -> Vec<String> {
+        let (start, expansions) = self.expansions(line);
+        // Expansions are numbered after the ones they happen inside of.
+        let mut current = expansions.iter().rposition(|expansion| expansion.flat.contains(&col)).map(|index| start + index);
+        let mut notes = Vec::new();
+        while let Some(index) = current {
+            let expansion = &self.expansions[index];
+            let defined = expansion.definition.and_then(|span| Some((self.origin(span.line)?, span.col)));
+            notes.push(match defined {
+                Some(((file, line), col)) => format!("in this expansion of `{}`, defined at {}:{}:{}", expansion.name, file, line, col),
+                None => format!("in this expansion of `{}`, which the compiler defines", expansion.name)
+            });
+            current = expansion.parent;
+        }
+        notes
+    }
+}
+
+/// The result of preprocessing: the source the parser reads, and how to find where each
+/// of its lines was written.
+pub(crate) struct Preprocessed {
+    pub text: String,
+    pub map: LineMap,
+    pub diagnostics: Vec<Diagnostic>
+}
+
+/// An `#if` being read, with its `#elif`s and `#else`.
+struct Conditional {
+    span: Span,
+    /// Whether the lines around the `#if` are kept; nothing inside it is otherwise.
+    outer: bool,
+    /// Whether the current branch is kept.
+    active: bool,
+    /// Whether some branch has been kept, so later ones aren't.
+    taken: bool,
+    seen_else: bool
+}
+
+/// A directive line, for pointing diagnostics into it.
+struct Directive<'l> {
+    line: &'l str,
+    /// The byte offset and 1-based number of the line in the flattened source.
+    start: usize,
+    number: usize
+}
+
+impl Directive<'_> {
+
+    /// The span of `range`, a byte range of the line. Directive lines are blanked one
+    /// space per character, so columns and offsets agree.
+    fn span(&self, range: Range<usize>) -> Span {
+        let col = self.line[..range.start].chars().count();
+        let len = self.line[range].chars().count();
+        Span::new(self.start + col, self.start + col + len, self.number, col + 1)
+    }
+}
+
+/// Flattens a file and the files it includes into one source, keeping the lines that
+/// `#if`s select and expanding `#define`d names. Every line of every file read gives
+/// one line of output, blank for directives and unselected lines, so line numbers only
+/// shift where another file is included.
+pub(crate) struct Preprocessor<'i> {
+    include_dirs: &'i [PathBuf],
+    globals: Globals,
+    macros: Vec<Macro>,
+    names: HashMap<String, usize>,
+    /// Every identifier written in the files the source may include, which the names
+    /// macros give their locals keep clear of.
+    written: HashSet<String>,
+    text: String,
+    map: LineMap,
+    diagnostics: Vec<Diagnostic>,
+    /// The files being read, outermost first, to catch a file that includes itself.
+    stack: Vec<PathBuf>
+}
+
+impl<'i> Preprocessor<'i> {
+
+    pub fn new(include_dirs: &'i [PathBuf], globals: Globals) -> Self {
+        Self {
+            include_dirs,
+            globals,
+            macros: Vec::new(),
+            names: HashMap::new(),
+            written: HashSet::new(),
+            text: String::new(),
+            map: LineMap::default(),
+            diagnostics: Vec::new(),
+            stack: Vec::new()
+        }
+    }
+
+    /// Preprocesses `src`, read from `path` and shown in diagnostics as `name`.
+    pub fn run(mut self, path: &Path, name: String, src: &str) -> Preprocessed {
+        self.gather(path, src, &mut Vec::new());
+        self.file(path, name, src);
+        Preprocessed {
+            text: self.text,
+            map: self.map,
+            diagnostics: self.diagnostics
+        }
+    }
+
+    fn push_line(&mut self, text: &str, file: usize, line: usize) {
+        self.text.push_str(text);
+        self.text.push('\n');
+        self.map.lines.push(Origin { file, line });
+    }
+
+    /// Collects the identifiers of `src` and, whatever the conditionals around them, of
+    /// the files it includes, skipping those in `seen`.
+    fn gather(&mut self, path: &Path, src: &str, seen: &mut Vec<PathBuf>) {
+        seen.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        let mut comment_depth = 0;
+        for line in src.lines() {
+            let included = line.trim_start().strip_prefix('#')
+                .and_then(|rest| rest.trim_start().strip_prefix("include"))
+                .and_then(|rest| strip_comment(rest).trim().strip_prefix('"')?.strip_suffix('"'))
+                .and_then(|name| self.locate(name, path));
+            let tokens = Token::tokenize(line, &mut comment_depth);
+            self.written.extend(tokens.into_iter().filter(|token| token.ty == node::Type::Ident).map(|token| token.text));
+
+            let Some(included) = included else {
+                continue;
+            };
+            let canonical = included.canonicalize().unwrap_or_else(|_| included.clone());
+            if !seen.contains(&canonical) {
+                if let Ok(src) = fs::read_to_string(&included) {
+                    self.gather(&included, &src, seen);
+                }
+            }
+        }
+    }
+
+    fn file(&mut self, path: &Path, name: String, src: &str) {
+        let file = self.map.add_file(name, src);
+        self.stack.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut comment_depth = 0;
+        for (index, line) in src.lines().enumerate() {
+            let active = conditionals.last().is_none_or(|conditional| conditional.active);
+            let directive = Directive { line, start: self.text.len(), number: self.map.lines.len() + 1 };
+            if comment_depth == 0 && line.trim_start().starts_with('#') {
+                self.push_line(&blank(line), file, index + 1);
+                self.directive(&directive, path, &mut conditionals);
+            } else if active {
+                let tokens = Token::tokenize(line, &mut comment_depth);
+                let mut expander = Expander::new(&self.macros, &self.names, &self.written, &self.globals, self.map.expansions.len(), directive.number, false);
+                let (expanded, errors) = expander.line(tokens);
+                self.map.expansions.append(&mut expander.expansions);
+                for (diag, cols) in errors {
+                    let span = line_span(&expanded, directive.start, directive.number, cols);
+                    self.diagnostics.push(diag.with_primary(span, ""));
+                }
+                self.push_line(&expanded, file, index + 1);
+            } else {
+                Token::tokenize(line, &mut comment_depth);
+                self.push_line(&blank(line), file, index + 1);
+            }
+        }
+
+        for conditional in conditionals {
+            self.diagnostics.push(Diagnostic::error("unterminated `#if`")
+                .with_code(codes::UNBALANCED_CONDITIONAL)
+                .with_primary(conditional.span, "")
+                .with_note("every `#if` needs an `#endif` in the same file"));
+        }
+        self.stack.pop();
+    }
+
+    fn directive(&mut self, directive: &Directive<'_>, path: &Path, conditionals: &mut Vec<Conditional>) {
+        let line = directive.line;
+        let hash = line.len() - line.trim_start().len();
+        let name_start = hash + 1 + (line[hash + 1..].len() - line[hash + 1..].trim_start().len());
+        let name_end = word_end(line, name_start);
+        let name = &line[name_start..name_end];
+        let rest = &line[name_end..];
+        let span = directive.span(hash..name_end);
+        let rest_start = name_end + (rest.len() - rest.trim_start().len());
+        let arguments = directive.span(rest_start..line.trim_end().len().max(rest_start));
+        let active = conditionals.last().is_none_or(|conditional| conditional.active);
+
+        match name {
+            "if" => {
+                let keep = active && self.condition(rest.trim(), arguments, name);
+                conditionals.push(Conditional { span, outer: active, active: keep, taken: keep, seen_else: false });
+            },
+            "elif" | "else" => {
+                let Some(conditional) = conditionals.last_mut() else {
+                    self.unbalanced(format!("`#{}` without `#if`", name), span);
+                    return;
+                };
+                if conditional.seen_else {
+                    self.unbalanced(format!("`#{}` after `#else`", name), span);
+                    return;
+                }
+                let (outer, taken) = (conditional.outer, conditional.taken);
+                let keep = outer && !taken && (name == "else" || self.condition(rest.trim(), arguments, name));
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = keep;
+                conditional.taken |= keep;
+                conditional.seen_else = name == "else";
+            },
+            "endif" => {
+                if conditionals.pop().is_none() {
+                    self.unbalanced("`#endif` without `#if`".to_string(), span);
+                }
+            },
+            _ if !active => {},
+            "include" => self.include(directive, rest, name_end, path),
+            "define" => self.define(directive, name_end),
+            "error" => {
+                let message = rest.trim();
+                let message = if message.is_empty() { "`#error`" } else { message };
+                self.diagnostics.push(Diagnostic::error(message)
+                    .with_code(codes::ERROR_DIRECTIVE)
+                    .with_primary(directive.span(hash..line.trim_end().len()), ""));
+            },
+            // A `#` on its own does nothing.
+            "" if rest.trim().is_empty() => {},
+            _ => self.diagnostics.push(Diagnostic::error(format!("unknown directive `#{}`", name))
+                .with_code(codes::UNKNOWN_DIRECTIVE)
+                .with_primary(span, "")
+                .with_note("the directives are `#include`, `#define`, `#if`, `#elif`, `#else`, `#endif` and `#error`"))
+        }
+    }
+
+    fn unbalanced(&mut self, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message).with_code(codes::UNBALANCED_CONDITIONAL).with_primary(span, ""));
+    }
+
+    fn malformed(&mut self, message: &str, span: Span) {
+        self.diagnostics.push(Diagnostic::error(message).with_code(codes::MALFORMED_DIRECTIVE).with_primary(span, ""));
+    }
+
+    /// Evaluates the condition of an `#if` or `#elif`, which is at `span`. `defined(NAME)`
+    /// is 1 when `NAME` is a macro or a predefined constant.
+    fn condition(&mut self, text: &str, span: Span, directive: &str) -> bool {
+        if text.is_empty() {
+            self.malformed(&format!("expected a condition after `#{}`", directive), span);
+            return false;
+        }
+        let text = self.defined(text);
+        match self.evaluate(&text, span, &mut Vec::new()) {
+            Ok(value) => value != 0,
+            Err(diag) => {
+                self.diagnostics.push(diag);
+                false
+            }
+        }
+    }
+
+    /// The value of the object-like macro `index` where a condition uses it.
+    fn evaluate_macro(&self, index: usize, expanding: &mut Vec<usize>) -> Result<i64, Diagnostic> {
+        let definition = &self.macros[index];
+        self.evaluate(&self.defined(&definition.value), definition.value_span, expanding)
+    }
+
+    /// Evaluates `text`, which is at `span`. Once function-like macros are expanded its
+    /// columns no longer match what was written, so errors in it point at all of `span`.
+    fn evaluate(&self, text: &str, span: Span, expanding: &mut Vec<usize>) -> Result<i64, Diagnostic> {
+        let expanded = self.expand_functions(text, span)?;
+        let yarn = Yarn::borrowed(&expanded);
+        OpNode::from_yarn(&yarn, span, self).and_then(|node| node.evaluate(self, expanding)).map_err(|mut diag| {
+            if expanded != text {
+                for label in diag.labels.iter_mut().filter(|label| label.span.line == span.line) {
+                    label.span = span;
+                }
+            }
+            diag
+        })
+    }
+
+    /// Expands the function-like macros in a condition written at `span`. The others are
+    /// left to be evaluated by name, so their errors can point into their definitions.
+    fn expand_functions(&self, text: &str, span: Span) -> Result<String, Diagnostic> {
+        let mut expander = Expander::new(&self.macros, &self.names, &self.written, &self.globals, 0, 0, true);
+        let (text, errors) = expander.line(Token::tokenize(text, &mut 0));
+        match errors.into_iter().next() {
+            Some((diag, _)) => Err(diag.with_primary(span, "")),
+            None => Ok(text)
+        }
+    }
+
+    /// Replaces each `defined NAME` and `defined(NAME)` in `text` with 1 or 0, padded
+    /// with spaces to keep the columns of what follows.
+    fn defined(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut pos = 0;
+        while let Some(ch) = text[pos..].chars().next() {
+            let rest = &text[pos..];
+            let len = if ch == '"' {
+                string_end(rest)
+            } else if ch.is_alphanumeric() || ch == '_' {
+                let word = &rest[..word_end(rest, 0)];
+                if let Some((name, len)) = defined_operand(&rest[word.len()..]).filter(|_| word == "defined") {
+                    let defined = self.names.contains_key(name) || Constants::from_name(name).is_some();
+                    let len = word.len() + len;
+                    out.push(if defined { '1' } else { '0' });
+                    out.push_str(&" ".repeat(rest[..len].chars().count() - 1));
+                    pos += len;
+                    continue;
+                }
+                word.len()
+            } else {
+                ch.len_utf8()
+            };
+            out.push_str(&rest[..len]);
+            pos += len;
+        }
+        out
+    }
+
+    fn define(&mut self, directive: &Directive<'_>, after: usize) {
+        let line = directive.line;
+        let start = after + (line[after..].len() - line[after..].trim_start().len());
+        let end = word_end(line, start);
+        if start == end || line[start..].starts_with(|ch: char| ch.is_ascii_digit()) {
+            self.malformed("expected a macro name after `#define`", directive.span(start..line.len()));
+            return;
+        }
+        let name = &line[start..end];
+        // A function-like macro has its parameters right after the name; with a space
+        // between, they are the start of the body.
+        let (params, variadic, body) = if line[end..].starts_with('(') {
+            match parameters(&line[end + 1..]) {
+                Ok((params, variadic, len)) => (Some(params), variadic, end + 1 + len),
+                Err(message) => {
+                    self.malformed(&message, directive.span(end..line.trim_end().len()));
+                    return;
+                }
+            }
+        } else {
+            (None, false, end)
+        };
+        let value = strip_comment(&line[body..]).trim();
+        let value_start = body + (line[body..].len() - line[body..].trim_start().len());
+        let definition = Macro {
+            name: name.to_string(),
+            params,
+            variadic,
+            value: value.to_string(),
+            body: Token::tokenize(value, &mut 0),
+            span: directive.span(start..end),
+            value_span: directive.span(value_start..value_start + value.len())
+        };
+        if let Err(message) = check_body(&definition) {
+            self.malformed(&message, definition.value_span);
+            return;
+        }
+
+        if let Some(&index) = self.names.get(name) {
+            let previous = &self.macros[index];
+            if (&previous.params, previous.variadic, &previous.value) != (&definition.params, definition.variadic, &definition.value) {
+                self.diagnostics.push(Diagnostic::warning(format!("`{}` redefined", name))
+                    .with_primary(definition.span, "")
+                    .with_secondary(previous.span, "previously defined here"));
+            }
+            self.macros[index] = definition;
+        } else {
+            self.names.insert(name.to_string(), self.macros.len());
+            self.macros.push(definition);
+        }
+    }
+
+    /// Finds the file `#include "name"` in `from` reads: next to `from` first, then in
+    /// each `-I` directory.
+    fn locate(&self, name: &str, from: &Path) -> Option<PathBuf> {
+        let here = from.parent().unwrap_or(Path::new("")).to_path_buf();
+        std::iter::once(&here).chain(self.include_dirs).map(|dir| dir.join(name)).find(|path| path.is_file())
+    }
+
+    /// Reads the file named in an `#include`, looking where `locate` does.
+    fn include(&mut self, directive: &Directive<'_>, rest: &str, after: usize, from: &Path) {
+        let argument = strip_comment(rest).trim();
+        let start = after + (rest.len() - rest.trim_start().len());
+        let span = directive.span(start..start + argument.len());
+        let Some(name) = argument.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')).filter(|name| !name.is_empty()) else {
+            self.malformed("expected a file name in quotes after `#include`", span);
+            return;
+        };
+
+        let Some(path) = self.locate(name, from) else {
+            let here = from.parent().unwrap_or(Path::new("")).to_path_buf();
+            let searched: Vec<String> = std::iter::once(&here).chain(self.include_dirs).map(|dir| {
+                let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir.as_path() };
+                format!("`{}`", dir.display())
+            }).collect();
+            self.diagnostics.push(Diagnostic::error(format!("couldn't find `{}`", name))
+                .with_code(codes::INCLUDE_NOT_FOUND)
+                .with_primary(span, "")
+                .with_note(format!("searched {}", searched.join(", ")))
+                .with_help("add the directory it is in with -I"));
+            return;
+        };
+
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.stack.contains(&canonical) {
+            self.diagnostics.push(Diagnostic::error(format!("`{}` includes itself", path.display()))
+                .with_code(codes::RECURSIVE_INCLUDE)
+                .with_primary(span, ""));
+            return;
+        }
+        match fs::read_to_string(&path) {
+            Ok(src) => self.file(&path, path.display().to_string(), &src),
+            Err(err) => self.diagnostics.push(Diagnostic::error(format!("couldn't read `{}`: {}", path.display(), err))
+                .with_code(codes::INCLUDE_NOT_FOUND)
+                .with_primary(span, ""))
+        }
+    }
+}
+
+/// The span of columns `cols` of `text`, a line of the flattened source starting at
+/// byte `start`.
+fn line_span(text: &str, start: usize, number: usize, cols: Range<usize>) -> Span {
+    let byte = |col: usize| text.char_indices().nth(col).map_or(text.len(), |(index, _)| index);
+    Span::new(start + byte(cols.start), start + byte(cols.end), number, cols.start + 1)
+}
+
+/// A line of spaces as long, in characters, as `line`.
+fn blank(line: &str) -> String {
+    " ".repeat(line.chars().count())
+}
+
+/// The end of the word starting at `start` in `text`.
+fn word_end(text: &str, start: usize) -> usize {
+    text[start..].find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).map_or(text.len(), |end| start + end)
+}
+
+/// The length of the string literal that `text` starts with, up to and including its
+/// closing quote or the end of the line.
+fn string_end(text: &str) -> usize {
+    let mut escaped = false;
+    for (index, ch) in text.char_indices().skip(1) {
+        match ch {
+            '"' if !escaped => return index + 1,
+            '\\' => escaped = !escaped,
+            _ => escaped = false
+        }
+    }
+    text.len()
+}
+
+/// Drops a `//` comment from the end of a directive, outside of any string.
+fn strip_comment(text: &str) -> &str {
+    let mut pos = 0;
+    while let Some(ch) = text[pos..].chars().next() {
+        if text[pos..].starts_with("//") {
+            return &text[..pos];
+        }
+        pos += if ch == '"' { string_end(&text[pos..]) } else { ch.len_utf8() };
+    }
+    text
+}
+
+/// The name after `defined`, in parentheses or not, and how much of `text` it takes.
+fn defined_operand(text: &str) -> Option<(&str, usize)> {
+    let trimmed = text.trim_start();
+    let (inner, parens) = match trimmed.strip_prefix('(') {
+        Some(inner) => (inner.trim_start(), true),
+        None => (trimmed, false)
+    };
+    let name = &inner[..word_end(inner, 0)];
+    if name.is_empty() {
+        return None;
+    }
+    let after = &inner[name.len()..];
+    let len = if parens {
+        let close = after.trim_start().strip_prefix(')')?;
+        text.len() - close.len()
+    } else {
+        text.len() - after.len()
+    };
+    Some((name, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory holding `files`, by path relative to it.
+    fn tree(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("beta-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, src) in files {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, src).unwrap();
+        }
+        root
+    }
+
+    /// Preprocesses `main.beta` in `root`, searching `include_dirs` after it.
+    fn run(root: &Path, include_dirs: &[PathBuf]) -> Preprocessed {
+        let path = root.join("main.beta");
+        let src = fs::read_to_string(&path).unwrap();
+        Preprocessor::new(include_dirs, Globals::new(None)).run(&path, "main.beta".into(), &src)
+    }
+
+    fn preprocess(src: &str) -> Preprocessed {
+        Preprocessor::new(&[], Globals::new(None)).run(Path::new("test.beta"), "test.beta".into(), src)
+    }
+
+    /// The lines of `preprocessed` that aren't blank.
+    fn lines(preprocessed: &Preprocessed) -> Vec<&str> {
+        preprocessed.text.lines().filter(|line| !line.trim().is_empty()).collect()
+    }
+
+    fn errors(preprocessed: &Preprocessed) -> Vec<(Option<&str>, &str)> {
+        preprocessed.diagnostics.iter().map(|diag| (diag.code, diag.message.as_str())).collect()
+    }
+
+    #[test]
+    fn includes_look_next_to_the_including_file_first() {
+        let root = tree("search", &[
+            ("main.beta", "#include \"a.beta\"\n#include \"b.beta\"\n"),
+            ("a.beta", "let here = 1;\n"),
+            ("lib/a.beta", "let there = 1;\n"),
+            ("lib/b.beta", "#include \"c.beta\"\n"),
+            ("lib/c.beta", "let nested = 1;\n")
+        ]);
+        let preprocessed = run(&root, &[root.join("lib")]);
+        assert_eq!(errors(&preprocessed), []);
+        // `c.beta` is found next to `b.beta`, which includes it.
+        assert_eq!(lines(&preprocessed), ["let here = 1;", "let nested = 1;"]);
+
+        let preprocessed = run(&root, &[]);
+        assert_eq!(errors(&preprocessed), [(Some(codes::INCLUDE_NOT_FOUND), "couldn't find `b.beta`")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn include_cycles_are_cut() {
+        let root = tree("cycle", &[
+            ("main.beta", "#include \"a.beta\"\n#include \"a.beta\"\n"),
+            ("a.beta", "let a = 1;\n#include \"b.beta\"\n"),
+            ("b.beta", "#include \"a.beta\"\n")
+        ]);
+        let preprocessed = run(&root, &[]);
+        let cycle = format!("`{}` includes itself", root.join("a.beta").display());
+        // Including a file twice is fine; only including it from inside itself is not.
+        assert_eq!(errors(&preprocessed), [(Some(codes::RECURSIVE_INCLUDE), cycle.as_str()); 2]);
+        assert_eq!(lines(&preprocessed), ["let a = 1;", "let a = 1;"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let preprocessed = preprocess("\
+#if 0
+#if 1
+let a = 1;
+#else
+let b = 1;
+#endif
+#elif 2 > 1
+let c = 1;
+#else
+let d = 1;
+#endif
+");
+        assert_eq!(errors(&preprocessed), []);
+        assert_eq!(lines(&preprocessed), ["let c = 1;"]);
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        let preprocessed = preprocess("#else\n#endif\n#if 1\n#else\n#elif 1\n#else\n#endif\n#if 1\n");
+        let unbalanced = Some(codes::UNBALANCED_CONDITIONAL);
+        assert_eq!(errors(&preprocessed), [
+            (unbalanced, "`#else` without `#if`"),
+            (unbalanced, "`#endif` without `#if`"),
+            (unbalanced, "`#elif` after `#else`"),
+            (unbalanced, "`#else` after `#else`"),
+            (unbalanced, "unterminated `#if`")
+        ]);
+    }
+
+    #[test]
+    fn error_directives() {
+        let preprocessed = preprocess("#if 0\n#error not this one\n#endif\n#error stop here\n#error\n");
+        assert_eq!(errors(&preprocessed), [
+            (Some(codes::ERROR_DIRECTIVE), "stop here"),
+            (Some(codes::ERROR_DIRECTIVE), "`#error`")
+        ]);
+        assert_eq!(preprocessed.diagnostics[0].primary_span().map(|span| span.line), Some(4));
+    }
+
+    #[test]
+    fn lines_map_back_to_included_files() {
+        let root = tree("map", &[
+            ("main.beta", "let a = 1;\n#include \"inc.beta\"\nlet b = 2;\n"),
+            ("inc.beta", "// included\nlet c = 3;\n")
+        ]);
+        let preprocessed = run(&root, &[]);
+        let included = root.join("inc.beta").display().to_string();
+        let map = &preprocessed.map;
+        assert_eq!(preprocessed.text.lines().nth(3), Some("let c = 3;"));
+        assert_eq!(map.origin(1), Some(("main.beta", 1)));
+        assert_eq!(map.origin(4), Some((included.as_str(), 2)));
+        assert_eq!(map.origin(5), Some(("main.beta", 3)));
+        assert_eq!(map.text(4), Some("let c = 3;"));
+        // The end of input continues from the last line.
+        assert_eq!(map.origin(6), Some(("main.beta", 4)));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
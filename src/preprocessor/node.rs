@@ -1,10 +1,23 @@
-use crate::common::{syntax_tree::{BinOp, UniOp}, yarn::Yarn, Constants};
+use crate::{
+    common::{
+        diagnostics::{codes, Diagnostic},
+        lexer::Span,
+        parser::{ParseError, ParseErrorKind, Parser},
+        syntax_tree::{BinOp, Literal, Node, UniOp},
+        yarn::Yarn,
+        Constants
+    },
+    interpreter::{arith_error, value::ArithError}
+};
+
+use super::{string_end, word_end, Preprocessor};
 
 
 
 pub(super) enum Value {
     Predefined(Constants),
     ProgramDefined(usize),
+    Integer(u64),
     Undefined
 }
 
@@ -12,29 +25,368 @@ pub(super) enum OpNode {
     BinaryOp {
         lhs: Box<OpNode>,
         rhs: Box<OpNode>,
-        op: BinOp
+        op: BinOp,
+        span: Span
     },
     UnaryOp {
         lhs: Box<OpNode>,
-        op: UniOp
+        op: UniOp,
+        span: Span
     },
     Value {
-        inner: Value
+        inner: Value,
+        span: Span
     }
 }
 
+/// What a preprocessor token is. Only names take part in expansion; everything else
+/// is carried through as written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(super) enum Type {
+    Ident,
+    Number,
+    Str,
+    Punct,
+    Space,
+    Comment
+}
 
+/// A piece of a line as the preprocessor sees it.
+#[derive(Clone, Debug)]
+pub(super) struct Token {
+    pub ty: Type,
+    pub text: String,
+    /// The 0-based column, in characters, the token starts at in the text it was read from.
+    pub col: usize,
+    /// The expansion the token came out of, or `None` if it was written where it is.
+    pub expansion: Option<usize>
 }
 
-pub(super) struct Token<'a> {
-    ty: Type,
-    chunk: &'a Chunk<'a>,
+impl Token {
+
+    pub fn new(ty: Type, text: impl Into<String>) -> Self {
+        Self { ty, text: text.into(), col: 0, expansion: None }
+    }
+
+    pub fn is(&self, text: &str) -> bool {
+        self.ty == Type::Punct && self.text == text
+    }
+
+    /// Whether the token leaves the meaning of what's around it alone.
+    pub fn is_blank(&self) -> bool {
+        matches!(self.ty, Type::Space | Type::Comment)
+    }
+
+    /// Splits `text` into tokens. `comment_depth` carries nested block comments from one
+    /// line to the next.
+    pub fn tokenize(text: &str, comment_depth: &mut usize) -> Vec<Self> {
+        let mut tokens = Vec::new();
+        let (mut pos, mut col) = (0, 0);
+        while let Some(ch) = text[pos..].chars().next() {
+            let rest = &text[pos..];
+            let (ty, len) = if *comment_depth > 0 {
+                (Type::Comment, comment_end(rest, comment_depth))
+            } else if rest.starts_with("//") {
+                (Type::Comment, rest.len())
+            } else if let Some(inner) = rest.strip_prefix("/*") {
+                *comment_depth += 1;
+                (Type::Comment, 2 + comment_end(inner, comment_depth))
+            } else if ch == '"' {
+                (Type::Str, string_end(rest))
+            } else if ch.is_alphabetic() || ch == '_' {
+                (Type::Ident, word_end(rest, 0))
+            } else if ch.is_ascii_digit() {
+                let mut end = word_end(rest, 0);
+                // The fraction of a float belongs to the number.
+                if rest[end..].starts_with('.') && rest[end + 1..].starts_with(|ch: char| ch.is_ascii_digit()) {
+                    end = word_end(rest, end + 1);
+                }
+                (Type::Number, end)
+            } else if ch.is_whitespace() {
+                (Type::Space, rest.find(|ch: char| !ch.is_whitespace()).unwrap_or(rest.len()))
+            } else if rest.starts_with("...") {
+                (Type::Punct, 3)
+            } else if rest.starts_with("##") {
+                (Type::Punct, 2)
+            } else {
+                (Type::Punct, ch.len_utf8())
+            };
+            let text = &rest[..len];
+            tokens.push(Self { ty, text: text.to_string(), col, expansion: None });
+            pos += len;
+            col += text.chars().count();
+        }
+        tokens
+    }
+}
+
+/// The length of the rest of a block comment that `text` is inside of, `depth` deep,
+/// up to and including its last `*/` or the end of the line.
+fn comment_end(text: &str, depth: &mut usize) -> usize {
+    let mut pos = 0;
+    while let Some(ch) = text[pos..].chars().next() {
+        if text[pos..].starts_with("*/") {
+            *depth -= 1;
+            pos += 2;
+            if *depth == 0 {
+                return pos;
+            }
+        } else if text[pos..].starts_with("/*") {
+            *depth += 1;
+            pos += 2;
+        } else {
+            pos += ch.len_utf8();
+        }
+    }
+    pos
 }
 
 impl OpNode {
-    pub(super) fn from_yarn<'a>(string: &Yarn<'a>) -> Self {
-        todo!()
+    /// Parses a constant expression, using the same operator precedence as the main parser.
+    /// Names are looked up in `preprocessor`: its macros first, then the predefined
+    /// constants; any other name is undefined. The expression is read on its own but
+    /// was written at `at`, so spans, including those of errors, are moved there.
+    pub(super) fn from_yarn<'a>(string: &Yarn<'a>, at: Span, preprocessor: &Preprocessor<'_>) -> Result<Self, Diagnostic> {
+        let place = |span: Span| place(span, string.as_slice(), at);
+        let parse = || {
+            let mut parser = Parser::new(string);
+            let expr = parser.parse_expr()?;
+            parser.expect_end()?;
+            if let Some(err) = parser.take_errors().into_iter().next() {
+                return Err(err);
+            }
+            Self::from_node(&expr, preprocessor)
+        };
+        match parse() {
+            Ok(mut node) => {
+                node.place(&place);
+                Ok(node)
+            },
+            Err(mut diag) => {
+                for label in &mut diag.labels {
+                    label.span = place(label.span);
+                }
+                Err(diag)
+            }
+        }
+    }
+
+    fn place(&mut self, place: &impl Fn(Span) -> Span) {
+        match self {
+            Self::BinaryOp { lhs, rhs, span, .. } => {
+                lhs.place(place);
+                rhs.place(place);
+                *span = place(*span);
+            },
+            Self::UnaryOp { lhs, span, .. } => {
+                lhs.place(place);
+                *span = place(*span);
+            },
+            Self::Value { span, .. } => *span = place(*span)
+        }
+    }
+
+    fn from_node(node: &Node<'_>, preprocessor: &Preprocessor<'_>) -> Result<Self, Diagnostic> {
+        match node {
+            Node::BinaryOp { lhs, rhs, op, span } => Ok(Self::BinaryOp {
+                lhs: Box::new(Self::from_node(lhs, preprocessor)?),
+                rhs: Box::new(Self::from_node(rhs, preprocessor)?),
+                op: *op,
+                span: *span
+            }),
+            Node::UnaryOp { lhs, op: op @ (UniOp::Negative | UniOp::LogNot | UniOp::BitNot), span, .. } => Ok(Self::UnaryOp {
+                lhs: Box::new(Self::from_node(lhs, preprocessor)?),
+                op: *op,
+                span: *span
+            }),
+            Node::Literal { value: Literal::Int(value), span } => Ok(Self::Value {
+                inner: Value::Integer(*value),
+                span: *span
+            }),
+            Node::Literal { value: Literal::Boolean(value), span } => Ok(Self::Value {
+                inner: Value::Integer(*value as u64),
+                span: *span
+            }),
+            Node::Ident { name, span } => {
+                let name = name.as_slice();
+                let inner = match (preprocessor.names.get(name), Constants::from_name(name)) {
+                    (Some(&index), _) => Value::ProgramDefined(index),
+                    (None, Some(constant)) => Value::Predefined(constant),
+                    (None, None) => Value::Undefined
+                };
+                Ok(Self::Value { inner, span: *span })
+            },
+            other => Err(ParseError {
+                kind: ParseErrorKind::NotConstant,
+                span: other.span()
+            }.into())
+        }
     }
+
+    /// The value of the expression, with comparisons and logical operators giving 0 or
+    /// 1 and undefined names reading as 0. Arithmetic is on signed 64-bit integers and
+    /// fails the way it would at runtime. `expanding` holds the macros being evaluated;
+    /// a macro that refers to itself reads as undefined there.
+    pub(super) fn evaluate(&self, preprocessor: &Preprocessor<'_>, expanding: &mut Vec<usize>) -> Result<i64, Diagnostic> {
+        match self {
+            Self::Value { inner: Value::Integer(value), span } => i64::try_from(*value).map_err(|_| {
+                Diagnostic::error(format!("`{}` doesn't fit in a 64-bit signed integer", value))
+                    .with_code(codes::LITERAL_OUT_OF_RANGE)
+                    .with_primary(*span, "")
+            }),
+            Self::Value { inner: Value::Undefined, .. } => Ok(0),
+            Self::Value { inner: Value::Predefined(constant), .. } => Ok(preprocessor.globals.value(*constant)),
+            Self::Value { inner: Value::ProgramDefined(index), .. } if expanding.contains(index) => Ok(0),
+            Self::Value { inner: Value::ProgramDefined(index), span } if preprocessor.macros[*index].params.is_some() => {
+                let name = &preprocessor.macros[*index].name;
+                Err(Diagnostic::error(format!("`{}` takes arguments", name))
+                    .with_code(codes::WRONG_MACRO_ARG_COUNT)
+                    .with_primary(*span, "")
+                    .with_secondary(preprocessor.macros[*index].span, "defined here"))
+            },
+            Self::Value { inner: Value::ProgramDefined(index), span } => {
+                expanding.push(*index);
+                let result = preprocessor.evaluate_macro(*index, expanding);
+                expanding.pop();
+                result.map_err(|diag| {
+                    let name = &preprocessor.macros[*index].name;
+                    diag.with_secondary(*span, format!("in this expansion of `{}`", name))
+                })
+            },
+            Self::UnaryOp { lhs, op, span } => {
+                let value = lhs.evaluate(preprocessor, expanding)?;
+                match op {
+                    UniOp::Negative => value.checked_neg().ok_or_else(|| arith_error(ArithError::Overflow, "negate").with_primary(*span, "")),
+                    UniOp::LogNot => Ok((value == 0) as i64),
+                    UniOp::BitNot => Ok(!value),
+                    UniOp::Increment | UniOp::Decrement => Err(not_allowed(op.as_str(), *span))
+                }
+            },
+            Self::BinaryOp { lhs, rhs, op, span } => {
+                let lhs = lhs.evaluate(preprocessor, expanding)?;
+                // Only the operand that decides the result is evaluated, as at runtime.
+                match op {
+                    BinOp::LogAnd if lhs == 0 => return Ok(0),
+                    BinOp::LogOr if lhs != 0 => return Ok(1),
+                    _ => {}
+                }
+                let rhs = rhs.evaluate(preprocessor, expanding)?;
+                let checked = match op {
+                    BinOp::Add => lhs.checked_add(rhs),
+                    BinOp::Subtract => lhs.checked_sub(rhs),
+                    BinOp::Multiply => lhs.checked_mul(rhs),
+                    BinOp::Divide => lhs.checked_div(rhs),
+                    BinOp::Modulus => lhs.checked_rem(rhs),
+                    BinOp::Equals => Some((lhs == rhs) as i64),
+                    BinOp::NotEquals => Some((lhs != rhs) as i64),
+                    BinOp::GreaterThan => Some((lhs > rhs) as i64),
+                    BinOp::GreaterThanEq => Some((lhs >= rhs) as i64),
+                    BinOp::LessThan => Some((lhs < rhs) as i64),
+                    BinOp::LessThanEq => Some((lhs <= rhs) as i64),
+                    BinOp::LogAnd | BinOp::LogOr => Some((rhs != 0) as i64),
+                    _ => return Err(not_allowed(op.as_str(), *span))
+                };
+                checked.ok_or_else(|| {
+                    let err = if rhs == 0 { ArithError::DivideByZero } else { ArithError::Overflow };
+                    arith_error(err, op.verb()).with_primary(*span, "")
+                })
+            }
+        }
+    }
+}
+
+/// Where `span`, in `text` read on its own, is in a line where `text` starts at `at`.
+fn place(span: Span, text: &str, at: Span) -> Span {
+    let chars = |offset: usize| text.get(..offset.min(text.len())).map_or(offset, |prefix| prefix.chars().count());
+    let (start, end) = (chars(span.start), chars(span.end));
+    Span::new(at.start + start, at.start + end, at.line, at.col + start)
+}
+
+fn not_allowed(op: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("`{}` can't be used in a condition", op))
+        .with_code(codes::NOT_CONSTANT)
+        .with_primary(span, "")
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::common::{Globals, Targets};
+
+    use super::*;
+
+    /// Evaluates `src` as a condition written at column 5 of line 3, for `target`.
+    fn evaluate(src: &str, target: Option<Targets>) -> Result<i64, Diagnostic> {
+        let preprocessor = Preprocessor::new(&[], Globals::new(target));
+        let at = Span::new(40, 40 + src.len(), 3, 5);
+        OpNode::from_yarn(&Yarn::borrowed(src), at, &preprocessor).and_then(|node| node.evaluate(&preprocessor, &mut Vec::new()))
+    }
+
+    fn value(src: &str) -> i64 {
+        evaluate(src, None).unwrap_or_else(|diag| panic!("`{}`: {}", src, diag.message))
+    }
+
+    fn failure(src: &str) -> (Option<&'static str>, String) {
+        let diag = evaluate(src, None).expect_err(src);
+        (diag.code, diag.message)
+    }
+
+    #[test]
+    fn arithmetic_and_logic() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("-7 / 2"), -3);
+        assert_eq!(value("-7 % 2"), -1);
+        assert_eq!(value("2 < 3 && !(1 == 2)"), 1);
+        assert_eq!(value("~0"), -1);
+        // The side that doesn't decide the result isn't evaluated.
+        assert_eq!(value("0 && 1 / 0"), 0);
+        assert_eq!(value("1 || 1 / 0"), 1);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(failure("1 / 0"), (Some(codes::DIVIDE_BY_ZERO), "attempt to divide by zero".into()));
+        assert_eq!(failure("1 % (2 - 2)"), (Some(codes::DIVIDE_BY_ZERO), "attempt to calculate the remainder with a divisor of zero".into()));
+    }
+
+    #[test]
+    fn overflow() {
+        let overflow = |verb: &str| (Some(codes::ARITHMETIC_OVERFLOW), format!("attempt to {} with overflow", verb));
+        assert_eq!(failure("9223372036854775807 + 1"), overflow("add"));
+        assert_eq!(failure("(-9223372036854775807 - 1) / -1"), overflow("divide"));
+        assert_eq!(failure("(-9223372036854775807 - 1) % -1"), overflow("calculate the remainder"));
+        assert_eq!(failure("9223372036854775808").0, Some(codes::LITERAL_OUT_OF_RANGE));
+        assert_eq!(value("-9223372036854775807 - 1"), i64::MIN);
+    }
+
+    #[test]
+    fn errors_point_where_the_condition_was_written() {
+        let diag = evaluate("1 + 2 / 0", None).unwrap_err();
+        let span = diag.primary_span().unwrap();
+        assert_eq!((span.line, span.col, span.start, span.end), (3, 9, 44, 49));
+    }
+
+    #[test]
+    fn predefined_constants_follow_the_target() {
+        let of = |src: &str, target| evaluate(src, Some(target)).unwrap();
+        assert_eq!(of("__POINTER_WIDTH__", Targets::X86_64Linux), 64);
+        assert_eq!(of("__POINTER_WIDTH__", Targets::Wasm32), 32);
+        assert_eq!(of("__TARGET_OS__ == __OS_LINUX__", Targets::X86_64Linux), 1);
+        assert_eq!(of("__TARGET_OS__ == __OS_WASI__", Targets::Wasm32), 1);
+        assert_eq!(of("__TARGET_OS__", Targets::Bytecode), 0);
+        assert_eq!(of("__LITTLE_ENDIAN__ && !__BIG_ENDIAN__", Targets::Wasm32), 1);
+    }
+
+    #[test]
+    fn undefined_names_are_zero() {
+        assert_eq!(value("NOT_DEFINED"), 0);
+        assert_eq!(value("NOT_DEFINED + 3"), 3);
+        assert_eq!(value("!NOT_DEFINED"), 1);
+    }
+
+    #[test]
+    fn only_constant_expressions() {
+        assert_eq!(failure("f(1)").0, Some(codes::NOT_CONSTANT));
+        assert_eq!(failure("y++").0, Some(codes::NOT_CONSTANT));
+        assert_eq!(failure("x = 1").0, Some(codes::EXPECTED_TOKEN));
+    }
+}
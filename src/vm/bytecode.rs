@@ -0,0 +1,233 @@
+use std::{
+    fmt::{Display, Formatter, Result},
+    hash::{Hash, Hasher}
+};
+
+use crate::{
+    analysis::symbols::Builtin,
+    interpreter::value::{IntTy, Value},
+    ir::{BinaryOp, UnaryOp}
+};
+
+/// A value in the constant pool. Unlike IR constants, these are typed: the pool holds
+/// exactly the value an instruction pushes.
+#[derive(Clone, Debug)]
+pub(crate) enum Constant {
+    Int(i128, IntTy),
+    Float(f64, u32),
+    Bool(bool),
+    /// Also the names of functions, globals, classes, fields and methods.
+    Str(String)
+}
+
+impl Constant {
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Int(value, ty) => Value::Int(*value, *ty),
+            Self::Float(value, bits) => Value::float(*value, *bits),
+            Self::Bool(value) => Value::Bool(*value),
+            Self::Str(value) => Value::Str(value.as_str().into())
+        }
+    }
+}
+
+/// Floats are compared by their bits, so the pool keeps `0.0` and `-0.0` apart and
+/// can hold a NaN.
+impl PartialEq for Constant {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Int(a, aty), Self::Int(b, bty)) => a == b && aty == bty,
+            (Self::Float(a, abits), Self::Float(b, bbits)) => a.to_bits() == b.to_bits() && abits == bbits,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Str(a), Self::Str(b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+impl Eq for Constant {}
+
+impl Hash for Constant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Int(value, ty) => (value, ty).hash(state),
+            Self::Float(value, bits) => (value.to_bits(), bits).hash(state),
+            Self::Bool(value) => value.hash(state),
+            Self::Str(value) => value.hash(state)
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Int(value, ty) => write!(f, "{}{} {}", if ty.signed { "i" } else { "u" }, ty.bits, value),
+            Self::Float(value, bits) => write!(f, "f{} {:?}", bits, value),
+            Self::Bool(value) => write!(f, "bool {}", value),
+            Self::Str(value) => write!(f, "str {:?}", value)
+        }
+    }
+}
+
+/// One instruction. Operands come off the stack and results go back on it; locals are
+/// slots in the current frame, with the arguments in the first ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    /// Pushes a constant from the pool.
+    Const(u32),
+    Load(u32),
+    Store(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    /// Jumps go to an instruction index in the same function.
+    Jump(u32),
+    /// Pops a `Boolean` and jumps when it is true.
+    JumpIf(u32),
+    JumpUnless(u32),
+    /// Calls a function by index, taking its arguments off the stack and pushing its
+    /// result, which is `()` for a function without one.
+    Call(u32),
+    /// Calls the method named by a pool string on the class of the receiver, the first
+    /// of the given number of arguments.
+    Invoke(u32, u32),
+    /// Calls a runtime function with the given number of arguments; it pushes nothing.
+    Builtin(Builtin, u32),
+    /// Builds an instance of a class from as many values as it has fields.
+    New(u32),
+    /// Reads the field with this position in the instance's class.
+    GetField(u32),
+    /// Pops a value and an instance and stores the value in the instance.
+    SetField(u32),
+    Pop,
+    Return,
+    ReturnVoid,
+    Unreachable
+}
+
+impl Op {
+
+    /// Whether control never continues with the next instruction.
+    pub fn ends_block(&self) -> bool {
+        matches!(self, Self::Jump(_) | Self::Return | Self::ReturnVoid | Self::Unreachable)
+    }
+
+    /// The instruction a jump may go to.
+    pub fn target(&self) -> Option<u32> {
+        match self {
+            Self::Jump(target) | Self::JumpIf(target) | Self::JumpUnless(target) => Some(*target),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Global {
+    /// Pool index of the name.
+    pub name: u32,
+    /// Pool index of the initial value; globals of a class type start out as `()` until
+    /// `.init` stores an instance.
+    pub init: Option<u32>
+}
+
+/// The runtime shape of an `obj` or `comp`, and the methods its instances dispatch to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Class {
+    pub name: u32,
+    /// Pool indices of the field names, in layout order.
+    pub fields: Vec<u32>,
+    /// Pool index of each method name with the function implementing it.
+    pub methods: Vec<(u32, u32)>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Function {
+    pub name: u32,
+    pub params: u32,
+    /// The number of local slots, parameters included.
+    pub locals: u32,
+    pub code: Vec<Op>
+}
+
+/// A compiled program: what a `.bbc` file holds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Program {
+    pub constants: Vec<Constant>,
+    pub globals: Vec<Global>,
+    pub classes: Vec<Class>,
+    pub functions: Vec<Function>
+}
+
+impl Program {
+
+    /// The pool string at `index`, which names something in the program.
+    pub fn name(&self, index: u32) -> &str {
+        match self.constants.get(index as usize) {
+            Some(Constant::Str(name)) => name,
+            _ => "?"
+        }
+    }
+
+    pub fn function(&self, name: &str) -> Option<u32> {
+        self.functions.iter().position(|function| self.name(function.name) == name).map(|index| index as u32)
+    }
+
+    fn op(&self, f: &mut Formatter<'_>, op: &Op) -> Result {
+        match op {
+            Op::Const(index) => write!(f, "const #{} ; {}", index, self.constants[*index as usize]),
+            Op::Load(slot) => write!(f, "load {}", slot),
+            Op::Store(slot) => write!(f, "store {}", slot),
+            Op::GetGlobal(index) => write!(f, "get_global @{}", self.name(self.globals[*index as usize].name)),
+            Op::SetGlobal(index) => write!(f, "set_global @{}", self.name(self.globals[*index as usize].name)),
+            Op::Binary(op) => f.write_str(op.as_str()),
+            Op::Unary(op) => f.write_str(op.as_str()),
+            Op::Jump(target) => write!(f, "jump {}", target),
+            Op::JumpIf(target) => write!(f, "jump_if {}", target),
+            Op::JumpUnless(target) => write!(f, "jump_unless {}", target),
+            Op::Call(index) => write!(f, "call @{}", self.name(self.functions[*index as usize].name)),
+            Op::Invoke(name, argc) => write!(f, "invoke {}/{}", self.name(*name), argc),
+            Op::Builtin(builtin, argc) => write!(f, "{}/{}", builtin.name(), argc),
+            Op::New(index) => write!(f, "new {}", self.name(self.classes[*index as usize].name)),
+            Op::GetField(index) => write!(f, "get_field {}", index),
+            Op::SetField(index) => write!(f, "set_field {}", index),
+            Op::Pop => f.write_str("pop"),
+            Op::Return => f.write_str("return"),
+            Op::ReturnVoid => f.write_str("return_void"),
+            Op::Unreachable => f.write_str("unreachable")
+        }
+    }
+}
+
+/// A listing of the program, for `--emit=bytecode`.
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "constants:")?;
+        for (index, constant) in self.constants.iter().enumerate() {
+            writeln!(f, "    #{} = {}", index, constant)?;
+        }
+        for global in &self.globals {
+            match global.init {
+                Some(init) => writeln!(f, "global @{} = #{}", self.name(global.name), init)?,
+                None => writeln!(f, "global @{}", self.name(global.name))?
+            }
+        }
+        for class in &self.classes {
+            let fields: Vec<&str> = class.fields.iter().map(|field| self.name(*field)).collect();
+            writeln!(f, "class {} {{ {} }}", self.name(class.name), fields.join(", "))?;
+            for (name, function) in &class.methods {
+                writeln!(f, "    {} -> @{}", self.name(*name), self.name(self.functions[*function as usize].name))?;
+            }
+        }
+        for function in &self.functions {
+            writeln!(f, "\nfn @{} (params {}, locals {}):", self.name(function.name), function.params, function.locals)?;
+            for (index, op) in function.code.iter().enumerate() {
+                write!(f, "{:>6}: ", index)?;
+                self.op(f, op)?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
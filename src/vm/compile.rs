@@ -0,0 +1,349 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::{
+    analysis::symbols::Builtin,
+    codegen::{check, operand_type, type_of},
+    common::diagnostics::Diagnostic,
+    interpreter::value::IntTy,
+    ir::{ssa, BlockId, Const, Function, Inst, Module, Operand, Reg, Terminator, Ty}
+};
+
+use super::bytecode::{self, Class, Constant, Global, Op, Program};
+
+fn builtin(name: &str) -> Option<Builtin> {
+    Builtin::ALL.iter().copied().find(|builtin| builtin.name() == name)
+}
+
+/// The pool entry for `value` read as a `ty`.
+fn constant(value: &Const, ty: &Ty) -> Constant {
+    match value {
+        Const::Int(value) if ty.is_integer() => Constant::Int(*value, IntTy { bits: ty.bits().unwrap_or(64), signed: ty.is_signed() }),
+        Const::Int(value) if ty.is_float() => Constant::Float(*value as f64, ty.bits().unwrap_or(64)),
+        Const::Int(value) => Constant::Int(*value, IntTy { bits: 64, signed: *value <= i64::MAX as i128 }),
+        Const::Float(value) => Constant::Float(*value, ty.bits().filter(|_| ty.is_float()).unwrap_or(64)),
+        Const::Bool(value) => Constant::Bool(*value),
+        Const::Str(value) => Constant::Str(value.clone())
+    }
+}
+
+/// Builds the program's tables; functions and classes are numbered before any code is
+/// compiled, so calls and `new` can refer forward.
+struct Compiler<'m> {
+    module: &'m Module,
+    program: Program,
+    pool: HashMap<Constant, u32>,
+    functions: HashMap<&'m str, u32>,
+    classes: HashMap<&'m str, u32>,
+    globals: HashMap<&'m str, u32>
+}
+
+impl<'m> Compiler<'m> {
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        match self.pool.entry(constant) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                self.program.constants.push(entry.key().clone());
+                *entry.insert(self.program.constants.len() as u32 - 1)
+            }
+        }
+    }
+
+    fn string(&mut self, value: &str) -> u32 {
+        self.constant(Constant::Str(value.to_string()))
+    }
+
+    /// The class and method name a call to `func` dispatches through, when `func` is a
+    /// method taking an instance of its own type first.
+    fn method(&self, func: &str) -> Option<&'m str> {
+        let function = self.module.function(func)?;
+        let (owner, method) = function.name.rsplit_once("::")?;
+        let takes_self = function.params.first().is_some_and(|(_, ty)| matches!(ty, Ty::Struct(name) if name == owner));
+        (takes_self && self.classes.contains_key(owner)).then_some(method)
+    }
+}
+
+/// Compiles one function. Each register gets a local slot; an instruction pushes its
+/// operands, runs, and stores its result, so the stack is empty between instructions.
+struct FunctionCompiler<'c, 'm> {
+    compiler: &'c mut Compiler<'m>,
+    function: &'m Function,
+    types: HashMap<Reg, Ty>,
+    slots: HashMap<Reg, u32>,
+    code: Vec<Op>,
+    starts: HashMap<BlockId, u32>,
+    /// Jumps to blocks that hadn't been placed yet, patched once they are.
+    patches: Vec<(usize, BlockId)>
+}
+
+impl<'c, 'm> FunctionCompiler<'c, 'm> {
+
+    fn new(compiler: &'c mut Compiler<'m>, function: &'m Function) -> Self {
+        let mut types: HashMap<Reg, Ty> = function.params.iter().cloned().collect();
+        let mut slots: HashMap<Reg, u32> = HashMap::new();
+        for (reg, _) in &function.params {
+            let slot = slots.len() as u32;
+            slots.insert(*reg, slot);
+        }
+        for (reg, ty) in function.blocks.iter().flat_map(|block| &block.insts).filter_map(Inst::def) {
+            types.insert(reg, ty.clone());
+            let slot = slots.len() as u32;
+            slots.entry(reg).or_insert(slot);
+        }
+        Self { compiler, function, types, slots, code: Vec::new(), starts: HashMap::new(), patches: Vec::new() }
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.code.push(op);
+    }
+
+    fn jump(&mut self, op: fn(u32) -> Op, target: BlockId) {
+        self.patches.push((self.code.len(), target));
+        self.emit(op(0));
+    }
+
+    /// Pushes `operand`, read as a `ty`.
+    fn push(&mut self, operand: &Operand, ty: &Ty) {
+        match operand {
+            Operand::Reg(reg) => self.emit(Op::Load(self.slots[reg])),
+            Operand::Const(value) => {
+                let index = self.compiler.constant(constant(value, ty));
+                self.emit(Op::Const(index));
+            }
+        }
+    }
+
+    fn store(&mut self, reg: Reg) {
+        self.emit(Op::Store(self.slots[&reg]));
+    }
+
+    /// The class of the instance in `obj` and the position of `field` in it.
+    fn field(&self, obj: Reg, field: &str) -> (u32, Ty) {
+        let Ty::Struct(name) = &self.types[&obj] else {
+            unreachable!("the verifier only allows field access on instances");
+        };
+        let structure = self.compiler.module.structure(name).expect("the verifier checks struct names");
+        let index = structure.fields.iter().position(|(other, _)| other == field).expect("the verifier checks field names");
+        (index as u32, structure.fields[index].1.clone())
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dst, ty, src } => {
+                self.push(src, ty);
+                self.store(*dst);
+            },
+            Inst::Binary { dst, ty, op, lhs, rhs } => {
+                let operand_ty = operand_type(&self.types, *op, ty, lhs, rhs);
+                self.push(lhs, &operand_ty);
+                self.push(rhs, &operand_ty);
+                self.emit(Op::Binary(*op));
+                self.store(*dst);
+            },
+            Inst::Unary { dst, ty, op, src } => {
+                self.push(src, ty);
+                self.emit(Op::Unary(*op));
+                self.store(*dst);
+            },
+            Inst::Call { dst, func, args } => {
+                let module = self.compiler.module;
+                match module.function(func) {
+                    Some(callee) => {
+                        for (arg, (_, ty)) in args.iter().zip(&callee.params) {
+                            self.push(arg, ty);
+                        }
+                        match self.compiler.method(func) {
+                            Some(method) => {
+                                let name = self.compiler.string(method);
+                                self.emit(Op::Invoke(name, args.len() as u32));
+                            },
+                            None => self.emit(Op::Call(self.compiler.functions[func.as_str()]))
+                        }
+                        match dst {
+                            Some((dst, _)) => self.store(*dst),
+                            None => self.emit(Op::Pop)
+                        }
+                    },
+                    None => {
+                        for arg in args {
+                            let ty = type_of(&self.types, arg);
+                            self.push(arg, &ty);
+                        }
+                        let builtin = builtin(func).expect("calls were checked");
+                        self.emit(Op::Builtin(builtin, args.len() as u32));
+                    }
+                }
+            },
+            Inst::Load { dst, global, .. } => {
+                self.emit(Op::GetGlobal(self.compiler.globals[global.as_str()]));
+                self.store(*dst);
+            },
+            Inst::Store { global, src } => {
+                let ty = self.compiler.module.global(global).expect("the verifier checks global names").ty.clone();
+                self.push(src, &ty);
+                self.emit(Op::SetGlobal(self.compiler.globals[global.as_str()]));
+            },
+            Inst::New { dst, ty, args } => {
+                let Ty::Struct(name) = ty else {
+                    unreachable!("the verifier only allows `new` of structs");
+                };
+                let structure = self.compiler.module.structure(name).expect("the verifier checks struct names");
+                for (arg, (_, ty)) in args.iter().zip(&structure.fields) {
+                    self.push(arg, ty);
+                }
+                self.emit(Op::New(self.compiler.classes[name.as_str()]));
+                self.store(*dst);
+            },
+            Inst::GetField { dst, obj, field, .. } => {
+                let (index, _) = self.field(*obj, field);
+                self.emit(Op::Load(self.slots[obj]));
+                self.emit(Op::GetField(index));
+                self.store(*dst);
+            },
+            Inst::SetField { obj, field, src } => {
+                let (index, ty) = self.field(*obj, field);
+                self.emit(Op::Load(self.slots[obj]));
+                self.push(src, &ty);
+                self.emit(Op::SetField(index));
+            },
+            // An instance carries its class, which is all a trait object needs here.
+            Inst::Dyn { dst, src, .. } => {
+                self.emit(Op::Load(self.slots[src]));
+                self.store(*dst);
+            },
+            Inst::CallDyn { dst, recv, method, args } => {
+                let Ty::Dyn(with) = &self.types[recv] else {
+                    unreachable!("the verifier only allows `calldyn` on trait objects");
+                };
+                let module = self.compiler.module;
+                let (_, slot) = module.trait_def(with).and_then(|def| def.slot(method)).expect("the verifier checks slots");
+                self.emit(Op::Load(self.slots[recv]));
+                for (arg, ty) in args.iter().zip(&slot.params) {
+                    self.push(arg, ty);
+                }
+                let name = self.compiler.string(method);
+                self.emit(Op::Invoke(name, args.len() as u32 + 1));
+                match dst {
+                    Some((dst, _)) => self.store(*dst),
+                    None => self.emit(Op::Pop)
+                }
+            },
+            Inst::Phi { .. } => unreachable!("phis are removed before compiling")
+        }
+    }
+
+    /// Compiles the terminator of a block followed by the block `next`, which control
+    /// can fall into without a jump.
+    fn term(&mut self, term: &Terminator, next: Option<BlockId>) {
+        match term {
+            Terminator::Ret(None) => self.emit(Op::ReturnVoid),
+            Terminator::Ret(Some(value)) => {
+                let ty = self.function.ret.clone();
+                self.push(value, &ty);
+                self.emit(Op::Return);
+            },
+            Terminator::Jump(target) if Some(*target) == next => {},
+            Terminator::Jump(target) => self.jump(Op::Jump, *target),
+            Terminator::Branch { cond, then, els } => {
+                self.push(cond, &Ty::Bool);
+                if Some(*els) == next {
+                    self.jump(Op::JumpIf, *then);
+                } else {
+                    self.jump(Op::JumpUnless, *els);
+                    if Some(*then) != next {
+                        self.jump(Op::Jump, *then);
+                    }
+                }
+            },
+            Terminator::Unreachable => self.emit(Op::Unreachable)
+        }
+    }
+
+    fn compile(mut self) -> bytecode::Function {
+        let blocks = &self.function.blocks;
+        for (idx, block) in blocks.iter().enumerate() {
+            self.starts.insert(block.id, self.code.len() as u32);
+            for inst in &block.insts {
+                self.inst(inst);
+            }
+            self.term(&block.term, blocks.get(idx + 1).map(|next| next.id));
+        }
+        for (at, target) in std::mem::take(&mut self.patches) {
+            let start = self.starts[&target];
+            match &mut self.code[at] {
+                Op::Jump(to) | Op::JumpIf(to) | Op::JumpUnless(to) => *to = start,
+                _ => unreachable!("only jumps are patched")
+            }
+        }
+        bytecode::Function {
+            name: self.compiler.string(&self.function.name),
+            params: self.function.params.len() as u32,
+            locals: self.slots.len() as u32,
+            code: self.code
+        }
+    }
+}
+
+/// Compiles an optimized, verified module for the VM. Calls to a method that takes its
+/// receiver first become `invoke`s, dispatched on the class of the instance at run time.
+/// Calls on trait objects do too: a class also lists the functions of its vtables, under
+/// the qualified name of the trait method, such as `Show::show`.
+pub(crate) fn compile(module: &Module) -> Result<Program, Vec<Diagnostic>> {
+    check(module, "the bytecode VM")?;
+    let mut module = module.clone();
+    for function in &mut module.functions {
+        ssa::destruct(function);
+    }
+    let module = &module;
+
+    let mut compiler = Compiler {
+        module,
+        program: Program::default(),
+        pool: HashMap::new(),
+        functions: HashMap::new(),
+        classes: HashMap::new(),
+        globals: HashMap::new()
+    };
+    for (index, function) in module.functions.iter().enumerate() {
+        compiler.functions.insert(&function.name, index as u32);
+    }
+    for structure in &module.structs {
+        let name = compiler.string(&structure.name);
+        let fields = structure.fields.iter().map(|(field, _)| compiler.string(field)).collect();
+        compiler.classes.insert(&structure.name, compiler.program.classes.len() as u32);
+        compiler.program.classes.push(Class { name, fields, methods: Vec::new() });
+    }
+    for function in &module.functions {
+        if let Some(method) = compiler.method(&function.name) {
+            let owner = &function.name[..function.name.len() - method.len() - 2];
+            let (class, name) = (compiler.classes[owner], compiler.string(method));
+            let index = compiler.functions[function.name.as_str()];
+            compiler.program.classes[class as usize].methods.push((name, index));
+        }
+    }
+    for vtable in &module.vtables {
+        let def = module.trait_def(&vtable.with).expect("the verifier checks vtables");
+        let class = compiler.classes[vtable.obj.as_str()] as usize;
+        for (slot, function) in def.slots.iter().zip(&vtable.functions) {
+            let (name, index) = (compiler.string(&slot.method), compiler.functions[function.as_str()]);
+            // Supertraits' methods are in the vtables of every trait requiring them.
+            if !compiler.program.classes[class].methods.contains(&(name, index)) {
+                compiler.program.classes[class].methods.push((name, index));
+            }
+        }
+    }
+    for global in &module.globals {
+        let name = compiler.string(&global.name);
+        let init = global.init.clone()
+            .or_else(|| Const::zero(&global.ty))
+            .map(|init| compiler.constant(constant(&init, &global.ty)));
+        compiler.globals.insert(&global.name, compiler.program.globals.len() as u32);
+        compiler.program.globals.push(Global { name, init });
+    }
+
+    for function in &module.functions {
+        let compiled = FunctionCompiler::new(&mut compiler, function).compile();
+        compiler.program.functions.push(compiled);
+    }
+    Ok(compiler.program)
+}
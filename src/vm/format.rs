@@ -0,0 +1,523 @@
+use std::fmt::Display;
+
+use crate::{
+    analysis::symbols::Builtin,
+    interpreter::value::IntTy,
+    ir::{BinaryOp, UnaryOp}
+};
+
+use super::bytecode::{Class, Constant, Function, Global, Op, Program};
+
+/// The first bytes of every `.bbc` file.
+pub(crate) const MAGIC: &[u8] = b"\x7fBBC";
+
+/// Bumped whenever the encoding changes, so an old file is rejected rather than misread.
+pub(crate) const VERSION: u16 = 1;
+
+/// Constant pool entry tags.
+mod tag {
+    pub const INT: u8 = 0;
+    pub const FLOAT: u8 = 1;
+    pub const BOOL: u8 = 2;
+    pub const STR: u8 = 3;
+}
+
+/// Opcodes. Binary and unary operators are numbered from their base in the order of
+/// `BinaryOp::ALL` and `UnaryOp::ALL`, and builtins in the order of `Builtin::ALL`.
+mod opcode {
+    pub const CONST: u8 = 0x01;
+    pub const LOAD: u8 = 0x02;
+    pub const STORE: u8 = 0x03;
+    pub const GET_GLOBAL: u8 = 0x04;
+    pub const SET_GLOBAL: u8 = 0x05;
+    pub const BINARY: u8 = 0x10;
+    pub const UNARY: u8 = 0x20;
+    pub const JUMP: u8 = 0x30;
+    pub const JUMP_IF: u8 = 0x31;
+    pub const JUMP_UNLESS: u8 = 0x32;
+    pub const CALL: u8 = 0x40;
+    pub const INVOKE: u8 = 0x41;
+    pub const BUILTIN: u8 = 0x42;
+    pub const NEW: u8 = 0x50;
+    pub const GET_FIELD: u8 = 0x51;
+    pub const SET_FIELD: u8 = 0x52;
+    pub const POP: u8 = 0x60;
+    pub const RETURN: u8 = 0x61;
+    pub const RETURN_VOID: u8 = 0x62;
+    pub const UNREACHABLE: u8 = 0x63;
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i128) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn index(out: &mut Vec<u8>, value: u32) {
+    unsigned(out, value as u64);
+}
+
+fn position<T: PartialEq>(all: &[T], item: &T) -> u8 {
+    all.iter().position(|other| other == item).expect("every operator and builtin is listed") as u8
+}
+
+fn op(out: &mut Vec<u8>, op: &Op) {
+    match op {
+        Op::Const(value) => {
+            out.push(opcode::CONST);
+            index(out, *value);
+        },
+        Op::Load(slot) => {
+            out.push(opcode::LOAD);
+            index(out, *slot);
+        },
+        Op::Store(slot) => {
+            out.push(opcode::STORE);
+            index(out, *slot);
+        },
+        Op::GetGlobal(global) => {
+            out.push(opcode::GET_GLOBAL);
+            index(out, *global);
+        },
+        Op::SetGlobal(global) => {
+            out.push(opcode::SET_GLOBAL);
+            index(out, *global);
+        },
+        Op::Binary(op) => out.push(opcode::BINARY + position(&BinaryOp::ALL, op)),
+        Op::Unary(op) => out.push(opcode::UNARY + position(&UnaryOp::ALL, op)),
+        Op::Jump(target) => {
+            out.push(opcode::JUMP);
+            index(out, *target);
+        },
+        Op::JumpIf(target) => {
+            out.push(opcode::JUMP_IF);
+            index(out, *target);
+        },
+        Op::JumpUnless(target) => {
+            out.push(opcode::JUMP_UNLESS);
+            index(out, *target);
+        },
+        Op::Call(function) => {
+            out.push(opcode::CALL);
+            index(out, *function);
+        },
+        Op::Invoke(name, argc) => {
+            out.push(opcode::INVOKE);
+            index(out, *name);
+            index(out, *argc);
+        },
+        Op::Builtin(builtin, argc) => {
+            out.push(opcode::BUILTIN);
+            out.push(position(&Builtin::ALL, builtin));
+            index(out, *argc);
+        },
+        Op::New(class) => {
+            out.push(opcode::NEW);
+            index(out, *class);
+        },
+        Op::GetField(field) => {
+            out.push(opcode::GET_FIELD);
+            index(out, *field);
+        },
+        Op::SetField(field) => {
+            out.push(opcode::SET_FIELD);
+            index(out, *field);
+        },
+        Op::Pop => out.push(opcode::POP),
+        Op::Return => out.push(opcode::RETURN),
+        Op::ReturnVoid => out.push(opcode::RETURN_VOID),
+        Op::Unreachable => out.push(opcode::UNREACHABLE)
+    }
+}
+
+/// The `.bbc` encoding: the magic bytes and a little-endian `u16` version, then the
+/// constant pool, globals, classes and functions, each as a count followed by entries.
+/// Numbers are LEB128 and names are pool indices.
+pub(crate) fn encode(program: &Program) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+
+    index(&mut out, program.constants.len() as u32);
+    for constant in &program.constants {
+        match constant {
+            Constant::Int(value, ty) => {
+                out.extend([tag::INT, ty.bits as u8, ty.signed as u8]);
+                signed(&mut out, *value);
+            },
+            Constant::Float(value, bits) => {
+                out.extend([tag::FLOAT, *bits as u8]);
+                out.extend(value.to_le_bytes());
+            },
+            Constant::Bool(value) => out.extend([tag::BOOL, *value as u8]),
+            Constant::Str(value) => {
+                out.push(tag::STR);
+                index(&mut out, value.len() as u32);
+                out.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+
+    index(&mut out, program.globals.len() as u32);
+    for global in &program.globals {
+        index(&mut out, global.name);
+        // 0 is no initializer, so the pool index is stored one up.
+        index(&mut out, global.init.map_or(0, |init| init + 1));
+    }
+
+    index(&mut out, program.classes.len() as u32);
+    for class in &program.classes {
+        index(&mut out, class.name);
+        index(&mut out, class.fields.len() as u32);
+        for field in &class.fields {
+            index(&mut out, *field);
+        }
+        index(&mut out, class.methods.len() as u32);
+        for (name, function) in &class.methods {
+            index(&mut out, *name);
+            index(&mut out, *function);
+        }
+    }
+
+    index(&mut out, program.functions.len() as u32);
+    for function in &program.functions {
+        index(&mut out, function.name);
+        index(&mut out, function.params);
+        index(&mut out, function.locals);
+        index(&mut out, function.code.len() as u32);
+        for item in &function.code {
+            op(&mut out, item);
+        }
+    }
+    out
+}
+
+/// Why a `.bbc` file couldn't be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LoadError {
+    /// The bytes don't follow the encoding.
+    Malformed { offset: usize, message: String },
+    /// The program decodes but isn't safe to run, e.g. a jump out of its function. The
+    /// function is missing for a problem with a global or class.
+    Invalid { function: Option<String>, op: usize, message: String }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed { offset, message } => write!(f, "at offset {:#x}: {}", offset, message),
+            Self::Invalid { function: Some(function), op, message } => write!(f, "in `{}` at instruction {}: {}", function, op, message),
+            Self::Invalid { function: None, message, .. } => f.write_str(message)
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    offset: usize
+}
+
+impl<'b> Reader<'b> {
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, LoadError> {
+        Err(LoadError::Malformed { offset: self.offset, message: message.into() })
+    }
+
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        match self.bytes.get(self.offset) {
+            Some(byte) => {
+                self.offset += 1;
+                Ok(*byte)
+            },
+            None => self.error("unexpected end of file")
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8], LoadError> {
+        match self.bytes.get(self.offset..self.offset.saturating_add(len)) {
+            Some(bytes) => {
+                self.offset += len;
+                Ok(bytes)
+            },
+            None => self.error("unexpected end of file")
+        }
+    }
+
+    fn index(&mut self) -> Result<u32, LoadError> {
+        let start = self.offset;
+        let mut value = 0u64;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).or_else(|_| {
+                    self.offset = start;
+                    self.error("integer too large")
+                });
+            }
+        }
+        self.offset = start;
+        self.error("integer representation too long")
+    }
+
+    fn signed(&mut self) -> Result<i128, LoadError> {
+        let start = self.offset;
+        let mut value = 0i128;
+        for shift in (0..133).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as i128) << shift;
+            if byte & 0x80 == 0 {
+                if shift + 7 < 128 && byte & 0x40 != 0 {
+                    value |= -1i128 << (shift + 7);
+                }
+                return Ok(value);
+            }
+        }
+        self.offset = start;
+        self.error("integer representation too long")
+    }
+
+    /// A count of items that each take at least a byte, so a corrupt count fails here
+    /// instead of reserving a huge vector.
+    fn count(&mut self) -> Result<usize, LoadError> {
+        let count = self.index()? as usize;
+        if count > self.bytes.len() - self.offset {
+            return self.error(format!("count {} is larger than the rest of the file", count));
+        }
+        Ok(count)
+    }
+
+    fn constant(&mut self) -> Result<Constant, LoadError> {
+        match self.byte()? {
+            tag::INT => {
+                let bits = self.byte()? as u32;
+                let signed = match self.byte()? {
+                    0 => false,
+                    1 => true,
+                    other => return self.error(format!("invalid signedness {}", other))
+                };
+                if ![8, 16, 32, 64].contains(&bits) {
+                    return self.error(format!("invalid integer width {}", bits));
+                }
+                let ty = IntTy { bits, signed };
+                let value = self.signed()?;
+                if !ty.contains(value) {
+                    return self.error(format!("{} is out of range for its type", value));
+                }
+                Ok(Constant::Int(value, ty))
+            },
+            tag::FLOAT => {
+                let bits = self.byte()? as u32;
+                if ![8, 16, 32, 64].contains(&bits) {
+                    return self.error(format!("invalid float width {}", bits));
+                }
+                let bytes = self.take(8)?.try_into().expect("eight bytes were taken");
+                Ok(Constant::Float(f64::from_le_bytes(bytes), bits))
+            },
+            tag::BOOL => match self.byte()? {
+                0 => Ok(Constant::Bool(false)),
+                1 => Ok(Constant::Bool(true)),
+                other => self.error(format!("invalid boolean {}", other))
+            },
+            tag::STR => {
+                let len = self.count()?;
+                let bytes = self.take(len)?;
+                match std::str::from_utf8(bytes) {
+                    Ok(value) => Ok(Constant::Str(value.to_string())),
+                    Err(_) => self.error("string is not valid UTF-8")
+                }
+            },
+            other => {
+                self.offset -= 1;
+                self.error(format!("unknown constant tag {}", other))
+            }
+        }
+    }
+
+    fn op(&mut self) -> Result<Op, LoadError> {
+        let start = self.offset;
+        let code = self.byte()?;
+        Ok(match code {
+            opcode::CONST => Op::Const(self.index()?),
+            opcode::LOAD => Op::Load(self.index()?),
+            opcode::STORE => Op::Store(self.index()?),
+            opcode::GET_GLOBAL => Op::GetGlobal(self.index()?),
+            opcode::SET_GLOBAL => Op::SetGlobal(self.index()?),
+            _ if (opcode::BINARY..opcode::BINARY + BinaryOp::ALL.len() as u8).contains(&code) => {
+                Op::Binary(BinaryOp::ALL[(code - opcode::BINARY) as usize])
+            },
+            _ if (opcode::UNARY..opcode::UNARY + UnaryOp::ALL.len() as u8).contains(&code) => {
+                Op::Unary(UnaryOp::ALL[(code - opcode::UNARY) as usize])
+            },
+            opcode::JUMP => Op::Jump(self.index()?),
+            opcode::JUMP_IF => Op::JumpIf(self.index()?),
+            opcode::JUMP_UNLESS => Op::JumpUnless(self.index()?),
+            opcode::CALL => Op::Call(self.index()?),
+            opcode::INVOKE => Op::Invoke(self.index()?, self.index()?),
+            opcode::BUILTIN => {
+                let builtin = self.byte()?;
+                match Builtin::ALL.get(builtin as usize) {
+                    Some(builtin) => Op::Builtin(*builtin, self.index()?),
+                    None => {
+                        self.offset -= 1;
+                        return self.error(format!("unknown builtin {}", builtin));
+                    }
+                }
+            },
+            opcode::NEW => Op::New(self.index()?),
+            opcode::GET_FIELD => Op::GetField(self.index()?),
+            opcode::SET_FIELD => Op::SetField(self.index()?),
+            opcode::POP => Op::Pop,
+            opcode::RETURN => Op::Return,
+            opcode::RETURN_VOID => Op::ReturnVoid,
+            opcode::UNREACHABLE => Op::Unreachable,
+            _ => {
+                self.offset = start;
+                return self.error(format!("unknown opcode {:#04x}", code));
+            }
+        })
+    }
+}
+
+/// Reads a `.bbc` file back and verifies it, so the VM can run it without checking
+/// each instruction's operands again.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Program, LoadError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC) {
+        return Err(LoadError::Malformed { offset: 0, message: "not a bytecode file".into() });
+    }
+    let version = u16::from_le_bytes(reader.take(2)?.try_into().expect("two bytes were taken"));
+    if version != VERSION {
+        reader.offset -= 2;
+        return reader.error(format!("unsupported bytecode version {} (expected {})", version, VERSION));
+    }
+
+    let mut program = Program::default();
+    for _ in 0..reader.count()? {
+        program.constants.push(reader.constant()?);
+    }
+    for _ in 0..reader.count()? {
+        let name = reader.index()?;
+        let init = reader.index()?.checked_sub(1);
+        program.globals.push(Global { name, init });
+    }
+    for _ in 0..reader.count()? {
+        let name = reader.index()?;
+        let fields = (0..reader.count()?).map(|_| reader.index()).collect::<Result<_, _>>()?;
+        let methods = (0..reader.count()?).map(|_| Ok((reader.index()?, reader.index()?))).collect::<Result<_, _>>()?;
+        program.classes.push(Class { name, fields, methods });
+    }
+    for _ in 0..reader.count()? {
+        let name = reader.index()?;
+        let params = reader.index()?;
+        let locals = reader.index()?;
+        let code = (0..reader.count()?).map(|_| reader.op()).collect::<Result<_, _>>()?;
+        program.functions.push(Function { name, params, locals, code });
+    }
+    if reader.offset != bytes.len() {
+        return reader.error("unexpected data after the last function");
+    }
+
+    super::verify::verify(&program)?;
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir::parse::parse_module, vm::{compile::compile, Vm}};
+
+    /// Structs, a trait object, a global and constants of every kind.
+    const IR: &str = r#"
+struct Point { x: i32, y: f64 }
+
+trait Show { @Show::show() -> i32 }
+
+vtable Point as Show { @Point::Show::show }
+
+extern @println(...) -> void
+
+global @scale: i64 = 3
+
+fn @Point::Show::show(%0: Point) -> i32 {
+bb0:
+    %1: i32 = getfield %0, x
+    %2: i32 = mul %1, 2
+    ret %2
+}
+
+fn @main() -> void {
+bb0:
+    %0: Point = new Point(4, 1.5)
+    %1: Point = copy %0
+    %2: dyn Show = dyn Point %1
+    %3: dyn Show = copy %2
+    %4: i32 = calldyn %3, @Show::show()
+    %5: i64 = load @scale
+    %6: i64 = mul %5, 2
+    %7: f64 = getfield %1, y
+    %8: bool = gt %7, 1.0
+    call @println("point", %1, %4, %6, %8)
+    ret
+}
+"#;
+
+    fn program() -> Program {
+        compile(&parse_module(IR).unwrap()).unwrap()
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        decode(bytes).expect_err("a broken file").to_string()
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = program();
+        let bytes = encode(&program);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded, program);
+        assert_eq!(encode(&decoded), bytes);
+
+        let mut out = Vec::new();
+        Vm::new(&decoded, &mut out).run().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "point Point { x: 4, y: 1.5 } 8 6 true\n");
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let bytes = encode(&program());
+        assert_eq!(error(b"\0asm\x01\0\0\0"), "at offset 0x0: not a bytecode file");
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(error(&newer), format!("at offset 0x4: unsupported bytecode version {} (expected {})", VERSION + 1, VERSION));
+    }
+
+    #[test]
+    fn rejects_truncated_and_padded_files() {
+        let bytes = encode(&program());
+        for len in [MAGIC.len() + 2, bytes.len() / 2, bytes.len() - 1] {
+            assert!(decode(&bytes[..len]).is_err(), "{} of {} bytes", len, bytes.len());
+        }
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(error(&padded).ends_with("unexpected data after the last function"));
+    }
+}
@@ -0,0 +1,233 @@
+pub(crate) mod bytecode;
+pub(crate) mod compile;
+pub(crate) mod format;
+mod verify;
+
+use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc};
+
+use crate::{
+    analysis::symbols::Builtin,
+    common::diagnostics::{codes, Diagnostic},
+    interpreter::{
+        arith_error,
+        value::{ArithError, Instance, Value}
+    },
+    ir::{Module, UnaryOp}
+};
+
+use self::bytecode::{Op, Program};
+
+/// Frames live on the heap rather than the host stack, so the VM lets calls nest far
+/// deeper than the interpreter does and only stops runaway recursion.
+pub(crate) const MAX_CALL_DEPTH: usize = 1_000_000;
+
+fn error(code: &'static str, message: impl Into<String>) -> Diagnostic {
+    Diagnostic::error(message).with_code(code)
+}
+
+/// A function being run: where it is, and where its locals start on the stack.
+#[derive(Clone, Copy)]
+struct Frame {
+    function: u32,
+    pc: usize,
+    base: usize
+}
+
+/// Runs verified bytecode. Values are the interpreter's, so both print and fail alike;
+/// runtime errors carry no source location, as bytecode has none.
+pub(crate) struct Vm<'p, 'o> {
+    program: &'p Program,
+    constants: Vec<Value>,
+    globals: Vec<Value>,
+    /// Each class's methods by name, for `invoke`.
+    methods: HashMap<&'p str, HashMap<&'p str, u32>>,
+    stack: Vec<Value>,
+    /// The callers of the running function.
+    frames: Vec<Frame>,
+    /// Frames that don't count toward `MAX_CALL_DEPTH`: `.init` runs the top-level
+    /// statements, which the interpreter runs outside of any call.
+    uncounted: usize,
+    out: &'o mut dyn Write
+}
+
+impl<'p, 'o> Vm<'p, 'o> {
+
+    pub fn new(program: &'p Program, out: &'o mut dyn Write) -> Self {
+        let constants: Vec<Value> = program.constants.iter().map(|constant| constant.to_value()).collect();
+        let globals = program.globals.iter()
+            .map(|global| global.init.map_or(Value::Void, |init| constants[init as usize].clone()))
+            .collect();
+        let methods = program.classes.iter()
+            .map(|class| {
+                let methods = class.methods.iter().map(|(name, function)| (program.name(*name), *function)).collect();
+                (program.name(class.name), methods)
+            })
+            .collect();
+        Self {
+            program,
+            constants,
+            globals,
+            methods,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            uncounted: 0,
+            out
+        }
+    }
+
+    /// Runs the module's `.init`, then `main` if the program defines one.
+    pub fn run(&mut self) -> Result<(), Diagnostic> {
+        for entry in [Module::INIT, "main"] {
+            if let Some(function) = self.program.function(entry) {
+                self.uncounted = (entry == Module::INIT) as usize;
+                self.execute(function)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("verified code never underflows the stack")
+    }
+
+    /// Starts a call to `function`, whose arguments are on top of the stack.
+    fn enter(&mut self, function: u32) -> Result<Frame, Diagnostic> {
+        let callee = &self.program.functions[function as usize];
+        if self.frames.len() >= MAX_CALL_DEPTH + self.uncounted {
+            let name = self.program.name(callee.name);
+            return Err(error(codes::STACK_OVERFLOW, format!("call to `{}` exceeds the maximum call depth", name))
+                .with_note(format!("calls may nest at most {} deep", MAX_CALL_DEPTH)));
+        }
+        let base = self.stack.len() - callee.params as usize;
+        self.stack.resize(base + callee.locals as usize, Value::Void);
+        Ok(Frame { function, pc: 0, base })
+    }
+
+    fn instance(value: Value) -> Result<Rc<RefCell<Instance>>, Diagnostic> {
+        match value {
+            Value::Instance(instance) => Ok(instance),
+            other => Err(error(codes::INVALID_OPERATION, format!("`{}` is not an instance", other)))
+        }
+    }
+
+    /// The function `name` runs on the receiver `argc` values down the stack.
+    fn dispatch(&self, name: u32, argc: u32) -> Result<u32, Diagnostic> {
+        let method = self.program.name(name);
+        let receiver = &self.stack[self.stack.len() - argc as usize];
+        let Value::Instance(instance) = receiver else {
+            return Err(error(codes::INVALID_OPERATION, format!("`{}` has no method `{}`", receiver, method)));
+        };
+        let class = &instance.borrow().ty;
+        let function = self.methods.get(class.as_str()).and_then(|methods| methods.get(method)).copied();
+        match function {
+            Some(function) if self.program.functions[function as usize].params == argc => Ok(function),
+            Some(_) => Err(error(codes::INVALID_OPERATION, format!("`{}::{}` takes a different number of arguments", class, method))),
+            None => Err(error(codes::INVALID_OPERATION, format!("`{}` has no method `{}`", class, method)))
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, argc: u32) -> Result<(), Diagnostic> {
+        let args = self.stack.split_off(self.stack.len() - argc as usize);
+        let text = args.iter().map(Value::to_string).collect::<Vec<_>>().join(" ");
+        let result = match builtin {
+            Builtin::Print => write!(self.out, "{}", text),
+            Builtin::Println => writeln!(self.out, "{}", text)
+        };
+        result.map_err(|err| error(codes::INVALID_OPERATION, format!("couldn't write output: {}", err)))
+    }
+
+    /// Runs `function` to completion. The running frame is kept out of `frames`, in
+    /// locals, so the dispatch loop doesn't go through the vector for every instruction.
+    fn execute(&mut self, function: u32) -> Result<Value, Diagnostic> {
+        let program = self.program;
+        let mut frame = self.enter(function)?;
+        let mut code = &program.functions[function as usize].code;
+        loop {
+            let op = code[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::Const(index) => self.stack.push(self.constants[index as usize].clone()),
+                Op::Load(slot) => self.stack.push(self.stack[frame.base + slot as usize].clone()),
+                Op::Store(slot) => {
+                    let value = self.pop();
+                    self.stack[frame.base + slot as usize] = value;
+                },
+                Op::GetGlobal(index) => self.stack.push(self.globals[index as usize].clone()),
+                Op::SetGlobal(index) => {
+                    let value = self.pop();
+                    self.globals[index as usize] = value;
+                },
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let op = op.bin_op();
+                    let result = lhs.binary(op, &rhs).map_err(|err| arith_error(err, op.verb()))?;
+                    self.stack.push(result);
+                },
+                Op::Unary(op) => {
+                    let value = self.pop();
+                    let result = match op {
+                        UnaryOp::Neg => value.negate().map_err(|err| arith_error(err, "negate")),
+                        UnaryOp::Not => value.not().map_err(|err| arith_error(err, "negate")),
+                        UnaryOp::BitNot => value.bit_not().map_err(|err| arith_error(err, "complement"))
+                    }?;
+                    self.stack.push(result);
+                },
+                Op::Jump(target) => frame.pc = target as usize,
+                Op::JumpIf(target) | Op::JumpUnless(target) => {
+                    let cond = self.pop().truthy().ok_or_else(|| arith_error(ArithError::Mismatch, "test"))?;
+                    if cond == matches!(op, Op::JumpIf(_)) {
+                        frame.pc = target as usize;
+                    }
+                },
+                Op::Call(_) | Op::Invoke(..) => {
+                    let callee = match op {
+                        Op::Invoke(name, argc) => self.dispatch(name, argc)?,
+                        Op::Call(callee) => callee,
+                        _ => unreachable!()
+                    };
+                    self.frames.push(frame);
+                    frame = self.enter(callee)?;
+                    code = &program.functions[callee as usize].code;
+                },
+                Op::Builtin(builtin, argc) => self.builtin(builtin, argc)?,
+                Op::New(index) => {
+                    let class = &program.classes[index as usize];
+                    let values = self.stack.split_off(self.stack.len() - class.fields.len());
+                    let fields = class.fields.iter().map(|field| program.name(*field).to_string()).zip(values).collect();
+                    let instance = Instance { ty: program.name(class.name).to_string(), fields };
+                    self.stack.push(Value::Instance(Rc::new(RefCell::new(instance))));
+                },
+                Op::GetField(index) => {
+                    let instance = Self::instance(self.pop())?;
+                    let value = instance.borrow().fields.get(index as usize).map(|(_, value)| value.clone());
+                    let value = value.ok_or_else(|| error(codes::INVALID_OPERATION, format!("no field {}", index)))?;
+                    self.stack.push(value);
+                },
+                Op::SetField(index) => {
+                    let value = self.pop();
+                    let instance = Self::instance(self.pop())?;
+                    let mut instance = instance.borrow_mut();
+                    match instance.fields.get_mut(index as usize) {
+                        Some((_, field)) => *field = value,
+                        None => return Err(error(codes::INVALID_OPERATION, format!("no field {}", index)))
+                    }
+                },
+                Op::Pop => {
+                    self.pop();
+                },
+                Op::Return | Op::ReturnVoid => {
+                    let value = if op == Op::Return { self.pop() } else { Value::Void };
+                    self.stack.truncate(frame.base);
+                    let Some(caller) = self.frames.pop() else {
+                        return Ok(value);
+                    };
+                    frame = caller;
+                    code = &program.functions[frame.function as usize].code;
+                    self.stack.push(value);
+                },
+                Op::Unreachable => return Err(error(codes::INVALID_OPERATION, "entered unreachable code"))
+            }
+        }
+    }
+}
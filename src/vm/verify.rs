@@ -0,0 +1,162 @@
+use super::{
+    bytecode::{Constant, Function, Op, Program},
+    format::LoadError
+};
+
+struct Checker<'p> {
+    program: &'p Program,
+    function: &'p Function,
+    op: usize
+}
+
+impl<'p> Checker<'p> {
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, LoadError> {
+        Err(LoadError::Invalid {
+            function: Some(self.program.name(self.function.name).to_string()),
+            op: self.op,
+            message: message.into()
+        })
+    }
+
+    fn check(&self, index: u32, len: usize, what: &str) -> Result<(), LoadError> {
+        if (index as usize) < len {
+            Ok(())
+        } else {
+            self.error(format!("{} {} is out of range", what, index))
+        }
+    }
+
+    /// The number of values the instruction takes off the stack and puts back.
+    fn effect(&self, op: &Op) -> Result<(u32, u32), LoadError> {
+        let program = self.program;
+        Ok(match op {
+            Op::Const(index) => {
+                self.check(*index, program.constants.len(), "constant")?;
+                (0, 1)
+            },
+            Op::Load(slot) | Op::Store(slot) => {
+                self.check(*slot, self.function.locals as usize, "local")?;
+                if matches!(op, Op::Load(_)) { (0, 1) } else { (1, 0) }
+            },
+            Op::GetGlobal(index) | Op::SetGlobal(index) => {
+                self.check(*index, program.globals.len(), "global")?;
+                if matches!(op, Op::GetGlobal(_)) { (0, 1) } else { (1, 0) }
+            },
+            Op::Binary(_) => (2, 1),
+            Op::Unary(_) | Op::GetField(_) => (1, 1),
+            Op::Jump(target) | Op::JumpIf(target) | Op::JumpUnless(target) => {
+                self.check(*target, self.function.code.len(), "jump target")?;
+                if matches!(op, Op::Jump(_)) { (0, 0) } else { (1, 0) }
+            },
+            Op::Call(index) => {
+                self.check(*index, program.functions.len(), "function")?;
+                (program.functions[*index as usize].params, 1)
+            },
+            Op::Invoke(name, argc) => {
+                if !matches!(program.constants.get(*name as usize), Some(Constant::Str(_))) {
+                    return self.error(format!("method name #{} is not a string", name));
+                }
+                if *argc == 0 {
+                    return self.error("a method call needs a receiver");
+                }
+                (*argc, 1)
+            },
+            Op::Builtin(_, argc) => (*argc, 0),
+            Op::New(index) => {
+                self.check(*index, program.classes.len(), "class")?;
+                (program.classes[*index as usize].fields.len() as u32, 1)
+            },
+            Op::SetField(_) => (2, 0),
+            Op::Pop => (1, 0),
+            Op::Return => (1, 0),
+            Op::ReturnVoid | Op::Unreachable => (0, 0)
+        })
+    }
+
+    /// Follows every path through the function, so each instruction is known to see the
+    /// same stack height whichever way control reaches it, and never too few values.
+    fn stack(&mut self) -> Result<(), LoadError> {
+        let code = &self.function.code;
+        let mut heights: Vec<Option<u32>> = vec![None; code.len()];
+        let mut work = vec![(0usize, 0u32)];
+        while let Some((start, height)) = work.pop() {
+            let (mut index, mut height) = (start, height);
+            loop {
+                self.op = index;
+                let Some(op) = code.get(index) else {
+                    return self.error("control runs past the end of the function");
+                };
+                match heights[index] {
+                    Some(seen) if seen == height => break,
+                    Some(seen) => return self.error(format!("stack height is {} on one path here and {} on another", seen, height)),
+                    None => heights[index] = Some(height)
+                }
+                let (pops, pushes) = self.effect(op)?;
+                if height < pops {
+                    return self.error(format!("`{:?}` needs {} values but the stack holds {}", op, pops, height));
+                }
+                height = height - pops + pushes;
+                if let Some(target) = op.target() {
+                    work.push((target as usize, height));
+                }
+                if op.ends_block() {
+                    break;
+                }
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn name(program: &Program, index: u32, what: &str) -> Result<(), LoadError> {
+    match program.constants.get(index as usize) {
+        Some(Constant::Str(_)) => Ok(()),
+        _ => Err(LoadError::Invalid {
+            function: None,
+            op: 0,
+            message: format!("the name of {} is not a string in the constant pool", what)
+        })
+    }
+}
+
+/// Checks what decoding alone can't: that every index refers to something and that the
+/// stack never underflows.
+pub(super) fn verify(program: &Program) -> Result<(), LoadError> {
+    for global in &program.globals {
+        name(program, global.name, "a global")?;
+        if global.init.is_some_and(|init| init as usize >= program.constants.len()) {
+            return Err(LoadError::Invalid {
+                function: None,
+                op: 0,
+                message: format!("the initializer of `{}` is out of range", program.name(global.name))
+            });
+        }
+    }
+    for class in &program.classes {
+        name(program, class.name, "a class")?;
+        for field in &class.fields {
+            name(program, *field, "a field")?;
+        }
+        for (method, function) in &class.methods {
+            name(program, *method, "a method")?;
+            if *function as usize >= program.functions.len() {
+                return Err(LoadError::Invalid {
+                    function: None,
+                    op: 0,
+                    message: format!("method `{}` of `{}` is out of range", program.name(*method), program.name(class.name))
+                });
+            }
+        }
+    }
+    for function in &program.functions {
+        name(program, function.name, "a function")?;
+        let mut checker = Checker { program, function, op: 0 };
+        if function.params > function.locals {
+            return checker.error("there are more parameters than locals");
+        }
+        checker.stack()?;
+    }
+    Ok(())
+}